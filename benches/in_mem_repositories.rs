@@ -0,0 +1,75 @@
+//! Compares the in memory repositories split into shards with a single shard (a single lock,
+//! as they used to be), with concurrent workers processing transactions of different clients.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use transactioner::infrastructure::in_mem_dbs::DEFAULT_SHARDS;
+use transactioner::{
+    ClientID, ClientInMemRepository, DisputeState, MoneyType, TTransactionService, Transaction,
+    TransactionID, TransactionInMemRepository, TransactionService, TransactionType,
+};
+
+const WORKERS: u32 = 8;
+const TRANSACTIONS_PER_WORKER: u32 = 2_000;
+
+/// Every worker deposits to, and then disputes the deposits of, a client of its own
+async fn process_concurrently(shards: usize) {
+    let service = Arc::new(TransactionService::new(
+        ClientInMemRepository::with_shards(shards),
+        TransactionInMemRepository::with_shards(shards),
+    ));
+
+    let workers = (0..WORKERS).map(|worker| {
+        let service = service.clone();
+
+        tokio::spawn(async move {
+            for index in 0..TRANSACTIONS_PER_WORKER {
+                let tx_id = worker * TRANSACTIONS_PER_WORKER + index;
+
+                for tx_type in [
+                    TransactionType::Deposit {
+                        amount: MoneyType::new(10000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                    TransactionType::Dispute,
+                ] {
+                    let transaction = Transaction::builder()
+                        .with_client_id(ClientID(worker as u16))
+                        .with_tx_id(TransactionID(tx_id))
+                        .with_tx_type(tx_type)
+                        .build();
+
+                    service.process_transaction(transaction).await.unwrap();
+                }
+            }
+        })
+    });
+
+    for worker in workers.collect::<Vec<_>>() {
+        worker.await.unwrap();
+    }
+}
+
+fn bench_shards(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("in_mem_repositories");
+
+    group.sample_size(20);
+
+    for shards in [1, DEFAULT_SHARDS] {
+        group.bench_with_input(BenchmarkId::new("shards", shards), &shards, |b, &shards| {
+            b.iter(|| runtime.block_on(process_concurrently(shards)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_shards);
+criterion_main!(benches);
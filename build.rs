@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The gRPC service is generated from its protocol definition, which needs `protoc`
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/transactions.proto")?;
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! Export the state of the accounts in a format of your own.
+//!
+//! The transactions are read from CSV (a file given as the first argument, or a small
+//! built-in input), and the accounts are written as an aligned plain text table by
+//! implementing [`TClientStateExporter`].
+//!
+//! ```text
+//! cargo run --example custom_exporter [transactions.csv]
+//! ```
+
+use std::convert::Infallible;
+use std::path::PathBuf;
+
+use futures::{Stream, StreamExt};
+
+use transactioner::models::client::ClientAccountStatus;
+use transactioner::{
+    CSVTransactionProvider, ClientInMemRepository, ShareableClientRepository, StoredClient,
+    TClientRepository, TClientStateExporter, TTransactionService, TTransactionStreamProvider,
+    TransactionInMemRepository, TransactionService, FLOATING_POINT_ACC,
+};
+
+const SAMPLE_INPUT: &str = "type,client,tx,amount
+deposit,1,1,10.0
+deposit,2,2,4.5
+withdrawal,1,3,2.5
+dispute,2,2,
+chargeback,2,2,
+";
+
+/// Writes every account as a row of a plain text table, ordered by client id
+struct TableExporter {
+    /// The decimal places of the amounts, the ones the input was read with
+    precision: u32,
+}
+
+impl TClientStateExporter for TableExporter {
+    type Error = Infallible;
+
+    async fn export_state(
+        &self,
+        state: impl Stream<Item = StoredClient>,
+    ) -> Result<(), Infallible> {
+        let mut rows = Vec::new();
+
+        futures::pin_mut!(state);
+
+        while let Some(client) = state.next().await {
+            let client = client.lock().await;
+
+            let status = match client.account_status() {
+                ClientAccountStatus::Active => "active",
+                ClientAccountStatus::Frozen => "frozen",
+                ClientAccountStatus::Closed => "closed",
+            };
+
+            rows.push((
+                client.client_id(),
+                format!(
+                    "{:>6} {:>14} {:>14} {:>14}  {}",
+                    client.client_id().to_string(),
+                    client.available().display(self.precision).to_string(),
+                    client.held().display(self.precision).to_string(),
+                    client.total().display(self.precision).to_string(),
+                    status
+                ),
+            ));
+        }
+
+        rows.sort_by_key(|(client, _)| *client);
+
+        println!(
+            "{:>6} {:>14} {:>14} {:>14}  status",
+            "client", "available", "held", "total"
+        );
+
+        for (_, row) in rows {
+            println!("{}", row);
+        }
+
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+    let service = TransactionService::new(clients.clone(), TransactionInMemRepository::default());
+
+    let mut transactions = match std::env::args_os().nth(1).map(PathBuf::from) {
+        Some(path) => match tokio::fs::File::open(&path).await {
+            Ok(file) => {
+                CSVTransactionProvider::new(file)
+                    .subscribe_to_tx_stream()
+                    .await
+            }
+            Err(err) => {
+                eprintln!("Failed to open {}: {}", path.display(), err);
+
+                return;
+            }
+        },
+        None => {
+            CSVTransactionProvider::new(SAMPLE_INPUT.as_bytes())
+                .subscribe_to_tx_stream()
+                .await
+        }
+    };
+
+    while let Some(tx) = transactions.next().await {
+        // The rejections are reported by the service, through tracing
+        let _ = service.process_transaction(tx).await;
+    }
+
+    let state = clients
+        .find_all_clients()
+        .await
+        .expect("The in-memory repository never fails");
+
+    let exporter = TableExporter {
+        precision: FLOATING_POINT_ACC as u32,
+    };
+
+    let Ok(()) = exporter.export_state(state).await;
+}
@@ -0,0 +1,129 @@
+//! Embed the engine behind a transaction source of your own.
+//!
+//! The transactions are produced by a task of the application (standing in for whatever feed
+//! it receives them from) and handed to the engine through a channel, by implementing
+//! [`TTransactionStreamProvider`] over it. The resulting state of the accounts is exported
+//! as CSV to the standard output.
+//!
+//! ```text
+//! cargo run --example custom_provider
+//! ```
+
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use tokio::sync::mpsc;
+
+use transactioner::{
+    ClientID, ClientInMemRepository, CsvStateExporter, DisputeState, MoneyType,
+    ShareableClientRepository, TClientRepository, TClientStateExporter, TRejectionReason,
+    TTransactionService, TTransactionStreamProvider, Transaction, TransactionID,
+    TransactionInMemRepository, TransactionService, TransactionType, FLOATING_POINT_ACC,
+};
+
+/// Hands the engine the transactions sent through a channel, until every sender is dropped
+struct ChannelProvider {
+    receiver: mpsc::Receiver<Transaction>,
+}
+
+impl TTransactionStreamProvider for ChannelProvider {
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        stream::unfold(self.receiver, |mut receiver| async move {
+            receiver.recv().await.map(|tx| (tx, receiver))
+        })
+        .boxed()
+    }
+}
+
+fn transaction(client: u16, tx: u32, tx_type: TransactionType) -> Transaction {
+    Transaction::builder()
+        .with_client_id(ClientID(client))
+        .with_tx_id(TransactionID(tx))
+        .with_tx_type(tx_type)
+        .build()
+}
+
+fn amount(amount: &str) -> MoneyType {
+    // The exporter writes the amounts with the default precision as well
+    MoneyType::parse(amount, FLOATING_POINT_ACC as u32)
+        .expect("The amounts of the example are valid")
+}
+
+#[tokio::main]
+async fn main() {
+    let (sender, receiver) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let feed = [
+            transaction(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount("100.0"),
+                    dispute: DisputeState::default(),
+                },
+            ),
+            transaction(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: amount("20.5"),
+                    dispute: DisputeState::default(),
+                },
+            ),
+            transaction(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount("30.25"),
+                    dispute: DisputeState::default(),
+                },
+            ),
+            // More than the client has, so it is rejected
+            transaction(
+                2,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount("50.0"),
+                    dispute: DisputeState::default(),
+                },
+            ),
+            transaction(2, 2, TransactionType::Dispute),
+        ];
+
+        for tx in feed {
+            if sender.send(tx).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+    let service = TransactionService::new(clients.clone(), TransactionInMemRepository::default());
+
+    let mut transactions = ChannelProvider { receiver }.subscribe_to_tx_stream().await;
+
+    while let Some(tx) = transactions.next().await {
+        let tx_id = tx.transaction_id();
+
+        if let Err(err) = service.process_transaction(tx).await {
+            eprintln!(
+                "Rejected transaction {} ({}): {}",
+                tx_id,
+                err.rejection_code().code(),
+                err
+            );
+        }
+    }
+
+    CsvStateExporter::stdout()
+        .with_sorted_output(true)
+        .export_state(
+            clients
+                .find_all_clients()
+                .await
+                .expect("The in-memory repository never fails"),
+        )
+        .await
+        .expect("Failed to export the state");
+}
@@ -0,0 +1,88 @@
+//! Keep the accounts and the transactions in PostgreSQL.
+//!
+//! The database at `DATABASE_URL` is brought up to date with the migrations of the crate,
+//! and the transactions of the CSV file given as the first argument are applied to the
+//! accounts stored there, so running it again with another file carries on from the state
+//! left by the previous runs.
+//!
+//! ```text
+//! DATABASE_URL=postgres://localhost/transactioner \
+//!     cargo run --example postgres --features postgres -- transactions.csv
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use futures::StreamExt;
+
+use transactioner::infrastructure::postgres::{self, PgClientRepository, PgTransactionRepository};
+use transactioner::{
+    CSVTransactionProvider, CsvStateExporter, ShareableClientRepository, TClientRepository,
+    TClientStateExporter, TTransactionService, TTransactionStreamProvider, TransactionService,
+};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let (Ok(database_url), Some(input)) = (
+        std::env::var("DATABASE_URL"),
+        std::env::args_os().nth(1).map(PathBuf::from),
+    ) else {
+        eprintln!("Usage: DATABASE_URL=postgres://... postgres <transactions.csv>");
+
+        return ExitCode::from(2);
+    };
+
+    let pool = match postgres::connect(&database_url).await {
+        Ok(pool) => pool,
+        Err(err) => {
+            eprintln!("Failed to connect to the database: {}", err);
+
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let file = match tokio::fs::File::open(&input).await {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Failed to open {}: {}", input.display(), err);
+
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let clients = ShareableClientRepository::from(PgClientRepository::new(pool.clone()));
+
+    let service = TransactionService::new(clients.clone(), PgTransactionRepository::new(pool));
+
+    let mut transactions = CSVTransactionProvider::new(file)
+        .subscribe_to_tx_stream()
+        .await;
+
+    let (mut accepted, mut rejected) = (0, 0);
+
+    while let Some(tx) = transactions.next().await {
+        match service.process_transaction(tx).await {
+            Ok(_) => accepted += 1,
+            Err(_) => rejected += 1,
+        }
+    }
+
+    eprintln!("Accepted {} transactions, rejected {}", accepted, rejected);
+
+    let exported = match clients.find_all_clients_sorted().await {
+        Ok(state) => CsvStateExporter::stdout()
+            .export_state(state)
+            .await
+            .map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+
+    match exported {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Failed to export the accounts: {}", err);
+
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Receive the transactions over HTTP, answering each request with their outcome.
+//!
+//! Transactions are submitted with `POST /transactions`, as a single JSON record or an array
+//! of them, and the response is sent once all of them have been processed. On Ctrl-C the
+//! server stops accepting requests, finishes the ones it received, and exports the state of
+//! the accounts as CSV to the standard output.
+//!
+//! ```text
+//! cargo run --example rest_server --features http
+//!
+//! curl -X POST localhost:8080/transactions \
+//!     -d '[{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"},
+//!          {"type": "withdrawal", "client": 1, "tx": 2, "amount": "5.0"}]'
+//! ```
+
+use futures::StreamExt;
+
+use transactioner::tx_reception::http::HttpTransactionProvider;
+use transactioner::tx_reception::ProcessingOutcome;
+use transactioner::{
+    ClientInMemRepository, CsvStateExporter, ShareableClientRepository,
+    TAcknowledgedStreamProvider, TClientRepository, TClientStateExporter, TRejectionReason,
+    TTransactionService, TransactionInMemRepository, TransactionService,
+};
+
+const LISTEN_ADDRESS: &str = "127.0.0.1:8080";
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+    let service = TransactionService::new(clients.clone(), TransactionInMemRepository::default());
+
+    let provider = HttpTransactionProvider::bind(LISTEN_ADDRESS)
+        .await?
+        .with_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        });
+
+    eprintln!(
+        "Receiving transactions on http://{}",
+        provider.local_addr()?
+    );
+
+    provider
+        .subscribe_to_acknowledged_stream()
+        .await
+        .for_each(|acknowledgeable| async {
+            let (tx, acknowledger) = acknowledgeable.into_parts();
+
+            let outcome = match service.process_transaction(tx).await {
+                Ok(processed) => ProcessingOutcome::from(processed),
+                Err(err) => ProcessingOutcome::Rejected(err.rejection_code()),
+            };
+
+            acknowledger.acknowledge(outcome).await;
+        })
+        .await;
+
+    CsvStateExporter::stdout()
+        .with_sorted_output(true)
+        .export_state(
+            clients
+                .find_all_clients()
+                .await
+                .expect("The in-memory repository never fails"),
+        )
+        .await
+        .map_err(std::io::Error::other)
+}
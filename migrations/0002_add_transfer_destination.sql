@@ -0,0 +1,2 @@
+-- The client receiving the funds, only set for transfers
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS to_client INTEGER;
@@ -0,0 +1,4 @@
+-- When the transaction happened, in seconds since the unix epoch, if known
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS occurred_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS transactions_occurred_at ON transactions (occurred_at);
@@ -0,0 +1,2 @@
+-- Closed accounts reject every transaction, for good
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS closed BOOLEAN NOT NULL DEFAULT FALSE;
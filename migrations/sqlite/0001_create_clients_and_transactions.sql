@@ -0,0 +1,33 @@
+-- The same schema as the PostgreSQL one, with amounts stored as fixed point integers with the
+-- same precision used by the engine
+
+CREATE TABLE IF NOT EXISTS clients (
+    client_id INTEGER PRIMARY KEY,
+    available BIGINT NOT NULL,
+    held BIGINT NOT NULL,
+    locked BOOLEAN NOT NULL,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    transaction_count BIGINT NOT NULL,
+    version BIGINT NOT NULL,
+    open_disputes BIGINT NOT NULL,
+    held_for_deposits BIGINT NOT NULL,
+    held_for_withdrawals BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS transactions (
+    tx_id BIGINT PRIMARY KEY,
+    client_id INTEGER NOT NULL,
+    -- 'deposit', 'withdrawal' or 'transfer', the only transactions which are stored
+    tx_type TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    client_sequence BIGINT,
+    -- NULL when never disputed, otherwise 'disputed', 'resolved' or 'chargeback'
+    dispute_state TEXT,
+    -- The client receiving the funds, only set for transfers
+    to_client INTEGER,
+    -- When the transaction happened, in seconds since the unix epoch, if known
+    occurred_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS transactions_client_id ON transactions (client_id);
+CREATE INDEX IF NOT EXISTS transactions_occurred_at ON transactions (occurred_at);
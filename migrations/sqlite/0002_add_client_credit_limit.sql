@@ -0,0 +1,2 @@
+-- How far below zero withdrawals can drive the available funds, NULL when there's no credit line
+ALTER TABLE clients ADD COLUMN credit_limit BIGINT;
@@ -0,0 +1,629 @@
+use std::io;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::header::{ETAG, IF_MATCH};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::models::client::{Client, ClientAccountStatus, ClientOperationError};
+use crate::models::money::DisplayAmount;
+use crate::models::transactions::{AuthorizationState, DisputeStage, Transaction, TransactionType};
+use crate::models::{ClientID, Timestamp, TransactionID};
+use crate::rejections::TRejectionReason;
+use crate::repositories::clients::TClientRepository;
+use crate::repositories::transactions::TTransactionRepository;
+use crate::repositories::RepoError;
+use crate::services::backpressure::{PersistenceBacklog, PersistenceHealth};
+
+/// The state of an account, as exposed by the admin API
+#[derive(Serialize)]
+struct AccountView {
+    client: ClientID,
+    available: DisplayAmount,
+    held: DisplayAmount,
+    total: DisplayAmount,
+    locked: bool,
+    closed: bool,
+    /// `null` for the accounts without a credit line
+    credit_limit: Option<DisplayAmount>,
+    version: u64,
+}
+
+/// A stored transaction, as exposed by the admin API
+#[derive(Serialize)]
+struct TransactionView {
+    tx: TransactionID,
+    client: ClientID,
+    #[serde(rename = "type")]
+    tx_type: &'static str,
+    /// `null` for the transactions without an amount, such as disputes
+    amount: Option<DisplayAmount>,
+    /// The client receiving the funds of a transfer
+    to: Option<ClientID>,
+    timestamp: Option<Timestamp>,
+    /// Where the disputes of the transaction stand, `not_disputed` for the transactions which
+    /// can't be disputed
+    dispute: DisputeStage,
+    /// Whether the funds of an authorization are still held, `null` for other transactions
+    authorization: Option<AuthorizationState>,
+}
+
+impl TransactionView {
+    /// The view of the transaction, with its amount written with `precision` decimal places
+    fn new(transaction: &Transaction, precision: u32) -> Self {
+        let authorization = match transaction.tx_type() {
+            TransactionType::Authorize { state, .. } => Some(*state),
+            _ => None,
+        };
+
+        Self {
+            tx: transaction.transaction_id(),
+            client: transaction.client(),
+            tx_type: transaction.tx_type().name(),
+            amount: transaction
+                .amount()
+                .ok()
+                .map(|amount| amount.display(precision)),
+            to: transaction.destination(),
+            timestamp: transaction.timestamp(),
+            dispute: transaction.dispute_state().stage(),
+            authorization,
+        }
+    }
+}
+
+/// The health of the engine, as exposed by the admin API
+#[derive(Serialize)]
+struct HealthView {
+    /// `lagging` while the ingestion is paused for the storage to catch up, `healthy` otherwise
+    persistence: &'static str,
+    /// The amount of changes applied in memory but not yet written to the storage
+    backlog: u64,
+    /// Whether the storage refused the last change written to it
+    failing: bool,
+}
+
+/// The body of the responses to the requests which were refused, identifying the reason with its
+/// [`RejectionCode`](crate::rejections::RejectionCode)
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    reason: &'static str,
+    message: String,
+}
+
+impl ErrorBody {
+    fn new<E>(error: &E) -> Self
+    where
+        E: TRejectionReason + std::fmt::Display,
+    {
+        Self {
+            code: error.rejection_code().code(),
+            reason: error.rejection_code().name(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// The account metadata which can be changed through the admin API
+#[derive(Deserialize)]
+struct AccountUpdate {
+    locked: bool,
+}
+
+impl AccountView {
+    /// The view of the account, with its balances written with `precision` decimal places
+    fn new(client: &Client, precision: u32) -> Self {
+        Self {
+            client: client.client_id(),
+            available: client.available().display(precision),
+            held: client.held().display(precision),
+            total: client.total().display(precision),
+            locked: *client.account_status() != ClientAccountStatus::Active,
+            closed: *client.account_status() == ClientAccountStatus::Closed,
+            credit_limit: client.credit_limit().map(|limit| limit.display(precision)),
+            version: client.version(),
+        }
+    }
+}
+
+/// The repositories the admin API reads from, shared with the transaction processing
+struct AdminState<CR, TR> {
+    clients: Arc<CR>,
+    transactions: Arc<TR>,
+    /// The amount of decimal places the amounts are written with
+    precision: u32,
+    /// The changes waiting to be written to the storage, when it is written in the background
+    backlog: Option<Arc<PersistenceBacklog>>,
+}
+
+impl<CR, TR> Clone for AdminState<CR, TR> {
+    fn clone(&self) -> Self {
+        Self {
+            clients: self.clients.clone(),
+            transactions: self.transactions.clone(),
+            precision: self.precision,
+            backlog: self.backlog.clone(),
+        }
+    }
+}
+
+/// Build the router of the admin API, which lets external tools read and change the accounts
+/// while the engine is running.
+///
+/// The accounts (`GET /clients`, `GET /clients/{client}`) and the stored transactions
+/// (`GET /transactions/{tx}`) are read as they are at the time of the request, in between the
+/// transactions being processed.
+///
+/// Every account is returned with its version as the `ETag`, and changes must carry the version
+/// they were based on in the `If-Match` header. Changes based on an outdated version are
+/// rejected with `412 Precondition Failed`, instead of clobbering whatever happened in between.
+///
+/// The amounts are written with `precision` decimal places, the ones they are processed with.
+///
+/// `GET /health` reports how far behind the storage is, given the `backlog` of the changes
+/// written to it in the background, and answers `503 Service Unavailable` while the ingestion
+/// is paused for it to catch up.
+pub fn router<CR, TR>(
+    client_repository: Arc<CR>,
+    transaction_repository: Arc<TR>,
+    precision: u32,
+    backlog: Option<Arc<PersistenceBacklog>>,
+) -> Router
+where
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
+{
+    Router::new()
+        .route("/clients", get(list_accounts::<CR, TR>))
+        .route(
+            "/clients/{client}",
+            get(get_account::<CR, TR>).put(update_account::<CR, TR>),
+        )
+        .route("/transactions/{tx}", get(get_transaction::<CR, TR>))
+        .route("/health", get(get_health::<CR, TR>))
+        .with_state(AdminState {
+            clients: client_repository,
+            transactions: transaction_repository,
+            precision,
+            backlog,
+        })
+}
+
+/// Serve the admin API on the given listener
+pub async fn serve<CR, TR>(
+    listener: TcpListener,
+    client_repository: Arc<CR>,
+    transaction_repository: Arc<TR>,
+    precision: u32,
+    backlog: Option<Arc<PersistenceBacklog>>,
+) -> io::Result<()>
+where
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
+{
+    axum::serve(
+        listener,
+        router(
+            client_repository,
+            transaction_repository,
+            precision,
+            backlog,
+        ),
+    )
+    .await
+}
+
+fn account_response(client: &Client, precision: u32) -> Response {
+    let etag = HeaderValue::from_str(&format!("\"{}\"", client.version()))
+        .expect("A quoted number is a valid header value");
+
+    ([(ETAG, etag)], Json(AccountView::new(client, precision))).into_response()
+}
+
+/// Changes based on an outdated version are a failed precondition, while every other failure
+/// of the repository means we can't serve the request for now
+fn error_response(err: RepoError) -> Response {
+    let status = match err {
+        RepoError::VersionConflict(_) => StatusCode::PRECONDITION_FAILED,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(ErrorBody::new(&err))).into_response()
+}
+
+/// Parse the version out of an `If-Match` header
+fn expected_version(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(IF_MATCH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .ok()
+}
+
+/// Every account, ordered by client id
+async fn list_accounts<CR, TR>(State(state): State<AdminState<CR, TR>>) -> Response
+where
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
+{
+    match state.clients.find_all_clients_sorted().await {
+        Ok(clients) => {
+            // Each account is only locked while it is read, so the processing carries on
+            let accounts =
+                clients
+                    .then(|client| async move {
+                        AccountView::new(&*client.lock().await, state.precision)
+                    })
+                    .collect::<Vec<_>>()
+                    .await;
+
+            Json(accounts).into_response()
+        }
+        Err(err) => error_response(err),
+    }
+}
+
+async fn get_account<CR, TR>(
+    State(state): State<AdminState<CR, TR>>,
+    Path(client_id): Path<ClientID>,
+) -> Response
+where
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
+{
+    match state.clients.find_client_by_id(client_id).await {
+        Ok(Some(client)) => account_response(&*client.lock().await, state.precision),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => error_response(err),
+    }
+}
+
+async fn update_account<CR, TR>(
+    State(state): State<AdminState<CR, TR>>,
+    Path(client_id): Path<ClientID>,
+    headers: HeaderMap,
+    Json(update): Json<AccountUpdate>,
+) -> Response
+where
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
+{
+    let Some(expected_version) = expected_version(&headers) else {
+        return StatusCode::PRECONDITION_REQUIRED.into_response();
+    };
+
+    let stored_client = match state.clients.find_client_by_id(client_id).await {
+        Ok(Some(stored_client)) => stored_client,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(err) => return error_response(err),
+    };
+
+    let mut client = stored_client.lock().await.clone();
+
+    // Closed accounts are closed for good
+    if *client.account_status() == ClientAccountStatus::Closed {
+        let err = ClientOperationError::AccountClosed;
+
+        return (StatusCode::CONFLICT, Json(ErrorBody::new(&err))).into_response();
+    }
+
+    client.set_account_status(if update.locked {
+        ClientAccountStatus::Frozen
+    } else {
+        ClientAccountStatus::Active
+    });
+
+    match state
+        .clients
+        .save_client_if_version(client, expected_version)
+        .await
+    {
+        Ok(stored_client) => account_response(&*stored_client.lock().await, state.precision),
+        Err(err) => error_response(err),
+    }
+}
+
+async fn get_transaction<CR, TR>(
+    State(state): State<AdminState<CR, TR>>,
+    Path(tx_id): Path<TransactionID>,
+) -> Response
+where
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
+{
+    match state.transactions.find_tx_by_id(tx_id).await {
+        Ok(Some(transaction)) => Json(TransactionView::new(
+            &*transaction.lock().await,
+            state.precision,
+        ))
+        .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => error_response(err),
+    }
+}
+
+/// Without a backlog, every change is written before moving on, so the storage is never behind
+async fn get_health<CR, TR>(State(state): State<AdminState<CR, TR>>) -> Response {
+    let (health, failing) = state
+        .backlog
+        .as_ref()
+        .map_or((PersistenceHealth::Healthy, false), |backlog| {
+            (backlog.health(), backlog.is_failing())
+        });
+
+    let (status, persistence) = match health {
+        PersistenceHealth::Healthy => (StatusCode::OK, "healthy"),
+        PersistenceHealth::Lagging { .. } => (StatusCode::SERVICE_UNAVAILABLE, "lagging"),
+    };
+
+    let view = HealthView {
+        persistence,
+        backlog: state
+            .backlog
+            .as_ref()
+            .map_or(0, |backlog| backlog.backlog()),
+        failing,
+    };
+
+    (status, Json(view)).into_response()
+}
+
+#[cfg(test)]
+mod admin_api_tests {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::header::{ETAG, IF_MATCH};
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::admin::router;
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::client::{Client, ClientAccountStatus};
+    use crate::models::transactions::{DisputeMode, DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::transactions::TTransactionRepository;
+    use crate::services::backpressure::PersistenceBacklog;
+
+    fn update(version: Option<&str>, locked: bool) -> Request<Body> {
+        let mut request = Request::put("/clients/1").header("content-type", "application/json");
+
+        if let Some(version) = version {
+            request = request.header(IF_MATCH, version);
+        }
+
+        request
+            .body(Body::from(format!(r#"{{"locked": {}}}"#, locked)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_optimistic_concurrency() {
+        let client_repository = Arc::new(ClientInMemRepository::default());
+        let transaction_repository = Arc::new(TransactionInMemRepository::default());
+
+        let stored_client = client_repository
+            .store_client(
+                Client::builder()
+                    .with_client_id(ClientID(1))
+                    .with_account_status(ClientAccountStatus::Frozen)
+                    .build(),
+            )
+            .await
+            .unwrap();
+
+        let response = router(
+            client_repository.clone(),
+            transaction_repository.clone(),
+            4,
+            None,
+        )
+        .oneshot(Request::get("/clients/1").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"0\"");
+
+        // Changes without a version are refused
+        let response = router(
+            client_repository.clone(),
+            transaction_repository.clone(),
+            4,
+            None,
+        )
+        .oneshot(update(None, false))
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+        let response = router(
+            client_repository.clone(),
+            transaction_repository.clone(),
+            4,
+            None,
+        )
+        .oneshot(update(Some("\"0\""), false))
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"1\"");
+        assert_eq!(
+            *stored_client.lock().await.account_status(),
+            ClientAccountStatus::Active
+        );
+
+        // The engine changes the account in the meantime
+        stored_client
+            .lock()
+            .await
+            .set_account_status(ClientAccountStatus::Frozen);
+
+        let response = router(
+            client_repository.clone(),
+            transaction_repository.clone(),
+            4,
+            None,
+        )
+        .oneshot(update(Some("\"1\""), false))
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["code"], "E3001");
+        assert_eq!(
+            *stored_client.lock().await.account_status(),
+            ClientAccountStatus::Frozen
+        );
+    }
+
+    async fn body(request: Request<Body>, router: axum::Router) -> serde_json::Value {
+        let response = router.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_read_endpoints() {
+        let client_repository = Arc::new(ClientInMemRepository::default());
+        let transaction_repository = Arc::new(TransactionInMemRepository::default());
+
+        for client in [2, 1] {
+            client_repository
+                .store_client(Client::builder().with_client_id(ClientID(client)).build())
+                .await
+                .unwrap();
+        }
+
+        let dispute = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(1))
+            .with_tx_type(TransactionType::Dispute)
+            .build();
+
+        transaction_repository
+            .store_tx(
+                Transaction::builder()
+                    .with_client_id(ClientID(1))
+                    .with_tx_id(TransactionID(1))
+                    .with_tx_type(TransactionType::Deposit {
+                        amount: MoneyType::new(15_000),
+                        dispute: DisputeState::Open {
+                            dispute: Box::new(dispute),
+                            mode: DisputeMode::Hold,
+                        },
+                    })
+                    .build(),
+            )
+            .await
+            .unwrap();
+
+        let router = router(client_repository, transaction_repository, 4, None);
+
+        let accounts = body(
+            Request::get("/clients").body(Body::empty()).unwrap(),
+            router.clone(),
+        )
+        .await;
+
+        let clients = accounts
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|account| account["client"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(clients, vec![1, 2]);
+
+        let transaction = body(
+            Request::get("/transactions/1").body(Body::empty()).unwrap(),
+            router.clone(),
+        )
+        .await;
+
+        assert_eq!(transaction["type"], "deposit");
+        assert_eq!(transaction["amount"], "1.5000");
+        assert_eq!(transaction["dispute"], "open");
+        assert!(transaction["authorization"].is_null());
+
+        let response = router
+            .oneshot(Request::get("/transactions/2").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let client_repository = Arc::new(ClientInMemRepository::default());
+        let transaction_repository = Arc::new(TransactionInMemRepository::default());
+
+        let backlog = Arc::new(PersistenceBacklog::new(2, 0));
+
+        let router = router(
+            client_repository,
+            transaction_repository,
+            4,
+            Some(backlog.clone()),
+        );
+
+        let health = body(
+            Request::get("/health").body(Body::empty()).unwrap(),
+            router.clone(),
+        )
+        .await;
+
+        assert_eq!(health["persistence"], "healthy");
+        assert_eq!(health["backlog"], 0);
+
+        backlog.enqueued();
+        backlog.write_failed();
+        backlog.enqueued();
+
+        let response = router
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        // Load balancers stop sending submissions while the storage catches up
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(health["persistence"], "lagging");
+        assert_eq!(health["backlog"], 2);
+        assert_eq!(health["failing"], true);
+    }
+}
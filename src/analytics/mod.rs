@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+
+use futures::{Stream, StreamExt};
+
+use crate::models::transactions::Transaction;
+
+/// Counts the incoming transactions per client id bucket and per chunk of the input, so we can
+/// see how skewed the load is (e.g. a handful of clients dominating a part of the input), to
+/// inform the sharding and cache sizing configuration.
+pub struct ActivityHeatmap {
+    client_bucket_size: u16,
+    chunk_size: u64,
+    state: Mutex<HeatmapState>,
+}
+
+#[derive(Default)]
+struct HeatmapState {
+    received: u64,
+    /// The transaction count, indexed by (input chunk, client bucket)
+    counts: BTreeMap<(u64, u16), u64>,
+}
+
+impl ActivityHeatmap {
+    /// Group the clients in buckets of `client_bucket_size` consecutive ids, and the input in
+    /// chunks of `chunk_size` transactions
+    pub fn new(client_bucket_size: u16, chunk_size: u64) -> Self {
+        Self {
+            client_bucket_size: client_bucket_size.max(1),
+            chunk_size: chunk_size.max(1),
+            state: Default::default(),
+        }
+    }
+
+    /// Count a transaction, in the order it was received
+    pub fn record(&self, transaction: &Transaction) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let chunk = state.received / self.chunk_size;
+        let bucket = transaction.client().0 / self.client_bucket_size;
+
+        state.received += 1;
+
+        *state.counts.entry((chunk, bucket)).or_default() += 1;
+    }
+
+    /// Count every transaction of the stream as it goes by
+    pub fn track<'a, S>(&'a self, stream: S) -> impl Stream<Item = Transaction> + 'a
+    where
+        S: Stream<Item = Transaction> + 'a,
+    {
+        stream.inspect(move |transaction| self.record(transaction))
+    }
+
+    /// Write the heatmap as CSV, with a row per chunk and client bucket which received
+    /// any transactions. Buckets are identified by their first client id.
+    pub fn export(&self, writer: impl Write) -> Result<(), csv::Error> {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let mut csv_writer = csv::Writer::from_writer(writer);
+
+        csv_writer.write_record(["chunk_start", "client_bucket_start", "transactions"])?;
+
+        for ((chunk, bucket), count) in &state.counts {
+            csv_writer.write_record([
+                (chunk * self.chunk_size).to_string(),
+                (bucket * self.client_bucket_size).to_string(),
+                count.to_string(),
+            ])?;
+        }
+
+        csv_writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod heatmap_tests {
+    use futures::{stream, StreamExt};
+
+    use crate::analytics::ActivityHeatmap;
+    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::ClientID;
+    use crate::models::TransactionID;
+
+    #[tokio::test]
+    async fn test_heatmap_export() {
+        let heatmap = ActivityHeatmap::new(10, 2);
+
+        let transactions = [1, 5, 12, 3, 25].map(|client| {
+            Transaction::builder()
+                .with_client_id(ClientID(client))
+                .with_tx_id(TransactionID(client.into()))
+                .with_tx_type(TransactionType::Dispute)
+                .build()
+        });
+
+        let tracked = heatmap
+            .track(stream::iter(transactions))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(tracked.len(), 5);
+
+        let mut output = Vec::new();
+
+        heatmap.export(&mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "chunk_start,client_bucket_start,transactions\n\
+             0,0,2\n\
+             2,0,1\n\
+             2,10,1\n\
+             4,20,1\n"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::Write;
+use std::sync::Mutex;
+
+use crate::events::TEffectsHandler;
+use crate::models::client::ClientAccountStatus;
+use crate::models::effects::{Effects, ExecutionMode, TransactionChange};
+use crate::models::money::format_amount;
+use crate::FLOATING_POINT_ACC;
+
+/// An audit log of the effects of every processed transaction, written as CSV.
+///
+/// Since it is built from the same effects which are applied to the state, the audit log of a
+/// dry run is exactly what would have been applied by a real run.
+pub struct AuditLog<W: Write> {
+    writer: Mutex<csv::Writer<W>>,
+}
+
+impl<W: Write> AuditLog<W> {
+    pub fn new(writer: W) -> Self {
+        let mut writer = csv::Writer::from_writer(writer);
+
+        if let Err(err) = writer.write_record([
+            "mode",
+            "client",
+            "tx",
+            "change",
+            "available_delta",
+            "held_delta",
+            "status",
+        ]) {
+            tracing::error!(error = %err, "Failed to write audit log header");
+        }
+
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<W> {
+        self.writer.into_inner().ok()?.into_inner().ok()
+    }
+}
+
+impl<W: Write + Send> TEffectsHandler for AuditLog<W> {
+    fn handle(&self, effects: &Effects, mode: ExecutionMode) {
+        let precision = FLOATING_POINT_ACC as u32;
+
+        let mode = match mode {
+            ExecutionMode::Apply => "apply",
+            ExecutionMode::DryRun => "dry-run",
+        };
+
+        let change = match effects.transaction_change {
+            TransactionChange::Store(_) => "store",
+            TransactionChange::OpenDispute(..) => "open-dispute",
+            TransactionChange::SettleDispute(_) => "settle-dispute",
+            TransactionChange::ReverseChargeback(_) => "reverse-chargeback",
+            TransactionChange::Capture(_) => "capture",
+            TransactionChange::Expire(_) => "expire",
+            TransactionChange::Credit(_) => "credit",
+            TransactionChange::CollectFee(_) => "collect-fee",
+            TransactionChange::Administer(_) => "administer",
+        };
+
+        let status = match effects.status_change {
+            None => "",
+            Some(ClientAccountStatus::Active) => "active",
+            Some(ClientAccountStatus::Frozen) => "frozen",
+            Some(ClientAccountStatus::Closed) => "closed",
+        };
+
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+
+        let result = writer
+            .write_record([
+                mode,
+                &effects.client.to_string(),
+                &effects.transaction().transaction_id().to_string(),
+                change,
+                &format_amount(effects.available_delta, precision),
+                &format_amount(effects.held_delta, precision),
+                status,
+            ])
+            .and_then(|_| Ok(writer.flush()?));
+
+        if let Err(err) = result {
+            tracing::error!(
+                tx = %effects.transaction().transaction_id(),
+                error = %err,
+                "Failed to write audit log entry"
+            );
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::cli::{PolicyArgs, ProcessArgs, ServeArgs, Storage, WithdrawalDisputes, MAX_PRECISION};
+
+/// The settings of the engine read from a TOML or YAML file given with `--config`, such as
+///
+/// ```toml
+/// precision = 4
+///
+/// [input]
+/// path = "transactions.csv"
+/// lenient = true
+///
+/// [storage]
+/// backend = "sqlite:state.db"
+///
+/// [output]
+/// path = "accounts.csv"
+///
+/// [policies]
+/// withdrawal_disputes = "hold"
+/// fees = { withdrawal = "0.5", chargeback = "1.5%" }
+/// fee_account = 9
+/// ```
+///
+/// The values are written the same way as on the command line, which takes precedence over the
+/// file: the file only fills in what the command line leaves at its defaults.
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    /// The amount of decimal places of the amounts read and exported
+    pub precision: Option<u32>,
+    pub input: InputConfig,
+    pub storage: StorageConfig,
+    pub output: OutputConfig,
+    pub channels: ChannelConfig,
+    pub policies: PolicyConfig,
+}
+
+/// Where the transactions come from
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// The CSV file processed by `process`, or a glob pattern matching the files
+    pub path: Option<PathBuf>,
+    /// The address `serve` receives the transactions on
+    pub listen: Option<SocketAddr>,
+    pub lenient: bool,
+    pub ingestion_timestamps: bool,
+    pub admin_source: bool,
+    pub merge_by_timestamp: bool,
+}
+
+/// Where the state of the accounts is kept
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// `memory` or `sqlite:<path>`
+    pub backend: Option<String>,
+    /// The amount of shards of the in memory storage
+    pub shards: Option<u64>,
+    pub wal: Option<PathBuf>,
+}
+
+/// Where the results of the runs are written
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// The exported state, instead of the standard output
+    pub path: Option<PathBuf>,
+    /// Export the accounts ordered by client id
+    pub sort_by_client: bool,
+    pub warnings: Option<PathBuf>,
+    pub dead_letters: Option<PathBuf>,
+}
+
+/// How many transactions can be waiting between the stages of the engine
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelConfig {
+    /// The transactions received by `serve` waiting to be processed
+    pub capacity: Option<u64>,
+}
+
+/// The rules the transactions are processed with, see [`PolicyArgs`]
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    pub withdrawal_disputes: Option<String>,
+    pub dispute_window_days: Option<u64>,
+    pub authorization_expiry_hours: Option<u64>,
+    /// The fee of each type of transaction, e.g. `withdrawal = "0.5"`
+    pub fees: BTreeMap<String, String>,
+    pub fee_account: Option<u16>,
+    /// e.g. `["withdrawals=1000/24h"]`
+    pub velocity_limits: Vec<String>,
+    pub blocked_clients: Option<PathBuf>,
+    #[cfg(feature = "screening")]
+    pub screening_url: Option<String>,
+    pub quarantine_blocked: bool,
+    #[cfg(feature = "rules")]
+    pub rules: Option<PathBuf>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read the configuration {0:?}")]
+    IoError(#[from] io::Error),
+    #[error("The configuration {0:?} is neither TOML (.toml) nor YAML (.yaml or .yml)")]
+    UnknownFormat(PathBuf),
+    #[error("Invalid TOML configuration {0}")]
+    InvalidToml(#[from] toml::de::Error),
+    #[error("Invalid YAML configuration {0}")]
+    InvalidYaml(#[from] serde_yaml::Error),
+    #[error("Invalid `{0}` in the configuration: {1}")]
+    InvalidValue(&'static str, String),
+}
+
+impl AppConfig {
+    /// Read the configuration of a TOML or YAML file, depending on its extension
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+
+        let contents = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Ok(toml::from_str(&contents)?),
+            Some("yaml" | "yml") => Ok(serde_yaml::from_str(&contents)?),
+            _ => Err(ConfigError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    /// Fill in the arguments of `process` which were not given on the command line
+    pub fn apply_to_process(self, args: &mut ProcessArgs) -> Result<(), ConfigError> {
+        if args.input.is_empty() {
+            args.input.extend(self.input.path);
+        }
+
+        args.output = args.output.take().or(self.output.path);
+        args.sort_by_client |= self.output.sort_by_client;
+        args.warnings = args.warnings.take().or(self.output.warnings);
+        args.dead_letters = args.dead_letters.take().or(self.output.dead_letters);
+        apply_precision(self.precision, &mut args.precision)?;
+        args.lenient |= self.input.lenient;
+        args.ingestion_timestamps |= self.input.ingestion_timestamps;
+        args.admin_source |= self.input.admin_source;
+        args.merge_by_timestamp |= self.input.merge_by_timestamp;
+
+        apply_storage(
+            self.storage,
+            &mut args.storage,
+            &mut args.shards,
+            &mut args.wal,
+        )?;
+
+        apply_policies(self.policies, &mut args.policies)
+    }
+
+    /// Fill in the arguments of `serve` which were not given on the command line
+    pub fn apply_to_serve(self, args: &mut ServeArgs) -> Result<(), ConfigError> {
+        if let Some(listen) = self
+            .input
+            .listen
+            .filter(|_| args.listen == default_listen())
+        {
+            args.listen = listen;
+        }
+
+        if self.channels.capacity == Some(0) {
+            return Err(ConfigError::InvalidValue(
+                "channels.capacity",
+                "the channels must hold at least one transaction".to_string(),
+            ));
+        }
+
+        args.channel_capacity = args.channel_capacity.or(self.channels.capacity);
+        args.output = args.output.take().or(self.output.path);
+        args.sort_by_client |= self.output.sort_by_client;
+        apply_precision(self.precision, &mut args.precision)?;
+
+        apply_storage(
+            self.storage,
+            &mut args.storage,
+            &mut args.shards,
+            &mut args.wal,
+        )?;
+
+        apply_policies(self.policies, &mut args.policies)
+    }
+}
+
+/// The address `serve` listens on when none is given
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8080))
+}
+
+fn apply_precision(config: Option<u32>, precision: &mut Option<u32>) -> Result<(), ConfigError> {
+    if config.is_some_and(|config| i64::from(config) > MAX_PRECISION) {
+        return Err(ConfigError::InvalidValue(
+            "precision",
+            format!(
+                "the amounts can have at most {} decimal places",
+                MAX_PRECISION
+            ),
+        ));
+    }
+
+    *precision = precision.or(config);
+
+    Ok(())
+}
+
+fn apply_storage(
+    config: StorageConfig,
+    storage: &mut Storage,
+    shards: &mut Option<u64>,
+    wal: &mut Option<PathBuf>,
+) -> Result<(), ConfigError> {
+    if let Some(backend) = config.backend.filter(|_| *storage == Storage::Memory) {
+        *storage = backend
+            .parse()
+            .map_err(|err| ConfigError::InvalidValue("storage.backend", err))?;
+    }
+
+    if config.shards == Some(0) {
+        return Err(ConfigError::InvalidValue(
+            "storage.shards",
+            "there must be at least one shard".to_string(),
+        ));
+    }
+
+    *shards = shards.or(config.shards);
+    *wal = wal.take().or(config.wal);
+
+    Ok(())
+}
+
+fn apply_policies(config: PolicyConfig, policies: &mut PolicyArgs) -> Result<(), ConfigError> {
+    if let (WithdrawalDisputes::Hold, Some(disputes)) =
+        (policies.withdrawal_disputes, config.withdrawal_disputes)
+    {
+        policies.withdrawal_disputes = WithdrawalDisputes::from_str(&disputes, false)
+            .map_err(|err| ConfigError::InvalidValue("policies.withdrawal_disputes", err))?;
+    }
+
+    policies.dispute_window_days = policies.dispute_window_days.or(config.dispute_window_days);
+    policies.authorization_expiry_hours = policies
+        .authorization_expiry_hours
+        .or(config.authorization_expiry_hours);
+    policies.fee_account = policies.fee_account.or(config.fee_account);
+
+    if policies.fees.is_empty() {
+        policies.fees = config
+            .fees
+            .iter()
+            .map(|(tx_type, fee)| format!("{}={}", tx_type, fee).parse())
+            .collect::<Result<_, _>>()
+            .map_err(|err| ConfigError::InvalidValue("policies.fees", err))?;
+    }
+
+    if !policies.fees.is_empty() && policies.fee_account.is_none() {
+        return Err(ConfigError::InvalidValue(
+            "policies.fees",
+            "the fees need a `fee_account` to be credited to".to_string(),
+        ));
+    }
+
+    if policies.velocity_limits.is_empty() {
+        policies.velocity_limits = config
+            .velocity_limits
+            .iter()
+            .map(|limit| limit.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|err| ConfigError::InvalidValue("policies.velocity_limits", err))?;
+    }
+
+    policies.blocked_clients = policies.blocked_clients.take().or(config.blocked_clients);
+    policies.quarantine_blocked |= config.quarantine_blocked;
+
+    #[cfg(feature = "screening")]
+    if let Some(url) = config
+        .screening_url
+        .filter(|_| policies.screening_url.is_none())
+    {
+        policies.screening_url =
+            Some(url.parse::<reqwest::Url>().map_err(|err| {
+                ConfigError::InvalidValue("policies.screening_url", err.to_string())
+            })?);
+    }
+
+    #[cfg(feature = "rules")]
+    {
+        policies.rules = policies.rules.take().or(config.rules);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod config_tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use crate::cli::config::{AppConfig, ConfigError};
+    use crate::cli::{Cli, Command, Fee, ProcessArgs, ServeArgs, Storage, WithdrawalDisputes};
+
+    const CONFIG: &str = r#"
+        precision = 2
+
+        [input]
+        path = "transactions.csv"
+        listen = "0.0.0.0:9000"
+        lenient = true
+
+        [storage]
+        backend = "sqlite:state.db"
+        shards = 16
+
+        [output]
+        path = "accounts.csv"
+        sort_by_client = true
+
+        [channels]
+        capacity = 128
+
+        [policies]
+        withdrawal_disputes = "provisional-credit"
+        fees = { withdrawal = "0.5" }
+        fee_account = 9
+        velocity_limits = ["transactions=10/1h"]
+    "#;
+
+    fn process_args(args: &[&str]) -> ProcessArgs {
+        let cli = Cli::try_parse_from(["transactioner", "process"].iter().chain(args)).unwrap();
+
+        match cli.command {
+            Command::Process(args) => args,
+            _ => panic!("Expected the process command"),
+        }
+    }
+
+    fn serve_args(args: &[&str]) -> ServeArgs {
+        let cli = Cli::try_parse_from(["transactioner", "serve"].iter().chain(args)).unwrap();
+
+        match cli.command {
+            Command::Serve(args) => args,
+            _ => panic!("Expected the serve command"),
+        }
+    }
+
+    #[test]
+    fn test_process_config() {
+        let config = toml::from_str::<AppConfig>(CONFIG).unwrap();
+
+        let mut args = process_args(&["--config", "app.toml", "--output", "out.csv"]);
+
+        config.apply_to_process(&mut args).unwrap();
+
+        assert_eq!(args.input, vec![PathBuf::from("transactions.csv")]);
+        // The command line takes precedence over the file
+        assert_eq!(args.output, Some(PathBuf::from("out.csv")));
+        assert_eq!(args.precision, Some(2));
+        assert!(args.lenient);
+        assert!(args.sort_by_client);
+        assert_eq!(args.storage, Storage::Sqlite("state.db".into()));
+        assert_eq!(args.shards, Some(16));
+        assert_eq!(
+            args.policies.withdrawal_disputes,
+            WithdrawalDisputes::ProvisionalCredit
+        );
+        assert_eq!(args.policies.fees[0].fee, Fee::Flat("0.5".parse().unwrap()));
+        assert_eq!(args.policies.velocity_limits.len(), 1);
+
+        // Without a configuration, the input is still required
+        assert!(Cli::try_parse_from(["transactioner", "process"]).is_err());
+    }
+
+    #[test]
+    fn test_serve_config() {
+        let config = serde_yaml::from_str::<AppConfig>(
+            "input:\n  listen: 0.0.0.0:9000\nchannels:\n  capacity: 128\n",
+        )
+        .unwrap();
+
+        let mut args = serve_args(&["--config", "app.yaml"]);
+
+        config.apply_to_serve(&mut args).unwrap();
+
+        assert_eq!(args.listen.port(), 9000);
+        assert_eq!(args.channel_capacity, Some(128));
+        assert_eq!(args.storage, Storage::Memory);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let apply = |config: &str| {
+            toml::from_str::<AppConfig>(config)
+                .unwrap()
+                .apply_to_process(&mut process_args(&["input.csv"]))
+        };
+
+        assert!(matches!(
+            apply("[storage]\nbackend = \"postgres\""),
+            Err(ConfigError::InvalidValue("storage.backend", _))
+        ));
+        assert!(matches!(
+            apply("[policies]\nfees = { withdrawal = \"0.5\" }"),
+            Err(ConfigError::InvalidValue("policies.fees", _))
+        ));
+        assert!(matches!(
+            apply("[policies]\nvelocity_limits = [\"deposits=1/1h\"]"),
+            Err(ConfigError::InvalidValue("policies.velocity_limits", _))
+        ));
+
+        // Misspelled settings are never silently ignored
+        assert!(toml::from_str::<AppConfig>("[input]\nlenent = true").is_err());
+    }
+}
@@ -0,0 +1,817 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use thiserror::Error;
+
+use transactioner::limits::VelocityLimit;
+use transactioner::models::money::{self, parse_amount, AmountParseError};
+use transactioner::repositories::RepoError;
+use transactioner::screening::ScreeningError;
+use transactioner::services::decision::DisputePolicy;
+use transactioner::services::speculative::BatchError;
+use transactioner::state_exporter::comparison::StateComparisonError;
+use transactioner::state_exporter::{statements, StateExporterError};
+use transactioner::wal::WalError;
+use transactioner::MoneyType;
+
+#[cfg(feature = "config")]
+pub mod config;
+
+/// Exit code of runs which failed because of their input, rather than of the engine
+const EXIT_INVALID_INPUT: u8 = 3;
+
+/// Exit code of runs whose results are not the expected ones
+const EXIT_MISMATCH: u8 = 4;
+
+/// The most decimal places the amounts can have, as the bounds of the arguments
+const MAX_PRECISION: i64 = money::MAX_PRECISION as i64;
+
+/// The types of transactions which can be charged a fee
+const CHARGEABLE_TYPES: [&str; 8] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "representment",
+    "authorize",
+    "capture",
+];
+
+/// Processes the transactions of client accounts and reports the resulting state of the accounts
+#[derive(Parser, Debug)]
+#[command(name = "transactioner", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Process a CSV file of transactions and export the state of the accounts as CSV
+    Process(ProcessArgs),
+    /// Receive transactions over HTTP until stopped, then export the state of the accounts as CSV
+    Serve(ServeArgs),
+    /// Check that every row of a CSV file of transactions is valid, without processing them
+    Verify(VerifyArgs),
+    /// Rebuild the state of the accounts from a write-ahead log, comparing it with the stored
+    /// state to detect divergences
+    Replay(ReplayArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ProcessArgs {
+    /// The CSV files with the transactions to process (`-` for the standard input), unless
+    /// they're given by the configuration. Quoted glob patterns (e.g. `"txs-*.csv"`) are
+    /// expanded to the files they match, in order.
+    #[arg(required_unless_present = "config")]
+    pub input: Vec<PathBuf>,
+    /// Read the settings which are not given on the command line from this TOML or YAML file
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Export the state of the accounts to this file instead of the standard output
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Export the accounts ordered by client id, so the output is the same from one run to the
+    /// next
+    #[arg(long)]
+    pub sort_by_client: bool,
+    /// The format the state of the accounts is exported in
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub format: ExportFormat,
+    /// Skip the invalid rows of the input, instead of stopping at the first one
+    #[arg(long)]
+    pub lenient: bool,
+    /// Timestamp the transactions without a `timestamp` column with the time they are read at
+    #[arg(long)]
+    pub ingestion_timestamps: bool,
+    /// Trust the input with administrative transactions, such as unfreezing accounts
+    #[arg(long)]
+    pub admin_source: bool,
+    /// Show how far the input has been read on the standard error, as the share of the size of
+    /// the input files read so far along with the rows read per second
+    #[arg(long)]
+    pub progress: bool,
+    /// Merge the transactions of the input files by timestamp, instead of processing one file
+    /// after the other
+    #[arg(long)]
+    pub merge_by_timestamp: bool,
+    /// Log the accepted transactions to this write-ahead log, replaying it first if it exists
+    #[arg(long)]
+    pub wal: Option<PathBuf>,
+    /// Where to keep the state of the accounts, `memory` or `sqlite:<path>`
+    #[arg(long, default_value = "memory")]
+    pub storage: Storage,
+    /// Split the accounts kept in memory into this many shards
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub shards: Option<u64>,
+    /// Process the input in batches of this many transactions, running the ones which don't
+    /// involve the clients or transactions of an earlier one of their batch in parallel
+    #[arg(long, value_name = "TRANSACTIONS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub parallel_batches: Option<u64>,
+    /// The amount of decimal places of the amounts read and exported
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=MAX_PRECISION))]
+    pub precision: Option<u32>,
+    #[command(flatten)]
+    pub policies: PolicyArgs,
+    /// Write the warnings raised while processing to this file
+    #[arg(long)]
+    pub warnings: Option<PathBuf>,
+    /// Write the rejected transactions, along with the reason they were rejected, to this file
+    #[arg(long)]
+    pub dead_letters: Option<PathBuf>,
+    /// The format of the rejected transactions file
+    #[arg(long, value_enum, default_value_t = DeadLetterFormat::Csv, requires = "dead_letters")]
+    pub dead_letter_format: DeadLetterFormat,
+    /// Process the rejected transactions once more after the rest of the input, as some are
+    /// only rejected because of the order they arrived in
+    #[arg(long)]
+    pub retry_rejected: bool,
+    /// Export the activity heatmap of the input to this file
+    #[arg(long)]
+    pub heatmap: Option<PathBuf>,
+    /// Once the input is processed, release the funds held by the authorizations which expired
+    /// by this unix timestamp without being captured
+    #[arg(
+        long,
+        value_name = "TIMESTAMP",
+        requires = "authorization_expiry_hours"
+    )]
+    pub expire_holds_at: Option<u64>,
+    /// Write the manifest of the run, with the checksums of its inputs and outputs, to this file
+    #[cfg(feature = "json")]
+    #[arg(long)]
+    pub manifest: Option<PathBuf>,
+    /// Also export the state of the accounts to this Parquet file
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "FILE")]
+    pub parquet_state: Option<PathBuf>,
+    /// Export the processed transactions, with the outcome of their disputes, to this Parquet
+    /// file
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "FILE")]
+    pub parquet_transactions: Option<PathBuf>,
+    #[command(flatten)]
+    pub snapshots: SnapshotArgs,
+    #[command(flatten)]
+    pub statements: StatementArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// The address to receive the transactions on, through `POST /transactions`
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+    /// Also receive the transactions over gRPC on this address, through the
+    /// `TransactionIngestion` service
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    pub grpc: Option<SocketAddr>,
+    /// Also serve the admin API on this address
+    #[arg(long)]
+    pub admin: Option<SocketAddr>,
+    /// Read the settings which are not given on the command line from this TOML or YAML file
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Once stopped with Ctrl-C or SIGTERM, export the state of the accounts to this file
+    /// instead of the standard output
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Export the accounts ordered by client id, so the output is the same from one run to the
+    /// next
+    #[arg(long)]
+    pub sort_by_client: bool,
+    /// The format the state of the accounts is exported in
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub format: ExportFormat,
+    /// Log the accepted transactions to this write-ahead log, replaying it first if it exists
+    #[arg(long)]
+    pub wal: Option<PathBuf>,
+    /// Where to keep the state of the accounts, `memory` or `sqlite:<path>`
+    #[arg(long, default_value = "memory")]
+    pub storage: Storage,
+    /// Split the accounts kept in memory into this many shards
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub shards: Option<u64>,
+    /// The amount of received transactions which can be waiting to be processed, after which
+    /// the submissions wait for the processing to catch up
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub channel_capacity: Option<u64>,
+    /// Before receiving any transaction, load the accounts of this many of the most recently
+    /// active clients of the storage
+    #[arg(long, value_name = "CLIENTS")]
+    pub warm_up_clients: Option<usize>,
+    /// Before receiving any transaction, load this many of the most recent transactions of the
+    /// storage which can still be disputed
+    #[arg(long, value_name = "TRANSACTIONS")]
+    pub warm_up_transactions: Option<usize>,
+    /// Write the accounts to the storage in the background, pausing the ingestion while more
+    /// than this many changes are waiting to be written, until half of them are
+    #[arg(long, value_name = "CHANGES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub write_behind: Option<u64>,
+    /// The amount of decimal places of the amounts received, served and exported
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=MAX_PRECISION))]
+    pub precision: Option<u32>,
+    #[command(flatten)]
+    pub policies: PolicyArgs,
+    #[command(flatten)]
+    pub snapshots: SnapshotArgs,
+    /// Release the funds held by expired authorizations every this many seconds
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "authorization_expiry_hours",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub hold_sweep_interval: Option<u64>,
+    /// POST a JSON notification to this URL whenever an account is frozen or charged back
+    #[cfg(feature = "webhooks")]
+    #[arg(long)]
+    pub webhook: Option<reqwest::Url>,
+}
+
+/// The rules the transactions are processed with
+#[derive(Args, Debug)]
+pub struct PolicyArgs {
+    /// How disputes of withdrawals affect the accounts
+    #[arg(long, value_enum, default_value_t = WithdrawalDisputes::Hold)]
+    pub withdrawal_disputes: WithdrawalDisputes,
+    /// Reject the disputes filed more than this many days after the disputed transaction, when
+    /// both have timestamps
+    #[arg(long)]
+    pub dispute_window_days: Option<u64>,
+    /// Reject the captures of authorizations made more than this many hours before them, when
+    /// both have timestamps
+    #[arg(long)]
+    pub authorization_expiry_hours: Option<u64>,
+    /// Charge a fee for a type of transaction, as `<type>=<amount>` or `<type>=<percentage>%`
+    /// (e.g. `withdrawal=0.5` or `chargeback=1.5%`)
+    #[arg(long = "fee", value_name = "TYPE=FEE", requires = "fee_account")]
+    pub fees: Vec<FeeArg>,
+    /// The client the fees are credited to
+    #[arg(long)]
+    pub fee_account: Option<u16>,
+    /// Limit what each client can do within a rolling window, as `withdrawals=<amount>/<window>`
+    /// or `transactions=<count>/<window>`, with windows such as `90s`, `30m`, `24h` or `7d`
+    /// (e.g. `withdrawals=1000/24h`). Only transactions with timestamps are limited.
+    #[arg(long = "velocity-limit", value_name = "KIND=MAX/WINDOW")]
+    pub velocity_limits: Vec<VelocityLimitArg>,
+    /// Block the clients listed in this file, with a client id per line
+    #[arg(long, value_name = "FILE")]
+    pub blocked_clients: Option<PathBuf>,
+    /// Ask the screening service at this URL whether each client is blocked
+    #[cfg(feature = "screening")]
+    #[arg(long, conflicts_with = "blocked_clients")]
+    pub screening_url: Option<reqwest::Url>,
+    /// Set the transactions of blocked clients aside instead of rejecting them
+    #[arg(long)]
+    pub quarantine_blocked: bool,
+    /// Reject the transactions matching the rules declared in this TOML or YAML file
+    #[cfg(feature = "rules")]
+    #[arg(long, value_name = "FILE")]
+    pub rules: Option<PathBuf>,
+}
+
+/// A fee charged for a type of transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeArg {
+    pub tx_type: String,
+    pub fee: Fee,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fee {
+    Flat(AmountArg),
+    /// A share of the amount, in basis points
+    Percentage(u32),
+}
+
+impl FromStr for FeeArg {
+    type Err = String;
+
+    fn from_str(fee: &str) -> Result<Self, Self::Err> {
+        let (tx_type, fee) = fee
+            .split_once('=')
+            .ok_or_else(|| "expected `<type>=<amount>` or `<type>=<percentage>%`".to_string())?;
+
+        if !CHARGEABLE_TYPES.contains(&tx_type) {
+            return Err(format!(
+                "`{}` can't be charged, expected one of {}",
+                tx_type,
+                CHARGEABLE_TYPES.join(", ")
+            ));
+        }
+
+        let fee = match fee.strip_suffix('%') {
+            // Percentages with two decimal places are exactly basis points
+            Some(percentage) => parse_amount(percentage, 2)
+                .ok()
+                .and_then(|basis_points| u32::try_from(basis_points.raw()).ok())
+                .map(Fee::Percentage)
+                .ok_or_else(|| format!("invalid percentage `{}`", percentage))?,
+            None => Fee::Flat(fee.parse()?),
+        };
+
+        Ok(Self {
+            tx_type: tx_type.to_string(),
+            fee,
+        })
+    }
+}
+
+/// An amount given on the command line, which is only converted once the amount of decimal
+/// places of the run is known, as the amounts are stored with as many of them as the input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmountArg(String);
+
+impl AmountArg {
+    /// The amount with the given amount of decimal places, which must be enough for it
+    pub fn at_precision(&self, precision: u32) -> Result<MoneyType, CliError> {
+        parse_amount(&self.0, precision).map_err(|err| CliError::InvalidAmount(self.0.clone(), err))
+    }
+}
+
+impl FromStr for AmountArg {
+    type Err = String;
+
+    fn from_str(amount: &str) -> Result<Self, Self::Err> {
+        parse_amount(amount, MAX_PRECISION as u32)
+            .ok()
+            .filter(|amount| !amount.is_negative())
+            .map(|_| Self(amount.trim().to_string()))
+            .ok_or_else(|| format!("invalid amount `{}`", amount))
+    }
+}
+
+/// A rolling limit every client is held to, whose amount is only converted once the amount
+/// of decimal places of the run is known, as with [`AmountArg`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VelocityLimitArg {
+    WithdrawnAmount { max: AmountArg, window: Duration },
+    TransactionCount { max: u64, window: Duration },
+}
+
+impl VelocityLimitArg {
+    /// The limit, with its amount at the given amount of decimal places
+    pub fn at_precision(&self, precision: u32) -> Result<VelocityLimit, CliError> {
+        Ok(match self {
+            VelocityLimitArg::WithdrawnAmount { max, window } => VelocityLimit::WithdrawnAmount {
+                max: max.at_precision(precision)?,
+                window: *window,
+            },
+            VelocityLimitArg::TransactionCount { max, window } => VelocityLimit::TransactionCount {
+                max: *max,
+                window: *window,
+            },
+        })
+    }
+}
+
+impl FromStr for VelocityLimitArg {
+    type Err = String;
+
+    fn from_str(limit: &str) -> Result<Self, Self::Err> {
+        let (kind, max, window) = limit
+            .split_once('=')
+            .and_then(|(kind, rest)| {
+                let (max, window) = rest.split_once('/')?;
+
+                Some((kind, max, window))
+            })
+            .ok_or_else(|| "expected `<kind>=<max>/<window>`".to_string())?;
+
+        let window = parse_window(window)?;
+
+        match kind {
+            "withdrawals" => Ok(VelocityLimitArg::WithdrawnAmount {
+                max: max.parse()?,
+                window,
+            }),
+            "transactions" => max
+                .parse()
+                .map(|max| VelocityLimitArg::TransactionCount { max, window })
+                .map_err(|_| format!("invalid count `{}`", max)),
+            kind => Err(format!(
+                "unknown limit `{}`, expected `withdrawals` or `transactions`",
+                kind
+            )),
+        }
+    }
+}
+
+/// Parse a window of time such as `30m` or `24h`
+fn parse_window(window: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid window `{}`, expected e.g. `30m` or `24h`", window);
+
+    let (amount, seconds) = [("s", 1), ("m", 60), ("h", 60 * 60), ("d", 24 * 60 * 60)]
+        .into_iter()
+        .find_map(|(unit, seconds)| Some((window.strip_suffix(unit)?, seconds)))
+        .ok_or_else(invalid)?;
+
+    amount
+        .parse::<u64>()
+        .ok()
+        .filter(|amount| *amount > 0)
+        .and_then(|amount| amount.checked_mul(seconds))
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// Periodic exports of the state of the accounts while the transactions are processed
+#[derive(Args, Debug)]
+pub struct SnapshotArgs {
+    /// Export snapshots of the state of the accounts to timestamped files in this directory
+    #[arg(long)]
+    pub snapshot_dir: Option<PathBuf>,
+    /// Take a snapshot every this many processed transactions
+    #[arg(long, requires = "snapshot_dir", value_parser = clap::value_parser!(u64).range(1..))]
+    pub snapshot_every: Option<u64>,
+    /// Take a snapshot every this many seconds
+    #[arg(long, requires = "snapshot_dir", value_parser = clap::value_parser!(u64).range(1..))]
+    pub snapshot_interval: Option<u64>,
+}
+
+/// Statements of the accounts, exported once the transactions are processed
+#[derive(Args, Debug)]
+pub struct StatementArgs {
+    /// Write the statement of each account, listing its transactions with the running
+    /// balances, to its own file in this directory
+    #[arg(long)]
+    pub statements: Option<PathBuf>,
+    /// Only write the statement of this client
+    #[arg(long, requires = "statements")]
+    pub statement_client: Option<u16>,
+    /// The format of the statements
+    #[arg(long, value_enum, default_value_t = StatementFormat::Csv, requires = "statements")]
+    pub statement_format: StatementFormat,
+}
+
+/// The choices of [`statements::StatementFormat`]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatementFormat {
+    /// A CSV file per account
+    Csv,
+    /// A JSON file per account, with an array of its entries
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl From<StatementFormat> for statements::StatementFormat {
+    fn from(format: StatementFormat) -> Self {
+        match format {
+            StatementFormat::Csv => statements::StatementFormat::Csv,
+            #[cfg(feature = "json")]
+            StatementFormat::Json => statements::StatementFormat::Json,
+        }
+    }
+}
+
+/// The choices of [`DisputePolicy`]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WithdrawalDisputes {
+    /// Skip them, with a warning
+    Ignore,
+    /// Hold the withdrawn amount until the dispute is settled
+    Hold,
+    /// Credit the withdrawn amount right away, taking it back if charged back
+    ProvisionalCredit,
+}
+
+impl From<WithdrawalDisputes> for DisputePolicy {
+    fn from(disputes: WithdrawalDisputes) -> Self {
+        match disputes {
+            WithdrawalDisputes::Ignore => DisputePolicy::Ignore,
+            WithdrawalDisputes::Hold => DisputePolicy::Hold,
+            WithdrawalDisputes::ProvisionalCredit => DisputePolicy::ProvisionalCredit,
+        }
+    }
+}
+
+/// Where the state of the accounts is kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Storage {
+    /// In memory, for the length of the run
+    Memory,
+    /// In the SQLite database of the given file, which is created if it doesn't exist
+    Sqlite(PathBuf),
+}
+
+impl FromStr for Storage {
+    type Err = String;
+
+    fn from_str(storage: &str) -> Result<Self, Self::Err> {
+        match storage.split_once(':') {
+            None if storage == "memory" => Ok(Storage::Memory),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Storage::Sqlite(PathBuf::from(path))),
+            _ => Err("expected `memory` or `sqlite:<path>`".to_string()),
+        }
+    }
+}
+
+/// How the state of the accounts is exported
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A CSV row per account
+    Csv,
+    /// A JSON array with a record per account
+    #[cfg(feature = "json")]
+    Json,
+    /// A line of JSON per account
+    #[cfg(feature = "json")]
+    Ndjson,
+}
+
+/// How the rejected transactions are written
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadLetterFormat {
+    /// The CSV input format, followed by the rejection code and error
+    Csv,
+    /// The JSON Lines input format, with the rejection code and error
+    #[cfg(feature = "json")]
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// The CSV files with the transactions to check (`-` for the standard input), or quoted
+    /// glob patterns matching them
+    #[arg(required = true)]
+    pub input: Vec<PathBuf>,
+    /// Trust the input with administrative transactions, such as unfreezing accounts
+    #[arg(long)]
+    pub admin_source: bool,
+    /// Merge the transactions of the input files by timestamp, when comparing the resulting
+    /// state with the expected one
+    #[arg(long)]
+    pub merge_by_timestamp: bool,
+    /// Also process the input and compare the resulting state of the accounts with this CSV
+    /// export, writing the differences to the standard output
+    #[arg(long, value_name = "FILE")]
+    pub expected: Option<PathBuf>,
+    /// The amount of decimal places of the amounts of the input and of the expected state
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=MAX_PRECISION))]
+    pub precision: Option<u32>,
+    /// The policies the input is processed with, when comparing it with the expected state
+    #[command(flatten)]
+    pub policies: PolicyArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// The write-ahead log with the transactions to replay, in the order they were accepted
+    pub wal: PathBuf,
+    /// The storage with the state to compare the replayed state with, `sqlite:<path>`. Nothing
+    /// is compared with the default, `memory`.
+    #[arg(long, default_value = "memory")]
+    pub storage: Storage,
+    /// Export the replayed state of the accounts to this file instead of the standard output
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Export the accounts ordered by client id, so the output is the same from one run to the
+    /// next
+    #[arg(long)]
+    pub sort_by_client: bool,
+    /// The format the state of the accounts is exported in
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub format: ExportFormat,
+    /// The amount of decimal places of the amounts the transactions were processed with
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=MAX_PRECISION))]
+    pub precision: Option<u32>,
+    /// The policies the transactions were processed with, so they are replayed the same way
+    #[command(flatten)]
+    pub policies: PolicyArgs,
+}
+
+/// The reasons a command could not run to completion
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("Failed to open {0:?}: {1}")]
+    OpenFailed(PathBuf, #[source] io::Error),
+    #[cfg(feature = "parquet")]
+    #[error("Failed to read {0:?}: {1}")]
+    ParquetInput(
+        PathBuf,
+        #[source] transactioner::tx_reception::parquet::ParquetInputError,
+    ),
+    #[cfg(feature = "s3")]
+    #[error("S3 failure: {0}")]
+    S3(#[from] transactioner::tx_reception::s3::S3Error),
+    #[error("Failed to write {0:?}: {1}")]
+    WriteFailed(PathBuf, String),
+    #[error("The amount {0:?} does not fit the precision of the amounts: {1}")]
+    InvalidAmount(String, #[source] AmountParseError),
+    #[error("The input has {0} invalid rows")]
+    InvalidInput(usize),
+    #[error("No input was given, neither on the command line nor by the configuration")]
+    MissingInput,
+    #[error("Invalid input pattern {0:?}: {1}")]
+    InvalidPattern(String, #[source] glob::PatternError),
+    #[error("No input matches {0:?}")]
+    NoMatchingInput(String),
+    #[error("The stored state of {0} clients diverged from the replay of their transactions")]
+    Diverged(usize),
+    #[error("The state of the accounts has {0} differences with the expected one")]
+    StateMismatch(usize),
+    #[error("Failed to compare the state with the expected one: {0}")]
+    Comparison(#[from] StateComparisonError),
+    #[error("Failed to recover the write-ahead log: {0}")]
+    WriteAheadLog(#[from] WalError),
+    #[error("Storage failure: {0}")]
+    Repository(#[from] RepoError),
+    #[cfg(feature = "http")]
+    #[error("{0} only applies to a persistent storage")]
+    NotPersistent(&'static str),
+    #[error("Failed to process a batch: {0}")]
+    Batch(#[from] BatchError),
+    #[error("Failed to export the state: {0}")]
+    Export(#[from] StateExporterError),
+    #[error("Failed to load the blocked clients: {0}")]
+    Screening(#[from] ScreeningError),
+    #[cfg(feature = "rules")]
+    #[error("Failed to load the rules: {0}")]
+    Rules(#[from] transactioner::services::rules::RuleError),
+    #[cfg(feature = "config")]
+    #[error("Failed to load the configuration: {0}")]
+    Config(#[from] config::ConfigError),
+    #[cfg(feature = "sqlite")]
+    #[error("Failed to open the database: {0}")]
+    Database(#[from] transactioner::infrastructure::sqlite::SqliteError),
+    #[cfg(not(all(feature = "http", feature = "sqlite", feature = "config")))]
+    #[error("This build does not support {0:?}, it needs the {1:?} feature")]
+    Unsupported(&'static str, &'static str),
+    #[error("IO failure: {0}")]
+    IoError(#[from] io::Error),
+}
+
+impl CliError {
+    /// Invalid input is told apart from failures of the engine, while invalid arguments are
+    /// reported by clap with exit code 2
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            CliError::InvalidInput(_) => ExitCode::from(EXIT_INVALID_INPUT),
+            CliError::StateMismatch(_) | CliError::Diverged(_) => ExitCode::from(EXIT_MISMATCH),
+            _ => ExitCode::FAILURE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use clap::Parser;
+
+    use transactioner::limits::VelocityLimit;
+    use transactioner::MoneyType;
+
+    use crate::cli::{Cli, Command, Fee, FeeArg, Storage, VelocityLimitArg};
+
+    #[test]
+    fn test_parse_commands() {
+        let cli = Cli::try_parse_from([
+            "transactioner",
+            "process",
+            "input.csv",
+            "--output",
+            "out.csv",
+            "--lenient",
+        ])
+        .unwrap();
+
+        let Command::Process(args) = cli.command else {
+            panic!("Expected the process command");
+        };
+
+        assert_eq!(args.input, vec![PathBuf::from("input.csv")]);
+        assert_eq!(args.output.unwrap().to_str(), Some("out.csv"));
+        assert!(args.lenient);
+
+        let cli = Cli::try_parse_from([
+            "transactioner",
+            "process",
+            "txs-00.csv",
+            "txs-01.csv",
+            "--merge-by-timestamp",
+        ])
+        .unwrap();
+
+        assert!(
+            matches!(cli.command, Command::Process(args) if args.input.len() == 2
+            && args.merge_by_timestamp)
+        );
+
+        let cli = Cli::try_parse_from(["transactioner", "serve"]).unwrap();
+
+        assert!(
+            matches!(cli.command, Command::Serve(args) if args.listen.port() == 8080
+            && args.storage == Storage::Memory)
+        );
+
+        let cli = Cli::try_parse_from(["transactioner", "serve", "--storage", "sqlite:state.db"])
+            .unwrap();
+
+        assert!(matches!(cli.command, Command::Serve(args)
+            if args.storage == Storage::Sqlite("state.db".into())));
+
+        // The input is required, and the binary name is not mistaken for it
+        assert!(Cli::try_parse_from(["transactioner", "verify"]).is_err());
+        assert!(Cli::try_parse_from(["transactioner", "serve", "--listen", "nowhere"]).is_err());
+        assert!(Cli::try_parse_from(["transactioner", "serve", "--snapshot-every", "10"]).is_err());
+        assert!(Cli::try_parse_from(["transactioner", "serve", "--storage", "sqlite:"]).is_err());
+        assert!(Cli::try_parse_from([
+            "transactioner",
+            "process",
+            "input.csv",
+            "--dead-letter-format",
+            "csv"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_fees() {
+        let fee = "withdrawal=0.5".parse::<FeeArg>().unwrap();
+
+        assert_eq!(fee.tx_type, "withdrawal");
+
+        // Flat fees have the decimal places of the amounts of the run
+        let Fee::Flat(amount) = fee.fee else {
+            panic!("Expected a flat fee");
+        };
+
+        assert_eq!(amount.at_precision(4).ok(), Some(MoneyType::new(5_000)));
+        assert_eq!(amount.at_precision(2).ok(), Some(MoneyType::new(50)));
+        assert!("withdrawal=0.005".parse::<FeeArg>().is_ok_and(
+            |fee| matches!(fee.fee, Fee::Flat(amount) if amount.at_precision(2).is_err())
+        ));
+
+        assert_eq!(
+            "chargeback=1.5%".parse::<FeeArg>().map(|fee| fee.fee),
+            Ok(Fee::Percentage(150))
+        );
+
+        assert!("transfer=1".parse::<FeeArg>().is_err());
+        assert!("withdrawal=-1".parse::<FeeArg>().is_err());
+        assert!("withdrawal=0.001%".parse::<FeeArg>().is_err());
+        assert!("withdrawal".parse::<FeeArg>().is_err());
+
+        // The fees need an account to be credited to
+        assert!(Cli::try_parse_from([
+            "transactioner",
+            "process",
+            "input.csv",
+            "--fee",
+            "withdrawal=1"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_velocity_limits() {
+        let withdrawals = "withdrawals=1000/24h".parse::<VelocityLimitArg>().unwrap();
+
+        // The amount has the decimal places of the run
+        assert_eq!(
+            withdrawals.at_precision(2).unwrap(),
+            VelocityLimit::WithdrawnAmount {
+                max: MoneyType::new(100_000),
+                window: Duration::from_secs(24 * 60 * 60),
+            }
+        );
+        assert_eq!(
+            withdrawals.at_precision(4).unwrap(),
+            VelocityLimit::WithdrawnAmount {
+                max: MoneyType::new(10_000_000),
+                window: Duration::from_secs(24 * 60 * 60),
+            }
+        );
+        assert!("withdrawals=0.001/1h"
+            .parse::<VelocityLimitArg>()
+            .unwrap()
+            .at_precision(2)
+            .is_err());
+        assert_eq!(
+            "transactions=10/30m"
+                .parse::<VelocityLimitArg>()
+                .unwrap()
+                .at_precision(2)
+                .unwrap(),
+            VelocityLimit::TransactionCount {
+                max: 10,
+                window: Duration::from_secs(30 * 60),
+            }
+        );
+
+        assert!("deposits=10/1h".parse::<VelocityLimitArg>().is_err());
+        assert!("transactions=10/0h".parse::<VelocityLimitArg>().is_err());
+        assert!("transactions=10/1w".parse::<VelocityLimitArg>().is_err());
+        assert!("transactions=10".parse::<VelocityLimitArg>().is_err());
+        assert!("withdrawals=-1/1h".parse::<VelocityLimitArg>().is_err());
+    }
+}
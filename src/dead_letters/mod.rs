@@ -0,0 +1,371 @@
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::models::money::format_amount;
+use crate::models::transactions::{Transaction, TransactionType};
+use crate::models::{ClientID, MoneyType};
+use crate::rejections::{RejectionCode, TRejectionReason};
+use crate::services::transaction_service::TTransactionService;
+use crate::FLOATING_POINT_ACC;
+
+/// A transaction the engine refused, kept along with the reason it was refused, so it can be
+/// inspected, fixed and submitted again instead of being lost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedTransaction {
+    pub transaction: Transaction,
+    pub code: RejectionCode,
+    /// The description of the error which rejected the transaction
+    pub error: String,
+}
+
+impl RejectedTransaction {
+    pub fn new(transaction: Transaction, reason: &(impl TRejectionReason + fmt::Display)) -> Self {
+        Self {
+            transaction,
+            code: reason.rejection_code(),
+            error: reason.to_string(),
+        }
+    }
+}
+
+/// Receives the transactions rejected while processing (the dead-letter queue)
+pub trait TRejectedTransactionSink: Send + Sync {
+    fn reject(&self, rejected: &RejectedTransaction);
+}
+
+impl<S: TRejectedTransactionSink + ?Sized> TRejectedTransactionSink for Arc<S> {
+    fn reject(&self, rejected: &RejectedTransaction) {
+        (**self).reject(rejected)
+    }
+}
+
+/// Keeps the rejected transactions in memory, so they can be retried at the end of the run
+#[derive(Default)]
+pub struct RejectedTransactionBuffer {
+    rejected: Mutex<Vec<RejectedTransaction>>,
+}
+
+impl RejectedTransactionBuffer {
+    /// The rejected transactions, in the order they were rejected
+    pub fn into_rejected(self) -> Vec<RejectedTransaction> {
+        match self.rejected.into_inner() {
+            Ok(rejected) => rejected,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl TRejectedTransactionSink for RejectedTransactionBuffer {
+    fn reject(&self, rejected: &RejectedTransaction) {
+        let mut buffer = match self.rejected.lock() {
+            Ok(buffer) => buffer,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        buffer.push(rejected.clone());
+    }
+}
+
+/// Process the rejected transactions once more, in the order they were rejected, returning the
+/// ones which were rejected again.
+///
+/// Some transactions are only rejected because of the order they arrived in (e.g. a withdrawal
+/// arriving before the deposit which funds it), so they can succeed once everything else has
+/// been processed.
+pub async fn retry_rejected(
+    transaction_service: &impl TTransactionService,
+    rejected: Vec<RejectedTransaction>,
+) -> Vec<RejectedTransaction> {
+    let mut still_rejected = Vec::new();
+
+    for rejected in rejected {
+        if let Err(err) = transaction_service
+            .process_transaction(rejected.transaction.clone())
+            .await
+        {
+            still_rejected.push(RejectedTransaction::new(rejected.transaction, &err));
+        }
+    }
+
+    still_rejected
+}
+
+/// Writes every rejected transaction as a CSV row, with the transaction in the input format
+/// followed by the reason it was rejected
+pub struct CsvRejectedTransactionSink<W: Write> {
+    writer: Mutex<csv::Writer<W>>,
+}
+
+impl<W: Write> CsvRejectedTransactionSink<W> {
+    pub fn new(writer: W) -> Self {
+        let mut writer = csv::Writer::from_writer(writer);
+
+        if let Err(err) =
+            writer.write_record(["type", "client", "tx", "amount", "to", "code", "error"])
+        {
+            tracing::error!(error = %err, "Failed to write the rejected transactions header");
+        }
+
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<W> {
+        self.writer.into_inner().ok()?.into_inner().ok()
+    }
+}
+
+impl<W: Write + Send> TRejectedTransactionSink for CsvRejectedTransactionSink<W> {
+    fn reject(&self, rejected: &RejectedTransaction) {
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+
+        let (tx_type, amount, to_client) = input_fields(&rejected.transaction);
+
+        let result = writer
+            .write_record([
+                tx_type,
+                &rejected.transaction.client().to_string(),
+                &rejected.transaction.transaction_id().to_string(),
+                &amount
+                    .map(|amount| format_amount(amount, FLOATING_POINT_ACC as u32))
+                    .unwrap_or_default(),
+                &to_client
+                    .map(|to_client| to_client.to_string())
+                    .unwrap_or_default(),
+                rejected.code.code(),
+                &rejected.error,
+            ])
+            .and_then(|_| Ok(writer.flush()?));
+
+        if let Err(err) = result {
+            tracing::error!(
+                tx = %rejected.transaction.transaction_id(),
+                error = %err,
+                "Failed to write a rejected transaction"
+            );
+        }
+    }
+}
+
+/// Writes every rejected transaction as a line of JSON, with the transaction in the JSON Lines
+/// input format along with the reason it was rejected
+#[cfg(feature = "json")]
+pub struct JsonRejectedTransactionSink<W: Write> {
+    writer: Mutex<W>,
+}
+
+#[cfg(feature = "json")]
+impl<W: Write> JsonRejectedTransactionSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<W> {
+        self.writer.into_inner().ok()
+    }
+}
+
+#[cfg(feature = "json")]
+impl<W: Write + Send> TRejectedTransactionSink for JsonRejectedTransactionSink<W> {
+    fn reject(&self, rejected: &RejectedTransaction) {
+        #[derive(serde::Serialize)]
+        struct JsonRejectedRecord<'a> {
+            #[serde(rename = "type")]
+            tx_type: &'static str,
+            client: ClientID,
+            tx: crate::models::TransactionID,
+            #[serde(
+                skip_serializing_if = "Option::is_none",
+                with = "crate::models::money::serde_optional_amount"
+            )]
+            amount: Option<MoneyType>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            to: Option<ClientID>,
+            code: &'static str,
+            error: &'a str,
+        }
+
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+
+        let (tx_type, amount, to) = input_fields(&rejected.transaction);
+
+        let record = JsonRejectedRecord {
+            tx_type,
+            client: rejected.transaction.client(),
+            tx: rejected.transaction.transaction_id(),
+            amount,
+            to,
+            code: rejected.code.code(),
+            error: &rejected.error,
+        };
+
+        let result = serde_json::to_writer(&mut *writer, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"))
+            .and_then(|_| writer.flush());
+
+        if let Err(err) = result {
+            tracing::error!(
+                tx = %rejected.transaction.transaction_id(),
+                error = %err,
+                "Failed to write a rejected transaction"
+            );
+        }
+    }
+}
+
+/// The type, amount and destination of the transaction, as they are given in the input
+fn input_fields(transaction: &Transaction) -> (&'static str, Option<MoneyType>, Option<ClientID>) {
+    let (tx_type, amount) = match transaction.tx_type() {
+        TransactionType::Deposit { amount, .. } => ("deposit", Some(*amount)),
+        TransactionType::Withdrawal { amount, .. } => ("withdrawal", Some(*amount)),
+        TransactionType::Dispute => ("dispute", None),
+        TransactionType::Resolve => ("resolve", None),
+        TransactionType::Chargeback => ("chargeback", None),
+        TransactionType::Representment => ("representment", None),
+        TransactionType::Authorize { amount, .. } => ("authorize", Some(*amount)),
+        TransactionType::Capture => ("capture", None),
+        TransactionType::Transfer { amount, .. } => ("transfer", Some(*amount)),
+        TransactionType::Unfreeze => ("unfreeze", None),
+        TransactionType::Close => ("close", None),
+    };
+
+    (tx_type, amount, transaction.destination())
+}
+
+#[cfg(test)]
+mod dead_letter_tests {
+    use crate::dead_letters::{
+        retry_rejected, CsvRejectedTransactionSink, RejectedTransaction, RejectedTransactionBuffer,
+        TRejectedTransactionSink,
+    };
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::rejections::RejectionCode;
+    use crate::services::transaction_service::{TTransactionService, TransactionService};
+
+    #[tokio::test]
+    async fn test_retry_rejected_transactions() {
+        let tx_service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        );
+
+        let buffer = RejectedTransactionBuffer::default();
+
+        // The withdrawal arrives before the deposit which funds it, and the second one can
+        // never be covered
+        let transactions = [
+            (
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(10000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(30000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(90000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                1,
+                4,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(50000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+        ];
+
+        for (client_id, tx_id, tx_type) in transactions {
+            let tx = Transaction::builder()
+                .with_client_id(ClientID(client_id))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(tx_type)
+                .build();
+
+            if let Err(err) = tx_service.process_transaction(tx.clone()).await {
+                buffer.reject(&RejectedTransaction::new(tx, &err));
+            }
+        }
+
+        let rejected = buffer.into_rejected();
+
+        assert_eq!(rejected.len(), 2);
+        assert!(rejected
+            .iter()
+            .all(|rejected| rejected.code == RejectionCode::InsufficientFunds));
+
+        let still_rejected = retry_rejected(&tx_service, rejected).await;
+
+        assert_eq!(still_rejected.len(), 1);
+        assert_eq!(
+            still_rejected[0].transaction.transaction_id(),
+            TransactionID(3)
+        );
+
+        let sink = CsvRejectedTransactionSink::new(Vec::new());
+
+        still_rejected
+            .iter()
+            .for_each(|rejected| sink.reject(rejected));
+
+        let output = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+
+        let mut lines = output.lines();
+
+        assert_eq!(lines.next(), Some("type,client,tx,amount,to,code,error"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("withdrawal,1,3,9.0000,,E1001,"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_rejected_transactions() {
+        use crate::dead_letters::JsonRejectedTransactionSink;
+
+        let sink = JsonRejectedTransactionSink::new(Vec::new());
+
+        sink.reject(&RejectedTransaction {
+            transaction: Transaction::builder()
+                .with_client_id(ClientID(2))
+                .with_tx_id(TransactionID(7))
+                .with_tx_type(TransactionType::Chargeback)
+                .build(),
+            code: RejectionCode::TransactionNotDisputed,
+            error: "not disputed".into(),
+        });
+
+        let output = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            output,
+            "{\"type\":\"chargeback\",\"client\":2,\"tx\":7,\"code\":\"E1006\",\"error\":\"not disputed\"}\n"
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::models::effects::{Effects, ExecutionMode};
+use crate::models::{ClientID, MoneyType, TransactionID};
+
+/// The domain events produced by the transaction service.
+///
+/// Every change applied to the state of the system is described by exactly one of these
+/// events, which are emitted once by the service. Any subsystem that needs to react
+/// to changes (audit logging, notifications, CDC, projections, etc.) should consume
+/// these instead of hooking into the service on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DomainEvent {
+    AccountCreated {
+        client: ClientID,
+    },
+    DepositApplied {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    WithdrawalApplied {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    DisputeOpened {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    DisputeResolved {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    ChargebackApplied {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    /// A chargeback was reversed by a representment, giving the funds back to the client
+    ChargebackReversed {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    /// Funds of the client were held by an authorization
+    FundsAuthorized {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    /// An authorization was captured, withdrawing the funds it held
+    AuthorizationCaptured {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    /// An authorization expired before being captured, releasing the funds it held
+    AuthorizationExpired {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+    AccountFrozen {
+        client: ClientID,
+    },
+    AccountUnfrozen {
+        client: ClientID,
+    },
+    AccountClosed {
+        client: ClientID,
+    },
+    TransferSent {
+        client: ClientID,
+        transaction: TransactionID,
+        to_client: ClientID,
+        amount: MoneyType,
+    },
+    TransferReceived {
+        client: ClientID,
+        transaction: TransactionID,
+        from_client: ClientID,
+        amount: MoneyType,
+    },
+    /// The client was charged a fee for one of their transactions, credited to the collector
+    FeeCharged {
+        client: ClientID,
+        transaction: TransactionID,
+        collector: ClientID,
+        amount: MoneyType,
+    },
+    /// The fee charged to another client for one of their transactions was collected
+    FeeCollected {
+        client: ClientID,
+        transaction: TransactionID,
+        from_client: ClientID,
+        amount: MoneyType,
+    },
+}
+
+/// The kinds of domain events, so consumers can pick the ones they are interested in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    AccountCreated,
+    DepositApplied,
+    WithdrawalApplied,
+    DisputeOpened,
+    DisputeResolved,
+    ChargebackApplied,
+    ChargebackReversed,
+    FundsAuthorized,
+    AuthorizationCaptured,
+    AuthorizationExpired,
+    AccountFrozen,
+    AccountUnfrozen,
+    AccountClosed,
+    TransferSent,
+    TransferReceived,
+    FeeCharged,
+    FeeCollected,
+}
+
+impl DomainEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            DomainEvent::AccountCreated { .. } => EventKind::AccountCreated,
+            DomainEvent::DepositApplied { .. } => EventKind::DepositApplied,
+            DomainEvent::WithdrawalApplied { .. } => EventKind::WithdrawalApplied,
+            DomainEvent::DisputeOpened { .. } => EventKind::DisputeOpened,
+            DomainEvent::DisputeResolved { .. } => EventKind::DisputeResolved,
+            DomainEvent::ChargebackApplied { .. } => EventKind::ChargebackApplied,
+            DomainEvent::ChargebackReversed { .. } => EventKind::ChargebackReversed,
+            DomainEvent::FundsAuthorized { .. } => EventKind::FundsAuthorized,
+            DomainEvent::AuthorizationCaptured { .. } => EventKind::AuthorizationCaptured,
+            DomainEvent::AuthorizationExpired { .. } => EventKind::AuthorizationExpired,
+            DomainEvent::AccountFrozen { .. } => EventKind::AccountFrozen,
+            DomainEvent::AccountUnfrozen { .. } => EventKind::AccountUnfrozen,
+            DomainEvent::AccountClosed { .. } => EventKind::AccountClosed,
+            DomainEvent::TransferSent { .. } => EventKind::TransferSent,
+            DomainEvent::TransferReceived { .. } => EventKind::TransferReceived,
+            DomainEvent::FeeCharged { .. } => EventKind::FeeCharged,
+            DomainEvent::FeeCollected { .. } => EventKind::FeeCollected,
+        }
+    }
+
+    /// The client affected by this event
+    pub fn client(&self) -> ClientID {
+        match self {
+            DomainEvent::AccountCreated { client }
+            | DomainEvent::DepositApplied { client, .. }
+            | DomainEvent::WithdrawalApplied { client, .. }
+            | DomainEvent::DisputeOpened { client, .. }
+            | DomainEvent::DisputeResolved { client, .. }
+            | DomainEvent::ChargebackApplied { client, .. }
+            | DomainEvent::ChargebackReversed { client, .. }
+            | DomainEvent::FundsAuthorized { client, .. }
+            | DomainEvent::AuthorizationCaptured { client, .. }
+            | DomainEvent::AuthorizationExpired { client, .. }
+            | DomainEvent::AccountFrozen { client }
+            | DomainEvent::AccountUnfrozen { client }
+            | DomainEvent::AccountClosed { client }
+            | DomainEvent::TransferSent { client, .. }
+            | DomainEvent::TransferReceived { client, .. }
+            | DomainEvent::FeeCharged { client, .. }
+            | DomainEvent::FeeCollected { client, .. } => *client,
+        }
+    }
+}
+
+/// A consumer of domain events.
+///
+/// Handlers are called synchronously, in the order the events were produced, so they should
+/// not block. Handlers that need to perform I/O should hand the event off to their own task.
+pub trait TDomainEventHandler: Send + Sync {
+    fn handle(&self, event: &DomainEvent);
+}
+
+/// A consumer of the effects decided for every transaction.
+///
+/// Unlike domain events, effects are also produced when running in dry-run mode, so consumers
+/// such as the audit log or CDC see exactly the same values regardless of the mode.
+pub trait TEffectsHandler: Send + Sync {
+    fn handle(&self, effects: &Effects, mode: ExecutionMode);
+}
+
+impl<H: TDomainEventHandler + ?Sized> TDomainEventHandler for Arc<H> {
+    fn handle(&self, event: &DomainEvent) {
+        (**self).handle(event)
+    }
+}
+
+impl<H: TEffectsHandler + ?Sized> TEffectsHandler for Arc<H> {
+    fn handle(&self, effects: &Effects, mode: ExecutionMode) {
+        (**self).handle(effects, mode)
+    }
+}
+
+/// Fans the domain events out to any amount of subscribers, each receiving them on its own
+/// task, so consumers doing I/O (webhooks, audit logs, etc.) never hold up the processing.
+///
+/// The bus is registered as an event handler of the service, and can be cloned to subscribe
+/// from anywhere. Subscribers which fall more than `capacity` events behind skip the ones they
+/// missed.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::Sender::new(capacity.max(1)),
+        }
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> EventSubscription {
+        EventSubscription {
+            receiver: self.sender.subscribe(),
+            kinds: None,
+        }
+    }
+
+    /// Receive only the events of the given kinds published from now on
+    pub fn subscribe_to(&self, kinds: impl IntoIterator<Item = EventKind>) -> EventSubscription {
+        EventSubscription {
+            receiver: self.sender.subscribe(),
+            kinds: Some(kinds.into_iter().collect()),
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl TDomainEventHandler for EventBus {
+    fn handle(&self, event: &DomainEvent) {
+        // Only fails when nobody is subscribed, in which case there is nobody to tell
+        let _ = self.sender.send(event.clone());
+    }
+}
+
+/// The events of an [`EventBus`] received by a single subscriber
+pub struct EventSubscription {
+    receiver: broadcast::Receiver<DomainEvent>,
+    kinds: Option<HashSet<EventKind>>,
+}
+
+impl EventSubscription {
+    /// Wait for the next event, returning `None` once the bus and all of its clones are dropped
+    pub async fn recv(&mut self) -> Option<DomainEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if self.wants(&event) => return Some(event),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "Event subscriber fell behind, skipping events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    fn wants(&self, event: &DomainEvent) -> bool {
+        self.kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.contains(&event.kind()))
+    }
+}
+
+#[cfg(test)]
+mod events_tests {
+    use crate::events::{DomainEvent, EventBus, EventKind, TDomainEventHandler};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+
+    #[tokio::test]
+    async fn test_event_bus() {
+        let bus = EventBus::new(2);
+
+        let mut everything = bus.subscribe();
+        let mut deposits = bus.subscribe_to([EventKind::DepositApplied]);
+
+        let created = DomainEvent::AccountCreated {
+            client: ClientID(1),
+        };
+        let deposit = DomainEvent::DepositApplied {
+            client: ClientID(1),
+            transaction: TransactionID(1),
+            amount: MoneyType::new(10000),
+        };
+
+        bus.handle(&created);
+        bus.handle(&deposit);
+
+        assert_eq!(everything.recv().await, Some(created));
+        assert_eq!(everything.recv().await, Some(deposit.clone()));
+        assert_eq!(deposits.recv().await, Some(deposit));
+
+        // Falling behind skips the oldest events instead of holding up the publisher
+        for client in 2..5 {
+            bus.handle(&DomainEvent::AccountFrozen {
+                client: ClientID(client),
+            });
+        }
+
+        assert_eq!(
+            everything.recv().await.map(|event| event.client()),
+            Some(ClientID(3))
+        );
+
+        drop(bus);
+
+        assert_eq!(
+            everything.recv().await.map(|event| event.client()),
+            Some(ClientID(4))
+        );
+        assert_eq!(everything.recv().await, None);
+        assert_eq!(deposits.recv().await, None);
+    }
+}
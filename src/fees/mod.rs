@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use crate::models::transactions::{Transaction, TransactionType};
+use crate::models::{ClientID, MoneyType};
+
+/// The basis points (hundredths of a percent) in a whole amount
+const BASIS_POINTS: i128 = 10_000;
+
+/// How much a transaction is charged, given the amount it moves
+pub trait TFeePolicy: Send + Sync {
+    fn fee(&self, amount: MoneyType) -> MoneyType;
+}
+
+/// Any function can be used as a fee policy, which allows for tiered or capped fees
+impl<F> TFeePolicy for F
+where
+    F: Fn(MoneyType) -> MoneyType + Send + Sync,
+{
+    fn fee(&self, amount: MoneyType) -> MoneyType {
+        self(amount)
+    }
+}
+
+/// The same fee for every transaction, whatever its amount
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatFee(pub MoneyType);
+
+impl TFeePolicy for FlatFee {
+    fn fee(&self, _amount: MoneyType) -> MoneyType {
+        self.0
+    }
+}
+
+/// A share of the amount of the transaction, in basis points (hundredths of a percent),
+/// rounded down to the precision of the amounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PercentageFee {
+    basis_points: u32,
+}
+
+impl PercentageFee {
+    pub fn new(basis_points: u32) -> Self {
+        Self { basis_points }
+    }
+
+    pub fn basis_points(&self) -> u32 {
+        self.basis_points
+    }
+}
+
+impl TFeePolicy for PercentageFee {
+    fn fee(&self, amount: MoneyType) -> MoneyType {
+        let fee = i128::from(amount.raw()) * i128::from(self.basis_points) / BASIS_POINTS;
+
+        MoneyType::new(i64::try_from(fee).unwrap_or(i64::MAX))
+    }
+}
+
+/// The fees charged for each type of transaction, all of them credited to the same account.
+///
+/// Fees are taken from the available funds of the client along with the transaction they are
+/// charged for, even if that leaves the client owing funds, so a fee never rejects the
+/// transaction it is charged for. Transfers and
+/// administrative transactions are never charged, and neither are the transactions of the
+/// account collecting the fees.
+pub struct FeeSchedule {
+    collector: ClientID,
+    fees: HashMap<String, Box<dyn TFeePolicy>>,
+}
+
+impl FeeSchedule {
+    /// A schedule without any fees, which credits the ones added to it to the given account
+    pub fn new(collector: ClientID) -> Self {
+        Self {
+            collector,
+            fees: HashMap::new(),
+        }
+    }
+
+    /// Charge the transactions of the given type (as named in the input, e.g. `withdrawal`)
+    /// with the given fee, replacing the fee the type had before
+    pub fn with_fee(mut self, tx_type: impl Into<String>, fee: impl TFeePolicy + 'static) -> Self {
+        self.fees.insert(tx_type.into(), Box::new(fee));
+
+        self
+    }
+
+    /// The account the fees are credited to
+    pub fn collector(&self) -> ClientID {
+        self.collector
+    }
+
+    /// Whether the given transaction has a fee, which depends only on its type and its client
+    pub fn charges(&self, transaction: &Transaction) -> bool {
+        match transaction.tx_type() {
+            TransactionType::Transfer { .. }
+            | TransactionType::Unfreeze
+            | TransactionType::Close => false,
+            tx_type => {
+                transaction.client() != self.collector && self.fees.contains_key(tx_type.name())
+            }
+        }
+    }
+
+    /// The fee of the given transaction.
+    ///
+    /// It is computed from the amount of the transaction, or from the amount of the one it
+    /// references, for the transactions which don't have their own (such as chargebacks).
+    pub fn fee_of(&self, transaction: &Transaction, referenced: Option<&Transaction>) -> MoneyType {
+        if !self.charges(transaction) {
+            return MoneyType::ZERO;
+        }
+
+        let amount = match (transaction.amount(), referenced.map(Transaction::amount)) {
+            (Ok(amount), _) | (Err(_), Some(Ok(amount))) => amount,
+            _ => return MoneyType::ZERO,
+        };
+
+        self.fees
+            .get(transaction.tx_type().name())
+            .map_or(MoneyType::ZERO, |fee| fee.fee(amount))
+    }
+}
+
+#[cfg(test)]
+mod fee_tests {
+    use crate::fees::{FeeSchedule, FlatFee, PercentageFee, TFeePolicy};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+
+    fn tx(client: u16, tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(client))
+            .with_tx_id(TransactionID(1))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    #[test]
+    pub fn test_percentage_fee() {
+        let fee = PercentageFee::new(150);
+
+        assert_eq!(fee.fee(MoneyType::new(10_000)), MoneyType::new(150));
+        // Rounded down to the precision of the amounts
+        assert_eq!(fee.fee(MoneyType::new(99)), MoneyType::new(1));
+    }
+
+    #[test]
+    pub fn test_fee_schedule() {
+        let schedule = FeeSchedule::new(ClientID(9))
+            .with_fee("withdrawal", FlatFee(MoneyType::new(5)))
+            .with_fee("chargeback", PercentageFee::new(1_000))
+            .with_fee("transfer", FlatFee(MoneyType::new(5)));
+
+        let withdrawal = tx(
+            1,
+            TransactionType::Withdrawal {
+                amount: MoneyType::new(1_000),
+                dispute: DisputeState::NotDisputed,
+            },
+        );
+
+        assert_eq!(schedule.fee_of(&withdrawal, None), MoneyType::new(5));
+
+        // The collector is never charged
+        let own_withdrawal = tx(
+            9,
+            TransactionType::Withdrawal {
+                amount: MoneyType::new(1_000),
+                dispute: DisputeState::NotDisputed,
+            },
+        );
+
+        assert!(!schedule.charges(&own_withdrawal));
+
+        // Chargebacks are charged for the amount they charge back
+        let chargeback = tx(1, TransactionType::Chargeback);
+
+        assert_eq!(
+            schedule.fee_of(&chargeback, Some(&withdrawal)),
+            MoneyType::new(100)
+        );
+        assert_eq!(schedule.fee_of(&chargeback, None), MoneyType::new(0));
+
+        let transfer = tx(
+            1,
+            TransactionType::Transfer {
+                to_client: ClientID(2),
+                amount: MoneyType::new(1_000),
+            },
+        );
+
+        assert!(!schedule.charges(&transfer));
+        assert!(!schedule.charges(&tx(1, TransactionType::Dispute)));
+    }
+}
@@ -0,0 +1,741 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::events::DomainEvent;
+use crate::models::client::{Client, ClientAccountStatus, DisputedFunds};
+use crate::models::money::{format_amount, parse_amount};
+use crate::models::{ClientID, TransactionID};
+use crate::repositories::RepoError;
+use crate::services::decision::DisputePolicy;
+use crate::wal::truncate_torn_record;
+use crate::FLOATING_POINT_ACC;
+
+/// The position of an event in the store, starting at 1
+pub type EventSequence = u64;
+
+/// An event, along with its position in the store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedEvent {
+    pub sequence: EventSequence,
+    pub event: DomainEvent,
+}
+
+/// An append only store of the domain events, from which the state of the clients at any point
+/// in time can be rebuilt.
+///
+/// Unlike the client repositories, which only keep the current state of every client, this
+/// keeps every change, so we can audit how a client got to its state.
+pub trait TEventStore: Send + Sync {
+    /// Append an event, returning its position in the store
+    fn append(&self, event: &DomainEvent) -> Result<EventSequence, RepoError>;
+
+    /// Every event stored, in the order they were appended
+    fn events(&self) -> Result<Vec<RecordedEvent>, RepoError>;
+
+    /// The events of the given client, in the order they were appended
+    fn client_events(&self, client_id: ClientID) -> Result<Vec<RecordedEvent>, RepoError> {
+        Ok(self
+            .events()?
+            .into_iter()
+            .filter(|recorded| recorded.event.client() == client_id)
+            .collect())
+    }
+
+    /// Rebuild the client as it was right after the event at the given position (or as it is
+    /// now, without one), with the dispute policy the events were produced with. The client is
+    /// `None` if it did not exist yet.
+    fn client_at(
+        &self,
+        client_id: ClientID,
+        until: Option<EventSequence>,
+        policy: DisputePolicy,
+    ) -> Result<Option<Client>, RepoError> {
+        let events = self
+            .client_events(client_id)?
+            .into_iter()
+            .take_while(|recorded| until.is_none_or(|until| recorded.sequence <= until))
+            .map(|recorded| recorded.event);
+
+        rebuild_client(client_id, events, policy)
+    }
+}
+
+/// Rebuild a client by folding its events, in the order they happened.
+///
+/// The versions of the clients are not part of the events, so the rebuilt client is always at
+/// version 0. Events which could not have been produced by the transaction processing (e.g.
+/// withdrawing more than was available) mean the events are not valid.
+///
+/// The events of disputes don't say how the disputed funds were moved, so this needs the
+/// dispute policy of the transaction service which produced them.
+pub fn rebuild_client(
+    client_id: ClientID,
+    events: impl IntoIterator<Item = DomainEvent>,
+    policy: DisputePolicy,
+) -> Result<Option<Client>, RepoError> {
+    let mut client: Option<Client> = None;
+
+    // The kind of the transactions which might get disputed, as disputes only carry the amount
+    let mut disputable: HashMap<TransactionID, DisputedFunds> = HashMap::new();
+
+    for event in events {
+        if event.client() != client_id {
+            return Err(RepoError::InvalidData(format!(
+                "Event {:?} does not belong to client {}",
+                event, client_id
+            )));
+        }
+
+        if let DomainEvent::AccountCreated { .. } = event {
+            client = Some(Client::builder().with_client_id(client_id).build());
+
+            continue;
+        }
+
+        let Some(current) = client.as_mut() else {
+            return Err(RepoError::InvalidData(format!(
+                "Event {:?} happened before client {} was created",
+                event, client_id
+            )));
+        };
+
+        let disputed_funds = |transaction: &TransactionID| {
+            disputable.get(transaction).copied().ok_or_else(|| {
+                RepoError::InvalidData(format!(
+                    "Transaction {} of client {} can't be disputed",
+                    transaction, client_id
+                ))
+            })
+        };
+
+        let applied = match &event {
+            DomainEvent::AccountCreated { .. } => unreachable!("Handled above"),
+            DomainEvent::DepositApplied {
+                transaction,
+                amount,
+                ..
+            } => {
+                disputable.insert(*transaction, DisputedFunds::Deposited);
+
+                current.deposit(*amount)
+            }
+            DomainEvent::WithdrawalApplied {
+                transaction,
+                amount,
+                ..
+            } => {
+                disputable.insert(*transaction, DisputedFunds::Withdrawn);
+
+                current.withdraw(*amount)
+            }
+            DomainEvent::DisputeOpened {
+                transaction,
+                amount,
+                ..
+            } => match (disputed_funds(transaction)?, policy) {
+                (DisputedFunds::Deposited, _) => current.dispute_deposited_funds(*amount),
+                (DisputedFunds::Withdrawn, DisputePolicy::ProvisionalCredit) => {
+                    current.credit_withdrawn_funds(*amount)
+                }
+                (DisputedFunds::Withdrawn, _) => current.dispute_withdrawn_funds(*amount),
+            },
+            DomainEvent::DisputeResolved {
+                transaction,
+                amount,
+                ..
+            } => match (disputed_funds(transaction)?, policy) {
+                (DisputedFunds::Withdrawn, DisputePolicy::ProvisionalCredit) => {
+                    current.confirm_credited_funds()
+                }
+                (funds, _) => current.resolve_funds(funds, *amount),
+            },
+            DomainEvent::ChargebackApplied {
+                transaction,
+                amount,
+                ..
+            } => match (disputed_funds(transaction)?, policy) {
+                (DisputedFunds::Withdrawn, DisputePolicy::ProvisionalCredit) => {
+                    current.reverse_credited_funds(*amount)
+                }
+                (funds, _) => current.chargeback_funds(funds, *amount),
+            },
+            DomainEvent::ChargebackReversed {
+                transaction,
+                amount,
+                ..
+            } => {
+                disputed_funds(transaction)?;
+
+                current.represent_funds(*amount)
+            }
+            DomainEvent::FundsAuthorized { amount, .. } => current.authorize(*amount),
+            DomainEvent::AuthorizationCaptured { amount, .. } => {
+                current.capture_authorized_funds(*amount)
+            }
+            DomainEvent::AuthorizationExpired { amount, .. } => {
+                current.release_authorized_funds(*amount)
+            }
+            DomainEvent::AccountFrozen { .. } => {
+                current.set_account_status(ClientAccountStatus::Frozen);
+
+                Ok(())
+            }
+            DomainEvent::AccountUnfrozen { .. } => current.unfreeze(),
+            DomainEvent::AccountClosed { .. } => current.close(),
+            DomainEvent::TransferSent { amount, .. } => current.withdraw(*amount),
+            DomainEvent::TransferReceived { amount, .. } => current.deposit(*amount),
+            DomainEvent::FeeCharged { amount, .. } => current.charge_fee(*amount),
+            DomainEvent::FeeCollected { amount, .. } => current.collect_fee(*amount),
+        };
+
+        applied.map_err(|err| {
+            RepoError::InvalidData(format!("Event {:?} can't be applied: {}", event, err))
+        })?;
+    }
+
+    Ok(client)
+}
+
+/// Event store kept in memory
+#[derive(Default)]
+pub struct InMemEventStore {
+    events: Mutex<Vec<DomainEvent>>,
+}
+
+impl TEventStore for InMemEventStore {
+    fn append(&self, event: &DomainEvent) -> Result<EventSequence, RepoError> {
+        let mut events = match self.events.lock() {
+            Ok(events) => events,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        events.push(event.clone());
+
+        Ok(events.len() as EventSequence)
+    }
+
+    fn events(&self) -> Result<Vec<RecordedEvent>, RepoError> {
+        let events = match self.events.lock() {
+            Ok(events) => events,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        Ok(recorded(events.iter().cloned()))
+    }
+}
+
+/// Event store kept in a file, with one CSV record per event, which is only ever appended to
+pub struct FileEventStore {
+    file: Mutex<File>,
+    path: std::path::PathBuf,
+    appended: Mutex<EventSequence>,
+}
+
+impl FileEventStore {
+    /// Open the store at the given path, creating it if it doesn't exist. Just like the
+    /// write-ahead log, a record left partially written by a crash is removed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RepoError> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        truncate_torn_record(&mut file)?;
+
+        let store = Self {
+            file: Mutex::new(file),
+            path,
+            appended: Mutex::new(0),
+        };
+
+        let appended = store.events()?.len() as EventSequence;
+
+        *store.appended.lock().expect("Not shared yet") = appended;
+
+        Ok(store)
+    }
+}
+
+impl TEventStore for FileEventStore {
+    fn append(&self, event: &DomainEvent) -> Result<EventSequence, RepoError> {
+        let record = encode(event)?;
+
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        file.write_all(&record)?;
+        file.sync_data()?;
+
+        let mut appended = match self.appended.lock() {
+            Ok(appended) => appended,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        *appended += 1;
+
+        Ok(*appended)
+    }
+
+    fn events(&self) -> Result<Vec<RecordedEvent>, RepoError> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(&self.path)
+            .map_err(|err| RepoError::InvalidData(err.to_string()))?;
+
+        let events = csv_reader
+            .records()
+            .map(|record| {
+                record
+                    .map_err(|err| RepoError::InvalidData(err.to_string()))
+                    .and_then(|record| decode(&record))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(recorded(events))
+    }
+}
+
+impl<S: TEventStore + ?Sized> TEventStore for std::sync::Arc<S> {
+    fn append(&self, event: &DomainEvent) -> Result<EventSequence, RepoError> {
+        (**self).append(event)
+    }
+
+    fn events(&self) -> Result<Vec<RecordedEvent>, RepoError> {
+        (**self).events()
+    }
+}
+
+fn recorded(events: impl IntoIterator<Item = DomainEvent>) -> Vec<RecordedEvent> {
+    events
+        .into_iter()
+        .zip(1..)
+        .map(|(event, sequence)| RecordedEvent { sequence, event })
+        .collect()
+}
+
+/// Encode an event as `kind,client,transaction,counterparty,amount`, leaving out the fields the
+/// kind of event doesn't have
+fn encode(event: &DomainEvent) -> Result<Vec<u8>, RepoError> {
+    let amount = |amount: &_| format_amount(*amount, FLOATING_POINT_ACC as u32);
+
+    let (kind, transaction, counterparty, amount) = match event {
+        DomainEvent::AccountCreated { .. } => ("created", None, None, None),
+        DomainEvent::DepositApplied {
+            transaction,
+            amount: value,
+            ..
+        } => ("deposit", Some(transaction), None, Some(amount(value))),
+        DomainEvent::WithdrawalApplied {
+            transaction,
+            amount: value,
+            ..
+        } => ("withdrawal", Some(transaction), None, Some(amount(value))),
+        DomainEvent::DisputeOpened {
+            transaction,
+            amount: value,
+            ..
+        } => ("dispute", Some(transaction), None, Some(amount(value))),
+        DomainEvent::DisputeResolved {
+            transaction,
+            amount: value,
+            ..
+        } => ("resolve", Some(transaction), None, Some(amount(value))),
+        DomainEvent::ChargebackApplied {
+            transaction,
+            amount: value,
+            ..
+        } => ("chargeback", Some(transaction), None, Some(amount(value))),
+        DomainEvent::ChargebackReversed {
+            transaction,
+            amount: value,
+            ..
+        } => (
+            "representment",
+            Some(transaction),
+            None,
+            Some(amount(value)),
+        ),
+        DomainEvent::FundsAuthorized {
+            transaction,
+            amount: value,
+            ..
+        } => ("authorize", Some(transaction), None, Some(amount(value))),
+        DomainEvent::AuthorizationCaptured {
+            transaction,
+            amount: value,
+            ..
+        } => ("capture", Some(transaction), None, Some(amount(value))),
+        DomainEvent::AuthorizationExpired {
+            transaction,
+            amount: value,
+            ..
+        } => ("expire", Some(transaction), None, Some(amount(value))),
+        DomainEvent::AccountFrozen { .. } => ("frozen", None, None, None),
+        DomainEvent::AccountUnfrozen { .. } => ("unfrozen", None, None, None),
+        DomainEvent::AccountClosed { .. } => ("closed", None, None, None),
+        DomainEvent::TransferSent {
+            transaction,
+            to_client,
+            amount: value,
+            ..
+        } => (
+            "transfer-sent",
+            Some(transaction),
+            Some(to_client),
+            Some(amount(value)),
+        ),
+        DomainEvent::TransferReceived {
+            transaction,
+            from_client,
+            amount: value,
+            ..
+        } => (
+            "transfer-received",
+            Some(transaction),
+            Some(from_client),
+            Some(amount(value)),
+        ),
+        DomainEvent::FeeCharged {
+            transaction,
+            collector,
+            amount: value,
+            ..
+        } => (
+            "fee-charged",
+            Some(transaction),
+            Some(collector),
+            Some(amount(value)),
+        ),
+        DomainEvent::FeeCollected {
+            transaction,
+            from_client,
+            amount: value,
+            ..
+        } => (
+            "fee-collected",
+            Some(transaction),
+            Some(from_client),
+            Some(amount(value)),
+        ),
+    };
+
+    let optional = |value: Option<String>| value.unwrap_or_default();
+
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+
+    csv_writer
+        .write_record([
+            kind.to_string(),
+            event.client().to_string(),
+            optional(transaction.map(ToString::to_string)),
+            optional(counterparty.map(ToString::to_string)),
+            optional(amount),
+        ])
+        .map_err(|err| RepoError::InvalidData(err.to_string()))?;
+
+    csv_writer
+        .into_inner()
+        .map_err(|err| RepoError::IoError(err.into_error()))
+}
+
+fn decode(record: &csv::StringRecord) -> Result<DomainEvent, RepoError> {
+    let invalid = || RepoError::InvalidData(format!("Invalid event record {:?}", record));
+
+    let field = |index: usize| record.get(index).filter(|field| !field.is_empty());
+
+    let client: ClientID = field(1)
+        .and_then(|client| client.parse().ok())
+        .ok_or_else(invalid)?;
+
+    let transaction = || -> Result<TransactionID, RepoError> {
+        field(2).and_then(|tx| tx.parse().ok()).ok_or_else(invalid)
+    };
+
+    let counterparty = || -> Result<ClientID, RepoError> {
+        field(3)
+            .and_then(|client| client.parse().ok())
+            .ok_or_else(invalid)
+    };
+
+    let amount = || {
+        field(4)
+            .and_then(|amount| parse_amount(amount, FLOATING_POINT_ACC as u32).ok())
+            .ok_or_else(invalid)
+    };
+
+    let event = match field(0).ok_or_else(invalid)? {
+        "created" => DomainEvent::AccountCreated { client },
+        "deposit" => DomainEvent::DepositApplied {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "withdrawal" => DomainEvent::WithdrawalApplied {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "dispute" => DomainEvent::DisputeOpened {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "resolve" => DomainEvent::DisputeResolved {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "chargeback" => DomainEvent::ChargebackApplied {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "representment" => DomainEvent::ChargebackReversed {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "authorize" => DomainEvent::FundsAuthorized {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "capture" => DomainEvent::AuthorizationCaptured {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "expire" => DomainEvent::AuthorizationExpired {
+            client,
+            transaction: transaction()?,
+            amount: amount()?,
+        },
+        "frozen" => DomainEvent::AccountFrozen { client },
+        "unfrozen" => DomainEvent::AccountUnfrozen { client },
+        "closed" => DomainEvent::AccountClosed { client },
+        "transfer-sent" => DomainEvent::TransferSent {
+            client,
+            transaction: transaction()?,
+            to_client: counterparty()?,
+            amount: amount()?,
+        },
+        "transfer-received" => DomainEvent::TransferReceived {
+            client,
+            transaction: transaction()?,
+            from_client: counterparty()?,
+            amount: amount()?,
+        },
+        "fee-charged" => DomainEvent::FeeCharged {
+            client,
+            transaction: transaction()?,
+            collector: counterparty()?,
+            amount: amount()?,
+        },
+        "fee-collected" => DomainEvent::FeeCollected {
+            client,
+            transaction: transaction()?,
+            from_client: counterparty()?,
+            amount: amount()?,
+        },
+        _ => return Err(invalid()),
+    };
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod event_store_tests {
+    use std::sync::Arc;
+
+    use crate::events::DomainEvent;
+    use crate::infrastructure::event_store::{
+        EventSequence, FileEventStore, InMemEventStore, RecordedEvent, TEventStore,
+    };
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::client::ClientAccountStatus;
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::shareable::ShareableClientRepository;
+    use crate::repositories::RepoError;
+    use crate::services::decision::DisputePolicy;
+    use crate::services::transaction_service::{
+        TTransactionService, TransactionProcessingError, TransactionService,
+    };
+
+    #[tokio::test]
+    async fn test_rebuild_clients_from_events() {
+        let path = std::env::temp_dir().join(format!("event-store-{}.csv", std::process::id()));
+
+        let _ = std::fs::remove_file(&path);
+
+        let store = Arc::new(FileEventStore::open(&path).unwrap());
+
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                .with_event_store(store.clone());
+
+        let transactions = [
+            (
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(50000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(10000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                1,
+                3,
+                TransactionType::Transfer {
+                    to_client: ClientID(2),
+                    amount: MoneyType::new(5000),
+                },
+            ),
+            (1, 2, TransactionType::Dispute),
+            (1, 1, TransactionType::Dispute),
+            (1, 1, TransactionType::Chargeback),
+        ];
+
+        for (client_id, tx_id, tx_type) in transactions {
+            tx_service
+                .process_transaction(
+                    Transaction::builder()
+                        .with_client_id(ClientID(client_id))
+                        .with_tx_id(TransactionID(tx_id))
+                        .with_tx_type(tx_type)
+                        .build(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Reopen the store, to rebuild the clients from what was written to the file
+        let store = FileEventStore::open(&path).unwrap();
+
+        let events = store.events().unwrap();
+
+        for client_id in [ClientID(1), ClientID(2)] {
+            let stored = client_repo.find_client_by_id(client_id).await.unwrap();
+            let stored = stored.unwrap();
+            let stored = stored.lock().await;
+
+            let rebuilt = store
+                .client_at(client_id, None, DisputePolicy::default())
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(rebuilt.available(), stored.available());
+            assert_eq!(rebuilt.held(), stored.held());
+            assert_eq!(rebuilt.account_status(), stored.account_status());
+            assert_eq!(rebuilt.disputes(), stored.disputes());
+        }
+
+        // Client 1 before the chargeback
+        let before_chargeback = events
+            .iter()
+            .rfind(|recorded| {
+                matches!(
+                    recorded.event,
+                    crate::events::DomainEvent::DisputeOpened {
+                        transaction: TransactionID(1),
+                        ..
+                    }
+                )
+            })
+            .unwrap()
+            .sequence;
+
+        let client = store
+            .client_at(
+                ClientID(1),
+                Some(before_chargeback),
+                DisputePolicy::default(),
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(-15000));
+        assert_eq!(client.held(), MoneyType::new(60000));
+        assert_eq!(*client.account_status(), ClientAccountStatus::Active);
+
+        assert!(store
+            .client_at(ClientID(2), Some(1), DisputePolicy::default())
+            .unwrap()
+            .is_none());
+
+        std::fs::remove_file(&path).unwrap();
+
+        let in_mem = InMemEventStore::default();
+
+        events.iter().for_each(|recorded| {
+            assert_eq!(in_mem.append(&recorded.event).unwrap(), recorded.sequence)
+        });
+
+        assert_eq!(in_mem.events().unwrap(), events);
+    }
+
+    /// Event store which can't be written to
+    struct FailingEventStore;
+
+    impl TEventStore for FailingEventStore {
+        fn append(&self, _: &DomainEvent) -> Result<EventSequence, RepoError> {
+            Err(RepoError::IoError(std::io::Error::other("disk full")))
+        }
+
+        fn events(&self) -> Result<Vec<RecordedEvent>, RepoError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_store_failure() {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                .with_event_store(FailingEventStore);
+
+        let deposit = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(50000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .build();
+
+        // The deposit is rejected, instead of being applied without its events
+        assert!(matches!(
+            tx_service.process_transaction(deposit).await,
+            Err(TransactionProcessingError::RepositoryError(
+                RepoError::IoError(_)
+            ))
+        ));
+
+        let client = client_repo.find_client_by_id(ClientID(1)).await.unwrap();
+
+        if let Some(client) = client {
+            assert_eq!(client.lock().await.total(), MoneyType::ZERO);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::ops::Range;
 use std::sync::Arc;
 
 use futures::lock::Mutex;
@@ -7,15 +8,19 @@ use futures::{stream, StreamExt};
 
 use crate::models::client::Client;
 use crate::models::transactions::Transaction;
-use crate::models::{ClientID, TransactionID};
-use crate::repositories::clients::{StoredClient, TClientRepository};
+use crate::models::{ClientID, Timestamp, TransactionID};
+use crate::repositories::clients::{ClientVersionConflict, StoredClient, TClientRepository};
 use crate::repositories::transactions::{StoredTX, TTransactionRepository};
+use crate::repositories::RepoError;
+
+/// The amount of shards the in memory repositories split their entries into by default
+pub const DEFAULT_SHARDS: usize = 64;
 
 /// The in memory repository that will
 /// handle the storage of all our clients
 #[derive(Default)]
 pub struct ClientInMemRepository {
-    stored_clients: Mutex<HashMap<ClientID, StoredClient>>,
+    stored_clients: ShardedMap<ClientID, StoredClient>,
 }
 
 /// The in memory repository
@@ -23,70 +28,504 @@ pub struct ClientInMemRepository {
 /// of the transaction
 #[derive(Default)]
 pub struct TransactionInMemRepository {
-    stored_transactions: Mutex<HashMap<TransactionID, StoredTX>>,
+    stored_transactions: ShardedMap<TransactionID, StoredTX>,
+    /// The stored transactions of each client, in the order they were stored
+    by_client: ShardedMap<ClientID, Vec<StoredTX>>,
 }
 
-impl TTransactionRepository for TransactionInMemRepository {
-    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Option<StoredTX> {
-        let guard = self.stored_transactions.lock().await;
+impl ClientInMemRepository {
+    /// Split the clients into the given amount of shards, instead of [`DEFAULT_SHARDS`]
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            stored_clients: ShardedMap::new(shards),
+        }
+    }
+}
+
+impl TransactionInMemRepository {
+    /// Split the transactions into the given amount of shards, instead of [`DEFAULT_SHARDS`]
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            stored_transactions: ShardedMap::new(shards),
+            by_client: ShardedMap::new(shards),
+        }
+    }
+}
+
+/// A map split into shards by the id of its entries, each behind its own lock, so concurrent
+/// workers only contend when they access ids of the same shard
+struct ShardedMap<K, V> {
+    shards: Arc<[Mutex<HashMap<K, V>>]>,
+}
+
+impl<K, V> ShardedMap<K, V>
+where
+    K: Into<u64> + Copy + Eq + std::hash::Hash,
+    V: Clone,
+{
+    fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+        }
+    }
+
+    /// The shard of the given id. Ids are mostly sequential, so they are spread over the
+    /// shards as they are
+    fn shard(&self, key: K) -> &Mutex<HashMap<K, V>> {
+        &self.shards[(key.into() % self.shards.len() as u64) as usize]
+    }
+
+    async fn get(&self, key: K) -> Option<V> {
+        self.shard(key).lock().await.get(&key).cloned()
+    }
+
+    /// Insert the value, returning the one it replaced
+    async fn insert(&self, key: K, value: V) -> Option<V> {
+        self.shard(key).lock().await.insert(key, value)
+    }
+
+    /// Remove the value of the key, as long as it is still the given one
+    async fn remove_if(&self, key: K, is_value: impl FnOnce(&V) -> bool) {
+        let mut shard = self.shard(key).lock().await;
+
+        if shard.get(&key).is_some_and(is_value) {
+            shard.remove(&key);
+        }
+    }
+
+    /// Change the value of the key in place, starting from the default value if there is none
+    async fn update(&self, key: K, update: impl FnOnce(&mut V))
+    where
+        V: Default,
+    {
+        update(self.shard(key).lock().await.entry(key).or_default());
+    }
+
+    /// Every value in the map, locking one shard at a time
+    async fn values(&self) -> Vec<V> {
+        let mut values = Vec::new();
+
+        for shard in self.shards.iter() {
+            values.extend(shard.lock().await.values().cloned());
+        }
+
+        values
+    }
+
+    /// Every value in the map, ordered by key, locking one shard at a time
+    async fn sorted_values(&self) -> Vec<V>
+    where
+        K: Ord,
+    {
+        let mut entries = Vec::new();
+
+        for shard in self.shards.iter() {
+            entries.extend(
+                shard
+                    .lock()
+                    .await
+                    .iter()
+                    .map(|(key, value)| (*key, value.clone())),
+            );
+        }
+
+        entries.sort_by_key(|(key, _)| *key);
 
-        guard.get(&tx_id).cloned()
+        entries.into_iter().map(|(_, value)| value).collect()
     }
 
-    async fn save_tx(&self, _tx: StoredTX) {
+    /// Stream every value in the map, taking a snapshot of one shard at a time as the stream is
+    /// consumed, so only the values of a single shard are held at once.
+    ///
+    /// Values inserted while streaming are included if their shard wasn't reached yet.
+    fn stream_values(&self) -> BoxStream<'static, V>
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        let shards = self.shards.clone();
+
+        stream::iter(0..shards.len())
+            .then(move |shard| {
+                let shards = shards.clone();
+
+                async move {
+                    shards[shard]
+                        .lock()
+                        .await
+                        .values()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                }
+            })
+            .flat_map(stream::iter)
+            .boxed()
+    }
+}
+
+impl<K, V> Default for ShardedMap<K, V>
+where
+    K: Into<u64> + Copy + Eq + std::hash::Hash,
+    V: Clone,
+{
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl TTransactionRepository for TransactionInMemRepository {
+    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Result<Option<StoredTX>, RepoError> {
+        Ok(self.stored_transactions.get(tx_id).await)
+    }
+
+    async fn save_tx(&self, _tx: StoredTX) -> Result<(), RepoError> {
         // Atm, since this is only in memory, we don't actually
         // perform any changes.
+        Ok(())
     }
 
-    async fn store_tx(&self, tx: Transaction) -> StoredTX {
-        let tx_id = tx.transaction_id();
+    async fn store_tx(&self, tx: Transaction) -> Result<StoredTX, RepoError> {
+        let (tx_id, client_id) = (tx.transaction_id(), tx.client());
 
         let stored_tx = Arc::new(Mutex::new(tx));
 
-        {
-            let mut tx_guard = self.stored_transactions.lock().await;
+        let replaced = self
+            .stored_transactions
+            .insert(tx_id, stored_tx.clone())
+            .await;
+
+        // A transaction reusing the id of a stored one replaces it in the history of its client
+        if let Some(replaced) = replaced {
+            let replaced_client = replaced.lock().await.client();
 
-            tx_guard.insert(tx_id, stored_tx.clone());
+            self.by_client
+                .update(replaced_client, |history| {
+                    history.retain(|stored_tx| !Arc::ptr_eq(stored_tx, &replaced))
+                })
+                .await;
         }
 
-        stored_tx
+        self.by_client
+            .update(client_id, |history| history.push(stored_tx.clone()))
+            .await;
+
+        Ok(stored_tx)
     }
-}
 
-impl TClientRepository for ClientInMemRepository {
-    async fn find_all_clients(&self) -> BoxStream<'static, StoredClient> {
-        let client_guard = self.stored_clients.lock().await;
+    async fn remove_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        let (tx_id, client_id) = {
+            let transaction = tx.lock().await;
+
+            (transaction.transaction_id(), transaction.client())
+        };
+
+        // Unless another transaction with the same id was stored in the meantime
+        self.stored_transactions
+            .remove_if(tx_id, |stored_tx| Arc::ptr_eq(stored_tx, &tx))
+            .await;
+
+        self.by_client
+            .update(client_id, |history| {
+                history.retain(|stored_tx| !Arc::ptr_eq(stored_tx, &tx))
+            })
+            .await;
+
+        Ok(())
+    }
+
+    async fn find_txs_in_range(&self, range: Range<Timestamp>) -> Result<Vec<StoredTX>, RepoError> {
+        let stored = self.stored_transactions.values().await;
+
+        let mut in_range = Vec::new();
 
-        let stored_clients = client_guard
-            .values()
-            .cloned()
-            .collect::<Vec<StoredClient>>();
+        for stored_tx in stored {
+            let (timestamp, tx_id) = {
+                let tx = stored_tx.lock().await;
 
-        stream::iter(stored_clients).boxed()
+                (tx.timestamp(), tx.transaction_id())
+            };
+
+            if let Some(timestamp) = timestamp.filter(|timestamp| range.contains(timestamp)) {
+                in_range.push((timestamp, tx_id, stored_tx));
+            }
+        }
+
+        in_range.sort_by_key(|(timestamp, tx_id, _)| (*timestamp, *tx_id));
+
+        Ok(in_range
+            .into_iter()
+            .map(|(_, _, stored_tx)| stored_tx)
+            .collect())
     }
 
-    async fn find_client_by_id(&self, client_id: ClientID) -> Option<StoredClient> {
-        let client_guard = self.stored_clients.lock().await;
+    async fn find_txs_by_client(
+        &self,
+        client_id: ClientID,
+    ) -> Result<BoxStream<'static, StoredTX>, RepoError> {
+        let history = self.by_client.get(client_id).await.unwrap_or_default();
 
-        client_guard.get(&client_id).cloned()
+        Ok(stream::iter(history).boxed())
+    }
+}
+
+impl TClientRepository for ClientInMemRepository {
+    async fn find_all_clients(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        Ok(self.stored_clients.stream_values())
     }
 
-    async fn save_client(&self, _client: StoredClient) {
+    async fn find_all_clients_sorted(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        Ok(stream::iter(self.stored_clients.sorted_values().await).boxed())
+    }
+
+    async fn find_client_by_id(
+        &self,
+        client_id: ClientID,
+    ) -> Result<Option<StoredClient>, RepoError> {
+        Ok(self.stored_clients.get(client_id).await)
+    }
+
+    async fn save_client(&self, _client: StoredClient) -> Result<(), RepoError> {
         // Atm, since this is only in memory, we don't actually need
         // To save anything to the repository
+        Ok(())
     }
 
-    async fn store_client(&self, client: Client) -> StoredClient {
+    async fn store_client(&self, client: Client) -> Result<StoredClient, RepoError> {
         let cli_id = client.client_id();
 
         let stored_client = Arc::new(Mutex::new(client));
 
-        {
-            let mut client_guard = self.stored_clients.lock().await;
+        self.stored_clients
+            .insert(cli_id, stored_client.clone())
+            .await;
+
+        Ok(stored_client)
+    }
+
+    async fn save_client_if_version(
+        &self,
+        mut client: Client,
+        expected_version: u64,
+    ) -> Result<StoredClient, RepoError> {
+        let cli_id = client.client_id();
+
+        // Hold the lock of the client's shard for the whole check, so no one can store the
+        // client between our check and our write
+        let mut client_guard = self.stored_clients.shard(cli_id).lock().await;
+
+        let Some(stored_client) = client_guard.get(&cli_id).cloned() else {
+            if expected_version != 0 {
+                return Err(ClientVersionConflict {
+                    client: cli_id,
+                    expected: expected_version,
+                    current: 0,
+                }
+                .into());
+            }
+
+            client.succeed(expected_version);
+
+            let stored_client = Arc::new(Mutex::new(client));
 
             client_guard.insert(cli_id, stored_client.clone());
+
+            return Ok(stored_client);
+        };
+
+        {
+            // The transaction processing changes the stored client in place, so we also have
+            // to hold its lock from the check until the write
+            let mut current = stored_client.lock().await;
+
+            if current.version() != expected_version {
+                return Err(ClientVersionConflict {
+                    client: cli_id,
+                    expected: expected_version,
+                    current: current.version(),
+                }
+                .into());
+            }
+
+            client.succeed(expected_version);
+
+            *current = client;
+        }
+
+        Ok(stored_client)
+    }
+}
+
+#[cfg(test)]
+mod in_mem_tests {
+    use futures::StreamExt;
+
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::client::Client;
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::transactions::TTransactionRepository;
+
+    #[tokio::test]
+    async fn test_sharded_clients() {
+        let client_repo = ClientInMemRepository::with_shards(3);
+
+        for client_id in 1..=10 {
+            let client = Client::builder()
+                .with_client_id(ClientID(client_id))
+                .build();
+
+            client_repo.save_client_if_version(client, 0).await.unwrap();
+        }
+
+        // Clients of the same shard don't get in the way of each other
+        assert!(client_repo
+            .find_client_by_id(ClientID(4))
+            .await
+            .unwrap()
+            .is_some());
+        assert!(client_repo
+            .find_client_by_id(ClientID(11))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(client_repo
+            .save_client_if_version(Client::builder().with_client_id(ClientID(7)).build(), 0)
+            .await
+            .is_err());
+
+        let mut client_ids = Vec::new();
+
+        let mut clients = client_repo.find_all_clients().await.unwrap();
+
+        while let Some(client) = clients.next().await {
+            client_ids.push(client.lock().await.client_id());
         }
 
-        stored_client
+        client_ids.sort();
+
+        assert_eq!(client_ids, (1..=10).map(ClientID).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_find_all_clients_lazily() {
+        let client_repo = ClientInMemRepository::with_shards(2);
+
+        client_repo
+            .store_client(Client::builder().with_client_id(ClientID(2)).build())
+            .await
+            .unwrap();
+
+        let mut clients = client_repo.find_all_clients().await.unwrap();
+
+        assert_eq!(
+            clients.next().await.unwrap().lock().await.client_id(),
+            ClientID(2)
+        );
+
+        // The second shard is only read once the first one has been streamed
+        client_repo
+            .store_client(Client::builder().with_client_id(ClientID(3)).build())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            clients.next().await.unwrap().lock().await.client_id(),
+            ClientID(3)
+        );
+        assert!(clients.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_find_all_clients_sorted() {
+        let client_repo = ClientInMemRepository::with_shards(3);
+
+        for id in [7, 2, 9, 4, 1] {
+            client_repo
+                .store_client(Client::builder().with_client_id(ClientID(id)).build())
+                .await
+                .unwrap();
+        }
+
+        let clients = client_repo
+            .find_all_clients_sorted()
+            .await
+            .unwrap()
+            .then(|client| async move { client.lock().await.client_id() })
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(clients, [1, 2, 4, 7, 9].map(ClientID).to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_find_txs_in_range() {
+        let tx_repo = TransactionInMemRepository::default();
+
+        for (tx_id, timestamp) in [(1, Some(300)), (2, Some(100)), (3, None), (4, Some(200))] {
+            let mut tx = Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(10000),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .build();
+
+            if let Some(timestamp) = timestamp {
+                tx.assign_timestamp(timestamp);
+            }
+
+            tx_repo.store_tx(tx).await.unwrap();
+        }
+
+        let mut in_range = Vec::new();
+
+        for stored_tx in tx_repo.find_txs_in_range(100..300).await.unwrap() {
+            in_range.push(stored_tx.lock().await.transaction_id());
+        }
+
+        // Ordered by when they happened, with the end of the range excluded
+        assert_eq!(in_range, vec![TransactionID(2), TransactionID(4)]);
+        assert!(tx_repo.find_txs_in_range(0..100).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_find_txs_by_client() {
+        let tx_repo = TransactionInMemRepository::default();
+
+        for (client, tx_id) in [(1, 1), (2, 2), (1, 3), (2, 1)] {
+            let tx = Transaction::builder()
+                .with_client_id(ClientID(client))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(10000),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .build();
+
+            tx_repo.store_tx(tx).await.unwrap();
+        }
+
+        let history = |client| {
+            let tx_repo = &tx_repo;
+
+            async move {
+                tx_repo
+                    .find_txs_by_client(ClientID(client))
+                    .await
+                    .unwrap()
+                    .then(|stored_tx| async move { stored_tx.lock().await.transaction_id() })
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        // The last transaction reused the id of the first, replacing it
+        assert_eq!(history(1).await, vec![TransactionID(3)]);
+        assert_eq!(history(2).await, vec![TransactionID(2), TransactionID(1)]);
+        assert!(history(3).await.is_empty());
     }
 }
@@ -0,0 +1,338 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use futures::stream::BoxStream;
+
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, Timestamp, TransactionID};
+use crate::repositories::transactions::{StoredTX, TTransactionRepository};
+use crate::repositories::RepoError;
+
+/// The amount of transactions kept in the cache by default
+pub const DEFAULT_MAX_CACHED_TRANSACTIONS: usize = 100_000;
+
+/// The memory taken by each cached entry besides the transaction itself: the shared allocation
+/// and lock of the stored transaction and the entries in the cache's maps
+const ENTRY_OVERHEAD: usize = 96;
+
+/// Transaction repository keeping the most recently used transactions of the repository behind
+/// it in memory, up to a maximum amount of transactions and of memory.
+///
+/// Changes are written through to the repository behind it when the transaction is saved, so
+/// the least recently used transactions can be evicted at any time, and are loaded from that
+/// repository again once they are needed (e.g. when they are disputed). Only the memory
+/// kept by the cache is bounded, the repository behind it should let go of the transactions
+/// once no one is using them.
+pub struct LruTransactionRepository<TR> {
+    repo: TR,
+    max_entries: usize,
+    max_bytes: Option<usize>,
+    cache: std::sync::Mutex<LruCache>,
+}
+
+#[derive(Default)]
+struct LruCache {
+    entries: HashMap<TransactionID, CacheEntry>,
+    /// The cached transactions, from the least to the most recently used
+    recency: BTreeMap<u64, TransactionID>,
+    next_use: u64,
+    bytes: usize,
+}
+
+struct CacheEntry {
+    stored_tx: StoredTX,
+    last_use: u64,
+    bytes: usize,
+}
+
+impl<TR> LruTransactionRepository<TR>
+where
+    TR: TTransactionRepository,
+{
+    pub fn new(repo: TR) -> Self {
+        Self {
+            repo,
+            max_entries: DEFAULT_MAX_CACHED_TRANSACTIONS,
+            max_bytes: None,
+            cache: Default::default(),
+        }
+    }
+
+    /// Keep at most this many transactions in memory, instead of
+    /// [`DEFAULT_MAX_CACHED_TRANSACTIONS`]
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+
+        self
+    }
+
+    /// Keep at most (approximately) this many bytes of transactions in memory
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+
+        self
+    }
+
+    /// The amount of transactions currently cached
+    pub fn cached_entries(&self) -> usize {
+        self.cache().entries.len()
+    }
+
+    /// The approximate memory taken by the cached transactions
+    pub fn cached_bytes(&self) -> usize {
+        self.cache().bytes
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, LruCache> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Cache the transaction as the most recently used one, evicting the least recently used
+    /// ones which no longer fit
+    fn cache_tx(&self, tx_id: TransactionID, stored_tx: StoredTX, bytes: usize) {
+        let mut cache = self.cache();
+
+        cache.insert(tx_id, stored_tx, bytes);
+
+        while cache.entries.len() > self.max_entries
+            || self
+                .max_bytes
+                .is_some_and(|max_bytes| cache.bytes > max_bytes)
+        {
+            if cache.evict_least_recently_used().is_none() {
+                break;
+            }
+        }
+    }
+}
+
+impl LruCache {
+    fn get(&mut self, tx_id: TransactionID) -> Option<StoredTX> {
+        let next_use = self.next_use;
+
+        let entry = self.entries.get_mut(&tx_id)?;
+
+        self.recency.remove(&entry.last_use);
+        self.recency.insert(next_use, tx_id);
+
+        entry.last_use = next_use;
+        self.next_use += 1;
+
+        Some(entry.stored_tx.clone())
+    }
+
+    fn insert(&mut self, tx_id: TransactionID, stored_tx: StoredTX, bytes: usize) {
+        self.remove(tx_id);
+
+        self.recency.insert(self.next_use, tx_id);
+        self.entries.insert(
+            tx_id,
+            CacheEntry {
+                stored_tx,
+                last_use: self.next_use,
+                bytes,
+            },
+        );
+
+        self.next_use += 1;
+        self.bytes += bytes;
+    }
+
+    fn remove(&mut self, tx_id: TransactionID) {
+        if let Some(entry) = self.entries.remove(&tx_id) {
+            self.recency.remove(&entry.last_use);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    fn evict_least_recently_used(&mut self) -> Option<TransactionID> {
+        let (_, tx_id) = self.recency.pop_first()?;
+
+        if let Some(entry) = self.entries.remove(&tx_id) {
+            self.bytes -= entry.bytes;
+        }
+
+        Some(tx_id)
+    }
+}
+
+/// The approximate memory taken by a cached transaction
+fn footprint(transaction: &Transaction) -> usize {
+    let state = transaction.dispute_state();
+    let dispute = [state.dispute(), state.settlement(), state.representment()]
+        .into_iter()
+        .flatten()
+        .count()
+        * size_of::<Transaction>();
+
+    ENTRY_OVERHEAD + size_of::<Transaction>() + dispute
+}
+
+impl<TR> TTransactionRepository for LruTransactionRepository<TR>
+where
+    TR: TTransactionRepository,
+{
+    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Result<Option<StoredTX>, RepoError> {
+        if let Some(stored_tx) = self.cache().get(tx_id) {
+            return Ok(Some(stored_tx));
+        }
+
+        let Some(stored_tx) = self.repo.find_tx_by_id(tx_id).await? else {
+            return Ok(None);
+        };
+
+        let bytes = footprint(&*stored_tx.lock().await);
+
+        self.cache_tx(tx_id, stored_tx.clone(), bytes);
+
+        Ok(Some(stored_tx))
+    }
+
+    async fn save_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        let (tx_id, bytes) = {
+            let transaction = tx.lock().await;
+
+            (transaction.transaction_id(), footprint(&transaction))
+        };
+
+        self.repo.save_tx(tx.clone()).await?;
+
+        // Opening a dispute makes the transaction take more memory
+        self.cache_tx(tx_id, tx, bytes);
+
+        Ok(())
+    }
+
+    async fn store_tx(&self, tx: Transaction) -> Result<StoredTX, RepoError> {
+        let (tx_id, bytes) = (tx.transaction_id(), footprint(&tx));
+
+        let stored_tx = self.repo.store_tx(tx).await?;
+
+        self.cache_tx(tx_id, stored_tx.clone(), bytes);
+
+        Ok(stored_tx)
+    }
+
+    async fn remove_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        let tx_id = tx.lock().await.transaction_id();
+
+        self.repo.remove_tx(tx).await?;
+
+        self.cache().remove(tx_id);
+
+        Ok(())
+    }
+
+    async fn find_txs_in_range(&self, range: Range<Timestamp>) -> Result<Vec<StoredTX>, RepoError> {
+        // Scanning a range of time would flush out the transactions actually in use
+        self.repo.find_txs_in_range(range).await
+    }
+
+    async fn find_txs_by_client(
+        &self,
+        client_id: ClientID,
+    ) -> Result<BoxStream<'static, StoredTX>, RepoError> {
+        // Same as the ranges, the history of a client is rarely what's in use
+        self.repo.find_txs_by_client(client_id).await
+    }
+}
+
+#[cfg(test)]
+mod lru_cache_tests {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+
+    use crate::infrastructure::lru_cache::LruTransactionRepository;
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::transactions::{MockTTransactionRepository, TTransactionRepository};
+
+    fn deposit(tx_id: u32) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(tx_id))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(10000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_evicted_transactions_are_reloaded() {
+        let mut store = MockTTransactionRepository::new();
+
+        store
+            .expect_store_tx()
+            .times(3)
+            .returning(|tx| Box::pin(async move { Ok(Arc::new(Mutex::new(tx))) }));
+
+        // Only the evicted transaction is ever loaded from the store
+        store
+            .expect_find_tx_by_id()
+            .withf(|tx_id| *tx_id == TransactionID(1))
+            .times(1)
+            .returning(|tx_id| {
+                Box::pin(async move { Ok(Some(Arc::new(Mutex::new(deposit(tx_id.0))))) })
+            });
+
+        let repo = LruTransactionRepository::new(store).with_max_entries(2);
+
+        for tx_id in 1..=3 {
+            repo.store_tx(deposit(tx_id)).await.unwrap();
+        }
+
+        assert_eq!(repo.cached_entries(), 2);
+
+        // The transactions still cached are served from memory
+        assert!(repo
+            .find_tx_by_id(TransactionID(2))
+            .await
+            .unwrap()
+            .is_some());
+        assert!(repo
+            .find_tx_by_id(TransactionID(3))
+            .await
+            .unwrap()
+            .is_some());
+
+        assert!(repo
+            .find_tx_by_id(TransactionID(1))
+            .await
+            .unwrap()
+            .is_some());
+        assert!(repo
+            .find_tx_by_id(TransactionID(1))
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(repo.cached_entries(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes() {
+        let mut store = MockTTransactionRepository::new();
+
+        store
+            .expect_store_tx()
+            .returning(|tx| Box::pin(async move { Ok(Arc::new(Mutex::new(tx))) }));
+
+        let repo = LruTransactionRepository::new(store);
+
+        repo.store_tx(deposit(1)).await.unwrap();
+
+        let footprint = repo.cached_bytes();
+
+        let repo = repo.with_max_bytes(footprint * 3);
+
+        for tx_id in 2..=10 {
+            repo.store_tx(deposit(tx_id)).await.unwrap();
+        }
+
+        assert_eq!(repo.cached_entries(), 3);
+        assert_eq!(repo.cached_bytes(), footprint * 3);
+    }
+}
@@ -1 +1,12 @@
-pub(super) mod in_mem_dbs;
+pub mod event_store;
+pub mod in_mem_dbs;
+pub mod lru_cache;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub mod sql;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod write_behind;
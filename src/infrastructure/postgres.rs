@@ -0,0 +1,49 @@
+use sqlx::migrate::MigrateError;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use thiserror::Error;
+
+use crate::infrastructure::sql::{impl_sql_backend, SqlClientRepository, SqlTransactionRepository};
+use crate::repositories::RepoError;
+use crate::secrets::{SecretsError, TSecretsProvider};
+
+/// Connect to the database at the given url, bringing its schema up to date
+pub async fn connect(database_url: &str) -> Result<PgPool, PostgresError> {
+    let pool = PgPoolOptions::new().connect(database_url).await?;
+
+    sqlx::migrate!().run(&pool).await?;
+
+    Ok(pool)
+}
+
+/// Connect to the database whose url (along with its credentials) is the `database_url` secret
+pub async fn connect_with_secrets(
+    secrets: &impl TSecretsProvider,
+) -> Result<PgPool, PostgresError> {
+    let database_url = secrets.required_secret("database_url").await?;
+
+    connect(&database_url).await
+}
+
+#[derive(Error, Debug)]
+pub enum PostgresError {
+    #[error("Database error {0:?}")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("Failed to migrate the database {0:?}")]
+    MigrationError(#[from] MigrateError),
+    #[error("Failed to load the database credentials {0:?}")]
+    SecretsError(#[from] SecretsError),
+}
+
+impl From<PostgresError> for RepoError {
+    fn from(err: PostgresError) -> Self {
+        RepoError::BackendError(Box::new(err))
+    }
+}
+
+/// Client repository backed by PostgreSQL, see [`SqlClientRepository`]
+pub type PgClientRepository = SqlClientRepository<PgPool>;
+
+/// Transaction repository backed by PostgreSQL, see [`SqlTransactionRepository`]
+pub type PgTransactionRepository = SqlTransactionRepository<PgPool>;
+
+impl_sql_backend!(PgPool);
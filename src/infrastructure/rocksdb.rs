@@ -0,0 +1,706 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Weak};
+
+use futures::lock::Mutex;
+use futures::stream::BoxStream;
+use futures::{stream, StreamExt};
+use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
+
+use crate::models::transactions::{
+    AuthorizationState, DisputeMode, DisputeStage, DisputeState, Transaction, TransactionType,
+};
+use crate::models::{ClientID, MoneyType, Timestamp, TransactionID};
+use crate::repositories::transactions::{StoredTX, TTransactionRepository};
+use crate::repositories::RepoError;
+
+/// The column family indexing the transactions by when they happened
+const BY_TIME: &str = "by_time";
+
+/// The column family indexing the transactions by their client, in the order they were applied
+const BY_CLIENT: &str = "by_client";
+
+/// The version of the encoding of the stored transactions, which is the first byte of each
+const RECORD_FORMAT: u8 = 1;
+
+const RECORD_LEN: usize = 32;
+
+/// The amount of loaded transactions tracked before the ones no longer in use are forgotten
+const MIN_PRUNE_AT: usize = 1024;
+
+/// Transaction repository backed by a RocksDB database.
+///
+/// Unlike the other repositories, loaded transactions are only kept in memory while someone is
+/// still working on them, so the stored transactions can far exceed the available memory. Those
+/// working on the same transaction at the same time still share (and lock) the same instance.
+/// Changes are written to the database when the transaction is saved.
+///
+/// RocksDB reads are served from its block cache or from local disk, so they are done in place
+/// rather than on a blocking thread.
+pub struct RocksDbTransactionRepository {
+    db: DB,
+    loaded_transactions: std::sync::Mutex<LoadedTransactions>,
+}
+
+/// The transactions handed out which may still be in use
+struct LoadedTransactions {
+    transactions: HashMap<TransactionID, Weak<Mutex<Transaction>>>,
+    prune_at: usize,
+}
+
+impl From<rocksdb::Error> for RepoError {
+    fn from(err: rocksdb::Error) -> Self {
+        RepoError::BackendError(Box::new(err))
+    }
+}
+
+impl RocksDbTransactionRepository {
+    /// Open the database in the given directory, creating it if it doesn't exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RepoError> {
+        let mut options = Options::default();
+
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+
+        let db = DB::open_cf(&options, path, [BY_TIME, BY_CLIENT])?;
+
+        Ok(Self {
+            db,
+            loaded_transactions: std::sync::Mutex::new(LoadedTransactions {
+                transactions: HashMap::new(),
+                prune_at: MIN_PRUNE_AT,
+            }),
+        })
+    }
+
+    fn loaded_transactions(&self) -> std::sync::MutexGuard<'_, LoadedTransactions> {
+        self.loaded_transactions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Find the transaction, loading it from the database if no one is working on it
+    fn load(&self, tx_id: TransactionID) -> Result<Option<StoredTX>, RepoError> {
+        if let Some(stored_tx) = self.loaded_transactions().find(tx_id) {
+            return Ok(Some(stored_tx));
+        }
+
+        // Don't hold back everyone else while reading from the database
+        let Some(record) = self.db.get(tx_key(tx_id))? else {
+            return Ok(None);
+        };
+
+        let transaction = decode(tx_id, &record)?;
+
+        let mut loaded = self.loaded_transactions();
+
+        // Someone else might have loaded it in the meantime
+        if let Some(stored_tx) = loaded.find(tx_id) {
+            return Ok(Some(stored_tx));
+        }
+
+        let stored_tx = Arc::new(Mutex::new(transaction));
+
+        loaded.track(tx_id, &stored_tx);
+
+        Ok(Some(stored_tx))
+    }
+
+    /// Write the transaction, keeping its entries in the indexes up to date
+    fn write(&self, transaction: &Transaction) -> Result<(), RepoError> {
+        let tx_id = transaction.transaction_id();
+        let (by_time, by_client) = (self.by_time(), self.by_client());
+
+        let record = encode(transaction)?;
+
+        let mut batch = WriteBatch::default();
+
+        // A transaction reusing the id of a stored one replaces it, along with its index entries
+        if let Some(previous) = self.db.get(tx_key(tx_id))? {
+            let previous = decode(tx_id, &previous)?;
+
+            if let Some(timestamp) = previous.timestamp() {
+                batch.delete_cf(by_time, time_key(timestamp, tx_id));
+            }
+
+            batch.delete_cf(by_client, client_key(&previous));
+        }
+
+        if let Some(timestamp) = transaction.timestamp() {
+            batch.put_cf(by_time, time_key(timestamp, tx_id), b"");
+        }
+
+        batch.put_cf(by_client, client_key(transaction), b"");
+
+        batch.put(tx_key(tx_id), record);
+
+        Ok(self.db.write(batch)?)
+    }
+
+    /// The ids of the transactions which happened within the range, ordered by when they did
+    fn ids_in_range(&self, range: Range<Timestamp>) -> Result<Vec<TransactionID>, RepoError> {
+        let start = time_key(range.start, TransactionID::default());
+
+        let mut tx_ids = Vec::new();
+
+        for entry in self.db.iterator_cf(
+            self.by_time(),
+            IteratorMode::From(&start, Direction::Forward),
+        ) {
+            let (key, _) = entry?;
+
+            let (timestamp, tx_id) = key.split_at(size_of::<Timestamp>());
+
+            let timestamp =
+                Timestamp::from_be_bytes(timestamp.try_into().map_err(|_| {
+                    RepoError::InvalidData("timestamp of the time index".to_string())
+                })?);
+
+            if timestamp >= range.end {
+                break;
+            }
+
+            tx_ids.push(TransactionID(u32::from_be_bytes(
+                tx_id
+                    .try_into()
+                    .map_err(|_| RepoError::InvalidData("tx id of the time index".to_string()))?,
+            )));
+        }
+
+        Ok(tx_ids)
+    }
+
+    /// The ids of the transactions of the client, in the order they were applied to it
+    fn ids_of_client(&self, client_id: ClientID) -> Result<Vec<TransactionID>, RepoError> {
+        let prefix = client_id.0.to_be_bytes();
+
+        let mut tx_ids = Vec::new();
+
+        for entry in self.db.iterator_cf(
+            self.by_client(),
+            IteratorMode::From(&prefix, Direction::Forward),
+        ) {
+            let (key, _) = entry?;
+
+            if !key.starts_with(&prefix) {
+                break;
+            }
+
+            let tx_id = key[CLIENT_KEY_LEN - size_of::<u32>()..]
+                .try_into()
+                .map_err(|_| RepoError::InvalidData("tx id of the client index".to_string()))?;
+
+            tx_ids.push(TransactionID(u32::from_be_bytes(tx_id)));
+        }
+
+        Ok(tx_ids)
+    }
+
+    fn by_time(&self) -> &rocksdb::ColumnFamily {
+        self.db
+            .cf_handle(BY_TIME)
+            .expect("Created when the database is opened")
+    }
+
+    fn by_client(&self) -> &rocksdb::ColumnFamily {
+        self.db
+            .cf_handle(BY_CLIENT)
+            .expect("Created when the database is opened")
+    }
+}
+
+impl LoadedTransactions {
+    fn find(&self, tx_id: TransactionID) -> Option<StoredTX> {
+        self.transactions.get(&tx_id).and_then(Weak::upgrade)
+    }
+
+    fn track(&mut self, tx_id: TransactionID, stored_tx: &StoredTX) {
+        self.transactions.insert(tx_id, Arc::downgrade(stored_tx));
+
+        if self.transactions.len() >= self.prune_at {
+            self.transactions
+                .retain(|_, stored_tx| stored_tx.strong_count() > 0);
+
+            self.prune_at = MIN_PRUNE_AT.max(self.transactions.len() * 2);
+        }
+    }
+
+    /// Stop tracking the transaction, unless another one with the same id was loaded since
+    fn forget(&mut self, tx_id: TransactionID, stored_tx: &StoredTX) {
+        if self
+            .transactions
+            .get(&tx_id)
+            .is_some_and(|tracked| tracked.as_ptr() == Arc::as_ptr(stored_tx))
+        {
+            self.transactions.remove(&tx_id);
+        }
+    }
+}
+
+impl TTransactionRepository for RocksDbTransactionRepository {
+    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Result<Option<StoredTX>, RepoError> {
+        self.load(tx_id)
+    }
+
+    async fn save_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        let transaction = tx.lock().await.clone();
+
+        self.write(&transaction)
+    }
+
+    async fn store_tx(&self, tx: Transaction) -> Result<StoredTX, RepoError> {
+        let tx_id = tx.transaction_id();
+
+        self.write(&tx)?;
+
+        let stored_tx = Arc::new(Mutex::new(tx));
+
+        self.loaded_transactions().track(tx_id, &stored_tx);
+
+        Ok(stored_tx)
+    }
+
+    async fn remove_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        let transaction = tx.lock().await.clone();
+        let tx_id = transaction.transaction_id();
+
+        let mut batch = WriteBatch::default();
+
+        if let Some(timestamp) = transaction.timestamp() {
+            batch.delete_cf(self.by_time(), time_key(timestamp, tx_id));
+        }
+
+        batch.delete_cf(self.by_client(), client_key(&transaction));
+        batch.delete(tx_key(tx_id));
+
+        self.db.write(batch)?;
+
+        self.loaded_transactions().forget(tx_id, &tx);
+
+        Ok(())
+    }
+
+    async fn find_txs_in_range(&self, range: Range<Timestamp>) -> Result<Vec<StoredTX>, RepoError> {
+        let tx_ids = self.ids_in_range(range)?;
+
+        let mut in_range = Vec::with_capacity(tx_ids.len());
+
+        for tx_id in tx_ids {
+            if let Some(stored_tx) = self.load(tx_id)? {
+                in_range.push(stored_tx);
+            }
+        }
+
+        Ok(in_range)
+    }
+
+    async fn find_txs_by_client(
+        &self,
+        client_id: ClientID,
+    ) -> Result<BoxStream<'static, StoredTX>, RepoError> {
+        let mut history = Vec::new();
+
+        for tx_id in self.ids_of_client(client_id)? {
+            if let Some(stored_tx) = self.load(tx_id)? {
+                history.push(stored_tx);
+            }
+        }
+
+        Ok(stream::iter(history).boxed())
+    }
+}
+
+/// Keys are big endian, so the transactions are ordered by id
+fn tx_key(tx_id: TransactionID) -> [u8; 4] {
+    tx_id.0.to_be_bytes()
+}
+
+fn time_key(timestamp: Timestamp, tx_id: TransactionID) -> [u8; 12] {
+    let mut key = [0; 12];
+
+    key[..8].copy_from_slice(&timestamp.to_be_bytes());
+    key[8..].copy_from_slice(&tx_id.0.to_be_bytes());
+
+    key
+}
+
+const CLIENT_KEY_LEN: usize = 14;
+
+fn client_key(transaction: &Transaction) -> [u8; CLIENT_KEY_LEN] {
+    let mut key = [0; CLIENT_KEY_LEN];
+
+    key[..2].copy_from_slice(&transaction.client().0.to_be_bytes());
+    key[2..10].copy_from_slice(&transaction.client_sequence().unwrap_or(0).to_be_bytes());
+    key[10..].copy_from_slice(&transaction.transaction_id().0.to_be_bytes());
+
+    key
+}
+
+/// Encode a stored transaction as a fixed size record of
+/// `format, type, dispute state, flags, client, amount, destination, client sequence, timestamp`.
+///
+/// Only deposits, withdrawals, authorizations and transfers are ever stored, as disputes, their
+/// settlements and captures are stored as the state of the transaction they target, so any other
+/// transaction is refused. Authorizations can't be disputed, so their dispute state is whether they were captured or
+/// expired. The flags tell whether the client sequence and the timestamp are set, and whether
+/// the dispute provisionally credited the client.
+fn encode(transaction: &Transaction) -> Result<Vec<u8>, RepoError> {
+    let (tx_type, amount, dispute_state) = match transaction.tx_type() {
+        TransactionType::Deposit { amount, dispute } => (0u8, *amount, dispute_state(dispute)),
+        TransactionType::Withdrawal { amount, dispute } => (1, *amount, dispute_state(dispute)),
+        TransactionType::Transfer { amount, .. } => (2, *amount, 0),
+        TransactionType::Authorize { amount, state } => {
+            let state = match state {
+                AuthorizationState::Pending => 0,
+                AuthorizationState::Captured => 1,
+                AuthorizationState::Expired => 2,
+            };
+
+            (3, *amount, state)
+        }
+        other => {
+            return Err(RepoError::InvalidData(format!(
+                "a {} can't be stored as tx {}",
+                other.name(),
+                transaction.transaction_id()
+            )))
+        }
+    };
+
+    let credited = transaction.dispute_state().mode() == Some(DisputeMode::ProvisionalCredit);
+
+    let flags = u8::from(transaction.client_sequence().is_some())
+        | (u8::from(transaction.timestamp().is_some()) << 1)
+        | (u8::from(credited) << 2);
+
+    let mut record = Vec::with_capacity(RECORD_LEN);
+
+    record.extend([RECORD_FORMAT, tx_type, dispute_state, flags]);
+    record.extend(transaction.client().0.to_le_bytes());
+    record.extend(amount.raw().to_le_bytes());
+    record.extend(
+        transaction
+            .destination()
+            .unwrap_or_default()
+            .0
+            .to_le_bytes(),
+    );
+    record.extend(transaction.client_sequence().unwrap_or(0).to_le_bytes());
+    record.extend(transaction.timestamp().unwrap_or(0).to_le_bytes());
+
+    Ok(record)
+}
+
+fn dispute_state(dispute: &DisputeState) -> u8 {
+    match dispute.stage() {
+        DisputeStage::NotDisputed => 0,
+        DisputeStage::Open => 1,
+        DisputeStage::Resolved => 2,
+        DisputeStage::ChargedBack => 3,
+        DisputeStage::Represented => 4,
+    }
+}
+
+fn decode(tx_id: TransactionID, record: &[u8]) -> Result<Transaction, RepoError> {
+    let invalid = |what: &str| RepoError::InvalidData(format!("{} of tx {}", what, tx_id));
+
+    let record: &[u8; RECORD_LEN] = record.try_into().map_err(|_| invalid("length"))?;
+
+    if record[0] != RECORD_FORMAT {
+        return Err(invalid("format"));
+    }
+
+    let (tx_type, dispute_state, flags) = (record[1], record[2], record[3]);
+
+    let client_id = ClientID(u16::from_le_bytes([record[4], record[5]]));
+    let amount = MoneyType::new(i64::from_le_bytes(
+        record[6..14].try_into().expect("8 bytes"),
+    ));
+    let to_client = ClientID(u16::from_le_bytes([record[14], record[15]]));
+    let client_sequence = u64::from_le_bytes(record[16..24].try_into().expect("8 bytes"));
+    let timestamp = Timestamp::from_le_bytes(record[24..32].try_into().expect("8 bytes"));
+
+    let tx_type = match tx_type {
+        0 => TransactionType::Deposit {
+            amount,
+            dispute: DisputeState::NotDisputed,
+        },
+        1 => TransactionType::Withdrawal {
+            amount,
+            dispute: DisputeState::NotDisputed,
+        },
+        2 => TransactionType::Transfer { to_client, amount },
+        3 => TransactionType::Authorize {
+            amount,
+            state: AuthorizationState::Pending,
+        },
+        _ => return Err(invalid("type")),
+    };
+
+    let mut transaction = Transaction::builder()
+        .with_tx_id(tx_id)
+        .with_client_id(client_id)
+        .with_tx_type(tx_type)
+        .build();
+
+    if flags & 1 != 0 {
+        transaction.assign_client_sequence(client_sequence);
+    }
+
+    if flags & 2 != 0 {
+        transaction.assign_timestamp(timestamp);
+    }
+
+    // Replay the dispute through the model, so it goes through the same checks
+    let related = |tx_type| {
+        Transaction::builder()
+            .with_tx_id(tx_id)
+            .with_client_id(client_id)
+            .with_tx_type(tx_type)
+            .build()
+    };
+
+    if let TransactionType::Authorize { .. } = transaction.tx_type() {
+        match dispute_state {
+            0 => {}
+            1 => transaction
+                .capture(related(TransactionType::Capture))
+                .map_err(|_| invalid("capture"))?,
+            2 => transaction.expire().map_err(|_| invalid("expiry"))?,
+            _ => return Err(invalid("authorization state")),
+        }
+
+        return Ok(transaction);
+    }
+
+    let settlement = match dispute_state {
+        0 => return Ok(transaction),
+        1 => None,
+        2 => Some(TransactionType::Resolve),
+        3 | 4 => Some(TransactionType::Chargeback),
+        _ => return Err(invalid("dispute state")),
+    };
+
+    let mode = match flags & 4 {
+        0 => DisputeMode::Hold,
+        _ => DisputeMode::ProvisionalCredit,
+    };
+
+    transaction
+        .dispute_with_mode(related(TransactionType::Dispute), mode)
+        .map_err(|_| invalid("dispute"))?;
+
+    if let Some(settlement) = settlement {
+        transaction
+            .settle_dispute(related(settlement))
+            .map_err(|_| invalid("dispute settlement"))?;
+    }
+
+    if dispute_state == 4 {
+        transaction
+            .represent(related(TransactionType::Representment))
+            .map_err(|_| invalid("representment"))?;
+    }
+
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod rocksdb_tests {
+    use futures::StreamExt;
+
+    use crate::infrastructure::rocksdb::{decode, encode, RocksDbTransactionRepository};
+    use crate::models::transactions::{
+        AuthorizationState, DisputeStage, DisputeState, Transaction, TransactionType,
+    };
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::transactions::TTransactionRepository;
+    use crate::repositories::RepoError;
+
+    fn transaction(tx_id: u32, tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_tx_id(TransactionID(tx_id))
+            .with_client_id(ClientID(3))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    #[test]
+    fn test_transaction_records() {
+        let mut deposit = transaction(
+            7,
+            TransactionType::Deposit {
+                amount: MoneyType::new(1234),
+                dispute: DisputeState::NotDisputed,
+            },
+        );
+
+        deposit.assign_client_sequence(2);
+        deposit.assign_timestamp(1_700_000_000);
+
+        assert_eq!(
+            decode(TransactionID(7), &encode(&deposit).unwrap()).unwrap(),
+            deposit
+        );
+
+        deposit
+            .dispute(transaction(7, TransactionType::Dispute))
+            .unwrap();
+        deposit
+            .settle_dispute(transaction(7, TransactionType::Chargeback))
+            .unwrap();
+
+        assert_eq!(
+            decode(TransactionID(7), &encode(&deposit).unwrap()).unwrap(),
+            deposit
+        );
+
+        deposit
+            .represent(transaction(7, TransactionType::Representment))
+            .unwrap();
+
+        assert_eq!(
+            decode(TransactionID(7), &encode(&deposit).unwrap()).unwrap(),
+            deposit
+        );
+
+        let mut authorization = transaction(
+            9,
+            TransactionType::Authorize {
+                amount: MoneyType::new(300),
+                state: AuthorizationState::Pending,
+            },
+        );
+
+        assert_eq!(
+            decode(TransactionID(9), &encode(&authorization).unwrap()).unwrap(),
+            authorization
+        );
+
+        authorization
+            .capture(transaction(9, TransactionType::Capture))
+            .unwrap();
+
+        assert_eq!(
+            decode(TransactionID(9), &encode(&authorization).unwrap()).unwrap(),
+            authorization
+        );
+
+        let mut expired = transaction(
+            10,
+            TransactionType::Authorize {
+                amount: MoneyType::new(300),
+                state: AuthorizationState::Pending,
+            },
+        );
+
+        expired.expire().unwrap();
+
+        assert_eq!(
+            decode(TransactionID(10), &encode(&expired).unwrap()).unwrap(),
+            expired
+        );
+
+        let transfer = transaction(
+            8,
+            TransactionType::Transfer {
+                to_client: ClientID(4),
+                amount: MoneyType::new(100),
+            },
+        );
+
+        assert_eq!(
+            decode(TransactionID(8), &encode(&transfer).unwrap()).unwrap(),
+            transfer
+        );
+        assert!(decode(TransactionID(8), &encode(&transfer).unwrap()[1..]).is_err());
+
+        // Disputes are kept as the state of the transaction they target, never on their own
+        assert!(matches!(
+            encode(&transaction(7, TransactionType::Dispute)),
+            Err(RepoError::InvalidData(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rocksdb_repository() {
+        let path =
+            std::env::temp_dir().join(format!("transactioner-rocksdb-{}", std::process::id()));
+
+        {
+            let repo = RocksDbTransactionRepository::open(&path).unwrap();
+
+            for (tx_id, timestamp) in [(1, 300), (2, 100), (3, 200)] {
+                let mut deposit = transaction(
+                    tx_id,
+                    TransactionType::Deposit {
+                        amount: MoneyType::new(10000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                );
+
+                deposit.assign_timestamp(timestamp);
+                deposit.assign_client_sequence(u64::from(4 - tx_id));
+
+                repo.store_tx(deposit).await.unwrap();
+            }
+
+            let stored_tx = repo.find_tx_by_id(TransactionID(2)).await.unwrap().unwrap();
+
+            // Everyone working on the transaction shares the same instance
+            let found_again = repo.find_tx_by_id(TransactionID(2)).await.unwrap().unwrap();
+
+            assert!(std::sync::Arc::ptr_eq(&stored_tx, &found_again));
+
+            stored_tx
+                .lock()
+                .await
+                .dispute(transaction(2, TransactionType::Dispute))
+                .unwrap();
+
+            repo.save_tx(stored_tx).await.unwrap();
+        }
+
+        // Everything is found again once the database is reopened
+        let repo = RocksDbTransactionRepository::open(&path).unwrap();
+
+        let stored_tx = repo.find_tx_by_id(TransactionID(2)).await.unwrap().unwrap();
+
+        assert_eq!(
+            stored_tx.lock().await.dispute_state().stage(),
+            DisputeStage::Open
+        );
+
+        assert!(repo
+            .find_tx_by_id(TransactionID(4))
+            .await
+            .unwrap()
+            .is_none());
+
+        let mut in_range = Vec::new();
+
+        for stored_tx in repo.find_txs_in_range(100..300).await.unwrap() {
+            in_range.push(stored_tx.lock().await.transaction_id());
+        }
+
+        assert_eq!(in_range, vec![TransactionID(2), TransactionID(3)]);
+
+        let history = repo
+            .find_txs_by_client(ClientID(3))
+            .await
+            .unwrap()
+            .then(|stored_tx| async move { stored_tx.lock().await.transaction_id() })
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            history,
+            vec![TransactionID(3), TransactionID(2), TransactionID(1)]
+        );
+
+        drop((stored_tx, repo));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
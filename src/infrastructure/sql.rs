@@ -0,0 +1,1105 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Range;
+use std::sync::Arc;
+
+use futures::lock::Mutex;
+use futures::stream::BoxStream;
+use futures::{stream, StreamExt};
+use sqlx::FromRow;
+use thiserror::Error;
+
+use crate::models::client::{Client, ClientAccountStatus, DisputeLedger};
+use crate::models::transactions::{
+    AuthorizationState, DisputeMode, DisputeStage, DisputeState, Transaction, TransactionType,
+};
+use crate::models::{ClientID, MoneyType, Timestamp, TransactionID};
+use crate::repositories::clients::{ClientVersionConflict, StoredClient, TClientRepository};
+use crate::repositories::transactions::{StoredTX, TTransactionRepository};
+use crate::repositories::warm_up::TWarmUpSource;
+use crate::repositories::RepoError;
+
+/// The queries the SQL repositories run against their database.
+///
+/// Every database takes the same statements, so this is implemented for their pools with the
+/// `impl_sql_backend` macro, as they only differ in their types.
+pub trait TSqlBackend: Send + Sync + 'static {
+    fn find_client_row(
+        &self,
+        client_id: ClientID,
+    ) -> impl Future<Output = Result<Option<ClientRow>, sqlx::Error>> + Send;
+
+    /// Every client row, ordered by the client id
+    fn find_all_client_rows(
+        &self,
+    ) -> impl Future<Output = Result<Vec<ClientRow>, sqlx::Error>> + Send;
+
+    fn find_client_version(
+        &self,
+        client_id: ClientID,
+    ) -> impl Future<Output = Result<i64, sqlx::Error>> + Send;
+
+    /// The rows of the `limit` clients with the most recent transactions, most recent first
+    fn find_hottest_client_rows(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<ClientRow>, sqlx::Error>> + Send;
+
+    /// Run one of the client statements with the given row, returning the amount of changed rows
+    fn execute_client_row(
+        &self,
+        row: &ClientRow,
+        statement: &'static str,
+        expected_version: Option<u64>,
+    ) -> impl Future<Output = Result<u64, sqlx::Error>> + Send;
+
+    fn find_transaction_row(
+        &self,
+        tx_id: TransactionID,
+    ) -> impl Future<Output = Result<Option<TransactionRow>, sqlx::Error>> + Send;
+
+    fn find_transaction_rows_in_range(
+        &self,
+        range: Range<Timestamp>,
+    ) -> impl Future<Output = Result<Vec<TransactionRow>, sqlx::Error>> + Send;
+
+    /// The rows of the transactions of the client, in the order they were applied to it
+    fn find_transaction_rows_by_client(
+        &self,
+        client_id: ClientID,
+    ) -> impl Future<Output = Result<Vec<TransactionRow>, sqlx::Error>> + Send;
+
+    /// The rows of the `limit` most recent deposits and withdrawals which are not disputed or
+    /// whose dispute is still open, most recent first
+    fn find_recent_disputable_transaction_rows(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<TransactionRow>, sqlx::Error>> + Send;
+
+    fn upsert_transaction_row(
+        &self,
+        row: TransactionRow,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    /// Write the client sequence and the dispute state of the row, the only parts of a stored
+    /// transaction which ever change
+    fn update_transaction_row(
+        &self,
+        row: TransactionRow,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    fn delete_transaction_row(
+        &self,
+        tx_id: TransactionID,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+}
+
+/// Implement [`TSqlBackend`] for the pool of a database
+macro_rules! impl_sql_backend {
+    ($pool:ty) => {
+        impl $crate::infrastructure::sql::TSqlBackend for $pool {
+            async fn find_client_row(
+                &self,
+                client_id: $crate::models::ClientID,
+            ) -> Result<Option<$crate::infrastructure::sql::ClientRow>, sqlx::Error> {
+                sqlx::query_as("SELECT * FROM clients WHERE client_id = $1")
+                    .bind(i32::from(client_id.0))
+                    .fetch_optional(self)
+                    .await
+            }
+
+            async fn find_all_client_rows(
+                &self,
+            ) -> Result<Vec<$crate::infrastructure::sql::ClientRow>, sqlx::Error> {
+                sqlx::query_as("SELECT * FROM clients ORDER BY client_id")
+                    .fetch_all(self)
+                    .await
+            }
+
+            async fn find_client_version(
+                &self,
+                client_id: $crate::models::ClientID,
+            ) -> Result<i64, sqlx::Error> {
+                sqlx::query_scalar("SELECT version FROM clients WHERE client_id = $1")
+                    .bind(i32::from(client_id.0))
+                    .fetch_one(self)
+                    .await
+            }
+
+            async fn find_hottest_client_rows(
+                &self,
+                limit: usize,
+            ) -> Result<Vec<$crate::infrastructure::sql::ClientRow>, sqlx::Error> {
+                sqlx::query_as($crate::infrastructure::sql::SELECT_HOTTEST_CLIENTS)
+                    .bind(limit as i64)
+                    .fetch_all(self)
+                    .await
+            }
+
+            async fn execute_client_row(
+                &self,
+                row: &$crate::infrastructure::sql::ClientRow,
+                statement: &'static str,
+                expected_version: Option<u64>,
+            ) -> Result<u64, sqlx::Error> {
+                let mut query = sqlx::query(statement)
+                    .bind(row.client_id)
+                    .bind(row.available)
+                    .bind(row.held)
+                    .bind(row.locked)
+                    .bind(row.transaction_count)
+                    .bind(row.version)
+                    .bind(row.open_disputes)
+                    .bind(row.held_for_deposits)
+                    .bind(row.held_for_withdrawals)
+                    .bind(row.closed)
+                    .bind(row.credit_limit);
+
+                if let Some(expected_version) = expected_version {
+                    query = query.bind(expected_version as i64);
+                }
+
+                Ok(query.execute(self).await?.rows_affected())
+            }
+
+            async fn find_transaction_row(
+                &self,
+                tx_id: $crate::models::TransactionID,
+            ) -> Result<Option<$crate::infrastructure::sql::TransactionRow>, sqlx::Error> {
+                sqlx::query_as("SELECT * FROM transactions WHERE tx_id = $1")
+                    .bind(i64::from(tx_id.0))
+                    .fetch_optional(self)
+                    .await
+            }
+
+            async fn find_transaction_rows_in_range(
+                &self,
+                range: std::ops::Range<$crate::models::Timestamp>,
+            ) -> Result<Vec<$crate::infrastructure::sql::TransactionRow>, sqlx::Error> {
+                sqlx::query_as($crate::infrastructure::sql::SELECT_TRANSACTIONS_IN_RANGE)
+                    .bind(range.start as i64)
+                    .bind(range.end as i64)
+                    .fetch_all(self)
+                    .await
+            }
+
+            async fn find_transaction_rows_by_client(
+                &self,
+                client_id: $crate::models::ClientID,
+            ) -> Result<Vec<$crate::infrastructure::sql::TransactionRow>, sqlx::Error> {
+                sqlx::query_as($crate::infrastructure::sql::SELECT_CLIENT_TRANSACTIONS)
+                    .bind(i32::from(client_id.0))
+                    .fetch_all(self)
+                    .await
+            }
+
+            async fn find_recent_disputable_transaction_rows(
+                &self,
+                limit: usize,
+            ) -> Result<Vec<$crate::infrastructure::sql::TransactionRow>, sqlx::Error> {
+                sqlx::query_as($crate::infrastructure::sql::SELECT_RECENT_DISPUTABLE_TRANSACTIONS)
+                    .bind(limit as i64)
+                    .fetch_all(self)
+                    .await
+            }
+
+            async fn upsert_transaction_row(
+                &self,
+                row: $crate::infrastructure::sql::TransactionRow,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query($crate::infrastructure::sql::UPSERT_TRANSACTION)
+                    .bind(row.tx_id)
+                    .bind(row.client_id)
+                    .bind(row.tx_type)
+                    .bind(row.amount)
+                    .bind(row.client_sequence)
+                    .bind(row.dispute_state)
+                    .bind(row.to_client)
+                    .bind(row.occurred_at)
+                    .execute(self)
+                    .await?;
+
+                Ok(())
+            }
+
+            async fn update_transaction_row(
+                &self,
+                row: $crate::infrastructure::sql::TransactionRow,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query($crate::infrastructure::sql::UPDATE_TRANSACTION)
+                    .bind(row.tx_id)
+                    .bind(row.client_sequence)
+                    .bind(row.dispute_state)
+                    .execute(self)
+                    .await?;
+
+                Ok(())
+            }
+
+            async fn delete_transaction_row(
+                &self,
+                tx_id: $crate::models::TransactionID,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query("DELETE FROM transactions WHERE tx_id = $1")
+                    .bind(i64::from(tx_id.0))
+                    .execute(self)
+                    .await?;
+
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use impl_sql_backend;
+
+/// A stored row which does not describe a valid client or transaction
+#[derive(Error, Debug)]
+#[error("The stored row is not valid: {0}")]
+pub struct InvalidRow(String);
+
+impl From<InvalidRow> for RepoError {
+    fn from(err: InvalidRow) -> Self {
+        RepoError::InvalidData(err.0)
+    }
+}
+
+impl From<sqlx::Error> for RepoError {
+    fn from(err: sqlx::Error) -> Self {
+        RepoError::BackendError(Box::new(err))
+    }
+}
+
+/// Client repository backed by a SQL database.
+///
+/// Every client is loaded at most once, and then kept in memory, so everyone working on the same
+/// client shares (and locks) the same instance, just like with the in memory repository. Changes
+/// are written to the database when the client is saved.
+pub struct SqlClientRepository<B> {
+    backend: B,
+    loaded_clients: Mutex<HashMap<ClientID, StoredClient>>,
+}
+
+/// Transaction repository backed by a SQL database.
+///
+/// Works like the [`SqlClientRepository`], keeping every loaded transaction in memory.
+pub struct SqlTransactionRepository<B> {
+    backend: B,
+    loaded_transactions: Mutex<HashMap<TransactionID, StoredTX>>,
+}
+
+/// A stored client, as found in the `clients` table
+#[derive(FromRow, Debug, PartialEq, Eq)]
+pub struct ClientRow {
+    pub(crate) client_id: i32,
+    pub(crate) available: i64,
+    pub(crate) held: i64,
+    pub(crate) locked: bool,
+    pub(crate) closed: bool,
+    pub(crate) transaction_count: i64,
+    pub(crate) version: i64,
+    pub(crate) open_disputes: i64,
+    pub(crate) held_for_deposits: i64,
+    pub(crate) held_for_withdrawals: i64,
+    pub(crate) credit_limit: Option<i64>,
+}
+
+/// A stored transaction, as found in the `transactions` table
+#[derive(FromRow, Debug, PartialEq, Eq)]
+pub struct TransactionRow {
+    pub(crate) tx_id: i64,
+    pub(crate) client_id: i32,
+    pub(crate) tx_type: String,
+    pub(crate) amount: i64,
+    pub(crate) client_sequence: Option<i64>,
+    pub(crate) dispute_state: Option<String>,
+    pub(crate) to_client: Option<i32>,
+    pub(crate) occurred_at: Option<i64>,
+}
+
+const INSERT_CLIENT: &str = "INSERT INTO clients (client_id, available, held, locked, \
+    transaction_count, version, open_disputes, held_for_deposits, held_for_withdrawals, closed, \
+    credit_limit) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)";
+
+// Never overwrite a newer version of the client, written by someone else
+const UPDATE_CLIENT: &str = "UPDATE clients SET available = $2, held = $3, locked = $4, \
+    transaction_count = $5, version = $6, open_disputes = $7, held_for_deposits = $8, \
+    held_for_withdrawals = $9, closed = $10, credit_limit = $11 \
+    WHERE client_id = $1 AND version < $6";
+
+const UPDATE_CLIENT_IF_VERSION: &str = "UPDATE clients SET available = $2, held = $3, \
+    locked = $4, transaction_count = $5, version = $6, open_disputes = $7, \
+    held_for_deposits = $8, held_for_withdrawals = $9, closed = $10, credit_limit = $11 \
+    WHERE client_id = $1 AND version = $12";
+
+// Transactions reusing the id of a stored one are only stored if the duplicates are processed,
+// in which case they replace the stored one
+pub(crate) const UPSERT_TRANSACTION: &str = "INSERT INTO transactions (tx_id, client_id, \
+    tx_type, amount, client_sequence, dispute_state, to_client, occurred_at) \
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+    ON CONFLICT (tx_id) DO UPDATE SET client_id = $2, tx_type = $3, amount = $4, \
+    client_sequence = $5, dispute_state = $6, to_client = $7, occurred_at = $8";
+
+pub(crate) const SELECT_TRANSACTIONS_IN_RANGE: &str = "SELECT * FROM transactions \
+    WHERE occurred_at >= $1 AND occurred_at < $2 ORDER BY occurred_at, tx_id";
+
+pub(crate) const SELECT_CLIENT_TRANSACTIONS: &str = "SELECT * FROM transactions \
+    WHERE client_id = $1 ORDER BY client_sequence, tx_id";
+
+// Without timestamps, the most recent transactions are the ones with the largest ids
+pub(crate) const SELECT_HOTTEST_CLIENTS: &str = "SELECT * FROM clients ORDER BY \
+    (SELECT COALESCE(MAX(occurred_at), 0) FROM transactions \
+    WHERE transactions.client_id = clients.client_id) DESC, \
+    (SELECT COALESCE(MAX(tx_id), 0) FROM transactions \
+    WHERE transactions.client_id = clients.client_id) DESC, client_id LIMIT $1";
+
+pub(crate) const SELECT_RECENT_DISPUTABLE_TRANSACTIONS: &str = "SELECT * FROM transactions \
+    WHERE tx_type IN ('deposit', 'withdrawal') \
+    AND (dispute_state IS NULL OR dispute_state LIKE 'disputed%') \
+    ORDER BY COALESCE(occurred_at, 0) DESC, tx_id DESC LIMIT $1";
+
+pub(crate) const UPDATE_TRANSACTION: &str =
+    "UPDATE transactions SET client_sequence = $2, dispute_state = $3 WHERE tx_id = $1";
+
+impl From<&Client> for ClientRow {
+    fn from(client: &Client) -> Self {
+        Self {
+            client_id: client.client_id().0.into(),
+            available: client.available().into(),
+            held: client.held().into(),
+            locked: *client.account_status() == ClientAccountStatus::Frozen,
+            closed: *client.account_status() == ClientAccountStatus::Closed,
+            transaction_count: client.transaction_count() as i64,
+            version: client.version() as i64,
+            open_disputes: client.disputes().open_disputes(),
+            held_for_deposits: client.disputes().held_for_deposits().into(),
+            held_for_withdrawals: client.disputes().held_for_withdrawals().into(),
+            credit_limit: client.credit_limit().map(Into::into),
+        }
+    }
+}
+
+impl TryFrom<ClientRow> for Client {
+    type Error = InvalidRow;
+
+    fn try_from(row: ClientRow) -> Result<Self, Self::Error> {
+        let client_id = u16::try_from(row.client_id)
+            .map(ClientID)
+            .map_err(|_| InvalidRow(format!("client id {}", row.client_id)))?;
+
+        let mut client = Client::builder()
+            .with_client_id(client_id)
+            .with_available(MoneyType::new(row.available))
+            .with_held(MoneyType::new(row.held))
+            .with_account_status(match (row.closed, row.locked) {
+                (true, _) => ClientAccountStatus::Closed,
+                (false, true) => ClientAccountStatus::Frozen,
+                (false, false) => ClientAccountStatus::Active,
+            })
+            .with_transaction_count(row.transaction_count as u64)
+            .with_version(row.version as u64)
+            .with_disputes(DisputeLedger::new(
+                row.open_disputes,
+                MoneyType::new(row.held_for_deposits),
+                MoneyType::new(row.held_for_withdrawals),
+            ))
+            .build();
+
+        client.set_credit_limit(row.credit_limit.map(MoneyType::new));
+
+        Ok(client)
+    }
+}
+
+/// The suffix of the dispute state of the disputes which provisionally credited the client
+const CREDITED_SUFFIX: &str = "_credited";
+
+/// How each stage of a dispute is kept in the dispute state column, along with how the funds
+/// were moved when it was opened
+fn dispute_state_name(dispute: &DisputeState) -> Option<String> {
+    let name = match dispute.stage() {
+        DisputeStage::NotDisputed => return None,
+        DisputeStage::Open => "disputed",
+        DisputeStage::Resolved => "resolved",
+        DisputeStage::ChargedBack => "chargeback",
+        DisputeStage::Represented => "represented",
+    };
+
+    match dispute.mode() {
+        Some(DisputeMode::ProvisionalCredit) => Some(format!("{}{}", name, CREDITED_SUFFIX)),
+        _ => Some(name.to_string()),
+    }
+}
+
+impl TryFrom<&Transaction> for TransactionRow {
+    type Error = RepoError;
+
+    /// Only deposits, withdrawals, authorizations and transfers are ever stored, as disputes,
+    /// their settlements and captures are stored as the state of the transaction they target,
+    /// so any other transaction is refused.
+    ///
+    /// Authorizations can't be disputed, so the dispute state column keeps whether they were
+    /// captured or expired instead.
+    fn try_from(transaction: &Transaction) -> Result<Self, Self::Error> {
+        let (tx_type, amount, dispute_state) = match transaction.tx_type() {
+            TransactionType::Deposit { amount, dispute } => {
+                ("deposit", *amount, dispute_state_name(dispute))
+            }
+            TransactionType::Withdrawal { amount, dispute } => {
+                ("withdrawal", *amount, dispute_state_name(dispute))
+            }
+            TransactionType::Authorize { amount, state } => {
+                let state = match state {
+                    AuthorizationState::Pending => None,
+                    AuthorizationState::Captured => Some("captured".to_string()),
+                    AuthorizationState::Expired => Some("expired".to_string()),
+                };
+
+                ("authorize", *amount, state)
+            }
+            TransactionType::Transfer { amount, .. } => ("transfer", *amount, None),
+            other => {
+                return Err(RepoError::InvalidData(format!(
+                    "a {} can't be stored as tx {}",
+                    other.name(),
+                    transaction.transaction_id()
+                )))
+            }
+        };
+
+        Ok(Self {
+            tx_id: transaction.transaction_id().0.into(),
+            client_id: transaction.client().0.into(),
+            tx_type: tx_type.to_string(),
+            amount: amount.into(),
+            client_sequence: transaction.client_sequence().map(|seq| seq as i64),
+            dispute_state,
+            to_client: transaction
+                .destination()
+                .map(|to_client| to_client.0.into()),
+            occurred_at: transaction.timestamp().map(|timestamp| timestamp as i64),
+        })
+    }
+}
+
+impl TryFrom<TransactionRow> for Transaction {
+    type Error = InvalidRow;
+
+    fn try_from(row: TransactionRow) -> Result<Self, Self::Error> {
+        let invalid = |what: &str| InvalidRow(format!("{} of tx {}", what, row.tx_id));
+
+        let tx_id = u32::try_from(row.tx_id)
+            .map(TransactionID)
+            .map_err(|_| invalid("id"))?;
+        let client_id = u16::try_from(row.client_id)
+            .map(ClientID)
+            .map_err(|_| invalid("client id"))?;
+
+        let tx_type = match row.tx_type.as_str() {
+            "deposit" => TransactionType::Deposit {
+                amount: MoneyType::new(row.amount),
+                dispute: DisputeState::NotDisputed,
+            },
+            "withdrawal" => TransactionType::Withdrawal {
+                amount: MoneyType::new(row.amount),
+                dispute: DisputeState::NotDisputed,
+            },
+            "authorize" => TransactionType::Authorize {
+                amount: MoneyType::new(row.amount),
+                state: AuthorizationState::Pending,
+            },
+            "transfer" => TransactionType::Transfer {
+                to_client: row
+                    .to_client
+                    .and_then(|to_client| u16::try_from(to_client).map(ClientID).ok())
+                    .ok_or_else(|| invalid("destination"))?,
+                amount: MoneyType::new(row.amount),
+            },
+            _ => return Err(invalid("type")),
+        };
+
+        let mut transaction = Transaction::builder()
+            .with_tx_id(tx_id)
+            .with_client_id(client_id)
+            .with_tx_type(tx_type)
+            .build();
+
+        if let Some(client_sequence) = row.client_sequence {
+            transaction.assign_client_sequence(client_sequence as u64);
+        }
+
+        if let Some(occurred_at) = row.occurred_at {
+            transaction.assign_timestamp(occurred_at as Timestamp);
+        }
+
+        // Replay the dispute through the model, so it goes through the same checks
+        let related = |tx_type| {
+            Transaction::builder()
+                .with_tx_id(tx_id)
+                .with_client_id(client_id)
+                .with_tx_type(tx_type)
+                .build()
+        };
+
+        if let TransactionType::Authorize { .. } = transaction.tx_type() {
+            match row.dispute_state.as_deref() {
+                None => {}
+                Some("captured") => transaction
+                    .capture(related(TransactionType::Capture))
+                    .map_err(|_| invalid("capture"))?,
+                Some("expired") => transaction.expire().map_err(|_| invalid("expiry"))?,
+                Some(_) => return Err(invalid("authorization state")),
+            }
+
+            return Ok(transaction);
+        }
+
+        let Some(dispute_state) = row.dispute_state.as_deref() else {
+            return Ok(transaction);
+        };
+
+        let (dispute_state, mode) = match dispute_state.strip_suffix(CREDITED_SUFFIX) {
+            Some(dispute_state) => (dispute_state, DisputeMode::ProvisionalCredit),
+            None => (dispute_state, DisputeMode::Hold),
+        };
+
+        let (settlement, represented) = match dispute_state {
+            "disputed" => (None, false),
+            "resolved" => (Some(TransactionType::Resolve), false),
+            "chargeback" => (Some(TransactionType::Chargeback), false),
+            "represented" => (Some(TransactionType::Chargeback), true),
+            _ => return Err(invalid("dispute state")),
+        };
+
+        transaction
+            .dispute_with_mode(related(TransactionType::Dispute), mode)
+            .map_err(|_| invalid("dispute"))?;
+
+        if let Some(settlement) = settlement {
+            transaction
+                .settle_dispute(related(settlement))
+                .map_err(|_| invalid("dispute settlement"))?;
+        }
+
+        if represented {
+            transaction
+                .represent(related(TransactionType::Representment))
+                .map_err(|_| invalid("representment"))?;
+        }
+
+        Ok(transaction)
+    }
+}
+
+impl<B> SqlClientRepository<B>
+where
+    B: TSqlBackend,
+{
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            loaded_clients: Default::default(),
+        }
+    }
+
+    /// Find the client, loading it from the database if it isn't loaded yet
+    async fn load(
+        &self,
+        loaded_clients: &mut HashMap<ClientID, StoredClient>,
+        client_id: ClientID,
+    ) -> Result<Option<StoredClient>, RepoError> {
+        if let Some(client) = loaded_clients.get(&client_id) {
+            return Ok(Some(client.clone()));
+        }
+
+        let Some(row) = self.backend.find_client_row(client_id).await? else {
+            return Ok(None);
+        };
+
+        let stored_client = Arc::new(Mutex::new(Client::try_from(row)?));
+
+        loaded_clients.insert(client_id, stored_client.clone());
+
+        Ok(Some(stored_client))
+    }
+}
+
+impl<B> TClientRepository for SqlClientRepository<B>
+where
+    B: TSqlBackend,
+{
+    async fn find_all_clients(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        let rows = self.backend.find_all_client_rows().await?;
+
+        let mut loaded_clients = self.loaded_clients.lock().await;
+
+        let stored_clients = rows
+            .into_iter()
+            .map(|row| {
+                let client = Client::try_from(row)?;
+
+                // The loaded instance might have changes which are yet to be saved
+                Ok(loaded_clients
+                    .entry(client.client_id())
+                    .or_insert_with(|| Arc::new(Mutex::new(client)))
+                    .clone())
+            })
+            .collect::<Result<Vec<StoredClient>, RepoError>>()?;
+
+        Ok(stream::iter(stored_clients).boxed())
+    }
+
+    async fn find_all_clients_sorted(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        // The rows are already ordered by the query
+        self.find_all_clients().await
+    }
+
+    async fn find_client_by_id(
+        &self,
+        client_id: ClientID,
+    ) -> Result<Option<StoredClient>, RepoError> {
+        let mut loaded_clients = self.loaded_clients.lock().await;
+
+        self.load(&mut loaded_clients, client_id).await
+    }
+
+    async fn save_client(&self, client: StoredClient) -> Result<(), RepoError> {
+        let row = ClientRow::from(&*client.lock().await);
+
+        let saved = self
+            .backend
+            .execute_client_row(&row, UPDATE_CLIENT, None)
+            .await?;
+
+        if saved == 0 {
+            // Someone else has written a newer version of this client, so ours is outdated.
+            // Drop it, so the next time it is used it's loaded again.
+            tracing::warn!(
+                client = row.client_id,
+                version = row.version,
+                "The client was changed by someone else, discarding our version"
+            );
+
+            let client_id = u16::try_from(row.client_id)
+                .map(ClientID)
+                .expect("Loaded from a ClientID");
+
+            self.loaded_clients.lock().await.remove(&client_id);
+        }
+
+        Ok(())
+    }
+
+    async fn store_client(&self, client: Client) -> Result<StoredClient, RepoError> {
+        let cli_id = client.client_id();
+
+        self.backend
+            .execute_client_row(&ClientRow::from(&client), INSERT_CLIENT, None)
+            .await?;
+
+        let stored_client = Arc::new(Mutex::new(client));
+
+        self.loaded_clients
+            .lock()
+            .await
+            .insert(cli_id, stored_client.clone());
+
+        Ok(stored_client)
+    }
+
+    async fn save_client_if_version(
+        &self,
+        mut client: Client,
+        expected_version: u64,
+    ) -> Result<StoredClient, RepoError> {
+        let cli_id = client.client_id();
+
+        let mut loaded_clients = self.loaded_clients.lock().await;
+
+        let conflict = |current| ClientVersionConflict {
+            client: cli_id,
+            expected: expected_version,
+            current,
+        };
+
+        let Some(stored_client) = self.load(&mut loaded_clients, cli_id).await? else {
+            if expected_version != 0 {
+                return Err(conflict(0).into());
+            }
+
+            client.succeed(expected_version);
+
+            self.backend
+                .execute_client_row(&ClientRow::from(&client), INSERT_CLIENT, None)
+                .await?;
+
+            let stored_client = Arc::new(Mutex::new(client));
+
+            loaded_clients.insert(cli_id, stored_client.clone());
+
+            return Ok(stored_client);
+        };
+
+        let mut current = stored_client.lock().await;
+
+        if current.version() != expected_version {
+            return Err(conflict(current.version()).into());
+        }
+
+        client.succeed(expected_version);
+
+        let saved = self
+            .backend
+            .execute_client_row(
+                &ClientRow::from(&client),
+                UPDATE_CLIENT_IF_VERSION,
+                Some(expected_version),
+            )
+            .await?;
+
+        if saved == 0 {
+            // Someone else has written to the database since we loaded the client
+            let stored_version = self.backend.find_client_version(cli_id).await?;
+
+            drop(current);
+
+            loaded_clients.remove(&cli_id);
+
+            return Err(conflict(stored_version as u64).into());
+        }
+
+        *current = client;
+
+        drop(current);
+
+        Ok(stored_client)
+    }
+}
+
+impl<B> SqlTransactionRepository<B>
+where
+    B: TSqlBackend,
+{
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            loaded_transactions: Default::default(),
+        }
+    }
+
+    /// The transactions of the rows, reusing the ones which are already loaded
+    async fn share_rows(&self, rows: Vec<TransactionRow>) -> Result<Vec<StoredTX>, RepoError> {
+        let mut loaded_transactions = self.loaded_transactions.lock().await;
+
+        let mut stored = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            let tx_id = u32::try_from(row.tx_id)
+                .map(TransactionID)
+                .map_err(|_| InvalidRow(format!("id of tx {}", row.tx_id)))?;
+
+            // The loaded transactions might have changes which were not saved yet
+            let stored_tx = match loaded_transactions.get(&tx_id) {
+                Some(stored_tx) => stored_tx.clone(),
+                None => {
+                    let stored_tx = Arc::new(Mutex::new(Transaction::try_from(row)?));
+
+                    loaded_transactions.insert(tx_id, stored_tx.clone());
+
+                    stored_tx
+                }
+            };
+
+            stored.push(stored_tx);
+        }
+
+        Ok(stored)
+    }
+}
+
+impl<B> TTransactionRepository for SqlTransactionRepository<B>
+where
+    B: TSqlBackend,
+{
+    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Result<Option<StoredTX>, RepoError> {
+        let mut loaded_transactions = self.loaded_transactions.lock().await;
+
+        if let Some(tx) = loaded_transactions.get(&tx_id) {
+            return Ok(Some(tx.clone()));
+        }
+
+        let Some(row) = self.backend.find_transaction_row(tx_id).await? else {
+            return Ok(None);
+        };
+
+        let stored_tx = Arc::new(Mutex::new(Transaction::try_from(row)?));
+
+        loaded_transactions.insert(tx_id, stored_tx.clone());
+
+        Ok(Some(stored_tx))
+    }
+
+    async fn save_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        let row = TransactionRow::try_from(&*tx.lock().await)?;
+
+        self.backend.update_transaction_row(row).await?;
+
+        Ok(())
+    }
+
+    async fn store_tx(&self, tx: Transaction) -> Result<StoredTX, RepoError> {
+        let tx_id = tx.transaction_id();
+
+        self.backend
+            .upsert_transaction_row(TransactionRow::try_from(&tx)?)
+            .await?;
+
+        let stored_tx = Arc::new(Mutex::new(tx));
+
+        self.loaded_transactions
+            .lock()
+            .await
+            .insert(tx_id, stored_tx.clone());
+
+        Ok(stored_tx)
+    }
+
+    async fn remove_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        let tx_id = tx.lock().await.transaction_id();
+
+        self.backend.delete_transaction_row(tx_id).await?;
+
+        let mut loaded_transactions = self.loaded_transactions.lock().await;
+
+        if loaded_transactions
+            .get(&tx_id)
+            .is_some_and(|loaded| Arc::ptr_eq(loaded, &tx))
+        {
+            loaded_transactions.remove(&tx_id);
+        }
+
+        Ok(())
+    }
+
+    async fn find_txs_in_range(&self, range: Range<Timestamp>) -> Result<Vec<StoredTX>, RepoError> {
+        let rows = self.backend.find_transaction_rows_in_range(range).await?;
+
+        self.share_rows(rows).await
+    }
+
+    async fn find_txs_by_client(
+        &self,
+        client_id: ClientID,
+    ) -> Result<BoxStream<'static, StoredTX>, RepoError> {
+        let rows = self
+            .backend
+            .find_transaction_rows_by_client(client_id)
+            .await?;
+
+        Ok(stream::iter(self.share_rows(rows).await?).boxed())
+    }
+}
+
+/// The source of the warm-up of the repositories stored in a SQL database, reading the clients
+/// and transactions most likely to be needed right after starting
+pub struct SqlWarmUpSource<B> {
+    backend: B,
+}
+
+impl<B> SqlWarmUpSource<B>
+where
+    B: TSqlBackend,
+{
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+}
+
+impl<B> TWarmUpSource for SqlWarmUpSource<B>
+where
+    B: TSqlBackend,
+{
+    /// Nothing is preloaded when the rows can't be read, they're loaded once needed instead
+    async fn hottest_clients(&self, limit: usize) -> Vec<Client> {
+        let rows = match self.backend.find_hottest_client_rows(limit).await {
+            Ok(rows) => rows,
+            Err(err) => {
+                tracing::warn!(error = %err, "Failed to read the clients to warm up");
+
+                return Vec::new();
+            }
+        };
+
+        rows.into_iter()
+            .filter_map(|row| Client::try_from(row).ok())
+            .collect()
+    }
+
+    async fn recent_disputable_transactions(&self, limit: usize) -> Vec<Transaction> {
+        let rows = match self
+            .backend
+            .find_recent_disputable_transaction_rows(limit)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(err) => {
+                tracing::warn!(error = %err, "Failed to read the transactions to warm up");
+
+                return Vec::new();
+            }
+        };
+
+        rows.into_iter()
+            .filter_map(|row| Transaction::try_from(row).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod sql_tests {
+    use crate::infrastructure::sql::{ClientRow, TransactionRow};
+    use crate::models::client::{Client, ClientAccountStatus};
+    use crate::models::transactions::{
+        AuthorizationState, DisputeMode, DisputeState, Transaction, TransactionType,
+    };
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::RepoError;
+
+    fn related(tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_tx_id(TransactionID(7))
+            .with_client_id(ClientID(3))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    #[test]
+    fn test_transaction_rows() {
+        let mut transaction = related(TransactionType::Withdrawal {
+            amount: MoneyType::new(1234),
+            dispute: DisputeState::NotDisputed,
+        });
+
+        transaction.assign_client_sequence(2);
+
+        let row = TransactionRow::try_from(&transaction).unwrap();
+
+        assert_eq!(row.tx_type, "withdrawal");
+        assert_eq!(row.dispute_state, None);
+        assert_eq!(Transaction::try_from(row).unwrap(), transaction);
+
+        transaction
+            .dispute(related(TransactionType::Dispute))
+            .unwrap();
+
+        let row = TransactionRow::try_from(&transaction).unwrap();
+
+        assert_eq!(row.dispute_state.as_deref(), Some("disputed"));
+        assert_eq!(Transaction::try_from(row).unwrap(), transaction);
+
+        transaction
+            .settle_dispute(related(TransactionType::Chargeback))
+            .unwrap();
+
+        let row = TransactionRow::try_from(&transaction).unwrap();
+
+        assert_eq!(row.dispute_state.as_deref(), Some("chargeback"));
+        assert_eq!(Transaction::try_from(row).unwrap(), transaction);
+
+        transaction
+            .represent(related(TransactionType::Representment))
+            .unwrap();
+
+        let row = TransactionRow::try_from(&transaction).unwrap();
+
+        assert_eq!(row.dispute_state.as_deref(), Some("represented"));
+        assert_eq!(Transaction::try_from(row).unwrap(), transaction);
+
+        // The provisional credit is kept, so the dispute is settled the same way once loaded
+        let mut credited = related(TransactionType::Withdrawal {
+            amount: MoneyType::new(1234),
+            dispute: DisputeState::NotDisputed,
+        });
+
+        credited
+            .dispute_with_mode(
+                related(TransactionType::Dispute),
+                DisputeMode::ProvisionalCredit,
+            )
+            .unwrap();
+        credited
+            .settle_dispute(related(TransactionType::Resolve))
+            .unwrap();
+
+        let row = TransactionRow::try_from(&credited).unwrap();
+
+        assert_eq!(row.dispute_state.as_deref(), Some("resolved_credited"));
+        assert_eq!(Transaction::try_from(row).unwrap(), credited);
+
+        let mut authorization = related(TransactionType::Authorize {
+            amount: MoneyType::new(300),
+            state: AuthorizationState::Pending,
+        });
+
+        let row = TransactionRow::try_from(&authorization).unwrap();
+
+        assert_eq!(row.tx_type, "authorize");
+        assert_eq!(row.dispute_state, None);
+        assert_eq!(Transaction::try_from(row).unwrap(), authorization);
+
+        authorization
+            .capture(related(TransactionType::Capture))
+            .unwrap();
+
+        let row = TransactionRow::try_from(&authorization).unwrap();
+
+        assert_eq!(row.dispute_state.as_deref(), Some("captured"));
+        assert_eq!(Transaction::try_from(row).unwrap(), authorization);
+
+        let mut expired = related(TransactionType::Authorize {
+            amount: MoneyType::new(300),
+            state: AuthorizationState::Pending,
+        });
+
+        expired.expire().unwrap();
+
+        let row = TransactionRow::try_from(&expired).unwrap();
+
+        assert_eq!(row.dispute_state.as_deref(), Some("expired"));
+        assert_eq!(Transaction::try_from(row).unwrap(), expired);
+
+        // Disputes are kept as the state of the transaction they target, never on their own
+        assert!(matches!(
+            TransactionRow::try_from(&related(TransactionType::Dispute)),
+            Err(RepoError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_client_rows() {
+        let mut client = Client::builder().with_client_id(ClientID(3)).build();
+
+        client.deposit(MoneyType::new(500)).unwrap();
+        client.dispute_deposited_funds(MoneyType::new(200)).unwrap();
+        client.set_account_status(ClientAccountStatus::Frozen);
+
+        let row = ClientRow::from(&client);
+        let restored = Client::try_from(row).unwrap();
+
+        assert_eq!(ClientRow::from(&restored), ClientRow::from(&client));
+
+        let overdrawn = Client::builder()
+            .with_client_id(ClientID(4))
+            .with_available(MoneyType::new(-150))
+            .with_credit_limit(MoneyType::new(200))
+            .build();
+
+        let row = ClientRow::from(&overdrawn);
+
+        assert_eq!(row.credit_limit, Some(200));
+        assert_eq!(
+            Client::try_from(row).unwrap().credit_limit(),
+            Some(MoneyType::new(200))
+        );
+    }
+}
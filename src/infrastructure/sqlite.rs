@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use sqlx::migrate::MigrateError;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use thiserror::Error;
+
+use crate::infrastructure::sql::{impl_sql_backend, SqlClientRepository, SqlTransactionRepository};
+use crate::repositories::RepoError;
+
+/// Open the database in the given file, creating it if it doesn't exist yet, and bring its
+/// schema up to date
+pub async fn connect(path: impl AsRef<Path>) -> Result<SqlitePool, SqliteError> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
+
+    // SQLite only takes one writer at a time anyway
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+
+    Ok(pool)
+}
+
+#[derive(Error, Debug)]
+pub enum SqliteError {
+    #[error("Database error {0:?}")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("Failed to migrate the database {0:?}")]
+    MigrationError(#[from] MigrateError),
+}
+
+impl From<SqliteError> for RepoError {
+    fn from(err: SqliteError) -> Self {
+        RepoError::BackendError(Box::new(err))
+    }
+}
+
+/// Client repository stored in a single SQLite file, see [`SqlClientRepository`]
+pub type SqliteClientRepository = SqlClientRepository<SqlitePool>;
+
+/// Transaction repository stored in a single SQLite file, see [`SqlTransactionRepository`]
+pub type SqliteTransactionRepository = SqlTransactionRepository<SqlitePool>;
+
+impl_sql_backend!(SqlitePool);
+
+#[cfg(test)]
+mod sqlite_tests {
+    use futures::StreamExt;
+
+    use crate::infrastructure::sql::SqlWarmUpSource;
+    use crate::infrastructure::sqlite::{
+        connect, SqliteClientRepository, SqliteTransactionRepository,
+    };
+    use crate::models::transactions::{DisputeStage, DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::transactions::TTransactionRepository;
+    use crate::repositories::warm_up::TWarmUpSource;
+    use crate::services::transaction_service::{TTransactionService, TransactionService};
+
+    fn transaction(tx_id: u32, tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(tx_id))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_repositories() {
+        let path = std::env::temp_dir().join(format!("transactioner-{}.db", std::process::id()));
+
+        {
+            let pool = connect(&path).await.unwrap();
+
+            let service = TransactionService::new(
+                SqliteClientRepository::new(pool.clone()),
+                SqliteTransactionRepository::new(pool),
+            );
+
+            for tx in [
+                transaction(
+                    1,
+                    TransactionType::Deposit {
+                        amount: MoneyType::new(50000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                transaction(
+                    2,
+                    TransactionType::Withdrawal {
+                        amount: MoneyType::new(10000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                transaction(1, TransactionType::Dispute),
+            ] {
+                service.process_transaction(tx).await.unwrap();
+            }
+        }
+
+        // Everything is found again once the file is reopened
+        let pool = connect(&path).await.unwrap();
+
+        let clients = SqliteClientRepository::new(pool.clone())
+            .find_all_clients()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(clients.len(), 1);
+
+        let client = clients[0].lock().await;
+
+        assert_eq!(client.available(), MoneyType::new(-10000));
+        assert_eq!(client.held(), MoneyType::new(50000));
+
+        let stored_tx = SqliteTransactionRepository::new(pool.clone())
+            .find_tx_by_id(TransactionID(1))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            stored_tx.lock().await.dispute_state().stage(),
+            DisputeStage::Open
+        );
+
+        drop(client);
+
+        // The disputed deposit and the withdrawal can both still be needed by a dispute
+        let source = SqlWarmUpSource::new(pool);
+
+        assert_eq!(source.hottest_clients(5).await.len(), 1);
+        assert_eq!(
+            source
+                .recent_disputable_transactions(5)
+                .await
+                .iter()
+                .map(|tx| tx.transaction_id())
+                .collect::<Vec<_>>(),
+            vec![TransactionID(2), TransactionID(1)]
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+
+use crate::models::client::Client;
+use crate::models::ClientID;
+use crate::repositories::clients::{StoredClient, TClientRepository};
+use crate::repositories::RepoError;
+use crate::services::backpressure::PersistenceBacklog;
+
+/// How long to wait before retrying a write the durable storage refused
+const FLUSH_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Client repository which doesn't wait for the changes to the clients to be written to the
+/// repository behind it, writing them in the background instead.
+///
+/// Since the clients are changed in place, the processing carries on with the in memory state
+/// while the writes are pending. They are tracked in the given [`PersistenceBacklog`], so the
+/// ingestion can be paused when the storage falls too far behind. Failed writes are retried
+/// until they succeed, so an unavailable storage keeps the backlog growing.
+pub struct WriteBehindClientRepository<CR> {
+    repo: Arc<CR>,
+    pending: flume::Sender<StoredClient>,
+    backlog: Arc<PersistenceBacklog>,
+}
+
+impl<CR> WriteBehindClientRepository<CR>
+where
+    CR: TClientRepository + 'static,
+{
+    /// Start writing the changes to the given repository in the background
+    pub fn new(repo: CR, backlog: Arc<PersistenceBacklog>) -> Self {
+        let repo = Arc::new(repo);
+
+        let (pending, to_flush) = flume::unbounded();
+
+        tokio::spawn(flush(repo.clone(), to_flush, backlog.clone()));
+
+        Self {
+            repo,
+            pending,
+            backlog,
+        }
+    }
+
+    pub fn backlog(&self) -> &Arc<PersistenceBacklog> {
+        &self.backlog
+    }
+}
+
+/// Write every pending change, in the order they were made
+async fn flush<CR>(
+    repo: Arc<CR>,
+    to_flush: flume::Receiver<StoredClient>,
+    backlog: Arc<PersistenceBacklog>,
+) where
+    CR: TClientRepository,
+{
+    while let Ok(client) = to_flush.recv_async().await {
+        while let Err(err) = repo.save_client(client.clone()).await {
+            backlog.write_failed();
+
+            let client_id = client.lock().await.client_id();
+
+            tracing::warn!(
+                client = %client_id,
+                error = %err,
+                "Failed to write a client, retrying"
+            );
+
+            tokio::time::sleep(FLUSH_RETRY_DELAY).await;
+        }
+
+        backlog.flushed();
+    }
+}
+
+impl<CR> TClientRepository for WriteBehindClientRepository<CR>
+where
+    CR: TClientRepository,
+{
+    async fn find_all_clients(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        self.repo.find_all_clients().await
+    }
+
+    async fn find_all_clients_sorted(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        self.repo.find_all_clients_sorted().await
+    }
+
+    async fn find_client_by_id(
+        &self,
+        client_id: ClientID,
+    ) -> Result<Option<StoredClient>, RepoError> {
+        self.repo.find_client_by_id(client_id).await
+    }
+
+    async fn save_client(&self, client: StoredClient) -> Result<(), RepoError> {
+        self.backlog.enqueued();
+
+        if self.pending.send(client).is_err() {
+            // The flushing task is gone, so this change will never be written
+            self.backlog.flushed();
+
+            return Err(RepoError::BackendError(
+                "The write-behind flushing task has stopped".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn store_client(&self, client: Client) -> Result<StoredClient, RepoError> {
+        self.repo.store_client(client).await
+    }
+
+    async fn save_client_if_version(
+        &self,
+        client: Client,
+        expected_version: u64,
+    ) -> Result<StoredClient, RepoError> {
+        // Checking the version needs the durable state, so this one can't be deferred
+        self.repo
+            .save_client_if_version(client, expected_version)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod write_behind_tests {
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::lock::Mutex;
+
+    use crate::infrastructure::write_behind::WriteBehindClientRepository;
+    use crate::models::client::Client;
+    use crate::models::ClientID;
+    use crate::repositories::clients::{MockTClientRepository, TClientRepository};
+    use crate::repositories::RepoError;
+    use crate::services::backpressure::{PersistenceBacklog, PersistenceHealth};
+
+    #[tokio::test]
+    async fn test_backlog_while_storage_unavailable() {
+        let available = Arc::new(AtomicBool::new(false));
+        let written = Arc::new(AtomicU64::new(0));
+
+        let mut durable = MockTClientRepository::new();
+
+        {
+            let (available, written) = (available.clone(), written.clone());
+
+            durable.expect_save_client().returning(move |_| {
+                let result = if available.load(Ordering::SeqCst) {
+                    written.fetch_add(1, Ordering::SeqCst);
+
+                    Ok(())
+                } else {
+                    Err(RepoError::InvalidData("unavailable".into()))
+                };
+
+                Box::pin(async move { result })
+            });
+        }
+
+        let backlog = Arc::new(PersistenceBacklog::new(2, 0));
+
+        let repo = WriteBehindClientRepository::new(durable, backlog.clone());
+
+        let client = Arc::new(Mutex::new(
+            Client::builder().with_client_id(ClientID(1)).build(),
+        ));
+
+        for _ in 0..3 {
+            repo.save_client(client.clone()).await.unwrap();
+        }
+
+        assert_eq!(backlog.backlog(), 3);
+        assert_eq!(backlog.health(), PersistenceHealth::Lagging { backlog: 2 });
+
+        // The flushing task keeps retrying the first change
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(backlog.is_failing());
+
+        available.store(true, Ordering::SeqCst);
+
+        backlog.wait_until_drained().await;
+
+        assert_eq!(written.load(Ordering::SeqCst), 3);
+        assert_eq!(backlog.health(), PersistenceHealth::Healthy);
+        assert!(!backlog.is_failing());
+    }
+}
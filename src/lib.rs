@@ -0,0 +1,78 @@
+//! A transaction processing engine for client accounts.
+//!
+//! Transactions are received from a [`TTransactionStreamProvider`] (a CSV file, JSON Lines,
+//! Kafka, HTTP, etc.), applied by the [`TransactionService`] to the clients stored in a
+//! [`TClientRepository`], and the resulting state of the clients is exported through a
+//! [`TClientStateExporter`].
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use transactioner::{
+//!     CSVTransactionProvider, ClientInMemRepository, CsvStateExporter, ShareableClientRepository,
+//!     TClientRepository, TClientStateExporter, TTransactionService, TTransactionStreamProvider,
+//!     TransactionInMemRepository, TransactionService,
+//! };
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+//!
+//! let service = TransactionService::new(clients.clone(), TransactionInMemRepository::default());
+//!
+//! let provider = CSVTransactionProvider::open("transactions.csv").await?;
+//!
+//! let mut transactions = provider.subscribe_to_tx_stream().await;
+//!
+//! while let Some(transaction) = transactions.next().await {
+//!     if let Err(err) = service.process_transaction(transaction).await {
+//!         eprintln!("{}", err);
+//!     }
+//! }
+//!
+//! CsvStateExporter::stdout()
+//!     .export_state(clients.find_all_clients().await?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "http")]
+pub mod admin;
+pub mod analytics;
+pub mod audit;
+pub mod dead_letters;
+pub mod events;
+pub mod fees;
+pub mod infrastructure;
+pub mod limits;
+pub mod models;
+pub mod notifications;
+pub mod rejections;
+pub mod report;
+pub mod repositories;
+pub mod screening;
+pub mod secrets;
+pub mod services;
+pub mod state_exporter;
+pub mod tx_reception;
+pub mod wal;
+pub mod warnings;
+
+/// The amount of decimal places the amounts are stored with
+pub const FLOATING_POINT_ACC: i32 = 4;
+
+pub use infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+pub use models::client::Client;
+pub use models::transactions::{DisputeState, Transaction, TransactionType};
+pub use models::{ClientID, MoneyType, TransactionID};
+pub use rejections::{RejectionCode, TRejectionReason};
+pub use repositories::clients::{StoredClient, TClientRepository};
+pub use repositories::shareable::{ShareableClientRepository, ShareableTransactionRepository};
+pub use repositories::transactions::{StoredTX, TTransactionRepository};
+pub use repositories::RepoError;
+pub use services::transaction_service::{
+    Processed, TTransactionService, TransactionProcessingError, TransactionService,
+};
+pub use state_exporter::{CsvStateExporter, TClientStateExporter};
+pub use tx_reception::{
+    CSVTransactionProvider, TAcknowledgedStreamProvider, TTransactionStreamProvider,
+};
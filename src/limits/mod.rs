@@ -0,0 +1,252 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::models::transactions::{Transaction, TransactionType};
+use crate::models::{ClientID, MoneyType, Timestamp};
+
+/// A cap on how much a client can do within a rolling window of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VelocityLimit {
+    /// At most `max` withdrawn within any `window`. Outgoing transfers and authorizations
+    /// count as withdrawals, as they take funds out of the account just the same.
+    WithdrawnAmount { max: MoneyType, window: Duration },
+    /// At most `max` transactions moving funds (deposits, withdrawals, authorizations and
+    /// outgoing transfers) within any `window`
+    TransactionCount { max: u64, window: Duration },
+}
+
+impl VelocityLimit {
+    pub fn window(&self) -> Duration {
+        match self {
+            VelocityLimit::WithdrawnAmount { window, .. }
+            | VelocityLimit::TransactionCount { window, .. } => *window,
+        }
+    }
+
+    /// Check whether the given movement, along with the ones of the client inside of the
+    /// window ending with it, stays within the limit
+    fn check<'a>(
+        &self,
+        history: impl Iterator<Item = &'a Movement>,
+        movement: &Movement,
+    ) -> Result<(), VelocityLimitExceeded> {
+        let window_start = movement.at.saturating_sub(self.window().as_secs());
+
+        let mut in_window = history.filter(|previous| previous.at > window_start);
+
+        match *self {
+            VelocityLimit::WithdrawnAmount { max, window } => {
+                let withdrawn = in_window.try_fold(movement.withdrawn, |withdrawn, previous| {
+                    withdrawn.checked_add(previous.withdrawn)
+                });
+
+                match withdrawn {
+                    Some(withdrawn) if withdrawn <= max => Ok(()),
+                    withdrawn => Err(VelocityLimitExceeded::WithdrawnAmount {
+                        withdrawn: withdrawn.unwrap_or(MoneyType::MAX),
+                        max,
+                        window,
+                    }),
+                }
+            }
+            VelocityLimit::TransactionCount { max, window } => {
+                let count = in_window.count() as u64 + 1;
+
+                if count <= max {
+                    Ok(())
+                } else {
+                    Err(VelocityLimitExceeded::TransactionCount { count, max, window })
+                }
+            }
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VelocityLimitExceeded {
+    #[error("{withdrawn:?} would be withdrawn within {window:?}, over the limit of {max:?}")]
+    WithdrawnAmount {
+        withdrawn: MoneyType,
+        max: MoneyType,
+        window: Duration,
+    },
+    #[error("{count} transactions would be made within {window:?}, over the limit of {max}")]
+    TransactionCount {
+        count: u64,
+        max: u64,
+        window: Duration,
+    },
+}
+
+/// A transaction of a client, as far as the limits are concerned
+#[derive(Debug, Clone, Copy)]
+struct Movement {
+    at: Timestamp,
+    withdrawn: MoneyType,
+}
+
+impl Movement {
+    /// Only the transactions moving funds with a timestamp are limited
+    fn of(transaction: &Transaction) -> Option<Self> {
+        let withdrawn = match transaction.tx_type() {
+            TransactionType::Deposit { .. } => MoneyType::ZERO,
+            TransactionType::Withdrawal { amount, .. }
+            | TransactionType::Authorize { amount, .. }
+            | TransactionType::Transfer { amount, .. } => *amount,
+            _ => return None,
+        };
+
+        Some(Self {
+            at: transaction.timestamp()?,
+            withdrawn,
+        })
+    }
+}
+
+/// The rolling limits every client is held to, along with the recent transactions of each
+/// client they are checked against.
+///
+/// Transactions are checked before being applied, and only recorded once they are, so the
+/// rejected ones don't count towards the limits. The windows end at the timestamp of the
+/// transaction being checked, not at the current time, so the input is limited the same way
+/// however late it is processed.
+#[derive(Debug, Default)]
+pub struct VelocityLimits {
+    limits: Vec<VelocityLimit>,
+    history: Mutex<HashMap<ClientID, VecDeque<Movement>>>,
+}
+
+impl VelocityLimits {
+    /// Add a limit, on top of the ones already registered
+    pub fn with_limit(mut self, limit: VelocityLimit) -> Self {
+        self.limits.push(limit);
+
+        self
+    }
+
+    pub fn limits(&self) -> &[VelocityLimit] {
+        &self.limits
+    }
+
+    /// Check the transaction against every limit, without recording it
+    pub fn check(&self, transaction: &Transaction) -> Result<(), VelocityLimitExceeded> {
+        let Some(movement) = Movement::of(transaction) else {
+            return Ok(());
+        };
+
+        let history = self.history.lock().unwrap();
+
+        let previous = history.get(&transaction.client());
+
+        self.limits
+            .iter()
+            .try_for_each(|limit| limit.check(previous.into_iter().flatten(), &movement))
+    }
+
+    /// Record an applied transaction, so it counts towards the limits of the next ones
+    pub fn record(&self, transaction: &Transaction) {
+        let Some(movement) = Movement::of(transaction) else {
+            return;
+        };
+
+        // Nothing older than the widest window can count towards any limit anymore
+        let Some(widest) = self.limits.iter().map(VelocityLimit::window).max() else {
+            return;
+        };
+
+        let oldest = movement.at.saturating_sub(widest.as_secs());
+
+        let mut history = self.history.lock().unwrap();
+
+        let movements = history.entry(transaction.client()).or_default();
+
+        movements.retain(|previous| previous.at > oldest);
+        movements.push_back(movement);
+    }
+}
+
+#[cfg(test)]
+mod limit_tests {
+    use std::time::Duration;
+
+    use crate::limits::{VelocityLimit, VelocityLimitExceeded, VelocityLimits};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+
+    const HOUR: u64 = 60 * 60;
+
+    fn withdrawal(client: u16, amount: i64, at: u64) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(client))
+            .with_tx_id(TransactionID(at as u32))
+            .with_tx_type(TransactionType::Withdrawal {
+                amount: MoneyType::new(amount),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_timestamp(at)
+            .build()
+    }
+
+    #[test]
+    fn test_withdrawn_amount_limit() {
+        let limits = VelocityLimits::default().with_limit(VelocityLimit::WithdrawnAmount {
+            max: MoneyType::new(1_000),
+            window: Duration::from_secs(24 * HOUR),
+        });
+
+        limits.record(&withdrawal(1, 600, HOUR));
+
+        assert!(matches!(
+            limits.check(&withdrawal(1, 500, 2 * HOUR)),
+            Err(VelocityLimitExceeded::WithdrawnAmount { withdrawn, .. })
+                if withdrawn == MoneyType::new(1_100)
+        ));
+
+        // Other clients have limits of their own
+        assert!(limits.check(&withdrawal(2, 500, 2 * HOUR)).is_ok());
+        assert!(limits.check(&withdrawal(1, 400, 2 * HOUR)).is_ok());
+
+        // Checking a transaction doesn't count it
+        assert!(limits.check(&withdrawal(1, 400, 2 * HOUR)).is_ok());
+
+        // Once the first withdrawal leaves the window, the whole limit is available again
+        assert!(limits.check(&withdrawal(1, 1_000, 25 * HOUR)).is_ok());
+
+        // Transactions without a timestamp are never limited
+        let untimed = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(5))
+            .with_tx_type(TransactionType::Withdrawal {
+                amount: MoneyType::new(5_000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .build();
+
+        assert!(limits.check(&untimed).is_ok());
+    }
+
+    #[test]
+    fn test_transaction_count_limit() {
+        let limits = VelocityLimits::default().with_limit(VelocityLimit::TransactionCount {
+            max: 2,
+            window: Duration::from_secs(HOUR),
+        });
+
+        limits.record(&withdrawal(1, 1, 100));
+        limits.record(&withdrawal(1, 1, 200));
+
+        assert_eq!(
+            limits.check(&withdrawal(1, 1, 300)),
+            Err(VelocityLimitExceeded::TransactionCount {
+                count: 3,
+                max: 2,
+                window: Duration::from_secs(HOUR),
+            })
+        );
+
+        assert!(limits.check(&withdrawal(1, 1, HOUR + 150)).is_ok());
+    }
+}
@@ -1,164 +1,2018 @@
-use std::path::PathBuf;
+use std::fs::File;
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
+use clap::Parser;
 use futures::stream::BoxStream;
 use futures::StreamExt;
+use tokio::io::AsyncRead;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
 
-use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
-use crate::models::client::Client;
-use crate::models::transactions::Transaction;
-use crate::models::{ClientID, TransactionID};
-use crate::repositories::clients::{StoredClient, TClientRepository};
-use crate::repositories::transactions::{StoredTX, TTransactionRepository};
-use crate::services::transaction_service::{TTransactionService, TransactionService};
-use crate::state_exporter::TClientStateExporter;
-use crate::tx_reception::{CSVTransactionProvider, TTransactionStreamProvider};
+use transactioner::analytics::ActivityHeatmap;
+#[cfg(feature = "json")]
+use transactioner::dead_letters::JsonRejectedTransactionSink;
+use transactioner::dead_letters::{
+    retry_rejected, CsvRejectedTransactionSink, RejectedTransaction, RejectedTransactionBuffer,
+    TRejectedTransactionSink,
+};
+#[cfg(all(feature = "http", any(feature = "webhooks", feature = "websocket")))]
+use transactioner::events::EventBus;
+use transactioner::fees::{FeeSchedule, FlatFee, PercentageFee};
+#[cfg(all(feature = "http", feature = "sqlite"))]
+use transactioner::infrastructure::sql::SqlWarmUpSource;
+#[cfg(feature = "sqlite")]
+use transactioner::infrastructure::sqlite::{
+    self, SqliteClientRepository, SqliteTransactionRepository,
+};
+#[cfg(all(feature = "http", feature = "sqlite"))]
+use transactioner::infrastructure::write_behind::WriteBehindClientRepository;
+use transactioner::limits::VelocityLimits;
+#[cfg(all(feature = "http", feature = "webhooks"))]
+use transactioner::notifications::webhook::WebhookNotifier;
+#[cfg(feature = "json")]
+use transactioner::report::manifest::{FileDigest, ManifestRecorder};
+use transactioner::report::{RejectionCounter, RunReport, SummaryRecorder};
+#[cfg(all(feature = "http", feature = "sqlite"))]
+use transactioner::repositories::warm_up::TWarmUpSource;
+#[cfg(feature = "screening")]
+use transactioner::screening::remote::RemoteScreeningProvider;
+use transactioner::screening::{BlockedClientList, ScreeningAction, TScreeningProvider};
+#[cfg(feature = "http")]
+use transactioner::services::backpressure::PersistenceBacklog;
+use transactioner::services::decision::DisputabilityWindow;
+use transactioner::services::replay::diff_clients;
+#[cfg(feature = "rules")]
+use transactioner::services::rules::RuleSet;
+use transactioner::services::speculative::SpeculativeBatchProcessor;
+use transactioner::services::transaction_service::HoldSweep;
+#[cfg(feature = "rules")]
+use transactioner::services::validation::TTransactionValidator;
+#[cfg(all(feature = "http", feature = "sqlite"))]
+use transactioner::services::warm_up::warm_up_caches;
+#[cfg(feature = "http")]
+use transactioner::services::warm_up::WarmUpConfig;
+use transactioner::state_exporter::comparison::{compare_states, write_differences};
+#[cfg(feature = "json")]
+use transactioner::state_exporter::json::{JsonLayout, JsonStateExporter};
+#[cfg(feature = "parquet")]
+use transactioner::state_exporter::parquet::{ParquetStateExporter, ParquetTransactionLogExporter};
+#[cfg(feature = "s3")]
+use transactioner::state_exporter::s3::S3StateExporter;
+use transactioner::state_exporter::snapshots::StateSnapshotter;
+use transactioner::state_exporter::statements::StatementExporter;
+use transactioner::state_exporter::ExportSchema;
+#[cfg(feature = "compression")]
+use transactioner::tx_reception::compression::DecompressingReader;
+#[cfg(all(feature = "http", feature = "grpc"))]
+use transactioner::tx_reception::grpc::GrpcTransactionProvider;
+#[cfg(feature = "http")]
+use transactioner::tx_reception::http::HttpTransactionProvider;
+use transactioner::tx_reception::merge::{MergeOrder, MergedTransactionProvider};
+use transactioner::tx_reception::metrics::ReaderMetrics;
+#[cfg(feature = "parquet")]
+use transactioner::tx_reception::parquet::ParquetTransactionProvider;
+#[cfg(feature = "remote-input")]
+use transactioner::tx_reception::remote::RemoteInput;
+#[cfg(feature = "s3")]
+use transactioner::tx_reception::s3::{S3Error, S3Location, S3TransactionProvider};
+#[cfg(feature = "http")]
+use transactioner::tx_reception::{AcknowledgeableTransaction, ProcessingOutcome};
+use transactioner::tx_reception::{CsvErrorMode, CsvReadOptions, RowError};
+use transactioner::wal::{LoggedEntry, WriteAheadLog};
+use transactioner::warnings::{TWarningSink, Warning, WarningCounter, WarningLog};
+#[cfg(feature = "http")]
+use transactioner::{admin, TAcknowledgedStreamProvider};
+use transactioner::{
+    CSVTransactionProvider, ClientID, ClientInMemRepository, CsvStateExporter,
+    ShareableClientRepository, ShareableTransactionRepository, TClientRepository,
+    TClientStateExporter, TRejectionReason, TTransactionRepository, TTransactionService,
+    TTransactionStreamProvider, Transaction, TransactionInMemRepository, TransactionService,
+    FLOATING_POINT_ACC,
+};
 
-mod infrastructure;
-mod models;
-mod repositories;
-mod services;
-mod state_exporter;
-mod tx_reception;
+#[cfg(feature = "config")]
+use crate::cli::config::AppConfig;
+use crate::cli::{
+    Cli, CliError, Command, DeadLetterFormat, ExportFormat, Fee, PolicyArgs, ProcessArgs,
+    ReplayArgs, ServeArgs, SnapshotArgs, StatementArgs, Storage, VerifyArgs,
+};
 
-pub(crate) const FLOATING_POINT_ACC: i32 = 4;
+mod cli;
 
-fn initialize_client_repo() -> impl TClientRepository {
-    ClientInMemRepository::default()
+/// The amount of consecutive client ids, and of consecutive input transactions, grouped
+/// together in the activity heatmap
+const HEATMAP_CLIENT_BUCKET: u16 = 256;
+const HEATMAP_CHUNK: u64 = 10_000;
+
+const SECONDS_PER_HOUR: u64 = 60 * 60;
+const SECONDS_PER_DAY: u64 = 24 * SECONDS_PER_HOUR;
+
+/// How often the progress of the input is shown, and the width of its bar
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const PROGRESS_BAR_WIDTH: u64 = 30;
+
+/// The events reported when `RUST_LOG` is not set
+const DEFAULT_LOG_FILTER: &str = "warn";
+
+/// Report the events of the engine to the standard error, filtered by `RUST_LOG`, so they
+/// never mix with the exported state
+fn initialize_tracing() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+
+    log_subscriber(filter, std::io::stderr, std::io::stderr().is_terminal()).init();
 }
 
-fn initialize_transaction_repo() -> impl TTransactionRepository {
-    TransactionInMemRepository::default()
+/// The subscriber writing the events allowed by the filter, along with their fields
+fn log_subscriber<W>(filter: EnvFilter, writer: W, ansi: bool) -> impl Subscriber + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .finish()
 }
 
 fn initialize_service(
     client_repo: impl TClientRepository,
     transaction_repo: impl TTransactionRepository,
-) -> impl TTransactionService {
-    TransactionService::new(client_repo, transaction_repo)
+    warnings: impl TWarningSink + 'static,
+    write_ahead_log: Option<WriteAheadLog>,
+    screening: Option<Arc<dyn TScreeningProvider>>,
+    policies: &PolicyArgs,
+    precision: u32,
+) -> Result<TransactionService<impl TClientRepository, impl TTransactionRepository>, CliError> {
+    let mut service = TransactionService::new(client_repo, transaction_repo)
+        .with_dispute_policy(policies.withdrawal_disputes.into())
+        .with_disputability_window(DisputabilityWindow {
+            max_age: policies
+                .dispute_window_days
+                .map(|days| Duration::from_secs(days * SECONDS_PER_DAY)),
+            ..Default::default()
+        });
+
+    if let Some(hours) = policies.authorization_expiry_hours {
+        service = service.with_authorization_expiry(Duration::from_secs(hours * SECONDS_PER_HOUR));
+    }
+
+    if let Some(collector) = policies.fee_account {
+        let mut fees = FeeSchedule::new(ClientID(collector));
+
+        for fee in &policies.fees {
+            fees = match &fee.fee {
+                Fee::Flat(amount) => {
+                    fees.with_fee(&fee.tx_type, FlatFee(amount.at_precision(precision)?))
+                }
+                Fee::Percentage(basis_points) => {
+                    fees.with_fee(&fee.tx_type, PercentageFee::new(*basis_points))
+                }
+            };
+        }
+
+        service = service.with_fee_schedule(fees);
+    }
+
+    if !policies.velocity_limits.is_empty() {
+        let mut limits = VelocityLimits::default();
+
+        for limit in &policies.velocity_limits {
+            limits = limits.with_limit(limit.at_precision(precision)?);
+        }
+
+        service = service.with_velocity_limits(limits);
+    }
+
+    if let Some(screening) = screening {
+        let action = if policies.quarantine_blocked {
+            ScreeningAction::Quarantine
+        } else {
+            ScreeningAction::Reject
+        };
+
+        service = service.with_screening(screening, action);
+    }
+
+    service.register_warning_sink(warnings);
+
+    Ok(match write_ahead_log {
+        Some(write_ahead_log) => service.with_write_ahead_log(write_ahead_log),
+        None => service,
+    })
+}
+
+/// Compile the rejection rules of the file given on the command line, if any, into a validator
+/// of the service
+#[cfg(feature = "rules")]
+fn register_rules<CR, TR>(
+    service: &mut TransactionService<CR, TR>,
+    policies: &PolicyArgs,
+    precision: u32,
+) -> Result<(), CliError>
+where
+    CR: TClientRepository,
+{
+    let Some(path) = &policies.rules else {
+        return Ok(());
+    };
+
+    let rules = Arc::new(RuleSet::from_file(path, precision)?);
+
+    // The rules look at the deposits applied so far, so they have to see the effects as well
+    service.register_effects_handler(rules.clone());
+    service.register_validator(move |transaction: &Transaction| rules.validate(transaction));
+
+    Ok(())
 }
 
-fn initialize_tx_receiver() -> impl TTransactionStreamProvider {
-    let args: Vec<String> = std::env::args().collect();
+/// The screening of the clients asked for on the command line, if any
+async fn initialize_screening(
+    policies: &PolicyArgs,
+) -> Result<Option<Arc<dyn TScreeningProvider>>, CliError> {
+    #[cfg(feature = "screening")]
+    if let Some(url) = &policies.screening_url {
+        return Ok(Some(Arc::new(RemoteScreeningProvider::new(url.clone()))));
+    }
 
-    if args.len() < 2 {
-        panic!("No arguments provided");
+    match &policies.blocked_clients {
+        Some(path) => Ok(Some(Arc::new(BlockedClientList::from_file(path).await?))),
+        None => Ok(None),
     }
+}
 
-    let csv_file = args.get(1).expect("No file provided");
+/// Replay the write-ahead log at the given path, if one was given, before opening it for
+/// appending the new transactions
+fn initialize_write_ahead_log(
+    path: Option<&Path>,
+) -> Result<(Option<WriteAheadLog>, Vec<LoggedEntry>), CliError> {
+    let Some(path) = path else {
+        return Ok((None, Vec::new()));
+    };
 
-    let path = PathBuf::from(csv_file);
+    let logged = WriteAheadLog::read(path)?;
 
-    CSVTransactionProvider::from(path)
+    let write_ahead_log =
+        WriteAheadLog::open(path).map_err(|err| CliError::OpenFailed(path.to_path_buf(), err))?;
+
+    Ok((Some(write_ahead_log), logged))
 }
 
-fn initialize_state_exporter() -> impl TClientStateExporter {
-    state_exporter::ClientExporter
+/// The input given as `-` is read from the standard input
+const STDIN_INPUT: &str = "-";
+
+fn is_stdin(input: &Path) -> bool {
+    input == Path::new(STDIN_INPUT)
 }
 
-#[tokio::main]
-async fn main() {
-    let tx_receiver = initialize_tx_receiver();
+/// The CSV input, either a file or the standard input, decompressed as it is read when it is
+/// compressed (with the `compression` feature)
+type CsvInput = Box<dyn AsyncRead + Unpin + Send>;
 
-    let client_repo = ShareableClientRepository::from(initialize_client_repo());
-    let transaction_repo = initialize_transaction_repo();
+/// Where the transactions of an input are read from, depending on its extension
+enum InputReceiver {
+    Csv(CSVTransactionProvider<CsvInput>),
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetTransactionProvider),
+    #[cfg(feature = "s3")]
+    S3(S3TransactionProvider),
+}
 
-    let transaction_service = initialize_service(client_repo.clone(), transaction_repo);
+impl InputReceiver {
+    fn subscribe_to_row_errors(&mut self) -> BoxStream<'static, RowError> {
+        match self {
+            InputReceiver::Csv(provider) => provider.subscribe_to_row_errors(),
+            #[cfg(feature = "parquet")]
+            InputReceiver::Parquet(provider) => provider.subscribe_to_row_errors(),
+            #[cfg(feature = "s3")]
+            InputReceiver::S3(provider) => provider.subscribe_to_row_errors(),
+        }
+    }
+}
 
-    tx_receiver
-        .subscribe_to_tx_stream()
-        .await
-        .for_each(|tx| async {
-            if let Err(err) = transaction_service.process_transaction(tx).await {
-                eprintln!("Error processing transaction: {}", err);
-            }
-        })
-        .await;
+impl TTransactionStreamProvider for InputReceiver {
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        match self {
+            InputReceiver::Csv(provider) => provider.subscribe_to_tx_stream().await,
+            #[cfg(feature = "parquet")]
+            InputReceiver::Parquet(provider) => provider.subscribe_to_tx_stream().await,
+            #[cfg(feature = "s3")]
+            InputReceiver::S3(provider) => provider.subscribe_to_tx_stream().await,
+        }
+    }
+}
 
-    let state_exporter = initialize_state_exporter();
+/// The transactions of every input, read as a single stream
+struct TxReceiver(MergedTransactionProvider<InputReceiver>);
 
-    let state = client_repo.find_all_clients().await;
+impl TxReceiver {
+    /// The row errors of every input, one input after the other
+    fn subscribe_to_row_errors(&mut self) -> BoxStream<'static, RowError> {
+        let row_errors = self
+            .0
+            .inputs_mut()
+            .map(InputReceiver::subscribe_to_row_errors)
+            .collect::<Vec<_>>();
 
-    state_exporter
-        .export_state(state)
+        futures::stream::iter(row_errors).flatten().boxed()
+    }
+
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        self.0.subscribe_to_tx_stream().await
+    }
+}
+
+fn merge_order(merge_by_timestamp: bool) -> MergeOrder {
+    if merge_by_timestamp {
+        MergeOrder::Timestamp
+    } else {
+        MergeOrder::Concatenated
+    }
+}
+
+/// Expand the glob patterns among the inputs (e.g. `txs-*.csv`, quoted so the shell leaves it
+/// alone) to the files they match, in the order their paths are sorted in
+fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, CliError> {
+    if inputs.is_empty() {
+        return Err(CliError::MissingInput);
+    }
+
+    let mut expanded = Vec::with_capacity(inputs.len());
+
+    for input in inputs {
+        let pattern = input.to_string_lossy();
+
+        // Plain paths (and URLs) are kept as they are, so the missing ones are reported when
+        // opened
+        if !pattern.contains(['*', '?', '[']) || pattern.contains("://") {
+            expanded.push(input.clone());
+
+            continue;
+        }
+
+        let matches = glob::glob(&pattern)
+            .map_err(|err| CliError::InvalidPattern(pattern.to_string(), err))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| CliError::OpenFailed(err.path().to_path_buf(), err.into()))?;
+
+        if matches.is_empty() {
+            return Err(CliError::NoMatchingInput(pattern.into_owned()));
+        }
+
+        expanded.extend(matches);
+    }
+
+    Ok(expanded)
+}
+
+/// How the rows of every input are read
+#[derive(Clone)]
+struct InputSettings {
+    precision: u32,
+    error_mode: CsvErrorMode,
+    ingestion_timestamps: bool,
+    admin_source: bool,
+    warnings: Arc<dyn TWarningSink>,
+    /// Counts what every input reads, for the progress of the run
+    metrics: Option<ReaderMetrics>,
+}
+
+/// Read the inputs one after the other, or merged by timestamp, each of them as it would be
+/// read on its own
+async fn initialize_tx_receiver(
+    inputs: &[PathBuf],
+    merge_order: MergeOrder,
+    settings: &InputSettings,
+) -> Result<TxReceiver, CliError> {
+    let mut receivers = Vec::with_capacity(inputs.len());
+
+    for input in inputs {
+        receivers.push(initialize_input_receiver(input, settings.clone()).await?);
+    }
+
+    Ok(TxReceiver(
+        MergedTransactionProvider::new(receivers).with_order(merge_order),
+    ))
+}
+
+/// Read the input as Parquet when it has the `.parquet` extension (with the `parquet`
+/// feature), or as CSV otherwise, decompressing it if it's compressed with gzip or zstd (with
+/// the `compression` feature). The input `-` is read from the standard input, and URLs are
+/// downloaded as they are read (with the `remote-input` feature), both as CSV. Inputs given as
+/// `s3://bucket/prefix` read the objects under it (with the `s3` feature).
+async fn initialize_input_receiver(
+    input: &Path,
+    settings: InputSettings,
+) -> Result<InputReceiver, CliError> {
+    let mut options = CsvReadOptions::default()
+        .with_precision(settings.precision)
+        .with_error_mode(settings.error_mode)
+        .with_ingestion_timestamps(settings.ingestion_timestamps)
+        .with_admin_source(settings.admin_source)
+        .with_warning_sink(settings.warnings);
+
+    if let Some(metrics) = settings.metrics.clone() {
+        options = options.with_metrics(metrics);
+    }
+
+    #[cfg(feature = "s3")]
+    if let Some(location) = s3_location(input).transpose()? {
+        let provider = S3TransactionProvider::from_env(&location).await?;
+
+        return Ok(InputReceiver::S3(provider.with_options(options)));
+    }
+
+    #[cfg(feature = "parquet")]
+    if input
+        .extension()
+        .is_some_and(|extension| extension == "parquet")
+    {
+        let provider = ParquetTransactionProvider::open(input)
+            .map_err(|err| CliError::ParquetInput(input.to_path_buf(), err))?;
+
+        return Ok(InputReceiver::Parquet(provider.with_options(options)));
+    }
+
+    let file = open_csv_input(input, settings.metrics.as_ref())
         .await
-        .expect("Failed to export state");
+        .map_err(|err| CliError::OpenFailed(input.to_path_buf(), err))?;
+
+    Ok(InputReceiver::Csv(
+        CSVTransactionProvider::new(file).with_options(options),
+    ))
 }
 
-pub struct ShareableTransactionRepository<TR> {
-    repo: Arc<TR>,
+/// Open the input, decompressing it as it is read when it is compressed (with the
+/// `compression` feature). The bytes of the files are counted as they are read from the disk,
+/// before being decompressed, so they can be compared with their size.
+async fn open_csv_input(
+    input: &Path,
+    metrics: Option<&ReaderMetrics>,
+) -> std::io::Result<CsvInput> {
+    #[cfg(feature = "remote-input")]
+    if let Some(url) = remote_url(input) {
+        return decompressing(RemoteInput::open(url).await?).await;
+    }
+
+    if is_stdin(input) {
+        return decompressing(tokio::io::stdin()).await;
+    }
+
+    let file: CsvInput = match metrics {
+        Some(metrics) => Box::new(metrics.count_bytes(tokio::fs::File::open(input).await?)),
+        None => Box::new(tokio::fs::File::open(input).await?),
+    };
+
+    #[cfg(feature = "compression")]
+    let file: CsvInput = Box::new(
+        DecompressingReader::detect_with_path(input, tokio::io::BufReader::new(file)).await?,
+    );
+
+    Ok(file)
 }
 
-pub struct ShareableClientRepository<CR> {
-    repo: Arc<CR>,
+/// Inputs without a path are told to be compressed from their first bytes alone
+#[cfg(feature = "compression")]
+async fn decompressing(
+    reader: impl AsyncRead + Unpin + Send + 'static,
+) -> std::io::Result<CsvInput> {
+    let reader = tokio::io::BufReader::new(reader);
+
+    Ok(Box::new(DecompressingReader::detect(reader).await?))
 }
 
-impl<TR> From<TR> for ShareableTransactionRepository<TR> {
-    fn from(repo: TR) -> Self {
-        Self {
-            repo: Arc::new(repo),
+#[cfg(not(feature = "compression"))]
+async fn decompressing(
+    reader: impl AsyncRead + Unpin + Send + 'static,
+) -> std::io::Result<CsvInput> {
+    Ok(Box::new(reader))
+}
+
+/// The S3 location of the inputs and outputs given as `s3://bucket/key`
+#[cfg(feature = "s3")]
+fn s3_location(path: &Path) -> Option<Result<S3Location, S3Error>> {
+    path.to_str()
+        .filter(|path| path.starts_with("s3://"))
+        .map(str::parse)
+}
+
+/// Inputs given as an `http://` or `https://` URL are downloaded as they are read
+#[cfg(feature = "remote-input")]
+fn remote_url(input: &Path) -> Option<reqwest::Url> {
+    let input = input.to_str()?;
+
+    if !(input.starts_with("http://") || input.starts_with("https://")) {
+        return None;
+    }
+
+    reqwest::Url::parse(input).ok()
+}
+
+/// The warnings are always counted for the run report and, if a file is given, also written
+/// to it
+fn initialize_warnings(
+    warnings_file: Option<&Path>,
+) -> Result<(Arc<WarningCounter>, Arc<dyn TWarningSink>), CliError> {
+    let counter = Arc::new(WarningCounter::default());
+
+    let sink: Arc<dyn TWarningSink> = match warnings_file {
+        Some(path) => {
+            let file =
+                File::create(path).map_err(|err| CliError::OpenFailed(path.to_path_buf(), err))?;
+
+            Arc::new(WarningSinks(vec![
+                Box::new(counter.clone()),
+                Box::new(WarningLog::new(file)),
+            ]))
+        }
+        None => counter.clone(),
+    };
+
+    Ok((counter, sink))
+}
+
+/// Open the file the rejected transactions are written to, if one was given
+fn initialize_dead_letters(
+    path: Option<&Path>,
+    format: DeadLetterFormat,
+) -> Result<Option<Box<dyn TRejectedTransactionSink>>, CliError> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+
+    let file = File::create(path).map_err(|err| CliError::OpenFailed(path.to_path_buf(), err))?;
+
+    let sink: Box<dyn TRejectedTransactionSink> = match format {
+        DeadLetterFormat::Csv => Box::new(CsvRejectedTransactionSink::new(file)),
+        #[cfg(feature = "json")]
+        DeadLetterFormat::Json => Box::new(JsonRejectedTransactionSink::new(file)),
+    };
+
+    Ok(Some(sink))
+}
+
+/// Build the snapshotter of the state asked for on the command line, if any
+fn initialize_snapshotter<CR>(
+    args: &SnapshotArgs,
+    client_repo: CR,
+    precision: u32,
+) -> Result<Option<StateSnapshotter<CR>>, CliError>
+where
+    CR: TClientRepository,
+{
+    let Some(directory) = &args.snapshot_dir else {
+        return Ok(None);
+    };
+
+    std::fs::create_dir_all(directory)
+        .map_err(|err| CliError::OpenFailed(directory.clone(), err))?;
+
+    let mut snapshotter = StateSnapshotter::new(client_repo, directory).with_precision(precision);
+
+    if let Some(transactions) = args.snapshot_every {
+        snapshotter = snapshotter.with_transaction_interval(transactions);
+    }
+
+    if let Some(seconds) = args.snapshot_interval {
+        snapshotter = snapshotter.with_time_interval(Duration::from_secs(seconds));
+    }
+
+    Ok(Some(snapshotter))
+}
+
+/// Take the periodic snapshots of the state, if there are any, until the given processing ends
+async fn with_periodic_snapshots<CR, T>(
+    snapshotter: Option<&StateSnapshotter<CR>>,
+    processing: impl Future<Output = T>,
+) -> T
+where
+    CR: TClientRepository,
+{
+    let snapshots = async {
+        if let Some(snapshotter) = snapshotter {
+            snapshotter.run_periodically().await;
         }
+
+        std::future::pending().await
+    };
+
+    tokio::select! {
+        processed = processing => processed,
+        processed = snapshots => processed,
     }
 }
 
-impl<TR> Clone for ShareableTransactionRepository<TR> {
-    fn clone(&self) -> Self {
+/// How far the inputs have been read
+struct Progress {
+    metrics: ReaderMetrics,
+    /// The size of the inputs, unless some of them aren't files whose bytes are counted (e.g.
+    /// the standard input, or the Parquet files)
+    total_bytes: Option<u64>,
+}
+
+impl Progress {
+    fn new(inputs: &[PathBuf]) -> Self {
+        let total_bytes = inputs
+            .iter()
+            .map(|input| {
+                if is_stdin(input)
+                    || input
+                        .extension()
+                        .is_some_and(|extension| extension == "parquet")
+                {
+                    return None;
+                }
+
+                std::fs::metadata(input)
+                    .ok()
+                    .filter(|metadata| metadata.is_file())
+                    .map(|metadata| metadata.len())
+            })
+            .sum();
+
         Self {
-            repo: self.repo.clone(),
+            metrics: ReaderMetrics::default(),
+            total_bytes,
+        }
+    }
+
+    fn line(&self) -> String {
+        let stats = self.metrics.stats();
+
+        let rows = format!(
+            "{} rows ({} invalid), {:.0} rows/s",
+            stats.rows_read,
+            stats.parse_failures,
+            stats.rows_per_second()
+        );
+
+        let Some(total) = self.total_bytes.filter(|total| *total > 0) else {
+            return rows;
+        };
+
+        let read = stats.bytes_read.min(total);
+
+        let filled = (read as u128 * PROGRESS_BAR_WIDTH as u128 / total as u128) as u64;
+
+        format!(
+            "[{}{}] {:5.1}% of {}, {}/s, {}",
+            "#".repeat(filled as usize),
+            " ".repeat((PROGRESS_BAR_WIDTH - filled) as usize),
+            read as f64 * 100.0 / total as f64,
+            format_size(total),
+            format_size(stats.bytes_per_second() as u64),
+            rows
+        )
+    }
+
+    /// Overwrite the line of the progress shown before
+    fn show(&self) {
+        eprint!("\r{}", self.line());
+    }
+}
+
+/// The size in bytes, in the largest binary unit it has at least one of
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = "B";
+
+    for larger in ["KiB", "MiB", "GiB", "TiB"] {
+        if size < 1024.0 {
+            break;
+        }
+
+        size /= 1024.0;
+        unit = larger;
+    }
+
+    format!("{:.1} {}", size, unit)
+}
+
+/// Show the progress of the inputs, if asked to, until the given processing ends
+async fn with_progress<T>(progress: Option<&Progress>, processing: impl Future<Output = T>) -> T {
+    let showing = async {
+        match progress {
+            Some(progress) => {
+                let mut ticks = tokio::time::interval(PROGRESS_INTERVAL);
+
+                loop {
+                    ticks.tick().await;
+
+                    progress.show();
+                }
+            }
+            None => std::future::pending().await,
         }
+    };
+
+    let processed = tokio::select! {
+        processed = processing => processed,
+        processed = showing => processed,
+    };
+
+    // Once more, so the last line shows the whole input as read
+    if let Some(progress) = progress {
+        progress.show();
+
+        eprintln!();
     }
+
+    processed
 }
 
-impl<TR> TTransactionRepository for ShareableTransactionRepository<TR>
+/// Count a processed transaction towards the next snapshot of the state, if there is one
+async fn snapshot_processed<CR>(snapshotter: Option<&StateSnapshotter<CR>>)
 where
-    TR: TTransactionRepository,
+    CR: TClientRepository,
 {
-    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Option<StoredTX> {
-        self.repo.find_tx_by_id(tx_id).await
+    if let Some(snapshotter) = snapshotter {
+        if let Err(err) = snapshotter.transaction_processed().await {
+            eprintln!("Failed to take a snapshot of the state: {}", err);
+        }
+    }
+}
+
+/// Export the state of every account in the given format, ordered by client id if asked to
+async fn export_state(
+    client_repo: &impl TClientRepository,
+    precision: u32,
+    sort_by_client: bool,
+    format: ExportFormat,
+) -> Result<Vec<u8>, CliError> {
+    let clients = if sort_by_client {
+        client_repo.find_all_clients_sorted().await?
+    } else {
+        client_repo.find_all_clients().await?
+    };
+
+    match format {
+        ExportFormat::Csv => {
+            let state_exporter = CsvStateExporter::new(Vec::new()).with_precision(precision)?;
+
+            state_exporter.export_state(clients).await?;
+
+            Ok(state_exporter.into_inner())
+        }
+        #[cfg(feature = "json")]
+        ExportFormat::Json | ExportFormat::Ndjson => {
+            let layout = match format {
+                ExportFormat::Json => JsonLayout::Array,
+                _ => JsonLayout::Lines,
+            };
+
+            let state_exporter =
+                JsonStateExporter::new(Vec::new(), layout).with_precision(precision);
+
+            state_exporter.export_state(clients).await?;
+
+            Ok(state_exporter.into_inner())
+        }
     }
+}
+
+/// Write the output of the run to the given file, or to the standard output. Outputs given as
+/// `s3://bucket/key` are uploaded to that object (with the `s3` feature).
+async fn write_output(path: Option<&Path>, output: &[u8]) -> Result<(), CliError> {
+    #[cfg(feature = "s3")]
+    if let Some(location) = path.and_then(s3_location).transpose()? {
+        S3StateExporter::from_env(location)
+            .await
+            .upload(output.to_vec())
+            .await?;
 
-    async fn save_tx(&self, tx: StoredTX) {
-        self.repo.save_tx(tx).await
+        return Ok(());
     }
 
-    async fn store_tx(&self, tx: Transaction) -> StoredTX {
-        self.repo.store_tx(tx).await
+    match path {
+        Some(path) => std::fs::write(path, output),
+        None => std::io::stdout().write_all(output),
     }
+    .map_err(|err| {
+        CliError::WriteFailed(
+            path.map_or_else(|| PathBuf::from("stdout"), Path::to_path_buf),
+            err.to_string(),
+        )
+    })
 }
 
-impl<CR> From<CR> for ShareableClientRepository<CR> {
-    fn from(repo: CR) -> Self {
-        Self {
-            repo: Arc::new(repo),
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    initialize_tracing();
+
+    let result = match cli.command {
+        Command::Process(args) => process(args).await,
+        Command::Serve(args) => serve(args).await,
+        Command::Verify(args) => verify(args).await,
+        Command::Replay(args) => replay(args).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+
+            err.exit_code()
         }
     }
 }
 
-impl<CR> Clone for ShareableClientRepository<CR> {
-    fn clone(&self) -> Self {
-        Self {
-            repo: self.repo.clone(),
+/// The repositories keeping the state of the accounts in memory, split into the given amount
+/// of shards
+fn in_mem_repositories(shards: Option<u64>) -> (ClientInMemRepository, TransactionInMemRepository) {
+    match shards {
+        Some(shards) => (
+            ClientInMemRepository::with_shards(shards as usize),
+            TransactionInMemRepository::with_shards(shards as usize),
+        ),
+        None => Default::default(),
+    }
+}
+
+/// Process every transaction of the input and export the resulting state
+async fn process(args: ProcessArgs) -> Result<(), CliError> {
+    #[cfg(feature = "config")]
+    let args = {
+        let mut args = args;
+
+        if let Some(path) = args.config.clone() {
+            AppConfig::from_file(path)?.apply_to_process(&mut args)?;
+        }
+
+        args
+    };
+
+    #[cfg(not(feature = "config"))]
+    if args.config.is_some() {
+        return Err(CliError::Unsupported("--config", "config"));
+    }
+
+    match args.storage.clone() {
+        Storage::Memory => {
+            let (client_repo, transaction_repo) = in_mem_repositories(args.shards);
+
+            process_with(args, client_repo, transaction_repo).await
+        }
+        #[cfg(feature = "sqlite")]
+        Storage::Sqlite(path) => {
+            let pool = sqlite::connect(path).await?;
+
+            process_with(
+                args,
+                SqliteClientRepository::new(pool.clone()),
+                SqliteTransactionRepository::new(pool),
+            )
+            .await
         }
+        #[cfg(not(feature = "sqlite"))]
+        Storage::Sqlite(_) => Err(CliError::Unsupported("--storage sqlite", "sqlite")),
     }
 }
 
-impl<CR> TClientRepository for ShareableClientRepository<CR>
+/// Process the input with the state of the accounts kept in the given repositories
+async fn process_with<CR, TR>(
+    args: ProcessArgs,
+    client_repo: CR,
+    transaction_repo: TR,
+) -> Result<(), CliError>
 where
-    CR: TClientRepository,
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
 {
-    async fn find_all_clients(&self) -> BoxStream<'static, StoredClient> {
-        self.repo.find_all_clients().await
+    let inputs = expand_inputs(&args.input)?;
+
+    let precision = args.precision.unwrap_or(FLOATING_POINT_ACC as u32);
+
+    #[cfg(feature = "json")]
+    let mut manifest = match &args.manifest {
+        Some(_) => {
+            let mut recorder = ManifestRecorder::start(std::env::args().skip(1));
+
+            // Only the files can be digested, as the standard input and the downloads can't be
+            // read twice
+            for input in inputs
+                .iter()
+                .filter(|input| !is_stdin(input) && input.is_file())
+            {
+                recorder.record_input(
+                    FileDigest::of_file(input)
+                        .map_err(|err| CliError::OpenFailed(input.clone(), err))?,
+                );
+            }
+
+            Some(recorder)
+        }
+        None => None,
+    };
+
+    let error_mode = if args.lenient {
+        CsvErrorMode::Lenient
+    } else {
+        CsvErrorMode::Strict
+    };
+
+    let (warning_counter, warnings) = initialize_warnings(args.warnings.as_deref())?;
+
+    let progress = args.progress.then(|| Progress::new(&inputs));
+
+    let settings = InputSettings {
+        precision,
+        error_mode,
+        ingestion_timestamps: args.ingestion_timestamps,
+        admin_source: args.admin_source,
+        warnings: warnings.clone(),
+        metrics: progress.as_ref().map(|progress| progress.metrics.clone()),
+    };
+
+    let mut tx_receiver =
+        initialize_tx_receiver(&inputs, merge_order(args.merge_by_timestamp), &settings).await?;
+
+    let row_errors = tx_receiver.subscribe_to_row_errors();
+
+    let client_repo = ShareableClientRepository::from(client_repo);
+    let transaction_repo = ShareableTransactionRepository::from(transaction_repo);
+
+    let (write_ahead_log, logged) = initialize_write_ahead_log(args.wal.as_deref())?;
+
+    let screening = initialize_screening(&args.policies).await?;
+
+    let mut transaction_service = initialize_service(
+        client_repo.clone(),
+        transaction_repo.clone(),
+        warnings,
+        write_ahead_log,
+        screening,
+        &args.policies,
+        precision,
+    )?;
+
+    #[cfg(feature = "rules")]
+    register_rules(&mut transaction_service, &args.policies, precision)?;
+
+    recover(&transaction_service, logged).await;
+
+    // Registered after the recovery, so only the transactions of this run are summarized
+    let summary = Arc::new(SummaryRecorder::new(precision));
+
+    transaction_service.register_effects_handler(summary.clone());
+
+    // Shared with the tasks processing the batches in parallel
+    let transaction_service = Arc::new(transaction_service);
+
+    let dead_letters =
+        initialize_dead_letters(args.dead_letters.as_deref(), args.dead_letter_format)?;
+
+    let (processed, rejections) = (AtomicU64::new(0), RejectionCounter::default());
+
+    // Only counted once we know whether they were rejected for good
+    let rejected = RejectedTransactionBuffer::default();
+
+    let heatmap = ActivityHeatmap::new(HEATMAP_CLIENT_BUCKET, HEATMAP_CHUNK);
+
+    let snapshotter = initialize_snapshotter(&args.snapshots, client_repo.clone(), precision)?;
+
+    let transactions = heatmap.track(tx_receiver.subscribe_to_tx_stream().await);
+
+    let processing = async {
+        let Some(batch_size) = args.parallel_batches else {
+            transactions
+                .for_each(|tx| async {
+                    processed.fetch_add(1, Ordering::Relaxed);
+
+                    // The rejection itself is reported by the service
+                    if let Err(err) = transaction_service.process_transaction(tx.clone()).await {
+                        rejected.reject(&RejectedTransaction::new(tx, &err));
+                    }
+
+                    snapshot_processed(snapshotter.as_ref()).await;
+                })
+                .await;
+
+            return Ok(());
+        };
+
+        let parallelism = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+
+        let processor = SpeculativeBatchProcessor::new(transaction_service.clone(), parallelism);
+
+        let mut batches = transactions.chunks(batch_size as usize);
+
+        while let Some(batch) = batches.next().await {
+            processed.fetch_add(batch.len() as u64, Ordering::Relaxed);
+
+            let outcome = processor.process_batch(batch.clone()).await?;
+
+            // The results are in the order of the transactions of the batch
+            for (tx, (_, result)) in batch.into_iter().zip(outcome.results) {
+                if let Err(err) = result {
+                    rejected.reject(&RejectedTransaction::new(tx, &err));
+                }
+
+                snapshot_processed(snapshotter.as_ref()).await;
+            }
+        }
+
+        Ok::<_, CliError>(())
+    };
+
+    with_progress(
+        progress.as_ref(),
+        with_periodic_snapshots(snapshotter.as_ref(), processing),
+    )
+    .await?;
+
+    let mut rejected = rejected.into_rejected();
+
+    if args.retry_rejected && !rejected.is_empty() {
+        let retried = rejected.len();
+
+        rejected = retry_rejected(&*transaction_service, rejected).await;
+
+        eprintln!(
+            "Retried {} rejected transactions, {} of which were accepted",
+            retried,
+            retried - rejected.len()
+        );
+    }
+
+    if let Some(now) = args.expire_holds_at {
+        match transaction_service.expire_holds(now).await {
+            Ok(sweep) => {
+                report_failed_expiries(&sweep);
+
+                eprintln!("Expired {} authorizations", sweep.expired);
+            }
+            Err(err) => eprintln!("Failed to expire the authorizations: {}", err),
+        }
     }
 
-    async fn find_client_by_id(&self, client_id: ClientID) -> Option<StoredClient> {
-        self.repo.find_client_by_id(client_id).await
+    // Each of them was also reported as a warning, so they can be found in the warnings file
+    let quarantined = transaction_service.take_quarantined();
+
+    if !quarantined.is_empty() {
+        eprintln!(
+            "Quarantined {} transactions of blocked clients",
+            quarantined.len()
+        );
     }
 
-    async fn save_client(&self, client: StoredClient) {
-        self.repo.save_client(client).await
+    for rejected in &rejected {
+        rejections.record(rejected.code);
+        summary.record_rejected(&rejected.transaction);
+
+        if let Some(dead_letters) = &dead_letters {
+            dead_letters.reject(rejected);
+        }
     }
 
-    async fn store_client(&self, client: Client) -> StoredClient {
-        self.repo.store_client(client).await
+    let invalid_rows = row_errors
+        .inspect(|row_error| {
+            processed.fetch_add(1, Ordering::Relaxed);
+            rejections.record(row_error.error.rejection_code());
+
+            eprintln!("Invalid transaction: {}", row_error);
+        })
+        .count()
+        .await;
+
+    let rejections = rejections.counts();
+
+    let report = RunReport {
+        processed: processed.into_inner(),
+        rejected: rejections.values().sum(),
+        rejections,
+        warnings: warning_counter.counts(),
+        summary: summary.finish(&client_repo).await?,
+    };
+
+    eprint!("{}", report);
+
+    if invalid_rows > 0 && error_mode == CsvErrorMode::Strict {
+        eprintln!("Stopped at the first invalid row, use --lenient to skip invalid rows instead");
+
+        return Err(CliError::InvalidInput(invalid_rows));
+    }
+
+    if let Some(path) = &args.heatmap {
+        let file = File::create(path).map_err(|err| CliError::OpenFailed(path.clone(), err))?;
+
+        heatmap
+            .export(file)
+            .map_err(|err| CliError::WriteFailed(path.clone(), err.to_string()))?;
+    }
+
+    // Keep the exported state around, so we know exactly what was written
+    let output = export_state(&client_repo, precision, args.sort_by_client, args.format).await?;
+
+    write_output(args.output.as_deref(), &output).await?;
+
+    #[cfg(feature = "parquet")]
+    export_parquet(
+        &args,
+        precision,
+        client_repo.clone(),
+        transaction_repo.clone(),
+    )
+    .await?;
+
+    export_statements(&args.statements, client_repo, transaction_repo).await?;
+
+    #[cfg(feature = "json")]
+    if let (Some(path), Some(mut recorder)) = (&args.manifest, manifest.take()) {
+        let output_name = args
+            .output
+            .as_ref()
+            .map_or_else(|| "stdout".to_string(), |path| path.display().to_string());
+
+        recorder.record_output(FileDigest::of_bytes(output_name, &output));
+
+        let outputs = [&args.heatmap, &args.dead_letters];
+
+        #[cfg(feature = "parquet")]
+        let outputs = outputs
+            .into_iter()
+            .chain([&args.parquet_state, &args.parquet_transactions]);
+
+        for path in outputs.into_iter().flatten() {
+            recorder.record_output(
+                FileDigest::of_file(path).map_err(|err| CliError::OpenFailed(path.clone(), err))?,
+            );
+        }
+
+        let file = File::create(path).map_err(|err| CliError::OpenFailed(path.clone(), err))?;
+
+        recorder
+            .finish(&report)
+            .write(file)
+            .map_err(|err| CliError::WriteFailed(path.clone(), err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Export the state of the accounts and the processed transactions to the Parquet files asked
+/// for on the command line, if any
+#[cfg(feature = "parquet")]
+async fn export_parquet(
+    args: &ProcessArgs,
+    precision: u32,
+    client_repo: impl TClientRepository,
+    transaction_repo: impl TTransactionRepository,
+) -> Result<(), CliError> {
+    if let Some(path) = &args.parquet_state {
+        let file = File::create(path).map_err(|err| CliError::OpenFailed(path.clone(), err))?;
+
+        let clients = if args.sort_by_client {
+            client_repo.find_all_clients_sorted().await?
+        } else {
+            client_repo.find_all_clients().await?
+        };
+
+        ParquetStateExporter::new(file)
+            .with_precision(precision)
+            .export_state(clients)
+            .await?;
+    }
+
+    if let Some(path) = &args.parquet_transactions {
+        let file = File::create(path).map_err(|err| CliError::OpenFailed(path.clone(), err))?;
+
+        let exported = ParquetTransactionLogExporter::new(client_repo, transaction_repo)
+            .with_precision(precision)
+            .export(file)
+            .await?;
+
+        eprintln!("Exported {} transactions to {}", exported, path.display());
+    }
+
+    Ok(())
+}
+
+/// Write the statements of the accounts asked for on the command line, if any
+async fn export_statements(
+    args: &StatementArgs,
+    client_repo: impl TClientRepository,
+    transaction_repo: impl TTransactionRepository,
+) -> Result<(), CliError> {
+    let Some(directory) = &args.statements else {
+        return Ok(());
+    };
+
+    let exporter = StatementExporter::new(client_repo, transaction_repo, directory)
+        .with_format(args.statement_format.into());
+
+    match args.statement_client {
+        Some(client) => {
+            exporter.export_client(ClientID(client)).await?;
+        }
+        None => {
+            exporter.export_all().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Receive transactions over HTTP, acknowledging each of them with its outcome, until stopped
+#[cfg(feature = "http")]
+async fn serve(args: ServeArgs) -> Result<(), CliError> {
+    #[cfg(feature = "config")]
+    let args = {
+        let mut args = args;
+
+        if let Some(path) = args.config.clone() {
+            AppConfig::from_file(path)?.apply_to_serve(&mut args)?;
+        }
+
+        args
+    };
+
+    #[cfg(not(feature = "config"))]
+    if args.config.is_some() {
+        return Err(CliError::Unsupported("--config", "config"));
+    }
+
+    match args.storage.clone() {
+        Storage::Memory => {
+            if warm_up_config(&args).is_some() {
+                return Err(CliError::NotPersistent(
+                    "--warm-up-clients or --warm-up-transactions",
+                ));
+            }
+
+            if args.write_behind.is_some() {
+                return Err(CliError::NotPersistent("--write-behind"));
+            }
+
+            let (client_repo, transaction_repo) = in_mem_repositories(args.shards);
+
+            serve_with(args, client_repo, transaction_repo, None).await
+        }
+        #[cfg(feature = "sqlite")]
+        Storage::Sqlite(path) => {
+            let pool = sqlite::connect(path).await?;
+
+            let client_repo = SqliteClientRepository::new(pool.clone());
+            let transaction_repo = SqliteTransactionRepository::new(pool.clone());
+
+            warm_up(
+                warm_up_config(&args),
+                &SqlWarmUpSource::new(pool.clone()),
+                &client_repo,
+                &transaction_repo,
+            )
+            .await?;
+
+            let served = match args.write_behind {
+                Some(high_watermark) => {
+                    let backlog =
+                        Arc::new(PersistenceBacklog::new(high_watermark, high_watermark / 2));
+
+                    let client_repo =
+                        WriteBehindClientRepository::new(client_repo, backlog.clone());
+
+                    let served =
+                        serve_with(args, client_repo, transaction_repo, Some(backlog.clone()))
+                            .await;
+
+                    if backlog.backlog() > 0 {
+                        eprintln!(
+                            "Waiting for {} changes to be written to the storage",
+                            backlog.backlog()
+                        );
+                    }
+
+                    backlog.wait_until_drained().await;
+
+                    served
+                }
+                None => serve_with(args, client_repo, transaction_repo, None).await,
+            };
+
+            // Wait for the connections to finish their writes before exiting
+            pool.close().await;
+
+            served
+        }
+        #[cfg(not(feature = "sqlite"))]
+        Storage::Sqlite(_) => Err(CliError::Unsupported("--storage sqlite", "sqlite")),
+    }
+}
+
+/// How much of the storage to load before receiving any transaction, if any of it
+#[cfg(feature = "http")]
+fn warm_up_config(args: &ServeArgs) -> Option<WarmUpConfig> {
+    if args.warm_up_clients.is_none() && args.warm_up_transactions.is_none() {
+        return None;
+    }
+
+    Some(WarmUpConfig {
+        clients: args.warm_up_clients.unwrap_or_default(),
+        transactions: args.warm_up_transactions.unwrap_or_default(),
+    })
+}
+
+/// Load the most needed accounts and transactions of the storage into the repositories, if
+/// asked to
+#[cfg(all(feature = "http", feature = "sqlite"))]
+async fn warm_up<WS, CR, TR>(
+    config: Option<WarmUpConfig>,
+    source: &WS,
+    client_repo: &CR,
+    transaction_repo: &TR,
+) -> Result<(), CliError>
+where
+    WS: TWarmUpSource,
+    CR: TClientRepository,
+    TR: TTransactionRepository,
+{
+    let Some(config) = config else {
+        return Ok(());
+    };
+
+    let report = warm_up_caches(source, client_repo, transaction_repo, &config).await?;
+
+    eprintln!(
+        "Warmed up {} clients and {} transactions",
+        report.clients_loaded, report.transactions_loaded
+    );
+
+    Ok(())
+}
+
+/// Serve the transactions with the state of the accounts kept in the given repositories, only
+/// receiving them while the storage keeps up with the `backlog` of the changes written to it in
+/// the background, if any
+#[cfg(feature = "http")]
+async fn serve_with<CR, TR>(
+    args: ServeArgs,
+    client_repo: CR,
+    transaction_repo: TR,
+    backlog: Option<Arc<PersistenceBacklog>>,
+) -> Result<(), CliError>
+where
+    CR: TClientRepository + 'static,
+    TR: TTransactionRepository + 'static,
+{
+    let (_, warnings) = initialize_warnings(None)?;
+
+    let precision = args.precision.unwrap_or(FLOATING_POINT_ACC as u32);
+
+    let client_repo = ShareableClientRepository::from(client_repo);
+    let transaction_repo = ShareableTransactionRepository::from(transaction_repo);
+
+    let (write_ahead_log, logged) = initialize_write_ahead_log(args.wal.as_deref())?;
+
+    let screening = initialize_screening(&args.policies).await?;
+
+    let transaction_service = initialize_service(
+        client_repo.clone(),
+        transaction_repo.clone(),
+        warnings,
+        write_ahead_log,
+        screening,
+        &args.policies,
+        precision,
+    )?;
+
+    // Published to the WebSockets which subscribe to the changes to the balances
+    #[cfg(feature = "websocket")]
+    let (transaction_service, events) = {
+        let mut transaction_service = transaction_service;
+
+        let events = EventBus::default();
+
+        transaction_service.register_event_handler(events.clone());
+
+        (transaction_service, events)
+    };
+
+    #[cfg(feature = "webhooks")]
+    let transaction_service = {
+        let mut transaction_service = transaction_service;
+
+        if let Some(endpoint) = args.webhook {
+            let bus = EventBus::default();
+
+            WebhookNotifier::new(endpoint)
+                .with_precision(precision)
+                .spawn(&bus);
+
+            transaction_service.register_event_handler(bus);
+        }
+
+        transaction_service
+    };
+
+    #[cfg(feature = "rules")]
+    let transaction_service = {
+        let mut transaction_service = transaction_service;
+
+        register_rules(&mut transaction_service, &args.policies, precision)?;
+
+        transaction_service
+    };
+
+    recover(&transaction_service, logged).await;
+
+    if let Some(admin_addr) = args.admin {
+        let listener = tokio::net::TcpListener::bind(admin_addr).await?;
+
+        let client_repo = Arc::new(client_repo.clone());
+        let transaction_repo = Arc::new(transaction_repo.clone());
+        let backlog = backlog.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) =
+                admin::serve(listener, client_repo, transaction_repo, precision, backlog).await
+            {
+                eprintln!("Admin API failed: {}", err);
+            }
+        });
+    }
+
+    let mut provider = HttpTransactionProvider::bind(args.listen)
+        .await?
+        .with_precision(precision)
+        .with_shutdown(async {
+            shutdown_signal().await;
+
+            eprintln!("Stopping, after processing the transactions received so far");
+        });
+
+    if let Some(capacity) = args.channel_capacity {
+        provider = provider.with_channel_capacity(capacity as usize);
+    }
+
+    #[cfg(feature = "websocket")]
+    let provider = provider.with_event_bus(events);
+
+    eprintln!("Receiving transactions on {}", provider.local_addr()?);
+
+    let transactions = provider.subscribe_to_acknowledged_stream().await;
+
+    #[cfg(feature = "grpc")]
+    let transactions = match args.grpc {
+        Some(grpc_addr) => {
+            let mut provider = GrpcTransactionProvider::bind(grpc_addr)
+                .await?
+                .with_precision(precision)
+                .with_shutdown(shutdown_signal());
+
+            if let Some(capacity) = args.channel_capacity {
+                provider = provider.with_channel_capacity(capacity as usize);
+            }
+
+            eprintln!(
+                "Receiving transactions over gRPC on {}",
+                provider.local_addr()?
+            );
+
+            futures::stream::select(
+                transactions,
+                provider.subscribe_to_acknowledged_stream().await,
+            )
+            .boxed()
+        }
+        None => transactions,
+    };
+
+    // The submissions wait in the channels of the providers while the storage catches up
+    let transactions = match &backlog {
+        Some(backlog) => backlog.pause_while_lagging(transactions),
+        None => transactions,
+    };
+
+    let snapshotter = initialize_snapshotter(&args.snapshots, client_repo.clone(), precision)?;
+
+    let processing =
+        process_acknowledged_stream(&transaction_service, transactions, snapshotter.as_ref());
+
+    let sweeping = async {
+        if let Some(seconds) = args.hold_sweep_interval {
+            sweep_holds_periodically(&transaction_service, Duration::from_secs(seconds)).await;
+        }
+
+        std::future::pending::<()>().await
+    };
+
+    with_periodic_snapshots(snapshotter.as_ref(), async {
+        tokio::select! {
+            _ = processing => {}
+            _ = sweeping => {}
+        }
+    })
+    .await;
+
+    // Every received transaction was processed, so nothing is lost by exiting
+    write_output(
+        args.output.as_deref(),
+        &export_state(&client_repo, precision, args.sort_by_client, args.format).await?,
+    )
+    .await
+}
+
+/// Wait for the process to be asked to stop, with Ctrl-C (SIGINT) or SIGTERM
+#[cfg(feature = "http")]
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            eprintln!("Failed to listen for Ctrl-C: {}", err);
+
+            std::future::pending::<()>().await
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                eprintln!("Failed to listen for SIGTERM: {}", err);
+
+                std::future::pending::<()>().await
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Release the funds held by the expired authorizations every given interval, as of the time
+/// of each sweep
+#[cfg(feature = "http")]
+async fn sweep_holds_periodically<CR, TR>(
+    transaction_service: &TransactionService<CR, TR>,
+    interval: Duration,
+) where
+    CR: TClientRepository,
+    TR: TTransactionRepository,
+{
+    let mut ticks = tokio::time::interval(interval);
+
+    loop {
+        ticks.tick().await;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+
+        match transaction_service.expire_holds(now).await {
+            Ok(sweep) => {
+                report_failed_expiries(&sweep);
+
+                if sweep.expired > 0 {
+                    tracing::info!(expired = sweep.expired, "Expired authorizations");
+                }
+            }
+            Err(err) => tracing::error!(error = %err, "Failed to expire the authorizations"),
+        }
+    }
+}
+
+/// The authorizations which failed to expire are tried again by the next sweep
+fn report_failed_expiries(sweep: &HoldSweep) {
+    for (tx, err) in &sweep.failed {
+        tracing::error!(
+            tx = %tx,
+            code = err.rejection_code().code(),
+            error = %err,
+            "Failed to expire the authorization"
+        );
+    }
+}
+
+#[cfg(not(feature = "http"))]
+async fn serve(_args: ServeArgs) -> Result<(), CliError> {
+    Err(CliError::Unsupported("serve", "http"))
+}
+
+/// Check every row of the input, reporting all of the invalid ones. Given the expected state
+/// of the accounts, also process the input and compare the resulting state with it.
+async fn verify(args: VerifyArgs) -> Result<(), CliError> {
+    let precision = args.precision.unwrap_or(FLOATING_POINT_ACC as u32);
+
+    let settings = InputSettings {
+        precision,
+        error_mode: CsvErrorMode::Lenient,
+        ingestion_timestamps: false,
+        admin_source: args.admin_source,
+        warnings: Arc::new(WarningCounter::default()),
+        metrics: None,
+    };
+
+    let mut tx_receiver = initialize_tx_receiver(
+        &expand_inputs(&args.input)?,
+        merge_order(args.merge_by_timestamp),
+        &settings,
+    )
+    .await?;
+
+    let row_errors = tx_receiver.subscribe_to_row_errors();
+
+    let transactions = tx_receiver.subscribe_to_tx_stream().await;
+
+    let (valid_rows, processed_state) = match &args.expected {
+        Some(_) => {
+            let (valid_rows, client_repo) =
+                process_in_memory(transactions, &args.policies, precision).await?;
+
+            (valid_rows, Some(client_repo))
+        }
+        None => (transactions.count().await, None),
+    };
+
+    let invalid_rows = row_errors
+        .inspect(|row_error| eprintln!("Invalid transaction: {}", row_error))
+        .count()
+        .await;
+
+    eprintln!(
+        "Checked {} rows, {} of which are invalid",
+        valid_rows + invalid_rows,
+        invalid_rows
+    );
+
+    // The invalid rows are skipped, just as the expected state would have them be
+    let (Some(expected), Some(client_repo)) = (&args.expected, processed_state) else {
+        return match invalid_rows {
+            0 => Ok(()),
+            invalid_rows => Err(CliError::InvalidInput(invalid_rows)),
+        };
+    };
+
+    // Every column is exported, so the expected state can be in any of the schemas
+    let state_exporter = CsvStateExporter::new(Vec::new())
+        .with_schema(ExportSchema::V4)
+        .with_precision(precision)?;
+
+    state_exporter
+        .export_state(client_repo.find_all_clients().await?)
+        .await?;
+
+    let expected_state =
+        File::open(expected).map_err(|err| CliError::OpenFailed(expected.clone(), err))?;
+
+    let differences = compare_states(expected_state, state_exporter.into_inner().as_slice())?;
+
+    if differences.is_empty() {
+        eprintln!("The state of the accounts matches the expected one");
+
+        return Ok(());
+    }
+
+    write_differences(std::io::stdout(), &differences)
+        .map_err(|err| CliError::WriteFailed(PathBuf::from("stdout"), err.to_string()))?;
+
+    Err(CliError::StateMismatch(differences.len()))
+}
+
+/// Process the transactions with the given policies, keeping the state of the accounts in
+/// memory, and return the amount of transactions processed along with the resulting state
+async fn process_in_memory(
+    transactions: BoxStream<'static, Transaction>,
+    policies: &PolicyArgs,
+    precision: u32,
+) -> Result<(usize, ShareableClientRepository<ClientInMemRepository>), CliError> {
+    let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+    let screening = initialize_screening(policies).await?;
+
+    let transaction_service = initialize_service(
+        client_repo.clone(),
+        TransactionInMemRepository::default(),
+        Arc::new(WarningCounter::default()),
+        None,
+        screening,
+        policies,
+        precision,
+    )?;
+
+    #[cfg(feature = "rules")]
+    let transaction_service = {
+        let mut transaction_service = transaction_service;
+
+        register_rules(&mut transaction_service, policies, precision)?;
+
+        transaction_service
+    };
+
+    let processed = transactions
+        .then(|tx| async {
+            // The rejected transactions are part of the expected state as well
+            let _ = transaction_service.process_transaction(tx).await;
+        })
+        .count()
+        .await;
+
+    Ok((processed, client_repo))
+}
+
+/// Rebuild the state of the accounts from the write-ahead log, comparing it with the stored
+/// state, if there is any
+async fn replay(args: ReplayArgs) -> Result<(), CliError> {
+    match args.storage.clone() {
+        Storage::Memory => replay_with(args, None::<ClientInMemRepository>).await,
+        #[cfg(feature = "sqlite")]
+        Storage::Sqlite(path) => {
+            let pool = sqlite::connect(path).await?;
+
+            replay_with(args, Some(SqliteClientRepository::new(pool))).await
+        }
+        #[cfg(not(feature = "sqlite"))]
+        Storage::Sqlite(_) => Err(CliError::Unsupported("--storage sqlite", "sqlite")),
+    }
+}
+
+/// Replay the write-ahead log from scratch, in memory, and compare the result with the given
+/// stored state
+async fn replay_with<CR>(args: ReplayArgs, stored: Option<CR>) -> Result<(), CliError>
+where
+    CR: TClientRepository,
+{
+    let logged = WriteAheadLog::read(&args.wal)?;
+
+    let logged_count = logged.len();
+
+    let precision = args.precision.unwrap_or(FLOATING_POINT_ACC as u32);
+
+    let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+    let (_, warnings) = initialize_warnings(None)?;
+
+    // The logged transactions were already screened and validated when they were accepted
+    let transaction_service = initialize_service(
+        client_repo.clone(),
+        TransactionInMemRepository::default(),
+        warnings,
+        None,
+        None,
+        &args.policies,
+        precision,
+    )?;
+
+    let replayed = transaction_service.recover(logged).await;
+
+    eprintln!("Replayed {} of {} transactions", replayed, logged_count);
+
+    write_output(
+        args.output.as_deref(),
+        &export_state(&client_repo, precision, args.sort_by_client, args.format).await?,
+    )
+    .await?;
+
+    let Some(stored) = stored else {
+        return Ok(());
+    };
+
+    let divergences = diff_clients(&stored, &client_repo).await?;
+
+    for divergence in &divergences {
+        eprintln!("{}", divergence);
+    }
+
+    match divergences.len() {
+        0 => {
+            eprintln!("The stored state matches the replayed one");
+
+            Ok(())
+        }
+        diverged => Err(CliError::Diverged(diverged)),
+    }
+}
+
+/// Restore the state of a previous run from its write-ahead log
+async fn recover<CR, TR>(transaction_service: &TransactionService<CR, TR>, logged: Vec<LoggedEntry>)
+where
+    CR: TClientRepository,
+    TR: TTransactionRepository,
+{
+    if logged.is_empty() {
+        return;
+    }
+
+    let logged_count = logged.len();
+
+    let replayed = transaction_service.recover(logged).await;
+
+    eprintln!(
+        "Recovered {} of {} transactions from the write-ahead log",
+        replayed, logged_count
+    );
+}
+
+/// Drive a stream of transactions which must be acknowledged through the transaction service,
+/// acknowledging each of them with the outcome of its processing.
+#[cfg(feature = "http")]
+async fn process_acknowledged_stream<CR>(
+    transaction_service: &impl TTransactionService,
+    stream: BoxStream<'_, AcknowledgeableTransaction>,
+    snapshotter: Option<&StateSnapshotter<CR>>,
+) where
+    CR: TClientRepository,
+{
+    stream
+        .for_each(|acknowledgeable| async {
+            let (tx, acknowledger) = acknowledgeable.into_parts();
+
+            let outcome = match transaction_service.process_transaction(tx).await {
+                Ok(processed) => ProcessingOutcome::from(processed),
+                Err(err) => ProcessingOutcome::Rejected(err.rejection_code()),
+            };
+
+            acknowledger.acknowledge(outcome).await;
+
+            snapshot_processed(snapshotter).await;
+        })
+        .await;
+}
+
+/// Hands every warning to all of the given sinks
+struct WarningSinks(Vec<Box<dyn TWarningSink>>);
+
+impl TWarningSink for WarningSinks {
+    fn warn(&self, warning: &Warning) {
+        self.0.iter().for_each(|sink| sink.warn(warning));
+    }
+}
+
+#[cfg(test)]
+mod main_tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use tracing_subscriber::EnvFilter;
+
+    use crate::{log_subscriber, DEFAULT_LOG_FILTER};
+
+    /// Writer keeping everything written to it, shared with the test
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_log_subscriber() {
+        let captured = Captured::default();
+
+        let writer = captured.clone();
+        let subscriber = log_subscriber(
+            EnvFilter::new(DEFAULT_LOG_FILTER),
+            move || writer.clone(),
+            false,
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(tx = 1, "Transaction applied");
+            tracing::warn!(row = 2, code = "E001", "Invalid row");
+        });
+
+        let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+
+        // Only the warnings are reported by default, with their fields
+        assert!(!logged.contains("Transaction applied"));
+        assert!(logged.contains("WARN"));
+        assert!(logged.contains("Invalid row row=2 code=\"E001\""));
+    }
+
+    #[cfg(all(feature = "http", feature = "sqlite"))]
+    #[tokio::test]
+    async fn test_warm_up() {
+        use mockall::predicate::eq;
+        use transactioner::repositories::warm_up::MockTWarmUpSource;
+        use transactioner::services::warm_up::WarmUpConfig;
+        use transactioner::{ClientInMemRepository, TransactionInMemRepository};
+
+        use crate::warm_up;
+
+        let client_repo = ClientInMemRepository::default();
+        let transaction_repo = TransactionInMemRepository::default();
+
+        // Nothing is asked of the storage unless warming up was asked for
+        warm_up(
+            None,
+            &MockTWarmUpSource::new(),
+            &client_repo,
+            &transaction_repo,
+        )
+        .await
+        .unwrap();
+
+        let mut source = MockTWarmUpSource::new();
+
+        source
+            .expect_hottest_clients()
+            .with(eq(10))
+            .times(1)
+            .returning(|_| Box::pin(async { vec![] }));
+
+        let config = WarmUpConfig {
+            clients: 10,
+            transactions: 0,
+        };
+
+        warm_up(Some(config), &source, &client_repo, &transaction_repo)
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_process_acknowledged_stream() {
+        use futures::future::BoxFuture;
+        use futures::{FutureExt, StreamExt};
+        use transactioner::models::transactions::{DisputeState, Transaction, TransactionType};
+        use transactioner::state_exporter::snapshots::StateSnapshotter;
+        use transactioner::tx_reception::{
+            AcknowledgeableTransaction, ProcessingOutcome, TAcknowledger,
+        };
+        use transactioner::{
+            ClientID, ClientInMemRepository, MoneyType, RejectionCode, TransactionID,
+            TransactionInMemRepository, TransactionService,
+        };
+
+        use crate::process_acknowledged_stream;
+
+        /// Keeps the outcome of every transaction, in the order they were acknowledged
+        struct Recorder(Arc<Mutex<Vec<ProcessingOutcome>>>);
+
+        impl TAcknowledger for Recorder {
+            fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()> {
+                self.0.lock().unwrap().push(outcome);
+
+                futures::future::ready(()).boxed()
+            }
+        }
+
+        let service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        );
+
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+
+        let transactions = [(1, 100), (2, -500)].map(|(tx_id, amount): (u32, i64)| {
+            let tx_type = if amount > 0 {
+                TransactionType::Deposit {
+                    amount: MoneyType::new(amount),
+                    dispute: DisputeState::NotDisputed,
+                }
+            } else {
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(-amount),
+                    dispute: DisputeState::NotDisputed,
+                }
+            };
+
+            let transaction = Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(tx_type)
+                .build();
+
+            AcknowledgeableTransaction::new(transaction, Box::new(Recorder(outcomes.clone())))
+        });
+
+        process_acknowledged_stream(
+            &service,
+            futures::stream::iter(transactions).boxed(),
+            None::<&StateSnapshotter<ClientInMemRepository>>,
+        )
+        .await;
+
+        // Each submission is answered with the outcome of its own processing
+        assert_eq!(
+            *outcomes.lock().unwrap(),
+            vec![
+                ProcessingOutcome::Accepted,
+                ProcessingOutcome::Rejected(RejectionCode::InsufficientFunds)
+            ]
+        );
     }
 }
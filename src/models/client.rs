@@ -1,26 +1,140 @@
 use getset::{CopyGetters, Getters};
 use thiserror::Error;
 
+use crate::models::effects::Effects;
 use crate::models::{ClientID, MoneyType, NoVal};
 
 /// The current status of the account
-#[derive(PartialEq, Eq, Default)]
+#[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum ClientAccountStatus {
     #[default]
     Active,
     Frozen,
+    /// Closed for good, rejecting every transaction
+    Closed,
 }
 
-#[derive(Getters, CopyGetters)]
+/// The kind of transaction whose disputed funds are being held
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DisputedFunds {
+    Deposited,
+    Withdrawn,
+}
+
+/// Breakdown of the disputes open on an account, and of the funds held because of them
+///
+/// Also used to represent the change to that breakdown, which is why the counters are signed.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(CopyGetters, PartialEq, Eq, Default, Clone, Copy, Debug)]
+pub struct DisputeLedger {
+    #[get_copy = "pub"]
+    open_disputes: i64,
+    #[get_copy = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+    held_for_deposits: MoneyType,
+    #[get_copy = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+    held_for_withdrawals: MoneyType,
+}
+
+impl DisputeLedger {
+    pub fn new(
+        open_disputes: i64,
+        held_for_deposits: MoneyType,
+        held_for_withdrawals: MoneyType,
+    ) -> Self {
+        Self {
+            open_disputes,
+            held_for_deposits,
+            held_for_withdrawals,
+        }
+    }
+
+    /// The ledger after opening a dispute, unless it overflows
+    fn opened(&self, funds: DisputedFunds, amount: MoneyType) -> Option<Self> {
+        self.moved(1, funds, amount)
+    }
+
+    /// The ledger after closing a dispute, unless it overflows
+    fn closed(&self, funds: DisputedFunds, amount: MoneyType) -> Option<Self> {
+        self.moved(-1, funds, amount.checked_neg()?)
+    }
+
+    /// The ledger after opening (or closing) disputes which hold no funds
+    fn counted(&self, disputes: i64) -> Option<Self> {
+        let mut ledger = *self;
+
+        ledger.open_disputes = ledger.open_disputes.checked_add(disputes)?;
+
+        Some(ledger)
+    }
+
+    fn moved(&self, disputes: i64, funds: DisputedFunds, amount: MoneyType) -> Option<Self> {
+        let mut ledger = *self;
+
+        ledger.open_disputes = ledger.open_disputes.checked_add(disputes)?;
+
+        let held = match funds {
+            DisputedFunds::Deposited => &mut ledger.held_for_deposits,
+            DisputedFunds::Withdrawn => &mut ledger.held_for_withdrawals,
+        };
+
+        *held = held.checked_add(amount)?;
+
+        Some(ledger)
+    }
+
+    /// The change from the given ledger to this one
+    pub fn since(&self, previous: &DisputeLedger) -> DisputeLedger {
+        DisputeLedger {
+            open_disputes: self.open_disputes - previous.open_disputes,
+            held_for_deposits: self.held_for_deposits - previous.held_for_deposits,
+            held_for_withdrawals: self.held_for_withdrawals - previous.held_for_withdrawals,
+        }
+    }
+
+    /// Apply a change obtained from [`DisputeLedger::since`]
+    pub fn apply(&mut self, delta: &DisputeLedger) {
+        self.open_disputes += delta.open_disputes;
+        self.held_for_deposits += delta.held_for_deposits;
+        self.held_for_withdrawals += delta.held_for_withdrawals;
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Getters, CopyGetters, Clone, Debug)]
 pub struct Client {
     #[get_copy = "pub"]
     client_id: ClientID,
     #[get_copy = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
     available: MoneyType,
     #[get_copy = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
     held: MoneyType,
     #[get = "pub"]
     account_status: ClientAccountStatus,
+    /// The amount of deposits and withdrawals applied to this account
+    #[get_copy = "pub"]
+    transaction_count: u64,
+    /// Incremented on every change to this account, so writers can detect concurrent changes
+    #[get_copy = "pub"]
+    version: u64,
+    /// Why the held funds are being held
+    #[get = "pub"]
+    disputes: DisputeLedger,
+    /// How far below zero withdrawals can drive the available funds, if at all
+    #[get_copy = "pub"]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::models::money::serde_optional_amount")
+    )]
+    credit_limit: Option<MoneyType>,
 }
 
 impl Client {
@@ -32,26 +146,94 @@ impl Client {
         self.available + self.held
     }
 
-    pub fn deposit(&mut self, amount: MoneyType) -> Result<(), ClientOperationError> {
-        if let ClientAccountStatus::Frozen = self.account_status {
-            return Err(ClientOperationError::AccountFrozen);
+    /// Fail unless the account accepts transactions
+    fn check_active(&self) -> Result<(), ClientOperationError> {
+        match self.account_status {
+            ClientAccountStatus::Active => Ok(()),
+            ClientAccountStatus::Frozen => Err(ClientOperationError::AccountFrozen),
+            ClientAccountStatus::Closed => Err(ClientOperationError::AccountClosed),
+        }
+    }
+
+    /// Fail if the account was closed. Frozen accounts still settle their open disputes, as
+    /// the chargeback which froze them would otherwise leave the others open for good.
+    fn check_settleable(&self) -> Result<(), ClientOperationError> {
+        match self.account_status {
+            ClientAccountStatus::Closed => Err(ClientOperationError::AccountClosed),
+            ClientAccountStatus::Active | ClientAccountStatus::Frozen => Ok(()),
         }
+    }
+
+    /// The balances after moving the given amounts into them, as long as neither the balances
+    /// nor their total overflow
+    fn checked_balances(
+        &self,
+        available_delta: MoneyType,
+        held_delta: MoneyType,
+    ) -> Option<(MoneyType, MoneyType)> {
+        let available = self.available.checked_add(available_delta)?;
+        let held = self.held.checked_add(held_delta)?;
 
-        self.available += amount;
+        available.checked_add(held)?;
+
+        Some((available, held))
+    }
+
+    pub fn deposit(&mut self, amount: MoneyType) -> Result<(), ClientOperationError> {
+        self.check_active()?;
+
+        let (available, _) = self
+            .checked_balances(amount, MoneyType::ZERO)
+            .ok_or(DepositFundsError::Overflow(self.available, amount))?;
+
+        self.available = available;
+        self.transaction_count += 1;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            available = self.available.raw(),
+            "Deposited funds"
+        );
 
         Ok(())
     }
 
     pub fn withdraw(&mut self, amount: MoneyType) -> Result<(), ClientOperationError> {
-        if let ClientAccountStatus::Frozen = self.account_status {
-            return Err(ClientOperationError::AccountFrozen);
-        }
+        self.check_active()?;
 
-        if amount >= self.available {
-            return Err(WithdrawFundsError::NotEnoughFunds(self.available, amount).into());
+        match self.credit_limit {
+            None if amount > self.available => {
+                return Err(WithdrawFundsError::NotEnoughFunds(self.available, amount).into());
+            }
+            // Accounts with a credit line can go below zero, down to minus their limit
+            Some(limit)
+                if self
+                    .available
+                    .checked_add(limit)
+                    .is_some_and(|spendable| amount > spendable) =>
+            {
+                return Err(
+                    WithdrawFundsError::CreditLimitExceeded(self.available, amount, limit).into(),
+                );
+            }
+            _ => {}
         }
 
-        self.available -= amount;
+        let (available, _) = amount
+            .checked_neg()
+            .and_then(|amount| self.checked_balances(amount, MoneyType::ZERO))
+            .ok_or(WithdrawFundsError::Overflow(self.available, amount))?;
+
+        self.available = available;
+        self.transaction_count += 1;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            available = self.available.raw(),
+            "Withdrew funds"
+        );
 
         Ok(())
     }
@@ -62,13 +244,31 @@ impl Client {
         &mut self,
         amount: MoneyType,
     ) -> Result<(), ClientOperationError> {
-        if let ClientAccountStatus::Frozen = self.account_status {
-            return Err(ClientOperationError::AccountFrozen);
-        }
+        self.check_active()?;
 
         // When disputing deposited funds, we allow the available funds to go negative
-        self.available -= amount;
-        self.held += amount;
+        let overflow = DisputeFundsError::Overflow(self.held, amount);
+
+        let (available, held) = amount
+            .checked_neg()
+            .and_then(|negated| self.checked_balances(negated, amount))
+            .ok_or_else(|| overflow.clone())?;
+
+        let disputes = self
+            .disputes
+            .opened(DisputedFunds::Deposited, amount)
+            .ok_or(overflow)?;
+
+        self.available = available;
+        self.held = held;
+        self.disputes = disputes;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            held = self.held.raw(),
+            "Disputed deposited funds"
+        );
 
         Ok(())
     }
@@ -79,69 +279,518 @@ impl Client {
         &mut self,
         amount: MoneyType,
     ) -> Result<(), ClientOperationError> {
-        if let ClientAccountStatus::Frozen = self.account_status {
-            return Err(ClientOperationError::AccountFrozen);
-        }
+        self.check_active()?;
+
+        let overflow = DisputeFundsError::Overflow(self.held, amount);
+
+        let (_, held) = self
+            .checked_balances(MoneyType::ZERO, amount)
+            .ok_or_else(|| overflow.clone())?;
+
+        let disputes = self
+            .disputes
+            .opened(DisputedFunds::Withdrawn, amount)
+            .ok_or(overflow)?;
+
+        self.held = held;
+        self.disputes = disputes;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            held = self.held.raw(),
+            "Disputed withdrawn funds"
+        );
+
+        Ok(())
+    }
+
+    /// When provisionally crediting disputed withdrawn funds, the client gets them back right
+    /// away instead of having them held, so nothing is held for this dispute
+    pub fn credit_withdrawn_funds(
+        &mut self,
+        amount: MoneyType,
+    ) -> Result<(), ClientOperationError> {
+        self.check_active()?;
+
+        let overflow = DisputeFundsError::Overflow(self.held, amount);
+
+        let (available, _) = self
+            .checked_balances(amount, MoneyType::ZERO)
+            .ok_or_else(|| overflow.clone())?;
+
+        let disputes = self.disputes.counted(1).ok_or(overflow)?;
+
+        self.available = available;
+        self.disputes = disputes;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            available = self.available.raw(),
+            "Provisionally credited withdrawn funds"
+        );
+
+        Ok(())
+    }
+
+    /// Resolving a provisionally credited dispute makes the credit final, so only the dispute
+    /// is closed
+    pub fn confirm_credited_funds(&mut self) -> Result<(), ClientOperationError> {
+        self.check_settleable()?;
+
+        self.disputes = self
+            .disputes
+            .counted(-1)
+            .ok_or(ResolveError::Overflow(self.held, MoneyType::ZERO))?;
 
-        self.held += amount;
+        tracing::trace!(client = %self.client_id, "Confirmed credited funds");
+
+        Ok(())
+    }
+
+    /// Charging back a provisionally credited dispute takes the credit back, freezing the
+    /// account like any other chargeback
+    pub fn reverse_credited_funds(
+        &mut self,
+        amount: MoneyType,
+    ) -> Result<(), ClientOperationError> {
+        self.check_settleable()?;
+
+        let overflow = ChargeBackError::Overflow(self.held, amount);
+
+        let (available, _) = amount
+            .checked_neg()
+            .and_then(|amount| self.checked_balances(amount, MoneyType::ZERO))
+            .ok_or_else(|| overflow.clone())?;
+
+        let disputes = self.disputes.counted(-1).ok_or(overflow)?;
+
+        self.available = available;
+        self.account_status = ClientAccountStatus::Frozen;
+        self.disputes = disputes;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            available = self.available.raw(),
+            "Reversed credited funds"
+        );
 
         Ok(())
     }
 
     /// Charge back a given amount of funds, this will move the funds from the held
-    pub fn chargeback_funds(&mut self, amount: MoneyType) -> Result<(), ClientOperationError> {
-        if let ClientAccountStatus::Frozen = self.account_status {
-            return Err(ClientOperationError::AccountFrozen);
-        }
+    pub fn chargeback_funds(
+        &mut self,
+        funds: DisputedFunds,
+        amount: MoneyType,
+    ) -> Result<(), ClientOperationError> {
+        self.check_settleable()?;
 
         if self.held < amount {
             return Err(ChargeBackError::NotEnoughHeldFunds(self.held, amount).into());
         }
 
-        self.held -= amount;
+        let overflow = ChargeBackError::Overflow(self.held, amount);
+
+        let (_, held) = amount
+            .checked_neg()
+            .and_then(|amount| self.checked_balances(MoneyType::ZERO, amount))
+            .ok_or_else(|| overflow.clone())?;
+
+        let disputes = self.disputes.closed(funds, amount).ok_or(overflow)?;
+
+        self.held = held;
         self.account_status = ClientAccountStatus::Frozen;
+        self.disputes = disputes;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            held = self.held.raw(),
+            "Charged back funds"
+        );
+
+        Ok(())
+    }
+
+    /// Return the funds taken by a chargeback to the client, once the chargeback has been
+    /// reversed by a representment.
+    ///
+    /// Frozen accounts accept this, as the chargeback being reversed is usually what froze
+    /// them. Whether to reactivate the account is up to the caller.
+    pub fn represent_funds(&mut self, amount: MoneyType) -> Result<(), ClientOperationError> {
+        if let ClientAccountStatus::Closed = self.account_status {
+            return Err(ClientOperationError::AccountClosed);
+        }
+
+        let (available, _) = self
+            .checked_balances(amount, MoneyType::ZERO)
+            .ok_or(RepresentmentError::Overflow(self.available, amount))?;
+
+        self.available = available;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            available = self.available.raw(),
+            "Represented charged back funds"
+        );
+
+        Ok(())
+    }
+
+    /// Reserve funds for an authorization, moving them from the available funds to the held
+    /// ones, where they stay until the authorization is captured.
+    ///
+    /// The held funds of authorizations are not part of the dispute ledger.
+    pub fn authorize(&mut self, amount: MoneyType) -> Result<(), ClientOperationError> {
+        self.check_active()?;
+
+        if amount > self.available {
+            return Err(AuthorizeError::NotEnoughFunds(self.available, amount).into());
+        }
+
+        let (available, held) = amount
+            .checked_neg()
+            .and_then(|negated| self.checked_balances(negated, amount))
+            .ok_or(AuthorizeError::Overflow(self.held, amount))?;
+
+        self.available = available;
+        self.held = held;
+        self.transaction_count += 1;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            held = self.held.raw(),
+            "Authorized funds"
+        );
 
         Ok(())
     }
 
-    pub fn resolve_funds(&mut self, amount: MoneyType) -> Result<(), ClientOperationError> {
-        if let ClientAccountStatus::Frozen = self.account_status {
-            return Err(ClientOperationError::AccountFrozen);
+    /// Capture the funds held by an authorization, which leave the account just like a
+    /// withdrawal.
+    ///
+    /// The authorization was already counted as a transaction of the client, so the capture
+    /// isn't counted again.
+    pub fn capture_authorized_funds(
+        &mut self,
+        amount: MoneyType,
+    ) -> Result<(), ClientOperationError> {
+        self.check_active()?;
+
+        if self.held < amount {
+            return Err(CaptureError::NotEnoughHeldFunds(self.held, amount).into());
+        }
+
+        let (_, held) = amount
+            .checked_neg()
+            .and_then(|negated| self.checked_balances(MoneyType::ZERO, negated))
+            .ok_or(CaptureError::Overflow(self.held, amount))?;
+
+        self.held = held;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            held = self.held.raw(),
+            "Captured authorized funds"
+        );
+
+        Ok(())
+    }
+
+    /// Give the funds held by an expired authorization back to the client.
+    ///
+    /// Frozen accounts accept this, as the expiry is not something the client does.
+    pub fn release_authorized_funds(
+        &mut self,
+        amount: MoneyType,
+    ) -> Result<(), ClientOperationError> {
+        if let ClientAccountStatus::Closed = self.account_status {
+            return Err(ClientOperationError::AccountClosed);
         }
 
+        if self.held < amount {
+            return Err(ReleaseError::NotEnoughHeldFunds(self.held, amount).into());
+        }
+
+        let (available, held) = amount
+            .checked_neg()
+            .and_then(|negated| self.checked_balances(amount, negated))
+            .ok_or(ReleaseError::Overflow(self.available, amount))?;
+
+        self.available = available;
+        self.held = held;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            held = self.held.raw(),
+            "Released authorized funds"
+        );
+
+        Ok(())
+    }
+
+    /// Charge a fee for one of the client's transactions, taking it from the available funds.
+    ///
+    /// The fee is charged even when the available funds don't cover it, leaving the account
+    /// owing the rest, so a fee never stops the transaction it is charged for. Frozen accounts
+    /// accept this, as a chargeback (the usual reason for freezing them) can have its own fee.
+    pub fn charge_fee(&mut self, fee: MoneyType) -> Result<(), ClientOperationError> {
+        if let ClientAccountStatus::Closed = self.account_status {
+            return Err(ClientOperationError::AccountClosed);
+        }
+
+        let (available, _) = fee
+            .checked_neg()
+            .and_then(|negated| self.checked_balances(negated, MoneyType::ZERO))
+            .ok_or(FeeError::Overflow(self.available, fee))?;
+
+        self.available = available;
+
+        tracing::trace!(
+            client = %self.client_id,
+            fee = fee.raw(),
+            available = self.available.raw(),
+            "Charged a fee"
+        );
+
+        Ok(())
+    }
+
+    /// Credit a fee charged to another client to this account, which collects the fees
+    pub fn collect_fee(&mut self, fee: MoneyType) -> Result<(), ClientOperationError> {
+        if let ClientAccountStatus::Closed = self.account_status {
+            return Err(ClientOperationError::AccountClosed);
+        }
+
+        let (available, _) = self
+            .checked_balances(fee, MoneyType::ZERO)
+            .ok_or(FeeError::Overflow(self.available, fee))?;
+
+        self.available = available;
+
+        tracing::trace!(
+            client = %self.client_id,
+            fee = fee.raw(),
+            available = self.available.raw(),
+            "Collected a fee"
+        );
+
+        Ok(())
+    }
+
+    /// Reactivate the account, once the reason it was frozen for has been dealt with
+    pub fn unfreeze(&mut self) -> Result<(), ClientOperationError> {
+        match self.account_status {
+            ClientAccountStatus::Frozen => {}
+            ClientAccountStatus::Active => return Err(ClientOperationError::AccountNotFrozen),
+            ClientAccountStatus::Closed => return Err(ClientOperationError::AccountClosed),
+        }
+
+        self.account_status = ClientAccountStatus::Active;
+
+        tracing::trace!(client = %self.client_id, "Unfroze the account");
+
+        Ok(())
+    }
+
+    /// Close the account for good, which is only possible once it holds no funds
+    pub fn close(&mut self) -> Result<(), ClientOperationError> {
+        if let ClientAccountStatus::Closed = self.account_status {
+            return Err(ClientOperationError::AccountClosed);
+        }
+
+        if self.available != MoneyType::ZERO || self.held != MoneyType::ZERO {
+            return Err(ClientOperationError::AccountNotEmpty(
+                self.available,
+                self.held,
+            ));
+        }
+
+        self.account_status = ClientAccountStatus::Closed;
+
+        tracing::trace!(client = %self.client_id, "Closed the account");
+
+        Ok(())
+    }
+
+    /// Apply the effects which were decided for this client.
+    ///
+    /// The effects have already been checked against all of the client's invariants when
+    /// they were decided, so applying them can't fail.
+    pub fn apply_effects(&mut self, effects: &Effects) {
+        self.available += effects.available_delta;
+        self.held += effects.held_delta;
+        self.transaction_count += effects.transaction_count_delta;
+        self.disputes.apply(&effects.disputes_delta);
+
+        if let Some(status) = effects.status_change {
+            self.account_status = status;
+        }
+
+        self.version += 1;
+
+        tracing::debug!(
+            client = %self.client_id,
+            available = self.available.raw(),
+            held = self.held.raw(),
+            status = ?self.account_status,
+            version = self.version,
+            "Applied the effects of a transaction"
+        );
+    }
+
+    /// Grant the account a credit line of the given limit, or take it away with `None`.
+    ///
+    /// Funds which are already owed are kept, even if they exceed the new limit.
+    pub fn set_credit_limit(&mut self, credit_limit: Option<MoneyType>) {
+        self.credit_limit = credit_limit;
+    }
+
+    /// Change the status of the account directly, outside of the transaction processing
+    /// (e.g. an administrator unfreezing an account)
+    pub fn set_account_status(&mut self, status: ClientAccountStatus) {
+        self.account_status = status;
+        self.version += 1;
+
+        tracing::debug!(client = %self.client_id, status = ?status, "Changed the account status");
+    }
+
+    /// Mark this client as being the version after the given one
+    pub(crate) fn succeed(&mut self, version: u64) {
+        self.version = version + 1;
+    }
+
+    /// Undo the changes which took this client from `before` to `after`, keeping whatever
+    /// changed since then, as a new version.
+    ///
+    /// The status is only restored if it is still the one it was changed to.
+    pub(crate) fn revert(&mut self, before: &Client, after: &Client) {
+        self.available -= after.available - before.available;
+        self.held -= after.held - before.held;
+        self.transaction_count -= after.transaction_count - before.transaction_count;
+        self.disputes.apply(&before.disputes.since(&after.disputes));
+
+        if self.account_status == after.account_status {
+            self.account_status = before.account_status;
+        }
+
+        self.version += 1;
+    }
+
+    pub fn resolve_funds(
+        &mut self,
+        funds: DisputedFunds,
+        amount: MoneyType,
+    ) -> Result<(), ClientOperationError> {
+        self.check_settleable()?;
+
         if self.held < amount {
             return Err(ResolveError::NotEnoughHeldFunds(self.held, amount).into());
         }
 
-        self.held -= amount;
-        self.available += amount;
+        let overflow = ResolveError::Overflow(self.held, amount);
+
+        let (available, held) = amount
+            .checked_neg()
+            .and_then(|negated| self.checked_balances(amount, negated))
+            .ok_or_else(|| overflow.clone())?;
+
+        let disputes = self.disputes.closed(funds, amount).ok_or(overflow)?;
+
+        self.available = available;
+        self.held = held;
+        self.disputes = disputes;
+
+        tracing::trace!(
+            client = %self.client_id,
+            amount = amount.raw(),
+            held = self.held.raw(),
+            "Resolved funds"
+        );
 
         Ok(())
     }
 }
 
-#[derive(Error, Debug)]
-pub enum DepositFundsError {}
+#[derive(Error, Debug, Clone)]
+pub enum DepositFundsError {
+    #[error("Depositing {1:?} would overflow the balance of the account (Available {0:?})")]
+    Overflow(MoneyType, MoneyType),
+}
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum WithdrawFundsError {
     #[error("The account does not have enough funds ({0:?} while trying to withdraw {1:?})")]
     NotEnoughFunds(MoneyType, MoneyType),
+    #[error("Withdrawing {1:?} would overflow the balance of the account (Available {0:?})")]
+    Overflow(MoneyType, MoneyType),
+    #[error("Withdrawing {1:?} would exceed the credit limit of the account (Available {0:?}, Limit {2:?})")]
+    CreditLimitExceeded(MoneyType, MoneyType, MoneyType),
 }
 
-#[derive(Error, Debug)]
-pub enum DisputeFundsError {}
+#[derive(Error, Debug, Clone)]
+pub enum DisputeFundsError {
+    #[error("Disputing {1:?} would overflow the balance of the account (Held {0:?})")]
+    Overflow(MoneyType, MoneyType),
+}
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum ChargeBackError {
     #[error("Attempting to charge back a larger amount than what is held. Held value: {0:?} charging back {1:?}")]
     NotEnoughHeldFunds(MoneyType, MoneyType),
+    #[error("Charging back {1:?} would overflow the balance of the account (Held {0:?})")]
+    Overflow(MoneyType, MoneyType),
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum ResolveError {
     #[error("Attempting to resolve funds that are larger than the amount of funds that we are holding. Held value {0:?}, resolving {1:?}")]
     NotEnoughHeldFunds(MoneyType, MoneyType),
+    #[error("Resolving {1:?} would overflow the balance of the account (Held {0:?})")]
+    Overflow(MoneyType, MoneyType),
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum RepresentmentError {
+    #[error("Representing {1:?} would overflow the balance of the account (Available {0:?})")]
+    Overflow(MoneyType, MoneyType),
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum AuthorizeError {
+    #[error("The account does not have enough funds ({0:?} while trying to authorize {1:?})")]
+    NotEnoughFunds(MoneyType, MoneyType),
+    #[error("Authorizing {1:?} would overflow the balance of the account (Held {0:?})")]
+    Overflow(MoneyType, MoneyType),
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum CaptureError {
+    #[error("Attempting to capture a larger amount than what is held. Held value: {0:?} capturing {1:?}")]
+    NotEnoughHeldFunds(MoneyType, MoneyType),
+    #[error("Capturing {1:?} would overflow the balance of the account (Held {0:?})")]
+    Overflow(MoneyType, MoneyType),
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum ReleaseError {
+    #[error("Attempting to release a larger amount than what is held. Held value: {0:?} releasing {1:?}")]
+    NotEnoughHeldFunds(MoneyType, MoneyType),
+    #[error("Releasing {1:?} would overflow the balance of the account (Available {0:?})")]
+    Overflow(MoneyType, MoneyType),
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum FeeError {
+    #[error("A fee of {1:?} would overflow the balance of the account (Available {0:?})")]
+    Overflow(MoneyType, MoneyType),
 }
 
 /// A wrapper for all client errors, so they can be more easily propagated
@@ -150,6 +799,12 @@ pub enum ResolveError {
 pub enum ClientOperationError {
     #[error("Cannot deposit funds as the account is frozen")]
     AccountFrozen,
+    #[error("Cannot unfreeze the account as it is not frozen")]
+    AccountNotFrozen,
+    #[error("The account is closed")]
+    AccountClosed,
+    #[error("Cannot close the account as it still has funds (Available {0:?}, Held {1:?})")]
+    AccountNotEmpty(MoneyType, MoneyType),
     #[error("Deposit Error {0:?}")]
     DepositError(#[from] DepositFundsError),
     #[error("Withdraw Error {0:?}")]
@@ -160,6 +815,16 @@ pub enum ClientOperationError {
     ChargebackError(#[from] ChargeBackError),
     #[error("Resolve Error {0:?}")]
     ResolveError(#[from] ResolveError),
+    #[error("Representment Error {0:?}")]
+    RepresentmentError(#[from] RepresentmentError),
+    #[error("Authorize Error {0:?}")]
+    AuthorizeError(#[from] AuthorizeError),
+    #[error("Capture Error {0:?}")]
+    CaptureError(#[from] CaptureError),
+    #[error("Release Error {0:?}")]
+    ReleaseError(#[from] ReleaseError),
+    #[error("Fee Error {0:?}")]
+    FeeError(#[from] FeeError),
 }
 
 /// Using the type state builder pattern for compile type safety
@@ -172,6 +837,10 @@ pub struct ClientBuilder<CLID> {
     available: MoneyType,
     held: MoneyType,
     account_status: ClientAccountStatus,
+    transaction_count: u64,
+    version: u64,
+    disputes: DisputeLedger,
+    credit_limit: Option<MoneyType>,
 }
 
 impl<CLID> ClientBuilder<CLID> {
@@ -192,6 +861,30 @@ impl<CLID> ClientBuilder<CLID> {
 
         self
     }
+
+    pub fn with_transaction_count(mut self, transaction_count: u64) -> Self {
+        self.transaction_count = transaction_count;
+
+        self
+    }
+
+    pub fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+
+        self
+    }
+
+    pub fn with_disputes(mut self, disputes: DisputeLedger) -> Self {
+        self.disputes = disputes;
+
+        self
+    }
+
+    pub fn with_credit_limit(mut self, credit_limit: MoneyType) -> Self {
+        self.credit_limit = Some(credit_limit);
+
+        self
+    }
 }
 
 impl ClientBuilder<NoVal> {
@@ -201,6 +894,10 @@ impl ClientBuilder<NoVal> {
             available: self.available,
             held: self.held,
             account_status: self.account_status,
+            transaction_count: self.transaction_count,
+            version: self.version,
+            disputes: self.disputes,
+            credit_limit: self.credit_limit,
         }
     }
 }
@@ -212,6 +909,10 @@ impl ClientBuilder<ClientID> {
             available: self.available,
             held: self.held,
             account_status: self.account_status,
+            transaction_count: self.transaction_count,
+            version: self.version,
+            disputes: self.disputes,
+            credit_limit: self.credit_limit,
         }
     }
 }
@@ -223,83 +924,358 @@ impl Default for ClientBuilder<NoVal> {
             available: Default::default(),
             held: Default::default(),
             account_status: Default::default(),
+            transaction_count: Default::default(),
+            version: Default::default(),
+            disputes: Default::default(),
+            credit_limit: Default::default(),
         }
     }
 }
 
 #[cfg(test)]
 mod client_tests {
-    use crate::models::client::{Client, ClientAccountStatus};
+    use proptest::prelude::*;
+
+    use crate::models::client::{
+        AuthorizeError, Client, ClientAccountStatus, ClientOperationError, DepositFundsError,
+        DisputedFunds, WithdrawFundsError,
+    };
+    use crate::models::{ClientID, MoneyType};
 
     #[test]
     pub fn test_client_init() {
-        let client = Client::builder().with_client_id(1).build();
+        let _client = Client::builder().with_client_id(ClientID(1)).build();
     }
 
     #[test]
     pub fn test_negative_withdrawal() {
-        let mut client = Client::builder().with_client_id(1).build();
+        let mut client = Client::builder().with_client_id(ClientID(1)).build();
 
-        assert!(client.withdraw(1).is_err())
+        assert!(client.withdraw(MoneyType::new(1)).is_err())
     }
 
     #[test]
     pub fn test_frozen_movement() {
         let mut client = Client::builder()
-            .with_client_id(1)
-            .with_available(100)
-            .with_held(100)
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(100))
+            .with_held(MoneyType::new(100))
             .with_account_status(ClientAccountStatus::Frozen)
             .build();
 
-        assert!(client.withdraw(1).is_err());
-        assert!(client.deposit(1).is_err());
+        assert!(client.withdraw(MoneyType::new(1)).is_err());
+        assert!(client.deposit(MoneyType::new(1)).is_err());
     }
 
     #[test]
     pub fn test_overflow_held() {
-        let mut client = Client::builder().with_client_id(1).build();
+        let mut client = Client::builder().with_client_id(ClientID(1)).build();
 
-        assert!(client.resolve_funds(100).is_err());
-        assert!(client.chargeback_funds(100).is_err());
+        assert!(client
+            .resolve_funds(DisputedFunds::Deposited, MoneyType::new(100))
+            .is_err());
+        assert!(client
+            .chargeback_funds(DisputedFunds::Deposited, MoneyType::new(100))
+            .is_err());
     }
 
     #[test]
     pub fn test_resolved_dispute() {
-        let mut client = Client::builder().with_client_id(1).build();
+        let mut client = Client::builder().with_client_id(ClientID(1)).build();
 
-        client.deposit(100).unwrap();
+        client.deposit(MoneyType::new(100)).unwrap();
 
-        client.dispute_deposited_funds(100).unwrap();
+        client.dispute_deposited_funds(MoneyType::new(100)).unwrap();
 
-        assert_eq!(client.available(), 0);
-        assert_eq!(client.held(), 100);
+        assert_eq!(client.available(), MoneyType::new(0));
+        assert_eq!(client.held(), MoneyType::new(100));
 
-        client.resolve_funds(100).unwrap();
+        assert_eq!(client.disputes().open_disputes(), 1);
+        assert_eq!(client.disputes().held_for_deposits(), MoneyType::new(100));
 
-        assert_eq!(client.available(), 100);
-        assert_eq!(client.held(), 0);
-        assert_eq!(client.total(), 100);
+        client
+            .resolve_funds(DisputedFunds::Deposited, MoneyType::new(100))
+            .unwrap();
+
+        assert_eq!(client.disputes().open_disputes(), 0);
+        assert_eq!(client.disputes().held_for_deposits(), MoneyType::new(0));
+        assert_eq!(client.available(), MoneyType::new(100));
+        assert_eq!(client.held(), MoneyType::new(0));
+        assert_eq!(client.total(), MoneyType::new(100));
     }
 
     #[test]
     pub fn test_charged_back_dispute() {
-        let mut client = Client::builder().with_client_id(1).build();
+        let mut client = Client::builder().with_client_id(ClientID(1)).build();
 
-        client.deposit(100).unwrap();
+        client.deposit(MoneyType::new(100)).unwrap();
 
-        client.dispute_deposited_funds(100).unwrap();
+        client.dispute_deposited_funds(MoneyType::new(100)).unwrap();
 
-        assert_eq!(client.available(), 0);
-        assert_eq!(client.held(), 100);
+        assert_eq!(client.available(), MoneyType::new(0));
+        assert_eq!(client.held(), MoneyType::new(100));
 
-        client.chargeback_funds(100).unwrap();
+        client
+            .chargeback_funds(DisputedFunds::Deposited, MoneyType::new(100))
+            .unwrap();
 
-        assert_eq!(client.available(), 0);
-        assert_eq!(client.held(), 0);
-        assert_eq!(client.total(), 0);
-        if let ClientAccountStatus::Frozen = client.account_status() {
+        assert_eq!(client.available(), MoneyType::new(0));
+        assert_eq!(client.held(), MoneyType::new(0));
+        assert_eq!(client.total(), MoneyType::new(0));
+        if let ClientAccountStatus::Active = client.account_status() {
             panic!("Account should be frozen")
         }
     }
+
+    #[test]
+    pub fn test_authorized_funds() {
+        let mut client = Client::builder().with_client_id(ClientID(1)).build();
+
+        client.deposit(MoneyType::new(100)).unwrap();
+
+        assert!(matches!(
+            client.authorize(MoneyType::new(150)),
+            Err(ClientOperationError::AuthorizeError(
+                AuthorizeError::NotEnoughFunds(..)
+            ))
+        ));
+
+        client.authorize(MoneyType::new(60)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(40));
+        assert_eq!(client.held(), MoneyType::new(60));
+        assert_eq!(client.disputes().open_disputes(), 0);
+
+        client.capture_authorized_funds(MoneyType::new(60)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(40));
+        assert_eq!(client.held(), MoneyType::new(0));
+        assert_eq!(client.transaction_count(), 2);
+
+        client.authorize(MoneyType::new(30)).unwrap();
+        client.set_account_status(ClientAccountStatus::Frozen);
+
+        // Expired holds are given back even to frozen accounts
+        client.release_authorized_funds(MoneyType::new(30)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(40));
+        assert_eq!(client.held(), MoneyType::new(0));
+    }
+
+    #[test]
+    pub fn test_credited_dispute() {
+        let mut client = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(100))
+            .build();
+
+        client.credit_withdrawn_funds(MoneyType::new(40)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(140));
+        assert_eq!(client.held(), MoneyType::new(0));
+        assert_eq!(client.disputes().open_disputes(), 1);
+
+        let mut resolved = client.clone();
+
+        resolved.confirm_credited_funds().unwrap();
+
+        assert_eq!(resolved.available(), MoneyType::new(140));
+        assert_eq!(resolved.disputes().open_disputes(), 0);
+
+        client.reverse_credited_funds(MoneyType::new(40)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(100));
+        assert_eq!(client.disputes().open_disputes(), 0);
+        assert_eq!(*client.account_status(), ClientAccountStatus::Frozen);
+    }
+
+    #[test]
+    pub fn test_overflowing_deposit() {
+        let mut client = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::MAX - MoneyType::new(10))
+            .build();
+
+        assert!(matches!(
+            client.deposit(MoneyType::new(11)),
+            Err(ClientOperationError::DepositError(
+                DepositFundsError::Overflow(..)
+            ))
+        ));
+
+        assert_eq!(client.available(), MoneyType::MAX - MoneyType::new(10));
+        assert_eq!(client.transaction_count(), 0);
+
+        client.deposit(MoneyType::new(10)).unwrap();
+
+        // The held funds count towards the total, which must not overflow either
+        assert!(client.dispute_withdrawn_funds(MoneyType::new(1)).is_err());
+        assert_eq!(client.held(), MoneyType::new(0));
+    }
+
+    #[test]
+    pub fn test_credit_limit() {
+        let mut client = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(50))
+            .with_credit_limit(MoneyType::new(100))
+            .build();
+
+        client.withdraw(MoneyType::new(120)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(-70));
+
+        assert!(matches!(
+            client.withdraw(MoneyType::new(31)),
+            Err(ClientOperationError::WithdrawError(
+                WithdrawFundsError::CreditLimitExceeded(..)
+            ))
+        ));
+
+        // The whole credit line can be used
+        client.withdraw(MoneyType::new(30)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(-100));
+        assert_eq!(client.transaction_count(), 2);
+
+        // Taking the credit line away keeps what is owed, but blocks further withdrawals
+        client.set_credit_limit(None);
+        client.deposit(MoneyType::new(150)).unwrap();
+
+        assert!(client.withdraw(MoneyType::new(51)).is_err());
+        assert_eq!(client.available(), MoneyType::new(50));
+    }
+
+    #[test]
+    pub fn test_withdrawal_boundaries() {
+        let mut client = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(50))
+            .build();
+
+        // Without a credit line, the whole of the available funds can be withdrawn
+        assert!(matches!(
+            client.withdraw(MoneyType::new(51)),
+            Err(ClientOperationError::WithdrawError(
+                WithdrawFundsError::NotEnoughFunds(..)
+            ))
+        ));
+
+        client.withdraw(MoneyType::new(50)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(0));
+
+        // With one, down to exactly minus the limit
+        client.set_credit_limit(Some(MoneyType::new(100)));
+
+        assert!(matches!(
+            client.withdraw(MoneyType::new(101)),
+            Err(ClientOperationError::WithdrawError(
+                WithdrawFundsError::CreditLimitExceeded(..)
+            ))
+        ));
+
+        client.withdraw(MoneyType::new(100)).unwrap();
+
+        assert_eq!(client.available(), MoneyType::new(-100));
+    }
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Deposit(MoneyType),
+        Withdraw(MoneyType),
+        DisputeDeposited(MoneyType),
+        DisputeWithdrawn(MoneyType),
+        Resolve(DisputedFunds, MoneyType),
+        Chargeback(DisputedFunds, MoneyType),
+        Represent(MoneyType),
+    }
+
+    fn amount() -> impl Strategy<Value = MoneyType> {
+        prop_oneof![
+            0..1_000_000i64,
+            i64::MAX - 1_000_000..=i64::MAX,
+            any::<i64>(),
+        ]
+        .prop_map(MoneyType::new)
+    }
+
+    fn disputed_funds() -> impl Strategy<Value = DisputedFunds> {
+        prop_oneof![
+            Just(DisputedFunds::Deposited),
+            Just(DisputedFunds::Withdrawn)
+        ]
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            4 => amount().prop_map(Operation::Deposit),
+            2 => amount().prop_map(Operation::Withdraw),
+            2 => amount().prop_map(Operation::DisputeDeposited),
+            2 => amount().prop_map(Operation::DisputeWithdrawn),
+            1 => (disputed_funds(), amount()).prop_map(|(funds, amount)| Operation::Resolve(funds, amount)),
+            1 => (disputed_funds(), amount()).prop_map(|(funds, amount)| Operation::Chargeback(funds, amount)),
+            1 => amount().prop_map(Operation::Represent),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_total_never_wraps(
+            available in amount(),
+            operations in prop::collection::vec(operation(), 1..50),
+        ) {
+            let mut client = Client::builder()
+                .with_client_id(ClientID(1))
+                .with_available(available)
+                .build();
+
+            for operation in operations {
+                let before = (client.available(), client.held());
+
+                let result = match operation {
+                    Operation::Deposit(amount) => client.deposit(amount),
+                    Operation::Withdraw(amount) => client.withdraw(amount),
+                    Operation::DisputeDeposited(amount) => client.dispute_deposited_funds(amount),
+                    Operation::DisputeWithdrawn(amount) => client.dispute_withdrawn_funds(amount),
+                    Operation::Resolve(funds, amount) => client.resolve_funds(funds, amount),
+                    Operation::Chargeback(funds, amount) => client.chargeback_funds(funds, amount),
+                    Operation::Represent(amount) => client.represent_funds(amount),
+                };
+
+                // A rejected operation leaves the balances untouched
+                if result.is_err() {
+                    prop_assert_eq!((client.available(), client.held()), before);
+                }
+
+                prop_assert!(client.available().checked_add(client.held()).is_some());
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_clients() {
+        let mut client = Client::builder().with_client_id(ClientID(1)).build();
+
+        client.deposit(MoneyType::new(20000)).unwrap();
+        client
+            .dispute_deposited_funds(MoneyType::new(5000))
+            .unwrap();
+        client.set_account_status(ClientAccountStatus::Frozen);
+
+        let json = serde_json::to_value(&client).unwrap();
+
+        assert_eq!(json["available"], "1.5000");
+        assert_eq!(json["account_status"], "frozen");
+        assert_eq!(json["disputes"]["held_for_deposits"], "0.5000");
+
+        let restored = serde_json::from_value::<Client>(json).unwrap();
+
+        assert_eq!(restored.available(), client.available());
+        assert_eq!(restored.held(), client.held());
+        assert_eq!(restored.account_status(), client.account_status());
+        assert_eq!(restored.disputes(), client.disputes());
+        assert_eq!(restored.version(), client.version());
+    }
 }
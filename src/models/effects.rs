@@ -0,0 +1,81 @@
+use crate::events::DomainEvent;
+use crate::models::client::{ClientAccountStatus, DisputeLedger};
+use crate::models::transactions::{DisputeMode, Transaction};
+use crate::models::{ClientID, MoneyType};
+
+/// The outcome of deciding on a transaction, describing every change that has to be
+/// applied to the state of the system, without actually applying any of it.
+///
+/// Since all of the business rules have already been checked when producing the effects,
+/// applying them can't violate any of the model's invariants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Effects {
+    /// The client targeted by the transaction
+    pub client: ClientID,
+    /// The change to the client's available funds
+    pub available_delta: MoneyType,
+    /// The change to the client's held funds
+    pub held_delta: MoneyType,
+    /// The new status of the client's account, if it changed
+    pub status_change: Option<ClientAccountStatus>,
+    /// The amount of deposits and withdrawals applied to the client
+    pub transaction_count_delta: u64,
+    /// The change to the client's open disputes and the funds held because of them
+    pub disputes_delta: DisputeLedger,
+    /// The change to the stored transactions
+    pub transaction_change: TransactionChange,
+    /// The domain events describing these effects
+    pub events: Vec<DomainEvent>,
+}
+
+impl Effects {
+    /// The transaction which produced these effects
+    pub fn transaction(&self) -> &Transaction {
+        match &self.transaction_change {
+            TransactionChange::Store(transaction)
+            | TransactionChange::OpenDispute(transaction, _)
+            | TransactionChange::SettleDispute(transaction)
+            | TransactionChange::ReverseChargeback(transaction)
+            | TransactionChange::Capture(transaction)
+            | TransactionChange::Expire(transaction)
+            | TransactionChange::Credit(transaction)
+            | TransactionChange::CollectFee(transaction)
+            | TransactionChange::Administer(transaction) => transaction,
+        }
+    }
+}
+
+/// Whether the effects of transactions are actually applied to the state of the system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutionMode {
+    #[default]
+    Apply,
+    /// Decide on the effects of each transaction, without applying any of them
+    DryRun,
+}
+
+/// The changes a transaction can make to the stored transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionChange {
+    /// Store a new transaction, which is an entity in its own right
+    Store(Transaction),
+    /// Attach the given dispute to the stored transaction it disputes, moving the funds with
+    /// the given mode
+    OpenDispute(Transaction, DisputeMode),
+    /// Settle the dispute of the stored transaction with the given resolution
+    SettleDispute(Transaction),
+    /// Reverse the chargeback of the stored transaction with the given representment
+    ReverseChargeback(Transaction),
+    /// Capture the stored authorization with the given capture
+    Capture(Transaction),
+    /// Expire the given authorization, which was not captured in time
+    Expire(Transaction),
+    /// Credit the destination of the given transfer, which is stored along with the debit
+    /// of its source, so there is nothing to change
+    Credit(Transaction),
+    /// Credit the fee charged for the given transaction to the account collecting the fees,
+    /// which changes nothing about the transaction itself
+    CollectFee(Transaction),
+    /// Nothing to change, as administrative transactions only act on the client
+    Administer(Transaction),
+}
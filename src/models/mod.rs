@@ -1,24 +1,83 @@
 pub mod client;
+pub mod effects;
+pub mod money;
 pub mod transactions;
 
-/// General type declarations, so when we want to change them, we can just change them in one spot,
-/// instead of having to deal with changing it everywhere.
-///
-/// This breaks a bit of the containment generally found in models, but in my opinion makes the
-/// code much more maintainable
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
 
-/// The type of client ids
-pub type ClientID = u16;
+pub use money::MoneyType;
 
-/// The type of transaction ids
-pub type TransactionID = u32;
+/// Declare an id type wrapping the given integer, so ids of different entities can't be
+/// mixed up with each other or with any other number
+macro_rules! id_type {
+    ($(#[$attr:meta])* $name:ident($int:ty)) => {
+        $(#[$attr])*
+        #[cfg_attr(
+            feature = "serde",
+            derive(serde::Serialize, serde::Deserialize),
+            serde(transparent)
+        )]
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Default, Clone, Copy, Debug)]
+        pub struct $name(pub $int);
 
-/// The type for the amounts transacted in the system
-/// Use regular longs as floats have precision misshapes even
-/// with 64 bits which can lead to non precise accounts.
-/// Instead, we multiply the float by the precision we want and then
-/// use the long version in every
-pub type MoneyType = i64;
+        impl From<$int> for $name {
+            fn from(id: $int) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<$name> for $int {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(id: $name) -> Self {
+                id.0.into()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseIntError;
+
+            fn from_str(id: &str) -> Result<Self, Self::Err> {
+                id.parse().map(Self)
+            }
+        }
+    };
+}
+
+// General type declarations, so when we want to change them, we can just change them in one spot,
+// instead of having to deal with changing it everywhere.
+//
+// This breaks a bit of the containment generally found in models, but in my opinion makes the
+// code much more maintainable
+
+id_type!(
+    /// The id of a client
+    ClientID(u16)
+);
+
+id_type!(
+    /// The id of a transaction
+    TransactionID(u32)
+);
+
+/// The type of the sequence numbers assigned to transactions, reflecting the order
+/// in which they were received
+pub type SequenceNumber = u64;
+
+/// The type of the timestamps of the transactions, in seconds since the unix epoch
+pub type Timestamp = u64;
 
 /// No value type for the type state builders,
 /// indicates that the corresponding field has not yet been filled
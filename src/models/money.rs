@@ -0,0 +1,838 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+use thiserror::Error;
+
+/// The type for the amounts transacted in the system
+/// Use regular longs as floats have precision misshapes even
+/// with 64 bits which can lead to non precise accounts.
+/// Instead, we multiply the float by the precision we want and then
+/// use the long version in every
+///
+/// It is signed on purpose: disputing a deposit which was already (partially) withdrawn leaves
+/// the account with negative available funds.
+///
+/// The wrapped value is in units of 10^-precision, where the precision is the one the run was
+/// configured with ([`FLOATING_POINT_ACC`](crate::FLOATING_POINT_ACC) by default). An amount
+/// doesn't know its precision, so it is given whenever one is parsed ([`MoneyType::parse`]) or
+/// displayed ([`MoneyType::display`]).
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Default, Clone, Copy, Debug)]
+pub struct MoneyType(i64);
+
+impl MoneyType {
+    pub const ZERO: Self = Self(0);
+    pub const MAX: Self = Self(i64::MAX);
+    pub const MIN: Self = Self(i64::MIN);
+
+    /// The amount with the given raw value, in units of 10^-precision
+    pub const fn new(raw: i64) -> Self {
+        Self(raw)
+    }
+
+    /// The raw value of the amount, in units of 10^-precision
+    pub const fn raw(self) -> i64 {
+        self.0
+    }
+
+    /// Same as [`parse_amount`]
+    pub fn parse(amount: &str, precision: u32) -> Result<Self, AmountParseError> {
+        parse_amount(amount, precision)
+    }
+
+    /// Display the amount with `precision` decimal places, as [`format_amount`] does
+    pub fn display(self, precision: u32) -> DisplayAmount {
+        DisplayAmount {
+            amount: self,
+            precision,
+        }
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        self.0.checked_neg().map(Self)
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+}
+
+impl From<i64> for MoneyType {
+    fn from(raw: i64) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<MoneyType> for i64 {
+    fn from(amount: MoneyType) -> Self {
+        amount.0
+    }
+}
+
+impl Add for MoneyType {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl Sub for MoneyType {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl Neg for MoneyType {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl AddAssign for MoneyType {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for MoneyType {
+    fn sub_assign(&mut self, other: Self) {
+        self.0 -= other.0;
+    }
+}
+
+impl Sum for MoneyType {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        Self(iter.map(|amount| amount.0).sum())
+    }
+}
+
+/// An amount along with the precision it is displayed with, see [`MoneyType::display`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayAmount {
+    amount: MoneyType,
+    precision: u32,
+}
+
+impl fmt::Display for DisplayAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_amount(self.amount, self.precision))
+    }
+}
+
+/// Parse a decimal amount into its fixed point representation, with `precision` decimal places.
+///
+/// This never goes through floating point, so an amount is either represented exactly or
+/// rejected. Amounts with more decimal places than `precision` are rejected instead of being
+/// silently truncated.
+pub fn parse_amount(amount: &str, precision: u32) -> Result<MoneyType, AmountParseError> {
+    let amount = amount.trim();
+
+    let (negative, digits) = match amount.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, amount.strip_prefix('+').unwrap_or(amount)),
+    };
+
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(AmountParseError::InvalidFormat(amount.to_string()));
+    }
+
+    if !whole
+        .bytes()
+        .chain(fraction.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return Err(AmountParseError::InvalidFormat(amount.to_string()));
+    }
+
+    if fraction.len() > precision as usize {
+        return Err(AmountParseError::ExcessPrecision(precision, fraction.len()));
+    }
+
+    let scale = scale_for(precision)?;
+
+    let whole_value = digits_to_money(whole)?
+        .checked_mul(scale)
+        .ok_or(AmountParseError::Overflow)?;
+
+    let fraction_value = digits_to_money(fraction)?
+        .checked_mul(scale_for(precision - fraction.len() as u32)?)
+        .ok_or(AmountParseError::Overflow)?;
+
+    let value = whole_value
+        .checked_add(fraction_value)
+        .ok_or(AmountParseError::Overflow)?;
+
+    Ok(MoneyType(if negative { -value } else { value }))
+}
+
+/// Same as [`parse_amount`], except amounts with more decimal places than `precision` are
+/// truncated to it instead of rejected, returning whether they had to be truncated.
+pub fn parse_truncated_amount(
+    amount: &str,
+    precision: u32,
+) -> Result<(MoneyType, bool), AmountParseError> {
+    match parse_amount(amount, precision) {
+        Err(AmountParseError::ExcessPrecision(..)) => {
+            // Only well formed amounts are checked for their precision
+            let (whole, fraction) = amount
+                .trim()
+                .split_once('.')
+                .expect("Only amounts with a fraction have decimal places");
+
+            let truncated = format!("{}.{}", whole, &fraction[..precision as usize]);
+
+            parse_amount(&truncated, precision).map(|amount| (amount, true))
+        }
+        result => result.map(|amount| (amount, false)),
+    }
+}
+
+/// Same as [`parse_amount`], except amounts with more decimal places than `precision` are
+/// rounded to the nearest amount with `precision` places, and to the even one of the two when
+/// exactly halfway between them (so `0.00005` rounds to `0.0000` and `0.00015` to `0.0002`),
+/// returning whether they had to be rounded.
+pub fn parse_rounded_amount(
+    amount: &str,
+    precision: u32,
+) -> Result<(MoneyType, bool), AmountParseError> {
+    let (truncated, rounded) = parse_truncated_amount(amount, precision)?;
+
+    if !rounded {
+        return Ok((truncated, false));
+    }
+
+    let (_, fraction) = amount
+        .trim()
+        .split_once('.')
+        .expect("Only amounts with a fraction have decimal places");
+
+    let mut dropped = fraction[precision as usize..].bytes();
+
+    let away_from_zero = match dropped.next() {
+        Some(b'5') if dropped.all(|digit| digit == b'0') => truncated.0 % 2 != 0,
+        Some(digit) => digit >= b'5',
+        None => false,
+    };
+
+    if !away_from_zero {
+        return Ok((truncated, true));
+    }
+
+    let step = if amount.trim().starts_with('-') {
+        -1
+    } else {
+        1
+    };
+
+    truncated
+        .0
+        .checked_add(step)
+        .map(|value| (MoneyType(value), true))
+        .ok_or(AmountParseError::Overflow)
+}
+
+/// What is done with the amounts which have more decimal places than the supported precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrecisionPolicy {
+    /// Drop the decimal places which are not supported, see [`parse_truncated_amount`]
+    #[default]
+    Truncate,
+    /// Round to the nearest supported amount, see [`parse_rounded_amount`]
+    RoundHalfEven,
+    /// Reject the amount, as [`parse_amount`] does
+    Reject,
+}
+
+impl PrecisionPolicy {
+    /// Parse the amount with [`parse_amount`], or the variant of it which applies this policy,
+    /// returning whether it had to be truncated or rounded
+    pub fn parse(
+        &self,
+        amount: &str,
+        precision: u32,
+    ) -> Result<(MoneyType, bool), AmountParseError> {
+        match self {
+            PrecisionPolicy::Truncate => parse_truncated_amount(amount, precision),
+            PrecisionPolicy::RoundHalfEven => parse_rounded_amount(amount, precision),
+            PrecisionPolicy::Reject => {
+                parse_amount(amount, precision).map(|amount| (amount, false))
+            }
+        }
+    }
+}
+
+/// How the amounts of an input are written, for the inputs produced with other locales, such
+/// as `1.234,56` (most of continental Europe) or `(1,234.56)` for a negative amount.
+///
+/// The default is the format of [`parse_amount`], with `.` as the decimal separator and
+/// without thousands separators. Thousands separators are only accepted between groups of
+/// three digits of the whole part, so an amount written with another format (e.g. `1.5` when
+/// `.` separates the thousands) is rejected instead of misread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountFormat {
+    decimal_separator: char,
+    thousands_separator: Option<char>,
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            thousands_separator: None,
+        }
+    }
+}
+
+impl AmountFormat {
+    /// The separators must differ from each other, and from the digits and signs
+    pub fn new(decimal_separator: char, thousands_separator: Option<char>) -> Self {
+        assert_ne!(
+            Some(decimal_separator),
+            thousands_separator,
+            "The decimal and thousands separators must differ"
+        );
+
+        Self {
+            decimal_separator,
+            thousands_separator,
+        }
+    }
+
+    /// `1.234,56`
+    pub fn european() -> Self {
+        Self::new(',', Some('.'))
+    }
+
+    /// Rewrite the amount in the format of [`parse_amount`]
+    fn normalize(&self, amount: &str) -> Result<String, AmountParseError> {
+        let invalid = || AmountParseError::InvalidFormat(amount.to_string());
+
+        let trimmed = amount.trim();
+
+        // Accounting exports write the negative amounts in parentheses
+        let (negative, unsigned) = match trimmed
+            .strip_prefix('(')
+            .and_then(|inner| inner.strip_suffix(')'))
+        {
+            Some(inner) if inner.trim().starts_with(['-', '+']) => return Err(invalid()),
+            Some(inner) => (true, inner.trim()),
+            None => (false, trimmed),
+        };
+
+        let (whole, fraction) = match unsigned.split_once(self.decimal_separator) {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (unsigned, None),
+        };
+
+        let whole = match self.thousands_separator {
+            Some(separator) if whole.contains(separator) => {
+                let (sign, digits) = match whole.strip_prefix(['-', '+']) {
+                    Some(digits) => (&whole[..1], digits),
+                    None => ("", whole),
+                };
+
+                let mut groups = digits.split(separator);
+
+                let first = groups.next().unwrap_or_default();
+
+                if first.is_empty() || first.len() > 3 || groups.any(|group| group.len() != 3) {
+                    return Err(invalid());
+                }
+
+                format!("{}{}", sign, digits.replace(separator, ""))
+            }
+            _ => whole.to_string(),
+        };
+
+        // Separators of the other format are only rejected once they reach `parse_amount`
+        let normalized = match fraction {
+            Some(fraction) => format!("{}.{}", whole, fraction),
+            None => whole,
+        };
+
+        Ok(if negative {
+            format!("-{}", normalized)
+        } else {
+            normalized
+        })
+    }
+
+    /// Same as [`parse_amount`], for an amount written in this format
+    pub fn parse(&self, amount: &str, precision: u32) -> Result<MoneyType, AmountParseError> {
+        parse_amount(&self.normalize(amount)?, precision)
+    }
+
+    /// Same as [`parse_truncated_amount`], for an amount written in this format
+    pub fn parse_truncated(
+        &self,
+        amount: &str,
+        precision: u32,
+    ) -> Result<(MoneyType, bool), AmountParseError> {
+        parse_truncated_amount(&self.normalize(amount)?, precision)
+    }
+
+    /// Same as [`PrecisionPolicy::parse`], for an amount written in this format
+    pub fn parse_with_policy(
+        &self,
+        amount: &str,
+        precision: u32,
+        policy: PrecisionPolicy,
+    ) -> Result<(MoneyType, bool), AmountParseError> {
+        policy.parse(&self.normalize(amount)?, precision)
+    }
+}
+
+/// The most decimal places the amounts can be configured with, as more wouldn't leave room for
+/// any useful balance
+pub const MAX_PRECISION: u32 = 9;
+
+/// Format a fixed point amount with exactly `precision` decimal places
+pub fn format_amount(amount: MoneyType, precision: u32) -> String {
+    let abs = amount.0.unsigned_abs();
+    let sign = if amount.is_negative() { "-" } else { "" };
+
+    if precision == 0 {
+        return format!("{}{}", sign, abs);
+    }
+
+    // Past 19 decimal places the scale doesn't fit, but then every amount is below one
+    let (units, fraction) = match 10u64.checked_pow(precision) {
+        Some(scale) => (abs / scale, abs % scale),
+        None => (0, abs),
+    };
+
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        units,
+        fraction,
+        width = precision as usize
+    )
+}
+
+fn scale_for(precision: u32) -> Result<i64, AmountParseError> {
+    10i64
+        .checked_pow(precision)
+        .ok_or(AmountParseError::Overflow)
+}
+
+fn digits_to_money(digits: &str) -> Result<i64, AmountParseError> {
+    if digits.is_empty() {
+        return Ok(0);
+    }
+
+    digits.parse().map_err(|_| AmountParseError::Overflow)
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AmountParseError {
+    #[error("The amount {0:?} is not a valid decimal number")]
+    InvalidFormat(String),
+    #[error("The amount has more decimal places than supported (Precision {0:?}, found {1:?})")]
+    ExcessPrecision(u32, usize),
+    #[error("The amount does not fit in the money type")]
+    Overflow,
+}
+
+/// An amount as written in a serialized record, as a string or as a number, kept as written
+/// until it is parsed with the precision of the run reading it.
+///
+/// Floats are only accepted when their decimal representation fits exactly in that precision.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecimalAmount(String);
+
+#[cfg(feature = "serde")]
+impl DecimalAmount {
+    /// Same as [`parse_amount`]
+    pub fn parse(&self, precision: u32) -> Result<MoneyType, AmountParseError> {
+        parse_amount(&self.0, precision)
+    }
+
+    /// Whether it was written as a blank string, rather than as an amount
+    pub fn is_blank(&self) -> bool {
+        self.0.trim().is_empty()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DecimalAmount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::{Error, Visitor};
+
+        struct AmountVisitor;
+
+        impl<'de> Visitor<'de> for AmountVisitor {
+            type Value = DecimalAmount;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a decimal amount")
+            }
+
+            fn visit_i64<E: Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(DecimalAmount(v.to_string()))
+            }
+
+            fn visit_u64<E: Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(DecimalAmount(v.to_string()))
+            }
+
+            fn visit_f64<E: Error>(self, v: f64) -> Result<Self::Value, E> {
+                if !v.is_finite() {
+                    return Err(E::custom("amounts must be finite numbers"));
+                }
+
+                // The display implementation yields the shortest representation that round
+                // trips to the same float, so if that does not fit the precision, the float is
+                // not exact.
+                Ok(DecimalAmount(v.to_string()))
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(DecimalAmount(v.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+/// Serialized as a string, with the decimal places it is displayed with
+#[cfg(feature = "serde")]
+impl serde::Serialize for DisplayAmount {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Serde (de)serializers for amounts, meant to be used with `#[serde(with = "...")]`.
+///
+/// Amounts are serialized as strings with [`FLOATING_POINT_ACC`](crate::FLOATING_POINT_ACC)
+/// decimal places, so API clients never have to go through floats. The serialized records carry
+/// no precision of their own, so they always use the default one, whichever the run was
+/// configured with. When deserializing we accept strings and integers, and only accept floats
+/// whose decimal representation fits exactly in our precision.
+#[cfg(feature = "serde")]
+pub mod serde_amount {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::models::money::DecimalAmount;
+    use crate::models::MoneyType;
+    use crate::FLOATING_POINT_ACC;
+
+    const PRECISION: u32 = FLOATING_POINT_ACC as u32;
+
+    pub fn serialize<S>(amount: &MoneyType, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&amount.display(PRECISION).to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<MoneyType, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        DecimalAmount::deserialize(deserializer)?
+            .parse(PRECISION)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Same as [`serde_amount`], for amounts which might not be present
+#[cfg(feature = "serde")]
+pub mod serde_optional_amount {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::models::money::serde_amount;
+    use crate::models::MoneyType;
+
+    pub fn serialize<S>(amount: &Option<MoneyType>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match amount {
+            Some(amount) => serde_amount::serialize(amount, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<MoneyType>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Amount(#[serde(with = "serde_amount")] MoneyType);
+
+        Ok(Option::<Amount>::deserialize(deserializer)?.map(|Amount(amount)| amount))
+    }
+}
+
+#[cfg(test)]
+mod money_tests {
+    use crate::models::money::{
+        format_amount, parse_amount, parse_rounded_amount, parse_truncated_amount, AmountFormat,
+        AmountParseError, PrecisionPolicy,
+    };
+    use crate::models::MoneyType;
+
+    #[test]
+    pub fn test_parse_exact_amounts() {
+        assert_eq!(parse_amount("1.0", 4), Ok(MoneyType::new(10000)));
+        assert_eq!(parse_amount("0.1235", 4), Ok(MoneyType::new(1235)));
+        assert_eq!(parse_amount(" 12 ", 4), Ok(MoneyType::new(120000)));
+        assert_eq!(parse_amount(".5", 4), Ok(MoneyType::new(5000)));
+        assert_eq!(parse_amount("-2.5", 4), Ok(MoneyType::new(-25000)));
+        assert_eq!(parse_amount("+2.", 4), Ok(MoneyType::new(20000)));
+    }
+
+    #[test]
+    pub fn test_parse_invalid_amounts() {
+        assert_eq!(
+            parse_amount("0.12345", 4),
+            Err(AmountParseError::ExcessPrecision(4, 5))
+        );
+        assert!(matches!(
+            parse_amount("1.2.3", 4),
+            Err(AmountParseError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            parse_amount(".", 4),
+            Err(AmountParseError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            parse_amount("1e5", 4),
+            Err(AmountParseError::InvalidFormat(_))
+        ));
+        assert_eq!(
+            parse_amount("99999999999999999999", 4),
+            Err(AmountParseError::Overflow)
+        );
+    }
+
+    #[test]
+    pub fn test_parse_truncated_amounts() {
+        assert_eq!(
+            parse_truncated_amount("0.1235", 4),
+            Ok((MoneyType::new(1235), false))
+        );
+        assert_eq!(
+            parse_truncated_amount("0.12359", 4),
+            Ok((MoneyType::new(1235), true))
+        );
+        assert_eq!(
+            parse_truncated_amount("-1.99", 0),
+            Ok((MoneyType::new(-1), true))
+        );
+        assert_eq!(
+            parse_truncated_amount("922337203685477.58079", 4),
+            Ok((MoneyType::MAX, true))
+        );
+        assert!(matches!(
+            parse_truncated_amount("1.2.345", 1),
+            Err(AmountParseError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_parse_rounded_amounts() {
+        assert_eq!(
+            parse_rounded_amount("0.1235", 4),
+            Ok((MoneyType::new(1235), false))
+        );
+        assert_eq!(
+            parse_rounded_amount("0.12359", 4),
+            Ok((MoneyType::new(1236), true))
+        );
+        assert_eq!(
+            parse_rounded_amount("0.12341", 4),
+            Ok((MoneyType::new(1234), true))
+        );
+
+        // Halfway between two amounts, the even one is taken
+        assert_eq!(
+            parse_rounded_amount("0.12345", 4),
+            Ok((MoneyType::new(1234), true))
+        );
+        assert_eq!(
+            parse_rounded_amount("0.12355", 4),
+            Ok((MoneyType::new(1236), true))
+        );
+        assert_eq!(
+            parse_rounded_amount("0.123450001", 4),
+            Ok((MoneyType::new(1235), true))
+        );
+        assert_eq!(
+            parse_rounded_amount("-2.5", 0),
+            Ok((MoneyType::new(-2), true))
+        );
+        assert_eq!(
+            parse_rounded_amount("-3.5", 0),
+            Ok((MoneyType::new(-4), true))
+        );
+
+        assert_eq!(
+            parse_rounded_amount("922337203685477.58079", 4),
+            Err(AmountParseError::Overflow)
+        );
+    }
+
+    #[test]
+    pub fn test_precision_policies() {
+        assert_eq!(
+            PrecisionPolicy::Truncate.parse("1.00009", 4),
+            Ok((MoneyType::new(10000), true))
+        );
+        assert_eq!(
+            PrecisionPolicy::RoundHalfEven.parse("1.00009", 4),
+            Ok((MoneyType::new(10001), true))
+        );
+        assert_eq!(
+            PrecisionPolicy::Reject.parse("1.00009", 4),
+            Err(AmountParseError::ExcessPrecision(4, 5))
+        );
+        assert_eq!(
+            PrecisionPolicy::Reject.parse("1.0001", 4),
+            Ok((MoneyType::new(10001), false))
+        );
+    }
+
+    #[test]
+    pub fn test_parse_localized_amounts() {
+        let european = AmountFormat::european();
+
+        assert_eq!(
+            european.parse("1.234,56", 4),
+            Ok(MoneyType::new(12_345_600))
+        );
+        assert_eq!(
+            european.parse("-1.234.567", 4),
+            Ok(MoneyType::new(-12_345_670_000))
+        );
+        assert_eq!(european.parse("0,5", 4), Ok(MoneyType::new(5000)));
+        assert_eq!(european.parse(" (12,50) ", 4), Ok(MoneyType::new(-125_000)));
+        assert_eq!(
+            european.parse_truncated("1.000,123456", 4),
+            Ok((MoneyType::new(10_001_234), true))
+        );
+
+        // Written with the separators the other way around
+        assert!(european.parse("1,234.56", 4).is_err());
+        assert!(european.parse("1.5", 4).is_err());
+
+        let english = AmountFormat::new('.', Some(','));
+
+        assert_eq!(
+            english.parse("(1,234.56)", 4),
+            Ok(MoneyType::new(-12_345_600))
+        );
+        assert!(english.parse("12,34.5", 4).is_err());
+        assert!(english.parse("(-1.0)", 4).is_err());
+
+        // Without thousands separators, the amounts are read as by `parse_amount`
+        assert_eq!(
+            AmountFormat::default().parse("(2.5)", 4),
+            Ok(MoneyType::new(-25000))
+        );
+        assert!(AmountFormat::default().parse("1,000", 4).is_err());
+    }
+
+    #[test]
+    pub fn test_format_amounts() {
+        assert_eq!(format_amount(MoneyType::new(10000), 4), "1.0000");
+        assert_eq!(format_amount(MoneyType::new(1235), 4), "0.1235");
+        assert_eq!(format_amount(MoneyType::new(-25000), 4), "-2.5000");
+        assert_eq!(format_amount(MoneyType::new(42), 0), "42");
+        assert_eq!(
+            format_amount(MoneyType::new(-42), 21),
+            "-0.000000000000000000042"
+        );
+        assert_eq!(
+            parse_amount(&format_amount(MoneyType::new(i64::MIN + 1), 4), 4),
+            Ok(MoneyType::new(i64::MIN + 1))
+        );
+    }
+
+    #[test]
+    pub fn test_amounts_round_trip() {
+        for precision in [0, 2, 4, 9] {
+            for raw in [0, 1, -1, 12_345, -987_654_321, i64::MAX, i64::MIN + 1] {
+                let amount = MoneyType::new(raw);
+
+                let displayed = amount.display(precision).to_string();
+
+                assert_eq!(MoneyType::parse(&displayed, precision), Ok(amount));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_amounts_with_other_precision() {
+        let amount = MoneyType::parse("1.25", 2).unwrap();
+
+        assert_eq!(amount.raw(), 125);
+
+        // The raw value only means something along with the precision it was parsed with
+        assert_eq!(amount.display(2).to_string(), "1.25");
+        assert_eq!(amount.display(4).to_string(), "0.0125");
+        assert_eq!(MoneyType::parse("1.25", 4).unwrap().raw(), 12_500);
+
+        assert_eq!(
+            MoneyType::parse("1.2345", 2),
+            Err(AmountParseError::ExcessPrecision(2, 4))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde_amounts() {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Payload {
+            #[serde(with = "crate::models::money::serde_amount")]
+            amount: MoneyType,
+        }
+
+        let payload = serde_json::to_string(&Payload {
+            amount: MoneyType::new(15000),
+        })
+        .unwrap();
+
+        assert_eq!(payload, r#"{"amount":"1.5000"}"#);
+
+        let parse = |json: &str| serde_json::from_str::<Payload>(json).map(|p| p.amount);
+
+        assert_eq!(parse(r#"{"amount":"1.5"}"#).unwrap(), MoneyType::new(15000));
+        assert_eq!(parse(r#"{"amount":2}"#).unwrap(), MoneyType::new(20000));
+        assert_eq!(parse(r#"{"amount":0.1235}"#).unwrap(), MoneyType::new(1235));
+        assert!(parse(r#"{"amount":0.12345}"#).is_err());
+        assert!(parse(r#"{"amount":"abc"}"#).is_err());
+    }
+}
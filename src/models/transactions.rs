@@ -1,15 +1,15 @@
 use getset::{CopyGetters, Getters};
 use thiserror::Error;
 
-use crate::models::{ClientID, MoneyType, NoVal, TransactionID};
-use crate::models::client::Client;
+use crate::models::{ClientID, MoneyType, NoVal, SequenceNumber, Timestamp, TransactionID};
 
 /// The transaction model, representing a transaction made in the
 /// system.
 ///
 /// Contains the transaction ID and type, the client who is targeted by it
 /// and the corresponding amount
-#[derive(Getters, CopyGetters, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Getters, CopyGetters, Debug, Clone, PartialEq, Eq)]
 pub struct Transaction {
     #[getset(get_copy = "pub")]
     transaction_id: TransactionID,
@@ -17,6 +17,12 @@ pub struct Transaction {
     tx_type: TransactionType,
     #[getset(get_copy = "pub")]
     client: ClientID,
+    /// The position of this transaction in the client's history, assigned once it is applied
+    #[getset(get_copy = "pub")]
+    client_sequence: Option<SequenceNumber>,
+    /// When the transaction happened, if known (given in the input or assigned at ingestion)
+    #[getset(get_copy = "pub")]
+    timestamp: Option<Timestamp>,
 }
 
 /// The type of transaction we are attempting to perform
@@ -26,33 +32,339 @@ pub struct Transaction {
 /// DO NOT POSSESS AMOUNTS, instead they use the client
 /// This way, we can, at compile time, assert that all transactions
 /// are well-formed
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", rename_all = "lowercase")
+)]
 pub enum TransactionType {
     Deposit {
+        #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
         amount: MoneyType,
-        dispute: Option<Box<Dispute>>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        dispute: DisputeState,
     },
     Withdrawal {
+        #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
         amount: MoneyType,
-        dispute: Option<Box<Dispute>>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        dispute: DisputeState,
     },
     Dispute,
     Resolve,
     Chargeback,
+    /// Reverse the chargeback of a transaction, after the merchant successfully contested it,
+    /// returning the charged back funds to the client
+    Representment,
+    /// Reserve funds of the client, holding them until the authorization is captured
+    Authorize {
+        #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+        amount: MoneyType,
+        #[cfg_attr(feature = "serde", serde(default))]
+        state: AuthorizationState,
+    },
+    /// Capture the authorization with the same id, withdrawing the funds it held
+    Capture,
+    /// Move funds from the client of the transaction to another client
+    Transfer {
+        to_client: ClientID,
+        #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+        amount: MoneyType,
+    },
+    /// Reactivate the account of the client, frozen by a chargeback.
+    ///
+    /// This is an administrative transaction, only accepted from admin sources.
+    Unfreeze,
+    /// Close the account of the client for good, once it holds no funds.
+    ///
+    /// This is an administrative transaction, only accepted from admin sources.
+    Close,
+}
+
+/// The names of every transaction type, as written in the input
+pub const TRANSACTION_TYPE_NAMES: [&str; 11] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "representment",
+    "authorize",
+    "capture",
+    "transfer",
+    "unfreeze",
+    "close",
+];
+
+impl TransactionType {
+    /// The name of the type, as written in the input
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit { .. } => "deposit",
+            TransactionType::Withdrawal { .. } => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Representment => "representment",
+            TransactionType::Authorize { .. } => "authorize",
+            TransactionType::Capture => "capture",
+            TransactionType::Transfer { .. } => "transfer",
+            TransactionType::Unfreeze => "unfreeze",
+            TransactionType::Close => "close",
+        }
+    }
+
+    /// Whether this is an administrative transaction, which only admin sources can submit
+    pub fn is_administrative(&self) -> bool {
+        matches!(self, TransactionType::Unfreeze | TransactionType::Close)
+    }
+}
+
+/// Where an authorization stands: its funds are held while it is pending, leave the account
+/// once it is captured, and go back to the client if it expires before that
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum AuthorizationState {
+    #[default]
+    Pending,
+    Captured,
+    Expired,
+}
+
+/// The lifecycle of the disputes of a deposit or withdrawal.
+///
+/// Since dispute and resolution transactions don't have their own ID, we treat them as a sort
+/// of Value Object, which does not live on without being attached to the original transaction.
+/// Each state keeps the transactions which took the dispute there, so the whole lifecycle is
+/// recorded on the disputed transaction:
+///
+/// ```text
+/// NotDisputed -> Open -> Resolved
+///                     -> ChargedBack -> Represented
+/// ```
+///
+/// Any other transition is rejected with an [`IllegalDisputeTransition`], which lets us handle
+/// wrongful disputes or resolutions by just discarding them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "state", rename_all = "snake_case")
+)]
+pub enum DisputeState {
+    #[default]
+    NotDisputed,
+    Open {
+        dispute: Box<Transaction>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        mode: DisputeMode,
+    },
+    Resolved {
+        dispute: Box<Transaction>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        mode: DisputeMode,
+        resolution: Box<Transaction>,
+    },
+    ChargedBack {
+        dispute: Box<Transaction>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        mode: DisputeMode,
+        chargeback: Box<Transaction>,
+    },
+    /// Charged back, and then reversed by a representment
+    Represented {
+        dispute: Box<Transaction>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        mode: DisputeMode,
+        chargeback: Box<Transaction>,
+        representment: Box<Transaction>,
+    },
+}
+
+/// How the funds were moved when a dispute was opened, which its settlement has to undo the
+/// same way, even if the dispute policy changed in the meantime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DisputeMode {
+    /// The disputed amount is held until the dispute is settled
+    #[default]
+    Hold,
+    /// The disputed withdrawal was credited back to the client right away
+    ProvisionalCredit,
+}
+
+/// The states of [`DisputeState`], without the transactions which led to them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DisputeStage {
+    NotDisputed,
+    Open,
+    Resolved,
+    ChargedBack,
+    Represented,
 }
 
-/// The dispute model.
-/// Since dispute and resolution transactions don't have their own ID,
-/// we will treat them as a sort of Value Object, which will not live on without
-/// being attached to the original transaction.
-/// This way we can successfully handle wrongful disputes or resolutions by just discarding
-/// them and we better represent the expected behaviour in the model
-#[derive(Debug, Clone, Getters)]
-pub struct Dispute {
-    #[get = "pub"]
-    dispute_transaction: Transaction,
-
-    resolution: Option<Transaction>,
+impl DisputeStage {
+    /// The name of the stage, as shown in the reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisputeStage::NotDisputed => "not_disputed",
+            DisputeStage::Open => "open",
+            DisputeStage::Resolved => "resolved",
+            DisputeStage::ChargedBack => "charged_back",
+            DisputeStage::Represented => "represented",
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("A dispute can't go from {from:?} to {to:?}")]
+pub struct IllegalDisputeTransition {
+    pub from: DisputeStage,
+    pub to: DisputeStage,
+}
+
+impl DisputeState {
+    pub fn stage(&self) -> DisputeStage {
+        match self {
+            DisputeState::NotDisputed => DisputeStage::NotDisputed,
+            DisputeState::Open { .. } => DisputeStage::Open,
+            DisputeState::Resolved { .. } => DisputeStage::Resolved,
+            DisputeState::ChargedBack { .. } => DisputeStage::ChargedBack,
+            DisputeState::Represented { .. } => DisputeStage::Represented,
+        }
+    }
+
+    /// The transaction which opened the dispute, if it was ever disputed
+    pub fn dispute(&self) -> Option<&Transaction> {
+        match self {
+            DisputeState::NotDisputed => None,
+            DisputeState::Open { dispute, .. }
+            | DisputeState::Resolved { dispute, .. }
+            | DisputeState::ChargedBack { dispute, .. }
+            | DisputeState::Represented { dispute, .. } => Some(dispute),
+        }
+    }
+
+    /// How the funds were moved when the dispute was opened, if it was ever disputed
+    pub fn mode(&self) -> Option<DisputeMode> {
+        match self {
+            DisputeState::NotDisputed => None,
+            DisputeState::Open { mode, .. }
+            | DisputeState::Resolved { mode, .. }
+            | DisputeState::ChargedBack { mode, .. }
+            | DisputeState::Represented { mode, .. } => Some(*mode),
+        }
+    }
+
+    /// The resolve or chargeback which settled the dispute, if it was settled
+    pub fn settlement(&self) -> Option<&Transaction> {
+        match self {
+            DisputeState::NotDisputed | DisputeState::Open { .. } => None,
+            DisputeState::Resolved { resolution, .. } => Some(resolution),
+            DisputeState::ChargedBack { chargeback, .. }
+            | DisputeState::Represented { chargeback, .. } => Some(chargeback),
+        }
+    }
+
+    /// The representment which reversed the chargeback, if it was reversed
+    pub fn representment(&self) -> Option<&Transaction> {
+        match self {
+            DisputeState::Represented { representment, .. } => Some(representment),
+            _ => None,
+        }
+    }
+
+    pub fn open(
+        &mut self,
+        dispute: Transaction,
+        mode: DisputeMode,
+    ) -> Result<(), IllegalDisputeTransition> {
+        self.transition(DisputeStage::Open, |state| match state {
+            DisputeState::NotDisputed => Ok(DisputeState::Open {
+                dispute: Box::new(dispute),
+                mode,
+            }),
+            state => Err(state),
+        })
+    }
+
+    pub fn resolve(&mut self, resolution: Transaction) -> Result<(), IllegalDisputeTransition> {
+        self.transition(DisputeStage::Resolved, |state| match state {
+            DisputeState::Open { dispute, mode } => Ok(DisputeState::Resolved {
+                dispute,
+                mode,
+                resolution: Box::new(resolution),
+            }),
+            state => Err(state),
+        })
+    }
+
+    pub fn charge_back(&mut self, chargeback: Transaction) -> Result<(), IllegalDisputeTransition> {
+        self.transition(DisputeStage::ChargedBack, |state| match state {
+            DisputeState::Open { dispute, mode } => Ok(DisputeState::ChargedBack {
+                dispute,
+                mode,
+                chargeback: Box::new(chargeback),
+            }),
+            state => Err(state),
+        })
+    }
+
+    pub fn represent(
+        &mut self,
+        representment: Transaction,
+    ) -> Result<(), IllegalDisputeTransition> {
+        self.transition(DisputeStage::Represented, |state| match state {
+            DisputeState::ChargedBack {
+                dispute,
+                mode,
+                chargeback,
+            } => Ok(DisputeState::Represented {
+                dispute,
+                mode,
+                chargeback,
+                representment: Box::new(representment),
+            }),
+            state => Err(state),
+        })
+    }
+
+    /// Move to the state returned by the given step, which hands the current state back when
+    /// it can't move from it to the given stage
+    fn transition(
+        &mut self,
+        to: DisputeStage,
+        step: impl FnOnce(Self) -> Result<Self, Self>,
+    ) -> Result<(), IllegalDisputeTransition> {
+        let from = self.stage();
+
+        match step(std::mem::take(self)) {
+            Ok(next) => {
+                *self = next;
+
+                Ok(())
+            }
+            Err(unchanged) => {
+                *self = unchanged;
+
+                Err(IllegalDisputeTransition { from, to })
+            }
+        }
+    }
 }
 
 impl Transaction {
@@ -64,14 +376,56 @@ impl Transaction {
     pub fn amount(&self) -> Result<MoneyType, TransactionError> {
         match self.tx_type {
             TransactionType::Deposit { amount, .. }
-            | TransactionType::Withdrawal { amount, .. } => Ok(amount),
+            | TransactionType::Withdrawal { amount, .. }
+            | TransactionType::Authorize { amount, .. }
+            | TransactionType::Transfer { amount, .. } => Ok(amount),
             _ => Err(TransactionError::IllegalAmountCheck),
         }
     }
 
+    /// The client receiving the funds, for transactions which involve a second client
+    pub fn destination(&self) -> Option<ClientID> {
+        match self.tx_type {
+            TransactionType::Transfer { to_client, .. } => Some(to_client),
+            _ => None,
+        }
+    }
+
+    /// Record the position of this transaction in the client's history
+    pub fn assign_client_sequence(&mut self, client_sequence: SequenceNumber) {
+        self.client_sequence = Some(client_sequence);
+    }
+
+    /// Record when this transaction happened
+    pub fn assign_timestamp(&mut self, timestamp: Timestamp) {
+        self.timestamp = Some(timestamp);
+    }
+
+    /// The state of the disputes of this transaction. Only deposits and withdrawals can be
+    /// disputed, every other transaction is never disputed.
+    pub fn dispute_state(&self) -> &DisputeState {
+        static NOT_DISPUTED: DisputeState = DisputeState::NotDisputed;
+
+        match &self.tx_type {
+            TransactionType::Deposit { dispute, .. }
+            | TransactionType::Withdrawal { dispute, .. } => dispute,
+            _ => &NOT_DISPUTED,
+        }
+    }
+
     /// Attempt to dispute this transaction with the given dispute_tx
-    /// transaction
+    /// transaction, holding the disputed funds
     pub fn dispute(&mut self, dispute_tx: Transaction) -> Result<(), TransactionError> {
+        self.dispute_with_mode(dispute_tx, DisputeMode::Hold)
+    }
+
+    /// Attempt to dispute this transaction with the given dispute_tx transaction, recording how
+    /// the disputed funds were moved
+    pub fn dispute_with_mode(
+        &mut self,
+        dispute_tx: Transaction,
+        mode: DisputeMode,
+    ) -> Result<(), TransactionError> {
         if let TransactionType::Dispute = dispute_tx.tx_type() {
             if dispute_tx.transaction_id != self.transaction_id {
                 return Err(TransactionDisputeError::TransactionNotDisputingThisOne(
@@ -81,30 +435,9 @@ impl Transaction {
                 .into());
             }
 
-            if dispute_tx.client() != self.client() {
-                return Err(TransactionDisputeError::TransactionTargettingWrongClient(
-                    self.client(),
-                    dispute_tx.client(),
-                )
-                .into());
-            }
+            self.disputes_of(&dispute_tx)?.open(dispute_tx, mode)?;
 
-            return match &mut self.tx_type {
-                TransactionType::Deposit { dispute, .. }
-                | TransactionType::Withdrawal { dispute, .. } => {
-                    if dispute.is_some() {
-                        return Err(TransactionDisputeError::TransactionAlreadyDisputed.into());
-                    }
-
-                    let _ = dispute.insert(Box::new(Dispute {
-                        dispute_transaction: dispute_tx,
-                        resolution: None,
-                    }));
-
-                    Ok(())
-                }
-                _ => Err(TransactionDisputeError::TransactionNotDisputable.into()),
-            };
+            return Ok(());
         }
 
         Err(TransactionDisputeError::ProvidedTransactionNotDispute.into())
@@ -127,37 +460,132 @@ impl Transaction {
                     );
                 }
 
-                if dispute_settlement.client() != self.client() {
-                    return Err(TransactionDisputeError::TransactionTargettingWrongClient(
-                        self.client(),
-                        dispute_settlement.client(),
-                    )
-                    .into());
+                let disputes = self.disputes_of(&dispute_settlement)?;
+
+                match dispute_settlement.tx_type() {
+                    TransactionType::Resolve => disputes.resolve(dispute_settlement)?,
+                    _ => disputes.charge_back(dispute_settlement)?,
                 }
 
-                match &mut self.tx_type {
-                    TransactionType::Deposit { dispute, .. }
-                    | TransactionType::Withdrawal { dispute, .. } => {
-                        if dispute.is_none() {
-                            return Err(TransactionDisputeError::TransactionNotDisputable.into());
-                        }
+                Ok(())
+            }
+            _ => Err(TransactionResolveDisputeError::ProvidedTransactionNotResolution.into()),
+        }
+    }
+
+    /// Reverse the chargeback of this transaction with the given representment
+    pub fn represent(&mut self, representment: Transaction) -> Result<(), TransactionError> {
+        if let TransactionType::Representment = representment.tx_type() {
+            if representment.transaction_id != self.transaction_id {
+                return Err(
+                    TransactionRepresentmentError::TransactionNotRepresentingThisOne(
+                        self.transaction_id,
+                        representment.transaction_id,
+                    )
+                    .into(),
+                );
+            }
+
+            self.disputes_of(&representment)?.represent(representment)?;
 
-                        let dispute_ref = dispute.as_mut().unwrap();
+            return Ok(());
+        }
+
+        Err(TransactionRepresentmentError::ProvidedTransactionNotRepresentment.into())
+    }
 
-                        if dispute_ref.resolution.is_some() {
-                            return Err(
-                                TransactionResolveDisputeError::DisputeAlreadyResolved.into()
-                            );
-                        }
+    /// Capture this authorization with the given capture
+    pub fn capture(&mut self, capture: Transaction) -> Result<(), TransactionError> {
+        if let TransactionType::Capture = capture.tx_type() {
+            if capture.transaction_id != self.transaction_id {
+                return Err(TransactionCaptureError::TransactionNotCapturingThisOne(
+                    self.transaction_id,
+                    capture.transaction_id,
+                )
+                .into());
+            }
+
+            if capture.client() != self.client() {
+                return Err(TransactionCaptureError::TransactionTargettingWrongClient(
+                    self.client(),
+                    capture.client(),
+                )
+                .into());
+            }
 
-                        dispute_ref.resolution = Some(dispute_settlement);
+            return match &mut self.tx_type {
+                TransactionType::Authorize {
+                    state: state @ AuthorizationState::Pending,
+                    ..
+                } => {
+                    *state = AuthorizationState::Captured;
 
-                        Ok(())
-                    }
-                    _ => Err(TransactionDisputeError::TransactionNotDisputable.into()),
+                    Ok(())
+                }
+                TransactionType::Authorize {
+                    state: AuthorizationState::Captured,
+                    ..
+                } => Err(TransactionCaptureError::AuthorizationAlreadyCaptured.into()),
+                TransactionType::Authorize { .. } => {
+                    Err(TransactionCaptureError::AuthorizationExpired.into())
                 }
+                _ => Err(TransactionCaptureError::TransactionNotAuthorization.into()),
+            };
+        }
+
+        Err(TransactionCaptureError::ProvidedTransactionNotCapture.into())
+    }
+
+    /// Expire this authorization, which was not captured in time, releasing its hold
+    pub fn expire(&mut self) -> Result<(), TransactionError> {
+        match &mut self.tx_type {
+            TransactionType::Authorize {
+                state: state @ AuthorizationState::Pending,
+                ..
+            } => {
+                *state = AuthorizationState::Expired;
+
+                Ok(())
             }
-            _ => Err(TransactionResolveDisputeError::ProvidedTransactionNotResolution.into()),
+            TransactionType::Authorize {
+                state: AuthorizationState::Captured,
+                ..
+            } => Err(TransactionCaptureError::AuthorizationAlreadyCaptured.into()),
+            TransactionType::Authorize { .. } => {
+                Err(TransactionCaptureError::AuthorizationExpired.into())
+            }
+            _ => Err(TransactionCaptureError::TransactionNotAuthorization.into()),
+        }
+    }
+
+    /// Whether this is an authorization still holding its funds
+    pub fn is_pending_authorization(&self) -> bool {
+        matches!(
+            self.tx_type,
+            TransactionType::Authorize {
+                state: AuthorizationState::Pending,
+                ..
+            }
+        )
+    }
+
+    /// The dispute state to move with the given step of a dispute, which has to come from
+    /// the client of this transaction
+    fn disputes_of(
+        &mut self,
+        step: &Transaction,
+    ) -> Result<&mut DisputeState, TransactionDisputeError> {
+        if step.client() != self.client() {
+            return Err(TransactionDisputeError::TransactionTargettingWrongClient(
+                self.client(),
+                step.client(),
+            ));
+        }
+
+        match &mut self.tx_type {
+            TransactionType::Deposit { dispute, .. }
+            | TransactionType::Withdrawal { dispute, .. } => Ok(dispute),
+            _ => Err(TransactionDisputeError::TransactionNotDisputable),
         }
     }
 }
@@ -170,26 +598,46 @@ pub enum TransactionDisputeError {
     TransactionNotDisputable,
     #[error("The provided transaction is not a dispute transaction.")]
     ProvidedTransactionNotDispute,
-    #[error("Transaction has already been disputed")]
-    TransactionAlreadyDisputed,
     #[error("The transaction is not disputing the current one (Current {0:?}, Disputed {1:?})")]
     TransactionNotDisputingThisOne(TransactionID, TransactionID),
     #[error("The dispute transaction is targetting the wrong client {0:?}, {1:?}")]
-    TransactionTargettingWrongClient(ClientID, ClientID)
+    TransactionTargettingWrongClient(ClientID, ClientID),
 }
 
 #[derive(Error, Debug)]
 pub enum TransactionResolveDisputeError {
     #[error("Failed to resolve due to {0:?}")]
     DisputeError(#[from] TransactionDisputeError),
-    #[error("Cannot resolve a dispute in a transaction that is not disputed")]
-    TransactionNotDisputed,
     #[error("The provided transaction is not a dispute resolution")]
     ProvidedTransactionNotResolution,
     #[error("The transaction is not resolving the current one (Current {0:?}, Disputed {1:?})")]
     TransactionNotResolvingThisOne(TransactionID, TransactionID),
-    #[error("This dispute has already been resolved")]
-    DisputeAlreadyResolved,
+}
+
+#[derive(Error, Debug)]
+pub enum TransactionRepresentmentError {
+    #[error("The provided transaction is not a representment")]
+    ProvidedTransactionNotRepresentment,
+    #[error(
+        "The transaction is not representing the current one (Current {0:?}, Represented {1:?})"
+    )]
+    TransactionNotRepresentingThisOne(TransactionID, TransactionID),
+}
+
+#[derive(Error, Debug)]
+pub enum TransactionCaptureError {
+    #[error("Only authorizations can be captured")]
+    TransactionNotAuthorization,
+    #[error("The authorization has already been captured")]
+    AuthorizationAlreadyCaptured,
+    #[error("The authorization has expired")]
+    AuthorizationExpired,
+    #[error("The provided transaction is not a capture")]
+    ProvidedTransactionNotCapture,
+    #[error("The transaction is not capturing the current one (Current {0:?}, Captured {1:?})")]
+    TransactionNotCapturingThisOne(TransactionID, TransactionID),
+    #[error("The capture transaction is targetting the wrong client {0:?}, {1:?}")]
+    TransactionTargettingWrongClient(ClientID, ClientID),
 }
 
 #[derive(Error, Debug)]
@@ -198,6 +646,12 @@ pub enum TransactionError {
     DisputeError(#[from] TransactionDisputeError),
     #[error("Resolve dispute error {0:?}")]
     ResolveDisputeError(#[from] TransactionResolveDisputeError),
+    #[error("Representment error {0:?}")]
+    RepresentmentError(#[from] TransactionRepresentmentError),
+    #[error("{0}")]
+    IllegalDisputeTransition(#[from] IllegalDisputeTransition),
+    #[error("Capture error {0:?}")]
+    CaptureError(#[from] TransactionCaptureError),
     #[error("Cannot check the amount of this transaction")]
     IllegalAmountCheck,
 }
@@ -210,6 +664,15 @@ pub struct TransactionBuilder<TID, TTY, CLID> {
     transaction_id: TID,
     tx_type: TTY,
     client_id: CLID,
+    timestamp: Option<Timestamp>,
+}
+
+impl<TID, TTY, CLID> TransactionBuilder<TID, TTY, CLID> {
+    pub fn with_timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = Some(timestamp);
+
+        self
+    }
 }
 
 impl<TTY, CLID> TransactionBuilder<NoVal, TTY, CLID> {
@@ -221,6 +684,7 @@ impl<TTY, CLID> TransactionBuilder<NoVal, TTY, CLID> {
             transaction_id,
             tx_type: self.tx_type,
             client_id: self.client_id,
+            timestamp: self.timestamp,
         }
     }
 }
@@ -234,6 +698,7 @@ impl<TID, CLID> TransactionBuilder<TID, NoVal, CLID> {
             transaction_id: self.transaction_id,
             tx_type,
             client_id: self.client_id,
+            timestamp: self.timestamp,
         }
     }
 }
@@ -244,6 +709,7 @@ impl<TID, TTY> TransactionBuilder<TID, TTY, NoVal> {
             transaction_id: self.transaction_id,
             tx_type: self.tx_type,
             client_id,
+            timestamp: self.timestamp,
         }
     }
 }
@@ -254,6 +720,8 @@ impl TransactionBuilder<TransactionID, TransactionType, ClientID> {
             transaction_id: self.transaction_id,
             tx_type: self.tx_type,
             client: self.client_id,
+            client_sequence: None,
+            timestamp: self.timestamp,
         }
     }
 }
@@ -264,53 +732,58 @@ impl Default for TransactionBuilder<NoVal, NoVal, NoVal> {
             transaction_id: Default::default(),
             tx_type: Default::default(),
             client_id: Default::default(),
+            timestamp: None,
         }
     }
 }
 
 #[cfg(test)]
 mod transaction_tests {
-    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::transactions::{
+        DisputeStage, DisputeState, IllegalDisputeTransition, Transaction, TransactionError,
+        TransactionType,
+    };
+    use crate::models::{ClientID, MoneyType, TransactionID};
 
     #[test]
     pub fn test_valid_transaction_init() {
         let transaction = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Deposit {
-                amount: 10000,
-                dispute: None,
+                amount: MoneyType::new(10000),
+                dispute: DisputeState::NotDisputed,
             })
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
-        assert_eq!(transaction.transaction_id(), 1);
-        assert_eq!(transaction.client(), 2);
+        assert_eq!(transaction.transaction_id(), TransactionID(1));
+        assert_eq!(transaction.client(), ClientID(2));
     }
 
     #[test]
     pub fn test_transaction_dispute() {
         let mut transaction = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Deposit {
-                amount: 10000,
-                dispute: None,
+                amount: MoneyType::new(10000),
+                dispute: DisputeState::NotDisputed,
             })
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         let dispute_tx = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Dispute)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.dispute(dispute_tx.clone()).is_ok());
         assert!(transaction.dispute(dispute_tx).is_err());
 
         let resolved_tx = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Resolve)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.settle_dispute(resolved_tx).is_ok());
@@ -319,39 +792,39 @@ mod transaction_tests {
     #[test]
     pub fn test_dispute_with_wrong_tx() {
         let mut transaction = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Deposit {
-                amount: 10000,
-                dispute: None,
+                amount: MoneyType::new(10000),
+                dispute: DisputeState::NotDisputed,
             })
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         let fake_dispute = Transaction::builder()
             //WRONG ID
-            .with_tx_id(2)
+            .with_tx_id(TransactionID(2))
             .with_tx_type(TransactionType::Dispute)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.dispute(fake_dispute.clone()).is_err());
         assert!(transaction.settle_dispute(fake_dispute).is_err());
 
         let fake_dispute = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             // WRONG TYPE
             .with_tx_type(TransactionType::Resolve)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.dispute(fake_dispute.clone()).is_err());
         assert!(transaction.settle_dispute(fake_dispute).is_err());
 
         let fake_dispute = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             // WRONG TYPE
             .with_tx_type(TransactionType::Chargeback)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.dispute(fake_dispute.clone()).is_err());
@@ -361,19 +834,19 @@ mod transaction_tests {
     #[test]
     pub fn test_wrong_client_dispute() {
         let mut transaction = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Deposit {
-                amount: 10000,
-                dispute: None,
+                amount: MoneyType::new(10000),
+                dispute: DisputeState::NotDisputed,
             })
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         let invalid_dispute = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Dispute)
             // Wrong client ID
-            .with_client_id(3)
+            .with_client_id(ClientID(3))
             .build();
 
         assert!(transaction.dispute(invalid_dispute).is_err());
@@ -382,18 +855,18 @@ mod transaction_tests {
     #[test]
     pub fn test_dispute_settlement() {
         let mut transaction = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Deposit {
-                amount: 10000,
-                dispute: None,
+                amount: MoneyType::new(10000),
+                dispute: DisputeState::NotDisputed,
             })
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         let valid_dispute = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Dispute)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.dispute(valid_dispute.clone()).is_ok());
@@ -401,19 +874,130 @@ mod transaction_tests {
 
         let invalid_settlement = Transaction::builder()
             // WRONG ID
-            .with_tx_id(2)
+            .with_tx_id(TransactionID(2))
             .with_tx_type(TransactionType::Resolve)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.settle_dispute(invalid_settlement).is_err());
 
         let valid_settlement = Transaction::builder()
-            .with_tx_id(1)
+            .with_tx_id(TransactionID(1))
             .with_tx_type(TransactionType::Resolve)
-            .with_client_id(2)
+            .with_client_id(ClientID(2))
             .build();
 
         assert!(transaction.settle_dispute(valid_settlement).is_ok());
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde_transactions() {
+        let related = |tx_type| {
+            Transaction::builder()
+                .with_tx_id(TransactionID(1))
+                .with_tx_type(tx_type)
+                .with_client_id(ClientID(2))
+                .build()
+        };
+
+        let mut transaction = related(TransactionType::Deposit {
+            amount: MoneyType::new(15000),
+            dispute: DisputeState::NotDisputed,
+        });
+
+        transaction.assign_timestamp(1_700_000_000);
+
+        let json = serde_json::to_value(&transaction).unwrap();
+
+        assert_eq!(json["tx_type"]["type"], "deposit");
+        assert_eq!(json["tx_type"]["amount"], "1.5000");
+
+        transaction
+            .dispute(related(TransactionType::Dispute))
+            .unwrap();
+        transaction
+            .settle_dispute(related(TransactionType::Chargeback))
+            .unwrap();
+
+        let json = serde_json::to_string(&transaction).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Transaction>(&json).unwrap(),
+            transaction
+        );
+    }
+
+    #[test]
+    pub fn test_dispute_lifecycle() {
+        let related = |tx_type| {
+            Transaction::builder()
+                .with_tx_id(TransactionID(1))
+                .with_tx_type(tx_type)
+                .with_client_id(ClientID(2))
+                .build()
+        };
+
+        let mut transaction = related(TransactionType::Deposit {
+            amount: MoneyType::new(10000),
+            dispute: DisputeState::NotDisputed,
+        });
+
+        assert!(matches!(
+            transaction.represent(related(TransactionType::Representment)),
+            Err(TransactionError::IllegalDisputeTransition(
+                IllegalDisputeTransition {
+                    from: DisputeStage::NotDisputed,
+                    to: DisputeStage::Represented,
+                }
+            ))
+        ));
+
+        transaction
+            .dispute(related(TransactionType::Dispute))
+            .unwrap();
+
+        assert_eq!(transaction.dispute_state().stage(), DisputeStage::Open);
+        assert!(transaction.dispute_state().settlement().is_none());
+
+        transaction
+            .settle_dispute(related(TransactionType::Chargeback))
+            .unwrap();
+
+        assert!(matches!(
+            transaction.settle_dispute(related(TransactionType::Resolve)),
+            Err(TransactionError::IllegalDisputeTransition(
+                IllegalDisputeTransition {
+                    from: DisputeStage::ChargedBack,
+                    to: DisputeStage::Resolved,
+                }
+            ))
+        ));
+
+        transaction
+            .represent(related(TransactionType::Representment))
+            .unwrap();
+
+        let state = transaction.dispute_state();
+
+        assert_eq!(state.stage(), DisputeStage::Represented);
+        assert_eq!(
+            state.dispute().unwrap().tx_type(),
+            &TransactionType::Dispute
+        );
+        assert_eq!(
+            state.settlement().unwrap().tx_type(),
+            &TransactionType::Chargeback
+        );
+        assert!(state.representment().is_some());
+
+        // A failed transition leaves the lifecycle as it was
+        assert!(transaction
+            .dispute(related(TransactionType::Dispute))
+            .is_err());
+        assert_eq!(
+            transaction.dispute_state().stage(),
+            DisputeStage::Represented
+        );
+    }
 }
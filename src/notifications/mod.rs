@@ -0,0 +1,42 @@
+#[cfg(feature = "webhooks")]
+pub mod webhook;
+
+use crate::events::{DomainEvent, EventKind};
+use crate::models::{ClientID, MoneyType, TransactionID};
+
+/// The kinds of domain events which are worth notifying someone outside of the engine about
+pub const NOTIFIED_EVENTS: [EventKind; 2] =
+    [EventKind::AccountFrozen, EventKind::ChargebackApplied];
+
+/// A change to an account which someone outside of the engine (e.g. the fraud team, or the
+/// client) has to hear about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    AccountFrozen {
+        client: ClientID,
+    },
+    ChargebackApplied {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: MoneyType,
+    },
+}
+
+impl Notification {
+    /// The notification for the given event, if it is one of the [`NOTIFIED_EVENTS`]
+    pub fn from_event(event: &DomainEvent) -> Option<Self> {
+        match *event {
+            DomainEvent::AccountFrozen { client } => Some(Notification::AccountFrozen { client }),
+            DomainEvent::ChargebackApplied {
+                client,
+                transaction,
+                amount,
+            } => Some(Notification::ChargebackApplied {
+                client,
+                transaction,
+                amount,
+            }),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use reqwest::{StatusCode, Url};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use crate::events::EventBus;
+use crate::models::money::DisplayAmount;
+use crate::models::{ClientID, TransactionID};
+use crate::notifications::{Notification, NOTIFIED_EVENTS};
+use crate::FLOATING_POINT_ACC;
+
+/// How long to wait for the endpoint to answer each attempt
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers the notifications by POSTing them as JSON to an HTTP endpoint.
+///
+/// Failed deliveries are retried, waiting twice as long before each new attempt, as long as
+/// the failure might be temporary (the endpoint could not be reached, or answered with a
+/// server error or `429 Too Many Requests`).
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    endpoint: Url,
+    max_attempts: u32,
+    retry_delay: Duration,
+    precision: u32,
+}
+
+/// The body a notification is delivered with, such as
+/// `{"event": "chargeback_applied", "client": 1, "transaction": 7, "amount": "1.5000"}`
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Payload {
+    AccountFrozen {
+        client: ClientID,
+    },
+    ChargebackApplied {
+        client: ClientID,
+        transaction: TransactionID,
+        amount: DisplayAmount,
+    },
+}
+
+impl Payload {
+    fn new(notification: &Notification, precision: u32) -> Self {
+        match *notification {
+            Notification::AccountFrozen { client } => Payload::AccountFrozen { client },
+            Notification::ChargebackApplied {
+                client,
+                transaction,
+                amount,
+            } => Payload::ChargebackApplied {
+                client,
+                transaction,
+                amount: amount.display(precision),
+            },
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WebhookError {
+    #[error("Failed to reach the webhook endpoint {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("The webhook endpoint answered with {0}")]
+    Rejected(StatusCode),
+}
+
+impl WebhookError {
+    fn is_temporary(&self) -> bool {
+        match self {
+            WebhookError::RequestFailed(_) => true,
+            WebhookError::Rejected(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+}
+
+impl WebhookNotifier {
+    pub fn new(endpoint: Url) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("The HTTP client has a valid configuration");
+
+        Self {
+            client,
+            endpoint,
+            max_attempts: 5,
+            retry_delay: Duration::from_millis(500),
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    /// The amount of decimal places the amounts are written with, which defaults to
+    /// [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// How many times to try delivering each notification, including the first one
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+
+        self
+    }
+
+    /// How long to wait before the first retry
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+
+        self
+    }
+
+    /// Deliver a notification, retrying it until it is delivered, it fails for good or it runs
+    /// out of attempts
+    pub async fn notify(&self, notification: &Notification) -> Result<(), WebhookError> {
+        let mut delay = self.retry_delay;
+
+        for attempt in 1.. {
+            match self.deliver(notification).await {
+                Err(err) if attempt < self.max_attempts && err.is_temporary() => {
+                    tracing::warn!(attempt, error = %err, "Failed to deliver a webhook, retrying");
+
+                    tokio::time::sleep(delay).await;
+
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+
+        unreachable!("Every attempt either returns or retries")
+    }
+
+    /// Deliver the notifications for the events published on the bus from now on, one at a
+    /// time and in order, until the bus is dropped
+    pub fn spawn(self, bus: &EventBus) -> JoinHandle<()> {
+        let mut events = bus.subscribe_to(NOTIFIED_EVENTS);
+
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let Some(notification) = Notification::from_event(&event) else {
+                    continue;
+                };
+
+                if let Err(err) = self.notify(&notification).await {
+                    tracing::error!(
+                        client = %event.client(),
+                        error = %err,
+                        "Failed to deliver a webhook notification"
+                    );
+                }
+            }
+        })
+    }
+
+    async fn deliver(&self, notification: &Notification) -> Result<(), WebhookError> {
+        let response = self
+            .client
+            .post(self.endpoint.clone())
+            .json(&Payload::new(notification, self.precision))
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(WebhookError::Rejected(status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod webhook_tests {
+    use std::time::Duration;
+
+    use reqwest::{StatusCode, Url};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use crate::events::{DomainEvent, EventBus, TDomainEventHandler};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::notifications::webhook::{Payload, WebhookError, WebhookNotifier};
+    use crate::notifications::Notification;
+
+    /// An endpoint answering each request with the next of the given statuses, forwarding the
+    /// bodies it receives
+    async fn endpoint(statuses: Vec<u16>) -> (Url, flume::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/hooks", listener.local_addr().unwrap())).unwrap();
+
+        let (bodies, received) = flume::unbounded();
+
+        tokio::spawn(async move {
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+
+                let mut content_length = 0;
+
+                loop {
+                    let mut line = String::new();
+
+                    stream.read_line(&mut line).await.unwrap();
+
+                    match line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        Some(length) => content_length = length.trim().parse().unwrap(),
+                        None if line.trim().is_empty() => break,
+                        None => {}
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+
+                stream.read_exact(&mut body).await.unwrap();
+
+                bodies.send(String::from_utf8(body).unwrap()).unwrap();
+
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries() {
+        let (url, received) = endpoint(vec![503, 200, 400]).await;
+
+        let notifier = WebhookNotifier::new(url)
+            .with_max_attempts(3)
+            .with_retry_delay(Duration::from_millis(1));
+
+        let chargeback = Notification::ChargebackApplied {
+            client: ClientID(1),
+            transaction: TransactionID(7),
+            amount: MoneyType::new(15000),
+        };
+
+        notifier.notify(&chargeback).await.unwrap();
+
+        let expected =
+            r#"{"event":"chargeback_applied","client":1,"transaction":7,"amount":"1.5000"}"#;
+
+        assert_eq!(received.drain().collect::<Vec<_>>(), vec![expected; 2]);
+
+        // Client errors won't go away by retrying
+        let rejected = notifier
+            .notify(&Notification::AccountFrozen {
+                client: ClientID(1),
+            })
+            .await;
+
+        assert!(matches!(
+            rejected,
+            Err(WebhookError::Rejected(StatusCode::BAD_REQUEST))
+        ));
+        assert_eq!(received.len(), 1);
+    }
+
+    #[test]
+    fn test_webhook_payload_precision() {
+        let chargeback = Notification::ChargebackApplied {
+            client: ClientID(1),
+            transaction: TransactionID(7),
+            amount: MoneyType::new(150),
+        };
+
+        assert_eq!(
+            serde_json::to_string(&Payload::new(&chargeback, 2)).unwrap(),
+            r#"{"event":"chargeback_applied","client":1,"transaction":7,"amount":"1.50"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notifies_events() {
+        let (url, received) = endpoint(vec![200]).await;
+
+        let bus = EventBus::default();
+
+        let notifier = WebhookNotifier::new(url).spawn(&bus);
+
+        bus.handle(&DomainEvent::AccountCreated {
+            client: ClientID(2),
+        });
+        bus.handle(&DomainEvent::AccountFrozen {
+            client: ClientID(2),
+        });
+
+        drop(bus);
+
+        notifier.await.unwrap();
+
+        assert_eq!(
+            received.drain().collect::<Vec<_>>(),
+            vec![r#"{"event":"account_frozen","client":2}"#]
+        );
+    }
+}
@@ -0,0 +1,500 @@
+use std::fmt;
+
+use crate::models::client::{
+    AuthorizeError, CaptureError, ChargeBackError, ClientOperationError, DepositFundsError,
+    DisputeFundsError, FeeError, ReleaseError, RepresentmentError, ResolveError,
+    WithdrawFundsError,
+};
+use crate::models::money::AmountParseError;
+use crate::models::transactions::{
+    DisputeStage, IllegalDisputeTransition, TransactionCaptureError, TransactionDisputeError,
+    TransactionError, TransactionRepresentmentError, TransactionResolveDisputeError,
+};
+use crate::repositories::clients::ClientVersionConflict;
+use crate::repositories::RepoError;
+use crate::services::transaction_service::TransactionProcessingError;
+use crate::tx_reception::RecordParseError;
+use crate::wal::WalError;
+
+/// The reason a transaction was rejected, identified by a stable code so downstream tools can
+/// branch on it instead of parsing the error messages.
+///
+/// Codes are grouped by the stage which rejected the transaction:
+/// `E0xxx` for parsing the input, `E1xxx` for the account rules, `E2xxx` for validating the
+/// transaction against the ones before it and `E3xxx` for storage failures.
+///
+/// Codes are never reused nor renumbered, new reasons get new codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RejectionCode {
+    MalformedRecord,
+    UnknownTransactionType,
+    MissingAmount,
+    MissingDestination,
+    InvalidAmount,
+    ExcessAmountPrecision,
+    AmountOverflow,
+    AdministrativeTransactionNotAllowed,
+    NegativeAmount,
+    ZeroAmount,
+
+    InsufficientFunds,
+    AccountFrozen,
+    InsufficientHeldFunds,
+    TransactionNotDisputable,
+    TransactionAlreadyDisputed,
+    TransactionNotDisputed,
+    DisputeAlreadyResolved,
+    DisputeWindowExpired,
+    TransferToSameClient,
+    TransferDestinationFrozen,
+    BalanceOverflow,
+    AccountNotFrozen,
+    AccountClosed,
+    AccountNotEmpty,
+    TransactionNotChargedBack,
+    ChargebackAlreadyRepresented,
+    TransactionNotAuthorization,
+    AuthorizationAlreadyCaptured,
+    AuthorizationExpired,
+    CreditLimitExceeded,
+    VelocityLimitExceeded,
+
+    DuplicateTransaction,
+    ReferencedTransactionNotFound,
+    ReferencedTransactionClientMismatch,
+    InvalidTransactionReference,
+    RejectedByValidator,
+    ClientBlocked,
+
+    VersionConflict,
+    WriteAheadLogFailure,
+    DatabaseFailure,
+    StorageFailure,
+    ScreeningFailure,
+}
+
+impl RejectionCode {
+    /// The stable code of the reason, such as `E1001`
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectionCode::MalformedRecord => "E0001",
+            RejectionCode::UnknownTransactionType => "E0002",
+            RejectionCode::MissingAmount => "E0003",
+            RejectionCode::MissingDestination => "E0004",
+            RejectionCode::InvalidAmount => "E0005",
+            RejectionCode::ExcessAmountPrecision => "E0006",
+            RejectionCode::AmountOverflow => "E0007",
+            RejectionCode::AdministrativeTransactionNotAllowed => "E0008",
+            RejectionCode::NegativeAmount => "E0009",
+            RejectionCode::ZeroAmount => "E0010",
+
+            RejectionCode::InsufficientFunds => "E1001",
+            RejectionCode::AccountFrozen => "E1002",
+            RejectionCode::InsufficientHeldFunds => "E1003",
+            RejectionCode::TransactionNotDisputable => "E1004",
+            RejectionCode::TransactionAlreadyDisputed => "E1005",
+            RejectionCode::TransactionNotDisputed => "E1006",
+            RejectionCode::DisputeAlreadyResolved => "E1007",
+            RejectionCode::DisputeWindowExpired => "E1008",
+            RejectionCode::TransferToSameClient => "E1009",
+            RejectionCode::TransferDestinationFrozen => "E1010",
+            RejectionCode::BalanceOverflow => "E1011",
+            RejectionCode::AccountNotFrozen => "E1012",
+            RejectionCode::AccountClosed => "E1013",
+            RejectionCode::AccountNotEmpty => "E1014",
+            RejectionCode::TransactionNotChargedBack => "E1015",
+            RejectionCode::ChargebackAlreadyRepresented => "E1016",
+            RejectionCode::TransactionNotAuthorization => "E1017",
+            RejectionCode::AuthorizationAlreadyCaptured => "E1018",
+            RejectionCode::AuthorizationExpired => "E1019",
+            RejectionCode::CreditLimitExceeded => "E1020",
+            RejectionCode::VelocityLimitExceeded => "E1021",
+
+            RejectionCode::DuplicateTransaction => "E2001",
+            RejectionCode::ReferencedTransactionNotFound => "E2002",
+            RejectionCode::ReferencedTransactionClientMismatch => "E2003",
+            RejectionCode::InvalidTransactionReference => "E2004",
+            RejectionCode::RejectedByValidator => "E2005",
+            RejectionCode::ClientBlocked => "E2006",
+
+            RejectionCode::VersionConflict => "E3001",
+            RejectionCode::WriteAheadLogFailure => "E3002",
+            RejectionCode::DatabaseFailure => "E3003",
+            RejectionCode::StorageFailure => "E3004",
+            RejectionCode::ScreeningFailure => "E3005",
+        }
+    }
+
+    /// Whether processing the same transaction again might succeed, which is only the case of
+    /// the `E3xxx` failures of the storage or of the services it depends on. Every other
+    /// reason comes from the transaction itself, or from the ones before it, so it would be
+    /// rejected again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            RejectionCode::VersionConflict
+                | RejectionCode::WriteAheadLogFailure
+                | RejectionCode::DatabaseFailure
+                | RejectionCode::StorageFailure
+                | RejectionCode::ScreeningFailure
+        )
+    }
+
+    /// The name of the reason, such as `InsufficientFunds`
+    pub fn name(&self) -> &'static str {
+        match self {
+            RejectionCode::MalformedRecord => "MalformedRecord",
+            RejectionCode::UnknownTransactionType => "UnknownTransactionType",
+            RejectionCode::MissingAmount => "MissingAmount",
+            RejectionCode::MissingDestination => "MissingDestination",
+            RejectionCode::InvalidAmount => "InvalidAmount",
+            RejectionCode::ExcessAmountPrecision => "ExcessAmountPrecision",
+            RejectionCode::AmountOverflow => "AmountOverflow",
+            RejectionCode::AdministrativeTransactionNotAllowed => {
+                "AdministrativeTransactionNotAllowed"
+            }
+            RejectionCode::NegativeAmount => "NegativeAmount",
+            RejectionCode::ZeroAmount => "ZeroAmount",
+
+            RejectionCode::InsufficientFunds => "InsufficientFunds",
+            RejectionCode::AccountFrozen => "AccountFrozen",
+            RejectionCode::InsufficientHeldFunds => "InsufficientHeldFunds",
+            RejectionCode::TransactionNotDisputable => "TransactionNotDisputable",
+            RejectionCode::TransactionAlreadyDisputed => "TransactionAlreadyDisputed",
+            RejectionCode::TransactionNotDisputed => "TransactionNotDisputed",
+            RejectionCode::DisputeAlreadyResolved => "DisputeAlreadyResolved",
+            RejectionCode::DisputeWindowExpired => "DisputeWindowExpired",
+            RejectionCode::TransferToSameClient => "TransferToSameClient",
+            RejectionCode::TransferDestinationFrozen => "TransferDestinationFrozen",
+            RejectionCode::BalanceOverflow => "BalanceOverflow",
+            RejectionCode::AccountNotFrozen => "AccountNotFrozen",
+            RejectionCode::AccountClosed => "AccountClosed",
+            RejectionCode::AccountNotEmpty => "AccountNotEmpty",
+            RejectionCode::TransactionNotChargedBack => "TransactionNotChargedBack",
+            RejectionCode::ChargebackAlreadyRepresented => "ChargebackAlreadyRepresented",
+            RejectionCode::TransactionNotAuthorization => "TransactionNotAuthorization",
+            RejectionCode::AuthorizationAlreadyCaptured => "AuthorizationAlreadyCaptured",
+            RejectionCode::AuthorizationExpired => "AuthorizationExpired",
+            RejectionCode::CreditLimitExceeded => "CreditLimitExceeded",
+            RejectionCode::VelocityLimitExceeded => "VelocityLimitExceeded",
+
+            RejectionCode::DuplicateTransaction => "DuplicateTransaction",
+            RejectionCode::ReferencedTransactionNotFound => "ReferencedTransactionNotFound",
+            RejectionCode::ReferencedTransactionClientMismatch => {
+                "ReferencedTransactionClientMismatch"
+            }
+            RejectionCode::InvalidTransactionReference => "InvalidTransactionReference",
+            RejectionCode::RejectedByValidator => "RejectedByValidator",
+            RejectionCode::ClientBlocked => "ClientBlocked",
+
+            RejectionCode::VersionConflict => "VersionConflict",
+            RejectionCode::WriteAheadLogFailure => "WriteAheadLogFailure",
+            RejectionCode::DatabaseFailure => "DatabaseFailure",
+            RejectionCode::StorageFailure => "StorageFailure",
+            RejectionCode::ScreeningFailure => "ScreeningFailure",
+        }
+    }
+}
+
+impl fmt::Display for RejectionCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.name())
+    }
+}
+
+/// Errors which can cause a transaction to be rejected
+pub trait TRejectionReason {
+    fn rejection_code(&self) -> RejectionCode;
+}
+
+impl TRejectionReason for RecordParseError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            RecordParseError::MalformedRecord(_) => RejectionCode::MalformedRecord,
+            RecordParseError::UnknownTransactionType(_) => RejectionCode::UnknownTransactionType,
+            RecordParseError::MissingAmount => RejectionCode::MissingAmount,
+            RecordParseError::MissingDestination => RejectionCode::MissingDestination,
+            RecordParseError::NegativeAmount(_) => RejectionCode::NegativeAmount,
+            RecordParseError::ZeroAmount => RejectionCode::ZeroAmount,
+            RecordParseError::AdministrativeTransaction(_) => {
+                RejectionCode::AdministrativeTransactionNotAllowed
+            }
+            RecordParseError::InvalidAmount(err) => err.rejection_code(),
+        }
+    }
+}
+
+impl TRejectionReason for AmountParseError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            AmountParseError::InvalidFormat(_) => RejectionCode::InvalidAmount,
+            AmountParseError::ExcessPrecision(..) => RejectionCode::ExcessAmountPrecision,
+            AmountParseError::Overflow => RejectionCode::AmountOverflow,
+        }
+    }
+}
+
+impl TRejectionReason for ClientOperationError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            ClientOperationError::AccountFrozen => RejectionCode::AccountFrozen,
+            ClientOperationError::AccountNotFrozen => RejectionCode::AccountNotFrozen,
+            ClientOperationError::AccountClosed => RejectionCode::AccountClosed,
+            ClientOperationError::AccountNotEmpty(..) => RejectionCode::AccountNotEmpty,
+            ClientOperationError::WithdrawError(WithdrawFundsError::NotEnoughFunds(..))
+            | ClientOperationError::AuthorizeError(AuthorizeError::NotEnoughFunds(..)) => {
+                RejectionCode::InsufficientFunds
+            }
+            ClientOperationError::WithdrawError(WithdrawFundsError::CreditLimitExceeded(..)) => {
+                RejectionCode::CreditLimitExceeded
+            }
+            ClientOperationError::ChargebackError(ChargeBackError::NotEnoughHeldFunds(..))
+            | ClientOperationError::ResolveError(ResolveError::NotEnoughHeldFunds(..))
+            | ClientOperationError::CaptureError(CaptureError::NotEnoughHeldFunds(..))
+            | ClientOperationError::ReleaseError(ReleaseError::NotEnoughHeldFunds(..)) => {
+                RejectionCode::InsufficientHeldFunds
+            }
+            ClientOperationError::DepositError(DepositFundsError::Overflow(..))
+            | ClientOperationError::WithdrawError(WithdrawFundsError::Overflow(..))
+            | ClientOperationError::DisputeError(DisputeFundsError::Overflow(..))
+            | ClientOperationError::ChargebackError(ChargeBackError::Overflow(..))
+            | ClientOperationError::ResolveError(ResolveError::Overflow(..))
+            | ClientOperationError::RepresentmentError(RepresentmentError::Overflow(..))
+            | ClientOperationError::AuthorizeError(AuthorizeError::Overflow(..))
+            | ClientOperationError::CaptureError(CaptureError::Overflow(..))
+            | ClientOperationError::ReleaseError(ReleaseError::Overflow(..))
+            | ClientOperationError::FeeError(FeeError::Overflow(..)) => {
+                RejectionCode::BalanceOverflow
+            }
+        }
+    }
+}
+
+impl TRejectionReason for TransactionDisputeError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            TransactionDisputeError::TransactionNotDisputable => {
+                RejectionCode::TransactionNotDisputable
+            }
+            TransactionDisputeError::ProvidedTransactionNotDispute
+            | TransactionDisputeError::TransactionNotDisputingThisOne(..)
+            | TransactionDisputeError::TransactionTargettingWrongClient(..) => {
+                RejectionCode::InvalidTransactionReference
+            }
+        }
+    }
+}
+
+impl TRejectionReason for TransactionResolveDisputeError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            TransactionResolveDisputeError::DisputeError(err) => err.rejection_code(),
+            TransactionResolveDisputeError::ProvidedTransactionNotResolution
+            | TransactionResolveDisputeError::TransactionNotResolvingThisOne(..) => {
+                RejectionCode::InvalidTransactionReference
+            }
+        }
+    }
+}
+
+impl TRejectionReason for TransactionRepresentmentError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            TransactionRepresentmentError::ProvidedTransactionNotRepresentment
+            | TransactionRepresentmentError::TransactionNotRepresentingThisOne(..) => {
+                RejectionCode::InvalidTransactionReference
+            }
+        }
+    }
+}
+
+impl TRejectionReason for TransactionCaptureError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            TransactionCaptureError::TransactionNotAuthorization => {
+                RejectionCode::TransactionNotAuthorization
+            }
+            TransactionCaptureError::AuthorizationAlreadyCaptured => {
+                RejectionCode::AuthorizationAlreadyCaptured
+            }
+            TransactionCaptureError::AuthorizationExpired => RejectionCode::AuthorizationExpired,
+            TransactionCaptureError::ProvidedTransactionNotCapture
+            | TransactionCaptureError::TransactionNotCapturingThisOne(..)
+            | TransactionCaptureError::TransactionTargettingWrongClient(..) => {
+                RejectionCode::InvalidTransactionReference
+            }
+        }
+    }
+}
+
+impl TRejectionReason for IllegalDisputeTransition {
+    fn rejection_code(&self) -> RejectionCode {
+        match (self.from, self.to) {
+            (_, DisputeStage::Open) => RejectionCode::TransactionAlreadyDisputed,
+            (DisputeStage::NotDisputed, DisputeStage::Resolved | DisputeStage::ChargedBack) => {
+                RejectionCode::TransactionNotDisputed
+            }
+            (_, DisputeStage::Resolved | DisputeStage::ChargedBack) => {
+                RejectionCode::DisputeAlreadyResolved
+            }
+            (DisputeStage::Represented, DisputeStage::Represented) => {
+                RejectionCode::ChargebackAlreadyRepresented
+            }
+            (_, DisputeStage::Represented | DisputeStage::NotDisputed) => {
+                RejectionCode::TransactionNotChargedBack
+            }
+        }
+    }
+}
+
+impl TRejectionReason for TransactionError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            TransactionError::DisputeError(err) => err.rejection_code(),
+            TransactionError::ResolveDisputeError(err) => err.rejection_code(),
+            TransactionError::RepresentmentError(err) => err.rejection_code(),
+            TransactionError::IllegalDisputeTransition(err) => err.rejection_code(),
+            TransactionError::CaptureError(err) => err.rejection_code(),
+            TransactionError::IllegalAmountCheck => RejectionCode::InvalidTransactionReference,
+        }
+    }
+}
+
+impl TRejectionReason for TransactionProcessingError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            TransactionProcessingError::ClientError(err) => err.rejection_code(),
+            TransactionProcessingError::TransactionError(err) => err.rejection_code(),
+            TransactionProcessingError::DisputedTransactionDoesNotExist(_)
+            | TransactionProcessingError::SettledDisputedTransactionDoesNotExist(_)
+            | TransactionProcessingError::RepresentedTransactionDoesNotExist(_)
+            | TransactionProcessingError::CapturedTransactionDoesNotExist(_) => {
+                RejectionCode::ReferencedTransactionNotFound
+            }
+            TransactionProcessingError::TransactionNoLongerDisputable(_)
+            | TransactionProcessingError::DisputeWindowExpired(..) => {
+                RejectionCode::DisputeWindowExpired
+            }
+            TransactionProcessingError::AuthorizationExpired(..) => {
+                RejectionCode::AuthorizationExpired
+            }
+            TransactionProcessingError::WithdrawalDisputeIgnored(_) => {
+                RejectionCode::TransactionNotDisputable
+            }
+            TransactionProcessingError::DuplicateTransaction(_) => {
+                RejectionCode::DuplicateTransaction
+            }
+            TransactionProcessingError::ReferencedTransactionClientMismatch(..) => {
+                RejectionCode::ReferencedTransactionClientMismatch
+            }
+            TransactionProcessingError::TransferToSameClient(_) => {
+                RejectionCode::TransferToSameClient
+            }
+            TransactionProcessingError::TransferInsufficientFunds(..) => {
+                RejectionCode::InsufficientFunds
+            }
+            TransactionProcessingError::TransferDestinationFrozen(..) => {
+                RejectionCode::TransferDestinationFrozen
+            }
+            TransactionProcessingError::TransferDestinationClosed(..) => {
+                RejectionCode::AccountClosed
+            }
+            TransactionProcessingError::VelocityLimitExceeded(..) => {
+                RejectionCode::VelocityLimitExceeded
+            }
+            TransactionProcessingError::ValidationError(_) => RejectionCode::RejectedByValidator,
+            TransactionProcessingError::ClientBlocked(..) => RejectionCode::ClientBlocked,
+            TransactionProcessingError::ScreeningError(_) => RejectionCode::ScreeningFailure,
+            TransactionProcessingError::WriteAheadLogError(_) => {
+                RejectionCode::WriteAheadLogFailure
+            }
+            TransactionProcessingError::RepositoryError(err) => err.rejection_code(),
+        }
+    }
+}
+
+impl TRejectionReason for ClientVersionConflict {
+    fn rejection_code(&self) -> RejectionCode {
+        RejectionCode::VersionConflict
+    }
+}
+
+impl TRejectionReason for RepoError {
+    fn rejection_code(&self) -> RejectionCode {
+        match self {
+            RepoError::VersionConflict(conflict) => conflict.rejection_code(),
+            RepoError::BackendError(_) => RejectionCode::DatabaseFailure,
+            RepoError::IoError(_) | RepoError::InvalidData(_) => RejectionCode::StorageFailure,
+        }
+    }
+}
+
+impl TRejectionReason for WalError {
+    fn rejection_code(&self) -> RejectionCode {
+        RejectionCode::WriteAheadLogFailure
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl TRejectionReason for crate::infrastructure::postgres::PostgresError {
+    fn rejection_code(&self) -> RejectionCode {
+        RejectionCode::DatabaseFailure
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl TRejectionReason for crate::infrastructure::sqlite::SqliteError {
+    fn rejection_code(&self) -> RejectionCode {
+        RejectionCode::DatabaseFailure
+    }
+}
+
+#[cfg(test)]
+mod rejections_tests {
+    use crate::models::client::{ClientOperationError, WithdrawFundsError};
+    use crate::models::{MoneyType, TransactionID};
+    use crate::rejections::{RejectionCode, TRejectionReason};
+    use crate::services::transaction_service::TransactionProcessingError;
+    use crate::tx_reception::RecordParseError;
+
+    #[test]
+    fn test_rejection_codes() {
+        let insufficient_funds =
+            TransactionProcessingError::ClientError(ClientOperationError::WithdrawError(
+                WithdrawFundsError::NotEnoughFunds(MoneyType::new(1), MoneyType::new(2)),
+            ));
+
+        assert_eq!(
+            insufficient_funds.rejection_code().to_string(),
+            "E1001 InsufficientFunds"
+        );
+
+        // Transfers are rejected with the same code as withdrawals, for the same reason
+        assert_eq!(
+            TransactionProcessingError::TransferInsufficientFunds(
+                TransactionID(1),
+                MoneyType::new(1),
+                MoneyType::new(2)
+            )
+            .rejection_code(),
+            RejectionCode::InsufficientFunds
+        );
+
+        assert_eq!(
+            RecordParseError::UnknownTransactionType("refund".to_string())
+                .rejection_code()
+                .code(),
+            "E0002"
+        );
+    }
+
+    #[test]
+    fn test_retryable_rejections() {
+        assert!(RejectionCode::DatabaseFailure.is_retryable());
+        assert!(RejectionCode::WriteAheadLogFailure.is_retryable());
+
+        assert!(!RejectionCode::MalformedRecord.is_retryable());
+        assert!(!RejectionCode::InsufficientFunds.is_retryable());
+        assert!(!RejectionCode::DuplicateTransaction.is_retryable());
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::report::{RunReport, TypeCounts};
+
+/// Describes how the output of a run was produced, so it can be tied back to the exact inputs,
+/// configuration and version of the engine which produced it.
+#[derive(Serialize, Debug)]
+pub struct RunManifest {
+    engine_version: &'static str,
+    /// The SHA-256 of the configuration of the run
+    config_hash: String,
+    inputs: Vec<FileDigest>,
+    outputs: Vec<FileDigest>,
+    processed: u64,
+    rejected: u64,
+    /// The amount of rejected transactions for each rejection code
+    rejections: BTreeMap<&'static str, u64>,
+    warnings: BTreeMap<&'static str, u64>,
+    /// The amount of accepted and rejected transactions of each type
+    transaction_types: BTreeMap<&'static str, TypeCounts>,
+    value_moved: String,
+    frozen_accounts: u64,
+    /// Seconds since the unix epoch
+    started_at: u64,
+    duration_ms: u128,
+}
+
+/// The checksum of a file read or written by a run
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileDigest {
+    path: String,
+    bytes: u64,
+    sha256: String,
+}
+
+impl FileDigest {
+    /// Digest the file at the given path
+    pub fn of_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let mut file = std::fs::File::open(path)?;
+
+        let mut hasher = Sha256::new();
+        let mut buffer = [0; 8192];
+        let mut bytes = 0;
+
+        loop {
+            let read = file.read(&mut buffer)?;
+
+            if read == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..read]);
+            bytes += read as u64;
+        }
+
+        Ok(Self {
+            path: path.display().to_string(),
+            bytes,
+            sha256: hex(&hasher.finalize()),
+        })
+    }
+
+    /// Digest content which was not written to a file, such as the standard output
+    pub fn of_bytes(name: impl Into<String>, content: &[u8]) -> Self {
+        Self {
+            path: name.into(),
+            bytes: content.len() as u64,
+            sha256: hex(&Sha256::digest(content)),
+        }
+    }
+}
+
+/// Collects the parts of the manifest which are known before the end of a run
+pub struct ManifestRecorder {
+    config_hash: String,
+    inputs: Vec<FileDigest>,
+    outputs: Vec<FileDigest>,
+    started_at: SystemTime,
+    started: Instant,
+}
+
+impl ManifestRecorder {
+    /// Start timing a run with the given configuration (e.g. its command line arguments)
+    pub fn start<I, S>(config: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut hasher = Sha256::new();
+
+        for setting in config {
+            hasher.update(setting.as_ref().as_bytes());
+            // Separate the settings, so ["ab", "c"] and ["a", "bc"] don't hash the same
+            hasher.update([0]);
+        }
+
+        Self {
+            config_hash: hex(&hasher.finalize()),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn record_input(&mut self, digest: FileDigest) {
+        self.inputs.push(digest);
+    }
+
+    pub fn record_output(&mut self, digest: FileDigest) {
+        self.outputs.push(digest);
+    }
+
+    /// Stop timing the run, which ended with the given report
+    pub fn finish(self, report: &RunReport) -> RunManifest {
+        RunManifest {
+            engine_version: env!("CARGO_PKG_VERSION"),
+            config_hash: self.config_hash,
+            inputs: self.inputs,
+            outputs: self.outputs,
+            processed: report.processed,
+            rejected: report.rejected,
+            rejections: report
+                .rejections
+                .iter()
+                .map(|(rejection, count)| (rejection.code(), *count))
+                .collect(),
+            warnings: report.warnings.clone(),
+            transaction_types: report.summary.by_type.clone(),
+            value_moved: report
+                .summary
+                .value_moved
+                .display(report.summary.precision)
+                .to_string(),
+            frozen_accounts: report.summary.frozen_accounts,
+            started_at: self
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_secs(),
+            duration_ms: self.started.elapsed().as_millis(),
+        }
+    }
+}
+
+impl RunManifest {
+    pub fn write(&self, writer: impl Write) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(writer, self)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use std::collections::BTreeMap;
+
+    use crate::models::MoneyType;
+    use crate::rejections::RejectionCode;
+    use crate::report::manifest::{FileDigest, ManifestRecorder};
+    use crate::report::{ProcessingSummary, RunReport, TypeCounts};
+
+    #[test]
+    fn test_run_manifest() {
+        let mut recorder = ManifestRecorder::start(["input.csv", "--wal=run.wal"]);
+
+        recorder.record_input(FileDigest::of_bytes("input.csv", b"abc"));
+        recorder.record_output(FileDigest::of_bytes("stdout", b""));
+
+        let report = RunReport {
+            processed: 3,
+            rejected: 1,
+            rejections: BTreeMap::from([(RejectionCode::InsufficientFunds, 1)]),
+            warnings: BTreeMap::from([("amount-rounded", 2)]),
+            summary: ProcessingSummary {
+                by_type: BTreeMap::from([(
+                    "withdrawal",
+                    TypeCounts {
+                        accepted: 2,
+                        rejected: 1,
+                    },
+                )]),
+                value_moved: MoneyType::new(25000),
+                frozen_accounts: 0,
+                precision: 4,
+            },
+        };
+
+        let manifest = serde_json::to_value(recorder.finish(&report)).unwrap();
+
+        assert_eq!(manifest["engine_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            manifest["config_hash"],
+            ManifestRecorder::start(["input.csv", "--wal=run.wal"]).config_hash
+        );
+        assert_ne!(
+            manifest["config_hash"],
+            ManifestRecorder::start(["input.csv--wal=", "run.wal"]).config_hash
+        );
+        assert_eq!(
+            manifest["inputs"][0]["sha256"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(manifest["inputs"][0]["bytes"], 3);
+        assert_eq!(
+            manifest["outputs"][0]["sha256"],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(manifest["processed"], 3);
+        assert_eq!(manifest["rejections"]["E1001"], 1);
+        assert_eq!(manifest["warnings"]["amount-rounded"], 2);
+        assert_eq!(manifest["transaction_types"]["withdrawal"]["rejected"], 1);
+        assert_eq!(manifest["value_moved"], "2.5000");
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use futures::StreamExt;
+
+use crate::events::TEffectsHandler;
+use crate::models::client::ClientAccountStatus;
+use crate::models::effects::{Effects, ExecutionMode, TransactionChange};
+use crate::models::transactions::Transaction;
+use crate::models::MoneyType;
+use crate::rejections::RejectionCode;
+use crate::repositories::clients::TClientRepository;
+use crate::repositories::RepoError;
+use crate::FLOATING_POINT_ACC;
+
+#[cfg(feature = "json")]
+pub mod manifest;
+
+/// Summary of a run of the engine, reported once all of the transactions have been handled
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RunReport {
+    /// The amount of transactions handed to the engine
+    pub processed: u64,
+    /// The amount of those transactions which were rejected with an error
+    pub rejected: u64,
+    /// The amount of rejected transactions for each reason
+    pub rejections: BTreeMap<RejectionCode, u64>,
+    /// The amount of warnings of each kind
+    pub warnings: BTreeMap<&'static str, u64>,
+    pub summary: ProcessingSummary,
+}
+
+impl RunReport {
+    pub fn total_warnings(&self) -> u64 {
+        self.warnings.values().sum()
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Processed {} transactions ({} rejected) with {} warnings",
+            self.processed,
+            self.rejected,
+            self.total_warnings()
+        )?;
+
+        for (rejection, count) in &self.rejections {
+            writeln!(f, "  {}: {}", rejection, count)?;
+        }
+
+        for (kind, count) in &self.warnings {
+            writeln!(f, "  {}: {}", kind, count)?;
+        }
+
+        write!(f, "{}", self.summary)
+    }
+}
+
+/// What the transactions of a run did to the accounts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessingSummary {
+    /// The amount of accepted and rejected transactions of each type
+    pub by_type: BTreeMap<&'static str, TypeCounts>,
+    /// The sum of the amounts of the accepted deposits, withdrawals and transfers
+    pub value_moved: MoneyType,
+    /// The amount of accounts which were frozen at the end of the run
+    pub frozen_accounts: u64,
+    /// The amount of decimal places of the amounts of the run
+    pub precision: u32,
+}
+
+impl Default for ProcessingSummary {
+    fn default() -> Self {
+        Self {
+            by_type: BTreeMap::new(),
+            value_moved: MoneyType::ZERO,
+            frozen_accounts: 0,
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TypeCounts {
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+impl fmt::Display for ProcessingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (tx_type, counts) in &self.by_type {
+            writeln!(
+                f,
+                "  {}: {} accepted, {} rejected",
+                tx_type, counts.accepted, counts.rejected
+            )?;
+        }
+
+        writeln!(
+            f,
+            "Moved {} in total, with {} accounts frozen",
+            self.value_moved.display(self.precision),
+            self.frozen_accounts
+        )
+    }
+}
+
+/// Collects the [`ProcessingSummary`] of a run, from the effects of the accepted transactions
+/// and the rejected transactions it is told about.
+///
+/// Transactions which are neither (such as ignored duplicates) are left out.
+#[derive(Default)]
+pub struct SummaryRecorder {
+    summary: Mutex<ProcessingSummary>,
+}
+
+impl SummaryRecorder {
+    /// A recorder for a run whose amounts have `precision` decimal places
+    pub fn new(precision: u32) -> Self {
+        Self {
+            summary: Mutex::new(ProcessingSummary {
+                precision,
+                ..ProcessingSummary::default()
+            }),
+        }
+    }
+
+    /// Record a transaction which was rejected for good
+    pub fn record_rejected(&self, transaction: &Transaction) {
+        self.update(|summary| {
+            summary
+                .by_type
+                .entry(transaction.tx_type().name())
+                .or_default()
+                .rejected += 1;
+        });
+    }
+
+    /// The summary of what was recorded, with the frozen accounts counted from the given
+    /// repository
+    pub async fn finish(
+        &self,
+        client_repository: &impl TClientRepository,
+    ) -> Result<ProcessingSummary, RepoError> {
+        let mut clients = client_repository.find_all_clients().await?;
+        let mut frozen_accounts = 0;
+
+        while let Some(client) = clients.next().await {
+            if *client.lock().await.account_status() == ClientAccountStatus::Frozen {
+                frozen_accounts += 1;
+            }
+        }
+
+        let mut summary = match self.summary.lock() {
+            Ok(summary) => summary.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+
+        summary.frozen_accounts = frozen_accounts;
+
+        Ok(summary)
+    }
+
+    fn update(&self, update: impl FnOnce(&mut ProcessingSummary)) {
+        let mut summary = match self.summary.lock() {
+            Ok(summary) => summary,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        update(&mut summary);
+    }
+}
+
+impl TEffectsHandler for SummaryRecorder {
+    fn handle(&self, effects: &Effects, _mode: ExecutionMode) {
+        // Transfers and fees are recorded along with the debit of their source, and expired
+        // authorizations were already recorded when they were accepted
+        if let TransactionChange::Credit(_)
+        | TransactionChange::CollectFee(_)
+        | TransactionChange::Expire(_) = effects.transaction_change
+        {
+            return;
+        }
+
+        let transaction = effects.transaction();
+
+        self.update(|summary| {
+            summary
+                .by_type
+                .entry(transaction.tx_type().name())
+                .or_default()
+                .accepted += 1;
+
+            if let (Ok(amount), TransactionChange::Store(_)) =
+                (transaction.amount(), &effects.transaction_change)
+            {
+                summary.value_moved = summary
+                    .value_moved
+                    .checked_add(amount)
+                    .unwrap_or(MoneyType::MAX);
+            }
+        });
+    }
+}
+
+/// Counts the rejected transactions for each reason, for the report of a run
+#[derive(Default)]
+pub struct RejectionCounter {
+    counts: Mutex<BTreeMap<RejectionCode, u64>>,
+}
+
+impl RejectionCounter {
+    pub fn record(&self, rejection: RejectionCode) {
+        let mut counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        *counts.entry(rejection).or_default() += 1;
+    }
+
+    /// The amount of rejections for each reason recorded so far
+    pub fn counts(&self) -> BTreeMap<RejectionCode, u64> {
+        match self.counts.lock() {
+            Ok(counts) => counts.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod report_tests {
+    use std::sync::Arc;
+
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::report::{SummaryRecorder, TypeCounts};
+    use crate::services::transaction_service::{TTransactionService, TransactionService};
+    use crate::ShareableClientRepository;
+
+    #[tokio::test]
+    async fn test_processing_summary() {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let mut tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default());
+
+        let recorder = Arc::new(SummaryRecorder::default());
+
+        tx_service.register_effects_handler(recorder.clone());
+
+        let transaction = |client, tx_id, tx_type| {
+            Transaction::builder()
+                .with_client_id(ClientID(client))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(tx_type)
+                .build()
+        };
+
+        let transactions = [
+            transaction(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(30000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            transaction(
+                1,
+                2,
+                TransactionType::Transfer {
+                    to_client: ClientID(2),
+                    amount: MoneyType::new(10000),
+                },
+            ),
+            transaction(
+                2,
+                3,
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(50000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            transaction(1, 1, TransactionType::Dispute),
+            transaction(1, 1, TransactionType::Chargeback),
+        ];
+
+        for transaction in transactions {
+            if tx_service
+                .process_transaction(transaction.clone())
+                .await
+                .is_err()
+            {
+                recorder.record_rejected(&transaction);
+            }
+        }
+
+        let summary = recorder.finish(&client_repo).await.unwrap();
+
+        assert_eq!(
+            summary.by_type.get("withdrawal"),
+            Some(&TypeCounts {
+                accepted: 0,
+                rejected: 1,
+            })
+        );
+        // The credit of the transfer's destination is not a transaction of its own
+        assert_eq!(summary.by_type["transfer"].accepted, 1);
+        assert_eq!(summary.by_type["chargeback"].accepted, 1);
+        assert_eq!(summary.value_moved, MoneyType::new(40000));
+        assert_eq!(summary.frozen_accounts, 1);
+    }
+}
@@ -1,27 +1,77 @@
 use crate::models::client::Client;
 use crate::models::ClientID;
+use crate::repositories::RepoError;
 use futures::lock::Mutex;
 use futures::stream::BoxStream;
 use mockall::automock;
+use std::future::Future;
 use std::sync::Arc;
+use thiserror::Error;
 
 pub type StoredClient = Arc<Mutex<Client>>;
 
 /// The client repository trait, meant to represent the storage of the client
 /// models.
+///
+/// Every operation can fail, so the repository can be backed by storage over a network or disk.
 #[automock]
 pub trait TClientRepository: Send + Sync {
-    /// Find all of the clients stored in this repository
-    async fn find_all_clients(&self) -> BoxStream<'static, StoredClient>;
+    /// Find all of the clients stored in this repository.
+    ///
+    /// The clients should be yielded as the stream is consumed, rather than all loaded up front,
+    /// so going over millions of them (e.g. to export them) doesn't need them all in memory.
+    fn find_all_clients(
+        &self,
+    ) -> impl Future<Output = Result<BoxStream<'static, StoredClient>, RepoError>> + Send;
+
+    /// Find all of the clients stored in this repository, ordered by their id, so exporting
+    /// them gives the same rows in the same order from one run to the next.
+    ///
+    /// Unlike [`find_all_clients`](Self::find_all_clients), this may have to load every client
+    /// before yielding the first one.
+    fn find_all_clients_sorted(
+        &self,
+    ) -> impl Future<Output = Result<BoxStream<'static, StoredClient>, RepoError>> + Send;
 
-    async fn find_client_by_id(&self, client_id: ClientID) -> Option<StoredClient>;
+    fn find_client_by_id(
+        &self,
+        client_id: ClientID,
+    ) -> impl Future<Output = Result<Option<StoredClient>, RepoError>> + Send;
 
     /// Save the changes made in this stored client instance
     ///
-    /// In order to implement this in a given repository, we should use the Unit Of Work
-    /// pattern.
-    async fn save_client(&self, client: StoredClient);
+    /// The transaction processing saves every client it changed once it's done with them,
+    /// through a [`UnitOfWork`](crate::services::unit_of_work::UnitOfWork), which undoes the
+    /// changes when saving fails.
+    fn save_client(
+        &self,
+        client: StoredClient,
+    ) -> impl Future<Output = Result<(), RepoError>> + Send;
 
     /// Register a client that does not yet exist in the repository
-    async fn store_client(&self, client: Client) -> StoredClient;
+    fn store_client(
+        &self,
+        client: Client,
+    ) -> impl Future<Output = Result<StoredClient, RepoError>> + Send;
+
+    /// Replace the stored client with the given one, as long as the stored version is still
+    /// the expected one (a client which isn't stored is at version 0).
+    ///
+    /// This is meant for writers outside of the transaction processing, which read a client,
+    /// change it and write it back, so they don't silently clobber changes made in the meantime.
+    /// The replaced client is given the version following the expected one, and a change
+    /// based on another version fails with [`RepoError::VersionConflict`].
+    fn save_client_if_version(
+        &self,
+        client: Client,
+        expected_version: u64,
+    ) -> impl Future<Output = Result<StoredClient, RepoError>> + Send;
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("The client {client:?} is at version {current:?}, expected version {expected:?}")]
+pub struct ClientVersionConflict {
+    pub client: ClientID,
+    pub expected: u64,
+    pub current: u64,
 }
@@ -1,2 +1,23 @@
-pub(crate) mod clients;
-pub(crate) mod transactions;
+use std::io;
+
+use thiserror::Error;
+
+use crate::repositories::clients::ClientVersionConflict;
+
+pub mod clients;
+pub mod shareable;
+pub mod transactions;
+pub mod warm_up;
+
+/// The failures of the storage behind the repositories
+#[derive(Error, Debug)]
+pub enum RepoError {
+    #[error("Storage IO error {0:?}")]
+    IoError(#[from] io::Error),
+    #[error("Storage backend error {0}")]
+    BackendError(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("The stored data is not valid: {0}")]
+    InvalidData(String),
+    #[error("Version conflict {0}")]
+    VersionConflict(#[from] ClientVersionConflict),
+}
@@ -0,0 +1,123 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use futures::stream::BoxStream;
+
+use crate::models::client::Client;
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, Timestamp, TransactionID};
+use crate::repositories::clients::{StoredClient, TClientRepository};
+use crate::repositories::transactions::{StoredTX, TTransactionRepository};
+use crate::repositories::RepoError;
+
+/// Transaction repository which can be cloned, sharing the same underlying repository
+pub struct ShareableTransactionRepository<TR> {
+    repo: Arc<TR>,
+}
+
+/// Client repository which can be cloned, sharing the same underlying repository
+pub struct ShareableClientRepository<CR> {
+    repo: Arc<CR>,
+}
+
+impl<TR> From<TR> for ShareableTransactionRepository<TR> {
+    fn from(repo: TR) -> Self {
+        Self {
+            repo: Arc::new(repo),
+        }
+    }
+}
+
+impl<TR> Clone for ShareableTransactionRepository<TR> {
+    fn clone(&self) -> Self {
+        Self {
+            repo: self.repo.clone(),
+        }
+    }
+}
+
+impl<TR> TTransactionRepository for ShareableTransactionRepository<TR>
+where
+    TR: TTransactionRepository,
+{
+    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Result<Option<StoredTX>, RepoError> {
+        self.repo.find_tx_by_id(tx_id).await
+    }
+
+    async fn save_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        self.repo.save_tx(tx).await
+    }
+
+    async fn store_tx(&self, tx: Transaction) -> Result<StoredTX, RepoError> {
+        self.repo.store_tx(tx).await
+    }
+
+    async fn remove_tx(&self, tx: StoredTX) -> Result<(), RepoError> {
+        self.repo.remove_tx(tx).await
+    }
+
+    async fn find_txs_in_range(&self, range: Range<Timestamp>) -> Result<Vec<StoredTX>, RepoError> {
+        self.repo.find_txs_in_range(range).await
+    }
+
+    async fn find_txs_by_client(
+        &self,
+        client_id: ClientID,
+    ) -> Result<BoxStream<'static, StoredTX>, RepoError> {
+        self.repo.find_txs_by_client(client_id).await
+    }
+}
+
+impl<CR> From<CR> for ShareableClientRepository<CR> {
+    fn from(repo: CR) -> Self {
+        Self {
+            repo: Arc::new(repo),
+        }
+    }
+}
+
+impl<CR> Clone for ShareableClientRepository<CR> {
+    fn clone(&self) -> Self {
+        Self {
+            repo: self.repo.clone(),
+        }
+    }
+}
+
+impl<CR> TClientRepository for ShareableClientRepository<CR>
+where
+    CR: TClientRepository,
+{
+    async fn find_all_clients(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        self.repo.find_all_clients().await
+    }
+
+    async fn find_all_clients_sorted(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+        self.repo.find_all_clients_sorted().await
+    }
+
+    async fn find_client_by_id(
+        &self,
+        client_id: ClientID,
+    ) -> Result<Option<StoredClient>, RepoError> {
+        self.repo.find_client_by_id(client_id).await
+    }
+
+    async fn save_client(&self, client: StoredClient) -> Result<(), RepoError> {
+        self.repo.save_client(client).await
+    }
+
+    async fn store_client(&self, client: Client) -> Result<StoredClient, RepoError> {
+        self.repo.store_client(client).await
+    }
+
+    async fn save_client_if_version(
+        &self,
+        client: Client,
+        expected_version: u64,
+    ) -> Result<StoredClient, RepoError> {
+        self.repo
+            .save_client_if_version(client, expected_version)
+            .await
+    }
+}
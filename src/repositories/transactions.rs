@@ -1,30 +1,63 @@
 use futures::lock::Mutex;
+use futures::stream::BoxStream;
 use mockall::automock;
+use std::future::Future;
+use std::ops::Range;
 use std::sync::Arc;
 
 use crate::models::transactions::Transaction;
-use crate::models::TransactionID;
+use crate::models::{ClientID, Timestamp, TransactionID};
+use crate::repositories::RepoError;
 
 pub type StoredTX = Arc<Mutex<Transaction>>;
 
 /// The repository abstraction for the transaction storage layer.
 ///
-/// In this case, this is meant to handle local concurrency, but it basically
-/// only supports in memory storage.
-/// At the moment, the only way I can think of to correctly support offsite repositories
-/// is to make all modifications run by this repository, which would mean we must have
-/// all of the transaction functions "mirrored" here
+/// This is meant to handle local concurrency, with every loaded transaction shared by
+/// everyone working on it. Every operation can fail, so offsite repositories can report
+/// their failures, as long as they write the changes when the transaction is saved.
 #[automock]
 pub trait TTransactionRepository: Send + Sync {
     /// Find a tx by a given ID
-    async fn find_tx_by_id(&self, tx_id: TransactionID) -> Option<StoredTX>;
+    fn find_tx_by_id(
+        &self,
+        tx_id: TransactionID,
+    ) -> impl Future<Output = Result<Option<StoredTX>, RepoError>> + Send;
 
     /// Indicate to the repository that we should save the changes done to the stored transaction
-    /// This could be done with the Unit Of Work pattern or something similar.
-    async fn save_tx(&self, tx: StoredTX);
+    ///
+    /// Called by the [`UnitOfWork`](crate::services::unit_of_work::UnitOfWork) of the
+    /// transaction processing, along with the clients changed by the same transaction.
+    fn save_tx(&self, tx: StoredTX) -> impl Future<Output = Result<(), RepoError>> + Send;
 
     /// Store a tx in the repository
     ///
     /// Store a transaction that is not in the repository into the repository
-    async fn store_tx(&self, tx: Transaction) -> StoredTX;
+    fn store_tx(&self, tx: Transaction)
+        -> impl Future<Output = Result<StoredTX, RepoError>> + Send;
+
+    /// Remove a transaction stored with [`store_tx`](Self::store_tx), as if it was never stored
+    ///
+    /// Called by the [`UnitOfWork`](crate::services::unit_of_work::UnitOfWork) when the
+    /// processing which stored it is undone, so the transaction can be processed again. The
+    /// transaction it replaced, if it reused the id of another one, is not restored.
+    fn remove_tx(&self, tx: StoredTX) -> impl Future<Output = Result<(), RepoError>> + Send;
+
+    /// Find the stored transactions which happened within the given range of time, ordered by
+    /// when they happened.
+    ///
+    /// Transactions without a timestamp are never part of any range.
+    fn find_txs_in_range(
+        &self,
+        range: Range<Timestamp>,
+    ) -> impl Future<Output = Result<Vec<StoredTX>, RepoError>> + Send;
+
+    /// Find the stored transactions of the given client, in the order they were applied to it.
+    ///
+    /// Transfers belong to the client sending the funds, the disputes and their settlements
+    /// are part of the transaction they target.
+    fn find_txs_by_client(
+        &self,
+        client_id: ClientID,
+    ) -> impl Future<Output = Result<BoxStream<'static, StoredTX>, RepoError>> + Send;
 }
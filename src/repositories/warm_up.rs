@@ -0,0 +1,20 @@
+use std::future::Future;
+
+use mockall::automock;
+
+use crate::models::client::Client;
+use crate::models::transactions::Transaction;
+
+/// A persistent store which is able to provide the data required to warm up the in memory
+/// repositories on a cold start.
+#[automock]
+pub trait TWarmUpSource: Send + Sync {
+    /// The `limit` clients with the most recent activity, most active first
+    fn hottest_clients(&self, limit: usize) -> impl Future<Output = Vec<Client>> + Send;
+
+    /// The `limit` most recent transactions that can still be disputed
+    fn recent_disputable_transactions(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = Vec<Transaction>> + Send;
+}
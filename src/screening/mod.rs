@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use thiserror::Error;
+
+use crate::models::ClientID;
+
+#[cfg(feature = "screening")]
+pub mod remote;
+
+/// Decides which clients are blocked from transacting, such as the ones on a sanctions list.
+///
+/// Every client involved in a transaction (both of them, for transfers) is screened before the
+/// transaction is processed, so the answers should be cheap or cached.
+pub trait TScreeningProvider: Send + Sync {
+    /// Whether the client is blocked
+    fn is_blocked(&self, client: ClientID) -> BoxFuture<'_, Result<bool, ScreeningError>>;
+}
+
+impl<P: TScreeningProvider + ?Sized> TScreeningProvider for Arc<P> {
+    fn is_blocked(&self, client: ClientID) -> BoxFuture<'_, Result<bool, ScreeningError>> {
+        (**self).is_blocked(client)
+    }
+}
+
+/// What happens to the transactions of blocked clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScreeningAction {
+    /// Reject them, like any other invalid transaction
+    #[default]
+    Reject,
+    /// Set them aside without applying them, so they can be reviewed
+    Quarantine,
+}
+
+#[derive(Error, Debug)]
+pub enum ScreeningError {
+    #[error("Failed to read the list of blocked clients {0:?}")]
+    IoError(#[from] io::Error),
+    #[error("Line {0} of the list of blocked clients is not a client id: {1:?}")]
+    InvalidEntry(usize, String),
+    #[cfg(feature = "screening")]
+    #[error("Failed to reach the screening service {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[cfg(feature = "screening")]
+    #[error("The screening service answered with {0}")]
+    UnexpectedStatus(reqwest::StatusCode),
+}
+
+/// A fixed list of blocked clients, usually read from a file
+#[derive(Debug, Clone, Default)]
+pub struct BlockedClientList {
+    blocked: HashSet<ClientID>,
+}
+
+impl BlockedClientList {
+    pub fn new(blocked: impl IntoIterator<Item = ClientID>) -> Self {
+        Self {
+            blocked: blocked.into_iter().collect(),
+        }
+    }
+
+    /// Read the list from a file with a client id per line. Empty lines and the lines starting
+    /// with `#` are skipped.
+    pub async fn from_file(path: impl AsRef<Path>) -> Result<Self, ScreeningError> {
+        let contents = tokio::fs::read_to_string(path).await?;
+
+        contents.parse()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocked.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocked.is_empty()
+    }
+}
+
+impl std::str::FromStr for BlockedClientList {
+    type Err = ScreeningError;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let blocked = contents
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| {
+                line.parse()
+                    .map(ClientID)
+                    .map_err(|_| ScreeningError::InvalidEntry(number, line.to_string()))
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        Ok(Self { blocked })
+    }
+}
+
+impl TScreeningProvider for BlockedClientList {
+    fn is_blocked(&self, client: ClientID) -> BoxFuture<'_, Result<bool, ScreeningError>> {
+        Box::pin(futures::future::ready(Ok(self.blocked.contains(&client))))
+    }
+}
+
+#[cfg(test)]
+mod screening_tests {
+    use crate::models::ClientID;
+    use crate::screening::{BlockedClientList, ScreeningError, TScreeningProvider};
+
+    #[tokio::test]
+    async fn test_blocked_client_list() {
+        let list = "# Sanctioned on 2024-01-01\n3\n\n  7 \n"
+            .parse::<BlockedClientList>()
+            .unwrap();
+
+        assert_eq!(list.len(), 2);
+        assert!(list.is_blocked(ClientID(3)).await.unwrap());
+        assert!(list.is_blocked(ClientID(7)).await.unwrap());
+        assert!(!list.is_blocked(ClientID(1)).await.unwrap());
+
+        assert!(matches!(
+            "3\nclient 4\n".parse::<BlockedClientList>(),
+            Err(ScreeningError::InvalidEntry(2, _))
+        ));
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+
+use crate::models::ClientID;
+use crate::screening::{ScreeningError, TScreeningProvider};
+
+/// How long to wait for the screening service to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Asks a screening service whether each client is blocked.
+///
+/// The service is sent a `GET` to `<endpoint>/<client>`, and answers with a JSON object such as
+/// `{"blocked": true}`. Clients it doesn't know (`404 Not Found`) are not blocked, while any
+/// other failure fails the screening, so no transaction goes through unscreened.
+pub struct RemoteScreeningProvider {
+    client: reqwest::Client,
+    endpoint: Url,
+}
+
+#[derive(Deserialize)]
+struct ScreeningResponse {
+    blocked: bool,
+}
+
+impl RemoteScreeningProvider {
+    pub fn new(endpoint: Url) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("The HTTP client has a valid configuration");
+
+        Self { client, endpoint }
+    }
+
+    fn client_url(&self, client: ClientID) -> Url {
+        let mut url = self.endpoint.clone();
+
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(&client.to_string());
+        }
+
+        url
+    }
+
+    async fn screen(&self, client: ClientID) -> Result<bool, ScreeningError> {
+        let response = self.client.get(self.client_url(client)).send().await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => {
+                Ok(response.json::<ScreeningResponse>().await?.blocked)
+            }
+            status => Err(ScreeningError::UnexpectedStatus(status)),
+        }
+    }
+}
+
+impl TScreeningProvider for RemoteScreeningProvider {
+    fn is_blocked(&self, client: ClientID) -> BoxFuture<'_, Result<bool, ScreeningError>> {
+        Box::pin(self.screen(client))
+    }
+}
+
+#[cfg(test)]
+mod remote_screening_tests {
+    use reqwest::{StatusCode, Url};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use crate::models::ClientID;
+    use crate::screening::remote::RemoteScreeningProvider;
+    use crate::screening::{ScreeningError, TScreeningProvider};
+
+    /// A screening service answering each request with the next of the given responses,
+    /// forwarding the paths it was asked for
+    async fn service(responses: Vec<(u16, &'static str)>) -> (Url, flume::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/screening/",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let (paths, received) = flume::unbounded();
+
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+
+                let mut request_line = String::new();
+
+                stream.read_line(&mut request_line).await.unwrap();
+
+                loop {
+                    let mut line = String::new();
+
+                    stream.read_line(&mut line).await.unwrap();
+
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+
+                let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+
+                paths.send(path).unwrap();
+
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_remote_screening() {
+        let (url, received) =
+            service(vec![(200, r#"{"blocked":true}"#), (404, ""), (503, "")]).await;
+
+        let provider = RemoteScreeningProvider::new(url);
+
+        assert!(provider.is_blocked(ClientID(3)).await.unwrap());
+        assert!(!provider.is_blocked(ClientID(4)).await.unwrap());
+
+        assert!(matches!(
+            provider.is_blocked(ClientID(5)).await,
+            Err(ScreeningError::UnexpectedStatus(
+                StatusCode::SERVICE_UNAVAILABLE
+            ))
+        ));
+
+        assert_eq!(
+            received.drain().collect::<Vec<_>>(),
+            vec!["/screening/3", "/screening/4", "/screening/5"]
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// Provides the credentials needed to connect to our storage and brokers, so they never have
+/// to be written into the configuration.
+///
+/// Secrets are identified by a short, lowercase, name such as `database_url`.
+pub trait TSecretsProvider: Send + Sync {
+    /// Look up a secret, which is `None` if the provider does not have it
+    fn secret(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<Option<String>, SecretsError>> + Send;
+
+    /// Look up a secret which must be present
+    fn required_secret(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<String, SecretsError>> + Send {
+        async move {
+            self.secret(name)
+                .await?
+                .ok_or_else(|| SecretsError::MissingSecret(name.to_string()))
+        }
+    }
+}
+
+/// Reads the secrets from environment variables, named after the secret in uppercase with the
+/// given prefix (`database_url` is read from `TRANSACTIONER_DATABASE_URL` by default).
+pub struct EnvSecretsProvider {
+    prefix: String,
+}
+
+impl Default for EnvSecretsProvider {
+    fn default() -> Self {
+        Self::new("TRANSACTIONER_")
+    }
+}
+
+impl EnvSecretsProvider {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    fn variable(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name.to_uppercase())
+    }
+}
+
+impl TSecretsProvider for EnvSecretsProvider {
+    async fn secret(&self, name: &str) -> Result<Option<String>, SecretsError> {
+        match std::env::var(self.variable(name)) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(std::env::VarError::NotUnicode(_)) => {
+                Err(SecretsError::InvalidSecret(name.to_string()))
+            }
+        }
+    }
+}
+
+/// Reads every secret from a file named after it in the given directory, as mounted by
+/// Docker and Kubernetes secrets (e.g. `/run/secrets/database_url`).
+///
+/// Trailing newlines are not part of the secret.
+pub struct FileSecretsProvider {
+    directory: PathBuf,
+}
+
+impl FileSecretsProvider {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl TSecretsProvider for FileSecretsProvider {
+    async fn secret(&self, name: &str) -> Result<Option<String>, SecretsError> {
+        // Names come from our own code, but we still don't want them escaping the directory
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(SecretsError::InvalidSecret(name.to_string()));
+        }
+
+        match tokio::fs::read_to_string(self.directory.join(name)).await {
+            Ok(secret) => Ok(Some(secret.trim_end_matches(['\r', '\n']).to_string())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Looks up each secret in the first provider, falling back to the second one when the first
+/// does not have it
+pub struct ChainedSecretsProvider<P, F> {
+    primary: P,
+    fallback: F,
+}
+
+impl<P, F> ChainedSecretsProvider<P, F> {
+    pub fn new(primary: P, fallback: F) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+impl<P, F> TSecretsProvider for ChainedSecretsProvider<P, F>
+where
+    P: TSecretsProvider,
+    F: TSecretsProvider,
+{
+    async fn secret(&self, name: &str) -> Result<Option<String>, SecretsError> {
+        match self.primary.secret(name).await? {
+            Some(secret) => Ok(Some(secret)),
+            None => self.fallback.secret(name).await,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SecretsError {
+    #[error("The secret {0:?} is not available")]
+    MissingSecret(String),
+    #[error("The secret {0:?} is not valid")]
+    InvalidSecret(String),
+    #[error("Failed to read the secret {0:?}")]
+    IoError(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod secrets_tests {
+    use crate::secrets::{
+        ChainedSecretsProvider, EnvSecretsProvider, FileSecretsProvider, SecretsError,
+        TSecretsProvider,
+    };
+
+    #[tokio::test]
+    async fn test_secret_providers() {
+        let directory = std::env::temp_dir().join(format!("secrets-test-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("database_url"), "postgres://from-file\n").unwrap();
+        std::fs::write(directory.join("kafka_password"), "hunter2").unwrap();
+
+        let prefix = format!("SECRETS_TEST_{}_", std::process::id());
+
+        std::env::set_var(format!("{}DATABASE_URL", prefix), "postgres://from-env");
+
+        let secrets = ChainedSecretsProvider::new(
+            EnvSecretsProvider::new(prefix),
+            FileSecretsProvider::new(&directory),
+        );
+
+        let database_url = secrets.secret("database_url").await.unwrap();
+        let kafka_password = secrets.required_secret("kafka_password").await.unwrap();
+        let missing = secrets.required_secret("kafka_username").await;
+        let escaping = secrets.secret("../database_url").await;
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(database_url.as_deref(), Some("postgres://from-env"));
+        assert_eq!(kafka_password, "hunter2");
+        assert!(matches!(missing, Err(SecretsError::MissingSecret(_))));
+        assert!(matches!(escaping, Err(SecretsError::InvalidSecret(_))));
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use tokio::sync::watch;
+
+/// The health of the persistence of the state, as seen by the ingestion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistenceHealth {
+    /// The durable storage is keeping up with the changes
+    Healthy,
+    /// The durable storage is falling behind, with the given amount of changes still waiting
+    /// to be written, so ingestion is paused until it catches up
+    Lagging { backlog: u64 },
+}
+
+/// Tracks the changes which were applied in memory but not yet written to durable storage
+/// (e.g. by a write-behind repository), so ingestion can be paused before the two diverge
+/// too far.
+///
+/// Once the backlog reaches the high watermark, the persistence is reported as lagging and
+/// ingestion is paused until the backlog drains down to the low watermark.
+pub struct PersistenceBacklog {
+    high_watermark: u64,
+    low_watermark: u64,
+    backlog: watch::Sender<u64>,
+    health: watch::Sender<PersistenceHealth>,
+    /// Whether the last attempt to write a change failed
+    failing: AtomicBool,
+}
+
+impl PersistenceBacklog {
+    /// The low watermark is capped at the high one
+    pub fn new(high_watermark: u64, low_watermark: u64) -> Self {
+        let high_watermark = high_watermark.max(1);
+
+        Self {
+            high_watermark,
+            low_watermark: low_watermark.min(high_watermark - 1),
+            backlog: watch::Sender::new(0),
+            health: watch::Sender::new(PersistenceHealth::Healthy),
+            failing: AtomicBool::new(false),
+        }
+    }
+
+    /// The amount of changes waiting to be written
+    pub fn backlog(&self) -> u64 {
+        *self.backlog.borrow()
+    }
+
+    pub fn health(&self) -> PersistenceHealth {
+        *self.health.borrow()
+    }
+
+    /// Whether the durable storage refused the last change we tried to write to it
+    pub fn is_failing(&self) -> bool {
+        self.failing.load(Ordering::Relaxed)
+    }
+
+    /// Be notified of every change of the health of the persistence
+    pub fn subscribe(&self) -> watch::Receiver<PersistenceHealth> {
+        self.health.subscribe()
+    }
+
+    /// A change is waiting to be written
+    pub fn enqueued(&self) {
+        self.backlog.send_modify(|backlog| *backlog += 1);
+
+        let backlog = self.backlog();
+
+        if backlog >= self.high_watermark {
+            self.health.send_if_modified(|health| {
+                let lagging = *health == PersistenceHealth::Healthy;
+
+                if lagging {
+                    tracing::warn!(
+                        backlog,
+                        "The storage is falling behind, pausing the ingestion"
+                    );
+
+                    *health = PersistenceHealth::Lagging { backlog };
+                }
+
+                lagging
+            });
+        }
+    }
+
+    /// Writing a change failed, and it is still waiting to be written
+    pub fn write_failed(&self) {
+        self.failing.store(true, Ordering::Relaxed);
+    }
+
+    /// A change was written, or given up on
+    pub fn flushed(&self) {
+        self.failing.store(false, Ordering::Relaxed);
+
+        self.backlog
+            .send_modify(|backlog| *backlog = backlog.saturating_sub(1));
+
+        if self.backlog() <= self.low_watermark {
+            self.health.send_if_modified(|health| {
+                let recovered = *health != PersistenceHealth::Healthy;
+
+                if recovered {
+                    tracing::info!("The storage caught up, resuming the ingestion");
+
+                    *health = PersistenceHealth::Healthy;
+                }
+
+                recovered
+            });
+        }
+    }
+
+    /// Wait until the persistence is healthy
+    pub async fn wait_until_healthy(&self) {
+        let mut health = self.subscribe();
+
+        // The sender lives as long as we do, so this can't fail
+        let _ = health
+            .wait_for(|health| *health == PersistenceHealth::Healthy)
+            .await;
+    }
+
+    /// Wait until every change has been written
+    pub async fn wait_until_drained(&self) {
+        let mut backlog = self.backlog.subscribe();
+
+        let _ = backlog.wait_for(|backlog| *backlog == 0).await;
+    }
+
+    /// Only pull from the given stream while the persistence is healthy, so the providers stop
+    /// receiving (and acknowledging) transactions while the storage catches up
+    pub fn pause_while_lagging<'a, S>(&'a self, stream: S) -> BoxStream<'a, S::Item>
+    where
+        S: Stream + Send + 'a,
+        S::Item: Send,
+    {
+        futures::stream::unfold(Box::pin(stream), move |mut stream| async move {
+            self.wait_until_healthy().await;
+
+            stream.next().await.map(|item| (item, stream))
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod backpressure_tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use crate::services::backpressure::{PersistenceBacklog, PersistenceHealth};
+
+    #[tokio::test]
+    async fn test_pause_while_lagging() {
+        let backlog = Arc::new(PersistenceBacklog::new(3, 1));
+        let mut health = backlog.subscribe();
+
+        let pulled = Arc::new(AtomicU64::new(0));
+
+        let source = {
+            let pulled = pulled.clone();
+
+            futures::stream::iter(0..10).inspect(move |_| {
+                pulled.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+
+        (0..3).for_each(|_| backlog.enqueued());
+
+        assert_eq!(backlog.health(), PersistenceHealth::Lagging { backlog: 3 });
+        assert!(health.has_changed().unwrap());
+        health.borrow_and_update();
+
+        let consumer = {
+            let backlog = backlog.clone();
+
+            tokio::spawn(async move { backlog.pause_while_lagging(source).count().await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Nothing is pulled from the provider while the storage is behind
+        assert_eq!(pulled.load(Ordering::SeqCst), 0);
+
+        // Draining down to the high watermark isn't enough to resume
+        backlog.flushed();
+        assert_eq!(backlog.health(), PersistenceHealth::Lagging { backlog: 3 });
+
+        backlog.flushed();
+        assert_eq!(backlog.health(), PersistenceHealth::Healthy);
+        assert!(health.has_changed().unwrap());
+
+        assert_eq!(consumer.await.unwrap(), 10);
+        assert_eq!(pulled.load(Ordering::SeqCst), 10);
+    }
+}
@@ -0,0 +1,966 @@
+use std::time::Duration;
+
+use crate::events::DomainEvent;
+use crate::models::client::{
+    Client, ClientAccountStatus, ClientOperationError, DisputedFunds, FeeError, WithdrawFundsError,
+};
+use crate::models::effects::{Effects, TransactionChange};
+use crate::models::transactions::{DisputeMode, Transaction, TransactionType};
+use crate::models::{MoneyType, Timestamp};
+use crate::services::transaction_service::TransactionProcessingError;
+
+/// Rules limiting for how long a transaction can be disputed.
+///
+/// Once a transaction falls outside the window it can never be disputed again, which
+/// means it can be safely compacted from the transaction repository.
+#[derive(Debug, Clone, Default)]
+pub struct DisputabilityWindow {
+    /// The amount of subsequent deposits and withdrawals of the same client after which
+    /// a transaction can no longer be disputed
+    pub max_subsequent_transactions: Option<u64>,
+    /// How long after a transaction it can still be disputed, like the dispute time limits of
+    /// the card networks.
+    ///
+    /// Only enforced when both the transaction and the dispute have timestamps.
+    pub max_age: Option<Duration>,
+}
+
+/// What to do with deposits and withdrawals which reuse the id of a stored transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Reject the transaction with a [`TransactionProcessingError::DuplicateTransaction`]
+    #[default]
+    Reject,
+    /// Silently skip the transaction
+    Ignore,
+    /// Process the transaction, replacing the stored one
+    Process,
+}
+
+/// How disputes of withdrawals affect the client, as payment schemes handle them differently.
+///
+/// Disputes of deposits always hold the disputed funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisputePolicy {
+    /// Skip the disputes of withdrawals, with a [`TransactionProcessingError::WithdrawalDisputeIgnored`]
+    Ignore,
+    /// Hold the withdrawn amount until the dispute is settled, crediting it to the client when
+    /// resolved and dropping it when charged back
+    #[default]
+    Hold,
+    /// Credit the withdrawn amount to the client right away, taking it back when charged back
+    ProvisionalCredit,
+}
+
+/// The policies which affect the decisions taken on transactions
+#[derive(Debug, Clone, Default)]
+pub struct DecisionPolicy {
+    pub disputability_window: DisputabilityWindow,
+    pub duplicates: DuplicatePolicy,
+    pub withdrawal_disputes: DisputePolicy,
+    /// How long after an authorization it can still be captured.
+    ///
+    /// Only enforced when both the authorization and the capture have timestamps.
+    pub authorization_expiry: Option<Duration>,
+}
+
+/// Decide on the effects of a given transaction.
+///
+/// This is the core of the transaction processing, containing all of the business rules, and is
+/// completely pure: it only looks at the current state of the targeted client and of the stored
+/// transaction with the same id (the referenced transaction for disputes, resolves,
+/// chargebacks, representments and captures, a duplicate for deposits, withdrawals and
+/// authorizations), without performing any I/O or changing any state. Applying the returned
+/// effects is up to the caller.
+pub fn decide(
+    transaction: Transaction,
+    client: &Client,
+    stored_tx: Option<&Transaction>,
+    policy: &DecisionPolicy,
+) -> Result<Effects, TransactionProcessingError> {
+    // We apply the operations to a copy of the client, which lets us reuse all the invariants
+    // maintained by the model, and then extract the resulting changes.
+    let mut next_client = client.clone();
+
+    let (client_id, tx_id) = (transaction.client(), transaction.transaction_id());
+
+    let mut events = Vec::new();
+
+    if let (
+        TransactionType::Deposit { .. }
+        | TransactionType::Withdrawal { .. }
+        | TransactionType::Authorize { .. },
+        Some(_),
+    ) = (transaction.tx_type(), stored_tx)
+    {
+        if policy.duplicates != DuplicatePolicy::Process {
+            return Err(TransactionProcessingError::DuplicateTransaction(tx_id));
+        }
+    }
+
+    let transaction_change = match *transaction.tx_type() {
+        TransactionType::Deposit { amount, .. } => {
+            next_client.deposit(amount)?;
+
+            events.push(DomainEvent::DepositApplied {
+                client: client_id,
+                transaction: tx_id,
+                amount,
+            });
+
+            let mut transaction = transaction;
+
+            transaction.assign_client_sequence(next_client.transaction_count());
+
+            TransactionChange::Store(transaction)
+        }
+        TransactionType::Withdrawal { amount, .. } => {
+            next_client.withdraw(amount)?;
+
+            events.push(DomainEvent::WithdrawalApplied {
+                client: client_id,
+                transaction: tx_id,
+                amount,
+            });
+
+            let mut transaction = transaction;
+
+            transaction.assign_client_sequence(next_client.transaction_count());
+
+            TransactionChange::Store(transaction)
+        }
+        TransactionType::Dispute => {
+            let disputed_tx = stored_tx.ok_or(
+                TransactionProcessingError::DisputedTransactionDoesNotExist(tx_id),
+            )?;
+
+            check_same_client(disputed_tx, &transaction)?;
+
+            check_disputability(
+                disputed_tx,
+                &transaction,
+                client,
+                &policy.disputability_window,
+            )?;
+
+            let mode = match (disputed_tx.tx_type(), policy.withdrawal_disputes) {
+                (TransactionType::Withdrawal { .. }, DisputePolicy::Ignore) => {
+                    return Err(TransactionProcessingError::WithdrawalDisputeIgnored(tx_id));
+                }
+                (TransactionType::Withdrawal { .. }, DisputePolicy::ProvisionalCredit) => {
+                    DisputeMode::ProvisionalCredit
+                }
+                _ => DisputeMode::Hold,
+            };
+
+            disputed_tx
+                .clone()
+                .dispute_with_mode(transaction.clone(), mode)?;
+
+            let amount = disputed_tx.amount()?;
+
+            match (disputed_tx.tx_type(), mode) {
+                (TransactionType::Deposit { .. }, _) => {
+                    next_client.dispute_deposited_funds(amount)?
+                }
+                (TransactionType::Withdrawal { .. }, DisputeMode::Hold) => {
+                    next_client.dispute_withdrawn_funds(amount)?
+                }
+                (TransactionType::Withdrawal { .. }, DisputeMode::ProvisionalCredit) => {
+                    next_client.credit_withdrawn_funds(amount)?
+                }
+                _ => unreachable!("Only deposits and withdrawals can be disputed"),
+            }
+
+            events.push(DomainEvent::DisputeOpened {
+                client: client_id,
+                transaction: tx_id,
+                amount,
+            });
+
+            TransactionChange::OpenDispute(transaction, mode)
+        }
+        TransactionType::Resolve | TransactionType::Chargeback => {
+            let disputed_tx = stored_tx
+                .ok_or(TransactionProcessingError::SettledDisputedTransactionDoesNotExist(tx_id))?;
+
+            check_same_client(disputed_tx, &transaction)?;
+
+            disputed_tx.clone().settle_dispute(transaction.clone())?;
+
+            let amount = disputed_tx.amount()?;
+
+            // Settled the way the dispute was opened, whatever the policy is now. Provisionally
+            // credited disputes hold no funds to release.
+            let mode = disputed_tx.dispute_state().mode().unwrap_or_default();
+
+            let funds = match (disputed_tx.tx_type(), mode) {
+                (TransactionType::Deposit { .. }, _) => Some(DisputedFunds::Deposited),
+                (TransactionType::Withdrawal { .. }, DisputeMode::ProvisionalCredit) => None,
+                (TransactionType::Withdrawal { .. }, DisputeMode::Hold) => {
+                    Some(DisputedFunds::Withdrawn)
+                }
+                _ => unreachable!("Only deposits and withdrawals can be disputed"),
+            };
+
+            if let TransactionType::Resolve = transaction.tx_type() {
+                match funds {
+                    Some(funds) => next_client.resolve_funds(funds, amount)?,
+                    None => next_client.confirm_credited_funds()?,
+                }
+
+                events.push(DomainEvent::DisputeResolved {
+                    client: client_id,
+                    transaction: tx_id,
+                    amount,
+                });
+            } else {
+                match funds {
+                    Some(funds) => next_client.chargeback_funds(funds, amount)?,
+                    None => next_client.reverse_credited_funds(amount)?,
+                }
+
+                events.push(DomainEvent::ChargebackApplied {
+                    client: client_id,
+                    transaction: tx_id,
+                    amount,
+                });
+
+                events.push(DomainEvent::AccountFrozen { client: client_id });
+            }
+
+            TransactionChange::SettleDispute(transaction)
+        }
+        TransactionType::Representment => {
+            let charged_back_tx = stored_tx
+                .ok_or(TransactionProcessingError::RepresentedTransactionDoesNotExist(tx_id))?;
+
+            check_same_client(charged_back_tx, &transaction)?;
+
+            charged_back_tx.clone().represent(transaction.clone())?;
+
+            let amount = charged_back_tx.amount()?;
+
+            // Whatever the chargeback took from the client, be it the disputed deposit or the
+            // withdrawn amount, it is given back
+            next_client.represent_funds(amount)?;
+
+            events.push(DomainEvent::ChargebackReversed {
+                client: client_id,
+                transaction: tx_id,
+                amount,
+            });
+
+            // The account was frozen because of the chargeback, but the disputes which are
+            // still open could end up in other chargebacks, so it stays frozen until they settle
+            if *next_client.account_status() == ClientAccountStatus::Frozen
+                && next_client.disputes().open_disputes() == 0
+            {
+                next_client.unfreeze()?;
+
+                events.push(DomainEvent::AccountUnfrozen { client: client_id });
+            }
+
+            TransactionChange::ReverseChargeback(transaction)
+        }
+        TransactionType::Authorize { amount, .. } => {
+            next_client.authorize(amount)?;
+
+            events.push(DomainEvent::FundsAuthorized {
+                client: client_id,
+                transaction: tx_id,
+                amount,
+            });
+
+            let mut transaction = transaction;
+
+            transaction.assign_client_sequence(next_client.transaction_count());
+
+            TransactionChange::Store(transaction)
+        }
+        TransactionType::Capture => {
+            let authorization = stored_tx.ok_or(
+                TransactionProcessingError::CapturedTransactionDoesNotExist(tx_id),
+            )?;
+
+            check_same_client(authorization, &transaction)?;
+
+            authorization.clone().capture(transaction.clone())?;
+
+            check_expiry(authorization, &transaction, policy.authorization_expiry)?;
+
+            let amount = authorization.amount()?;
+
+            next_client.capture_authorized_funds(amount)?;
+
+            events.push(DomainEvent::AuthorizationCaptured {
+                client: client_id,
+                transaction: tx_id,
+                amount,
+            });
+
+            TransactionChange::Capture(transaction)
+        }
+        TransactionType::Transfer { .. } => {
+            unreachable!("Transfers involve two clients, so they are decided by decide_transfer")
+        }
+        TransactionType::Unfreeze => {
+            next_client.unfreeze()?;
+
+            events.push(DomainEvent::AccountUnfrozen { client: client_id });
+
+            TransactionChange::Administer(transaction)
+        }
+        TransactionType::Close => {
+            next_client.close()?;
+
+            events.push(DomainEvent::AccountClosed { client: client_id });
+
+            TransactionChange::Administer(transaction)
+        }
+    };
+
+    Ok(effects_between(
+        client,
+        &next_client,
+        transaction_change,
+        events,
+    ))
+}
+
+/// Decide on the effects of a transfer, on both the client sending the funds and the one
+/// receiving them (in this order).
+///
+/// Just like [`decide`], this is pure, and the stored transaction is the one with the same id.
+pub fn decide_transfer(
+    transaction: Transaction,
+    source: &Client,
+    destination: &Client,
+    stored_tx: Option<&Transaction>,
+    policy: &DecisionPolicy,
+) -> Result<(Effects, Effects), TransactionProcessingError> {
+    let TransactionType::Transfer { to_client, amount } = *transaction.tx_type() else {
+        unreachable!("Only transfers are decided by decide_transfer")
+    };
+
+    let (client_id, tx_id) = (transaction.client(), transaction.transaction_id());
+
+    if stored_tx.is_some() && policy.duplicates != DuplicatePolicy::Process {
+        return Err(TransactionProcessingError::DuplicateTransaction(tx_id));
+    }
+
+    if to_client == client_id {
+        return Err(TransactionProcessingError::TransferToSameClient(tx_id));
+    }
+
+    match destination.account_status() {
+        ClientAccountStatus::Active => {}
+        ClientAccountStatus::Frozen => {
+            return Err(TransactionProcessingError::TransferDestinationFrozen(
+                tx_id, to_client,
+            ))
+        }
+        ClientAccountStatus::Closed => {
+            return Err(TransactionProcessingError::TransferDestinationClosed(
+                tx_id, to_client,
+            ))
+        }
+    }
+
+    let mut next_source = source.clone();
+    let mut next_destination = destination.clone();
+
+    next_source.withdraw(amount).map_err(|err| match err {
+        ClientOperationError::WithdrawError(WithdrawFundsError::NotEnoughFunds(
+            available,
+            requested,
+        )) => TransactionProcessingError::TransferInsufficientFunds(tx_id, available, requested),
+        err => err.into(),
+    })?;
+
+    next_destination.deposit(amount)?;
+
+    let mut transaction = transaction;
+
+    transaction.assign_client_sequence(next_source.transaction_count());
+
+    let debit = effects_between(
+        source,
+        &next_source,
+        TransactionChange::Store(transaction.clone()),
+        vec![DomainEvent::TransferSent {
+            client: client_id,
+            transaction: tx_id,
+            to_client,
+            amount,
+        }],
+    );
+
+    let credit = effects_between(
+        destination,
+        &next_destination,
+        TransactionChange::Credit(transaction),
+        vec![DomainEvent::TransferReceived {
+            client: to_client,
+            transaction: tx_id,
+            from_client: client_id,
+            amount,
+        }],
+    );
+
+    Ok((debit, credit))
+}
+
+/// Decide on the effects of expiring the given authorization at the given time, which releases
+/// the funds it holds back to its client.
+///
+/// Just like [`decide`], this is pure. Nothing is decided when the authorization has not
+/// expired by then: when it is not pending anymore, when it is still within the
+/// [`authorization_expiry`](DecisionPolicy::authorization_expiry), or when it has no
+/// timestamp to tell.
+pub fn decide_expiry(
+    authorization: &Transaction,
+    client: &Client,
+    now: Timestamp,
+    policy: &DecisionPolicy,
+) -> Result<Option<Effects>, TransactionProcessingError> {
+    let (Some(expiry), Some(authorized_at)) =
+        (policy.authorization_expiry, authorization.timestamp())
+    else {
+        return Ok(None);
+    };
+
+    if !authorization.is_pending_authorization()
+        || now.saturating_sub(authorized_at) <= expiry.as_secs()
+    {
+        return Ok(None);
+    }
+
+    let amount = authorization.amount()?;
+
+    let mut next_client = client.clone();
+
+    next_client.release_authorized_funds(amount)?;
+
+    Ok(Some(effects_between(
+        client,
+        &next_client,
+        TransactionChange::Expire(authorization.clone()),
+        vec![DomainEvent::AuthorizationExpired {
+            client: authorization.client(),
+            transaction: authorization.transaction_id(),
+            amount,
+        }],
+    )))
+}
+
+/// Add the given fee to the effects of a transaction, on top of everything else the transaction
+/// does to its client, and decide on the effects of crediting it to the collector of the fees.
+/// Returns the effects on the charged client and on the collector (in this order).
+///
+/// Just like [`decide`], this is pure. The given clients are as they were before the
+/// transaction.
+pub fn decide_fee(
+    effects: Effects,
+    client: &Client,
+    collector: &Client,
+    fee: MoneyType,
+) -> Result<(Effects, Effects), TransactionProcessingError> {
+    let transaction = effects.transaction().clone();
+
+    let (client_id, tx_id) = (transaction.client(), transaction.transaction_id());
+
+    let mut next_client = client.clone();
+
+    next_client.apply_effects(&effects);
+    next_client.charge_fee(fee)?;
+
+    let mut next_collector = collector.clone();
+
+    next_collector.collect_fee(fee)?;
+
+    let mut charged = effects;
+
+    charged.available_delta = charged
+        .available_delta
+        .checked_sub(fee)
+        .ok_or(FeeError::Overflow(client.available(), fee))
+        .map_err(ClientOperationError::from)?;
+    charged.events.push(DomainEvent::FeeCharged {
+        client: client_id,
+        transaction: tx_id,
+        collector: collector.client_id(),
+        amount: fee,
+    });
+
+    let collected = effects_between(
+        collector,
+        &next_collector,
+        TransactionChange::CollectFee(transaction),
+        vec![DomainEvent::FeeCollected {
+            client: collector.client_id(),
+            transaction: tx_id,
+            from_client: client_id,
+            amount: fee,
+        }],
+    );
+
+    Ok((charged, collected))
+}
+
+/// The effects which take the client to the next state
+fn effects_between(
+    client: &Client,
+    next_client: &Client,
+    transaction_change: TransactionChange,
+    events: Vec<DomainEvent>,
+) -> Effects {
+    Effects {
+        client: client.client_id(),
+        available_delta: next_client.available() - client.available(),
+        held_delta: next_client.held() - client.held(),
+        status_change: (next_client.account_status() != client.account_status())
+            .then(|| *next_client.account_status()),
+        transaction_count_delta: next_client.transaction_count() - client.transaction_count(),
+        disputes_delta: next_client.disputes().since(client.disputes()),
+        transaction_change,
+        events,
+    }
+}
+
+/// A transaction can only be disputed (or have its dispute settled) by the client it belongs to
+fn check_same_client(
+    referenced_tx: &Transaction,
+    transaction: &Transaction,
+) -> Result<(), TransactionProcessingError> {
+    if referenced_tx.client() != transaction.client() {
+        return Err(
+            TransactionProcessingError::ReferencedTransactionClientMismatch(
+                transaction.transaction_id(),
+                referenced_tx.client(),
+                transaction.client(),
+            ),
+        );
+    }
+
+    Ok(())
+}
+
+/// Check that the disputed transaction is still within the disputability window
+fn check_disputability(
+    disputed_tx: &Transaction,
+    dispute_tx: &Transaction,
+    client: &Client,
+    window: &DisputabilityWindow,
+) -> Result<(), TransactionProcessingError> {
+    if let (Some(max_age), Some(disputed_at), Some(transacted_at)) = (
+        window.max_age,
+        dispute_tx.timestamp(),
+        disputed_tx.timestamp(),
+    ) {
+        let age = disputed_at.saturating_sub(transacted_at);
+
+        if age > max_age.as_secs() {
+            return Err(TransactionProcessingError::DisputeWindowExpired(
+                disputed_tx.transaction_id(),
+                age,
+            ));
+        }
+    }
+
+    if let (Some(max_subsequent), Some(client_sequence)) = (
+        window.max_subsequent_transactions,
+        disputed_tx.client_sequence(),
+    ) {
+        let subsequent = client.transaction_count().saturating_sub(client_sequence);
+
+        if subsequent > max_subsequent {
+            return Err(TransactionProcessingError::TransactionNoLongerDisputable(
+                disputed_tx.transaction_id(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the authorization has not expired by the time it is captured
+fn check_expiry(
+    authorization: &Transaction,
+    capture: &Transaction,
+    expiry: Option<Duration>,
+) -> Result<(), TransactionProcessingError> {
+    if let (Some(expiry), Some(captured_at), Some(authorized_at)) =
+        (expiry, capture.timestamp(), authorization.timestamp())
+    {
+        let age = captured_at.saturating_sub(authorized_at);
+
+        if age > expiry.as_secs() {
+            return Err(TransactionProcessingError::AuthorizationExpired(
+                authorization.transaction_id(),
+                age,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod decision_tests {
+    use std::collections::HashMap;
+
+    use proptest::prelude::*;
+
+    use crate::events::DomainEvent;
+    use crate::models::client::{Client, ClientAccountStatus, ClientOperationError, FeeError};
+    use crate::models::effects::TransactionChange;
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::services::decision::{
+        decide, decide_fee, decide_transfer, DecisionPolicy, DuplicatePolicy,
+    };
+    use crate::services::transaction_service::TransactionProcessingError;
+
+    fn tx(tx_id: u32, tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(tx_id))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    fn deposit(tx_id: u32, amount: i64) -> Transaction {
+        tx(
+            tx_id,
+            TransactionType::Deposit {
+                amount: MoneyType::new(amount),
+                dispute: DisputeState::NotDisputed,
+            },
+        )
+    }
+
+    fn disputed_deposit(tx_id: u32, amount: i64) -> Transaction {
+        let mut deposit = deposit(tx_id, amount);
+
+        deposit
+            .dispute(tx(tx_id, TransactionType::Dispute))
+            .unwrap();
+
+        deposit
+    }
+
+    #[test]
+    pub fn test_decide_deposit() {
+        let client = Client::builder().with_client_id(ClientID(1)).build();
+
+        let effects = decide(deposit(1, 100), &client, None, &DecisionPolicy::default()).unwrap();
+
+        assert_eq!(effects.available_delta, MoneyType::new(100));
+        assert_eq!(effects.held_delta, MoneyType::new(0));
+        assert_eq!(effects.transaction_count_delta, 1);
+        assert!(effects.status_change.is_none());
+        assert!(matches!(
+            effects.transaction_change,
+            TransactionChange::Store(ref stored) if stored.client_sequence() == Some(1)
+        ));
+
+        // Deciding must never change the given state
+        assert_eq!(client.available(), MoneyType::new(0));
+    }
+
+    #[test]
+    pub fn test_decide_transfer() -> Result<(), TransactionProcessingError> {
+        let policy = DecisionPolicy::default();
+
+        let source = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(100))
+            .build();
+
+        let destination = Client::builder().with_client_id(ClientID(2)).build();
+
+        let transfer = |amount| {
+            tx(
+                3,
+                TransactionType::Transfer {
+                    to_client: ClientID(2),
+                    amount: MoneyType::new(amount),
+                },
+            )
+        };
+
+        let (debit, credit) = decide_transfer(transfer(40), &source, &destination, None, &policy)?;
+
+        assert_eq!(
+            (debit.client, debit.available_delta),
+            (ClientID(1), MoneyType::new(-40))
+        );
+        assert_eq!(
+            (credit.client, credit.available_delta),
+            (ClientID(2), MoneyType::new(40))
+        );
+        assert!(matches!(
+            debit.transaction_change,
+            TransactionChange::Store(_)
+        ));
+        assert!(matches!(
+            credit.transaction_change,
+            TransactionChange::Credit(_)
+        ));
+
+        assert!(matches!(
+            decide_transfer(transfer(400), &source, &destination, None, &policy),
+            Err(TransactionProcessingError::TransferInsufficientFunds(
+                TransactionID(3),
+                available,
+                amount
+            )) if (available, amount) == (MoneyType::new(100), MoneyType::new(400))
+        ));
+
+        let frozen = Client::builder()
+            .with_client_id(ClientID(2))
+            .with_account_status(ClientAccountStatus::Frozen)
+            .build();
+
+        assert!(matches!(
+            decide_transfer(transfer(40), &source, &frozen, None, &policy),
+            Err(TransactionProcessingError::TransferDestinationFrozen(
+                TransactionID(3),
+                ClientID(2)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_decide_fee() -> Result<(), TransactionProcessingError> {
+        let policy = DecisionPolicy::default();
+
+        let client = Client::builder().with_client_id(ClientID(1)).build();
+        let collector = Client::builder().with_client_id(ClientID(9)).build();
+
+        // The fee is charged even if the client can't afford it, leaving it owing the rest
+        let effects = decide(deposit(1, 100), &client, None, &policy)?;
+
+        let (charged, collected) = decide_fee(effects, &client, &collector, MoneyType::new(150))?;
+
+        assert_eq!(charged.available_delta, MoneyType::new(-50));
+        assert_eq!(collected.available_delta, MoneyType::new(150));
+
+        // Unless the balance would overflow
+        let indebted = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::MIN)
+            .build();
+
+        let effects = decide(deposit(2, 100), &indebted, None, &policy)?;
+
+        assert!(matches!(
+            decide_fee(effects, &indebted, &collector, MoneyType::new(150)),
+            Err(TransactionProcessingError::ClientError(
+                ClientOperationError::FeeError(FeeError::Overflow(..))
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_decide_rejections() {
+        let policy = DecisionPolicy::default();
+
+        let frozen = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(100))
+            .with_account_status(ClientAccountStatus::Frozen)
+            .build();
+
+        let active = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(100))
+            .build();
+
+        let cases = [
+            (deposit(1, 10), &frozen, None),
+            (
+                tx(
+                    1,
+                    TransactionType::Withdrawal {
+                        amount: MoneyType::new(1000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                &active,
+                None,
+            ),
+            (tx(1, TransactionType::Dispute), &active, None),
+            (tx(1, TransactionType::Resolve), &active, None),
+            (
+                tx(1, TransactionType::Resolve),
+                &active,
+                Some(deposit(1, 10)),
+            ),
+            (
+                tx(1, TransactionType::Dispute),
+                &active,
+                Some(disputed_deposit(1, 10)),
+            ),
+        ];
+
+        for (transaction, client, stored) in cases {
+            assert!(decide(transaction, client, stored.as_ref(), &policy).is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_decide_duplicates() {
+        let client = Client::builder().with_client_id(ClientID(1)).build();
+
+        let mut policy = DecisionPolicy::default();
+
+        assert!(matches!(
+            decide(deposit(1, 10), &client, Some(&deposit(1, 20)), &policy),
+            Err(TransactionProcessingError::DuplicateTransaction(
+                TransactionID(1)
+            ))
+        ));
+
+        policy.duplicates = DuplicatePolicy::Process;
+
+        assert!(decide(deposit(1, 10), &client, Some(&deposit(1, 20)), &policy).is_ok());
+    }
+
+    #[test]
+    pub fn test_decide_dispute_lifecycle() -> Result<(), TransactionProcessingError> {
+        let policy = DecisionPolicy::default();
+
+        let client = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(100))
+            .build();
+
+        let effects = decide(
+            tx(1, TransactionType::Dispute),
+            &client,
+            Some(&deposit(1, 100)),
+            &policy,
+        )?;
+
+        assert_eq!(
+            (effects.available_delta, effects.held_delta),
+            (MoneyType::new(-100), MoneyType::new(100))
+        );
+
+        let client = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_held(MoneyType::new(100))
+            .build();
+
+        let effects = decide(
+            tx(1, TransactionType::Chargeback),
+            &client,
+            Some(&disputed_deposit(1, 100)),
+            &policy,
+        )?;
+
+        assert_eq!(
+            (effects.available_delta, effects.held_delta),
+            (MoneyType::new(0), MoneyType::new(-100))
+        );
+        assert_eq!(effects.status_change, Some(ClientAccountStatus::Frozen));
+        assert_eq!(
+            effects.events.last(),
+            Some(&DomainEvent::AccountFrozen {
+                client: ClientID(1)
+            })
+        );
+
+        Ok(())
+    }
+
+    fn transaction() -> impl Strategy<Value = Transaction> {
+        let amount = (1..10_000i64).prop_map(MoneyType::new);
+
+        let tx_type = prop_oneof![
+            4 => amount.clone().prop_map(|amount| TransactionType::Deposit {
+                amount,
+                dispute: DisputeState::NotDisputed,
+            }),
+            2 => amount.prop_map(|amount| TransactionType::Withdrawal {
+                amount,
+                dispute: DisputeState::NotDisputed,
+            }),
+            2 => Just(TransactionType::Dispute),
+            1 => Just(TransactionType::Resolve),
+            1 => Just(TransactionType::Chargeback),
+            1 => Just(TransactionType::Representment),
+        ];
+
+        // Few ids, so the disputes and their settlements often find what they refer to
+        (1..8u32, tx_type).prop_map(|(tx_id, tx_type)| tx(tx_id, tx_type))
+    }
+
+    /// Apply the change decided for a transaction to the stored ones, as the service does
+    fn apply_change(stored: &mut HashMap<TransactionID, Transaction>, change: &TransactionChange) {
+        match change {
+            TransactionChange::Store(transaction) => {
+                stored.insert(transaction.transaction_id(), transaction.clone());
+            }
+            TransactionChange::OpenDispute(dispute, mode) => stored
+                .get_mut(&dispute.transaction_id())
+                .unwrap()
+                .dispute_with_mode(dispute.clone(), *mode)
+                .unwrap(),
+            TransactionChange::SettleDispute(settlement) => stored
+                .get_mut(&settlement.transaction_id())
+                .unwrap()
+                .settle_dispute(settlement.clone())
+                .unwrap(),
+            TransactionChange::ReverseChargeback(representment) => stored
+                .get_mut(&representment.transaction_id())
+                .unwrap()
+                .represent(representment.clone())
+                .unwrap(),
+            other => panic!("Unexpected change {:?}", other),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_decide_keeps_balances_consistent(
+            transactions in prop::collection::vec(transaction(), 1..60),
+        ) {
+            let policy = DecisionPolicy::default();
+
+            let mut client = Client::builder().with_client_id(ClientID(1)).build();
+            let mut stored = HashMap::new();
+
+            for transaction in transactions {
+                let stored_tx = stored.get(&transaction.transaction_id());
+
+                let Ok(effects) = decide(transaction, &client, stored_tx, &policy) else {
+                    continue;
+                };
+
+                apply_change(&mut stored, &effects.transaction_change);
+
+                client.apply_effects(&effects);
+
+                prop_assert_eq!(client.available() + client.held(), client.total());
+                prop_assert!(!client.held().is_negative());
+
+                // Every open dispute holds the amount of the transaction it disputes, and
+                // nothing else is held
+                let disputed = stored
+                    .values()
+                    .filter(|tx| matches!(tx.dispute_state(), DisputeState::Open { .. }))
+                    .map(|tx| tx.amount().unwrap())
+                    .fold(MoneyType::ZERO, |held, amount| held + amount);
+
+                prop_assert_eq!(client.held(), disputed);
+            }
+        }
+    }
+}
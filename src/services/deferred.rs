@@ -0,0 +1,148 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::models::transactions::Transaction;
+use crate::models::TransactionID;
+
+/// What to do when the deferred transaction buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Reject the new transaction, as if deferring was disabled
+    #[default]
+    RejectNew,
+    /// Drop the oldest deferred transaction to make room for the new one
+    DropOldest,
+}
+
+/// Configuration of the deferral of transactions which reference a transaction
+/// that has not arrived yet.
+#[derive(Debug, Clone, Default)]
+pub struct DeferralPolicy {
+    /// The maximum amount of deferred transactions. A capacity of 0 disables deferring.
+    pub capacity: usize,
+    pub eviction: EvictionPolicy,
+}
+
+/// Buffer of the disputes, resolves, chargebacks and representments which arrived before the
+/// transaction they reference, so they can be replayed once it arrives.
+#[derive(Debug, Default)]
+pub struct DeferredTransactions {
+    policy: DeferralPolicy,
+    by_reference: HashMap<TransactionID, VecDeque<Transaction>>,
+    /// The referenced ids of the parked transactions, in the order they were parked
+    arrival_order: VecDeque<TransactionID>,
+}
+
+impl DeferredTransactions {
+    pub fn new(policy: DeferralPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.arrival_order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arrival_order.is_empty()
+    }
+
+    /// Park a transaction until the transaction it references arrives.
+    ///
+    /// Returns the transaction back if it could not be parked.
+    pub fn park(&mut self, transaction: Transaction) -> Result<Option<Transaction>, Transaction> {
+        if self.policy.capacity == 0 {
+            return Err(transaction);
+        }
+
+        let mut evicted = None;
+
+        if self.len() >= self.policy.capacity {
+            match self.policy.eviction {
+                EvictionPolicy::RejectNew => return Err(transaction),
+                EvictionPolicy::DropOldest => evicted = self.evict_oldest(),
+            }
+        }
+
+        let tx_id = transaction.transaction_id();
+
+        self.by_reference
+            .entry(tx_id)
+            .or_default()
+            .push_back(transaction);
+        self.arrival_order.push_back(tx_id);
+
+        Ok(evicted)
+    }
+
+    /// Take all the transactions waiting on the given transaction, in the order they arrived
+    pub fn take(&mut self, tx_id: TransactionID) -> Vec<Transaction> {
+        let Some(parked) = self.by_reference.remove(&tx_id) else {
+            return Vec::new();
+        };
+
+        self.arrival_order.retain(|parked_id| *parked_id != tx_id);
+
+        parked.into()
+    }
+
+    fn evict_oldest(&mut self) -> Option<Transaction> {
+        let tx_id = self.arrival_order.pop_front()?;
+
+        let parked = self.by_reference.get_mut(&tx_id)?;
+
+        let evicted = parked.pop_front();
+
+        if parked.is_empty() {
+            self.by_reference.remove(&tx_id);
+        }
+
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod deferred_tests {
+    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::{ClientID, TransactionID};
+    use crate::services::deferred::{DeferralPolicy, DeferredTransactions, EvictionPolicy};
+
+    fn dispute(tx_id: u32) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(tx_id))
+            .with_tx_type(TransactionType::Dispute)
+            .build()
+    }
+
+    #[test]
+    pub fn test_eviction_policies() {
+        let mut rejecting = DeferredTransactions::new(DeferralPolicy {
+            capacity: 1,
+            eviction: EvictionPolicy::RejectNew,
+        });
+
+        assert!(rejecting.park(dispute(1)).is_ok());
+        assert!(rejecting.park(dispute(2)).is_err());
+        assert_eq!(rejecting.take(TransactionID(1)).len(), 1);
+        assert!(rejecting.is_empty());
+
+        let mut dropping = DeferredTransactions::new(DeferralPolicy {
+            capacity: 2,
+            eviction: EvictionPolicy::DropOldest,
+        });
+
+        dropping.park(dispute(1)).unwrap();
+        dropping.park(dispute(2)).unwrap();
+
+        let evicted = dropping.park(dispute(3)).unwrap();
+
+        assert_eq!(
+            evicted.map(|tx| tx.transaction_id()),
+            Some(TransactionID(1))
+        );
+        assert!(dropping.take(TransactionID(1)).is_empty());
+        assert_eq!(dropping.len(), 2);
+    }
+}
@@ -1 +1,14 @@
+pub mod backpressure;
+pub mod decision;
+pub mod deferred;
+pub mod replay;
+#[cfg(feature = "rules")]
+pub mod rules;
+pub mod sharding;
+pub mod speculative;
+#[cfg(feature = "tower")]
+pub mod tower_adapter;
 pub mod transaction_service;
+pub mod unit_of_work;
+pub mod validation;
+pub mod warm_up;
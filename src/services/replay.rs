@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use futures::StreamExt;
+
+use crate::models::client::Client;
+use crate::models::money::format_amount;
+use crate::models::ClientID;
+use crate::repositories::clients::TClientRepository;
+use crate::repositories::RepoError;
+use crate::FLOATING_POINT_ACC;
+
+/// A client whose stored state is not the state its transactions lead to when replayed
+#[derive(Debug, Clone)]
+pub struct ClientDivergence {
+    pub client: ClientID,
+    /// The state in the storage, if the client is stored at all
+    pub stored: Option<Client>,
+    /// The state the replay led to, if the replay created the client at all
+    pub replayed: Option<Client>,
+}
+
+/// Whether two states of a client are the same, as far as its transactions are concerned.
+///
+/// Versions only tell writers apart and credit limits are given outside of the transactions,
+/// so neither of them is compared.
+fn same_state(stored: &Client, replayed: &Client) -> bool {
+    stored.available() == replayed.available()
+        && stored.held() == replayed.held()
+        && stored.account_status() == replayed.account_status()
+        && stored.transaction_count() == replayed.transaction_count()
+        && stored.disputes() == replayed.disputes()
+}
+
+/// Compare every client of the stored state with the state rebuilt by replaying the
+/// transactions, returning the clients which differ, ordered by id
+pub async fn diff_clients(
+    stored: &impl TClientRepository,
+    replayed: &impl TClientRepository,
+) -> Result<Vec<ClientDivergence>, RepoError> {
+    let mut clients: BTreeMap<ClientID, ClientDivergence> = BTreeMap::new();
+
+    let mut stored_clients = stored.find_all_clients().await?;
+
+    while let Some(client) = stored_clients.next().await {
+        let client = client.lock().await.clone();
+
+        clients.insert(
+            client.client_id(),
+            ClientDivergence {
+                client: client.client_id(),
+                stored: Some(client),
+                replayed: None,
+            },
+        );
+    }
+
+    let mut replayed_clients = replayed.find_all_clients().await?;
+
+    while let Some(client) = replayed_clients.next().await {
+        let client = client.lock().await.clone();
+
+        let client_id = client.client_id();
+
+        clients
+            .entry(client_id)
+            .or_insert_with(|| ClientDivergence {
+                client: client_id,
+                stored: None,
+                replayed: None,
+            })
+            .replayed = Some(client);
+    }
+
+    Ok(clients
+        .into_values()
+        .filter(
+            |divergence| match (&divergence.stored, &divergence.replayed) {
+                (Some(stored), Some(replayed)) => !same_state(stored, replayed),
+                _ => true,
+            },
+        )
+        .collect())
+}
+
+/// Describe a state of the client, or its absence
+struct ClientState<'a>(Option<&'a Client>);
+
+impl fmt::Display for ClientState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = FLOATING_POINT_ACC as u32;
+
+        match self.0 {
+            Some(client) => write!(
+                f,
+                "available {}, held {}, {:?} after {} transactions with {} open disputes",
+                format_amount(client.available(), precision),
+                format_amount(client.held(), precision),
+                client.account_status(),
+                client.transaction_count(),
+                client.disputes().open_disputes()
+            ),
+            None => write!(f, "missing"),
+        }
+    }
+}
+
+impl fmt::Display for ClientDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Client {} is stored as {} but replayed as {}",
+            self.client,
+            ClientState(self.stored.as_ref()),
+            ClientState(self.replayed.as_ref())
+        )
+    }
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use crate::infrastructure::in_mem_dbs::ClientInMemRepository;
+    use crate::models::client::Client;
+    use crate::models::{ClientID, MoneyType};
+    use crate::repositories::clients::TClientRepository;
+    use crate::services::replay::diff_clients;
+
+    fn client(id: u16, available: i64) -> Client {
+        Client::builder()
+            .with_client_id(ClientID(id))
+            .with_available(MoneyType::new(available))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_diff_clients() {
+        let (stored, replayed) = (
+            ClientInMemRepository::default(),
+            ClientInMemRepository::default(),
+        );
+
+        for (id, stored_available, replayed_available) in [(1, 10, 10), (2, 10, 7)] {
+            stored
+                .store_client(client(id, stored_available))
+                .await
+                .unwrap();
+            replayed
+                .store_client(client(id, replayed_available))
+                .await
+                .unwrap();
+        }
+
+        stored.store_client(client(3, 1)).await.unwrap();
+        replayed.store_client(client(4, 1)).await.unwrap();
+
+        let divergences = diff_clients(&stored, &replayed).await.unwrap();
+
+        assert_eq!(
+            divergences
+                .iter()
+                .map(|divergence| divergence.client)
+                .collect::<Vec<_>>(),
+            vec![ClientID(2), ClientID(3), ClientID(4)]
+        );
+
+        assert_eq!(
+            divergences[1].to_string(),
+            "Client 3 is stored as available 0.0001, held 0.0000, Active after 0 transactions \
+             with 0 open disputes but replayed as missing"
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::events::{DomainEvent, TEffectsHandler};
+use crate::models::effects::{Effects, ExecutionMode};
+use crate::models::money::{parse_amount, AmountParseError};
+use crate::models::transactions::{Transaction, TRANSACTION_TYPE_NAMES};
+use crate::models::{ClientID, MoneyType};
+use crate::services::validation::{TTransactionValidator, ValidationError};
+
+/// The contents of a rules file, such as
+///
+/// ```toml
+/// [[rules]]
+/// name = "large-first-withdrawal"
+/// types = ["withdrawal"]
+/// amount_over = "10000.0000"
+/// without_prior_deposits = true
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A rule, as declared by the operators. It rejects the transactions which match every one of
+/// the conditions it declares.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// Identifies the rule in the rejections
+    pub name: String,
+    /// Only the transactions of these types, or of any type when empty
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// Only the transactions moving more than this amount, written as a decimal string so it
+    /// is never rounded
+    #[serde(default)]
+    pub amount_over: Option<String>,
+    /// Only the transactions of these clients, or of any client when empty
+    #[serde(default)]
+    pub clients: Vec<u16>,
+    /// Only the transactions of the clients which had no deposit applied yet
+    #[serde(default)]
+    pub without_prior_deposits: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum RuleError {
+    #[error("Failed to read the rules {0:?}")]
+    IoError(#[from] io::Error),
+    #[error("The rules {0:?} are neither TOML (.toml) nor YAML (.yaml or .yml)")]
+    UnknownFormat(PathBuf),
+    #[error("Invalid TOML rules {0}")]
+    InvalidToml(#[from] toml::de::Error),
+    #[error("Invalid YAML rules {0}")]
+    InvalidYaml(#[from] serde_yaml::Error),
+    #[error("The rule {0:?} refers to the unknown transaction type {1:?}")]
+    UnknownTransactionType(String, String),
+    #[error("The rule {0:?} has an invalid amount {1}")]
+    InvalidAmount(String, #[source] AmountParseError),
+    #[error("The rule {0:?} has no conditions, so it would reject every transaction")]
+    NoConditions(String),
+}
+
+#[derive(Error, Debug)]
+#[error("The transaction matches the rule {0:?}")]
+pub struct RuleMatched(pub String);
+
+/// A rule, compiled from its declaration
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    types: HashSet<String>,
+    amount_over: Option<MoneyType>,
+    clients: HashSet<ClientID>,
+    without_prior_deposits: bool,
+}
+
+impl Rule {
+    fn compile(config: RuleConfig, precision: u32) -> Result<Self, RuleError> {
+        if let Some(unknown) = config
+            .types
+            .iter()
+            .find(|tx_type| !TRANSACTION_TYPE_NAMES.contains(&tx_type.as_str()))
+        {
+            return Err(RuleError::UnknownTransactionType(
+                config.name,
+                unknown.clone(),
+            ));
+        }
+
+        let amount_over = match &config.amount_over {
+            Some(amount) => Some(
+                parse_amount(amount, precision)
+                    .map_err(|err| RuleError::InvalidAmount(config.name.clone(), err))?,
+            ),
+            None => None,
+        };
+
+        if config.types.is_empty()
+            && amount_over.is_none()
+            && config.clients.is_empty()
+            && !config.without_prior_deposits
+        {
+            return Err(RuleError::NoConditions(config.name));
+        }
+
+        Ok(Self {
+            name: config.name,
+            types: config.types.into_iter().collect(),
+            amount_over,
+            clients: config.clients.into_iter().map(ClientID).collect(),
+            without_prior_deposits: config.without_prior_deposits,
+        })
+    }
+
+    fn matches(&self, transaction: &Transaction, deposited: &HashSet<ClientID>) -> bool {
+        let client = transaction.client();
+
+        let over_amount = match self.amount_over {
+            Some(max) => transaction.amount().is_ok_and(|amount| amount > max),
+            None => true,
+        };
+
+        (self.types.is_empty() || self.types.contains(transaction.tx_type().name()))
+            && over_amount
+            && (self.clients.is_empty() || self.clients.contains(&client))
+            && !(self.without_prior_deposits && deposited.contains(&client))
+    }
+}
+
+/// The rejection rules declared by the operators, compiled once at startup so the policies
+/// can change without rebuilding the engine.
+///
+/// The rules are checked in the order they were declared, like any other validator. To know
+/// which clients already made deposits, the rule set has to be registered as an effects
+/// handler as well, so it only knows of the deposits applied since the engine started.
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    deposited: Mutex<HashSet<ClientID>>,
+}
+
+impl RuleSet {
+    /// Compile the rules, with their amounts at the given amount of decimal places
+    pub fn compile(config: RulesConfig, precision: u32) -> Result<Self, RuleError> {
+        Ok(Self {
+            rules: config
+                .rules
+                .into_iter()
+                .map(|rule| Rule::compile(rule, precision))
+                .collect::<Result<_, _>>()?,
+            deposited: Default::default(),
+        })
+    }
+
+    /// Read and compile the rules of a TOML or YAML file, depending on its extension
+    pub fn from_file(path: impl AsRef<Path>, precision: u32) -> Result<Self, RuleError> {
+        let path = path.as_ref();
+
+        let contents = std::fs::read_to_string(path)?;
+
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&contents)?,
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents)?,
+            _ => return Err(RuleError::UnknownFormat(path.to_path_buf())),
+        };
+
+        Self::compile(config, precision)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl TTransactionValidator for RuleSet {
+    fn validate(&self, transaction: &Transaction) -> Result<(), ValidationError> {
+        let deposited = self.deposited.lock().unwrap();
+
+        match self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(transaction, &deposited))
+        {
+            Some(rule) => Err(ValidationError::new(
+                "rules",
+                RuleMatched(rule.name.clone()),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TEffectsHandler for RuleSet {
+    fn handle(&self, effects: &Effects, mode: ExecutionMode) {
+        if mode == ExecutionMode::DryRun {
+            return;
+        }
+
+        for event in &effects.events {
+            if let DomainEvent::DepositApplied { client, .. } = event {
+                self.deposited.lock().unwrap().insert(*client);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod rules_tests {
+    use crate::events::TEffectsHandler;
+    use crate::models::client::Client;
+    use crate::models::effects::{Effects, ExecutionMode};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::services::decision::{decide, DecisionPolicy};
+    use crate::services::rules::{RuleError, RuleMatched, RuleSet, RulesConfig};
+    use crate::services::validation::TTransactionValidator;
+
+    const RULES: &str = r#"
+        [[rules]]
+        name = "large-first-withdrawal"
+        types = ["withdrawal"]
+        amount_over = "10000.0000"
+        without_prior_deposits = true
+
+        [[rules]]
+        name = "no-transfers-from-client-9"
+        types = ["transfer"]
+        clients = [9]
+    "#;
+
+    fn tx(client: u16, tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(client))
+            .with_tx_id(TransactionID(1))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    fn withdrawal(client: u16, amount: i64) -> Transaction {
+        tx(
+            client,
+            TransactionType::Withdrawal {
+                amount: MoneyType::new(amount),
+                dispute: DisputeState::NotDisputed,
+            },
+        )
+    }
+
+    fn deposited(client: u16) -> Effects {
+        let deposit = tx(
+            client,
+            TransactionType::Deposit {
+                amount: MoneyType::new(1),
+                dispute: DisputeState::NotDisputed,
+            },
+        );
+
+        let client = Client::builder().with_client_id(ClientID(client)).build();
+
+        decide(deposit, &client, None, &DecisionPolicy::default()).unwrap()
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = RuleSet::compile(toml::from_str::<RulesConfig>(RULES).unwrap(), 4).unwrap();
+
+        assert_eq!(rules.len(), 2);
+
+        let rejected = rules.validate(&withdrawal(1, 100_000_001)).unwrap_err();
+
+        assert!(matches!(
+            rejected.reason().downcast_ref::<RuleMatched>(),
+            Some(RuleMatched(name)) if name == "large-first-withdrawal"
+        ));
+
+        // Only the amounts over the limit match
+        assert!(rules.validate(&withdrawal(1, 100_000_000)).is_ok());
+
+        // Dry runs don't count as deposits
+        rules.handle(&deposited(1), ExecutionMode::DryRun);
+
+        assert!(rules.validate(&withdrawal(1, 100_000_001)).is_err());
+
+        rules.handle(&deposited(1), ExecutionMode::Apply);
+
+        assert!(rules.validate(&withdrawal(1, 100_000_001)).is_ok());
+
+        let transfer = |client| {
+            tx(
+                client,
+                TransactionType::Transfer {
+                    to_client: ClientID(2),
+                    amount: MoneyType::new(1),
+                },
+            )
+        };
+
+        assert!(rules.validate(&transfer(9)).is_err());
+        assert!(rules.validate(&transfer(8)).is_ok());
+
+        // The amounts are at the decimal places of the run
+        let rules = RuleSet::compile(toml::from_str::<RulesConfig>(RULES).unwrap(), 6).unwrap();
+
+        assert!(rules.validate(&withdrawal(1, 10_000_000_001)).is_err());
+        assert!(rules.validate(&withdrawal(1, 10_000_000_000)).is_ok());
+    }
+
+    #[test]
+    fn test_yaml_rules() {
+        let config = serde_yaml::from_str::<RulesConfig>(
+            "rules:\n  - name: no-chargebacks\n    types: [chargeback]\n",
+        )
+        .unwrap();
+
+        let rules = RuleSet::compile(config, 4).unwrap();
+
+        assert!(rules.validate(&tx(1, TransactionType::Chargeback)).is_err());
+        assert!(rules.validate(&tx(1, TransactionType::Dispute)).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let compile = |rules: &str| RuleSet::compile(toml::from_str(rules).unwrap(), 4);
+
+        assert!(matches!(
+            compile("[[rules]]\nname = \"typo\"\ntypes = [\"withdrawl\"]"),
+            Err(RuleError::UnknownTransactionType(..))
+        ));
+        assert!(matches!(
+            compile("[[rules]]\nname = \"precise\"\namount_over = \"1.00001\""),
+            Err(RuleError::InvalidAmount(..))
+        ));
+        assert!(matches!(
+            compile("[[rules]]\nname = \"everything\""),
+            Err(RuleError::NoConditions(..))
+        ));
+
+        // Misspelled conditions are never silently ignored
+        assert!(toml::from_str::<RulesConfig>("[[rules]]\nname = \"a\"\nclient = [1]").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::models::transactions::Transaction;
+use crate::models::ClientID;
+use crate::services::transaction_service::TTransactionService;
+
+/// Decides which shard processes the transactions of each client.
+///
+/// All transactions of a client must always go to the same shard during a run, as that is what
+/// guarantees they are processed in order. Between runs the shard count can change freely, since
+/// the shards don't hold any state of their own.
+pub trait TPartitioner: Send + Sync {
+    /// The shard, in `0..shard_count`, responsible for the given client
+    fn shard_for(&self, client: ClientID, shard_count: usize) -> usize;
+}
+
+/// The default partitioner, assigning clients by the hash of their id
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashPartitioner;
+
+impl TPartitioner for HashPartitioner {
+    fn shard_for(&self, client: ClientID, shard_count: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+
+        client.hash(&mut hasher);
+
+        (hasher.finish() % shard_count as u64) as usize
+    }
+}
+
+/// Partitioner using jump consistent hashing, so changing the amount of shards only moves
+/// the minimum amount of clients between them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsistentHashPartitioner;
+
+impl TPartitioner for ConsistentHashPartitioner {
+    fn shard_for(&self, client: ClientID, shard_count: usize) -> usize {
+        // Lamping and Veach's jump consistent hash
+        let mut key = u64::from(client);
+        let (mut bucket, mut next) = (-1i64, 0i64);
+
+        while next < shard_count as i64 {
+            bucket = next;
+            key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+            next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+        }
+
+        bucket.max(0) as usize
+    }
+}
+
+/// Any function can be used as a partitioner, which allows for things like tenant aware
+/// partitioning
+impl<F> TPartitioner for F
+where
+    F: Fn(ClientID, usize) -> usize + Send + Sync,
+{
+    fn shard_for(&self, client: ClientID, shard_count: usize) -> usize {
+        self(client, shard_count)
+    }
+}
+
+/// The amount of transactions handled by a single shard
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardStats {
+    pub processed: usize,
+    pub failed: usize,
+}
+
+/// Processes a transaction stream over multiple shards, each of them running on its own task.
+pub struct ShardedProcessor<S, P> {
+    service: Arc<S>,
+    partitioner: P,
+    shard_count: usize,
+    shard_capacity: usize,
+}
+
+impl<S> ShardedProcessor<S, HashPartitioner> {
+    pub fn new(service: Arc<S>, shard_count: usize) -> Self {
+        Self {
+            service,
+            partitioner: HashPartitioner,
+            shard_count: shard_count.max(1),
+            shard_capacity: 1024,
+        }
+    }
+}
+
+impl<S, P> ShardedProcessor<S, P> {
+    /// Use a different partitioner to assign the clients to shards
+    pub fn with_partitioner<NP>(self, partitioner: NP) -> ShardedProcessor<S, NP> {
+        ShardedProcessor {
+            service: self.service,
+            partitioner,
+            shard_count: self.shard_count,
+            shard_capacity: self.shard_capacity,
+        }
+    }
+
+    /// The amount of transactions which can be queued for each shard
+    pub fn with_shard_capacity(mut self, shard_capacity: usize) -> Self {
+        self.shard_capacity = shard_capacity.max(1);
+
+        self
+    }
+}
+
+impl<S, P> ShardedProcessor<S, P>
+where
+    S: TTransactionService + 'static,
+    P: TPartitioner,
+{
+    /// Process the whole stream, returning the statistics of each shard
+    pub async fn process_stream(
+        &self,
+        mut stream: BoxStream<'static, Transaction>,
+    ) -> Vec<ShardStats> {
+        let (senders, workers): (Vec<_>, Vec<_>) = (0..self.shard_count)
+            .map(|shard| {
+                let (sender, receiver) = flume::bounded::<Transaction>(self.shard_capacity);
+
+                let service = self.service.clone();
+
+                let worker = tokio::spawn(async move {
+                    let mut stats = ShardStats::default();
+
+                    while let Ok(tx) = receiver.recv_async().await {
+                        stats.processed += 1;
+
+                        if let Err(err) = service.process_transaction(tx).await {
+                            tracing::error!(shard, error = %err, "Error processing transaction");
+
+                            stats.failed += 1;
+                        }
+                    }
+
+                    stats
+                });
+
+                (sender, worker)
+            })
+            .unzip();
+
+        while let Some(tx) = stream.next().await {
+            // Guard against partitioners which don't respect the shard count
+            let shard =
+                self.partitioner.shard_for(tx.client(), self.shard_count) % self.shard_count;
+
+            if senders[shard].send_async(tx).await.is_err() {
+                tracing::error!(shard, "Shard stopped unexpectedly");
+            }
+        }
+
+        // Closing the channels lets the workers finish once they are drained
+        drop(senders);
+
+        let mut stats = Vec::with_capacity(workers.len());
+
+        for worker in workers {
+            stats.push(worker.await.unwrap_or_default());
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod sharding_tests {
+    use std::sync::Arc;
+
+    use futures::{stream, StreamExt};
+
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::services::sharding::{
+        ConsistentHashPartitioner, HashPartitioner, ShardedProcessor, TPartitioner,
+    };
+    use crate::services::transaction_service::TransactionService;
+    use crate::ShareableClientRepository;
+
+    #[test]
+    pub fn test_partitioners_stay_in_range() {
+        for shard_count in 1..16 {
+            for client in (0..500).map(ClientID) {
+                assert!(HashPartitioner.shard_for(client, shard_count) < shard_count);
+                assert!(ConsistentHashPartitioner.shard_for(client, shard_count) < shard_count);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_consistent_hash_moves_few_clients() {
+        let moved = (0..1000)
+            .map(ClientID)
+            .filter(|client| {
+                ConsistentHashPartitioner.shard_for(*client, 10)
+                    != ConsistentHashPartitioner.shard_for(*client, 11)
+            })
+            .count();
+
+        // Ideally only 1/11th of the clients should move to the new shard
+        assert!(moved < 200, "Moved {} clients", moved);
+    }
+
+    #[tokio::test]
+    async fn test_sharded_processing_keeps_client_order() {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let service = Arc::new(TransactionService::new(
+            client_repo.clone(),
+            TransactionInMemRepository::default(),
+        ));
+
+        let txs = (0..100u32).flat_map(|tx_id| {
+            let client = ClientID((tx_id % 7) as u16);
+
+            [
+                Transaction::builder()
+                    .with_client_id(client)
+                    .with_tx_id(TransactionID(tx_id * 2))
+                    .with_tx_type(TransactionType::Deposit {
+                        amount: MoneyType::new(100),
+                        dispute: DisputeState::NotDisputed,
+                    })
+                    .build(),
+                // Only succeeds if it is processed after the deposit
+                Transaction::builder()
+                    .with_client_id(client)
+                    .with_tx_id(TransactionID(tx_id * 2 + 1))
+                    .with_tx_type(TransactionType::Withdrawal {
+                        amount: MoneyType::new(50),
+                        dispute: DisputeState::NotDisputed,
+                    })
+                    .build(),
+            ]
+        });
+
+        let processor = ShardedProcessor::new(service, 3)
+            .with_partitioner(|client: ClientID, shards: usize| client.0 as usize % shards);
+
+        let stats = processor
+            .process_stream(stream::iter(txs.collect::<Vec<_>>()).boxed())
+            .await;
+
+        assert_eq!(
+            stats.iter().map(|stats| stats.processed).sum::<usize>(),
+            200
+        );
+        assert_eq!(stats.iter().map(|stats| stats.failed).sum::<usize>(), 0);
+
+        let client = client_repo
+            .find_client_by_id(ClientID(0))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Client 0 receives 15 pairs of transactions
+        assert_eq!(client.lock().await.available(), MoneyType::new(15 * 50));
+    }
+}
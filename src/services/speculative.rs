@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use futures::{stream, StreamExt};
+use thiserror::Error;
+use tokio::task::JoinError;
+
+use crate::models::transactions::Transaction;
+use crate::models::SequenceNumber;
+use crate::services::transaction_service::{Processed, TTransactionService};
+
+/// Batch processor which executes transactions in parallel regardless of their order,
+/// only falling back to serial execution for the transactions that conflict with an earlier one.
+///
+/// Every transaction in the batch is assigned a sequence number, corresponding to its position
+/// in the batch. A transaction conflicts with an earlier one when they involve the same client or
+/// reference the same transaction id (which is how disputes, resolves and chargebacks depend on
+/// the transaction they target). The conflicts are found from the transactions alone before
+/// anything is executed, so no transaction is ever executed twice. Conflict free transactions
+/// can be applied in any order without changing the final state, so they are all executed
+/// concurrently, after which the conflicting ones are executed serially in sequence order.
+///
+/// On low contention workloads this is much faster than strict per-client sharding, as the
+/// parallelism is not bound by the number of shards.
+pub struct SpeculativeBatchProcessor<S> {
+    service: Arc<S>,
+    max_parallel: usize,
+}
+
+/// A transaction tagged with its position in the batch
+type SequencedTransaction = (SequenceNumber, Transaction);
+
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error("The processing of a transaction of the batch failed {0}")]
+    TaskFailed(#[from] JoinError),
+}
+
+/// The outcome of processing a batch
+pub struct BatchOutcome<E> {
+    /// The amount of transactions which were executed in the parallel phase
+    pub parallel: usize,
+    /// The amount of transactions which had conflicts and were executed serially
+    pub serial: usize,
+    /// The result of every transaction in the batch, ordered by sequence number
+    pub results: Vec<(SequenceNumber, Result<Processed, E>)>,
+}
+
+impl<S> SpeculativeBatchProcessor<S>
+where
+    S: TTransactionService + 'static,
+    S::Error: 'static,
+{
+    pub fn new(service: Arc<S>, max_parallel: usize) -> Self {
+        Self {
+            service,
+            max_parallel: max_parallel.max(1),
+        }
+    }
+
+    /// Process a batch of transactions, returning the result of each of them.
+    ///
+    /// Fails if the processing of one of the transactions ran parallel to the others panics,
+    /// once all of them are done, as the state it left its client in is unknown.
+    pub async fn process_batch(
+        &self,
+        batch: Vec<Transaction>,
+    ) -> Result<BatchOutcome<S::Error>, BatchError> {
+        let (independent, conflicting) = Self::partition(batch);
+
+        let parallel = independent.len();
+        let serial = conflicting.len();
+
+        let mut results = stream::iter(independent)
+            .map(|(seq, transaction)| {
+                let service = self.service.clone();
+
+                async move {
+                    let handle =
+                        tokio::spawn(async move { service.process_transaction(transaction).await });
+
+                    (seq, handle.await)
+                }
+            })
+            .buffer_unordered(self.max_parallel)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .map(|(seq, joined)| Ok((seq, joined?)))
+            .collect::<Result<Vec<_>, BatchError>>()?;
+
+        for (seq, transaction) in conflicting {
+            results.push((seq, self.service.process_transaction(transaction).await));
+        }
+
+        results.sort_by_key(|(seq, _)| *seq);
+
+        Ok(BatchOutcome {
+            parallel,
+            serial,
+            results,
+        })
+    }
+
+    /// Split the batch into the transactions which don't depend on any earlier transaction
+    /// of the batch and the ones that do, both tagged with their sequence numbers.
+    fn partition(
+        batch: Vec<Transaction>,
+    ) -> (Vec<SequencedTransaction>, Vec<SequencedTransaction>) {
+        let mut seen_clients = HashSet::new();
+        let mut seen_transactions = HashSet::new();
+
+        let mut independent = Vec::new();
+        let mut conflicting = Vec::new();
+
+        for (seq, transaction) in batch.into_iter().enumerate() {
+            let new_client = seen_clients.insert(transaction.client());
+            let new_transaction = seen_transactions.insert(transaction.transaction_id());
+
+            // Transfers also conflict with anything targeting the client receiving the funds
+            let new_destination = transaction
+                .destination()
+                .is_none_or(|destination| seen_clients.insert(destination));
+
+            if new_client && new_transaction && new_destination {
+                independent.push((seq as SequenceNumber, transaction));
+            } else {
+                conflicting.push((seq as SequenceNumber, transaction));
+            }
+        }
+
+        (independent, conflicting)
+    }
+}
+
+#[cfg(test)]
+mod speculative_tests {
+    use std::sync::Arc;
+
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::services::speculative::{BatchError, SpeculativeBatchProcessor};
+    use crate::services::transaction_service::{
+        Processed, TTransactionService, TransactionProcessingError, TransactionService,
+    };
+    use crate::ShareableClientRepository;
+
+    /// Service whose processing of the transactions of client 9 panics
+    struct PanickingService;
+
+    impl TTransactionService for PanickingService {
+        type Error = TransactionProcessingError;
+
+        async fn process_transaction(
+            &self,
+            transaction: Transaction,
+        ) -> Result<Processed, TransactionProcessingError> {
+            assert_ne!(transaction.client(), ClientID(9), "Unexpected client");
+
+            Ok(Processed::Applied)
+        }
+    }
+
+    fn tx(client: u16, tx_id: u32, tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(client))
+            .with_tx_id(TransactionID(tx_id))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    fn deposit(amount: i64) -> TransactionType {
+        TransactionType::Deposit {
+            amount: MoneyType::new(amount),
+            dispute: DisputeState::NotDisputed,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_transactions_keep_order() {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let service = Arc::new(TransactionService::new(
+            client_repo.clone(),
+            TransactionInMemRepository::default(),
+        ));
+
+        let processor = SpeculativeBatchProcessor::new(service, 4);
+
+        let batch = vec![
+            tx(1, 1, deposit(1000)),
+            tx(2, 2, deposit(2000)),
+            tx(3, 3, deposit(3000)),
+            tx(
+                1,
+                4,
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(500),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            tx(2, 2, TransactionType::Dispute),
+        ];
+
+        let outcome = processor.process_batch(batch).await.unwrap();
+
+        assert_eq!(outcome.parallel, 3);
+        assert_eq!(outcome.serial, 2);
+        assert!(outcome.results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(
+            outcome
+                .results
+                .iter()
+                .map(|(seq, _)| *seq)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+
+        let client = client_repo
+            .find_client_by_id(ClientID(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(client.lock().await.available(), MoneyType::new(500));
+
+        let client = client_repo
+            .find_client_by_id(ClientID(2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(client.lock().await.held(), MoneyType::new(2000));
+    }
+
+    #[tokio::test]
+    async fn test_panicked_transaction_fails_the_batch() {
+        let processor = SpeculativeBatchProcessor::new(Arc::new(PanickingService), 4);
+
+        let batch = vec![tx(1, 1, deposit(1000)), tx(9, 2, deposit(1000))];
+
+        assert!(matches!(
+            processor.process_batch(batch).await,
+            Err(BatchError::TaskFailed(_))
+        ));
+
+        let outcome = processor
+            .process_batch(vec![tx(1, 1, deposit(1000))])
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.parallel, 1);
+    }
+}
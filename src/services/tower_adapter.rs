@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use futures::future::BoxFuture;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::PollSemaphore;
+
+use crate::models::transactions::Transaction;
+use crate::services::transaction_service::{Processed, TTransactionService};
+
+/// Adapter exposing any [`TTransactionService`] as a [`tower::Service`], so the engine
+/// can be slotted into existing tower/axum middleware stacks.
+///
+/// Readiness is tied to the number of transactions in flight: once `max_in_flight`
+/// transactions are being processed, `poll_ready` will not resolve until one of them finishes,
+/// which propagates backpressure to whoever is driving the service.
+pub struct TransactionServiceAdapter<S> {
+    service: Arc<S>,
+    semaphore: PollSemaphore,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<S> TransactionServiceAdapter<S> {
+    pub fn new(service: Arc<S>, max_in_flight: usize) -> Self {
+        Self {
+            service,
+            semaphore: PollSemaphore::new(Arc::new(Semaphore::new(max_in_flight))),
+            permit: None,
+        }
+    }
+}
+
+impl<S> Clone for TransactionServiceAdapter<S> {
+    fn clone(&self) -> Self {
+        // Each clone must acquire its own permit, so we don't share the one we might hold
+        Self {
+            service: self.service.clone(),
+            semaphore: self.semaphore.clone(),
+            permit: None,
+        }
+    }
+}
+
+impl<S> tower::Service<Transaction> for TransactionServiceAdapter<S>
+where
+    S: TTransactionService + 'static,
+    S::Error: 'static,
+{
+    type Response = Processed;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Processed, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.permit.is_none() {
+            // The semaphore is never closed, so we will always get a permit eventually
+            self.permit = ready!(self.semaphore.poll_acquire(cx));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, transaction: Transaction) -> Self::Future {
+        let permit = self
+            .permit
+            .take()
+            .expect("poll_ready must be called before call");
+
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let result = service.process_transaction(transaction).await;
+
+            drop(permit);
+
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tower_adapter_tests {
+    use std::sync::Arc;
+
+    use futures::FutureExt;
+    use tower::{Service, ServiceExt};
+
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::services::tower_adapter::TransactionServiceAdapter;
+    use crate::services::transaction_service::{TransactionProcessingError, TransactionService};
+
+    #[tokio::test]
+    async fn test_adapter_readiness() -> Result<(), TransactionProcessingError> {
+        let service = Arc::new(TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        ));
+
+        let mut adapter = TransactionServiceAdapter::new(service, 1);
+        let mut other = adapter.clone();
+
+        adapter.ready().await?;
+
+        // The only permit is held by the first adapter, so the second one can't be ready
+        assert!(other.ready().now_or_never().is_none());
+
+        let deposit = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(1000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        adapter.call(deposit).await?;
+
+        assert!(other.ready().now_or_never().is_some());
+
+        Ok(())
+    }
+}
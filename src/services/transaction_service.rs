@@ -1,26 +1,91 @@
 use std::error::Error;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::lock::Mutex;
+use futures::stream::BoxStream;
+use futures::StreamExt;
 
 use thiserror::Error;
 
+use crate::events::{DomainEvent, TDomainEventHandler, TEffectsHandler};
+use crate::fees::FeeSchedule;
+use crate::infrastructure::event_store::TEventStore;
+use crate::limits::{VelocityLimitExceeded, VelocityLimits};
 use crate::models::client::{Client, ClientOperationError};
+use crate::models::effects::{Effects, ExecutionMode, TransactionChange};
 use crate::models::transactions::{Transaction, TransactionError, TransactionType};
-use crate::models::{ClientID, TransactionID};
+use crate::models::{ClientID, MoneyType, Timestamp, TransactionID};
+use crate::rejections::TRejectionReason;
 use crate::repositories::clients::{StoredClient, TClientRepository};
-use crate::repositories::transactions::TTransactionRepository;
+use crate::repositories::transactions::{StoredTX, TTransactionRepository};
+use crate::repositories::RepoError;
+use crate::screening::{ScreeningAction, ScreeningError, TScreeningProvider};
+use crate::services::decision::{
+    decide, decide_expiry, decide_fee, decide_transfer, DecisionPolicy, DisputabilityWindow,
+    DisputePolicy, DuplicatePolicy,
+};
+use crate::services::deferred::{DeferralPolicy, DeferredTransactions};
+use crate::services::unit_of_work::UnitOfWork;
+use crate::services::validation::{TTransactionValidator, ValidationError, ValidatorChain};
+use crate::tx_reception::current_timestamp;
+use crate::wal::{LoggedEntry, TWriteAheadLog};
+use crate::warnings::{TWarningSink, Warning};
 
 /// The transaction processing service.
 /// Meant to process individual transactions taking into account a state of the system.
 pub trait TTransactionService: Send + Sync {
-    type Error: Error + Send + Sync;
+    type Error: Error + TRejectionReason + Send + Sync;
+
+    /// Process a given transaction, telling how it was when it wasn't rejected.
+    ///
+    /// The returned future is `Send` so the service can be driven from any task.
+    fn process_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> impl Future<Output = Result<Processed, Self::Error>> + Send;
+}
 
-    /// Process a given transaction.
-    async fn process_transaction(&self, transaction: Transaction) -> Result<(), Self::Error>;
+/// How a transaction which wasn't rejected was processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Processed {
+    /// Its effects were applied, or only decided on in a dry run
+    Applied,
+    /// It references a transaction which didn't arrive yet, so it was parked without any
+    /// effect, to be applied once that transaction arrives
+    Deferred,
+    /// One of its clients is blocked, so it was set aside without being applied, to be
+    /// reviewed
+    Quarantined,
+    /// It was ignored as the policies ask, such as a duplicate or the dispute of a withdrawal
+    Ignored,
 }
 
 /// The transaction service, meant to handle transactions
 pub struct TransactionService<CR, TR> {
     client_repository: CR,
     transaction_repository: TR,
+    event_handlers: Vec<Box<dyn TDomainEventHandler>>,
+    effects_handlers: Vec<Box<dyn TEffectsHandler>>,
+    warning_sinks: Vec<Box<dyn TWarningSink>>,
+    validators: ValidatorChain,
+    write_ahead_log: Option<Box<dyn TWriteAheadLog>>,
+    event_store: Option<Box<dyn TEventStore>>,
+    policy: DecisionPolicy,
+    fees: Option<FeeSchedule>,
+    limits: Option<VelocityLimits>,
+    screening: Option<Box<dyn TScreeningProvider>>,
+    screening_action: ScreeningAction,
+    mode: ExecutionMode,
+    deferred: std::sync::Mutex<DeferredTransactions>,
+    /// The transactions of blocked clients which were set aside, in the order they arrived
+    quarantined: std::sync::Mutex<Vec<Transaction>>,
+    /// Every authorization made before this time was swept, so the next sweep starts from it
+    holds_swept_until: AtomicU64,
+    /// When the earliest of the authorizations stored since the last sweep started was made
+    authorized_since_sweep: AtomicU64,
 }
 
 impl<CR, TR> TTransactionService for TransactionService<CR, TR>
@@ -30,203 +95,3171 @@ where
 {
     type Error = TransactionProcessingError;
 
-    async fn process_transaction(&self, transaction: Transaction) -> Result<(), Self::Error> {
-        let tx_client = match self
-            .client_repository
-            .find_client_by_id(transaction.client())
+    async fn process_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> Result<Processed, Self::Error> {
+        let (client_id, tx_id) = (transaction.client(), transaction.transaction_id());
+
+        // Keep a copy of the transactions which reference another one, so we can park
+        // them if the referenced transaction has not arrived yet
+        let deferrable = match transaction.tx_type() {
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Chargeback
+            | TransactionType::Representment
+            | TransactionType::Capture => Some(transaction.clone()),
+            _ => None,
+        };
+
+        let result = match self.validators.validate(&transaction) {
+            Ok(()) => match self.screen(&transaction).await {
+                Ok(Some(_)) if self.screening_action == ScreeningAction::Quarantine => {
+                    self.quarantine(transaction);
+
+                    return Ok(Processed::Quarantined);
+                }
+                Ok(Some(blocked)) => Err(TransactionProcessingError::ClientBlocked(tx_id, blocked)),
+                Ok(None) => self.run(transaction, self.mode, true).await,
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(err.into()),
+        };
+
+        match result {
+            Ok(effects) => {
+                if let (TransactionChange::Store(stored), ExecutionMode::Apply) =
+                    (&effects.transaction_change, self.mode)
+                {
+                    self.record_authorization(stored);
+
+                    self.replay_deferred(tx_id).await;
+                }
+
+                Ok(Processed::Applied)
+            }
+            Err(
+                err @ (TransactionProcessingError::DisputedTransactionDoesNotExist(_)
+                | TransactionProcessingError::SettledDisputedTransactionDoesNotExist(_)
+                | TransactionProcessingError::RepresentedTransactionDoesNotExist(_)
+                | TransactionProcessingError::CapturedTransactionDoesNotExist(_)),
+            ) => match deferrable {
+                Some(transaction) if self.mode == ExecutionMode::Apply => self
+                    .defer(transaction)
+                    .map(|()| Processed::Deferred)
+                    .map_err(|_| err),
+                _ => Err(err),
+            },
+            Err(TransactionProcessingError::DuplicateTransaction(_))
+                if self.policy.duplicates == DuplicatePolicy::Ignore =>
+            {
+                self.warn(Warning::DuplicateTransactionIgnored {
+                    client: client_id,
+                    transaction: tx_id,
+                });
+
+                Ok(Processed::Ignored)
+            }
+            Err(TransactionProcessingError::WithdrawalDisputeIgnored(_)) => {
+                self.warn(Warning::WithdrawalDisputeIgnored {
+                    client: client_id,
+                    transaction: tx_id,
+                });
+
+                Ok(Processed::Ignored)
+            }
+            Err(err) => {
+                tracing::error!(
+                    tx = %tx_id,
+                    client = %client_id,
+                    code = err.rejection_code().code(),
+                    error = %err,
+                    "Transaction rejected"
+                );
+
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<CR, TR> TransactionService<CR, TR>
+where
+    CR: TClientRepository,
+    TR: TTransactionRepository,
+{
+    /// Decide on the effects of the given transaction, without applying them.
+    ///
+    /// This goes through exactly the same path as processing a transaction, so the effects
+    /// are exactly what processing it would have applied.
+    pub async fn dry_run(
+        &self,
+        transaction: Transaction,
+    ) -> Result<Effects, TransactionProcessingError> {
+        self.validators.validate(&transaction)?;
+
+        if let Some(blocked) = self.screen(&transaction).await? {
+            return Err(TransactionProcessingError::ClientBlocked(
+                transaction.transaction_id(),
+                blocked,
+            ));
+        }
+
+        self.run(transaction, ExecutionMode::DryRun, true).await
+    }
+
+    /// The history of the client, as its transactions are now, in the order they were applied
+    /// to it. Meant for statements and for looking into disputes.
+    pub async fn client_history(
+        &self,
+        client_id: ClientID,
+    ) -> Result<BoxStream<'static, Transaction>, RepoError> {
+        Ok(self
+            .transaction_repository
+            .find_txs_by_client(client_id)
+            .await?
+            .then(|stored_tx| async move { stored_tx.lock().await.clone() })
+            .boxed())
+    }
+
+    /// Take the transactions of blocked clients quarantined so far, in the order they arrived,
+    /// so they can be reviewed and, once cleared, processed again
+    pub fn take_quarantined(&self) -> Vec<Transaction> {
+        let mut quarantined = match self.quarantined.lock() {
+            Ok(quarantined) => quarantined,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        std::mem::take(&mut *quarantined)
+    }
+
+    /// Release the funds held by the authorizations which expired by the given time without
+    /// being captured, each of them appended to the write-ahead log before its funds are.
+    ///
+    /// Nothing expires without an authorization expiry. The authorizations made without a
+    /// timestamp are stamped with the time they were processed at, so they expire as well.
+    /// Meant to be called periodically, or once all of the input has been processed. Each sweep
+    /// only goes through the authorizations made since the ones the previous sweep expired, and
+    /// those which failed to expire are tried again by the next one. Fails only when the
+    /// authorizations can't be looked up.
+    pub async fn expire_holds(&self, now: Timestamp) -> Result<HoldSweep, RepoError> {
+        let Some(expiry) = self.policy.authorization_expiry else {
+            return Ok(HoldSweep::default());
+        };
+
+        let authorized_before = now.saturating_sub(expiry.as_secs());
+
+        // The authorizations stored from now on might be missed by the lookup, so they are
+        // left to the next sweep
+        let stored_since = self
+            .authorized_since_sweep
+            .swap(Timestamp::MAX, Ordering::Relaxed);
+
+        let swept_until = self
+            .holds_swept_until
+            .load(Ordering::Relaxed)
+            .min(stored_since);
+
+        let authorizations = match self
+            .transaction_repository
+            .find_txs_in_range(swept_until..authorized_before)
             .await
         {
-            None => self.initialize_empty_client(transaction.client()).await,
-            Some(client) => client,
+            Ok(authorizations) => authorizations,
+            Err(err) => {
+                self.authorized_since_sweep
+                    .fetch_min(stored_since, Ordering::Relaxed);
+
+                return Err(err);
+            }
         };
 
-        let tx_processing_result = match transaction.tx_type() {
-            TransactionType::Deposit { amount, .. } => {
-                let mut client_guard = tx_client.lock().await;
+        let mut sweep = HoldSweep::default();
+        let mut retry_from = authorized_before;
 
-                client_guard.deposit(*amount)?;
+        for stored_tx in authorizations {
+            match self.expire_hold(&stored_tx, now, true).await {
+                Ok(true) => sweep.expired += 1,
+                Ok(false) => {}
+                Err(err) => {
+                    let authorization = stored_tx.lock().await;
 
-                // We only want to directly store the transactions which are
-                // Entities in their own right.
-                self.transaction_repository.store_tx(transaction).await;
+                    retry_from = retry_from.min(authorization.timestamp().unwrap_or_default());
 
-                Ok(())
+                    sweep.failed.push((authorization.transaction_id(), err));
+                }
             }
-            TransactionType::Withdrawal { amount, .. } => {
-                let mut client_guard = tx_client.lock().await;
+        }
 
-                client_guard.withdraw(*amount)?;
+        self.holds_swept_until.store(retry_from, Ordering::Relaxed);
 
-                // We only want to directly store the transactions which are
-                // Entities in their own right.
-                self.transaction_repository.store_tx(transaction).await;
+        Ok(sweep)
+    }
 
-                Ok(())
-            }
-            TransactionType::Dispute => {
-                match self
-                    .transaction_repository
-                    .find_tx_by_id(transaction.transaction_id())
-                    .await
-                {
-                    None => {
-                        return Err(TransactionProcessingError::DisputedTransactionDoesNotExist(
-                            transaction.transaction_id(),
-                        ));
+    /// Restore the state lost in a crash, by replaying the transactions and the expiries read
+    /// from the write-ahead log, in order. Returns the amount of them which were replayed.
+    ///
+    /// The replayed entries are not appended to the log again. The expiries are replayed as of
+    /// the time they happened, so they only apply again with the same authorization expiry.
+    pub async fn recover(&self, entries: Vec<LoggedEntry>) -> usize {
+        let mut replayed = 0;
+
+        for entry in entries {
+            match entry {
+                LoggedEntry::Transaction(transaction) => {
+                    match self.run(transaction, ExecutionMode::Apply, false).await {
+                        Ok(_) => replayed += 1,
+                        Err(err) => {
+                            tracing::error!(error = %err, "Error replaying logged transaction")
+                        }
                     }
-                    Some(disputed_tx) => {
-                        let mut tx_guard = disputed_tx.lock().await;
+                }
+                LoggedEntry::Expiry {
+                    authorization, at, ..
+                } => match self.replay_expiry(authorization, at).await {
+                    Ok(true) => replayed += 1,
+                    Ok(false) => tracing::warn!(
+                        tx = %authorization,
+                        "The logged expiry of the authorization no longer applies"
+                    ),
+                    Err(err) => tracing::error!(
+                        tx = %authorization,
+                        error = %err,
+                        "Error replaying logged expiry"
+                    ),
+                },
+            }
+        }
+
+        replayed
+    }
+
+    async fn replay_expiry(
+        &self,
+        authorization: TransactionID,
+        at: Timestamp,
+    ) -> Result<bool, TransactionProcessingError> {
+        match self
+            .transaction_repository
+            .find_tx_by_id(authorization)
+            .await?
+        {
+            Some(stored_tx) => self.expire_hold(&stored_tx, at, false).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Decide on the effects of the transaction and, depending on the mode, apply them.
+    ///
+    /// When applying, the transaction is appended to the write-ahead log (if there is one,
+    /// and unless it's being replayed from it) before any of its effects are applied.
+    #[tracing::instrument(
+        name = "transaction",
+        skip_all,
+        fields(tx = %transaction.transaction_id(), client = %transaction.client(), ?mode)
+    )]
+    async fn run(
+        &self,
+        transaction: Transaction,
+        mode: ExecutionMode,
+        write_ahead: bool,
+    ) -> Result<Effects, TransactionProcessingError> {
+        let transaction = self.stamp_authorization(transaction);
 
-                        tx_guard.dispute(transaction)?;
+        if let TransactionType::Transfer { .. } = transaction.tx_type() {
+            return self.run_transfer(transaction, mode, write_ahead).await;
+        }
 
-                        let mut client_guard = tx_client.lock().await;
+        let tx_client = self
+            .find_or_initialize_client(transaction.client(), mode)
+            .await?;
 
-                        match tx_guard.tx_type() {
-                            TransactionType::Deposit { amount, .. } => {
-                                client_guard.dispute_deposited_funds(*amount)?;
-                            }
-                            TransactionType::Withdrawal { amount, .. } => {
-                                client_guard.dispute_withdrawn_funds(*amount)?;
-                            }
-                            _ => unreachable!("Transaction type is not valid"),
-                        }
+        let referenced_tx = self.find_referenced_tx(&transaction).await?;
+
+        let mut referenced_guard = match &referenced_tx {
+            Some(stored_tx) => Some(stored_tx.lock().await),
+            None => None,
+        };
+
+        let collector = match &self.fees {
+            Some(fees) if fees.charges(&transaction) => Some((
+                fees.collector(),
+                self.find_or_initialize_client(fees.collector(), mode)
+                    .await?,
+            )),
+            _ => None,
+        };
+
+        // Just like the clients of a transfer, the client and the collector of the fees are
+        // always locked in the order of their ids
+        let (mut client_guard, mut collector_guard) = match &collector {
+            Some((collector_id, collector)) if *collector_id < transaction.client() => {
+                let collector_guard = collector.lock().await;
+
+                (tx_client.lock().await, Some(collector_guard))
+            }
+            Some((_, collector)) => {
+                let client_guard = tx_client.lock().await;
+
+                (client_guard, Some(collector.lock().await))
+            }
+            None => (tx_client.lock().await, None),
+        };
+
+        self.check_limits(&transaction)?;
+
+        let fee = match &self.fees {
+            Some(fees) => fees.fee_of(&transaction, referenced_guard.as_deref()),
+            None => MoneyType::ZERO,
+        };
+
+        let effects = decide(
+            transaction,
+            &client_guard,
+            referenced_guard.as_deref(),
+            &self.policy,
+        )?;
+
+        let (effects, fee_effects) = match &collector_guard {
+            Some(collector_guard) if fee.is_positive() => {
+                let (effects, collected) =
+                    decide_fee(effects, &client_guard, collector_guard, fee)?;
+
+                (effects, Some(collected))
+            }
+            _ => (effects, None),
+        };
+
+        let mut unit_of_work = UnitOfWork::default();
+
+        if let ExecutionMode::Apply = mode {
+            if let (Some(wal), true) = (&self.write_ahead_log, write_ahead) {
+                wal.append(effects.transaction())?;
+            }
+
+            let executed = match self
+                .execute(
+                    &effects,
+                    (&tx_client, &mut client_guard),
+                    referenced_tx.as_ref().zip(referenced_guard.as_deref_mut()),
+                    &mut unit_of_work,
+                )
+                .await
+            {
+                Ok(()) => match (&collector, collector_guard.as_deref_mut(), &fee_effects) {
+                    (Some((_, collector)), Some(collector_guard), Some(fee_effects)) => {
+                        self.execute(
+                            fee_effects,
+                            (collector, collector_guard),
+                            None,
+                            &mut unit_of_work,
+                        )
+                        .await
                     }
-                };
+                    _ => Ok(()),
+                },
+                Err(err) => Err(err),
+            }
+            .and_then(|()| {
+                self.store_events([Some(&effects), fee_effects.as_ref()].into_iter().flatten())
+            });
+
+            // Both the transaction and its fee are applied, or neither of them is
+            if let Err(err) = executed {
+                drop(client_guard);
+                drop(collector_guard);
+                drop(referenced_guard);
 
-                Ok(())
+                unit_of_work
+                    .rollback(&self.client_repository, &self.transaction_repository)
+                    .await;
+
+                return Err(err);
             }
-            TransactionType::Resolve | TransactionType::Chargeback => {
-                match self
-                    .transaction_repository
-                    .find_tx_by_id(transaction.transaction_id())
+
+            self.record_limits(effects.transaction());
+        }
+
+        drop(client_guard);
+        drop(collector_guard);
+        drop(referenced_guard);
+
+        if let ExecutionMode::Apply = mode {
+            unit_of_work
+                .commit(&self.client_repository, &self.transaction_repository)
+                .await?;
+
+            self.publish_events(&effects);
+
+            if let Some(fee_effects) = &fee_effects {
+                self.publish_events(fee_effects);
+            }
+        }
+
+        self.effects_handlers.iter().for_each(|handler| {
+            handler.handle(&effects, mode);
+
+            if let Some(fee_effects) = &fee_effects {
+                handler.handle(fee_effects, mode);
+            }
+        });
+
+        Ok(effects)
+    }
+
+    /// Decide on the effects of a transfer and, depending on the mode, apply them to both
+    /// clients at once. Returns the effects on the client sending the funds.
+    async fn run_transfer(
+        &self,
+        transaction: Transaction,
+        mode: ExecutionMode,
+        write_ahead: bool,
+    ) -> Result<Effects, TransactionProcessingError> {
+        let (source_id, tx_id) = (transaction.client(), transaction.transaction_id());
+
+        let destination_id = transaction
+            .destination()
+            .expect("Transfers always have a destination");
+
+        // Checked before locking anything, as we can't lock the same client twice
+        if source_id == destination_id {
+            return Err(TransactionProcessingError::TransferToSameClient(tx_id));
+        }
+
+        let source = self.find_or_initialize_client(source_id, mode).await?;
+        let destination = self.find_or_initialize_client(destination_id, mode).await?;
+
+        let referenced_tx = self.find_referenced_tx(&transaction).await?;
+
+        let referenced_guard = match &referenced_tx {
+            Some(stored_tx) => Some(stored_tx.lock().await),
+            None => None,
+        };
+
+        // The clients are always locked in the order of their ids, so two transfers between
+        // the same clients, in opposite directions, can't deadlock
+        let (mut source_guard, mut destination_guard) = if source_id < destination_id {
+            let source_guard = source.lock().await;
+
+            (source_guard, destination.lock().await)
+        } else {
+            let destination_guard = destination.lock().await;
+
+            (source.lock().await, destination_guard)
+        };
+
+        self.check_limits(&transaction)?;
+
+        let (debit, credit) = decide_transfer(
+            transaction,
+            &source_guard,
+            &destination_guard,
+            referenced_guard.as_deref(),
+            &self.policy,
+        )?;
+
+        let mut unit_of_work = UnitOfWork::default();
+
+        if let ExecutionMode::Apply = mode {
+            if let (Some(wal), true) = (&self.write_ahead_log, write_ahead) {
+                wal.append(debit.transaction())?;
+            }
+
+            let executed = match self
+                .execute(
+                    &debit,
+                    (&source, &mut source_guard),
+                    None,
+                    &mut unit_of_work,
+                )
+                .await
+            {
+                Ok(()) => {
+                    self.execute(
+                        &credit,
+                        (&destination, &mut destination_guard),
+                        None,
+                        &mut unit_of_work,
+                    )
                     .await
-                {
-                    None => {
-                        return Err(
-                            TransactionProcessingError::SettledDisputedTransactionDoesNotExist(
-                                transaction.transaction_id(),
-                            ),
-                        );
-                    }
-                    Some(disputed_tx) => {
-                        let mut tx_guard = disputed_tx.lock().await;
-
-                        tx_guard.settle_dispute(transaction.clone())?;
-
-                        let mut tx_client = tx_client.lock().await;
-
-                        match transaction.tx_type() {
-                            TransactionType::Resolve => {
-                                tx_client.resolve_funds(tx_guard.amount()?)?;
-                            }
-                            TransactionType::Chargeback => {
-                                tx_client.chargeback_funds(tx_guard.amount()?)?;
-                            }
-                            _ => {
-                                // This is unreachable as we have just checked it in the previous match
-                                unreachable!()
-                            }
-                        }
-                    }
-                };
+                }
+                Err(err) => Err(err),
+            }
+            .and_then(|()| self.store_events([&debit, &credit]));
+
+            // Both clients are changed, or neither of them is
+            if let Err(err) = executed {
+                drop(source_guard);
+                drop(destination_guard);
+                drop(referenced_guard);
 
-                Ok(())
+                unit_of_work
+                    .rollback(&self.client_repository, &self.transaction_repository)
+                    .await;
+
+                return Err(err);
             }
+
+            self.record_limits(debit.transaction());
+        }
+
+        drop(source_guard);
+        drop(destination_guard);
+        drop(referenced_guard);
+
+        if let ExecutionMode::Apply = mode {
+            unit_of_work
+                .commit(&self.client_repository, &self.transaction_repository)
+                .await?;
+
+            self.publish_events(&debit);
+            self.publish_events(&credit);
+        }
+
+        self.effects_handlers.iter().for_each(|handler| {
+            handler.handle(&debit, mode);
+            handler.handle(&credit, mode);
+        });
+
+        Ok(debit)
+    }
+
+    /// Check the transaction against the velocity limits, if there are any. Must be called
+    /// with the client locked, so no other transaction of the client is recorded in between.
+    fn check_limits(&self, transaction: &Transaction) -> Result<(), TransactionProcessingError> {
+        match &self.limits {
+            Some(limits) => limits.check(transaction).map_err(|err| {
+                TransactionProcessingError::VelocityLimitExceeded(transaction.transaction_id(), err)
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn record_limits(&self, transaction: &Transaction) {
+        if let Some(limits) = &self.limits {
+            limits.record(transaction);
+        }
+    }
+
+    /// Authorizations can only expire as of when they were made, so the ones which don't say
+    /// when are made now, once they can expire
+    fn stamp_authorization(&self, mut transaction: Transaction) -> Transaction {
+        if let (TransactionType::Authorize { .. }, None, Some(_)) = (
+            transaction.tx_type(),
+            transaction.timestamp(),
+            self.policy.authorization_expiry,
+        ) {
+            transaction.assign_timestamp(current_timestamp());
+        }
+
+        transaction
+    }
+
+    /// Make sure the next sweep of the holds goes back to the given stored authorization, even
+    /// when it was made before the ones already swept
+    fn record_authorization(&self, transaction: &Transaction) {
+        if let (TransactionType::Authorize { .. }, Some(authorized_at)) =
+            (transaction.tx_type(), transaction.timestamp())
+        {
+            self.authorized_since_sweep
+                .fetch_min(authorized_at, Ordering::Relaxed);
+        }
+    }
+
+    /// Expire the given stored transaction, if it is an authorization which expired by the
+    /// given time. Returns whether it expired.
+    async fn expire_hold(
+        &self,
+        stored_tx: &StoredTX,
+        now: Timestamp,
+        write_ahead: bool,
+    ) -> Result<bool, TransactionProcessingError> {
+        let mut authorization = stored_tx.lock().await;
+
+        if !authorization.is_pending_authorization() {
+            return Ok(false);
+        }
+
+        let tx_client = self
+            .find_or_initialize_client(authorization.client(), self.mode)
+            .await?;
+
+        let mut client_guard = tx_client.lock().await;
+
+        let Some(effects) = decide_expiry(&authorization, &client_guard, now, &self.policy)? else {
+            return Ok(false);
         };
 
-        self.client_repository.save_client(tx_client).await;
+        let mut unit_of_work = UnitOfWork::default();
+
+        if let ExecutionMode::Apply = self.mode {
+            if let (Some(wal), true) = (&self.write_ahead_log, write_ahead) {
+                wal.append_expiry(&authorization, now)?;
+            }
+
+            let executed = self
+                .execute(
+                    &effects,
+                    (&tx_client, &mut client_guard),
+                    Some((stored_tx, &mut *authorization)),
+                    &mut unit_of_work,
+                )
+                .await
+                .and_then(|()| self.store_events([&effects]));
+
+            if let Err(err) = executed {
+                drop(client_guard);
+                drop(authorization);
+
+                unit_of_work
+                    .rollback(&self.client_repository, &self.transaction_repository)
+                    .await;
+
+                return Err(err);
+            }
+        }
+
+        drop(client_guard);
+        drop(authorization);
+
+        if let ExecutionMode::Apply = self.mode {
+            unit_of_work
+                .commit(&self.client_repository, &self.transaction_repository)
+                .await?;
+
+            self.publish_events(&effects);
+        }
+
+        self.effects_handlers
+            .iter()
+            .for_each(|handler| handler.handle(&effects, self.mode));
 
-        tx_processing_result
+        Ok(true)
     }
-}
 
-impl<CR, TR> TransactionService<CR, TR>
-where
-    CR: TClientRepository,
-{
-    pub(crate) fn new(client_repo: CR, transaction_repo: TR) -> Self {
-        Self {
-            client_repository: client_repo,
-            transaction_repository: transaction_repo,
+    /// Find the client, creating it if it doesn't exist yet
+    async fn find_or_initialize_client(
+        &self,
+        client_id: ClientID,
+        mode: ExecutionMode,
+    ) -> Result<StoredClient, RepoError> {
+        Ok(
+            match self.client_repository.find_client_by_id(client_id).await? {
+                Some(client) => client,
+                None => match mode {
+                    ExecutionMode::Apply => self.initialize_empty_client(client_id).await?,
+                    // A dry run must not leave anything behind, so we don't store the new client
+                    ExecutionMode::DryRun => Arc::new(Mutex::new(
+                        Client::builder().with_client_id(client_id).build(),
+                    )),
+                },
+            },
+        )
+    }
+
+    /// Find the stored transaction with the same id as the given one.
+    ///
+    /// Disputes, resolves, chargebacks, representments and captures reference the transaction
+    /// they target, while deposits, withdrawals, authorizations and transfers must not reuse
+    /// the id of another transaction, so we have to load it before deciding anything.
+    /// Administrative transactions are never stored, so their ids don't matter.
+    async fn find_referenced_tx(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Option<StoredTX>, RepoError> {
+        match transaction.tx_type() {
+            TransactionType::Deposit { .. }
+            | TransactionType::Withdrawal { .. }
+            | TransactionType::Authorize { .. }
+            | TransactionType::Transfer { .. }
+                if self.policy.duplicates == DuplicatePolicy::Process =>
+            {
+                Ok(None)
+            }
+            TransactionType::Unfreeze | TransactionType::Close => Ok(None),
+            _ => {
+                self.transaction_repository
+                    .find_tx_by_id(transaction.transaction_id())
+                    .await
+            }
         }
     }
 
-    /// Initialize the empty client
-    async fn initialize_empty_client(&self, client_id: ClientID) -> StoredClient {
-        let client = Client::builder().with_client_id(client_id).build();
+    /// The first of the clients of the transaction which is blocked, if any of them is
+    async fn screen(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Option<ClientID>, TransactionProcessingError> {
+        let Some(screening) = &self.screening else {
+            return Ok(None);
+        };
+
+        for client in std::iter::once(transaction.client()).chain(transaction.destination()) {
+            if screening.is_blocked(client).await? {
+                return Ok(Some(client));
+            }
+        }
 
-        self.client_repository.store_client(client).await
+        Ok(None)
     }
-}
 
-/// The processing errors for the transaction service
-#[derive(Error, Debug)]
-pub enum TransactionProcessingError {
-    #[error("Client error {0:?}")]
-    ClientError(#[from] ClientOperationError),
-    #[error("Transaction error {0:?}")]
-    TransactionError(#[from] TransactionError),
-    #[error("The disputed transaction does not exist")]
-    DisputedTransactionDoesNotExist(TransactionID),
-    #[error("The settled dispute transaction does not exist")]
-    SettledDisputedTransactionDoesNotExist(TransactionID),
-}
+    /// Set aside a transaction of a blocked client, without applying it
+    fn quarantine(&self, transaction: Transaction) {
+        self.warn(Warning::TransactionQuarantined {
+            client: transaction.client(),
+            transaction: transaction.transaction_id(),
+        });
 
-#[cfg(test)]
-mod service_tests {
-    use futures::lock::Mutex;
-    use std::sync::Arc;
+        match self.quarantined.lock() {
+            Ok(mut quarantined) => quarantined.push(transaction),
+            Err(poisoned) => poisoned.into_inner().push(transaction),
+        }
+    }
 
-    use mockall::predicate::eq;
+    /// Park a transaction until the transaction it references arrives
+    fn defer(&self, transaction: Transaction) -> Result<(), Transaction> {
+        let mut deferred = match self.deferred.lock() {
+            Ok(deferred) => deferred,
+            Err(poisoned) => poisoned.into_inner(),
+        };
 
-    use crate::models::client::Client;
-    use crate::models::transactions::{Transaction, TransactionType};
-    use crate::repositories::clients::MockTClientRepository;
-    use crate::repositories::transactions::MockTTransactionRepository;
-    use crate::services::transaction_service::{
-        TTransactionService, TransactionProcessingError, TransactionService,
-    };
+        let (client, tx_id) = (transaction.client(), transaction.transaction_id());
 
-    #[tokio::test]
-    async fn test_deposit_transaction_processing() -> Result<(), TransactionProcessingError> {
-        let mut cli_repo = MockTClientRepository::new();
-        let mut tx_repo = MockTTransactionRepository::new();
+        let evicted = deferred.park(transaction)?;
 
-        let client = {
-            let client = Arc::new(Mutex::new(Client::builder().with_client_id(1).build()));
+        drop(deferred);
 
-            cli_repo
-                .expect_find_client_by_id()
-                .with(eq(1))
-                .return_const(Some(client.clone()));
+        self.warn(Warning::TransactionDeferred {
+            client,
+            transaction: tx_id,
+        });
 
-            cli_repo.expect_save_client().once().return_const(());
+        if let Some(evicted) = evicted {
+            self.warn(Warning::DeferredTransactionDropped {
+                client: evicted.client(),
+                transaction: evicted.transaction_id(),
+            });
+        }
 
-            tx_repo
-                .expect_store_tx()
-                .times(1)
-                .returning(|tx| Arc::new(Mutex::new(tx)));
+        Ok(())
+    }
 
-            client
+    /// Process the transactions which were waiting on the given transaction to arrive
+    async fn replay_deferred(&self, tx_id: TransactionID) {
+        let parked = {
+            let mut deferred = match self.deferred.lock() {
+                Ok(deferred) => deferred,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+
+            if deferred.is_empty() {
+                return;
+            }
+
+            deferred.take(tx_id)
         };
 
-        let tx_service = TransactionService::new(cli_repo, tx_repo);
+        for transaction in parked {
+            if let Err(err) = self.run(transaction, ExecutionMode::Apply, true).await {
+                tracing::error!(error = %err, "Error processing deferred transaction");
+            }
+        }
+    }
 
-        let test_tx = Transaction::builder()
-            .with_client_id(1)
-            .with_tx_type(TransactionType::Deposit {
-                amount: 1000,
-                dispute: None,
-            })
-            .with_tx_id(1)
-            .build();
+    /// Apply the decided effects to the client and the stored transactions, registering every
+    /// change in the unit of work, which saves them (or undoes them) once they are unlocked.
+    ///
+    /// This is the only place where the state of the system is changed. The transaction
+    /// change goes first, so the client is left untouched when it fails. The events are only
+    /// published once the unit of work is committed.
+    async fn execute(
+        &self,
+        effects: &Effects,
+        (stored_client, client): (&StoredClient, &mut Client),
+        referenced: Option<(&StoredTX, &mut Transaction)>,
+        unit_of_work: &mut UnitOfWork,
+    ) -> Result<(), TransactionProcessingError> {
+        let (stored_tx, mut referenced_tx) = referenced.unzip();
+        let tx_before = referenced_tx.as_deref().cloned();
 
-        tx_service.process_transaction(test_tx).await?;
+        match &effects.transaction_change {
+            TransactionChange::Store(transaction) => {
+                // We only want to directly store the transactions which are
+                // Entities in their own right.
+                let stored = self
+                    .transaction_repository
+                    .store_tx(transaction.clone())
+                    .await?;
 
-        let client_guard = client.lock().await;
+                unit_of_work.register_stored_tx(&stored);
+            }
+            TransactionChange::OpenDispute(dispute, mode) => {
+                if let Some(disputed_tx) = referenced_tx.as_deref_mut() {
+                    disputed_tx.dispute_with_mode(dispute.clone(), *mode)?;
+                }
+            }
+            TransactionChange::SettleDispute(settlement) => {
+                if let Some(disputed_tx) = referenced_tx.as_deref_mut() {
+                    disputed_tx.settle_dispute(settlement.clone())?;
+                }
+            }
+            TransactionChange::ReverseChargeback(representment) => {
+                if let Some(charged_back_tx) = referenced_tx.as_deref_mut() {
+                    charged_back_tx.represent(representment.clone())?;
+                }
+            }
+            TransactionChange::Capture(capture) => {
+                if let Some(authorization) = referenced_tx.as_deref_mut() {
+                    authorization.capture(capture.clone())?;
+                }
+            }
+            TransactionChange::Expire(_) => {
+                if let Some(authorization) = referenced_tx.as_deref_mut() {
+                    authorization.expire()?;
+                }
+            }
+            // The transfer was stored along with the debit of its source
+            TransactionChange::Credit(_)
+            | TransactionChange::CollectFee(_)
+            | TransactionChange::Administer(_) => {}
+        }
+
+        if let (Some(stored_tx), Some(before), Some(after)) =
+            (stored_tx, tx_before, referenced_tx.as_deref())
+        {
+            if before != *after {
+                unit_of_work.register_tx(stored_tx, before, after);
+            }
+        }
+
+        let client_before = client.clone();
+
+        client.apply_effects(effects);
+
+        unit_of_work.register_client(stored_client, client_before, client);
+
+        Ok(())
+    }
+
+    fn publish_events(&self, effects: &Effects) {
+        effects.events.iter().for_each(|event| self.publish(event));
+    }
+
+    /// Append the events of the given effects to the event store, if there is one
+    fn store_events<'a>(
+        &self,
+        effects: impl IntoIterator<Item = &'a Effects>,
+    ) -> Result<(), TransactionProcessingError> {
+        let Some(event_store) = &self.event_store else {
+            return Ok(());
+        };
+
+        for event in effects.into_iter().flat_map(|effects| &effects.events) {
+            event_store.append(event)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<CR, TR> TransactionService<CR, TR>
+where
+    CR: TClientRepository,
+{
+    pub fn new(client_repo: CR, transaction_repo: TR) -> Self {
+        Self {
+            client_repository: client_repo,
+            transaction_repository: transaction_repo,
+            event_handlers: Vec::new(),
+            effects_handlers: Vec::new(),
+            warning_sinks: Vec::new(),
+            validators: ValidatorChain::default(),
+            write_ahead_log: None,
+            event_store: None,
+            policy: DecisionPolicy::default(),
+            fees: None,
+            limits: None,
+            screening: None,
+            screening_action: ScreeningAction::default(),
+            mode: ExecutionMode::default(),
+            deferred: Default::default(),
+            quarantined: Default::default(),
+            holds_swept_until: AtomicU64::new(0),
+            authorized_since_sweep: AtomicU64::new(Timestamp::MAX),
+        }
+    }
+
+    /// Choose what happens to deposits and withdrawals reusing the id of a stored transaction
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.policy.duplicates = policy;
+
+        self
+    }
+
+    /// Park the disputes, resolves, chargebacks, representments and captures which arrive
+    /// before the transaction they reference, replaying them once it arrives.
+    pub fn with_deferral_policy(mut self, policy: DeferralPolicy) -> Self {
+        self.deferred = std::sync::Mutex::new(DeferredTransactions::new(policy));
+
+        self
+    }
+
+    /// Choose whether the processed transactions are actually applied
+    pub fn with_execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.mode = mode;
+
+        self
+    }
+
+    /// Choose how disputes of withdrawals affect the clients
+    pub fn with_dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.policy.withdrawal_disputes = policy;
+
+        self
+    }
+
+    /// Limit for how long transactions can be disputed
+    pub fn with_disputability_window(mut self, window: DisputabilityWindow) -> Self {
+        self.policy.disputability_window = window;
+
+        self
+    }
+
+    /// Charge the transactions with the fees of the given schedule, crediting them to its
+    /// collector
+    pub fn with_fee_schedule(mut self, fees: FeeSchedule) -> Self {
+        self.fees = Some(fees);
+
+        self
+    }
+
+    /// Screen the clients of every transaction with the given provider, rejecting or
+    /// quarantining the transactions of the blocked ones
+    pub fn with_screening(
+        mut self,
+        provider: impl TScreeningProvider + 'static,
+        action: ScreeningAction,
+    ) -> Self {
+        self.screening = Some(Box::new(provider));
+        self.screening_action = action;
+
+        self
+    }
+
+    /// Hold every client to the given rolling limits, rejecting the transactions which would
+    /// break them
+    pub fn with_velocity_limits(mut self, limits: VelocityLimits) -> Self {
+        self.limits = Some(limits);
+
+        self
+    }
+
+    /// Limit for how long authorizations can be captured, after which captures are rejected
+    pub fn with_authorization_expiry(mut self, expiry: Duration) -> Self {
+        self.policy.authorization_expiry = Some(expiry);
+
+        self
+    }
+
+    /// Append every accepted transaction to the given log before applying it, so the state
+    /// can be recovered after a crash
+    pub fn with_write_ahead_log(mut self, write_ahead_log: impl TWriteAheadLog + 'static) -> Self {
+        self.write_ahead_log = Some(Box::new(write_ahead_log));
+
+        self
+    }
+
+    /// Keep the events of every applied transaction in the given store. The events are
+    /// appended along with the changes, so a transaction whose events can't be stored is
+    /// rejected and undone instead of missing from the store.
+    pub fn with_event_store(mut self, event_store: impl TEventStore + 'static) -> Self {
+        self.event_store = Some(Box::new(event_store));
+
+        self
+    }
+
+    /// Register a handler which will receive every domain event produced by this service
+    pub fn register_event_handler(&mut self, handler: impl TDomainEventHandler + 'static) {
+        self.event_handlers.push(Box::new(handler));
+    }
+
+    /// Register a handler which will receive the effects of every processed transaction,
+    /// regardless of the execution mode
+    pub fn register_effects_handler(&mut self, handler: impl TEffectsHandler + 'static) {
+        self.effects_handlers.push(Box::new(handler));
+    }
+
+    /// Register a validator which every transaction must pass before being processed.
+    ///
+    /// Validators run in the order they were registered. The transactions replayed from the
+    /// write-ahead log were already validated, so they are not validated again.
+    pub fn register_validator(&mut self, validator: impl TTransactionValidator + 'static) {
+        self.validators.push(validator);
+    }
+
+    /// Register a sink which will receive the warnings produced by this service
+    pub fn register_warning_sink(&mut self, sink: impl TWarningSink + 'static) {
+        self.warning_sinks.push(Box::new(sink));
+    }
+
+    fn warn(&self, warning: Warning) {
+        self.warning_sinks
+            .iter()
+            .for_each(|sink| sink.warn(&warning));
+    }
+
+    /// Publish a domain event to all of the registered handlers
+    fn publish(&self, event: &DomainEvent) {
+        self.event_handlers
+            .iter()
+            .for_each(|handler| handler.handle(event));
+    }
+
+    /// Initialize the empty client
+    async fn initialize_empty_client(
+        &self,
+        client_id: ClientID,
+    ) -> Result<StoredClient, RepoError> {
+        let client = Client::builder().with_client_id(client_id).build();
+
+        let created = DomainEvent::AccountCreated { client: client_id };
+
+        // Stored first, so no client is ever created without its event
+        if let Some(event_store) = &self.event_store {
+            event_store.append(&created)?;
+        }
+
+        let stored_client = self.client_repository.store_client(client).await?;
+
+        self.publish(&created);
+
+        Ok(stored_client)
+    }
+}
+
+/// The outcome of a sweep of the holds of the expired authorizations
+#[derive(Debug, Default)]
+pub struct HoldSweep {
+    /// The amount of authorizations which expired
+    pub expired: usize,
+    /// The authorizations which should have expired but didn't, with why, in the order they
+    /// were made
+    pub failed: Vec<(TransactionID, TransactionProcessingError)>,
+}
+
+/// The processing errors for the transaction service
+#[derive(Error, Debug)]
+pub enum TransactionProcessingError {
+    #[error("Client error {0:?}")]
+    ClientError(#[from] ClientOperationError),
+    #[error("Transaction error {0:?}")]
+    TransactionError(#[from] TransactionError),
+    #[error("The disputed transaction does not exist")]
+    DisputedTransactionDoesNotExist(TransactionID),
+    #[error("The settled dispute transaction does not exist")]
+    SettledDisputedTransactionDoesNotExist(TransactionID),
+    #[error("The represented transaction does not exist")]
+    RepresentedTransactionDoesNotExist(TransactionID),
+    #[error("The captured authorization does not exist")]
+    CapturedTransactionDoesNotExist(TransactionID),
+    #[error("The transaction {0:?} is outside of the disputability window")]
+    TransactionNoLongerDisputable(TransactionID),
+    #[error("The transaction {0:?} happened {1:?} seconds before its dispute, outside of the dispute window")]
+    DisputeWindowExpired(TransactionID, Timestamp),
+    #[error("The authorization {0:?} happened {1:?} seconds before its capture, after it expired")]
+    AuthorizationExpired(TransactionID, Timestamp),
+    #[error("The dispute of withdrawal {0:?} was ignored, as withdrawals can't be disputed")]
+    WithdrawalDisputeIgnored(TransactionID),
+    #[error("A transaction with the id {0:?} already exists")]
+    DuplicateTransaction(TransactionID),
+    #[error("The transaction {0:?} belongs to client {1:?}, but was referenced by client {2:?}")]
+    ReferencedTransactionClientMismatch(TransactionID, ClientID, ClientID),
+    #[error("The transfer {0:?} has the same source and destination")]
+    TransferToSameClient(TransactionID),
+    #[error(
+        "The transfer {0:?} needs more funds than available ({1:?} while trying to transfer {2:?})"
+    )]
+    TransferInsufficientFunds(TransactionID, MoneyType, MoneyType),
+    #[error("The transfer {0:?} is to client {1:?}, whose account is frozen")]
+    TransferDestinationFrozen(TransactionID, ClientID),
+    #[error("The transfer {0:?} is to client {1:?}, whose account is closed")]
+    TransferDestinationClosed(TransactionID, ClientID),
+    #[error("The transaction {0:?} exceeds a velocity limit: {1}")]
+    VelocityLimitExceeded(TransactionID, VelocityLimitExceeded),
+    #[error("The transaction {0:?} involves client {1:?}, who is blocked")]
+    ClientBlocked(TransactionID, ClientID),
+    #[error("Failed to screen the clients of the transaction {0}")]
+    ScreeningError(#[from] ScreeningError),
+    #[error("{0}")]
+    ValidationError(#[from] ValidationError),
+    #[error("Failed to append the transaction to the write-ahead log {0:?}")]
+    WriteAheadLogError(#[from] std::io::Error),
+    #[error("Repository error {0}")]
+    RepositoryError(#[from] RepoError),
+}
+
+#[cfg(test)]
+mod service_tests {
+    use futures::lock::Mutex;
+    use futures::stream::BoxStream;
+    use futures::StreamExt;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    use mockall::predicate::eq;
+
+    use crate::audit::AuditLog;
+    use crate::events::{DomainEvent, TDomainEventHandler};
+    use crate::fees::{FeeSchedule, FlatFee, PercentageFee};
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::limits::{VelocityLimit, VelocityLimits};
+    use crate::models::client::{Client, ClientAccountStatus, ClientOperationError};
+    use crate::models::effects::ExecutionMode;
+    use crate::models::transactions::{
+        AuthorizationState, DisputeStage, DisputeState, Transaction, TransactionType,
+    };
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::rejections::{RejectionCode, TRejectionReason};
+    use crate::repositories::clients::MockTClientRepository;
+    use crate::repositories::clients::{StoredClient, TClientRepository};
+    use crate::repositories::transactions::{MockTTransactionRepository, TTransactionRepository};
+    use crate::repositories::RepoError;
+    use crate::screening::{BlockedClientList, ScreeningAction};
+    use crate::services::decision::{DisputabilityWindow, DisputePolicy, DuplicatePolicy};
+    use crate::services::deferred::DeferralPolicy;
+    use crate::services::transaction_service::{
+        HoldSweep, Processed, TTransactionService, TransactionProcessingError, TransactionService,
+    };
+    use crate::services::validation::AmountCap;
+    use crate::tx_reception::current_timestamp;
+    use crate::wal::WriteAheadLog;
+    use crate::warnings::WarningCounter;
+    use crate::{ShareableClientRepository, ShareableTransactionRepository};
+
+    #[tokio::test]
+    async fn test_deposit_transaction_processing() -> Result<(), TransactionProcessingError> {
+        let mut cli_repo = MockTClientRepository::new();
+        let mut tx_repo = MockTTransactionRepository::new();
+
+        let client = {
+            let client = Arc::new(Mutex::new(
+                Client::builder().with_client_id(ClientID(1)).build(),
+            ));
+
+            cli_repo
+                .expect_find_client_by_id()
+                .with(eq(ClientID(1)))
+                .returning({
+                    let client = client.clone();
+
+                    move |_| {
+                        let client = client.clone();
+
+                        Box::pin(async move { Ok(Some(client)) })
+                    }
+                });
+
+            cli_repo
+                .expect_save_client()
+                .once()
+                .returning(|_| Box::pin(async { Ok(()) }));
+
+            tx_repo
+                .expect_find_tx_by_id()
+                .with(eq(TransactionID(1)))
+                .returning(|_| Box::pin(async { Ok(None) }));
+
+            tx_repo
+                .expect_store_tx()
+                .times(1)
+                .returning(|tx| Box::pin(async move { Ok(Arc::new(Mutex::new(tx))) }));
+
+            client
+        };
+
+        let tx_service = TransactionService::new(cli_repo, tx_repo);
+
+        let test_tx = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(1000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        tx_service.process_transaction(test_tx).await?;
+
+        let client_guard = client.lock().await;
+
+        assert_eq!(client_guard.available(), MoneyType::new(1000));
+        assert_eq!(client_guard.held(), MoneyType::new(0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repository_failure() {
+        let mut cli_repo = MockTClientRepository::new();
+        let mut tx_repo = MockTTransactionRepository::new();
+
+        let client = Arc::new(Mutex::new(
+            Client::builder().with_client_id(ClientID(1)).build(),
+        ));
+
+        cli_repo.expect_find_client_by_id().returning({
+            let client = client.clone();
+
+            move |_| {
+                let client = client.clone();
+
+                Box::pin(async move { Ok(Some(client)) })
+            }
+        });
+
+        cli_repo.expect_save_client().never();
+
+        tx_repo
+            .expect_find_tx_by_id()
+            .returning(|_| Box::pin(async { Ok(None) }));
+
+        tx_repo.expect_store_tx().once().returning(|_| {
+            Box::pin(async {
+                Err(RepoError::IoError(std::io::Error::other(
+                    "connection reset",
+                )))
+            })
+        });
+
+        let tx_service = TransactionService::new(cli_repo, tx_repo);
+
+        let result = tx_service
+            .process_transaction(
+                Transaction::builder()
+                    .with_client_id(ClientID(1))
+                    .with_tx_id(TransactionID(1))
+                    .with_tx_type(TransactionType::Deposit {
+                        amount: MoneyType::new(1000),
+                        dispute: DisputeState::NotDisputed,
+                    })
+                    .build(),
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(TransactionProcessingError::RepositoryError(
+                RepoError::IoError(_)
+            ))
+        ));
+
+        // The deposit could not be stored, so it must not have been applied either
+        assert_eq!(client.lock().await.available(), MoneyType::new(0));
+    }
+
+    #[tokio::test]
+    async fn test_failed_save_is_undone() {
+        let mut cli_repo = MockTClientRepository::new();
+        let mut tx_repo = MockTTransactionRepository::new();
+
+        let client = Arc::new(Mutex::new(
+            Client::builder().with_client_id(ClientID(1)).build(),
+        ));
+
+        cli_repo.expect_find_client_by_id().returning({
+            let client = client.clone();
+
+            move |_| {
+                let client = client.clone();
+
+                Box::pin(async move { Ok(Some(client)) })
+            }
+        });
+
+        // Saved with the deposit, and then again once it's undone
+        cli_repo.expect_save_client().times(2).returning(|_| {
+            Box::pin(async {
+                Err(RepoError::IoError(std::io::Error::other(
+                    "connection reset",
+                )))
+            })
+        });
+
+        tx_repo
+            .expect_find_tx_by_id()
+            .returning(|_| Box::pin(async { Ok(None) }));
+
+        tx_repo
+            .expect_store_tx()
+            .once()
+            .returning(|tx| Box::pin(async move { Ok(Arc::new(Mutex::new(tx))) }));
+
+        // The stored deposit is removed, so it can be processed again
+        tx_repo
+            .expect_remove_tx()
+            .once()
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        let tx_service = TransactionService::new(cli_repo, tx_repo);
+
+        let result = tx_service
+            .process_transaction(
+                Transaction::builder()
+                    .with_client_id(ClientID(1))
+                    .with_tx_id(TransactionID(1))
+                    .with_tx_type(TransactionType::Deposit {
+                        amount: MoneyType::new(1000),
+                        dispute: DisputeState::NotDisputed,
+                    })
+                    .build(),
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(TransactionProcessingError::RepositoryError(
+                RepoError::IoError(_)
+            ))
+        ));
+
+        // The client could not be saved, so its changes are undone in memory as well
+        let client = client.lock().await;
+
+        assert_eq!(client.available(), MoneyType::new(0));
+        assert_eq!(client.transaction_count(), 0);
+    }
+
+    /// Client repository whose first save waits until it's released and then fails, so other
+    /// transactions can go through while the first one is being committed
+    struct StalledClientRepository {
+        repo: ClientInMemRepository,
+        stalled: std::sync::Mutex<Option<(oneshot::Sender<()>, oneshot::Receiver<()>)>>,
+    }
+
+    impl StalledClientRepository {
+        /// The repository, a receiver told once the first save started, and a sender which
+        /// makes it fail
+        fn new() -> (Self, oneshot::Receiver<()>, oneshot::Sender<()>) {
+            let (started, on_started) = oneshot::channel();
+            let (fail, on_fail) = oneshot::channel();
+
+            let repo = Self {
+                repo: ClientInMemRepository::default(),
+                stalled: std::sync::Mutex::new(Some((started, on_fail))),
+            };
+
+            (repo, on_started, fail)
+        }
+    }
+
+    impl TClientRepository for StalledClientRepository {
+        async fn find_all_clients(&self) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+            self.repo.find_all_clients().await
+        }
+
+        async fn find_all_clients_sorted(
+            &self,
+        ) -> Result<BoxStream<'static, StoredClient>, RepoError> {
+            self.repo.find_all_clients_sorted().await
+        }
+
+        async fn find_client_by_id(
+            &self,
+            client_id: ClientID,
+        ) -> Result<Option<StoredClient>, RepoError> {
+            self.repo.find_client_by_id(client_id).await
+        }
+
+        async fn save_client(&self, client: StoredClient) -> Result<(), RepoError> {
+            let stalled = self.stalled.lock().unwrap().take();
+
+            match stalled {
+                Some((started, on_fail)) => {
+                    let _ = started.send(());
+                    let _ = on_fail.await;
+
+                    Err(RepoError::IoError(std::io::Error::other(
+                        "connection reset",
+                    )))
+                }
+                None => self.repo.save_client(client).await,
+            }
+        }
+
+        async fn store_client(&self, client: Client) -> Result<StoredClient, RepoError> {
+            self.repo.store_client(client).await
+        }
+
+        async fn save_client_if_version(
+            &self,
+            client: Client,
+            expected_version: u64,
+        ) -> Result<StoredClient, RepoError> {
+            self.repo
+                .save_client_if_version(client, expected_version)
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_keeps_concurrent_changes() -> Result<(), TransactionProcessingError>
+    {
+        let (cli_repo, on_started, fail) = StalledClientRepository::new();
+
+        let cli_repo = ShareableClientRepository::from(cli_repo);
+        let tx_repo = ShareableTransactionRepository::from(TransactionInMemRepository::default());
+
+        let mut tx_service = TransactionService::new(cli_repo.clone(), tx_repo.clone());
+
+        let handler = RecordingHandler::default();
+
+        tx_service.register_event_handler(handler.clone());
+
+        let tx_service = Arc::new(tx_service);
+
+        let deposit = |tx_id, amount| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(amount),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .build()
+        };
+
+        let failing = tokio::spawn({
+            let tx_service = tx_service.clone();
+            let deposit = deposit(1, 10000);
+
+            async move { tx_service.process_transaction(deposit).await }
+        });
+
+        // The first deposit is applied and being committed, so the client is unlocked
+        on_started.await.unwrap();
+
+        tx_service.process_transaction(deposit(2, 5000)).await?;
+
+        fail.send(()).unwrap();
+
+        assert!(matches!(
+            failing.await.unwrap(),
+            Err(TransactionProcessingError::RepositoryError(
+                RepoError::IoError(_)
+            ))
+        ));
+
+        let client = cli_repo.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        // Only the failed deposit is undone, not the one which went through in the meantime
+        assert_eq!(client.lock().await.available(), MoneyType::new(5000));
+        assert_eq!(client.lock().await.transaction_count(), 1);
+
+        assert!(tx_repo.find_tx_by_id(TransactionID(1)).await?.is_none());
+
+        // Nothing is published about the deposit which failed
+        assert!(!handler.events.lock().unwrap().iter().any(|event| matches!(
+            event,
+            DomainEvent::DepositApplied { transaction, .. } if *transaction == TransactionID(1)
+        )));
+
+        // It was never stored, so it can be processed again
+        tx_service.process_transaction(deposit(1, 10000)).await?;
+
+        assert_eq!(client.lock().await.available(), MoneyType::new(15000));
+
+        Ok(())
+    }
+
+    #[derive(Default, Clone)]
+    struct RecordingHandler {
+        events: Arc<std::sync::Mutex<Vec<DomainEvent>>>,
+    }
+
+    impl TDomainEventHandler for RecordingHandler {
+        fn handle(&self, event: &DomainEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_domain_events_emitted() -> Result<(), TransactionProcessingError> {
+        let handler = RecordingHandler::default();
+
+        let mut tx_service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        );
+
+        tx_service.register_event_handler(handler.clone());
+
+        let deposit = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(1000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        let dispute = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Dispute)
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        let chargeback = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Chargeback)
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        tx_service.process_transaction(deposit).await?;
+        tx_service.process_transaction(dispute).await?;
+        tx_service.process_transaction(chargeback).await?;
+
+        let events = handler.events.lock().unwrap();
+
+        assert_eq!(
+            *events,
+            vec![
+                DomainEvent::AccountCreated {
+                    client: ClientID(1)
+                },
+                DomainEvent::DepositApplied {
+                    client: ClientID(1),
+                    transaction: TransactionID(1),
+                    amount: MoneyType::new(1000)
+                },
+                DomainEvent::DisputeOpened {
+                    client: ClientID(1),
+                    transaction: TransactionID(1),
+                    amount: MoneyType::new(1000)
+                },
+                DomainEvent::ChargebackApplied {
+                    client: ClientID(1),
+                    transaction: TransactionID(1),
+                    amount: MoneyType::new(1000)
+                },
+                DomainEvent::AccountFrozen {
+                    client: ClientID(1)
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unfreeze_account() -> Result<(), TransactionProcessingError> {
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(clients.clone(), TransactionInMemRepository::default());
+
+        let unfreeze = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Unfreeze)
+            .with_tx_id(TransactionID(100))
+            .build();
+
+        for (tx_id, tx_type) in [
+            (
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(1000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (1, TransactionType::Dispute),
+            (1, TransactionType::Chargeback),
+        ] {
+            tx_service
+                .process_transaction(
+                    Transaction::builder()
+                        .with_client_id(ClientID(1))
+                        .with_tx_type(tx_type)
+                        .with_tx_id(TransactionID(tx_id))
+                        .build(),
+                )
+                .await?;
+        }
+
+        tx_service.process_transaction(unfreeze.clone()).await?;
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        assert_eq!(
+            *client.lock().await.account_status(),
+            ClientAccountStatus::Active
+        );
+
+        // The account can be used again
+        tx_service
+            .process_transaction(
+                Transaction::builder()
+                    .with_client_id(ClientID(1))
+                    .with_tx_type(TransactionType::Deposit {
+                        amount: MoneyType::new(500),
+                        dispute: DisputeState::NotDisputed,
+                    })
+                    .with_tx_id(TransactionID(2))
+                    .build(),
+            )
+            .await?;
+
+        assert_eq!(client.lock().await.available(), MoneyType::new(500));
+
+        assert!(matches!(
+            tx_service.process_transaction(unfreeze).await,
+            Err(TransactionProcessingError::ClientError(
+                ClientOperationError::AccountNotFrozen
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_representment() -> Result<(), TransactionProcessingError> {
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+        let transactions =
+            ShareableTransactionRepository::from(TransactionInMemRepository::default());
+
+        let tx_service = TransactionService::new(clients.clone(), transactions.clone());
+
+        let tx = |tx_id, tx_type| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(tx_id))
+                .build()
+        };
+
+        let deposit = |amount| TransactionType::Deposit {
+            amount: MoneyType::new(amount),
+            dispute: DisputeState::NotDisputed,
+        };
+
+        for (tx_id, tx_type) in [
+            (1, deposit(1000)),
+            (2, deposit(500)),
+            (1, TransactionType::Dispute),
+            (1, TransactionType::Chargeback),
+        ] {
+            tx_service.process_transaction(tx(tx_id, tx_type)).await?;
+        }
+
+        tx_service
+            .process_transaction(tx(1, TransactionType::Representment))
+            .await?;
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        {
+            let client = client.lock().await;
+
+            assert_eq!(client.available(), MoneyType::new(1500));
+            assert_eq!(client.held(), MoneyType::new(0));
+            assert_eq!(*client.account_status(), ClientAccountStatus::Active);
+        }
+
+        let represented = transactions.find_tx_by_id(TransactionID(1)).await?.unwrap();
+
+        assert_eq!(
+            represented.lock().await.dispute_state().stage(),
+            DisputeStage::Represented
+        );
+
+        for (tx_id, code) in [
+            (1, RejectionCode::ChargebackAlreadyRepresented),
+            (2, RejectionCode::TransactionNotChargedBack),
+        ] {
+            let result = tx_service
+                .process_transaction(tx(tx_id, TransactionType::Representment))
+                .await;
+
+            assert_eq!(result.map_err(|err| err.rejection_code()), Err(code));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_representment_with_other_disputes() -> Result<(), TransactionProcessingError> {
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(clients.clone(), TransactionInMemRepository::default());
+
+        let tx = |tx_id, tx_type| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(tx_id))
+                .build()
+        };
+
+        let deposit = |amount| TransactionType::Deposit {
+            amount: MoneyType::new(amount),
+            dispute: DisputeState::NotDisputed,
+        };
+
+        // The chargeback freezes the account while the other dispute is still open, which is
+        // resolved while frozen
+        for (tx_id, tx_type) in [
+            (1, deposit(1000)),
+            (2, deposit(500)),
+            (1, TransactionType::Dispute),
+            (2, TransactionType::Dispute),
+            (1, TransactionType::Chargeback),
+            (2, TransactionType::Resolve),
+            (1, TransactionType::Representment),
+        ] {
+            tx_service.process_transaction(tx(tx_id, tx_type)).await?;
+        }
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+        let client = client.lock().await;
+
+        assert_eq!(client.available(), MoneyType::new(1500));
+        assert_eq!(client.held(), MoneyType::new(0));
+        assert_eq!(client.disputes().open_disputes(), 0);
+        assert_eq!(*client.account_status(), ClientAccountStatus::Active);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_authorization_capture() -> Result<(), TransactionProcessingError> {
+        const HOUR: u64 = 60 * 60;
+
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+        let transactions =
+            ShareableTransactionRepository::from(TransactionInMemRepository::default());
+
+        let tx_service = TransactionService::new(clients.clone(), transactions.clone())
+            .with_authorization_expiry(Duration::from_secs(24 * HOUR));
+
+        let tx = |tx_id, tx_type, timestamp| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(tx_id))
+                .with_timestamp(timestamp)
+                .build()
+        };
+
+        let authorize = |amount| TransactionType::Authorize {
+            amount: MoneyType::new(amount),
+            state: AuthorizationState::Pending,
+        };
+
+        for (tx_id, tx_type) in [
+            (
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(1000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (2, authorize(300)),
+            (3, authorize(200)),
+        ] {
+            tx_service
+                .process_transaction(tx(tx_id, tx_type, 0))
+                .await?;
+        }
+
+        assert_eq!(
+            tx_service
+                .process_transaction(tx(4, authorize(600), 0))
+                .await
+                .map_err(|err| err.rejection_code()),
+            Err(RejectionCode::InsufficientFunds)
+        );
+
+        tx_service
+            .process_transaction(tx(2, TransactionType::Capture, 2 * HOUR))
+            .await?;
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        {
+            let client = client.lock().await;
+
+            assert_eq!(client.available(), MoneyType::new(500));
+            assert_eq!(client.held(), MoneyType::new(200));
+            assert_eq!(client.transaction_count(), 3);
+        }
+
+        let captured = transactions.find_tx_by_id(TransactionID(2)).await?.unwrap();
+
+        assert!(matches!(
+            captured.lock().await.tx_type(),
+            TransactionType::Authorize {
+                state: AuthorizationState::Captured,
+                ..
+            }
+        ));
+
+        for (tx_id, code) in [
+            (2, RejectionCode::AuthorizationAlreadyCaptured),
+            (3, RejectionCode::AuthorizationExpired),
+            (1, RejectionCode::TransactionNotAuthorization),
+        ] {
+            let result = tx_service
+                .process_transaction(tx(tx_id, TransactionType::Capture, 48 * HOUR))
+                .await;
+
+            assert_eq!(result.map_err(|err| err.rejection_code()), Err(code));
+        }
+
+        // The expired authorization keeps holding its funds
+        assert_eq!(client.lock().await.held(), MoneyType::new(200));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expire_holds() -> Result<(), TransactionProcessingError> {
+        const HOUR: u64 = 60 * 60;
+
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+        let transactions =
+            ShareableTransactionRepository::from(TransactionInMemRepository::default());
+
+        let tx_service = TransactionService::new(clients.clone(), transactions.clone())
+            .with_authorization_expiry(Duration::from_secs(24 * HOUR));
+
+        let tx = |tx_id, tx_type, timestamp| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(tx_id))
+                .with_timestamp(timestamp)
+                .build()
+        };
+
+        let authorize = |amount| TransactionType::Authorize {
+            amount: MoneyType::new(amount),
+            state: AuthorizationState::Pending,
+        };
+
+        for (tx_id, tx_type, timestamp) in [
+            (
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(1000),
+                    dispute: DisputeState::NotDisputed,
+                },
+                0,
+            ),
+            (2, authorize(300), 0),
+            (3, authorize(200), 0),
+            (4, authorize(100), 12 * HOUR),
+            (2, TransactionType::Capture, 2 * HOUR),
+        ] {
+            tx_service
+                .process_transaction(tx(tx_id, tx_type, timestamp))
+                .await?;
+        }
+
+        // Only the authorization which was neither captured nor is still within the expiry
+        assert_eq!(tx_service.expire_holds(30 * HOUR).await?.expired, 1);
+        assert_eq!(tx_service.expire_holds(30 * HOUR).await?.expired, 0);
+
+        {
+            let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+            let client = client.lock().await;
+
+            assert_eq!(client.available(), MoneyType::new(600));
+            assert_eq!(client.held(), MoneyType::new(100));
+        }
+
+        let expired = transactions.find_tx_by_id(TransactionID(3)).await?.unwrap();
+
+        assert!(matches!(
+            expired.lock().await.tx_type(),
+            TransactionType::Authorize {
+                state: AuthorizationState::Expired,
+                ..
+            }
+        ));
+
+        assert_eq!(
+            tx_service
+                .process_transaction(tx(3, TransactionType::Capture, 30 * HOUR))
+                .await
+                .map_err(|err| err.rejection_code()),
+            Err(RejectionCode::AuthorizationExpired)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expire_holds_sweeps() -> Result<(), TransactionProcessingError> {
+        const HOUR: u64 = 60 * 60;
+
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+        let transactions =
+            ShareableTransactionRepository::from(TransactionInMemRepository::default());
+
+        let tx_service = TransactionService::new(clients.clone(), transactions.clone())
+            .with_authorization_expiry(Duration::from_secs(24 * HOUR));
+
+        let now = current_timestamp();
+
+        let tx = |client, tx_id, tx_type| {
+            Transaction::builder()
+                .with_client_id(ClientID(client))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(tx_type)
+                .build()
+        };
+
+        let authorize = |client, tx_id| {
+            tx(
+                client,
+                tx_id,
+                TransactionType::Authorize {
+                    amount: MoneyType::new(100),
+                    state: AuthorizationState::Pending,
+                },
+            )
+        };
+
+        let at = |mut transaction: Transaction, timestamp| {
+            transaction.assign_timestamp(timestamp);
+
+            transaction
+        };
+
+        tx_service
+            .process_transaction(at(
+                tx(
+                    1,
+                    1,
+                    TransactionType::Deposit {
+                        amount: MoneyType::new(1000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                now - 72 * HOUR,
+            ))
+            .await?;
+        tx_service
+            .process_transaction(at(authorize(1, 2), now - 48 * HOUR))
+            .await?;
+
+        // Stamped when processed, so it expires a day from now
+        tx_service.process_transaction(authorize(1, 3)).await?;
+
+        let stamped = transactions.find_tx_by_id(TransactionID(3)).await?.unwrap();
+
+        assert!(stamped.lock().await.timestamp() >= Some(now));
+
+        // Holding nothing, so its funds can't be released
+        transactions
+            .store_tx(at(authorize(2, 4), now - 48 * HOUR))
+            .await?;
+
+        let failed = |sweep: &HoldSweep| {
+            sweep
+                .failed
+                .iter()
+                .map(|(tx, err)| (*tx, err.rejection_code()))
+                .collect::<Vec<_>>()
+        };
+
+        // The failure doesn't stop the others from expiring
+        let sweep = tx_service.expire_holds(now).await?;
+
+        assert_eq!(sweep.expired, 1);
+        assert_eq!(
+            failed(&sweep),
+            vec![(TransactionID(4), RejectionCode::InsufficientHeldFunds)]
+        );
+
+        // Made before those which were swept, but still found by the next sweep
+        tx_service
+            .process_transaction(at(authorize(1, 5), now - 72 * HOUR))
+            .await?;
+
+        let sweep = tx_service.expire_holds(now).await?;
+
+        assert_eq!(sweep.expired, 1);
+        assert_eq!(failed(&sweep).len(), 1);
+
+        let sweep = tx_service.expire_holds(now + 25 * HOUR).await?;
+
+        assert_eq!(sweep.expired, 1);
+        assert_eq!(failed(&sweep).len(), 1);
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        assert_eq!(client.lock().await.held(), MoneyType::ZERO);
+        assert_eq!(client.lock().await.available(), MoneyType::new(1000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expiry_recovery() -> Result<(), TransactionProcessingError> {
+        const HOUR: u64 = 60 * 60;
+
+        let path =
+            std::env::temp_dir().join(format!("service-expiry-wal-{}.csv", std::process::id()));
+
+        let _ = std::fs::remove_file(&path);
+
+        let tx_service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        )
+        .with_authorization_expiry(Duration::from_secs(24 * HOUR))
+        .with_write_ahead_log(WriteAheadLog::open(&path)?.with_sync(false));
+
+        for (tx_id, tx_type) in [
+            (
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(1000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                2,
+                TransactionType::Authorize {
+                    amount: MoneyType::new(300),
+                    state: AuthorizationState::Pending,
+                },
+            ),
+        ] {
+            tx_service
+                .process_transaction(
+                    Transaction::builder()
+                        .with_client_id(ClientID(1))
+                        .with_tx_id(TransactionID(tx_id))
+                        .with_tx_type(tx_type)
+                        .with_timestamp(0)
+                        .build(),
+                )
+                .await?;
+        }
+
+        assert_eq!(tx_service.expire_holds(30 * HOUR).await?.expired, 1);
+
+        // Simulate a crash, losing all of the in memory state
+        drop(tx_service);
+
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                .with_authorization_expiry(Duration::from_secs(24 * HOUR));
+
+        assert_eq!(
+            tx_service
+                .recover(WriteAheadLog::read(&path).unwrap())
+                .await,
+            3
+        );
+
+        std::fs::remove_file(&path)?;
+
+        // The hold doesn't come back with the authorization
+        let client = client_repo.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        assert_eq!(client.lock().await.held(), MoneyType::ZERO);
+        assert_eq!(client.lock().await.available(), MoneyType::new(1000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fees() -> Result<(), TransactionProcessingError> {
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let fees = FeeSchedule::new(ClientID(9))
+            .with_fee("withdrawal", FlatFee(MoneyType::new(10)))
+            .with_fee("chargeback", PercentageFee::new(1_000));
+
+        let tx_service =
+            TransactionService::new(clients.clone(), TransactionInMemRepository::default())
+                .with_fee_schedule(fees);
+
+        let tx = |client, tx_id, tx_type| {
+            Transaction::builder()
+                .with_client_id(ClientID(client))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(tx_id))
+                .build()
+        };
+
+        let deposit = |amount| TransactionType::Deposit {
+            amount: MoneyType::new(amount),
+            dispute: DisputeState::NotDisputed,
+        };
+
+        let withdrawal = |amount| TransactionType::Withdrawal {
+            amount: MoneyType::new(amount),
+            dispute: DisputeState::NotDisputed,
+        };
+
+        for (client, tx_id, tx_type) in [
+            (1, 1, deposit(1000)),
+            (1, 2, withdrawal(100)),
+            (1, 3, deposit(500)),
+            (1, 3, TransactionType::Dispute),
+            (1, 3, TransactionType::Chargeback),
+            (9, 4, deposit(100)),
+            (9, 5, withdrawal(50)),
+        ] {
+            tx_service
+                .process_transaction(tx(client, tx_id, tx_type))
+                .await?;
+        }
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        // The chargeback fee is charged even though the chargeback froze the account
+        assert_eq!(client.lock().await.available(), MoneyType::new(840));
+
+        // A fee which can't be afforded doesn't stop the transaction, the client owes it instead
+        tx_service
+            .process_transaction(tx(2, 6, deposit(500)))
+            .await?;
+        tx_service
+            .process_transaction(tx(2, 7, withdrawal(495)))
+            .await?;
+
+        let client = clients.find_client_by_id(ClientID(2)).await?.unwrap();
+
+        assert_eq!(client.lock().await.available(), MoneyType::new(-5));
+
+        // But the transaction itself still needs the funds
+        assert_eq!(
+            tx_service
+                .process_transaction(tx(2, 8, withdrawal(1)))
+                .await
+                .map_err(|err| err.rejection_code()),
+            Err(RejectionCode::InsufficientFunds)
+        );
+
+        // The collector is credited the fees, and is never charged for its own transactions
+        let collector = clients.find_client_by_id(ClientID(9)).await?.unwrap();
+
+        assert_eq!(
+            collector.lock().await.available(),
+            MoneyType::new(60 + 10 + 100 - 50)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_velocity_limits() -> Result<(), TransactionProcessingError> {
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let limits = VelocityLimits::default().with_limit(VelocityLimit::WithdrawnAmount {
+            max: MoneyType::new(1000),
+            window: Duration::from_secs(24 * 60 * 60),
+        });
+
+        let tx_service =
+            TransactionService::new(clients.clone(), TransactionInMemRepository::default())
+                .with_velocity_limits(limits);
+
+        let tx = |tx_id, tx_type, timestamp| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(tx_id))
+                .with_timestamp(timestamp)
+                .build()
+        };
+
+        let withdrawal = |amount| TransactionType::Withdrawal {
+            amount: MoneyType::new(amount),
+            dispute: DisputeState::NotDisputed,
+        };
+
+        tx_service
+            .process_transaction(tx(
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(5000),
+                    dispute: DisputeState::NotDisputed,
+                },
+                1_000,
+            ))
+            .await?;
+
+        tx_service
+            .process_transaction(tx(2, withdrawal(600), 2_000))
+            .await?;
+
+        assert_eq!(
+            tx_service
+                .process_transaction(tx(3, withdrawal(500), 3_000))
+                .await
+                .map_err(|err| err.rejection_code()),
+            Err(RejectionCode::VelocityLimitExceeded)
+        );
+
+        // The rejected withdrawal doesn't count towards the limit
+        tx_service
+            .process_transaction(tx(4, withdrawal(400), 4_000))
+            .await?;
+
+        // A day after the first withdrawal, its amount can be withdrawn again
+        tx_service
+            .process_transaction(tx(5, withdrawal(600), 2_000 + 24 * 60 * 60))
+            .await?;
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        assert_eq!(client.lock().await.available(), MoneyType::new(3400));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_screening() -> Result<(), TransactionProcessingError> {
+        let deposit = |client, tx_id| {
+            Transaction::builder()
+                .with_client_id(ClientID(client))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(100),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .with_tx_id(TransactionID(tx_id))
+                .build()
+        };
+
+        let transfer = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Transfer {
+                to_client: ClientID(3),
+                amount: MoneyType::new(50),
+            })
+            .with_tx_id(TransactionID(3))
+            .build();
+
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(clients.clone(), TransactionInMemRepository::default())
+                .with_screening(
+                    BlockedClientList::new([ClientID(3)]),
+                    ScreeningAction::Reject,
+                );
+
+        tx_service.process_transaction(deposit(1, 1)).await?;
+
+        // Both sides of a transfer are screened
+        for blocked in [deposit(3, 2), transfer.clone()] {
+            assert_eq!(
+                tx_service
+                    .process_transaction(blocked)
+                    .await
+                    .map_err(|err| err.rejection_code()),
+                Err(RejectionCode::ClientBlocked)
+            );
+        }
+
+        assert!(clients.find_client_by_id(ClientID(3)).await?.is_none());
+
+        let warnings = Arc::new(WarningCounter::default());
+
+        let mut tx_service =
+            TransactionService::new(clients.clone(), TransactionInMemRepository::default())
+                .with_screening(
+                    BlockedClientList::new([ClientID(3)]),
+                    ScreeningAction::Quarantine,
+                );
+
+        tx_service.register_warning_sink(warnings.clone());
+
+        tx_service.process_transaction(transfer.clone()).await?;
+
+        assert_eq!(tx_service.take_quarantined(), vec![transfer]);
+        assert_eq!(warnings.counts().get("transaction-quarantined"), Some(&1));
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        assert_eq!(client.lock().await.available(), MoneyType::new(100));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_close_account() -> Result<(), TransactionProcessingError> {
+        let clients = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(clients.clone(), TransactionInMemRepository::default());
+
+        let tx = |client_id, tx_id, tx_type| {
+            Transaction::builder()
+                .with_client_id(ClientID(client_id))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(tx_id))
+                .build()
+        };
+
+        let deposit = |amount| TransactionType::Deposit {
+            amount: MoneyType::new(amount),
+            dispute: DisputeState::NotDisputed,
+        };
+
+        tx_service
+            .process_transaction(tx(1, 1, deposit(1000)))
+            .await?;
+        tx_service
+            .process_transaction(tx(2, 2, deposit(1000)))
+            .await?;
+
+        // Accounts can only be closed once they are empty
+        assert!(matches!(
+            tx_service
+                .process_transaction(tx(1, 100, TransactionType::Close))
+                .await,
+            Err(TransactionProcessingError::ClientError(
+                ClientOperationError::AccountNotEmpty(available, held)
+            )) if (available, held) == (MoneyType::new(1000), MoneyType::ZERO)
+        ));
+
+        // Such as once its funds were charged back
+        tx_service
+            .process_transaction(tx(1, 1, TransactionType::Dispute))
+            .await?;
+        tx_service
+            .process_transaction(tx(1, 1, TransactionType::Chargeback))
+            .await?;
+
+        tx_service
+            .process_transaction(tx(1, 101, TransactionType::Close))
+            .await?;
+
+        let client = clients.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        assert_eq!(
+            *client.lock().await.account_status(),
+            ClientAccountStatus::Closed
+        );
+
+        for (client_id, tx_type) in [
+            (1, deposit(500)),
+            (1, TransactionType::Unfreeze),
+            (
+                2,
+                TransactionType::Transfer {
+                    to_client: ClientID(1),
+                    amount: MoneyType::new(500),
+                },
+            ),
+        ] {
+            let result = tx_service
+                .process_transaction(tx(client_id, 4, tx_type))
+                .await;
+
+            assert_eq!(
+                result.map_err(|err| err.rejection_code()),
+                Err(RejectionCode::AccountClosed)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disputability_window() -> Result<(), TransactionProcessingError> {
+        let tx_service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        )
+        .with_disputability_window(DisputabilityWindow {
+            max_subsequent_transactions: Some(1),
+            ..Default::default()
+        });
+
+        for tx_id in 1..=3 {
+            let deposit = Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(1000),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .with_tx_id(TransactionID(tx_id))
+                .build();
+
+            tx_service.process_transaction(deposit).await?;
+        }
+
+        let dispute = |tx_id| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(TransactionType::Dispute)
+                .with_tx_id(TransactionID(tx_id))
+                .build()
+        };
+
+        // Two deposits were made after the first one, so it can no longer be disputed
+        assert!(matches!(
+            tx_service.process_transaction(dispute(1)).await,
+            Err(TransactionProcessingError::TransactionNoLongerDisputable(
+                TransactionID(1)
+            ))
+        ));
+
+        tx_service.process_transaction(dispute(2)).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispute_time_window() -> Result<(), TransactionProcessingError> {
+        const DAY: u64 = 24 * 60 * 60;
+
+        let tx_service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        )
+        .with_disputability_window(DisputabilityWindow {
+            max_age: Some(Duration::from_secs(120 * DAY)),
+            ..Default::default()
+        });
+
+        for tx_id in 1..=3 {
+            let mut deposit = Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(1000),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .with_tx_id(TransactionID(tx_id))
+                .build();
+
+            // The last deposit has no timestamp, so its age is unknown
+            if tx_id < 3 {
+                deposit.assign_timestamp(tx_id as u64 * 10 * DAY);
+            }
+
+            tx_service.process_transaction(deposit).await?;
+        }
+
+        let dispute = |tx_id| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(TransactionType::Dispute)
+                .with_tx_id(TransactionID(tx_id))
+                .with_timestamp(140 * DAY)
+                .build()
+        };
+
+        // Filed 130 days after the first deposit
+        assert!(matches!(
+            tx_service.process_transaction(dispute(1)).await,
+            Err(TransactionProcessingError::DisputeWindowExpired(TransactionID(1), age)) if age == 130 * DAY
+        ));
+
+        tx_service.process_transaction(dispute(2)).await?;
+        tx_service.process_transaction(dispute(3)).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_leaves_no_state() -> Result<(), TransactionProcessingError> {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let audit_log = Arc::new(AuditLog::new(Vec::new()));
+
+        let mut tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                .with_execution_mode(ExecutionMode::DryRun);
+
+        tx_service.register_effects_handler(audit_log.clone());
+
+        let deposit = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(1000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        let effects = tx_service.dry_run(deposit.clone()).await?;
+
+        assert_eq!(effects.available_delta, MoneyType::new(1000));
+
+        tx_service.process_transaction(deposit).await?;
+
+        assert!(client_repo
+            .find_client_by_id(ClientID(1))
+            .await
+            .unwrap()
+            .is_none());
+
+        drop(tx_service);
+
+        let audit =
+            String::from_utf8(Arc::into_inner(audit_log).unwrap().into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            audit,
+            "mode,client,tx,change,available_delta,held_delta,status\n\
+             dry-run,1,1,store,0.1000,0.0000,\n\
+             dry-run,1,1,store,0.1000,0.0000,\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejected_by_validator() -> Result<(), TransactionProcessingError> {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let mut tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default());
+
+        tx_service.register_validator(AmountCap::new(MoneyType::new(10000)));
+
+        let deposit = |tx_id, amount| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(amount),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .with_tx_id(TransactionID(tx_id))
+                .build()
+        };
+
+        let rejected = tx_service.process_transaction(deposit(1, 20000)).await;
+
+        assert!(matches!(
+            rejected,
+            Err(TransactionProcessingError::ValidationError(ref err)) if err.rule() == "amount-cap"
+        ));
+        assert_eq!(
+            rejected.unwrap_err().rejection_code(),
+            RejectionCode::RejectedByValidator
+        );
+
+        // The rejected transaction never reached the client
+        assert!(client_repo
+            .find_client_by_id(ClientID(1))
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(tx_service.dry_run(deposit(2, 20000)).await.is_err());
+
+        tx_service.process_transaction(deposit(3, 10000)).await?;
+
+        let client = client_repo.find_client_by_id(ClientID(1)).await?.unwrap();
+
+        assert_eq!(client.lock().await.available(), MoneyType::new(10000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deferred_dispute() -> Result<(), TransactionProcessingError> {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                .with_deferral_policy(DeferralPolicy {
+                    capacity: 10,
+                    ..Default::default()
+                });
+
+        let dispute = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Dispute)
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        // The deposit has not arrived yet, so the dispute must be parked
+        assert_eq!(
+            tx_service.process_transaction(dispute).await?,
+            Processed::Deferred
+        );
+
+        let deposit = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(1000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        assert_eq!(
+            tx_service.process_transaction(deposit).await?,
+            Processed::Applied
+        );
+
+        let client = client_repo
+            .find_client_by_id(ClientID(1))
+            .await
+            .unwrap()
+            .unwrap();
+        let client = client.lock().await;
+
+        assert_eq!(client.available(), MoneyType::new(0));
+        assert_eq!(client.held(), MoneyType::new(1000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispute_from_wrong_client() -> Result<(), TransactionProcessingError> {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default());
+
+        let deposit = Transaction::builder()
+            .with_client_id(ClientID(7))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(1000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        tx_service.process_transaction(deposit).await?;
+
+        for tx_type in [
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+        ] {
+            let foreign_tx = Transaction::builder()
+                .with_client_id(ClientID(5))
+                .with_tx_type(tx_type)
+                .with_tx_id(TransactionID(1))
+                .build();
+
+            assert!(matches!(
+                tx_service.process_transaction(foreign_tx).await,
+                Err(
+                    TransactionProcessingError::ReferencedTransactionClientMismatch(
+                        TransactionID(1),
+                        ClientID(7),
+                        ClientID(5)
+                    )
+                )
+            ));
+        }
+
+        for client_id in [5, 7] {
+            let client = client_repo
+                .find_client_by_id(ClientID(client_id))
+                .await
+                .unwrap()
+                .unwrap();
+            let client = client.lock().await;
+
+            assert_eq!(client.held(), MoneyType::new(0));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_history() -> Result<(), TransactionProcessingError> {
+        let tx_service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        );
+
+        let transaction = |client, tx_id, tx_type| {
+            Transaction::builder()
+                .with_client_id(ClientID(client))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(tx_type)
+                .build()
+        };
+
+        for (client, tx_id, tx_type) in [
+            (
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(10000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(10000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(5000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (1, 1, TransactionType::Dispute),
+        ] {
+            tx_service
+                .process_transaction(transaction(client, tx_id, tx_type))
+                .await?;
+        }
+
+        let history = tx_service
+            .client_history(ClientID(1))
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            history
+                .iter()
+                .map(|tx| tx.transaction_id())
+                .collect::<Vec<_>>(),
+            vec![TransactionID(1), TransactionID(3)]
+        );
+
+        // The dispute is part of the history of the disputed deposit
+        assert_eq!(history[0].dispute_state().stage(), DisputeStage::Open);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_policies() -> Result<(), TransactionProcessingError> {
+        let deposit = Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(1000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .with_tx_id(TransactionID(1))
+            .build();
+
+        for (policy, expected_available) in [
+            (DuplicatePolicy::Reject, 1000),
+            (DuplicatePolicy::Ignore, 1000),
+            (DuplicatePolicy::Process, 2000),
+        ] {
+            let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+            let mut tx_service =
+                TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                    .with_duplicate_policy(policy);
+
+            let warnings = Arc::new(WarningCounter::default());
+
+            tx_service.register_warning_sink(warnings.clone());
+
+            tx_service.process_transaction(deposit.clone()).await?;
+
+            let result = tx_service.process_transaction(deposit.clone()).await;
+
+            assert_eq!(
+                matches!(
+                    result,
+                    Err(TransactionProcessingError::DuplicateTransaction(
+                        TransactionID(1)
+                    ))
+                ),
+                policy == DuplicatePolicy::Reject
+            );
+
+            let client = client_repo
+                .find_client_by_id(ClientID(1))
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(
+                client.lock().await.available(),
+                MoneyType::new(expected_available)
+            );
+
+            // Only ignoring the duplicate is silent, so it must be reported as a warning
+            assert_eq!(
+                warnings.counts().contains_key("duplicate-ignored"),
+                policy == DuplicatePolicy::Ignore
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispute_spent_deposit() -> Result<(), TransactionProcessingError> {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default());
+
+        let transactions = [
+            (
+                1,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(10000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (
+                2,
+                TransactionType::Withdrawal {
+                    amount: MoneyType::new(6000),
+                    dispute: DisputeState::NotDisputed,
+                },
+            ),
+            (1, TransactionType::Dispute),
+        ];
+
+        for (tx_id, tx_type) in transactions {
+            tx_service
+                .process_transaction(
+                    Transaction::builder()
+                        .with_client_id(ClientID(1))
+                        .with_tx_id(TransactionID(tx_id))
+                        .with_tx_type(tx_type)
+                        .build(),
+                )
+                .await?;
+        }
+
+        let client = client_repo
+            .find_client_by_id(ClientID(1))
+            .await
+            .unwrap()
+            .unwrap();
+        let client = client.lock().await;
+
+        // Most of the disputed deposit was already spent, so the available funds go negative
+        assert_eq!(client.available(), MoneyType::new(-6000));
+        assert_eq!(client.held(), MoneyType::new(10000));
+        assert_eq!(client.total(), MoneyType::new(4000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdrawal_dispute_policies() -> Result<(), TransactionProcessingError> {
+        // The balances after the dispute, and after charging it back
+        let expected = [
+            (DisputePolicy::Ignore, (6000, 0), (6000, 0)),
+            (DisputePolicy::Hold, (6000, 4000), (6000, 0)),
+            (DisputePolicy::ProvisionalCredit, (10000, 0), (6000, 0)),
+        ];
+
+        for (policy, disputed, charged_back) in expected {
+            let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+            let warnings = Arc::new(WarningCounter::default());
+
+            let mut tx_service =
+                TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                    .with_dispute_policy(policy);
+
+            tx_service.register_warning_sink(warnings.clone());
+
+            let balances = || async {
+                let client = client_repo
+                    .find_client_by_id(ClientID(1))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let client = client.lock().await;
+
+                (client.available().raw(), client.held().raw())
+            };
+
+            let transactions = [
+                (
+                    1,
+                    TransactionType::Deposit {
+                        amount: MoneyType::new(10000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                (
+                    2,
+                    TransactionType::Withdrawal {
+                        amount: MoneyType::new(4000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                (2, TransactionType::Dispute),
+                (2, TransactionType::Chargeback),
+            ];
+
+            for (index, (tx_id, tx_type)) in transactions.into_iter().enumerate() {
+                let result = tx_service
+                    .process_transaction(
+                        Transaction::builder()
+                            .with_client_id(ClientID(1))
+                            .with_tx_id(TransactionID(tx_id))
+                            .with_tx_type(tx_type)
+                            .build(),
+                    )
+                    .await;
+
+                match (index, policy) {
+                    // The ignored dispute was never opened, so it can't be charged back
+                    (3, DisputePolicy::Ignore) => assert!(result.is_err()),
+                    _ => {
+                        result?;
+                    }
+                }
+
+                if index == 2 {
+                    assert_eq!(balances().await, disputed, "{:?}", policy);
+                }
+            }
+
+            assert_eq!(balances().await, charged_back, "{:?}", policy);
+
+            assert_eq!(
+                warnings.counts().get("withdrawal-dispute-ignored").copied(),
+                (policy == DisputePolicy::Ignore).then_some(1)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispute_settled_as_opened() -> Result<(), TransactionProcessingError> {
+        // The policy the dispute is opened with, the one it is resolved with, and the balances
+        // after the dispute
+        let expected = [
+            (
+                DisputePolicy::ProvisionalCredit,
+                DisputePolicy::Hold,
+                (10000, 0),
+            ),
+            (
+                DisputePolicy::Hold,
+                DisputePolicy::ProvisionalCredit,
+                (6000, 4000),
+            ),
+        ];
+
+        for (opened_with, resolved_with, disputed) in expected {
+            let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+            let tx_repo =
+                ShareableTransactionRepository::from(TransactionInMemRepository::default());
+
+            let balances = || async {
+                let client = client_repo
+                    .find_client_by_id(ClientID(1))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let client = client.lock().await;
+
+                (client.available().raw(), client.held().raw())
+            };
+
+            let transactions = [
+                (
+                    1,
+                    TransactionType::Deposit {
+                        amount: MoneyType::new(10000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                (
+                    2,
+                    TransactionType::Withdrawal {
+                        amount: MoneyType::new(4000),
+                        dispute: DisputeState::NotDisputed,
+                    },
+                ),
+                (2, TransactionType::Dispute),
+                (2, TransactionType::Resolve),
+            ];
+
+            let mut tx_service = TransactionService::new(client_repo.clone(), tx_repo.clone())
+                .with_dispute_policy(opened_with);
+
+            for (index, (tx_id, tx_type)) in transactions.into_iter().enumerate() {
+                if index == 3 {
+                    assert_eq!(balances().await, disputed, "{:?}", opened_with);
+
+                    // The service is restarted with another policy while the dispute is open
+                    tx_service = TransactionService::new(client_repo.clone(), tx_repo.clone())
+                        .with_dispute_policy(resolved_with);
+                }
+
+                tx_service
+                    .process_transaction(
+                        Transaction::builder()
+                            .with_client_id(ClientID(1))
+                            .with_tx_id(TransactionID(tx_id))
+                            .with_tx_type(tx_type)
+                            .build(),
+                    )
+                    .await?;
+            }
+
+            // Either way, resolving the dispute gives the withdrawn amount back for good
+            assert_eq!(balances().await, (10000, 0), "{:?}", opened_with);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_ahead_log_recovery() -> Result<(), TransactionProcessingError> {
+        let path = std::env::temp_dir().join(format!("service-wal-{}.csv", std::process::id()));
+
+        let _ = std::fs::remove_file(&path);
+
+        let deposit = |tx_id, amount| {
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_id(TransactionID(tx_id))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(amount),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .build()
+        };
+
+        let tx_service = TransactionService::new(
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        )
+        .with_write_ahead_log(WriteAheadLog::open(&path)?.with_sync(false));
+
+        tx_service.process_transaction(deposit(1, 1000)).await?;
+        tx_service.process_transaction(deposit(2, 500)).await?;
+
+        // Rejected transactions must not be replayed
+        assert!(tx_service
+            .process_transaction(deposit(1, 50))
+            .await
+            .is_err());
+
+        // Simulate a crash, losing all of the in memory state
+        drop(tx_service);
+
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service =
+            TransactionService::new(client_repo.clone(), TransactionInMemRepository::default())
+                .with_write_ahead_log(WriteAheadLog::open(&path)?.with_sync(false));
+
+        let logged = WriteAheadLog::read(&path).unwrap();
+
+        assert_eq!(tx_service.recover(logged).await, 2);
+
+        let client = client_repo
+            .find_client_by_id(ClientID(1))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(client.lock().await.available(), MoneyType::new(1500));
+
+        // Recovering doesn't log the transactions again
+        assert_eq!(WriteAheadLog::read(&path).unwrap().len(), 2);
+
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_transfers() -> Result<(), TransactionProcessingError> {
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let tx_service = Arc::new(TransactionService::new(
+            client_repo.clone(),
+            TransactionInMemRepository::default(),
+        ));
+
+        for client in [1, 2] {
+            tx_service
+                .process_transaction(
+                    Transaction::builder()
+                        .with_client_id(ClientID(client))
+                        .with_tx_id(TransactionID(client.into()))
+                        .with_tx_type(TransactionType::Deposit {
+                            amount: MoneyType::new(10000),
+                            dispute: DisputeState::NotDisputed,
+                        })
+                        .build(),
+                )
+                .await?;
+        }
+
+        // Transfers in both directions at once must neither deadlock nor lose any funds
+        let transfers = (0..200u32).map(|i| {
+            let (from, to) = if i % 2 == 0 { (1, 2) } else { (2, 1) };
+
+            let tx_service = tx_service.clone();
+
+            tokio::spawn(async move {
+                tx_service
+                    .process_transaction(
+                        Transaction::builder()
+                            .with_client_id(ClientID(from))
+                            .with_tx_id(TransactionID(100 + i))
+                            .with_tx_type(TransactionType::Transfer {
+                                to_client: ClientID(to),
+                                amount: MoneyType::new(10),
+                            })
+                            .build(),
+                    )
+                    .await
+            })
+        });
+
+        for transfer in futures::future::join_all(transfers).await {
+            transfer.expect("Transfer task panicked")?;
+        }
+
+        for client in [1, 2] {
+            let client = client_repo
+                .find_client_by_id(ClientID(client))
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(client.lock().await.available(), MoneyType::new(10000));
+        }
 
-        assert_eq!(client_guard.available(), 1000);
-        assert_eq!(client_guard.held(), 0);
+        assert!(matches!(
+            tx_service
+                .process_transaction(
+                    Transaction::builder()
+                        .with_client_id(ClientID(1))
+                        .with_tx_id(TransactionID(1000))
+                        .with_tx_type(TransactionType::Transfer {
+                            to_client: ClientID(1),
+                            amount: MoneyType::new(10),
+                        })
+                        .build(),
+                )
+                .await,
+            Err(TransactionProcessingError::TransferToSameClient(
+                TransactionID(1000)
+            ))
+        ));
 
         Ok(())
     }
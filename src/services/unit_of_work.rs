@@ -0,0 +1,294 @@
+use std::sync::Arc;
+
+use futures::lock::Mutex;
+
+use crate::models::client::Client;
+use crate::models::transactions::Transaction;
+use crate::repositories::clients::{StoredClient, TClientRepository};
+use crate::repositories::transactions::{StoredTX, TTransactionRepository};
+use crate::repositories::RepoError;
+
+/// The aggregates changed while processing a transaction, which are only written to the
+/// repositories once the processing is done.
+///
+/// Every aggregate is registered along with how it was before and after it was changed. They
+/// are no longer locked when they are saved, so other transactions may have changed them
+/// further by then. If any of them fails to be saved, only the changes registered in the unit
+/// are undone, keeping those made since by everyone else, and every changed aggregate is saved
+/// again. The transactions stored along the way are removed, so the transaction can be
+/// processed again rather than being rejected as a duplicate.
+#[derive(Default)]
+pub struct UnitOfWork {
+    stored: Vec<StoredTX>,
+    transactions: Vec<Dirty<Transaction>>,
+    clients: Vec<Dirty<Client>>,
+}
+
+struct Dirty<T> {
+    stored: Arc<Mutex<T>>,
+    before: T,
+    after: T,
+}
+
+impl UnitOfWork {
+    /// Register a client which was changed, as it was before and after the change
+    pub fn register_client(&mut self, stored: &StoredClient, before: Client, after: &Client) {
+        self.clients.push(Dirty {
+            stored: stored.clone(),
+            before,
+            after: after.clone(),
+        });
+    }
+
+    /// Register a stored transaction which was changed, as it was before and after the change
+    pub fn register_tx(&mut self, stored: &StoredTX, before: Transaction, after: &Transaction) {
+        self.transactions.push(Dirty {
+            stored: stored.clone(),
+            before,
+            after: after.clone(),
+        });
+    }
+
+    /// Register a transaction which was newly stored
+    pub fn register_stored_tx(&mut self, stored: &StoredTX) {
+        self.stored.push(stored.clone());
+    }
+
+    /// Save every registered aggregate, the transactions first, undoing all of the changes if
+    /// any of them fails to be saved.
+    ///
+    /// Must not be called while any of the aggregates is locked.
+    pub async fn commit<CR, TR>(
+        self,
+        client_repository: &CR,
+        transaction_repository: &TR,
+    ) -> Result<(), RepoError>
+    where
+        CR: TClientRepository,
+        TR: TTransactionRepository,
+    {
+        for dirty in &self.transactions {
+            if let Err(err) = transaction_repository.save_tx(dirty.stored.clone()).await {
+                self.rollback(client_repository, transaction_repository)
+                    .await;
+
+                return Err(err);
+            }
+        }
+
+        for dirty in &self.clients {
+            if let Err(err) = client_repository.save_client(dirty.stored.clone()).await {
+                self.rollback(client_repository, transaction_repository)
+                    .await;
+
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Undo the registered changes, in the reverse order they were made, and save the changed
+    /// aggregates again, as others might have saved them with the changes in the meantime.
+    ///
+    /// Must not be called while any of the aggregates is locked.
+    pub async fn rollback<CR, TR>(&self, client_repository: &CR, transaction_repository: &TR)
+    where
+        CR: TClientRepository,
+        TR: TTransactionRepository,
+    {
+        for dirty in self.clients.iter().rev() {
+            dirty
+                .stored
+                .lock()
+                .await
+                .revert(&dirty.before, &dirty.after);
+
+            if let Err(err) = client_repository.save_client(dirty.stored.clone()).await {
+                tracing::error!(
+                    client = %dirty.before.client_id(),
+                    error = %err,
+                    "Failed to undo the saved changes to a client"
+                );
+            }
+        }
+
+        for dirty in self.transactions.iter().rev() {
+            {
+                let mut transaction = dirty.stored.lock().await;
+
+                // The changes to a transaction build on each other, so they can only be
+                // undone as long as nothing was built on them
+                if *transaction != dirty.after {
+                    tracing::error!(
+                        tx = %dirty.before.transaction_id(),
+                        "The transaction was changed again before its changes could be undone"
+                    );
+
+                    continue;
+                }
+
+                *transaction = dirty.before.clone();
+            }
+
+            if let Err(err) = transaction_repository.save_tx(dirty.stored.clone()).await {
+                tracing::error!(
+                    tx = %dirty.before.transaction_id(),
+                    error = %err,
+                    "Failed to undo the saved changes to a transaction"
+                );
+            }
+        }
+
+        for stored_tx in self.stored.iter().rev() {
+            if let Err(err) = transaction_repository.remove_tx(stored_tx.clone()).await {
+                tracing::error!(error = %err, "Failed to remove a stored transaction");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_of_work_tests {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+
+    use crate::models::client::{Client, ClientAccountStatus};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::MockTClientRepository;
+    use crate::repositories::transactions::MockTTransactionRepository;
+    use crate::repositories::RepoError;
+    use crate::services::unit_of_work::UnitOfWork;
+
+    fn deposit() -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(1))
+            .with_tx_id(TransactionID(1))
+            .with_tx_type(TransactionType::Deposit {
+                amount: MoneyType::new(10000),
+                dispute: DisputeState::NotDisputed,
+            })
+            .build()
+    }
+
+    fn failing_save() -> Result<(), RepoError> {
+        Err(RepoError::IoError(std::io::Error::other(
+            "connection reset",
+        )))
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_is_undone() {
+        let mut cli_repo = MockTClientRepository::new();
+        let mut tx_repo = MockTTransactionRepository::new();
+
+        let client = Client::builder().with_client_id(ClientID(1)).build();
+
+        let stored_client = Arc::new(Mutex::new(client.clone()));
+        let stored_tx = Arc::new(Mutex::new(deposit()));
+
+        let mut unit_of_work = UnitOfWork::default();
+
+        let changed_version = {
+            let mut changed = stored_client.lock().await;
+
+            changed.deposit(MoneyType::new(10000)).unwrap();
+
+            unit_of_work.register_client(&stored_client, client, &changed);
+
+            changed.version()
+        };
+
+        {
+            let mut disputed = stored_tx.lock().await;
+
+            disputed
+                .dispute(
+                    Transaction::builder()
+                        .with_client_id(ClientID(1))
+                        .with_tx_id(TransactionID(1))
+                        .with_tx_type(TransactionType::Dispute)
+                        .build(),
+                )
+                .unwrap();
+
+            unit_of_work.register_tx(&stored_tx, deposit(), &disputed);
+        }
+
+        // The transaction is saved with the dispute, and then again without it
+        tx_repo
+            .expect_save_tx()
+            .times(2)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        // The client fails to be saved, and is saved again once undone
+        cli_repo
+            .expect_save_client()
+            .times(2)
+            .returning(|_| Box::pin(async { failing_save() }));
+
+        let result = unit_of_work.commit(&cli_repo, &tx_repo).await;
+
+        assert!(matches!(result, Err(RepoError::IoError(_))));
+
+        let client = stored_client.lock().await;
+
+        assert_eq!(client.available(), MoneyType::new(0));
+        assert_eq!(client.version(), changed_version + 1);
+
+        assert_eq!(*stored_tx.lock().await, deposit());
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_keeps_concurrent_changes() {
+        let mut cli_repo = MockTClientRepository::new();
+        let mut tx_repo = MockTTransactionRepository::new();
+
+        let client = Client::builder().with_client_id(ClientID(1)).build();
+
+        let stored_client = Arc::new(Mutex::new(client.clone()));
+        let stored_tx = Arc::new(Mutex::new(deposit()));
+
+        let mut unit_of_work = UnitOfWork::default();
+
+        {
+            let mut changed = stored_client.lock().await;
+
+            changed.deposit(MoneyType::new(10000)).unwrap();
+
+            unit_of_work.register_client(&stored_client, client, &changed);
+            unit_of_work.register_stored_tx(&stored_tx);
+        }
+
+        // Once unlocked, another transaction changes the client before the unit is committed
+        {
+            let mut changed = stored_client.lock().await;
+
+            changed.withdraw(MoneyType::new(2500)).unwrap();
+            changed.set_account_status(ClientAccountStatus::Frozen);
+        }
+
+        cli_repo
+            .expect_save_client()
+            .times(2)
+            .returning(|_| Box::pin(async { failing_save() }));
+
+        tx_repo
+            .expect_remove_tx()
+            .once()
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        let result = unit_of_work.commit(&cli_repo, &tx_repo).await;
+
+        assert!(matches!(result, Err(RepoError::IoError(_))));
+
+        // Only the changes of the unit are undone, the withdrawal and the freeze are kept
+        let client = stored_client.lock().await;
+
+        assert_eq!(client.available(), MoneyType::new(-2500));
+        assert_eq!(client.transaction_count(), 1);
+        assert_eq!(*client.account_status(), ClientAccountStatus::Frozen);
+    }
+}
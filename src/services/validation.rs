@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use thiserror::Error;
+
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, MoneyType};
+
+/// A rule every transaction has to follow before it is processed, such as a cap on the amounts
+/// or a list of blocked clients.
+///
+/// Validators only look at the transaction itself, they run before any client or stored
+/// transaction is loaded, so a rejected transaction never touches the state of the system.
+pub trait TTransactionValidator: Send + Sync {
+    /// Check the transaction, rejecting it with the reason it breaks the rule
+    fn validate(&self, transaction: &Transaction) -> Result<(), ValidationError>;
+}
+
+/// Any function can be used as a validator, which allows for one off rules
+impl<F> TTransactionValidator for F
+where
+    F: Fn(&Transaction) -> Result<(), ValidationError> + Send + Sync,
+{
+    fn validate(&self, transaction: &Transaction) -> Result<(), ValidationError> {
+        self(transaction)
+    }
+}
+
+/// The rejection of a transaction by a validator, carrying the validator's own error
+#[derive(Error, Debug)]
+#[error("The transaction was rejected by the {rule} rule: {reason}")]
+pub struct ValidationError {
+    rule: &'static str,
+    #[source]
+    reason: Box<dyn Error + Send + Sync>,
+}
+
+impl ValidationError {
+    pub fn new(rule: &'static str, reason: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self {
+            rule,
+            reason: reason.into(),
+        }
+    }
+
+    /// The name of the rule which rejected the transaction
+    pub fn rule(&self) -> &'static str {
+        self.rule
+    }
+
+    /// The error the validator rejected the transaction with
+    pub fn reason(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self.reason.as_ref()
+    }
+}
+
+/// The validators of a service, run in the order they were registered.
+///
+/// The first validator to reject a transaction stops the chain, so the cheaper rules
+/// should be registered first.
+#[derive(Default)]
+pub struct ValidatorChain {
+    validators: Vec<Box<dyn TTransactionValidator>>,
+}
+
+impl ValidatorChain {
+    /// Add a validator to the end of the chain
+    pub fn push(&mut self, validator: impl TTransactionValidator + 'static) {
+        self.validators.push(Box::new(validator));
+    }
+
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+}
+
+impl TTransactionValidator for ValidatorChain {
+    fn validate(&self, transaction: &Transaction) -> Result<(), ValidationError> {
+        self.validators
+            .iter()
+            .try_for_each(|validator| validator.validate(transaction))
+    }
+}
+
+/// Rejects the deposits, withdrawals and transfers moving more than the given amount
+#[derive(Debug, Clone, Copy)]
+pub struct AmountCap {
+    max: MoneyType,
+}
+
+#[derive(Error, Debug)]
+#[error("The amount {0:?} is over the cap of {1:?}")]
+pub struct AmountOverCap(pub MoneyType, pub MoneyType);
+
+impl AmountCap {
+    pub fn new(max: MoneyType) -> Self {
+        Self { max }
+    }
+}
+
+impl TTransactionValidator for AmountCap {
+    fn validate(&self, transaction: &Transaction) -> Result<(), ValidationError> {
+        match transaction.amount() {
+            Ok(amount) if amount > self.max => Err(ValidationError::new(
+                "amount-cap",
+                AmountOverCap(amount, self.max),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rejects every transaction from or to the given clients
+#[derive(Debug, Clone, Default)]
+pub struct BlockedClients {
+    clients: HashSet<ClientID>,
+}
+
+#[derive(Error, Debug)]
+#[error("The client {0} is blocked")]
+pub struct ClientBlocked(pub ClientID);
+
+impl BlockedClients {
+    pub fn new(clients: impl IntoIterator<Item = ClientID>) -> Self {
+        Self {
+            clients: clients.into_iter().collect(),
+        }
+    }
+}
+
+impl TTransactionValidator for BlockedClients {
+    fn validate(&self, transaction: &Transaction) -> Result<(), ValidationError> {
+        match std::iter::once(transaction.client())
+            .chain(transaction.destination())
+            .find(|client| self.clients.contains(client))
+        {
+            Some(client) => Err(ValidationError::new(
+                "blocked-clients",
+                ClientBlocked(client),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::services::validation::{
+        AmountCap, AmountOverCap, BlockedClients, ClientBlocked, TTransactionValidator,
+        ValidationError, ValidatorChain,
+    };
+
+    fn transfer(from: u16, to: u16, amount: i64) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(from))
+            .with_tx_id(TransactionID(1))
+            .with_tx_type(TransactionType::Transfer {
+                to_client: ClientID(to),
+                amount: MoneyType::new(amount),
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_validator_chain() {
+        let mut chain = ValidatorChain::default();
+
+        chain.push(AmountCap::new(MoneyType::new(10000)));
+        chain.push(BlockedClients::new([ClientID(3)]));
+        chain.push(|transaction: &Transaction| match transaction.client() {
+            ClientID(0) => Err(ValidationError::new(
+                "no-client-zero",
+                "client 0 is reserved",
+            )),
+            _ => Ok(()),
+        });
+
+        assert!(chain.validate(&transfer(1, 2, 10000)).is_ok());
+
+        let over_cap = chain.validate(&transfer(1, 3, 10001)).unwrap_err();
+
+        // The cap is checked first, so the blocked destination is never looked at
+        assert_eq!(over_cap.rule(), "amount-cap");
+        assert!(matches!(
+            over_cap.reason().downcast_ref::<AmountOverCap>(),
+            Some(AmountOverCap(amount, cap))
+                if (*amount, *cap) == (MoneyType::new(10001), MoneyType::new(10000))
+        ));
+
+        let blocked = chain.validate(&transfer(1, 3, 100)).unwrap_err();
+
+        assert!(matches!(
+            blocked.reason().downcast_ref::<ClientBlocked>(),
+            Some(ClientBlocked(ClientID(3)))
+        ));
+
+        let reserved = chain.validate(&transfer(0, 1, 100)).unwrap_err();
+
+        assert_eq!(reserved.rule(), "no-client-zero");
+        assert_eq!(reserved.reason().to_string(), "client 0 is reserved");
+    }
+}
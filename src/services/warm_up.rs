@@ -0,0 +1,175 @@
+use crate::repositories::clients::TClientRepository;
+use crate::repositories::transactions::TTransactionRepository;
+use crate::repositories::warm_up::TWarmUpSource;
+use crate::repositories::RepoError;
+
+/// How much data should be preloaded before opening ingestion
+#[derive(Debug, Clone, Default)]
+pub struct WarmUpConfig {
+    /// The amount of most active clients to preload
+    pub clients: usize,
+    /// The amount of recent, still disputable, transactions to preload
+    pub transactions: usize,
+}
+
+/// What was actually preloaded during the warm-up, counting the entries which were at hand in
+/// the repositories once it was done
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarmUpReport {
+    pub clients_loaded: usize,
+    pub transactions_loaded: usize,
+}
+
+/// Preload the hottest clients and the recently disputable transactions from the persistent
+/// store into the given repositories.
+///
+/// This is meant to be run before we start accepting transactions, so the first transactions
+/// don't all have to hit the persistent store at once. Entries which are already present
+/// in the repositories are left untouched. The repositories which load the entries of the
+/// persistent store on their own once they're looked up (e.g. the SQL ones) are warmed up by
+/// the lookups alone.
+///
+/// Stops at the first failure of the repositories.
+pub async fn warm_up_caches<WS, CR, TR>(
+    source: &WS,
+    client_repository: &CR,
+    transaction_repository: &TR,
+    config: &WarmUpConfig,
+) -> Result<WarmUpReport, RepoError>
+where
+    WS: TWarmUpSource,
+    CR: TClientRepository,
+    TR: TTransactionRepository,
+{
+    let mut report = WarmUpReport::default();
+
+    if config.clients > 0 {
+        for client in source.hottest_clients(config.clients).await {
+            if client_repository
+                .find_client_by_id(client.client_id())
+                .await?
+                .is_none()
+            {
+                client_repository.store_client(client).await?;
+            }
+
+            report.clients_loaded += 1;
+        }
+    }
+
+    if config.transactions > 0 {
+        for tx in source
+            .recent_disputable_transactions(config.transactions)
+            .await
+        {
+            if transaction_repository
+                .find_tx_by_id(tx.transaction_id())
+                .await?
+                .is_none()
+            {
+                transaction_repository.store_tx(tx).await?;
+            }
+
+            report.transactions_loaded += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod warm_up_tests {
+    use mockall::predicate::eq;
+
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::client::Client;
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::transactions::TTransactionRepository;
+    use crate::repositories::warm_up::MockTWarmUpSource;
+    use crate::services::warm_up::{warm_up_caches, WarmUpConfig, WarmUpReport};
+
+    #[tokio::test]
+    async fn test_warm_up_skips_cached_entries() {
+        let mut source = MockTWarmUpSource::new();
+
+        source.expect_hottest_clients().with(eq(2)).returning(|_| {
+            Box::pin(async {
+                vec![
+                    Client::builder()
+                        .with_client_id(ClientID(1))
+                        .with_available(MoneyType::new(10))
+                        .build(),
+                    Client::builder()
+                        .with_client_id(ClientID(2))
+                        .with_available(MoneyType::new(20))
+                        .build(),
+                ]
+            })
+        });
+
+        source
+            .expect_recent_disputable_transactions()
+            .with(eq(5))
+            .returning(|_| {
+                Box::pin(async {
+                    vec![Transaction::builder()
+                        .with_tx_id(TransactionID(1))
+                        .with_client_id(ClientID(1))
+                        .with_tx_type(TransactionType::Deposit {
+                            amount: MoneyType::new(10),
+                            dispute: DisputeState::NotDisputed,
+                        })
+                        .build()]
+                })
+            });
+
+        let client_repo = ClientInMemRepository::default();
+        let tx_repo = TransactionInMemRepository::default();
+
+        // Client 2 is already cached, so it must not be replaced
+        client_repo
+            .store_client(
+                Client::builder()
+                    .with_client_id(ClientID(2))
+                    .with_available(MoneyType::new(50))
+                    .build(),
+            )
+            .await
+            .unwrap();
+
+        let report = warm_up_caches(
+            &source,
+            &client_repo,
+            &tx_repo,
+            &WarmUpConfig {
+                clients: 2,
+                transactions: 5,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            report,
+            WarmUpReport {
+                clients_loaded: 2,
+                transactions_loaded: 1,
+            }
+        );
+
+        let client = client_repo
+            .find_client_by_id(ClientID(2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(client.lock().await.available(), MoneyType::new(50));
+
+        assert!(tx_repo
+            .find_tx_by_id(TransactionID(1))
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use thiserror::Error;
+
+use crate::models::money::parse_amount;
+use crate::models::ClientID;
+
+/// The columns of each row of the differences, see [`write_differences`]
+const DIFFERENCE_HEADER: [&str; 5] = ["client", "difference", "column", "expected", "actual"];
+
+/// A difference between the expected export of the state and the actual one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateDifference {
+    /// The client is expected, but is not part of the actual state
+    MissingClient(ClientID),
+    /// The client is part of the actual state, but is not expected
+    UnexpectedClient(ClientID),
+    /// A column of the client doesn't have the expected value
+    Mismatch {
+        client: ClientID,
+        column: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl StateDifference {
+    pub fn client(&self) -> ClientID {
+        match self {
+            StateDifference::MissingClient(client)
+            | StateDifference::UnexpectedClient(client)
+            | StateDifference::Mismatch { client, .. } => *client,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum StateComparisonError {
+    #[error("Failed to read the state {0}")]
+    CsvError(#[from] csv::Error),
+    #[error("The state has no `client` column")]
+    MissingClientColumn,
+    #[error("The expected column {0:?} is not part of the actual state")]
+    UnknownColumn(String),
+    #[error("The state has an invalid client id {0:?}")]
+    InvalidClient(String),
+    #[error("The client {0} is in the state more than once")]
+    DuplicateClient(ClientID),
+}
+
+/// The rows of an exported state, by client
+struct ExportedState {
+    columns: Vec<String>,
+    rows: BTreeMap<ClientID, Vec<String>>,
+}
+
+impl ExportedState {
+    fn read(state: impl Read) -> Result<Self, StateComparisonError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(state);
+
+        let columns: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+
+        let client_column = columns
+            .iter()
+            .position(|column| column == "client")
+            .ok_or(StateComparisonError::MissingClientColumn)?;
+
+        let mut rows = BTreeMap::new();
+
+        for record in reader.records() {
+            let record = record?;
+
+            let client = record
+                .get(client_column)
+                .and_then(|client| client.parse().ok())
+                .map(ClientID)
+                .ok_or_else(|| {
+                    StateComparisonError::InvalidClient(
+                        record.get(client_column).unwrap_or_default().to_string(),
+                    )
+                })?;
+
+            let values = record.iter().map(str::to_string).collect();
+
+            if rows.insert(client, values).is_some() {
+                return Err(StateComparisonError::DuplicateClient(client));
+            }
+        }
+
+        Ok(Self { columns, rows })
+    }
+
+    fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column == name)
+    }
+}
+
+/// Whether the values are the same, comparing the amounts by their value so `1.5` is the
+/// same as `1.5000`
+fn same_value(expected: &str, actual: &str) -> bool {
+    if expected == actual {
+        return true;
+    }
+
+    let precision = [expected, actual]
+        .into_iter()
+        .filter_map(|value| value.split_once('.'))
+        .map(|(_, fraction)| fraction.len() as u32)
+        .max()
+        .unwrap_or(0);
+
+    match (
+        parse_amount(expected, precision),
+        parse_amount(actual, precision),
+    ) {
+        (Ok(expected), Ok(actual)) => expected == actual,
+        _ => false,
+    }
+}
+
+/// Compare an expected export of the state with the actual one, both CSV with a header.
+///
+/// Only the columns of the expected state are compared, so it can be exported with any of
+/// the schemas, as long as the actual state has every one of its columns. The rows can be in
+/// any order, and the differences are returned ordered by client.
+pub fn compare_states(
+    expected: impl Read,
+    actual: impl Read,
+) -> Result<Vec<StateDifference>, StateComparisonError> {
+    let (expected, actual) = (ExportedState::read(expected)?, ExportedState::read(actual)?);
+
+    let columns = expected
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| *column != "client")
+        .map(|(index, column)| match actual.column(column) {
+            Some(actual_index) => Ok((column, index, actual_index)),
+            None => Err(StateComparisonError::UnknownColumn(column.clone())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut differences = Vec::new();
+
+    for (client, expected_row) in &expected.rows {
+        let Some(actual_row) = actual.rows.get(client) else {
+            differences.push(StateDifference::MissingClient(*client));
+            continue;
+        };
+
+        for (column, expected_index, actual_index) in &columns {
+            let expected = expected_row.get(*expected_index).map_or("", String::as_str);
+            let actual = actual_row.get(*actual_index).map_or("", String::as_str);
+
+            if !same_value(expected, actual) {
+                differences.push(StateDifference::Mismatch {
+                    client: *client,
+                    column: column.to_string(),
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+    }
+
+    differences.extend(
+        actual
+            .rows
+            .keys()
+            .filter(|client| !expected.rows.contains_key(client))
+            .map(|client| StateDifference::UnexpectedClient(*client)),
+    );
+
+    differences.sort_by_key(StateDifference::client);
+
+    Ok(differences)
+}
+
+/// Write the differences as CSV, with a row for each of them. The missing and unexpected
+/// clients have no column nor values.
+pub fn write_differences(
+    writer: impl Write,
+    differences: &[StateDifference],
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+
+    writer.write_record(DIFFERENCE_HEADER)?;
+
+    for difference in differences {
+        let client = difference.client().to_string();
+
+        match difference {
+            StateDifference::MissingClient(_) => {
+                writer.write_record([client.as_str(), "missing", "", "", ""])?
+            }
+            StateDifference::UnexpectedClient(_) => {
+                writer.write_record([client.as_str(), "unexpected", "", "", ""])?
+            }
+            StateDifference::Mismatch {
+                column,
+                expected,
+                actual,
+                ..
+            } => writer.write_record([client.as_str(), "mismatch", column, expected, actual])?,
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod comparison_tests {
+    use crate::models::ClientID;
+    use crate::state_exporter::comparison::{
+        compare_states, write_differences, StateComparisonError, StateDifference,
+    };
+
+    const ACTUAL: &str = "client,available,held,total,locked,open_disputes\n\
+                          1,1.5000,0.0000,1.5000,false,0\n\
+                          2,3.0000,1.0000,4.0000,true,1\n\
+                          4,1.0000,0.0000,1.0000,false,0\n";
+
+    #[test]
+    fn test_compare_states() {
+        // In another order, with fewer columns and decimal places
+        let expected = "client, available, held, total, locked\n\
+                        2,3,2,5,true\n\
+                        3,1,0,1,false\n\
+                        1,1.5,0,1.5,false\n";
+
+        let differences = compare_states(expected.as_bytes(), ACTUAL.as_bytes()).unwrap();
+
+        assert_eq!(
+            differences,
+            vec![
+                StateDifference::Mismatch {
+                    client: ClientID(2),
+                    column: "held".to_string(),
+                    expected: "2".to_string(),
+                    actual: "1.0000".to_string(),
+                },
+                StateDifference::Mismatch {
+                    client: ClientID(2),
+                    column: "total".to_string(),
+                    expected: "5".to_string(),
+                    actual: "4.0000".to_string(),
+                },
+                StateDifference::MissingClient(ClientID(3)),
+                StateDifference::UnexpectedClient(ClientID(4)),
+            ]
+        );
+
+        let mut diff = Vec::new();
+
+        write_differences(&mut diff, &differences[1..]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(diff).unwrap(),
+            "client,difference,column,expected,actual\n\
+             2,mismatch,total,5,4.0000\n\
+             3,missing,,,\n\
+             4,unexpected,,,\n"
+        );
+
+        assert!(compare_states(ACTUAL.as_bytes(), ACTUAL.as_bytes())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        assert!(matches!(
+            compare_states("client,closed\n1,false\n".as_bytes(), ACTUAL.as_bytes()),
+            Err(StateComparisonError::UnknownColumn(column)) if column == "closed"
+        ));
+        assert!(matches!(
+            compare_states("available\n1.0\n".as_bytes(), ACTUAL.as_bytes()),
+            Err(StateComparisonError::MissingClientColumn)
+        ));
+        assert!(matches!(
+            compare_states("client\n1\n1\n".as_bytes(), ACTUAL.as_bytes()),
+            Err(StateComparisonError::DuplicateClient(ClientID(1)))
+        ));
+    }
+}
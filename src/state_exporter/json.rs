@@ -0,0 +1,251 @@
+use std::path::Path;
+
+use futures::lock::Mutex;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::models::ClientID;
+use crate::repositories::clients::StoredClient;
+use crate::state_exporter::{ClientRow, StateExporterError, TClientStateExporter};
+use crate::FLOATING_POINT_ACC;
+
+/// How the client records are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JsonLayout {
+    /// A single JSON array with a record per client
+    #[default]
+    Array,
+    /// A JSON record per line (NDJSON), so the output can be read one client at a time
+    Lines,
+}
+
+/// A client record of the export. The amounts are decimal strings, as in the input, so they
+/// are never rounded by the readers.
+#[derive(Serialize)]
+struct JsonClientRecord<'a> {
+    client: ClientID,
+    available: &'a str,
+    held: &'a str,
+    total: &'a str,
+    locked: bool,
+}
+
+impl<'a> From<&'a ClientRow> for JsonClientRecord<'a> {
+    fn from(row: &'a ClientRow) -> Self {
+        Self {
+            client: row.client,
+            available: &row.available,
+            held: &row.held,
+            total: &row.total,
+            locked: row.locked,
+        }
+    }
+}
+
+/// JSON exporter for the client state, with the columns of the V1 CSV export.
+///
+/// Like the [`CsvStateExporter`](crate::state_exporter::CsvStateExporter), the records are
+/// written as the clients are streamed, unless they have to be sorted first.
+pub struct JsonStateExporter<W> {
+    sink: Mutex<W>,
+    layout: JsonLayout,
+    sort_by_client: bool,
+    precision: u32,
+}
+
+impl JsonStateExporter<tokio::io::Stdout> {
+    /// Export the state to the standard output
+    pub fn stdout(layout: JsonLayout) -> Self {
+        Self::new(tokio::io::stdout(), layout)
+    }
+}
+
+impl JsonStateExporter<tokio::fs::File> {
+    /// Export the state to the file at the given path, creating (or truncating) it
+    pub async fn to_file(
+        path: impl AsRef<Path>,
+        layout: JsonLayout,
+    ) -> Result<Self, StateExporterError> {
+        Ok(Self::new(tokio::fs::File::create(path).await?, layout))
+    }
+}
+
+impl<W> JsonStateExporter<W> {
+    pub fn new(sink: W, layout: JsonLayout) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+            layout,
+            sort_by_client: false,
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    /// Sort the exported records by client id.
+    ///
+    /// This requires collecting all of the client records before writing them.
+    pub fn with_sorted_output(mut self, sort_by_client: bool) -> Self {
+        self.sort_by_client = sort_by_client;
+
+        self
+    }
+
+    /// The amount of decimal places the amounts are stored with, which defaults to
+    /// [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.sink.into_inner()
+    }
+
+    /// Encode the record, along with whatever separates it from the previous one
+    fn encode(&self, row: &ClientRow, first: bool) -> Result<Vec<u8>, StateExporterError> {
+        let mut encoded = match (self.layout, first) {
+            (JsonLayout::Array, false) => b",\n".to_vec(),
+            _ => Vec::new(),
+        };
+
+        serde_json::to_writer(&mut encoded, &JsonClientRecord::from(row))
+            .map_err(|err| StateExporterError::IoError(err.into()))?;
+
+        if self.layout == JsonLayout::Lines {
+            encoded.push(b'\n');
+        }
+
+        Ok(encoded)
+    }
+}
+
+impl<W> TClientStateExporter for JsonStateExporter<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    type Error = StateExporterError;
+
+    async fn export_state(
+        &self,
+        state: impl Stream<Item = StoredClient>,
+    ) -> Result<(), StateExporterError> {
+        let mut sink = self.sink.lock().await;
+
+        let precision = self.precision;
+
+        if self.layout == JsonLayout::Array {
+            sink.write_all(b"[\n").await?;
+        }
+
+        let rows =
+            state.then(|client| async move { ClientRow::new(&*client.lock().await, precision) });
+
+        if self.sort_by_client {
+            let mut rows = rows.collect::<Vec<_>>().await;
+
+            rows.sort_by_key(|row| row.client);
+
+            for (index, row) in rows.iter().enumerate() {
+                sink.write_all(&self.encode(row, index == 0)?).await?;
+            }
+        } else {
+            let mut rows = std::pin::pin!(rows.enumerate());
+
+            while let Some((index, row)) = rows.next().await {
+                sink.write_all(&self.encode(&row, index == 0)?).await?;
+            }
+        }
+
+        if self.layout == JsonLayout::Array {
+            sink.write_all(b"\n]\n").await?;
+        }
+
+        sink.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod json_exporter_tests {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+    use futures::stream;
+
+    use crate::models::client::{Client, ClientAccountStatus};
+    use crate::models::{ClientID, MoneyType};
+    use crate::state_exporter::json::{JsonLayout, JsonStateExporter};
+    use crate::state_exporter::TClientStateExporter;
+
+    fn clients() -> Vec<Client> {
+        vec![
+            Client::builder()
+                .with_client_id(ClientID(2))
+                .with_available(MoneyType::new(15000))
+                .with_held(MoneyType::new(5000))
+                .with_account_status(ClientAccountStatus::Frozen)
+                .build(),
+            Client::builder()
+                .with_client_id(ClientID(1))
+                .with_available(MoneyType::new(10000))
+                .build(),
+        ]
+    }
+
+    async fn export(layout: JsonLayout) -> String {
+        let exporter = JsonStateExporter::new(Vec::new(), layout).with_sorted_output(true);
+
+        exporter
+            .export_state(stream::iter(
+                clients()
+                    .into_iter()
+                    .map(|client| Arc::new(Mutex::new(client))),
+            ))
+            .await
+            .unwrap();
+
+        String::from_utf8(exporter.into_inner()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_export() {
+        let output = export(JsonLayout::Array).await;
+
+        assert_eq!(
+            output,
+            "[\n\
+             {\"client\":1,\"available\":\"1.0000\",\"held\":\"0.0000\",\"total\":\"1.0000\",\"locked\":false},\n\
+             {\"client\":2,\"available\":\"1.5000\",\"held\":\"0.5000\",\"total\":\"2.0000\",\"locked\":true}\n\
+             ]\n"
+        );
+
+        let records: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_export() {
+        assert_eq!(
+            export(JsonLayout::Lines).await,
+            "{\"client\":1,\"available\":\"1.0000\",\"held\":\"0.0000\",\"total\":\"1.0000\",\"locked\":false}\n\
+             {\"client\":2,\"available\":\"1.5000\",\"held\":\"0.5000\",\"total\":\"2.0000\",\"locked\":true}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_json_export() {
+        let exporter = JsonStateExporter::new(Vec::new(), JsonLayout::Array);
+
+        exporter.export_state(stream::empty()).await.unwrap();
+
+        let output = String::from_utf8(exporter.into_inner()).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Vec<serde_json::Value>>(&output).unwrap(),
+            Vec::<serde_json::Value>::new()
+        );
+    }
+}
@@ -1,15 +1,32 @@
 use std::error::Error;
+use std::path::Path;
 
+use futures::lock::Mutex;
 use futures::{Stream, StreamExt};
 use thiserror::Error;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-use crate::models::client::ClientAccountStatus;
+use crate::models::client::{Client, ClientAccountStatus};
+use crate::models::money::{format_amount, MAX_PRECISION};
+use crate::models::ClientID;
 use crate::repositories::clients::StoredClient;
+use crate::repositories::RepoError;
 use crate::FLOATING_POINT_ACC;
 
+pub mod comparison;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod snapshots;
+pub mod statements;
+
 /// The state exporter, meant for the last part of the assignment,
 /// where we have to print out the state of the clients after all
 /// the transactions have been processed.
+#[allow(async_fn_in_trait)]
 pub trait TClientStateExporter {
     type Error: Error + Send + Sync;
 
@@ -19,50 +36,417 @@ pub trait TClientStateExporter {
     ) -> Result<(), Self::Error>;
 }
 
-pub struct ClientExporter;
+const CSV_HEADER: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+const DISPUTE_COLUMNS: [&str; 3] = [
+    "open_disputes",
+    "held_deposit_disputes",
+    "held_withdrawal_disputes",
+];
+
+const CLOSED_COLUMN: &str = "closed";
+
+const CREDIT_LIMIT_COLUMN: &str = "credit_limit";
+
+/// The columns included in the export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportSchema {
+    /// The client's balances and whether the account is locked
+    #[default]
+    V1,
+    /// The V1 columns, followed by the number of open disputes and the breakdown of the
+    /// held funds by the kind of transaction being disputed
+    V2,
+    /// The V2 columns, followed by whether the account is closed (closed accounts are
+    /// also locked)
+    V3,
+    /// The V3 columns, followed by the credit limit of the account (empty for accounts without
+    /// a credit line, whose available funds can't go below zero)
+    V4,
+}
+
+/// CSV exporter for the client state.
+///
+/// Uses a [`csv::Writer`] to produce the rows (so we get correct quoting and escaping) and
+/// writes them, row by row, to any [`AsyncWrite`] sink (stdout, a file, a socket, etc.).
+pub struct CsvStateExporter<W> {
+    sink: Mutex<W>,
+    sort_by_client: bool,
+    schema: ExportSchema,
+    precision: u32,
+}
+
+impl CsvStateExporter<tokio::io::Stdout> {
+    /// Export the state to the standard output
+    pub fn stdout() -> Self {
+        Self::new(tokio::io::stdout())
+    }
+}
+
+impl CsvStateExporter<tokio::fs::File> {
+    /// Export the state to the file at the given path, creating (or truncating) it
+    pub async fn to_file(path: impl AsRef<Path>) -> Result<Self, StateExporterError> {
+        Ok(Self::new(tokio::fs::File::create(path).await?))
+    }
+}
+
+impl<W> CsvStateExporter<W> {
+    pub fn new(sink: W) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+            sort_by_client: false,
+            schema: ExportSchema::default(),
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    /// Sort the exported rows by client id.
+    ///
+    /// This requires collecting all of the client rows before writing them.
+    pub fn with_sorted_output(mut self, sort_by_client: bool) -> Self {
+        self.sort_by_client = sort_by_client;
+
+        self
+    }
+
+    /// Choose the columns included in the export
+    pub fn with_schema(mut self, schema: ExportSchema) -> Self {
+        self.schema = schema;
+
+        self
+    }
 
-impl TClientStateExporter for ClientExporter {
+    /// The amount of decimal places the amounts are stored with, which defaults to
+    /// [`FLOATING_POINT_ACC`].
+    ///
+    /// Fails past [`MAX_PRECISION`], which is all the amounts can be read with.
+    pub fn with_precision(mut self, precision: u32) -> Result<Self, StateExporterError> {
+        if precision > MAX_PRECISION {
+            return Err(StateExporterError::InvalidPrecision(precision));
+        }
+
+        self.precision = precision;
+
+        Ok(self)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.sink.into_inner()
+    }
+}
+
+impl<W> TClientStateExporter for CsvStateExporter<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
     type Error = StateExporterError;
 
     async fn export_state(
         &self,
         state: impl Stream<Item = StoredClient>,
     ) -> Result<(), StateExporterError> {
-        println!("client, available, held, total, locked");
-
-        state
-            .for_each(|client| async move {
-                let client_guard = client.lock().await;
-
-                let formatted_available =
-                    (client_guard.available() as f64) / 10.0f64.powi(FLOATING_POINT_ACC);
-                let formatted_held =
-                    (client_guard.held() as f64) / 10.0f64.powi(FLOATING_POINT_ACC);
-                let formatted_total =
-                    (client_guard.total() as f64) / 10.0f64.powi(FLOATING_POINT_ACC);
-
-                let locked = match client_guard.account_status() {
-                    ClientAccountStatus::Active => false,
-                    ClientAccountStatus::Frozen => true,
-                };
-
-                println!(
-                    "{}, {}, {}, {}, {}",
-                    client_guard.client_id(),
-                    formatted_available,
-                    formatted_held,
-                    formatted_total,
-                    locked
-                );
-            })
-            .await;
+        let mut sink = self.sink.lock().await;
+
+        let (schema, precision) = (self.schema, self.precision);
+
+        let header = match schema {
+            ExportSchema::V1 => encode_record(CSV_HEADER)?,
+            ExportSchema::V2 => encode_record(CSV_HEADER.iter().chain(DISPUTE_COLUMNS.iter()))?,
+            ExportSchema::V3 => encode_record(
+                CSV_HEADER
+                    .iter()
+                    .chain(DISPUTE_COLUMNS.iter())
+                    .chain([&CLOSED_COLUMN]),
+            )?,
+            ExportSchema::V4 => encode_record(
+                CSV_HEADER
+                    .iter()
+                    .chain(DISPUTE_COLUMNS.iter())
+                    .chain([&CLOSED_COLUMN, &CREDIT_LIMIT_COLUMN]),
+            )?,
+        };
+
+        sink.write_all(&header).await?;
+
+        let rows =
+            state.then(|client| async move { ClientRow::new(&*client.lock().await, precision) });
+
+        if self.sort_by_client {
+            let mut rows = rows.collect::<Vec<_>>().await;
+
+            rows.sort_by_key(|row| row.client);
+
+            for row in rows {
+                sink.write_all(&row.encode(schema)?).await?;
+            }
+        } else {
+            let mut rows = std::pin::pin!(rows);
+
+            while let Some(row) = rows.next().await {
+                sink.write_all(&row.encode(schema)?).await?;
+            }
+        }
+
+        sink.flush().await?;
 
         Ok(())
     }
 }
 
+/// A single client row of the export, already formatted
+struct ClientRow {
+    client: ClientID,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    closed: bool,
+    open_disputes: String,
+    held_for_deposits: String,
+    held_for_withdrawals: String,
+    credit_limit: String,
+}
+
+impl ClientRow {
+    fn encode(&self, schema: ExportSchema) -> Result<Vec<u8>, StateExporterError> {
+        let client = self.client.to_string();
+
+        let v1 = [
+            client.as_str(),
+            &self.available,
+            &self.held,
+            &self.total,
+            if self.locked { "true" } else { "false" },
+        ];
+
+        let disputes = [
+            self.open_disputes.as_str(),
+            &self.held_for_deposits,
+            &self.held_for_withdrawals,
+        ];
+
+        let closed = if self.closed { "true" } else { "false" };
+
+        match schema {
+            ExportSchema::V1 => encode_record(v1),
+            ExportSchema::V2 => encode_record(v1.into_iter().chain(disputes)),
+            ExportSchema::V3 => encode_record(v1.into_iter().chain(disputes).chain([closed])),
+            ExportSchema::V4 => encode_record(
+                v1.into_iter()
+                    .chain(disputes)
+                    .chain([closed, &self.credit_limit]),
+            ),
+        }
+    }
+}
+
+impl ClientRow {
+    fn new(client: &Client, precision: u32) -> Self {
+        Self {
+            client: client.client_id(),
+            available: format_amount(client.available(), precision),
+            held: format_amount(client.held(), precision),
+            total: format_amount(client.total(), precision),
+            locked: match client.account_status() {
+                ClientAccountStatus::Active => false,
+                ClientAccountStatus::Frozen | ClientAccountStatus::Closed => true,
+            },
+            closed: *client.account_status() == ClientAccountStatus::Closed,
+            open_disputes: client.disputes().open_disputes().to_string(),
+            held_for_deposits: format_amount(client.disputes().held_for_deposits(), precision),
+            held_for_withdrawals: format_amount(
+                client.disputes().held_for_withdrawals(),
+                precision,
+            ),
+            credit_limit: client
+                .credit_limit()
+                .map(|limit| format_amount(limit, precision))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Encode a single record as a CSV line
+fn encode_record<I, T>(record: I) -> Result<Vec<u8>, StateExporterError>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+
+    csv_writer.write_record(record)?;
+
+    csv_writer
+        .into_inner()
+        .map_err(|err| StateExporterError::IoError(err.into_error()))
+}
+
 #[derive(Error, Debug)]
 pub enum StateExporterError {
-    // We don't really have any errors here, but we might as well
-    // have this here for future use.
+    #[error("Failed to write the CSV output {0:?}")]
+    CsvError(#[from] csv::Error),
+    #[error("Failed to write to the output sink {0:?}")]
+    IoError(#[from] std::io::Error),
+    #[error("Failed to read the state to export {0}")]
+    RepositoryError(#[from] RepoError),
+    #[error("Amounts can't be exported with {0} decimal places, at most {MAX_PRECISION}")]
+    InvalidPrecision(u32),
+    #[cfg(feature = "parquet")]
+    #[error("Failed to build the Parquet columns {0}")]
+    ArrowError(#[from] arrow::error::ArrowError),
+    #[cfg(feature = "parquet")]
+    #[error("Failed to write the Parquet output {0}")]
+    ParquetError(#[from] ::parquet::errors::ParquetError),
+    #[cfg(feature = "s3")]
+    #[error("Failed to upload the output {0}")]
+    S3Error(#[from] crate::tx_reception::s3::S3Error),
+}
+
+#[cfg(test)]
+mod exporter_tests {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+    use futures::stream;
+
+    use crate::models::client::{Client, ClientAccountStatus};
+    use crate::models::{ClientID, MoneyType};
+    use crate::state_exporter::{
+        CsvStateExporter, ExportSchema, StateExporterError, TClientStateExporter,
+    };
+
+    #[tokio::test]
+    async fn test_sorted_csv_export() {
+        let clients = vec![
+            Client::builder()
+                .with_client_id(ClientID(2))
+                .with_available(MoneyType::new(15000))
+                .build(),
+            Client::builder()
+                .with_client_id(ClientID(1))
+                .with_available(MoneyType::new(-5))
+                .with_held(MoneyType::new(12345))
+                .with_account_status(ClientAccountStatus::Frozen)
+                .build(),
+        ];
+
+        let exporter = CsvStateExporter::new(Vec::new()).with_sorted_output(true);
+
+        exporter
+            .export_state(stream::iter(
+                clients
+                    .into_iter()
+                    .map(|client| Arc::new(Mutex::new(client))),
+            ))
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(exporter.into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked\n\
+             1,-0.0005,1.2345,1.2340,true\n\
+             2,1.5000,0.0000,1.5000,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csv_export_precision() {
+        let client = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_available(MoneyType::new(-5))
+            .build();
+
+        let exporter = CsvStateExporter::new(Vec::new()).with_precision(2).unwrap();
+
+        exporter
+            .export_state(stream::iter([Arc::new(Mutex::new(client))]))
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(exporter.into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked\n\
+             1,-0.05,0.00,-0.05,false\n"
+        );
+
+        // More decimal places than the amounts can be read with are refused
+        assert!(matches!(
+            CsvStateExporter::new(Vec::<u8>::new()).with_precision(20),
+            Err(StateExporterError::InvalidPrecision(20))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_v2_csv_export() {
+        let mut client = Client::builder().with_client_id(ClientID(1)).build();
+
+        client.deposit(MoneyType::new(30000)).unwrap();
+        client.withdraw(MoneyType::new(10000)).unwrap();
+        client
+            .dispute_deposited_funds(MoneyType::new(30000))
+            .unwrap();
+        client
+            .dispute_withdrawn_funds(MoneyType::new(10000))
+            .unwrap();
+
+        let exporter = CsvStateExporter::new(Vec::new()).with_schema(ExportSchema::V2);
+
+        exporter
+            .export_state(stream::iter([Arc::new(Mutex::new(client))]))
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(exporter.into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,open_disputes,held_deposit_disputes,held_withdrawal_disputes\n\
+             1,-1.0000,4.0000,3.0000,false,2,3.0000,1.0000\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_v4_csv_export() {
+        let mut overdrawn = Client::builder()
+            .with_client_id(ClientID(1))
+            .with_credit_limit(MoneyType::new(50000))
+            .build();
+
+        overdrawn.deposit(MoneyType::new(10000)).unwrap();
+        overdrawn.withdraw(MoneyType::new(25000)).unwrap();
+
+        let clients = vec![
+            overdrawn,
+            Client::builder()
+                .with_client_id(ClientID(2))
+                .with_available(MoneyType::new(10000))
+                .build(),
+        ];
+
+        let exporter = CsvStateExporter::new(Vec::new())
+            .with_sorted_output(true)
+            .with_schema(ExportSchema::V4);
+
+        exporter
+            .export_state(stream::iter(
+                clients
+                    .into_iter()
+                    .map(|client| Arc::new(Mutex::new(client))),
+            ))
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(exporter.into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,open_disputes,held_deposit_disputes,held_withdrawal_disputes,closed,credit_limit\n\
+             1,-1.5000,0.0000,-1.5000,false,0,0.0000,0.0000,false,5.0000\n\
+             2,1.0000,0.0000,1.0000,false,0,0.0000,0.0000,false,\n"
+        );
+    }
 }
@@ -0,0 +1,501 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::Compression;
+use ::parquet::file::properties::WriterProperties;
+use arrow::array::{
+    ArrayRef, BooleanBuilder, Decimal128Builder, Int64Builder, StringBuilder, UInt16Builder,
+    UInt32Builder, UInt64Builder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use futures::lock::Mutex;
+use futures::{Stream, StreamExt};
+
+use crate::models::client::{Client, ClientAccountStatus};
+use crate::models::transactions::Transaction;
+use crate::repositories::clients::{StoredClient, TClientRepository};
+use crate::repositories::transactions::TTransactionRepository;
+use crate::state_exporter::{StateExporterError, TClientStateExporter};
+use crate::FLOATING_POINT_ACC;
+
+/// How many rows are buffered before they are written as a row group
+const BATCH_SIZE: usize = 8192;
+
+/// The amounts are stored in `i64`s, which have at most 19 digits
+const AMOUNT_DIGITS: u8 = 19;
+
+fn amount_type(precision: u32) -> DataType {
+    DataType::Decimal128(AMOUNT_DIGITS, precision as i8)
+}
+
+fn amount_builder(precision: u32) -> Result<Decimal128Builder, StateExporterError> {
+    Ok(Decimal128Builder::new().with_precision_and_scale(AMOUNT_DIGITS, precision as i8)?)
+}
+
+fn writer_properties() -> WriterProperties {
+    WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build()
+}
+
+/// The columns of the exported state, the same as the V4 CSV export
+fn state_schema(precision: u32) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", amount_type(precision), false),
+        Field::new("held", amount_type(precision), false),
+        Field::new("total", amount_type(precision), false),
+        Field::new("locked", DataType::Boolean, false),
+        Field::new("open_disputes", DataType::Int64, false),
+        Field::new("held_deposit_disputes", amount_type(precision), false),
+        Field::new("held_withdrawal_disputes", amount_type(precision), false),
+        Field::new("closed", DataType::Boolean, false),
+        Field::new("credit_limit", amount_type(precision), true),
+    ]))
+}
+
+/// The rows of the state which are yet to be written
+struct StateColumns {
+    client: UInt16Builder,
+    available: Decimal128Builder,
+    held: Decimal128Builder,
+    total: Decimal128Builder,
+    locked: BooleanBuilder,
+    open_disputes: Int64Builder,
+    held_for_deposits: Decimal128Builder,
+    held_for_withdrawals: Decimal128Builder,
+    closed: BooleanBuilder,
+    credit_limit: Decimal128Builder,
+    rows: usize,
+}
+
+impl StateColumns {
+    fn new(precision: u32) -> Result<Self, StateExporterError> {
+        Ok(Self {
+            client: UInt16Builder::new(),
+            available: amount_builder(precision)?,
+            held: amount_builder(precision)?,
+            total: amount_builder(precision)?,
+            locked: BooleanBuilder::new(),
+            open_disputes: Int64Builder::new(),
+            held_for_deposits: amount_builder(precision)?,
+            held_for_withdrawals: amount_builder(precision)?,
+            closed: BooleanBuilder::new(),
+            credit_limit: amount_builder(precision)?,
+            rows: 0,
+        })
+    }
+
+    fn push(&mut self, client: &Client) {
+        let status = *client.account_status();
+
+        self.client.append_value(client.client_id().0);
+        self.available.append_value(client.available().raw().into());
+        self.held.append_value(client.held().raw().into());
+        self.total.append_value(client.total().raw().into());
+        self.locked
+            .append_value(status != ClientAccountStatus::Active);
+        self.open_disputes
+            .append_value(client.disputes().open_disputes());
+        self.held_for_deposits
+            .append_value(client.disputes().held_for_deposits().raw().into());
+        self.held_for_withdrawals
+            .append_value(client.disputes().held_for_withdrawals().raw().into());
+        self.closed
+            .append_value(status == ClientAccountStatus::Closed);
+        self.credit_limit
+            .append_option(client.credit_limit().map(|limit| limit.raw().into()));
+
+        self.rows += 1;
+    }
+
+    /// Take the buffered rows, as a batch of the given schema
+    fn finish(&mut self, schema: SchemaRef) -> Result<RecordBatch, StateExporterError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.client.finish()),
+            Arc::new(self.available.finish()),
+            Arc::new(self.held.finish()),
+            Arc::new(self.total.finish()),
+            Arc::new(self.locked.finish()),
+            Arc::new(self.open_disputes.finish()),
+            Arc::new(self.held_for_deposits.finish()),
+            Arc::new(self.held_for_withdrawals.finish()),
+            Arc::new(self.closed.finish()),
+            Arc::new(self.credit_limit.finish()),
+        ];
+
+        self.rows = 0;
+
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+/// Parquet exporter for the client state, with the columns of the V4 CSV export.
+///
+/// The amounts are decimals with as many decimal places as they are stored with, so they are
+/// never rounded. The rows are written in row groups of up to 8192 clients as they
+/// are streamed, so the whole state is never held at once.
+pub struct ParquetStateExporter<W> {
+    sink: Mutex<W>,
+    precision: u32,
+}
+
+impl<W> ParquetStateExporter<W> {
+    pub fn new(sink: W) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    /// The amount of decimal places the amounts are stored with, which defaults to
+    /// [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.sink.into_inner()
+    }
+}
+
+impl<W> TClientStateExporter for ParquetStateExporter<W>
+where
+    W: Write + Send,
+{
+    type Error = StateExporterError;
+
+    async fn export_state(
+        &self,
+        state: impl Stream<Item = StoredClient>,
+    ) -> Result<(), StateExporterError> {
+        let mut sink = self.sink.lock().await;
+
+        let schema = state_schema(self.precision);
+
+        let mut writer =
+            ArrowWriter::try_new(&mut *sink, schema.clone(), Some(writer_properties()))?;
+
+        let mut columns = StateColumns::new(self.precision)?;
+
+        let mut state = std::pin::pin!(state);
+
+        while let Some(client) = state.next().await {
+            columns.push(&*client.lock().await);
+
+            if columns.rows == BATCH_SIZE {
+                writer.write(&columns.finish(schema.clone())?)?;
+            }
+        }
+
+        if columns.rows > 0 {
+            writer.write(&columns.finish(schema)?)?;
+        }
+
+        writer.close()?;
+
+        Ok(())
+    }
+}
+
+/// The columns of the exported transaction log
+fn transaction_schema(precision: u32) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("tx", DataType::UInt32, false),
+        Field::new("client", DataType::UInt16, false),
+        Field::new("type", DataType::Utf8, false),
+        Field::new("amount", amount_type(precision), true),
+        Field::new("to", DataType::UInt16, true),
+        Field::new("dispute", DataType::Utf8, false),
+        Field::new("client_sequence", DataType::UInt64, true),
+        Field::new("timestamp", DataType::UInt64, true),
+    ]))
+}
+
+/// The rows of the transaction log which are yet to be written
+struct TransactionColumns {
+    tx: UInt32Builder,
+    client: UInt16Builder,
+    tx_type: StringBuilder,
+    amount: Decimal128Builder,
+    to: UInt16Builder,
+    dispute: StringBuilder,
+    client_sequence: UInt64Builder,
+    timestamp: UInt64Builder,
+    rows: usize,
+}
+
+impl TransactionColumns {
+    fn new(precision: u32) -> Result<Self, StateExporterError> {
+        Ok(Self {
+            tx: UInt32Builder::new(),
+            client: UInt16Builder::new(),
+            tx_type: StringBuilder::new(),
+            amount: amount_builder(precision)?,
+            to: UInt16Builder::new(),
+            dispute: StringBuilder::new(),
+            client_sequence: UInt64Builder::new(),
+            timestamp: UInt64Builder::new(),
+            rows: 0,
+        })
+    }
+
+    fn push(&mut self, transaction: &Transaction) {
+        self.tx.append_value(transaction.transaction_id().0);
+        self.client.append_value(transaction.client().0);
+        self.tx_type.append_value(transaction.tx_type().name());
+        self.amount
+            .append_option(transaction.amount().ok().map(|amount| amount.raw().into()));
+        self.to
+            .append_option(transaction.destination().map(|to| to.0));
+        self.dispute
+            .append_value(transaction.dispute_state().stage().name());
+        self.client_sequence
+            .append_option(transaction.client_sequence());
+        self.timestamp.append_option(transaction.timestamp());
+
+        self.rows += 1;
+    }
+
+    /// Take the buffered rows, as a batch of the given schema
+    fn finish(&mut self, schema: SchemaRef) -> Result<RecordBatch, StateExporterError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.tx.finish()),
+            Arc::new(self.client.finish()),
+            Arc::new(self.tx_type.finish()),
+            Arc::new(self.amount.finish()),
+            Arc::new(self.to.finish()),
+            Arc::new(self.dispute.finish()),
+            Arc::new(self.client_sequence.finish()),
+            Arc::new(self.timestamp.finish()),
+        ];
+
+        self.rows = 0;
+
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+/// Writes every transaction kept by the transaction repository to a Parquet file, with their
+/// outcome, so the processed transactions can be loaded along with the state.
+///
+/// The transactions are written client by client, ordered by client id, each in the order they
+/// were applied. Like the [statements](crate::state_exporter::statements), only the
+/// transactions with a history (deposits, withdrawals, authorizations and transfers) are kept
+/// by the repositories, with the outcome of the disputes filed against them.
+pub struct ParquetTransactionLogExporter<CR, TR> {
+    client_repo: CR,
+    transaction_repo: TR,
+    precision: u32,
+}
+
+impl<CR, TR> ParquetTransactionLogExporter<CR, TR>
+where
+    CR: TClientRepository,
+    TR: TTransactionRepository,
+{
+    pub fn new(client_repo: CR, transaction_repo: TR) -> Self {
+        Self {
+            client_repo,
+            transaction_repo,
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    /// The amount of decimal places the amounts are stored with, which defaults to
+    /// [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Write the transaction log to the given sink, returning the amount of transactions
+    pub async fn export(&self, sink: impl Write + Send) -> Result<usize, StateExporterError> {
+        let schema = transaction_schema(self.precision);
+
+        let mut writer = ArrowWriter::try_new(sink, schema.clone(), Some(writer_properties()))?;
+
+        let mut columns = TransactionColumns::new(self.precision)?;
+
+        let mut exported = 0;
+
+        let mut clients = self.client_repo.find_all_clients_sorted().await?;
+
+        while let Some(client) = clients.next().await {
+            let client_id = client.lock().await.client_id();
+
+            let mut history = self.transaction_repo.find_txs_by_client(client_id).await?;
+
+            while let Some(stored_tx) = history.next().await {
+                columns.push(&*stored_tx.lock().await);
+
+                exported += 1;
+
+                if columns.rows == BATCH_SIZE {
+                    writer.write(&columns.finish(schema.clone())?)?;
+                }
+            }
+        }
+
+        if columns.rows > 0 {
+            writer.write(&columns.finish(schema)?)?;
+        }
+
+        writer.close()?;
+
+        Ok(exported)
+    }
+}
+
+#[cfg(test)]
+mod parquet_exporter_tests {
+    use std::sync::Arc;
+
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Decimal128Type, UInt16Type};
+    use bytes::Bytes;
+    use futures::lock::Mutex;
+    use futures::stream;
+
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::client::{Client, ClientAccountStatus};
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::transactions::TTransactionRepository;
+    use crate::state_exporter::parquet::{ParquetStateExporter, ParquetTransactionLogExporter};
+    use crate::state_exporter::TClientStateExporter;
+
+    fn read(file: Vec<u8>) -> arrow::record_batch::RecordBatch {
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(file))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        reader.next().unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_parquet_state_export() {
+        let clients = vec![
+            Client::builder()
+                .with_client_id(ClientID(1))
+                .with_available(MoneyType::new(15000))
+                .with_held(MoneyType::new(5000))
+                .with_account_status(ClientAccountStatus::Frozen)
+                .build(),
+            Client::builder()
+                .with_client_id(ClientID(2))
+                .with_credit_limit(MoneyType::new(10000))
+                .build(),
+        ];
+
+        let exporter = ParquetStateExporter::new(Vec::new());
+
+        exporter
+            .export_state(stream::iter(
+                clients
+                    .into_iter()
+                    .map(|client| Arc::new(Mutex::new(client))),
+            ))
+            .await
+            .unwrap();
+
+        let batch = read(exporter.into_inner());
+
+        assert_eq!(batch.num_rows(), 2);
+
+        let total = batch
+            .column_by_name("total")
+            .unwrap()
+            .as_primitive::<Decimal128Type>();
+
+        assert_eq!(total.value_as_string(0), "2.0000");
+
+        let locked = batch.column_by_name("locked").unwrap().as_boolean();
+
+        assert!(locked.value(0));
+        assert!(!locked.value(1));
+
+        let credit_limit = batch.column_by_name("credit_limit").unwrap();
+
+        assert!(credit_limit.is_null(0));
+        assert_eq!(
+            credit_limit
+                .as_primitive::<Decimal128Type>()
+                .value_as_string(1),
+            "1.0000"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parquet_transaction_log_export() {
+        let (client_repo, transaction_repo) = (
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        );
+
+        for client in [2, 1] {
+            client_repo
+                .store_client(Client::builder().with_client_id(ClientID(client)).build())
+                .await
+                .unwrap();
+
+            transaction_repo
+                .store_tx(
+                    Transaction::builder()
+                        .with_client_id(ClientID(client))
+                        .with_tx_id(TransactionID(u32::from(client) * 10))
+                        .with_tx_type(TransactionType::Deposit {
+                            amount: MoneyType::new(12345),
+                            dispute: DisputeState::NotDisputed,
+                        })
+                        .build(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut file = Vec::new();
+
+        let exported = ParquetTransactionLogExporter::new(client_repo, transaction_repo)
+            .export(&mut file)
+            .await
+            .unwrap();
+
+        assert_eq!(exported, 2);
+
+        let batch = read(file);
+
+        let clients = batch
+            .column_by_name("client")
+            .unwrap()
+            .as_primitive::<UInt16Type>();
+
+        assert_eq!(clients.values().to_vec(), vec![1, 2]);
+
+        assert_eq!(
+            batch
+                .column_by_name("amount")
+                .unwrap()
+                .as_primitive::<Decimal128Type>()
+                .value_as_string(0),
+            "1.2345"
+        );
+        assert_eq!(
+            batch
+                .column_by_name("type")
+                .unwrap()
+                .as_string::<i32>()
+                .value(1),
+            "deposit"
+        );
+        assert!(batch.column_by_name("to").unwrap().is_null(0));
+    }
+}
@@ -0,0 +1,97 @@
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::primitives::ByteStream;
+use futures::Stream;
+
+use crate::repositories::clients::StoredClient;
+use crate::state_exporter::{
+    CsvStateExporter, ExportSchema, StateExporterError, TClientStateExporter,
+};
+use crate::tx_reception::s3::{client_from_env, S3Error, S3Location};
+use crate::FLOATING_POINT_ACC;
+
+/// Exporter writing the client state to an S3 object, as the [`CsvStateExporter`] would write
+/// it.
+///
+/// S3 objects are written whole, so the export is kept in memory until it is uploaded, once
+/// every client was exported. Exports in other formats can be uploaded with
+/// [`S3StateExporter::upload`].
+pub struct S3StateExporter {
+    client: aws_sdk_s3::Client,
+    location: S3Location,
+    sort_by_client: bool,
+    schema: ExportSchema,
+    precision: u32,
+}
+
+impl S3StateExporter {
+    pub fn new(client: aws_sdk_s3::Client, location: S3Location) -> Self {
+        Self {
+            client,
+            location,
+            sort_by_client: false,
+            schema: ExportSchema::default(),
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    /// Export the state to the given location with the credentials of the standard AWS chain
+    pub async fn from_env(location: S3Location) -> Self {
+        Self::new(client_from_env().await, location)
+    }
+
+    /// Sort the exported rows by client id
+    pub fn with_sorted_output(mut self, sort_by_client: bool) -> Self {
+        self.sort_by_client = sort_by_client;
+
+        self
+    }
+
+    /// Choose the columns included in the export
+    pub fn with_schema(mut self, schema: ExportSchema) -> Self {
+        self.schema = schema;
+
+        self
+    }
+
+    /// The amount of decimal places the amounts are stored with, which defaults to
+    /// [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Write the given export to the object, replacing it if it exists
+    pub async fn upload(&self, export: Vec<u8>) -> Result<(), StateExporterError> {
+        self.client
+            .put_object()
+            .bucket(&self.location.bucket)
+            .key(&self.location.key)
+            .body(ByteStream::from(export))
+            .send()
+            .await
+            .map_err(|err| {
+                S3Error::WriteFailed(self.location.clone(), DisplayErrorContext(&err).to_string())
+            })?;
+
+        Ok(())
+    }
+}
+
+impl TClientStateExporter for S3StateExporter {
+    type Error = StateExporterError;
+
+    async fn export_state(
+        &self,
+        state: impl Stream<Item = StoredClient>,
+    ) -> Result<(), StateExporterError> {
+        let exporter = CsvStateExporter::new(Vec::new())
+            .with_sorted_output(self.sort_by_client)
+            .with_schema(self.schema)
+            .with_precision(self.precision)?;
+
+        exporter.export_state(state).await?;
+
+        self.upload(exporter.into_inner()).await
+    }
+}
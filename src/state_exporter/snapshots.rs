@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::repositories::clients::TClientRepository;
+use crate::state_exporter::{
+    CsvStateExporter, ExportSchema, StateExporterError, TClientStateExporter,
+};
+use crate::FLOATING_POINT_ACC;
+
+/// Periodically exports the current state of the clients to timestamped CSV files, so long (or
+/// never ending) streams don't have to end before their state can be seen.
+///
+/// Snapshots are taken every given amount of processed transactions, through
+/// [`StateSnapshotter::transaction_processed`], and/or every given amount of time, through
+/// [`StateSnapshotter::run_periodically`].
+///
+/// Every snapshot is named `snapshot-<unix millis>-<sequence>.csv` and only appears in the
+/// directory once it has been completely written.
+pub struct StateSnapshotter<CR> {
+    client_repo: CR,
+    directory: PathBuf,
+    schema: ExportSchema,
+    precision: u32,
+    every_transactions: Option<u64>,
+    every: Option<Duration>,
+    processed: AtomicU64,
+    taken: AtomicU64,
+}
+
+impl<CR> StateSnapshotter<CR>
+where
+    CR: TClientRepository,
+{
+    /// Write the snapshots of the clients in the given repository to the given directory
+    pub fn new(client_repo: CR, directory: impl Into<PathBuf>) -> Self {
+        Self {
+            client_repo,
+            directory: directory.into(),
+            schema: ExportSchema::default(),
+            precision: FLOATING_POINT_ACC as u32,
+            every_transactions: None,
+            every: None,
+            processed: AtomicU64::new(0),
+            taken: AtomicU64::new(0),
+        }
+    }
+
+    /// Take a snapshot every given amount of processed transactions
+    pub fn with_transaction_interval(mut self, transactions: u64) -> Self {
+        self.every_transactions = Some(transactions.max(1));
+
+        self
+    }
+
+    /// Take a snapshot every given amount of time
+    pub fn with_time_interval(mut self, every: Duration) -> Self {
+        self.every = Some(every);
+
+        self
+    }
+
+    /// Choose the columns included in the snapshots
+    pub fn with_schema(mut self, schema: ExportSchema) -> Self {
+        self.schema = schema;
+
+        self
+    }
+
+    /// The amount of decimal places of the exported amounts, which defaults to
+    /// [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Count a processed transaction, taking a snapshot if it completes the transaction interval
+    pub async fn transaction_processed(&self) -> Result<Option<PathBuf>, StateExporterError> {
+        let Some(every_transactions) = self.every_transactions else {
+            return Ok(None);
+        };
+
+        let processed = self.processed.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if !processed.is_multiple_of(every_transactions) {
+            return Ok(None);
+        }
+
+        self.snapshot().await.map(Some)
+    }
+
+    /// Take a snapshot every time interval, forever. Does nothing without a time interval.
+    ///
+    /// Failed snapshots are reported and don't stop the following ones.
+    pub async fn run_periodically(&self) {
+        let Some(every) = self.every else {
+            return;
+        };
+
+        let mut interval = tokio::time::interval(every);
+
+        // The first tick completes right away, and there's nothing worth a snapshot yet
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.snapshot().await {
+                tracing::error!(error = %err, "Failed to take a snapshot of the state");
+            }
+        }
+    }
+
+    /// Export the current state of the clients to a new snapshot, returning its path
+    pub async fn snapshot(&self) -> Result<PathBuf, StateExporterError> {
+        let sequence = self.taken.fetch_add(1, Ordering::Relaxed);
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_millis();
+
+        let name = format!("snapshot-{}-{:06}.csv", timestamp, sequence);
+
+        let path = self.directory.join(&name);
+        let partial_path = self.directory.join(format!(".{}.partial", name));
+
+        let exporter = CsvStateExporter::to_file(&partial_path)
+            .await?
+            .with_sorted_output(true)
+            .with_schema(self.schema)
+            .with_precision(self.precision)?;
+
+        exporter
+            .export_state(self.client_repo.find_all_clients().await?)
+            .await?;
+
+        tokio::fs::rename(&partial_path, &path).await?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use crate::infrastructure::in_mem_dbs::ClientInMemRepository;
+    use crate::models::client::Client;
+    use crate::models::{ClientID, MoneyType};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::shareable::ShareableClientRepository;
+    use crate::state_exporter::snapshots::StateSnapshotter;
+
+    #[tokio::test]
+    async fn test_snapshot_every_transactions() {
+        let directory = std::env::temp_dir().join(format!("snapshots-test-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let client_repo = ShareableClientRepository::from(ClientInMemRepository::default());
+
+        let snapshotter =
+            StateSnapshotter::new(client_repo.clone(), &directory).with_transaction_interval(2);
+
+        let mut snapshots = Vec::new();
+
+        for (client_id, available) in [(1, 10000), (2, 20000), (3, 30000)] {
+            client_repo
+                .store_client(
+                    Client::builder()
+                        .with_client_id(ClientID(client_id))
+                        .with_available(MoneyType::new(available))
+                        .build(),
+                )
+                .await
+                .unwrap();
+
+            snapshots.extend(snapshotter.transaction_processed().await.unwrap());
+        }
+
+        let contents = snapshots
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect::<Vec<_>>();
+
+        let files = std::fs::read_dir(&directory).unwrap().count();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        // Only the second transaction completed the interval
+        assert_eq!(files, 1);
+        assert_eq!(
+            contents,
+            vec![
+                "client,available,held,total,locked\n\
+                 1,1.0000,0.0000,1.0000,false\n\
+                 2,2.0000,0.0000,2.0000,false\n"
+            ]
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use std::path::PathBuf;
+
+use futures::StreamExt;
+
+use crate::models::money::format_amount;
+use crate::models::transactions::{AuthorizationState, DisputeStage, Transaction, TransactionType};
+use crate::models::{ClientID, MoneyType};
+use crate::repositories::clients::TClientRepository;
+use crate::repositories::transactions::TTransactionRepository;
+use crate::state_exporter::{encode_record, StateExporterError};
+use crate::FLOATING_POINT_ACC;
+
+const STATEMENT_HEADER: [&str; 8] = [
+    "tx",
+    "type",
+    "amount",
+    "to",
+    "status",
+    "available",
+    "held",
+    "total",
+];
+
+/// The format the statements are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatementFormat {
+    /// A CSV file per client, `<client>.csv`
+    #[default]
+    Csv,
+    /// A JSON array of the entries per client, `<client>.json`
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl StatementFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            StatementFormat::Csv => "csv",
+            #[cfg(feature = "json")]
+            StatementFormat::Json => "json",
+        }
+    }
+}
+
+/// Where a transaction of the statement stands, after the disputes filed against it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum StatementStatus {
+    Settled,
+    Disputed,
+    Resolved,
+    ChargedBack,
+    /// Charged back, and then given back to the client by a representment
+    Represented,
+    /// An authorization which was not captured yet, holding its amount
+    Authorized,
+    /// An authorization which was not captured in time, so its amount went back to the client
+    Expired,
+}
+
+impl StatementStatus {
+    fn of(transaction: &Transaction) -> Self {
+        match transaction.tx_type() {
+            TransactionType::Authorize {
+                state: AuthorizationState::Pending,
+                ..
+            } => return StatementStatus::Authorized,
+            TransactionType::Authorize {
+                state: AuthorizationState::Expired,
+                ..
+            } => return StatementStatus::Expired,
+            _ => {}
+        }
+
+        match transaction.dispute_state().stage() {
+            DisputeStage::NotDisputed => StatementStatus::Settled,
+            DisputeStage::Open => StatementStatus::Disputed,
+            DisputeStage::Resolved => StatementStatus::Resolved,
+            DisputeStage::ChargedBack => StatementStatus::ChargedBack,
+            DisputeStage::Represented => StatementStatus::Represented,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            StatementStatus::Settled => "settled",
+            StatementStatus::Disputed => "disputed",
+            StatementStatus::Resolved => "resolved",
+            StatementStatus::ChargedBack => "charged_back",
+            StatementStatus::Represented => "represented",
+            StatementStatus::Authorized => "authorized",
+            StatementStatus::Expired => "expired",
+        }
+    }
+}
+
+/// A line of a client's statement: one of their transactions, followed by the balances of the
+/// account right after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatementEntry {
+    pub tx: crate::models::TransactionID,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub tx_type: &'static str,
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+    pub amount: MoneyType,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub to: Option<ClientID>,
+    pub status: StatementStatus,
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+    pub available: MoneyType,
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+    pub held: MoneyType,
+    #[cfg_attr(feature = "serde", serde(with = "crate::models::money::serde_amount"))]
+    pub total: MoneyType,
+}
+
+/// The balances of a client's account, as their transactions are gone through in the order
+/// they were applied
+#[derive(Debug, Default)]
+struct RunningBalance {
+    available: MoneyType,
+    held: MoneyType,
+}
+
+impl RunningBalance {
+    /// Account for the transaction as it stands now, so a charged back deposit never counts
+    /// towards the balances and an open dispute keeps its funds held
+    fn apply(&mut self, transaction: &Transaction) -> Option<StatementEntry> {
+        let amount = transaction.amount().ok()?;
+
+        let status = StatementStatus::of(transaction);
+
+        let (available, held) = match (transaction.tx_type(), status) {
+            (TransactionType::Deposit { .. }, StatementStatus::Disputed) => {
+                (MoneyType::ZERO, amount)
+            }
+            (TransactionType::Deposit { .. }, StatementStatus::ChargedBack) => {
+                (MoneyType::ZERO, MoneyType::ZERO)
+            }
+            (TransactionType::Deposit { .. }, _) => (amount, MoneyType::ZERO),
+            (TransactionType::Withdrawal { .. }, StatementStatus::Disputed) => (-amount, amount),
+            // The withdrawn amount is credited back to the client
+            (
+                TransactionType::Withdrawal { .. },
+                StatementStatus::Resolved | StatementStatus::Represented,
+            ) => (MoneyType::ZERO, MoneyType::ZERO),
+            (TransactionType::Authorize { .. }, StatementStatus::Authorized) => (-amount, amount),
+            (TransactionType::Authorize { .. }, StatementStatus::Expired) => {
+                (MoneyType::ZERO, MoneyType::ZERO)
+            }
+            _ => (-amount, MoneyType::ZERO),
+        };
+
+        self.available += available;
+        self.held += held;
+
+        Some(StatementEntry {
+            tx: transaction.transaction_id(),
+            tx_type: transaction.tx_type().name(),
+            amount,
+            to: transaction.destination(),
+            status,
+            available: self.available,
+            held: self.held,
+            total: self.available + self.held,
+        })
+    }
+}
+
+/// Writes the statement of each client, the chronological list of the transactions they made
+/// along with the running balances of their account, to its own file in a directory
+/// (`<directory>/<client>.csv`).
+///
+/// The statements are built from the history kept by the transaction repository, so only the
+/// deposits, withdrawals, authorizations and outgoing transfers of the client are listed, each
+/// with the outcome of the disputes filed against it. Transfers received from other clients are
+/// in the statements of their senders. Disputed withdrawals are shown holding their amount, as
+/// they do under the default [`DisputePolicy`](crate::services::decision::DisputePolicy).
+pub struct StatementExporter<CR, TR> {
+    client_repo: CR,
+    transaction_repo: TR,
+    directory: PathBuf,
+    format: StatementFormat,
+    precision: u32,
+}
+
+impl<CR, TR> StatementExporter<CR, TR>
+where
+    CR: TClientRepository,
+    TR: TTransactionRepository,
+{
+    /// Write the statements of the clients in the given repositories to the given directory
+    pub fn new(client_repo: CR, transaction_repo: TR, directory: impl Into<PathBuf>) -> Self {
+        Self {
+            client_repo,
+            transaction_repo,
+            directory: directory.into(),
+            format: StatementFormat::default(),
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    pub fn with_format(mut self, format: StatementFormat) -> Self {
+        self.format = format;
+
+        self
+    }
+
+    /// The amount of decimal places of the amounts in the CSV statements
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// The entries of the statement of the given client
+    pub async fn statement(
+        &self,
+        client_id: ClientID,
+    ) -> Result<Vec<StatementEntry>, StateExporterError> {
+        let mut history = self.transaction_repo.find_txs_by_client(client_id).await?;
+
+        let (mut balance, mut entries) = (RunningBalance::default(), Vec::new());
+
+        while let Some(stored_tx) = history.next().await {
+            entries.extend(balance.apply(&*stored_tx.lock().await));
+        }
+
+        Ok(entries)
+    }
+
+    /// Write the statement of the given client, returning its path
+    pub async fn export_client(&self, client_id: ClientID) -> Result<PathBuf, StateExporterError> {
+        let entries = self.statement(client_id).await?;
+
+        let contents = match self.format {
+            StatementFormat::Csv => self.encode_csv(&entries)?,
+            #[cfg(feature = "json")]
+            StatementFormat::Json => serde_json::to_vec_pretty(&entries)
+                .map_err(|err| StateExporterError::IoError(err.into()))?,
+        };
+
+        let path = self
+            .directory
+            .join(format!("{}.{}", client_id, self.format.extension()));
+
+        tokio::fs::create_dir_all(&self.directory).await?;
+        tokio::fs::write(&path, contents).await?;
+
+        Ok(path)
+    }
+
+    /// Write the statement of every client, returning their paths
+    pub async fn export_all(&self) -> Result<Vec<PathBuf>, StateExporterError> {
+        let mut clients = Vec::new();
+
+        let mut stored_clients = self.client_repo.find_all_clients().await?;
+
+        while let Some(client) = stored_clients.next().await {
+            clients.push(client.lock().await.client_id());
+        }
+
+        clients.sort();
+
+        let mut paths = Vec::with_capacity(clients.len());
+
+        for client_id in clients {
+            paths.push(self.export_client(client_id).await?);
+        }
+
+        Ok(paths)
+    }
+
+    fn encode_csv(&self, entries: &[StatementEntry]) -> Result<Vec<u8>, StateExporterError> {
+        let mut contents = encode_record(STATEMENT_HEADER)?;
+
+        for entry in entries {
+            contents.extend(encode_record([
+                entry.tx.to_string().as_str(),
+                entry.tx_type,
+                &format_amount(entry.amount, self.precision),
+                &entry.to.map(|to| to.to_string()).unwrap_or_default(),
+                entry.status.name(),
+                &format_amount(entry.available, self.precision),
+                &format_amount(entry.held, self.precision),
+                &format_amount(entry.total, self.precision),
+            ])?);
+        }
+
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod statement_tests {
+    use crate::infrastructure::in_mem_dbs::{ClientInMemRepository, TransactionInMemRepository};
+    use crate::models::client::Client;
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::repositories::clients::TClientRepository;
+    use crate::repositories::transactions::TTransactionRepository;
+    use crate::state_exporter::statements::StatementExporter;
+
+    fn transaction(client: u16, tx_id: u32, tx_type: TransactionType) -> Transaction {
+        Transaction::builder()
+            .with_client_id(ClientID(client))
+            .with_tx_id(TransactionID(tx_id))
+            .with_tx_type(tx_type)
+            .build()
+    }
+
+    fn deposit(client: u16, tx_id: u32, amount: i64) -> Transaction {
+        transaction(
+            client,
+            tx_id,
+            TransactionType::Deposit {
+                amount: MoneyType::new(amount),
+                dispute: DisputeState::NotDisputed,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_statements() {
+        let directory =
+            std::env::temp_dir().join(format!("statements-test-{}", std::process::id()));
+
+        let (client_repo, tx_repo) = (
+            ClientInMemRepository::default(),
+            TransactionInMemRepository::default(),
+        );
+
+        for client in [1, 2] {
+            client_repo
+                .store_client(Client::builder().with_client_id(ClientID(client)).build())
+                .await
+                .unwrap();
+        }
+
+        let mut charged_back = deposit(1, 2, 20000);
+
+        charged_back
+            .dispute(transaction(1, 2, TransactionType::Dispute))
+            .unwrap();
+        charged_back
+            .settle_dispute(transaction(1, 2, TransactionType::Chargeback))
+            .unwrap();
+
+        let mut disputed = transaction(
+            1,
+            3,
+            TransactionType::Withdrawal {
+                amount: MoneyType::new(5000),
+                dispute: DisputeState::NotDisputed,
+            },
+        );
+
+        disputed
+            .dispute(transaction(1, 3, TransactionType::Dispute))
+            .unwrap();
+
+        let transfer = transaction(
+            1,
+            4,
+            TransactionType::Transfer {
+                to_client: ClientID(2),
+                amount: MoneyType::new(10000),
+            },
+        );
+
+        for tx in [deposit(1, 1, 30000), charged_back, disputed, transfer] {
+            tx_repo.store_tx(tx).await.unwrap();
+        }
+
+        let exporter = StatementExporter::new(client_repo, tx_repo, &directory);
+
+        let paths = exporter.export_all().await.unwrap();
+
+        let contents = paths
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect::<Vec<_>>();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            paths,
+            vec![directory.join("1.csv"), directory.join("2.csv")]
+        );
+        assert_eq!(
+            contents,
+            vec![
+                "tx,type,amount,to,status,available,held,total\n\
+                 1,deposit,3.0000,,settled,3.0000,0.0000,3.0000\n\
+                 2,deposit,2.0000,,charged_back,3.0000,0.0000,3.0000\n\
+                 3,withdrawal,0.5000,,disputed,2.5000,0.5000,3.0000\n\
+                 4,transfer,1.0000,2,settled,1.5000,0.5000,2.0000\n",
+                // The transfer is only listed by its sender
+                "tx,type,amount,to,status,available,held,total\n"
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader, ReadBuf};
+
+/// The first bytes of every gzip member
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The first bytes of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How an input is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression of an input from its first bytes, falling back to the extension
+    /// of its path (`.gz` or `.zst`) when they are too short to tell
+    pub fn detect(path: &Path, head: &[u8]) -> Self {
+        let compression = Self::from_head(head);
+
+        if compression != Compression::None {
+            return compression;
+        }
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") if head.len() < GZIP_MAGIC.len() => Compression::Gzip,
+            Some("zst") if head.len() < ZSTD_MAGIC.len() => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Detect the compression of an input from its first bytes alone, for inputs without a
+    /// path such as the standard input
+    pub fn from_head(head: &[u8]) -> Self {
+        if head.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Reads an input which may be compressed with gzip or zstd, decompressing it as it is read,
+/// so compressed dumps can be given to the
+/// [`CSVTransactionProvider`](crate::tx_reception::CSVTransactionProvider) as they are.
+///
+/// Inputs made of several concatenated members (or frames) are read as a whole.
+pub enum DecompressingReader<R> {
+    Plain(R),
+    Gzip(GzipDecoder<R>),
+    Zstd(ZstdDecoder<R>),
+}
+
+impl<R> DecompressingReader<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    pub fn new(reader: R, compression: Compression) -> Self {
+        match compression {
+            Compression::None => DecompressingReader::Plain(reader),
+            Compression::Gzip => {
+                let mut decoder = GzipDecoder::new(reader);
+
+                decoder.multiple_members(true);
+
+                DecompressingReader::Gzip(decoder)
+            }
+            Compression::Zstd => {
+                let mut decoder = ZstdDecoder::new(reader);
+
+                decoder.multiple_members(true);
+
+                DecompressingReader::Zstd(decoder)
+            }
+        }
+    }
+
+    /// Read the given input, detecting whether it is compressed from its first bytes
+    pub async fn detect(mut reader: R) -> io::Result<Self> {
+        // Peeked, so they are still read afterwards
+        let compression = Compression::from_head(reader.fill_buf().await?);
+
+        Ok(Self::new(reader, compression))
+    }
+
+    /// Read the given input of the file at the given path, detecting whether it is compressed
+    /// from its first bytes or, failing that, the extension of the path
+    pub async fn detect_with_path(path: impl AsRef<Path>, mut reader: R) -> io::Result<Self> {
+        // Peeked, so they are still read afterwards
+        let compression = Compression::detect(path.as_ref(), reader.fill_buf().await?);
+
+        Ok(Self::new(reader, compression))
+    }
+}
+
+impl DecompressingReader<BufReader<tokio::io::Stdin>> {
+    /// Read the standard input, detecting whether it is compressed from its first bytes
+    pub async fn stdin() -> io::Result<Self> {
+        Self::detect(BufReader::new(tokio::io::stdin())).await
+    }
+}
+
+impl DecompressingReader<BufReader<tokio::fs::File>> {
+    /// Open the file at the given path, detecting whether it is compressed from its first
+    /// bytes or, failing that, its extension
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = BufReader::new(tokio::fs::File::open(path.as_ref()).await?);
+
+        Self::detect_with_path(path, reader).await
+    }
+}
+
+impl<R> AsyncRead for DecompressingReader<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            DecompressingReader::Plain(reader) => Pin::new(reader).poll_read(cx, buf),
+            DecompressingReader::Gzip(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            DecompressingReader::Zstd(decoder) => Pin::new(decoder).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod compression_test {
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use async_compression::tokio::bufread::{GzipEncoder, ZstdEncoder};
+    use futures::StreamExt;
+    use tokio::io::AsyncReadExt;
+
+    use crate::models::TransactionID;
+    use crate::tx_reception::compression::{Compression, DecompressingReader};
+    use crate::tx_reception::{CSVTransactionProvider, TTransactionStreamProvider};
+
+    const CSV_DATA: &str = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 2.0\n";
+
+    async fn read_all(mut reader: impl tokio::io::AsyncRead + Unpin) -> Vec<u8> {
+        let mut contents = Vec::new();
+
+        reader.read_to_end(&mut contents).await.unwrap();
+
+        contents
+    }
+
+    #[test]
+    fn test_detect_compression() {
+        let path = Path::new("transactions.csv");
+
+        assert_eq!(
+            Compression::detect(path, &[0x1f, 0x8b, 0x08]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::detect(path, &[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::detect(path, b"type,client"), Compression::None);
+
+        // The extension only decides when the input is too short to tell
+        assert_eq!(
+            Compression::detect(Path::new("transactions.csv.gz"), &[]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::detect(Path::new("transactions.csv.zst"), b"type,client"),
+            Compression::None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decompressing_reader() {
+        let gzipped = read_all(GzipEncoder::new(CSV_DATA.as_bytes())).await;
+        let zstd = read_all(ZstdEncoder::new(CSV_DATA.as_bytes())).await;
+
+        for (compressed, compression) in [
+            (gzipped.clone(), Compression::Gzip),
+            (zstd, Compression::Zstd),
+            (CSV_DATA.as_bytes().to_vec(), Compression::None),
+        ] {
+            assert_eq!(
+                Compression::detect(Path::new("input"), &compressed),
+                compression
+            );
+
+            let reader = DecompressingReader::new(Cursor::new(compressed), compression);
+
+            let txs = CSVTransactionProvider::new(reader)
+                .subscribe_to_tx_stream()
+                .await
+                .map(|tx| tx.transaction_id())
+                .collect::<Vec<_>>()
+                .await;
+
+            assert_eq!(txs, vec![TransactionID(1), TransactionID(2)]);
+        }
+
+        // Concatenated members are read as a single input
+        let concatenated = [gzipped.clone(), gzipped].concat();
+
+        let contents = read_all(DecompressingReader::new(
+            concatenated.as_slice(),
+            Compression::Gzip,
+        ))
+        .await;
+
+        assert_eq!(contents, [CSV_DATA, CSV_DATA].concat().as_bytes());
+
+        // Without a path to go by, as for the standard input
+        let reader = DecompressingReader::detect(Cursor::new(concatenated))
+            .await
+            .unwrap();
+
+        assert!(matches!(reader, DecompressingReader::Gzip(_)));
+        assert_eq!(
+            read_all(reader).await,
+            [CSV_DATA, CSV_DATA].concat().as_bytes()
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+use std::future;
+use std::ops::RangeInclusive;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use thiserror::Error;
+
+use crate::models::transactions::{Transaction, TRANSACTION_TYPE_NAMES};
+use crate::models::{ClientID, TransactionID};
+use crate::tx_reception::TTransactionStreamProvider;
+
+/// Provider only handing out the transactions of another one which match every one of the
+/// given conditions, such as to re-run the history of a single client out of a large input
+/// when investigating an incident.
+///
+/// Without any condition, every transaction is handed out. The transactions are read and
+/// dropped as fast as the input is, so only those which match wait for the consumer.
+pub struct FilteredProvider<P> {
+    provider: P,
+    filter: TransactionFilter,
+}
+
+/// The conditions of a [`FilteredProvider`], each of them met by any transaction when not given
+#[derive(Debug, Clone, Default)]
+struct TransactionFilter {
+    clients: Option<HashSet<ClientID>>,
+    client_range: Option<RangeInclusive<ClientID>>,
+    tx_range: Option<RangeInclusive<TransactionID>>,
+    types: Option<HashSet<String>>,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Unknown transaction type {0:?}")]
+pub struct UnknownTransactionType(pub String);
+
+impl<P> FilteredProvider<P> {
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            filter: TransactionFilter::default(),
+        }
+    }
+
+    /// Only the transactions of the given clients, including the transfers they received
+    pub fn with_clients(mut self, clients: impl IntoIterator<Item = ClientID>) -> Self {
+        self.filter.clients = Some(clients.into_iter().collect());
+
+        self
+    }
+
+    /// Only the transactions of the clients in the given range, including the transfers they
+    /// received
+    pub fn with_client_range(mut self, clients: RangeInclusive<ClientID>) -> Self {
+        self.filter.client_range = Some(clients);
+
+        self
+    }
+
+    /// Only the transactions with an id in the given range. The disputes, settlements and
+    /// captures carry the id of the transaction they refer to, so they match along with it.
+    pub fn with_tx_range(mut self, transactions: RangeInclusive<TransactionID>) -> Self {
+        self.filter.tx_range = Some(transactions);
+
+        self
+    }
+
+    /// Only the transactions of the given types, named as in the input (e.g. `deposit`).
+    ///
+    /// Fails on a name which isn't that of a transaction type, as it would match nothing.
+    pub fn with_types<T>(
+        mut self,
+        types: impl IntoIterator<Item = T>,
+    ) -> Result<Self, UnknownTransactionType>
+    where
+        T: Into<String>,
+    {
+        let types = types
+            .into_iter()
+            .map(Into::into)
+            .collect::<HashSet<String>>();
+
+        if let Some(unknown) = types
+            .iter()
+            .find(|tx_type| !TRANSACTION_TYPE_NAMES.contains(&tx_type.as_str()))
+        {
+            return Err(UnknownTransactionType(unknown.clone()));
+        }
+
+        self.filter.types = Some(types);
+
+        Ok(self)
+    }
+}
+
+impl TransactionFilter {
+    fn matches(&self, tx: &Transaction) -> bool {
+        // Transfers belong to the history of both of their clients
+        let client_matches = self.matches_client(tx.client())
+            || tx
+                .destination()
+                .is_some_and(|to_client| self.matches_client(to_client));
+
+        client_matches
+            && self
+                .tx_range
+                .as_ref()
+                .is_none_or(|range| range.contains(&tx.transaction_id()))
+            && self
+                .types
+                .as_ref()
+                .is_none_or(|types| types.contains(tx.tx_type().name()))
+    }
+
+    fn matches_client(&self, client: ClientID) -> bool {
+        self.clients
+            .as_ref()
+            .is_none_or(|clients| clients.contains(&client))
+            && self
+                .client_range
+                .as_ref()
+                .is_none_or(|range| range.contains(&client))
+    }
+}
+
+impl<P> TTransactionStreamProvider for FilteredProvider<P>
+where
+    P: TTransactionStreamProvider,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let filter = self.filter;
+
+        self.provider
+            .subscribe_to_tx_stream()
+            .await
+            .filter(move |tx| future::ready(filter.matches(tx)))
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod filter_test {
+    use futures::StreamExt;
+
+    use crate::models::{ClientID, TransactionID};
+    use crate::tx_reception::filter::{FilteredProvider, UnknownTransactionType};
+    use crate::tx_reception::{CSVTransactionProvider, TTransactionStreamProvider};
+
+    const CSV_DATA: &str = "type,client,tx,amount,to
+deposit,1,1,5.0,
+deposit,2,2,5.0,
+withdrawal,3,3,1.0,
+transfer,2,4,1.0,1
+dispute,1,1,,
+deposit,4,5,5.0,";
+
+    async fn filtered_ids(
+        filter: impl FnOnce(
+            FilteredProvider<CSVTransactionProvider<&'static [u8]>>,
+        ) -> FilteredProvider<CSVTransactionProvider<&'static [u8]>>,
+    ) -> Vec<u32> {
+        filter(FilteredProvider::new(CSVTransactionProvider::new(
+            CSV_DATA.as_bytes(),
+        )))
+        .subscribe_to_tx_stream()
+        .await
+        .map(|tx| {
+            let TransactionID(id) = tx.transaction_id();
+
+            id
+        })
+        .collect()
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_filtered_provider() {
+        assert_eq!(
+            filtered_ids(|provider| provider).await,
+            vec![1, 2, 3, 4, 1, 5]
+        );
+
+        // The transfer received by the client is part of its history
+        assert_eq!(
+            filtered_ids(|provider| provider.with_clients([ClientID(1)])).await,
+            vec![1, 4, 1]
+        );
+        assert_eq!(
+            filtered_ids(|provider| provider.with_client_range(ClientID(3)..=ClientID(4))).await,
+            vec![3, 5]
+        );
+        assert_eq!(
+            filtered_ids(|provider| provider.with_tx_range(TransactionID(1)..=TransactionID(2)))
+                .await,
+            vec![1, 2, 1]
+        );
+        assert_eq!(
+            filtered_ids(|provider| provider
+                .with_types(["deposit", "dispute"])
+                .unwrap()
+                .with_clients([ClientID(1), ClientID(2)]))
+            .await,
+            vec![1, 2, 1]
+        );
+    }
+
+    #[test]
+    fn test_filtered_provider_unknown_type() {
+        let provider = FilteredProvider::new(CSVTransactionProvider::new(CSV_DATA.as_bytes()));
+
+        // A typo would otherwise silently filter out every transaction
+        assert_eq!(
+            provider.with_types(["deposit", "depsoit"]).err(),
+            Some(UnknownTransactionType("depsoit".to_string()))
+        );
+    }
+}
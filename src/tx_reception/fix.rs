@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::models::money::{format_amount, parse_amount, AmountParseError};
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, MoneyType, TransactionID};
+use crate::rejections::TRejectionReason;
+use crate::tx_reception::{parse_tx_type, RecordParseError, TTransactionStreamProvider};
+use crate::FLOATING_POINT_ACC;
+
+/// The delimiter of the fields of a FIX message
+const SOH: char = '\x01';
+
+/// The delimiter used instead of [`SOH`] by most logs meant to be read by people
+const PRINTABLE_DELIMITER: char = '|';
+
+const ACCOUNT: u32 = 1;
+const EXEC_ID: u32 = 17;
+const LAST_PX: u32 = 31;
+const LAST_QTY: u32 = 32;
+const MSG_TYPE: u32 = 35;
+const SIDE: u32 = 54;
+const EXEC_TYPE: u32 = 150;
+const GROSS_TRADE_AMT: u32 = 381;
+
+/// The `MsgType` of execution reports
+const EXECUTION_REPORT: &str = "8";
+
+/// The `ExecType`s of the execution reports of fills: `F` (trade) since FIX 4.3, and `1`
+/// (partial fill) or `2` (fill) before it
+const FILLS: [&str; 3] = ["F", "1", "2"];
+
+/// Provider reading the fills of a FIX drop-copy feed, as logged one message per line.
+///
+/// Every execution report of a fill is a transaction of the client of its `Account`: buys
+/// (`Side` `1`) are withdrawals of the cash paid, and sells (`Side` `2`, `5` or `6`) are
+/// deposits of the cash received. The amount is the `GrossTradeAmt` of the report, or its
+/// `LastQty` times its `LastPx` when it has none, and must be exactly representable with our
+/// precision. The `ExecID` of the report is the id of the transaction.
+///
+/// The fields can be delimited by `SOH` or by `|`, and anything before the `8=FIX` which
+/// starts the message (e.g. the timestamp of the log) is ignored, as are the lines and
+/// messages which are not execution reports of fills (e.g. heartbeats, or busted trades).
+/// Accounts are mapped to clients with [`Self::with_account`], and the accounts which were not
+/// mapped must be the id of their client.
+pub struct FixTransactionProvider<R> {
+    reader: R,
+    accounts: HashMap<String, ClientID>,
+}
+
+impl<R> FixTransactionProvider<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            accounts: HashMap::new(),
+        }
+    }
+
+    /// Take the fills of the given account as transactions of the given client
+    pub fn with_account(mut self, account: impl Into<String>, client: ClientID) -> Self {
+        self.accounts.insert(account.into(), client);
+
+        self
+    }
+}
+
+impl FixTransactionProvider<File> {
+    /// Read the fills of the FIX log at the given path
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
+
+/// The fields of a FIX message, in the order they were found in
+struct FixMessage<'a> {
+    fields: Vec<(u32, &'a str)>,
+}
+
+impl<'a> FixMessage<'a> {
+    /// Find the message logged in the line, if there is one
+    fn find(line: &'a str) -> Option<Result<Self, RecordParseError>> {
+        let message = &line[line.find("8=FIX")?..];
+
+        let delimiter = if message.contains(SOH) {
+            SOH
+        } else {
+            PRINTABLE_DELIMITER
+        };
+
+        let fields = message
+            .trim_end()
+            .split(delimiter)
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                field
+                    .split_once('=')
+                    .and_then(|(tag, value)| Some((tag.parse().ok()?, value)))
+                    .ok_or_else(|| {
+                        RecordParseError::MalformedRecord(format!("invalid field {:?}", field))
+                    })
+            })
+            .collect::<Result<_, _>>();
+
+        Some(fields.map(|fields| Self { fields }))
+    }
+
+    fn field(&self, tag: u32) -> Option<&'a str> {
+        self.fields
+            .iter()
+            .find(|(field_tag, _)| *field_tag == tag)
+            .map(|(_, value)| *value)
+    }
+
+    fn required(&self, tag: u32, name: &str) -> Result<&'a str, RecordParseError> {
+        self.field(tag).ok_or_else(|| {
+            RecordParseError::MalformedRecord(format!("missing the {} ({}) field", name, tag))
+        })
+    }
+
+    fn is_fill(&self) -> bool {
+        self.field(MSG_TYPE) == Some(EXECUTION_REPORT)
+            && self
+                .field(EXEC_TYPE)
+                .is_some_and(|exec_type| FILLS.contains(&exec_type))
+    }
+
+    fn into_transaction(
+        self,
+        accounts: &HashMap<String, ClientID>,
+    ) -> Result<Transaction, RecordParseError> {
+        let account = self.required(ACCOUNT, "Account")?;
+
+        let client = match accounts.get(account) {
+            Some(client) => *client,
+            None => account.parse().map_err(|_| {
+                RecordParseError::MalformedRecord(format!("unknown account {:?}", account))
+            })?,
+        };
+
+        let exec_id = self.required(EXEC_ID, "ExecID")?;
+
+        let tx_id: TransactionID = exec_id.parse().map_err(|_| {
+            RecordParseError::MalformedRecord(format!("invalid transaction {:?}", exec_id))
+        })?;
+
+        let amount = match self.field(GROSS_TRADE_AMT) {
+            Some(amount) => parse_amount(amount, FLOATING_POINT_ACC as u32)?,
+            None => fill_value(
+                self.required(LAST_QTY, "LastQty")?,
+                self.required(LAST_PX, "LastPx")?,
+            )?,
+        };
+
+        let tx_type = match self.required(SIDE, "Side")? {
+            "1" => "withdrawal",
+            "2" | "5" | "6" => "deposit",
+            side => {
+                return Err(RecordParseError::MalformedRecord(format!(
+                    "unsupported side {:?}",
+                    side
+                )))
+            }
+        };
+
+        Ok(Transaction::builder()
+            .with_client_id(client)
+            .with_tx_id(tx_id)
+            .with_tx_type(parse_tx_type(tx_type, Some(amount), None, false)?)
+            .build())
+    }
+}
+
+/// The value of a fill, its quantity times its price, computed without losing any decimal
+/// place, so it is only accepted when it fits our precision
+fn fill_value(quantity: &str, price: &str) -> Result<MoneyType, AmountParseError> {
+    let places = |value: &str| {
+        value
+            .trim()
+            .split_once('.')
+            .map_or(0, |(_, fraction)| fraction.len() as u32)
+    };
+
+    let (quantity_places, price_places) = (places(quantity), places(price));
+
+    // The places of the product must be formatted as well
+    10i64
+        .checked_pow(quantity_places + price_places)
+        .ok_or(AmountParseError::Overflow)?;
+
+    let quantity = parse_amount(quantity, quantity_places)?;
+    let price = parse_amount(price, price_places)?;
+
+    let value = quantity
+        .raw()
+        .checked_mul(price.raw())
+        .ok_or(AmountParseError::Overflow)?;
+
+    let value = format_amount(MoneyType::new(value), quantity_places + price_places);
+
+    // The trailing zeros of the product are not decimal places it needs
+    let value = if value.contains('.') {
+        value.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &value
+    };
+
+    parse_amount(value, FLOATING_POINT_ACC as u32)
+}
+
+impl<R> TTransactionStreamProvider for FixTransactionProvider<R>
+where
+    R: Read + Send + 'static,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let (tx_sender, rx) = flume::unbounded();
+
+        // Same as with the JSON Lines provider, the reading is blocking so we do it in a
+        // blocking task and propagate the transactions through a channel
+        tokio::task::spawn_blocking(move || {
+            let reader = BufReader::new(self.reader);
+
+            for (line_number, line) in reader.lines().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        tracing::error!(error = %err, "Failed to read the FIX input");
+                        break;
+                    }
+                };
+
+                let tx = match FixMessage::find(&line) {
+                    None => continue,
+                    Some(Ok(message)) if !message.is_fill() => continue,
+                    Some(message) => {
+                        message.and_then(|message| message.into_transaction(&self.accounts))
+                    }
+                };
+
+                let tx = match tx {
+                    Ok(tx) => tx,
+                    Err(err) => {
+                        tracing::warn!(
+                            line = line_number + 1,
+                            code = err.rejection_code().code(),
+                            error = %err,
+                            "Skipping the line"
+                        );
+                        continue;
+                    }
+                };
+
+                if tx_sender.send(tx).is_err() {
+                    // The stream has been dropped, no one is listening anymore
+                    break;
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+#[cfg(test)]
+mod fix_test {
+    use std::io::Cursor;
+
+    use futures::StreamExt;
+
+    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::tx_reception::fix::{fill_value, FixTransactionProvider};
+    use crate::tx_reception::TTransactionStreamProvider;
+
+    #[tokio::test]
+    async fn test_fix_reader() {
+        const FIX_LOG: &str = "\
+20240102-09:30:00.000 : 8=FIX.4.4|9=60|35=0|49=BROKER|56=CLIENT|34=1|10=000|
+20240102-09:30:01.000 : 8=FIX.4.4|9=150|35=8|1=ACC-1|17=1|150=F|54=1|32=10|31=12.5|10=000|
+20240102-09:30:02.000 : 8=FIX.4.4|9=150|35=8|1=2|17=2|150=F|54=2|32=3|31=1.5|381=4.55|10=000|
+20240102-09:30:03.000 : 8=FIX.4.4|9=150|35=8|1=2|17=3|150=0|54=1|38=10|44=1.5|10=000|
+20240102-09:30:04.000 : 8=FIX.4.4|9=150|35=8|1=2|17=EXEC-4|150=F|54=1|32=1|31=1|10=000|
+";
+
+        let provider = FixTransactionProvider::new(Cursor::new(FIX_LOG.replace('|', "\x01")))
+            .with_account("ACC-1", ClientID(1));
+
+        let txs = provider
+            .subscribe_to_tx_stream()
+            .await
+            .collect::<Vec<Transaction>>()
+            .await;
+
+        // The heartbeat and the new order are left out, and the fill without a numeric ExecID
+        // is skipped
+        assert_eq!(txs.len(), 2);
+
+        assert_eq!(txs[0].client(), ClientID(1));
+        assert!(matches!(
+            txs[0].tx_type(),
+            TransactionType::Withdrawal { amount, .. } if *amount == MoneyType::new(1_250_000)
+        ));
+        assert_eq!(txs[1].client(), ClientID(2));
+        assert_eq!(txs[1].transaction_id(), TransactionID(2));
+        assert!(matches!(
+            txs[1].tx_type(),
+            TransactionType::Deposit { amount, .. } if *amount == MoneyType::new(45_500)
+        ));
+    }
+
+    #[test]
+    fn test_fill_value() {
+        assert_eq!(fill_value("100", "2.25"), Ok(MoneyType::new(2_250_000)));
+        assert_eq!(fill_value("0.50", "10.10"), Ok(MoneyType::new(50_500)));
+        assert!(fill_value("0.001", "0.00001").is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::models::money::parse_amount;
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, TransactionID};
+use crate::rejections::{RejectionCode, TRejectionReason};
+use crate::tx_reception::{
+    parse_tx_type, AcknowledgeableTransaction, ProcessingOutcome, RecordParseError,
+    TAcknowledgedStreamProvider, TAcknowledger, TTransactionStreamProvider,
+};
+use crate::FLOATING_POINT_ACC;
+
+/// The messages and service generated from `proto/transactions.proto`
+pub mod proto {
+    tonic::include_proto!("transactioner");
+}
+
+use proto::transaction_ingestion_server::{TransactionIngestion, TransactionIngestionServer};
+use proto::{SubmissionResult, SubmissionResults, SubmissionStatus, TransactionRecord};
+
+/// Provider which receives transactions over gRPC, through the `TransactionIngestion`
+/// service of `proto/transactions.proto`.
+///
+/// Transactions are submitted either one at a time (`SubmitTransaction`) or as a client stream
+/// (`StreamTransactions`). When used as an acknowledged stream, the response is only sent once
+/// every transaction of the call has been processed, and contains the outcome of each of them.
+pub struct GrpcTransactionProvider {
+    listener: TcpListener,
+    capacity: Option<usize>,
+    precision: u32,
+    shutdown: Option<BoxFuture<'static, ()>>,
+}
+
+impl GrpcTransactionProvider {
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            capacity: None,
+            precision: FLOATING_POINT_ACC as u32,
+            shutdown: None,
+        })
+    }
+
+    /// The amount of received transactions which can be waiting to be processed, which is
+    /// unbounded by default. Once they are, the calls wait for the processing to catch up
+    /// before taking more transactions.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity.max(1));
+
+        self
+    }
+
+    /// The amount of decimal places the amounts of the calls are read with, which defaults to
+    /// [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Stop receiving transactions once the given future completes.
+    ///
+    /// The calls already received are still answered, once the transactions they carry are
+    /// processed, and the stream ends after handing out the last of them.
+    pub fn with_shutdown(mut self, signal: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.shutdown = Some(Box::pin(signal));
+
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl TransactionRecord {
+    /// Convert the record into a transaction, failing when it does not describe a valid one.
+    ///
+    /// Administrative transactions are never taken over gRPC.
+    fn into_transaction(self, precision: u32) -> Result<Transaction, RecordParseError> {
+        let client = client_id(self.client, "client")?;
+
+        let to_client = self.to.map(|to| client_id(to, "destination")).transpose()?;
+
+        let amount = self
+            .amount
+            .as_deref()
+            .map(|amount| parse_amount(amount, precision))
+            .transpose()?;
+
+        let mut transaction = Transaction::builder()
+            .with_client_id(client)
+            .with_tx_id(TransactionID(self.tx))
+            .with_tx_type(parse_tx_type(&self.r#type, amount, to_client, false)?)
+            .build();
+
+        if let Some(timestamp) = self.timestamp {
+            transaction.assign_timestamp(timestamp);
+        }
+
+        Ok(transaction)
+    }
+}
+
+/// Client ids are smaller than the integers of the protocol
+fn client_id(id: u32, name: &str) -> Result<ClientID, RecordParseError> {
+    u16::try_from(id)
+        .map(ClientID)
+        .map_err(|_| RecordParseError::MalformedRecord(format!("invalid {} {}", name, id)))
+}
+
+impl SubmissionResult {
+    fn new(tx: u32, status: SubmissionStatus, rejection: Option<RejectionCode>) -> Self {
+        Self {
+            tx,
+            status: status as i32,
+            code: rejection.map(|rejection| rejection.code().to_string()),
+            reason: rejection.map(|rejection| rejection.name().to_string()),
+        }
+    }
+}
+
+/// Acknowledges a transaction back to the call that submitted it
+struct GrpcAcknowledger {
+    responder: oneshot::Sender<ProcessingOutcome>,
+}
+
+impl TAcknowledger for GrpcAcknowledger {
+    fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()> {
+        // The client might have cancelled the call already, in which case there's no one to
+        // tell about the outcome
+        let _ = self.responder.send(outcome);
+
+        Box::pin(async {})
+    }
+}
+
+/// A submitted transaction, either invalid or waiting for the outcome of its processing
+type PendingResult = (
+    u32,
+    Result<oneshot::Receiver<ProcessingOutcome>, RejectionCode>,
+);
+
+/// Hands the submitted transactions to whoever is processing them
+struct IngestionService {
+    tx_sender: flume::Sender<AcknowledgeableTransaction>,
+    precision: u32,
+}
+
+impl IngestionService {
+    async fn submit(&self, record: TransactionRecord) -> Result<PendingResult, Status> {
+        let tx = record.tx;
+
+        let transaction = match record.into_transaction(self.precision) {
+            Ok(transaction) => transaction,
+            Err(err) => return Ok((tx, Err(err.rejection_code()))),
+        };
+
+        let (responder, outcome) = oneshot::channel();
+
+        let acknowledgeable =
+            AcknowledgeableTransaction::new(transaction, Box::new(GrpcAcknowledger { responder }));
+
+        self.tx_sender
+            .send_async(acknowledgeable)
+            .await
+            .map_err(|_| Status::unavailable("No longer receiving transactions"))?;
+
+        Ok((tx, Ok(outcome)))
+    }
+}
+
+/// Wait for the transaction to be processed, if it was valid
+async fn result((tx, outcome): PendingResult) -> SubmissionResult {
+    match outcome {
+        Err(rejection) => SubmissionResult::new(tx, SubmissionStatus::Invalid, Some(rejection)),
+        Ok(outcome) => match outcome.await {
+            Ok(ProcessingOutcome::Accepted) => {
+                SubmissionResult::new(tx, SubmissionStatus::Accepted, None)
+            }
+            Ok(ProcessingOutcome::Deferred) => {
+                SubmissionResult::new(tx, SubmissionStatus::Deferred, None)
+            }
+            Ok(ProcessingOutcome::Rejected(rejection)) => {
+                SubmissionResult::new(tx, SubmissionStatus::Rejected, Some(rejection))
+            }
+            // If the acknowledger was dropped, the transaction was never processed
+            Err(_) => SubmissionResult::new(tx, SubmissionStatus::Rejected, None),
+        },
+    }
+}
+
+#[tonic::async_trait]
+impl TransactionIngestion for IngestionService {
+    async fn submit_transaction(
+        &self,
+        request: Request<TransactionRecord>,
+    ) -> Result<Response<SubmissionResult>, Status> {
+        let pending = self.submit(request.into_inner()).await?;
+
+        Ok(Response::new(result(pending).await))
+    }
+
+    async fn stream_transactions(
+        &self,
+        request: Request<Streaming<TransactionRecord>>,
+    ) -> Result<Response<SubmissionResults>, Status> {
+        let mut records = request.into_inner();
+
+        let mut pending = Vec::new();
+
+        while let Some(record) = records.message().await? {
+            pending.push(self.submit(record).await?);
+        }
+
+        let mut results = Vec::with_capacity(pending.len());
+
+        for pending in pending {
+            results.push(result(pending).await);
+        }
+
+        Ok(Response::new(SubmissionResults { results }))
+    }
+}
+
+impl TAcknowledgedStreamProvider for GrpcTransactionProvider {
+    async fn subscribe_to_acknowledged_stream(
+        self,
+    ) -> BoxStream<'static, AcknowledgeableTransaction> {
+        let (tx_sender, rx) = match self.capacity {
+            Some(capacity) => flume::bounded(capacity),
+            None => flume::unbounded(),
+        };
+
+        let incoming = TcpListenerStream::new(self.listener);
+        let (precision, shutdown) = (self.precision, self.shutdown);
+
+        // The server holds the only senders, so the stream ends once it stops
+        tokio::spawn(async move {
+            let server = tonic::transport::Server::builder().add_service(
+                TransactionIngestionServer::new(IngestionService {
+                    tx_sender,
+                    precision,
+                }),
+            );
+
+            let served = match shutdown {
+                Some(shutdown) => {
+                    server
+                        .serve_with_incoming_shutdown(incoming, shutdown)
+                        .await
+                }
+                None => server.serve_with_incoming(incoming).await,
+            };
+
+            if let Err(err) = served {
+                tracing::error!(error = %err, "gRPC ingestion server failed");
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+impl TTransactionStreamProvider for GrpcTransactionProvider {
+    /// Receive transactions without reporting their outcome.
+    ///
+    /// Transactions are reported as accepted as soon as they are handed out by the stream.
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        self.subscribe_to_acknowledged_stream()
+            .await
+            .then(|acknowledgeable| async move {
+                let (transaction, acknowledger) = acknowledgeable.into_parts();
+
+                acknowledger.acknowledge(ProcessingOutcome::Accepted).await;
+
+                transaction
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod grpc_provider_tests {
+    use futures::StreamExt;
+
+    use crate::models::TransactionID;
+    use crate::rejections::RejectionCode;
+    use crate::tx_reception::grpc::proto::transaction_ingestion_client::TransactionIngestionClient;
+    use crate::tx_reception::grpc::proto::{SubmissionStatus, TransactionRecord};
+    use crate::tx_reception::grpc::GrpcTransactionProvider;
+    use crate::tx_reception::{ProcessingOutcome, TAcknowledgedStreamProvider};
+
+    fn record(tx_type: &str, tx: u32, amount: &str) -> TransactionRecord {
+        TransactionRecord {
+            r#type: tx_type.to_string(),
+            client: 1,
+            tx,
+            amount: Some(amount.to_string()),
+            to: None,
+            timestamp: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submissions() {
+        let provider = GrpcTransactionProvider::bind("127.0.0.1:0").await.unwrap();
+
+        let addr = provider.local_addr().unwrap();
+
+        let mut stream = provider.subscribe_to_acknowledged_stream().await;
+
+        // Act as the processing pipeline, rejecting withdrawals
+        tokio::spawn(async move {
+            while let Some(acknowledgeable) = stream.next().await {
+                let outcome = if acknowledgeable.transaction().transaction_id() == TransactionID(2)
+                {
+                    ProcessingOutcome::Rejected(RejectionCode::InsufficientFunds)
+                } else {
+                    ProcessingOutcome::Accepted
+                };
+
+                acknowledgeable.acknowledge(outcome).await;
+            }
+        });
+
+        let mut client = TransactionIngestionClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+
+        let result = client
+            .submit_transaction(record("deposit", 1, "1.0"))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(result.status, SubmissionStatus::Accepted as i32);
+
+        let results = client
+            .stream_transactions(futures::stream::iter(vec![
+                record("withdrawal", 2, "5.0"),
+                record("deposit", 3, "1.00001"),
+                record("deposit", 4, "1.0"),
+            ]))
+            .await
+            .unwrap()
+            .into_inner()
+            .results;
+
+        let statuses = results
+            .iter()
+            .map(|result| (result.tx, result.status, result.code.as_deref()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            statuses,
+            vec![
+                (2, SubmissionStatus::Rejected as i32, Some("E1001")),
+                (3, SubmissionStatus::Invalid as i32, Some("E0006")),
+                (4, SubmissionStatus::Accepted as i32, None),
+            ]
+        );
+    }
+}
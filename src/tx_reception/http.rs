@@ -0,0 +1,427 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::{Json, Router};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::oneshot;
+#[cfg(feature = "websocket")]
+use tokio::sync::watch;
+
+#[cfg(feature = "websocket")]
+use crate::events::EventBus;
+use crate::models::transactions::Transaction;
+use crate::models::TransactionID;
+use crate::rejections::{RejectionCode, TRejectionReason};
+use crate::tx_reception::json_lines::JsonTransactionRecord;
+#[cfg(feature = "websocket")]
+use crate::tx_reception::websocket;
+use crate::tx_reception::{
+    AcknowledgeableTransaction, ProcessingOutcome, TAcknowledgedStreamProvider, TAcknowledger,
+    TTransactionStreamProvider,
+};
+use crate::FLOATING_POINT_ACC;
+
+/// Provider which receives transactions over HTTP, through `POST /transactions`.
+///
+/// The body is either a single JSON transaction record (same format as the JSON Lines provider)
+/// or an array of them. When used as an acknowledged stream, the response is only sent once every
+/// transaction in the request has been processed, and contains the outcome of each of them.
+///
+/// With the `websocket` feature, the transactions can also be submitted through a WebSocket at
+/// `GET /ws`, one submission per message, which can also be pushed the changes to the balances
+/// published on the bus given to [`with_event_bus`](Self::with_event_bus).
+pub struct HttpTransactionProvider {
+    listener: TcpListener,
+    capacity: Option<usize>,
+    precision: u32,
+    shutdown: Option<BoxFuture<'static, ()>>,
+    #[cfg(feature = "websocket")]
+    events: Option<EventBus>,
+}
+
+/// The body of a transaction submission
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(super) enum TransactionSubmission {
+    Single(JsonTransactionRecord),
+    Batch(Vec<JsonTransactionRecord>),
+}
+
+impl TransactionSubmission {
+    fn into_records(self) -> Vec<JsonTransactionRecord> {
+        match self {
+            TransactionSubmission::Single(record) => vec![record],
+            TransactionSubmission::Batch(records) => records,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SubmissionStatus {
+    Accepted,
+    /// Parked until the transaction it references arrives
+    Deferred,
+    Rejected,
+    Invalid,
+}
+
+#[derive(Serialize)]
+struct SubmissionResult {
+    tx: TransactionID,
+    status: SubmissionStatus,
+    /// The code of the reason why the transaction was not accepted, see [`RejectionCode`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+}
+
+impl SubmissionResult {
+    fn new(tx: TransactionID, status: SubmissionStatus, rejection: Option<RejectionCode>) -> Self {
+        Self {
+            tx,
+            status,
+            code: rejection.map(|rejection| rejection.code()),
+            reason: rejection.map(|rejection| rejection.name()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(super) struct SubmissionResponse {
+    results: Vec<SubmissionResult>,
+}
+
+/// Acknowledges a transaction back to the request that submitted it
+struct HttpAcknowledger {
+    responder: oneshot::Sender<ProcessingOutcome>,
+}
+
+impl TAcknowledger for HttpAcknowledger {
+    fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()> {
+        // The client might have given up on the request already, in which case there's
+        // no one to tell about the outcome
+        let _ = self.responder.send(outcome);
+
+        Box::pin(async {})
+    }
+}
+
+impl HttpTransactionProvider {
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            capacity: None,
+            precision: FLOATING_POINT_ACC as u32,
+            shutdown: None,
+            #[cfg(feature = "websocket")]
+            events: None,
+        })
+    }
+
+    /// The amount of received transactions which can be waiting to be processed, which is
+    /// unbounded by default. Once they are, the submissions wait for the processing to catch
+    /// up before being answered.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity.max(1));
+
+        self
+    }
+
+    /// The amount of decimal places the amounts of the submissions are read with, which
+    /// defaults to [`FLOATING_POINT_ACC`]. The changes to the balances pushed to the sockets are
+    /// written with it as well.
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Stop receiving transactions once the given future completes.
+    ///
+    /// The submissions already received are still answered, once the transactions they
+    /// carry are processed, and the stream ends after handing out the last of them.
+    pub fn with_shutdown(mut self, signal: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.shutdown = Some(Box::pin(signal));
+
+        self
+    }
+
+    /// Push the changes to the balances published on the given bus to the WebSockets which
+    /// subscribe to them
+    #[cfg(feature = "websocket")]
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+/// Hands the submitted transactions over to whoever is processing them
+#[derive(Clone)]
+pub(super) struct Submitter {
+    tx_sender: flume::Sender<AcknowledgeableTransaction>,
+    /// The amount of decimal places the amounts are read with
+    pub(super) precision: u32,
+}
+
+/// Build the router handling the transaction submissions
+fn router(submitter: Submitter) -> Router {
+    Router::new()
+        .route("/transactions", post(submit_transactions))
+        .with_state(submitter)
+}
+
+async fn submit_transactions(
+    State(submitter): State<Submitter>,
+    Json(submission): Json<TransactionSubmission>,
+) -> (StatusCode, Json<SubmissionResponse>) {
+    match submitter.submit(submission).await {
+        Some(response) => (StatusCode::OK, Json(response)),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(SubmissionResponse { results: vec![] }),
+        ),
+    }
+}
+
+impl Submitter {
+    /// Hand the submitted transactions over to be processed, and wait for the outcome of each
+    /// of them. Returns nothing when the transactions are no longer being received.
+    pub(super) async fn submit(
+        &self,
+        submission: TransactionSubmission,
+    ) -> Option<SubmissionResponse> {
+        let records = submission.into_records();
+
+        let mut pending = Vec::with_capacity(records.len());
+
+        for record in records {
+            let tx_id = record.tx;
+
+            let transaction = match record.into_transaction(false, self.precision) {
+                Ok(transaction) => transaction,
+                Err(err) => {
+                    pending.push((tx_id, Err(err.rejection_code())));
+                    continue;
+                }
+            };
+
+            let (responder, outcome) = oneshot::channel();
+
+            let acknowledgeable = AcknowledgeableTransaction::new(
+                transaction,
+                Box::new(HttpAcknowledger { responder }),
+            );
+
+            if self.tx_sender.send_async(acknowledgeable).await.is_err() {
+                return None;
+            }
+
+            pending.push((tx_id, Ok(outcome)));
+        }
+
+        let mut results = Vec::with_capacity(pending.len());
+
+        for (tx, outcome) in pending {
+            let result = match outcome {
+                Err(rejection) => {
+                    SubmissionResult::new(tx, SubmissionStatus::Invalid, Some(rejection))
+                }
+                Ok(outcome) => match outcome.await {
+                    Ok(ProcessingOutcome::Accepted) => {
+                        SubmissionResult::new(tx, SubmissionStatus::Accepted, None)
+                    }
+                    Ok(ProcessingOutcome::Deferred) => {
+                        SubmissionResult::new(tx, SubmissionStatus::Deferred, None)
+                    }
+                    Ok(ProcessingOutcome::Rejected(rejection)) => {
+                        SubmissionResult::new(tx, SubmissionStatus::Rejected, Some(rejection))
+                    }
+                    // If the acknowledger was dropped, the transaction was never processed
+                    Err(_) => SubmissionResult::new(tx, SubmissionStatus::Rejected, None),
+                },
+            };
+
+            results.push(result);
+        }
+
+        Some(SubmissionResponse { results })
+    }
+}
+
+impl TAcknowledgedStreamProvider for HttpTransactionProvider {
+    async fn subscribe_to_acknowledged_stream(
+        self,
+    ) -> BoxStream<'static, AcknowledgeableTransaction> {
+        let (tx_sender, rx) = match self.capacity {
+            Some(capacity) => flume::bounded(capacity),
+            None => flume::unbounded(),
+        };
+
+        let (listener, shutdown) = (self.listener, self.shutdown);
+
+        let submitter = Submitter {
+            tx_sender,
+            precision: self.precision,
+        };
+
+        #[cfg(feature = "websocket")]
+        let (app, shutdown) = {
+            let (stop, stopped) = watch::channel(false);
+
+            let app =
+                router(submitter.clone()).merge(websocket::router(submitter, self.events, stopped));
+
+            // The server doesn't wait for the sockets, so they are told to close on their own
+            let shutdown = shutdown.map(|shutdown| -> BoxFuture<'static, ()> {
+                Box::pin(async move {
+                    shutdown.await;
+
+                    let _ = stop.send(true);
+                })
+            });
+
+            (app, shutdown)
+        };
+
+        #[cfg(not(feature = "websocket"))]
+        let app = router(submitter);
+
+        // The server (and its sockets) hold the only senders, so the stream ends once it stops
+        tokio::spawn(async move {
+            let server = axum::serve(listener, app);
+
+            let served = match shutdown {
+                Some(shutdown) => server.with_graceful_shutdown(shutdown).await,
+                None => server.await,
+            };
+
+            if let Err(err) = served {
+                tracing::error!(error = %err, "HTTP ingestion server failed");
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+impl TTransactionStreamProvider for HttpTransactionProvider {
+    /// Receive transactions without reporting their outcome.
+    ///
+    /// Transactions are reported as accepted as soon as they are handed out by the stream.
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        self.subscribe_to_acknowledged_stream()
+            .await
+            .then(|acknowledgeable| async move {
+                let (transaction, acknowledger) = acknowledgeable.into_parts();
+
+                acknowledger.acknowledge(ProcessingOutcome::Accepted).await;
+
+                transaction
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod http_provider_tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use futures::StreamExt;
+    use tower::ServiceExt;
+
+    use crate::models::TransactionID;
+    use crate::rejections::RejectionCode;
+    use crate::tx_reception::http::{router, HttpTransactionProvider, Submitter};
+    use crate::tx_reception::{
+        AcknowledgeableTransaction, ProcessingOutcome, TAcknowledgedStreamProvider,
+    };
+
+    #[tokio::test]
+    async fn test_batch_submission() {
+        let (tx_sender, rx) = flume::unbounded::<AcknowledgeableTransaction>();
+
+        // Act as the processing pipeline, rejecting withdrawals
+        tokio::spawn(async move {
+            let mut stream = rx.into_stream();
+
+            while let Some(acknowledgeable) = stream.next().await {
+                let outcome = if acknowledgeable.transaction().transaction_id() == TransactionID(2)
+                {
+                    ProcessingOutcome::Rejected(RejectionCode::InsufficientFunds)
+                } else {
+                    ProcessingOutcome::Accepted
+                };
+
+                acknowledgeable.acknowledge(outcome).await;
+            }
+        });
+
+        let body = r#"[
+            {"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"},
+            {"type": "withdrawal", "client": 1, "tx": 2, "amount": "5.0"},
+            {"type": "unknown", "client": 1, "tx": 3}
+        ]"#;
+
+        let response = router(Submitter {
+            tx_sender,
+            precision: 4,
+        })
+        .oneshot(
+            Request::post("/transactions")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            r#"{"results":[{"tx":1,"status":"accepted"},{"tx":2,"status":"rejected","code":"E1001","reason":"InsufficientFunds"},{"tx":3,"status":"invalid","code":"E0002","reason":"UnknownTransactionType"}]}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+        let provider = HttpTransactionProvider::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .with_shutdown(async {
+                let _ = stopped.await;
+            });
+
+        let stream = provider.subscribe_to_acknowledged_stream().await;
+
+        stop.send(()).unwrap();
+
+        // Once stopped, the stream ends instead of waiting for more submissions
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), stream.count())
+            .await
+            .unwrap();
+
+        assert_eq!(received, 0);
+    }
+}
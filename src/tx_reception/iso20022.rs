@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::models::money::parse_amount;
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, TransactionID};
+use crate::rejections::TRejectionReason;
+use crate::tx_reception::{parse_tx_type, RecordParseError, TTransactionStreamProvider};
+use crate::FLOATING_POINT_ACC;
+
+/// The status of the statement entries which were booked to the account, the only ones read
+const BOOKED: &str = "BOOK";
+
+/// Provider reading the transactions of ISO 20022 bank files, either customer credit transfer
+/// initiations (`pain.001`) or bank to customer statements (`camt.053`).
+///
+/// Every credit transfer of a `pain.001` file is a withdrawal from the debtor account, with the
+/// `EndToEndId` of the transfer as the id of the transaction. Every booked entry of a
+/// `camt.053` statement is a deposit to the account of the statement when it is a credit
+/// (`CRDT`), and a withdrawal when it is a debit (`DBIT`), with the `AcctSvcrRef` of the entry
+/// (or its `NtryRef`) as the id of the transaction. Entries which are not booked yet are left
+/// out, as they are only booked (or dropped) by a later statement.
+///
+/// Accounts are identified by their IBAN, or by their other id, and belong to the client they
+/// were mapped to with [`Self::with_account`]. Accounts which were not mapped to a client must
+/// have the id of their client as their id. The references used as transaction ids must be
+/// transaction ids as well, and the entries which can't be read as transactions are skipped.
+pub struct Iso20022TransactionProvider<R> {
+    reader: R,
+    accounts: HashMap<String, ClientID>,
+}
+
+impl<R> Iso20022TransactionProvider<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            accounts: HashMap::new(),
+        }
+    }
+
+    /// Take the transactions of the given account (an IBAN, or another id) as the transactions
+    /// of the given client
+    pub fn with_account(mut self, account: impl Into<String>, client: ClientID) -> Self {
+        self.accounts.insert(account.into(), client);
+
+        self
+    }
+}
+
+impl Iso20022TransactionProvider<File> {
+    /// Read the transactions of the ISO 20022 file at the given path
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
+
+/// The message of an ISO 20022 document, as found under its root `Document` element
+#[derive(Deserialize)]
+struct Document {
+    #[serde(rename = "$value")]
+    message: Message,
+}
+
+#[derive(Deserialize)]
+enum Message {
+    /// `pain.001`
+    #[serde(rename = "CstmrCdtTrfInitn")]
+    CreditTransferInitiation {
+        #[serde(rename = "PmtInf", default)]
+        payments: Vec<PaymentInformation>,
+    },
+    /// `camt.053`
+    #[serde(rename = "BkToCstmrStmt")]
+    Statement {
+        #[serde(rename = "Stmt", default)]
+        statements: Vec<Statement>,
+    },
+}
+
+/// The credit transfers made from a single account
+#[derive(Deserialize)]
+struct PaymentInformation {
+    #[serde(rename = "DbtrAcct")]
+    debtor_account: CashAccount,
+    #[serde(rename = "CdtTrfTxInf", default)]
+    transfers: Vec<CreditTransfer>,
+}
+
+#[derive(Deserialize)]
+struct CreditTransfer {
+    #[serde(rename = "PmtId")]
+    payment_id: PaymentId,
+    #[serde(rename = "Amt")]
+    amount: InstructedAmount,
+}
+
+#[derive(Deserialize)]
+struct PaymentId {
+    #[serde(rename = "EndToEndId")]
+    end_to_end_id: String,
+}
+
+#[derive(Deserialize)]
+struct InstructedAmount {
+    #[serde(rename = "InstdAmt")]
+    instructed: Amount,
+}
+
+/// An amount, whose currency is the one of the account
+#[derive(Deserialize)]
+struct Amount {
+    #[serde(rename = "$text")]
+    value: String,
+}
+
+/// The entries of a single account
+#[derive(Deserialize)]
+struct Statement {
+    #[serde(rename = "Acct")]
+    account: CashAccount,
+    #[serde(rename = "Ntry", default)]
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    #[serde(rename = "NtryRef")]
+    entry_reference: Option<String>,
+    #[serde(rename = "Amt")]
+    amount: Amount,
+    #[serde(rename = "CdtDbtInd")]
+    direction: CreditDebit,
+    #[serde(rename = "Sts")]
+    status: EntryStatus,
+    #[serde(rename = "AcctSvcrRef")]
+    servicer_reference: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq, Eq)]
+enum CreditDebit {
+    #[serde(rename = "CRDT")]
+    Credit,
+    #[serde(rename = "DBIT")]
+    Debit,
+}
+
+/// The status of an entry, which is given as a code of its own since version 8 of `camt.053`
+#[derive(Deserialize)]
+struct EntryStatus {
+    #[serde(rename = "$text")]
+    text: Option<String>,
+    #[serde(rename = "Cd")]
+    code: Option<String>,
+}
+
+impl EntryStatus {
+    fn is_booked(&self) -> bool {
+        self.code
+            .as_deref()
+            .or(self.text.as_deref())
+            .is_some_and(|status| status.trim() == BOOKED)
+    }
+}
+
+#[derive(Deserialize)]
+struct CashAccount {
+    #[serde(rename = "Id")]
+    id: AccountId,
+}
+
+#[derive(Deserialize)]
+struct AccountId {
+    #[serde(rename = "IBAN")]
+    iban: Option<String>,
+    #[serde(rename = "Othr")]
+    other: Option<OtherId>,
+}
+
+#[derive(Deserialize)]
+struct OtherId {
+    #[serde(rename = "Id")]
+    id: String,
+}
+
+impl CashAccount {
+    fn identifier(&self) -> Option<String> {
+        self.id
+            .iban
+            .as_ref()
+            .or(self.id.other.as_ref().map(|other| &other.id))
+            .map(|id| id.trim().to_string())
+    }
+}
+
+/// A movement of the funds of an account, as described by either kind of document
+struct Movement {
+    account: Option<String>,
+    reference: Option<String>,
+    amount: String,
+    credit: bool,
+}
+
+impl Document {
+    /// The movements described by the document, in the order they appear in
+    fn into_movements(self) -> Vec<Movement> {
+        match self.message {
+            Message::CreditTransferInitiation { payments } => payments
+                .into_iter()
+                .flat_map(|payment| {
+                    let account = payment.debtor_account.identifier();
+
+                    payment.transfers.into_iter().map(move |transfer| Movement {
+                        account: account.clone(),
+                        reference: Some(transfer.payment_id.end_to_end_id),
+                        amount: transfer.amount.instructed.value,
+                        credit: false,
+                    })
+                })
+                .collect(),
+            Message::Statement { statements } => statements
+                .into_iter()
+                .flat_map(|statement| {
+                    let account = statement.account.identifier();
+
+                    statement
+                        .entries
+                        .into_iter()
+                        .filter(|entry| entry.status.is_booked())
+                        .map(move |entry| Movement {
+                            account: account.clone(),
+                            reference: entry.servicer_reference.or(entry.entry_reference),
+                            amount: entry.amount.value,
+                            credit: entry.direction == CreditDebit::Credit,
+                        })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Movement {
+    fn into_transaction(
+        self,
+        accounts: &HashMap<String, ClientID>,
+    ) -> Result<Transaction, RecordParseError> {
+        let account = self
+            .account
+            .ok_or_else(|| RecordParseError::MalformedRecord("missing the account".to_string()))?;
+
+        let client = match accounts.get(&account) {
+            Some(client) => *client,
+            None => account.parse().map_err(|_| {
+                RecordParseError::MalformedRecord(format!("unknown account {:?}", account))
+            })?,
+        };
+
+        let reference = self.reference.unwrap_or_default();
+
+        let tx_id: TransactionID = reference.trim().parse().map_err(|_| {
+            RecordParseError::MalformedRecord(format!("invalid transaction {:?}", reference))
+        })?;
+
+        let amount = parse_amount(&self.amount, FLOATING_POINT_ACC as u32)?;
+
+        let tx_type = if self.credit { "deposit" } else { "withdrawal" };
+
+        Ok(Transaction::builder()
+            .with_client_id(client)
+            .with_tx_id(tx_id)
+            .with_tx_type(parse_tx_type(tx_type, Some(amount), None, false)?)
+            .build())
+    }
+}
+
+impl<R> TTransactionStreamProvider for Iso20022TransactionProvider<R>
+where
+    R: Read + Send + 'static,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let (tx_sender, rx) = flume::unbounded();
+
+        // The document is parsed as a whole, which is blocking, so it is read in a blocking task
+        tokio::task::spawn_blocking(move || {
+            let document =
+                match quick_xml::de::from_reader::<_, Document>(BufReader::new(self.reader)) {
+                    Ok(document) => document,
+                    Err(err) => {
+                        tracing::error!(error = %err, "Failed to read the ISO 20022 input");
+                        return;
+                    }
+                };
+
+            for (index, movement) in document.into_movements().into_iter().enumerate() {
+                let tx = match movement.into_transaction(&self.accounts) {
+                    Ok(tx) => tx,
+                    Err(err) => {
+                        tracing::warn!(
+                            entry = index + 1,
+                            code = err.rejection_code().code(),
+                            error = %err,
+                            "Skipping the entry"
+                        );
+                        continue;
+                    }
+                };
+
+                if tx_sender.send(tx).is_err() {
+                    // The stream has been dropped, no one is listening anymore
+                    break;
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+#[cfg(test)]
+mod iso20022_test {
+    use futures::StreamExt;
+
+    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::tx_reception::iso20022::Iso20022TransactionProvider;
+    use crate::tx_reception::TTransactionStreamProvider;
+
+    async fn read(provider: Iso20022TransactionProvider<&'static [u8]>) -> Vec<Transaction> {
+        provider
+            .subscribe_to_tx_stream()
+            .await
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_statement_entries() {
+        const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <GrpHdr><MsgId>STMT-1</MsgId></GrpHdr>
+    <Stmt>
+      <Id>1</Id>
+      <Acct><Id><IBAN>PT50000201231234567890154</IBAN></Id></Acct>
+      <Ntry>
+        <NtryRef>1</NtryRef>
+        <Amt Ccy="EUR">150.25</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">20.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <AcctSvcrRef>2</AcctSvcrRef>
+      </Ntry>
+      <Ntry>
+        <NtryRef>3</NtryRef>
+        <Amt Ccy="EUR">5.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>PDNG</Sts>
+      </Ntry>
+      <Ntry>
+        <NtryRef>REF-4</NtryRef>
+        <Amt Ccy="EUR">5.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+        let txs = read(
+            Iso20022TransactionProvider::new(STATEMENT.as_bytes())
+                .with_account("PT50000201231234567890154", ClientID(7)),
+        )
+        .await;
+
+        // The pending entry is left out, and the one without a numeric reference is skipped
+        assert_eq!(txs.len(), 2);
+
+        assert_eq!(txs[0].client(), ClientID(7));
+        assert_eq!(txs[0].transaction_id(), TransactionID(1));
+        assert!(matches!(
+            txs[0].tx_type(),
+            TransactionType::Deposit { amount, .. } if *amount == MoneyType::new(1_502_500)
+        ));
+        assert_eq!(txs[1].transaction_id(), TransactionID(2));
+        assert!(matches!(
+            txs[1].tx_type(),
+            TransactionType::Withdrawal { amount, .. } if *amount == MoneyType::new(200_000)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_credit_transfers() {
+        const TRANSFERS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.001.001.03">
+  <CstmrCdtTrfInitn>
+    <GrpHdr><MsgId>PAY-1</MsgId><NbOfTxs>2</NbOfTxs></GrpHdr>
+    <PmtInf>
+      <PmtInfId>1</PmtInfId>
+      <PmtMtd>TRF</PmtMtd>
+      <DbtrAcct><Id><Othr><Id>3</Id></Othr></Id></DbtrAcct>
+      <CdtTrfTxInf>
+        <PmtId><EndToEndId>10</EndToEndId></PmtId>
+        <Amt><InstdAmt Ccy="EUR">12.5</InstdAmt></Amt>
+      </CdtTrfTxInf>
+      <CdtTrfTxInf>
+        <PmtId><EndToEndId>11</EndToEndId></PmtId>
+        <Amt><InstdAmt Ccy="EUR">1.00</InstdAmt></Amt>
+      </CdtTrfTxInf>
+    </PmtInf>
+  </CstmrCdtTrfInitn>
+</Document>"#;
+
+        let txs = read(Iso20022TransactionProvider::new(TRANSFERS.as_bytes())).await;
+
+        assert_eq!(txs.len(), 2);
+
+        // Accounts which were not mapped are taken as the id of their client
+        assert!(txs.iter().all(|tx| tx.client() == ClientID(3)));
+        assert_eq!(txs[1].transaction_id(), TransactionID(11));
+        assert!(matches!(
+            txs[0].tx_type(),
+            TransactionType::Withdrawal { amount, .. } if *amount == MoneyType::new(125_000)
+        ));
+    }
+}
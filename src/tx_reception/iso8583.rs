@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use thiserror::Error;
+
+use crate::models::money::parse_amount;
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, MoneyType, TransactionID};
+use crate::rejections::TRejectionReason;
+use crate::tx_reception::{parse_tx_type, RecordParseError, TTransactionStreamProvider};
+use crate::FLOATING_POINT_ACC;
+
+const PRIMARY_ACCOUNT_NUMBER: usize = 2;
+const PROCESSING_CODE: usize = 3;
+const AMOUNT: usize = 4;
+const STAN: usize = 11;
+const ORIGINAL_DATA_ELEMENTS: usize = 90;
+const ACCOUNT_IDENTIFICATION: usize = 102;
+
+/// The decimal places of the amounts, as most currencies have
+const DEFAULT_MINOR_UNITS: u32 = 2;
+
+#[derive(Error, Debug)]
+pub enum Iso8583Error {
+    #[error("Failed to read the message {0}")]
+    IoError(#[from] io::Error),
+    #[error("The message is malformed: {0}")]
+    MalformedMessage(String),
+}
+
+/// How the value of a data element is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldFormat {
+    Fixed(usize),
+    /// Preceded by its length, in 2 digits
+    LlVar,
+    /// Preceded by its length, in 3 digits
+    LllVar,
+}
+
+/// The format of each data element, as defined by ISO 8583:1987, with the binary fields (the
+/// PIN and the MACs) written in hexadecimal
+fn field_format(field: usize) -> FieldFormat {
+    match field {
+        2 | 32..=35 | 44 | 45 | 99..=103 => FieldFormat::LlVar,
+        36 | 46..=48 | 54..=63 | 104..=127 => FieldFormat::LllVar,
+        3 | 11 | 12 | 38 | 73 => FieldFormat::Fixed(6),
+        4..=6 | 37 | 82..=85 => FieldFormat::Fixed(12),
+        7 | 74..=81 => FieldFormat::Fixed(10),
+        8..=10 | 41 => FieldFormat::Fixed(8),
+        13..=18 | 71 | 72 => FieldFormat::Fixed(4),
+        19..=24 | 40 | 49..=51 | 68..=70 => FieldFormat::Fixed(3),
+        25 | 26 | 39 | 67 | 92 => FieldFormat::Fixed(2),
+        27 | 65 | 66 | 91 => FieldFormat::Fixed(1),
+        28..=31 => FieldFormat::Fixed(9),
+        42 => FieldFormat::Fixed(15),
+        43 => FieldFormat::Fixed(40),
+        52 | 53 | 64 | 86..=89 | 96 | 128 => FieldFormat::Fixed(16),
+        90 | 95 => FieldFormat::Fixed(42),
+        93 => FieldFormat::Fixed(5),
+        94 => FieldFormat::Fixed(7),
+        97 => FieldFormat::Fixed(17),
+        98 => FieldFormat::Fixed(25),
+        _ => unreachable!("ISO 8583 only has data elements 2 to 128"),
+    }
+}
+
+/// An ISO 8583 message, with its data elements by their number
+struct Iso8583Message {
+    mti: String,
+    fields: HashMap<usize, String>,
+}
+
+impl Iso8583Message {
+    /// Parse a message with its MTI and bitmaps in ASCII (the bitmaps in hexadecimal)
+    fn parse(message: &[u8]) -> Result<Self, Iso8583Error> {
+        let message = std::str::from_utf8(message)
+            .map_err(|_| Iso8583Error::MalformedMessage("not ASCII".to_string()))?;
+
+        let mut rest = message;
+
+        let mut take = |length: usize, what: &str| {
+            if rest.len() < length || !rest.is_char_boundary(length) {
+                return Err(Iso8583Error::MalformedMessage(format!(
+                    "truncated {}",
+                    what
+                )));
+            }
+
+            let (taken, remaining) = rest.split_at(length);
+
+            rest = remaining;
+
+            Ok(taken)
+        };
+
+        let mti = take(4, "MTI")?.to_string();
+
+        let bitmap = |hex: &str| {
+            u64::from_str_radix(hex, 16)
+                .map_err(|_| Iso8583Error::MalformedMessage(format!("invalid bitmap {:?}", hex)))
+        };
+
+        let primary = bitmap(take(16, "bitmap")?)?;
+
+        // The first bit tells whether there is a secondary bitmap
+        let secondary = if primary & (1 << 63) != 0 {
+            bitmap(take(16, "secondary bitmap")?)?
+        } else {
+            0
+        };
+
+        let mut fields = HashMap::new();
+
+        for field in 2..=128 {
+            let (present, bit) = if field <= 64 {
+                (primary, field - 1)
+            } else {
+                (secondary, field - 65)
+            };
+
+            if present & (1 << (63 - bit)) == 0 {
+                continue;
+            }
+
+            let length = match field_format(field) {
+                FieldFormat::Fixed(length) => length,
+                variable => {
+                    let digits = if variable == FieldFormat::LlVar { 2 } else { 3 };
+
+                    let length = take(digits, "length")?;
+
+                    length.parse().map_err(|_| {
+                        Iso8583Error::MalformedMessage(format!(
+                            "invalid length {:?} of field {}",
+                            length, field
+                        ))
+                    })?
+                }
+            };
+
+            fields.insert(field, take(length, "field")?.to_string());
+        }
+
+        Ok(Self { mti, fields })
+    }
+
+    fn field(&self, field: usize) -> Option<&str> {
+        self.fields.get(&field).map(|value| value.trim())
+    }
+
+    fn required(&self, field: usize) -> Result<&str, RecordParseError> {
+        self.field(field).ok_or_else(|| {
+            RecordParseError::MalformedRecord(format!("missing the field {}", field))
+        })
+    }
+
+    /// The id of the transaction the message refers to, which is the system trace audit
+    /// number of the original message when it has one, and its own otherwise
+    fn original_tx_id(&self) -> Result<TransactionID, RecordParseError> {
+        // The MTI of the original message is followed by its STAN
+        match self
+            .field(ORIGINAL_DATA_ELEMENTS)
+            .and_then(|original| original.get(4..10))
+        {
+            Some(stan) => tx_id(stan),
+            None => self.tx_id(),
+        }
+    }
+
+    fn tx_id(&self) -> Result<TransactionID, RecordParseError> {
+        tx_id(self.required(STAN)?)
+    }
+}
+
+fn tx_id(stan: &str) -> Result<TransactionID, RecordParseError> {
+    stan.parse()
+        .map_err(|_| RecordParseError::MalformedRecord(format!("invalid transaction {:?}", stan)))
+}
+
+/// Provider reading ISO 8583 messages, such as the ones sent by a card switch, each preceded
+/// by its length in 2 bytes (big-endian).
+///
+/// Messages are read as ISO 8583:1987 with the MTI, the bitmaps and every data element
+/// written in ASCII, and mapped to transactions depending on their MTI:
+///
+/// - Authorization requests (`0100`) authorize the amount of the message.
+/// - Financial requests (`0200`) are withdrawals of purchases and cash withdrawals
+///   (processing codes `00`, `01` and `09`), and deposits of refunds and deposits (`20` and
+///   `21`).
+/// - Financial advices (`0220`) capture the authorization they complete.
+/// - Chargebacks (`0422` and `0442`) charge back the transaction they refer to, which must
+///   have been disputed.
+///
+/// Messages are identified by their system trace audit number (field 11), and the ones
+/// referring to an earlier message by the one of that message, in their original data elements
+/// (field 90). The amounts (field 4) are in minor units. Cards are mapped to clients with
+/// [`Self::with_account`] by their primary account number (field 2) or, when the message has
+/// one, by its account identification (field 102), and the accounts which were not mapped must
+/// be the id of their client. Every other message (such as responses, reversals or network
+/// management messages) is left out.
+pub struct Iso8583TransactionProvider<R> {
+    reader: R,
+    accounts: HashMap<String, ClientID>,
+    minor_units: u32,
+}
+
+impl<R> Iso8583TransactionProvider<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            accounts: HashMap::new(),
+            minor_units: DEFAULT_MINOR_UNITS,
+        }
+    }
+
+    /// Take the messages of the given card or account as transactions of the given client
+    pub fn with_account(mut self, account: impl Into<String>, client: ClientID) -> Self {
+        self.accounts.insert(account.into(), client);
+
+        self
+    }
+
+    /// The decimal places of the currency of the amounts, which defaults to 2
+    pub fn with_minor_units(mut self, minor_units: u32) -> Self {
+        self.minor_units = minor_units;
+
+        self
+    }
+}
+
+/// Maps the messages into transactions
+struct MessageMapper {
+    accounts: HashMap<String, ClientID>,
+    minor_units: u32,
+}
+
+impl MessageMapper {
+    /// The transaction described by the message, if it describes one
+    fn map(&self, message: &Iso8583Message) -> Option<Result<Transaction, RecordParseError>> {
+        let tx_type = match message.mti.as_str() {
+            "0100" => "authorize",
+            "0200" => match message
+                .field(PROCESSING_CODE)
+                .and_then(|code| code.get(..2))
+            {
+                Some("00" | "01" | "09") => "withdrawal",
+                Some("20" | "21") => "deposit",
+                code => {
+                    return Some(Err(RecordParseError::MalformedRecord(format!(
+                        "unsupported processing code {:?}",
+                        code.unwrap_or_default()
+                    ))))
+                }
+            },
+            "0220" => "capture",
+            "0422" | "0442" => "chargeback",
+            _ => return None,
+        };
+
+        Some(self.transaction(message, tx_type))
+    }
+
+    fn transaction(
+        &self,
+        message: &Iso8583Message,
+        tx_type: &str,
+    ) -> Result<Transaction, RecordParseError> {
+        let account = match message.field(ACCOUNT_IDENTIFICATION) {
+            Some(account) => account,
+            None => message.required(PRIMARY_ACCOUNT_NUMBER)?,
+        };
+
+        let client = match self.accounts.get(account) {
+            Some(client) => *client,
+            None => account.parse().map_err(|_| {
+                RecordParseError::MalformedRecord(format!("unknown account {:?}", account))
+            })?,
+        };
+
+        let (tx_id, amount) = match tx_type {
+            "capture" | "chargeback" => (message.original_tx_id()?, None),
+            _ => (
+                message.tx_id()?,
+                Some(self.amount(message.required(AMOUNT)?)?),
+            ),
+        };
+
+        Ok(Transaction::builder()
+            .with_client_id(client)
+            .with_tx_id(tx_id)
+            .with_tx_type(parse_tx_type(tx_type, amount, None, false)?)
+            .build())
+    }
+
+    /// Read an amount in minor units
+    fn amount(&self, minor: &str) -> Result<MoneyType, RecordParseError> {
+        let places = self.minor_units as usize;
+
+        if minor.is_empty() || !minor.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(RecordParseError::MalformedRecord(format!(
+                "invalid amount {:?}",
+                minor
+            )));
+        }
+
+        let minor = format!("{:0>width$}", minor, width = places + 1);
+
+        let (whole, fraction) = minor.split_at(minor.len() - places);
+
+        Ok(parse_amount(
+            &format!("{}.{}", whole, fraction),
+            FLOATING_POINT_ACC as u32,
+        )?)
+    }
+}
+
+/// Read the next message, returning none once the input ends between messages
+fn read_message(reader: &mut impl Read) -> Result<Option<Vec<u8>>, Iso8583Error> {
+    let mut header = [0; 2];
+
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut message = vec![0; u16::from_be_bytes(header) as usize];
+
+    reader.read_exact(&mut message)?;
+
+    Ok(Some(message))
+}
+
+impl<R> TTransactionStreamProvider for Iso8583TransactionProvider<R>
+where
+    R: Read + Send + 'static,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let (tx_sender, rx) = flume::unbounded();
+
+        let mut reader = self.reader;
+
+        let mapper = MessageMapper {
+            accounts: self.accounts,
+            minor_units: self.minor_units,
+        };
+
+        // Same as with the JSON Lines provider, the reading is blocking so we do it in a
+        // blocking task and propagate the transactions through a channel
+        tokio::task::spawn_blocking(move || {
+            for message_number in 1.. {
+                let message = match read_message(&mut reader) {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(err) => {
+                        tracing::error!(error = %err, "Failed to read the ISO 8583 input");
+                        break;
+                    }
+                };
+
+                let tx = match Iso8583Message::parse(&message) {
+                    Ok(message) => match mapper.map(&message) {
+                        Some(tx) => tx,
+                        None => continue,
+                    },
+                    Err(err) => Err(RecordParseError::MalformedRecord(err.to_string())),
+                };
+
+                let tx = match tx {
+                    Ok(tx) => tx,
+                    Err(err) => {
+                        tracing::warn!(
+                            message = message_number,
+                            code = err.rejection_code().code(),
+                            error = %err,
+                            "Skipping the message"
+                        );
+                        continue;
+                    }
+                };
+
+                if tx_sender.send(tx).is_err() {
+                    // The stream has been dropped, no one is listening anymore
+                    break;
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+#[cfg(test)]
+mod iso8583_test {
+    use std::io::Cursor;
+
+    use futures::StreamExt;
+
+    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::tx_reception::iso8583::Iso8583TransactionProvider;
+    use crate::tx_reception::TTransactionStreamProvider;
+
+    /// Build a message with the given data elements, framed by its length
+    fn message(mti: &str, fields: &[(usize, &str)]) -> Vec<u8> {
+        let mut bitmap = 0u128;
+        let mut body = String::new();
+
+        for (field, value) in fields {
+            bitmap |= 1 << (128 - field);
+
+            // The variable length fields the tests use
+            match *field {
+                2 | 102 => body.push_str(&format!("{:02}{}", value.len(), value)),
+                _ => body.push_str(value),
+            }
+        }
+
+        let bitmap = if bitmap as u64 != 0 {
+            format!("{:032X}", bitmap | 1 << 127)
+        } else {
+            format!("{:016X}", bitmap >> 64)
+        };
+
+        let message = format!("{}{}{}", mti, bitmap, body);
+
+        let mut framed = (message.len() as u16).to_be_bytes().to_vec();
+
+        framed.extend(message.as_bytes());
+
+        framed
+    }
+
+    #[tokio::test]
+    async fn test_iso8583_reader() {
+        let input = [
+            // Network management, left out
+            message("0800", &[(11, "000001")]),
+            message(
+                "0100",
+                &[(2, "4111111111111111"), (4, "000000001050"), (11, "000002")],
+            ),
+            message("0110", &[(2, "4111111111111111"), (11, "000002")]),
+            message(
+                "0220",
+                &[
+                    (2, "4111111111111111"),
+                    (11, "000003"),
+                    (90, &format!("0100000002{:032}", 0)),
+                ],
+            ),
+            message(
+                "0200",
+                &[
+                    (3, "200000"),
+                    (4, "000000000500"),
+                    (11, "000004"),
+                    (102, "2"),
+                ],
+            ),
+            // Balance inquiries are not transactions
+            message("0200", &[(3, "310000"), (11, "000005"), (102, "2")]),
+        ]
+        .concat();
+
+        let provider = Iso8583TransactionProvider::new(Cursor::new(input))
+            .with_account("4111111111111111", ClientID(1));
+
+        let txs = provider
+            .subscribe_to_tx_stream()
+            .await
+            .collect::<Vec<Transaction>>()
+            .await;
+
+        assert_eq!(txs.len(), 3);
+
+        assert_eq!(txs[0].client(), ClientID(1));
+        assert_eq!(txs[0].transaction_id(), TransactionID(2));
+        assert!(matches!(
+            txs[0].tx_type(),
+            TransactionType::Authorize { amount, .. } if *amount == MoneyType::new(105_000)
+        ));
+        assert_eq!(txs[1].transaction_id(), TransactionID(2));
+        assert!(matches!(txs[1].tx_type(), TransactionType::Capture));
+        assert_eq!(txs[2].client(), ClientID(2));
+        assert!(matches!(
+            txs[2].tx_type(),
+            TransactionType::Deposit { amount, .. } if *amount == MoneyType::new(50_000)
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::models::money::DecimalAmount;
+use crate::models::transactions::Transaction;
+use crate::models::{ClientID, Timestamp, TransactionID};
+use crate::rejections::{RejectionCode, TRejectionReason};
+use crate::tx_reception::{parse_tx_type, RecordParseError, TTransactionStreamProvider};
+use crate::FLOATING_POINT_ACC;
+
+/// Provider for newline delimited JSON (NDJSON) transactions.
+///
+/// Each line is a JSON object with the same fields as the CSV format, for example
+/// `{"type": "deposit", "client": 1, "tx": 1, "amount": "1.5"}`.
+/// Amounts can be given either as strings or numbers, but must be exactly representable
+/// with the precision they are read with.
+pub struct JsonLinesTransactionProvider<R> {
+    reader: R,
+    admin_source: bool,
+    precision: u32,
+}
+
+/// A single JSON transaction record, as found in each line of the NDJSON input
+#[derive(Deserialize)]
+pub(crate) struct JsonTransactionRecord {
+    #[serde(rename = "type")]
+    tx_type: String,
+    client: ClientID,
+    pub(crate) tx: TransactionID,
+    #[serde(default)]
+    amount: Option<DecimalAmount>,
+    /// The client receiving the funds of a transfer
+    #[serde(default)]
+    to: Option<ClientID>,
+    #[serde(default)]
+    timestamp: Option<Timestamp>,
+}
+
+impl JsonTransactionRecord {
+    /// Convert the record into a transaction.
+    ///
+    /// Fails when the record does not describe a valid transaction type, when its amount
+    /// doesn't fit the given precision, or when it is an administrative transaction and the
+    /// source isn't an admin source
+    pub(crate) fn into_transaction(
+        self,
+        admin_source: bool,
+        precision: u32,
+    ) -> Result<Transaction, RecordParseError> {
+        // A blank amount is as missing as an absent one, as an empty CSV field is
+        let amount = self
+            .amount
+            .filter(|amount| !amount.is_blank())
+            .map(|amount| amount.parse(precision))
+            .transpose()?;
+
+        let tx_type = parse_tx_type(&self.tx_type, amount, self.to, admin_source)?;
+
+        let mut transaction = Transaction::builder()
+            .with_client_id(self.client)
+            .with_tx_id(self.tx)
+            .with_tx_type(tx_type)
+            .build();
+
+        if let Some(timestamp) = self.timestamp {
+            transaction.assign_timestamp(timestamp);
+        }
+
+        Ok(transaction)
+    }
+}
+
+impl<R> JsonLinesTransactionProvider<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            admin_source: false,
+            precision: FLOATING_POINT_ACC as u32,
+        }
+    }
+
+    /// The amount of decimal places of the amounts, which defaults to [`FLOATING_POINT_ACC`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Trust the input with administrative transactions (e.g. unfreezing accounts), which are
+    /// rejected otherwise
+    pub fn with_admin_source(mut self, admin_source: bool) -> Self {
+        self.admin_source = admin_source;
+
+        self
+    }
+}
+
+impl<R> TTransactionStreamProvider for JsonLinesTransactionProvider<R>
+where
+    R: Read + Send + 'static,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let (tx_sender, rx) = flume::unbounded();
+
+        // Same as with the CSV provider, the reading is blocking so we do it in a
+        // blocking task and propagate the transactions through a channel
+        tokio::task::spawn_blocking(move || {
+            let reader = BufReader::new(self.reader);
+
+            for (line_number, line) in reader.lines().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        tracing::error!(error = %err, "Failed to read transaction input");
+                        break;
+                    }
+                };
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let record = match serde_json::from_str::<JsonTransactionRecord>(&line) {
+                    Ok(record) => record,
+                    Err(err) => {
+                        tracing::warn!(
+                            line = line_number + 1,
+                            code = RejectionCode::MalformedRecord.code(),
+                            error = %err,
+                            "Skipping the malformed line"
+                        );
+                        continue;
+                    }
+                };
+
+                let tx = match record.into_transaction(self.admin_source, self.precision) {
+                    Ok(tx) => tx,
+                    Err(err) => {
+                        tracing::warn!(
+                            line = line_number + 1,
+                            code = err.rejection_code().code(),
+                            error = %err,
+                            "Skipping the line"
+                        );
+                        continue;
+                    }
+                };
+
+                if tx_sender.send(tx).is_err() {
+                    // The stream has been dropped, no one is listening anymore
+                    break;
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+/// Fails when the file can't be opened
+impl TryFrom<PathBuf> for JsonLinesTransactionProvider<File> {
+    type Error = std::io::Error;
+
+    fn try_from(file: PathBuf) -> Result<Self, Self::Error> {
+        Ok(JsonLinesTransactionProvider::new(File::open(file)?))
+    }
+}
+
+#[cfg(test)]
+mod json_lines_test {
+    use futures::StreamExt;
+
+    use std::path::PathBuf;
+
+    use crate::models::transactions::{Transaction, TransactionType};
+    use crate::models::{MoneyType, TransactionID};
+    use crate::tx_reception::json_lines::{JsonLinesTransactionProvider, JsonTransactionRecord};
+    use crate::tx_reception::{RecordParseError, TTransactionStreamProvider};
+
+    #[tokio::test]
+    async fn test_json_lines_reader() {
+        const JSON_DATA: &str = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "1.5"}
+{"type": "withdrawal", "client": 1, "tx": 2, "amount": 0.25}
+
+not json at all
+{"type": "dispute", "client": 1, "tx": 1}
+{"type": "deposit", "client": 1, "tx": 3}
+"#;
+
+        let provider = JsonLinesTransactionProvider::new(JSON_DATA.as_bytes());
+
+        let txs = provider
+            .subscribe_to_tx_stream()
+            .await
+            .collect::<Vec<Transaction>>()
+            .await;
+
+        assert_eq!(txs.len(), 3);
+
+        assert!(matches!(
+            txs[0].tx_type(),
+            TransactionType::Deposit { amount, .. } if *amount == MoneyType::new(15000)
+        ));
+        assert!(matches!(
+            txs[1].tx_type(),
+            TransactionType::Withdrawal { amount, .. } if *amount == MoneyType::new(2500)
+        ));
+        assert!(matches!(txs[2].tx_type(), TransactionType::Dispute));
+        assert_eq!(txs[2].transaction_id(), TransactionID(1));
+    }
+
+    #[tokio::test]
+    async fn test_json_lines_precision() {
+        const JSON_DATA: &str = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "1.5"}
+{"type": "deposit", "client": 1, "tx": 2, "amount": 0.125}
+"#;
+
+        let txs = JsonLinesTransactionProvider::new(JSON_DATA.as_bytes())
+            .with_precision(2)
+            .subscribe_to_tx_stream()
+            .await
+            .collect::<Vec<Transaction>>()
+            .await;
+
+        // The second amount has more decimal places than the precision
+        assert_eq!(txs.len(), 1);
+        assert!(matches!(
+            txs[0].tx_type(),
+            TransactionType::Deposit { amount, .. } if *amount == MoneyType::new(150)
+        ));
+    }
+
+    #[test]
+    fn test_json_lines_missing_amount() {
+        for line in [
+            r#"{"type": "deposit", "client": 1, "tx": 1}"#,
+            r#"{"type": "deposit", "client": 1, "tx": 1, "amount": null}"#,
+            r#"{"type": "deposit", "client": 1, "tx": 1, "amount": " "}"#,
+        ] {
+            let record = serde_json::from_str::<JsonTransactionRecord>(line).unwrap();
+
+            assert!(
+                matches!(
+                    record.into_transaction(false, 4),
+                    Err(RecordParseError::MissingAmount)
+                ),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_json_lines_missing_file() {
+        let missing = PathBuf::from("/nonexistent/transactions.ndjson");
+
+        assert!(JsonLinesTransactionProvider::try_from(missing).is_err());
+    }
+}
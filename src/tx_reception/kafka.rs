@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::{Message, Offset, TopicPartitionList};
+use thiserror::Error;
+
+use crate::models::transactions::Transaction;
+use crate::rejections::TRejectionReason;
+use crate::secrets::{SecretsError, TSecretsProvider};
+use crate::tx_reception::json_lines::JsonTransactionRecord;
+use crate::tx_reception::{
+    AcknowledgeableTransaction, ProcessingOutcome, RecordParseError, TAcknowledgedStreamProvider,
+    TAcknowledger, TTransactionStreamProvider,
+};
+use crate::FLOATING_POINT_ACC;
+
+/// Provider consuming transactions from a Kafka topic.
+///
+/// The payload of each message is one transaction, written as a line of the JSON Lines input
+/// would be. Offsets are never committed automatically: when used as an acknowledged stream the
+/// offset of each message is committed once the transaction has been acknowledged, so a
+/// restart will resume from the first transaction which was not processed.
+///
+/// A transaction rejected because of a failure of the storage (`E3xxx`) is not committed, and
+/// its partition is read again from it after [`RETRY_DELAY`]. The offsets of the messages after
+/// it in the partition are held back until then, so a restart doesn't skip it either, and the
+/// ones which were already processed are rejected as duplicates when read again.
+pub struct KafkaTransactionProvider {
+    consumer: Arc<StreamConsumer>,
+    capacity: usize,
+}
+
+/// The amount of received messages which can be waiting to be processed by default
+pub const DEFAULT_KAFKA_CHANNEL_CAPACITY: usize = 1024;
+
+/// How long to wait before reading a partition again from a message whose transaction could not
+/// be processed because of a failure of the storage
+pub const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The first and the longest wait before receiving again after the brokers failed to deliver a
+/// message, doubling between the two on every failure in a row
+const MIN_RECEIVE_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RECEIVE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long seeking back to a message may block the consumer
+const SEEK_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl KafkaTransactionProvider {
+    /// Create a provider consuming the given topic as part of the given consumer group
+    pub fn new(brokers: &str, group_id: &str, topic: &str) -> Result<Self, KafkaError> {
+        let mut config = ClientConfig::new();
+
+        config
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group_id)
+            .set("auto.offset.reset", "earliest");
+
+        Self::from_config(config, topic)
+    }
+
+    /// Create a provider for brokers which require authentication, with the SASL credentials
+    /// read from the `kafka_username` and `kafka_password` secrets.
+    ///
+    /// The security protocol and SASL mechanism are not secret, so they are expected to be
+    /// set in the given configuration.
+    pub async fn with_credentials(
+        mut config: ClientConfig,
+        topic: &str,
+        secrets: &impl TSecretsProvider,
+    ) -> Result<Self, KafkaCredentialsError> {
+        config
+            .set(
+                "sasl.username",
+                secrets.required_secret("kafka_username").await?,
+            )
+            .set(
+                "sasl.password",
+                secrets.required_secret("kafka_password").await?,
+            );
+
+        Ok(Self::from_config(config, topic)?)
+    }
+
+    /// Create a provider from a custom consumer configuration.
+    ///
+    /// Automatic offset commits are always disabled, as we commit after processing.
+    pub fn from_config(mut config: ClientConfig, topic: &str) -> Result<Self, KafkaError> {
+        let consumer: StreamConsumer = config.set("enable.auto.commit", "false").create()?;
+
+        consumer.subscribe(&[topic])?;
+
+        Ok(Self {
+            consumer: Arc::new(consumer),
+            capacity: DEFAULT_KAFKA_CHANNEL_CAPACITY,
+        })
+    }
+
+    /// The amount of received messages which can be waiting to be processed, which defaults to
+    /// [`DEFAULT_KAFKA_CHANNEL_CAPACITY`]. Once they are, the consumer stops receiving until
+    /// the processing catches up.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+
+        self
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum KafkaCredentialsError {
+    #[error("Failed to load the broker credentials {0:?}")]
+    SecretsError(#[from] SecretsError),
+    #[error("Kafka error {0:?}")]
+    KafkaError(#[from] KafkaError),
+}
+
+/// What is done with a message once its transaction was processed
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    /// Its offset is committed
+    Commit,
+    /// Its partition is read again from it after the given delay
+    Retry(Duration),
+    /// Its offset isn't committed, as a message before it in its partition is read again
+    Hold,
+}
+
+/// The offset each partition is read again from, as the transaction of the message there could
+/// not be processed yet
+#[derive(Default)]
+struct RetryOffsets {
+    partitions: HashMap<(String, i32), i64>,
+}
+
+impl RetryOffsets {
+    fn reply(
+        &mut self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+        outcome: ProcessingOutcome,
+    ) -> Reply {
+        let key = (topic.to_string(), partition);
+        let retry_from = self.partitions.get(&key).copied();
+
+        match outcome {
+            ProcessingOutcome::Rejected(code) if code.is_retryable() => match retry_from {
+                // Read again along with the earlier message
+                Some(from) if from < offset => Reply::Hold,
+                _ => {
+                    self.partitions.insert(key, offset);
+
+                    Reply::Retry(RETRY_DELAY)
+                }
+            },
+            // Any other rejection would happen again, so the message is done with as well
+            _ => match retry_from {
+                Some(from) if from < offset => Reply::Hold,
+                Some(from) if from == offset => {
+                    self.partitions.remove(&key);
+
+                    Reply::Commit
+                }
+                _ => Reply::Commit,
+            },
+        }
+    }
+}
+
+/// Commits the offset of a single message once acknowledged
+struct KafkaAcknowledger {
+    consumer: Arc<StreamConsumer>,
+    retries: Arc<Mutex<RetryOffsets>>,
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+impl TAcknowledger for KafkaAcknowledger {
+    fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            let reply = self.retries.lock().unwrap().reply(
+                &self.topic,
+                self.partition,
+                self.offset,
+                outcome,
+            );
+
+            match reply {
+                Reply::Commit => self.commit(),
+                Reply::Retry(delay) => {
+                    // Not awaited, so the processing of the other partitions carries on
+                    tokio::spawn(async move {
+                        tokio::time::sleep(delay).await;
+
+                        self.seek();
+                    });
+                }
+                Reply::Hold => {}
+            }
+        })
+    }
+}
+
+impl KafkaAcknowledger {
+    fn commit(&self) {
+        let mut partitions = TopicPartitionList::new();
+
+        let result = partitions
+            .add_partition_offset(&self.topic, self.partition, Offset::Offset(self.offset + 1))
+            .and_then(|_| self.consumer.commit(&partitions, CommitMode::Async));
+
+        if let Err(err) = result {
+            tracing::error!(
+                topic = %self.topic,
+                partition = self.partition,
+                offset = self.offset,
+                error = %err,
+                "Failed to commit the offset of a message"
+            );
+        }
+    }
+
+    fn seek(&self) {
+        let result = self.consumer.seek(
+            &self.topic,
+            self.partition,
+            Offset::Offset(self.offset),
+            SEEK_TIMEOUT,
+        );
+
+        // Its offset is still held back, so it is read again after a restart instead
+        if let Err(err) = result {
+            tracing::error!(
+                topic = %self.topic,
+                partition = self.partition,
+                offset = self.offset,
+                error = %err,
+                "Failed to read a partition again from a message which could not be processed"
+            );
+        }
+    }
+}
+
+impl TAcknowledgedStreamProvider for KafkaTransactionProvider {
+    async fn subscribe_to_acknowledged_stream(
+        self,
+    ) -> BoxStream<'static, AcknowledgeableTransaction> {
+        let (tx_sender, rx) = flume::bounded(self.capacity);
+
+        let consumer = self.consumer;
+        let retries = Arc::new(Mutex::new(RetryOffsets::default()));
+
+        tokio::spawn(async move {
+            let mut backoff = MIN_RECEIVE_BACKOFF;
+
+            loop {
+                let message = match consumer.recv().await {
+                    Ok(message) => {
+                        backoff = MIN_RECEIVE_BACKOFF;
+
+                        message
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            error = %err,
+                            retry_in = ?backoff,
+                            "Failed to receive a message from Kafka"
+                        );
+
+                        tokio::time::sleep(backoff).await;
+
+                        backoff = (backoff * 2).min(MAX_RECEIVE_BACKOFF);
+                        continue;
+                    }
+                };
+
+                let acknowledger = Box::new(KafkaAcknowledger {
+                    consumer: consumer.clone(),
+                    retries: retries.clone(),
+                    topic: message.topic().to_string(),
+                    partition: message.partition(),
+                    offset: message.offset(),
+                });
+
+                let transaction = serde_json::from_slice::<JsonTransactionRecord>(
+                    message.payload().unwrap_or_default(),
+                )
+                .map_err(|err| RecordParseError::MalformedRecord(err.to_string()))
+                // Administrative transactions are never taken from the brokers
+                .and_then(|record| record.into_transaction(false, FLOATING_POINT_ACC as u32));
+
+                let transaction = match transaction {
+                    Ok(transaction) => transaction,
+                    Err(err) => {
+                        tracing::warn!(
+                            topic = message.topic(),
+                            partition = message.partition(),
+                            offset = message.offset(),
+                            code = err.rejection_code().code(),
+                            error = %err,
+                            "Skipping the malformed message"
+                        );
+
+                        // There's nothing to retry with a malformed message, so we move past it
+                        acknowledger
+                            .acknowledge(ProcessingOutcome::Rejected(err.rejection_code()))
+                            .await;
+                        continue;
+                    }
+                };
+
+                if tx_sender
+                    .send_async(AcknowledgeableTransaction::new(transaction, acknowledger))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+impl TTransactionStreamProvider for KafkaTransactionProvider {
+    /// Consume the topic without explicit acknowledgements.
+    ///
+    /// Each offset is committed as soon as the transaction is handed out by the stream.
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        self.subscribe_to_acknowledged_stream()
+            .await
+            .then(|acknowledgeable| async move {
+                let (transaction, acknowledger) = acknowledgeable.into_parts();
+
+                acknowledger.acknowledge(ProcessingOutcome::Accepted).await;
+
+                transaction
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod kafka_test {
+    use crate::rejections::RejectionCode;
+    use crate::tx_reception::kafka::{Reply, RetryOffsets, RETRY_DELAY};
+    use crate::tx_reception::ProcessingOutcome;
+
+    const TOPIC: &str = "transactions";
+
+    #[test]
+    fn test_processed_messages_committed() {
+        let mut retries = RetryOffsets::default();
+
+        assert_eq!(
+            retries.reply(TOPIC, 0, 1, ProcessingOutcome::Accepted),
+            Reply::Commit
+        );
+
+        // Processing it again would reject it again
+        assert_eq!(
+            retries.reply(
+                TOPIC,
+                0,
+                2,
+                ProcessingOutcome::Rejected(RejectionCode::InsufficientFunds)
+            ),
+            Reply::Commit
+        );
+    }
+
+    #[test]
+    fn test_retryable_rejections_read_again() {
+        let mut retries = RetryOffsets::default();
+
+        assert_eq!(
+            retries.reply(
+                TOPIC,
+                0,
+                3,
+                ProcessingOutcome::Rejected(RejectionCode::DatabaseFailure)
+            ),
+            Reply::Retry(RETRY_DELAY)
+        );
+
+        // The messages after it in the partition aren't committed until it is processed
+        assert_eq!(
+            retries.reply(TOPIC, 0, 4, ProcessingOutcome::Accepted),
+            Reply::Hold
+        );
+        assert_eq!(
+            retries.reply(
+                TOPIC,
+                0,
+                5,
+                ProcessingOutcome::Rejected(RejectionCode::StorageFailure)
+            ),
+            Reply::Hold
+        );
+
+        // While the other partitions carry on
+        assert_eq!(
+            retries.reply(TOPIC, 1, 4, ProcessingOutcome::Accepted),
+            Reply::Commit
+        );
+
+        // Once read again and processed, so are the ones after it
+        assert_eq!(
+            retries.reply(TOPIC, 0, 3, ProcessingOutcome::Accepted),
+            Reply::Commit
+        );
+        assert_eq!(
+            retries.reply(
+                TOPIC,
+                0,
+                4,
+                ProcessingOutcome::Rejected(RejectionCode::DuplicateTransaction)
+            ),
+            Reply::Commit
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use std::pin::Pin;
+
+use futures::stream::{self, BoxStream, Peekable};
+use futures::StreamExt;
+
+use crate::models::transactions::Transaction;
+use crate::tx_reception::TTransactionStreamProvider;
+
+/// How the transactions of several inputs are put into a single stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergeOrder {
+    /// Every transaction of an input, followed by those of the next one
+    #[default]
+    Concatenated,
+    /// The transactions of all of the inputs by timestamp, given each input is already sorted
+    /// by it. Transactions without a timestamp are taken as soon as they are reached, and ties
+    /// go to the input given first.
+    Timestamp,
+}
+
+/// Provider reading the transactions of several inputs (e.g. the files a day of transactions
+/// is split into) as a single stream.
+///
+/// Only the next transaction of each input is held at a time, so merging the inputs by
+/// timestamp doesn't read them any further ahead than concatenating them does.
+pub struct MergedTransactionProvider<P> {
+    inputs: Vec<P>,
+    order: MergeOrder,
+}
+
+impl<P> MergedTransactionProvider<P> {
+    pub fn new(inputs: Vec<P>) -> Self {
+        Self {
+            inputs,
+            order: MergeOrder::default(),
+        }
+    }
+
+    /// Choose how the transactions of the inputs are ordered, which defaults to
+    /// [`MergeOrder::Concatenated`]
+    pub fn with_order(mut self, order: MergeOrder) -> Self {
+        self.order = order;
+
+        self
+    }
+
+    /// The providers of the inputs, in the order they were given, so they can be configured
+    /// (e.g. to subscribe to their row errors) before they are merged
+    pub fn inputs_mut(&mut self) -> impl Iterator<Item = &mut P> {
+        self.inputs.iter_mut()
+    }
+}
+
+impl<P> TTransactionStreamProvider for MergedTransactionProvider<P>
+where
+    P: TTransactionStreamProvider,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let mut streams = Vec::with_capacity(self.inputs.len());
+
+        for input in self.inputs {
+            streams.push(input.subscribe_to_tx_stream().await);
+        }
+
+        match self.order {
+            MergeOrder::Concatenated => stream::iter(streams).flatten().boxed(),
+            MergeOrder::Timestamp => merge_by_timestamp(streams),
+        }
+    }
+}
+
+fn merge_by_timestamp(
+    streams: Vec<BoxStream<'static, Transaction>>,
+) -> BoxStream<'static, Transaction> {
+    let inputs: Vec<Peekable<BoxStream<'static, Transaction>>> =
+        streams.into_iter().map(StreamExt::peekable).collect();
+
+    stream::unfold(inputs, |mut inputs| async move {
+        let mut earliest = None;
+
+        for (index, input) in inputs.iter_mut().enumerate() {
+            let Some(timestamp) = Pin::new(input).peek().await.map(|tx| tx.timestamp()) else {
+                continue;
+            };
+
+            // Strictly earlier, so ties go to the input given first
+            if earliest.is_none_or(|(_, earliest)| earliest > timestamp) {
+                earliest = Some((index, timestamp));
+            }
+        }
+
+        let (index, _) = earliest?;
+
+        let tx = inputs[index].next().await?;
+
+        Some((tx, inputs))
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod merge_test {
+    use futures::StreamExt;
+
+    use crate::models::TransactionID;
+    use crate::tx_reception::merge::{MergeOrder, MergedTransactionProvider};
+    use crate::tx_reception::{CSVTransactionProvider, TTransactionStreamProvider};
+
+    fn inputs() -> Vec<CSVTransactionProvider<&'static [u8]>> {
+        vec![
+            CSVTransactionProvider::new(
+                "type, client, tx, amount, to, timestamp\n\
+                  deposit, 1, 1, 1.0, , 100\n\
+                  deposit, 1, 2, 1.0, , 300\n\
+                  deposit, 1, 3, 1.0, , 300\n"
+                    .as_bytes(),
+            ),
+            CSVTransactionProvider::new(
+                "type, client, tx, amount, to, timestamp\n\
+                  deposit, 2, 4, 1.0, , 200\n\
+                  deposit, 2, 5, 1.0, , 300\n\
+                  deposit, 2, 6, 1.0, , 400\n"
+                    .as_bytes(),
+            ),
+            CSVTransactionProvider::new(
+                "type, client, tx, amount\n deposit, 3, 7, 1.0\n".as_bytes(),
+            ),
+        ]
+    }
+
+    async fn merged_ids(order: MergeOrder) -> Vec<u32> {
+        MergedTransactionProvider::new(inputs())
+            .with_order(order)
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| {
+                let TransactionID(id) = tx.transaction_id();
+
+                id
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_concatenated_inputs() {
+        assert_eq!(
+            merged_ids(MergeOrder::Concatenated).await,
+            vec![1, 2, 3, 4, 5, 6, 7]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inputs_merged_by_timestamp() {
+        // The transaction without a timestamp is taken as soon as it is reached, and the ones
+        // at 300 keep the order of their inputs
+        assert_eq!(
+            merged_ids(MergeOrder::Timestamp).await,
+            vec![7, 1, 4, 2, 3, 5, 6]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Counts what the providers read, so the progress of a long input can be followed while it
+/// is being read.
+///
+/// Clones share the same counters, so one is handed to the provider (e.g. with
+/// [`CSVTransactionProvider::with_metrics`](crate::tx_reception::CSVTransactionProvider::with_metrics))
+/// and the other is looked at with [`Self::stats`]. The bytes are counted by the readers
+/// wrapped with [`Self::count_bytes`], as the provider might be given a decompressed input
+/// whose size is unknown, while the size of the file it is decompressed from is not.
+#[derive(Debug, Clone)]
+pub struct ReaderMetrics {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug)]
+struct Counters {
+    rows_read: AtomicU64,
+    bytes_read: AtomicU64,
+    parse_failures: AtomicU64,
+    started: Instant,
+}
+
+impl Default for ReaderMetrics {
+    fn default() -> Self {
+        Self {
+            counters: Arc::new(Counters {
+                rows_read: AtomicU64::new(0),
+                bytes_read: AtomicU64::new(0),
+                parse_failures: AtomicU64::new(0),
+                started: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl ReaderMetrics {
+    /// Count the bytes read from the given reader
+    pub fn count_bytes<R>(&self, reader: R) -> CountingReader<R> {
+        CountingReader {
+            reader,
+            metrics: self.clone(),
+        }
+    }
+
+    /// What was read since the metrics were created
+    pub fn stats(&self) -> ReaderStats {
+        ReaderStats {
+            rows_read: self.counters.rows_read.load(Ordering::Relaxed),
+            bytes_read: self.counters.bytes_read.load(Ordering::Relaxed),
+            parse_failures: self.counters.parse_failures.load(Ordering::Relaxed),
+            elapsed: self.counters.started.elapsed(),
+        }
+    }
+
+    pub(crate) fn record_row(&self) {
+        self.counters.rows_read.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failure(&self) {
+        self.counters.parse_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_bytes(&self, bytes: usize) {
+        self.counters
+            .bytes_read
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// The counters of a [`ReaderMetrics`] at some point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReaderStats {
+    /// Every row read, including the invalid ones
+    pub rows_read: u64,
+    pub bytes_read: u64,
+    /// The rows which could not be read as transactions
+    pub parse_failures: u64,
+    pub elapsed: Duration,
+}
+
+impl ReaderStats {
+    /// The average amount of rows read per second
+    pub fn rows_per_second(&self) -> f64 {
+        per_second(self.rows_read, self.elapsed)
+    }
+
+    /// The average amount of bytes read per second
+    pub fn bytes_per_second(&self) -> f64 {
+        per_second(self.bytes_read, self.elapsed)
+    }
+}
+
+fn per_second(count: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+
+    count as f64 / elapsed.as_secs_f64()
+}
+
+/// A reader which counts the bytes read through it, see [`ReaderMetrics::count_bytes`]
+pub struct CountingReader<R> {
+    reader: R,
+    metrics: ReaderMetrics,
+}
+
+impl<R> AsyncRead for CountingReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        let before = buf.filled().len();
+
+        let poll = Pin::new(&mut this.reader).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = poll {
+            this.metrics.record_bytes(buf.filled().len() - before);
+        }
+
+        poll
+    }
+}
+
+impl<R> io::Read for CountingReader<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+
+        self.metrics.record_bytes(read);
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use futures::StreamExt;
+
+    use crate::tx_reception::metrics::ReaderMetrics;
+    use crate::tx_reception::{CSVTransactionProvider, CsvErrorMode, TTransactionStreamProvider};
+
+    #[tokio::test]
+    async fn test_reader_metrics() {
+        const CSV_DATA: &str = "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,one
+withdrawal,1,3,0.5
+";
+
+        let metrics = ReaderMetrics::default();
+
+        let read = CSVTransactionProvider::new(metrics.count_bytes(CSV_DATA.as_bytes()))
+            .with_error_mode(CsvErrorMode::Lenient)
+            .with_metrics(metrics.clone())
+            .subscribe_to_tx_stream()
+            .await
+            .count()
+            .await;
+
+        assert_eq!(read, 2);
+
+        let stats = metrics.stats();
+
+        assert_eq!(stats.rows_read, 3);
+        assert_eq!(stats.parse_failures, 1);
+        assert_eq!(stats.bytes_read, CSV_DATA.len() as u64);
+    }
+}
@@ -1,19 +1,70 @@
-use std::fs::File;
+use std::collections::HashMap;
+#[cfg(feature = "blocking-csv")]
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use futures::future::BoxFuture;
 use futures::stream::BoxStream;
-use futures::StreamExt;
+use futures::{stream, StreamExt};
+use thiserror::Error;
+use tokio::io::AsyncRead;
 
-use crate::models::transactions::{Transaction, TransactionType};
-use crate::models::{ClientID, MoneyType, TransactionID};
+use crate::models::money::{AmountFormat, AmountParseError, PrecisionPolicy};
+use crate::models::transactions::{AuthorizationState, DisputeState, Transaction, TransactionType};
+use crate::models::{ClientID, MoneyType, Timestamp, TransactionID};
+use crate::rejections::{RejectionCode, TRejectionReason};
+use crate::services::transaction_service::Processed;
+use crate::tx_reception::metrics::ReaderMetrics;
+use crate::warnings::{TWarningSink, Warning};
 use crate::FLOATING_POINT_ACC;
 
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod filter;
+#[cfg(feature = "fix")]
+pub mod fix;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "iso20022")]
+pub mod iso20022;
+#[cfg(feature = "iso8583")]
+pub mod iso8583;
+#[cfg(feature = "json")]
+pub mod json_lines;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod merge;
+pub mod metrics;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "json")]
+pub mod queue;
+#[cfg(feature = "remote-input")]
+pub mod remote;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "sqs")]
+pub mod sqs;
+pub mod throttle;
+#[cfg(feature = "watch")]
+pub mod watch;
+#[cfg(feature = "websocket")]
+mod websocket;
+
 /// Transaction stream provider.
 /// This should return a stream with all transactions that we want to process.
 ///
 ///TODO: Should we support various providers, or a given provider being allowed
 /// to return multiple streams?
+// The providers are driven by whoever subscribes to them, so we don't require their futures to
+// be `Send`
+#[allow(async_fn_in_trait)]
 pub trait TTransactionStreamProvider {
     /// Subscribe to a transaction stream.
     ///
@@ -25,64 +76,578 @@ pub trait TTransactionStreamProvider {
     async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction>;
 }
 
+/// Provider for sources which need to be told when a transaction has been processed,
+/// such as message brokers which only commit their position after processing succeeds.
+#[allow(async_fn_in_trait)]
+pub trait TAcknowledgedStreamProvider {
+    /// Subscribe to a transaction stream where every transaction must be acknowledged,
+    /// through [`AcknowledgeableTransaction::acknowledge`], once it has been processed.
+    async fn subscribe_to_acknowledged_stream(
+        self,
+    ) -> BoxStream<'static, AcknowledgeableTransaction>;
+}
+
+/// The outcome of processing a transaction, reported back to the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingOutcome {
+    Accepted,
+    /// Parked until the transaction it references arrives, without any effect so far. The
+    /// engine keeps it from then on, so the sources are done with it as with an accepted one.
+    Deferred,
+    Rejected(RejectionCode),
+}
+
+impl From<Processed> for ProcessingOutcome {
+    fn from(processed: Processed) -> Self {
+        match processed {
+            Processed::Deferred => ProcessingOutcome::Deferred,
+            Processed::Applied | Processed::Quarantined | Processed::Ignored => {
+                ProcessingOutcome::Accepted
+            }
+        }
+    }
+}
+
+/// Acknowledges a single transaction back to the source it came from
+pub trait TAcknowledger: Send {
+    fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()>;
+}
+
+/// A transaction which has to be acknowledged back to its source after being processed
+pub struct AcknowledgeableTransaction {
+    transaction: Transaction,
+    acknowledger: Box<dyn TAcknowledger>,
+}
+
+impl AcknowledgeableTransaction {
+    pub fn new(transaction: Transaction, acknowledger: Box<dyn TAcknowledger>) -> Self {
+        Self {
+            transaction,
+            acknowledger,
+        }
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    /// Split the transaction from its acknowledger, so the transaction can be handed to the
+    /// service and acknowledged afterwards
+    pub fn into_parts(self) -> (Transaction, Box<dyn TAcknowledger>) {
+        (self.transaction, self.acknowledger)
+    }
+
+    pub async fn acknowledge(self, outcome: ProcessingOutcome) {
+        self.acknowledger.acknowledge(outcome).await
+    }
+}
+
+/// Provider reading the transactions from CSV input.
+///
+/// The input is read asynchronously, one row at a time as the transactions are consumed, so a
+/// slow consumer slows down the reading instead of having the transactions pile up in memory.
+/// Synchronous readers are supported through [`CSVTransactionProvider::blocking`], with the
+/// `blocking-csv` feature.
 pub struct CSVTransactionProvider<R> {
     file: R,
+    options: CsvReadOptions,
+}
+
+/// How the rows of the CSV input are turned into transactions, regardless of how they are
+/// read, so the same options can be given to every provider of CSV rows (e.g. the objects of a
+/// bucket or the files of a watched directory).
+///
+/// The Parquet inputs are read by the name of their columns, so they ignore the schema.
+#[derive(Clone)]
+pub struct CsvReadOptions {
+    schema: CsvSchema,
+    precision: u32,
+    error_mode: CsvErrorMode,
+    row_errors: Option<flume::Sender<RowError>>,
+    warnings: Option<Arc<dyn TWarningSink>>,
+    ingestion_timestamps: bool,
+    admin_source: bool,
+    amount_format: AmountFormat,
+    precision_policy: PrecisionPolicy,
+    unknown_types: UnknownTypes,
+    metrics: Option<ReaderMetrics>,
+}
+
+impl Default for CsvReadOptions {
+    fn default() -> Self {
+        Self {
+            schema: CsvSchema::default(),
+            precision: FLOATING_POINT_ACC as u32,
+            error_mode: CsvErrorMode::default(),
+            row_errors: None,
+            warnings: None,
+            ingestion_timestamps: false,
+            admin_source: false,
+            amount_format: AmountFormat::default(),
+            precision_policy: PrecisionPolicy::default(),
+            unknown_types: UnknownTypes::default(),
+            metrics: None,
+        }
+    }
+}
+
+impl CsvReadOptions {
+    /// Read the input with the given layout, instead of the one of the specification
+    pub fn with_schema(mut self, schema: CsvSchema) -> Self {
+        self.schema = schema;
+
+        self
+    }
+
+    /// Choose whether to carry on reading the input after finding an invalid row
+    pub fn with_error_mode(mut self, error_mode: CsvErrorMode) -> Self {
+        self.error_mode = error_mode;
+
+        self
+    }
+
+    /// Choose what is done with the rows of unknown transaction types
+    pub fn with_unknown_types(mut self, unknown_types: UnknownTypes) -> Self {
+        self.unknown_types = unknown_types;
+
+        self
+    }
+
+    /// The amount of decimal places of the amounts, which defaults to [`FLOATING_POINT_ACC`].
+    ///
+    /// Amounts are read exactly, without going through floating point, and those with more
+    /// decimal places are handled as [`Self::with_precision_policy`] says. This must match the
+    /// precision the rest of the system (e.g. the state exporter) uses for the amounts.
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Timestamp the transactions which don't have a `timestamp` column with the time they
+    /// were read at
+    pub fn with_ingestion_timestamps(mut self, ingestion_timestamps: bool) -> Self {
+        self.ingestion_timestamps = ingestion_timestamps;
+
+        self
+    }
+
+    /// Trust the input with administrative transactions (e.g. unfreezing accounts), which are
+    /// rejected otherwise
+    pub fn with_admin_source(mut self, admin_source: bool) -> Self {
+        self.admin_source = admin_source;
+
+        self
+    }
+
+    /// Choose what is done with the amounts which have more decimal places than the precision.
+    ///
+    /// They are truncated by default, and reported to the warning sink when they are truncated
+    /// or rounded. With [`PrecisionPolicy::Reject`] their rows are invalid instead, and
+    /// reported as row errors with [`RejectionCode::ExcessAmountPrecision`].
+    pub fn with_precision_policy(mut self, precision_policy: PrecisionPolicy) -> Self {
+        self.precision_policy = precision_policy;
+
+        self
+    }
+
+    /// Report the amounts which had to be truncated or rounded to the supported precision
+    pub fn with_warning_sink(mut self, sink: impl TWarningSink + 'static) -> Self {
+        self.warnings = Some(Arc::new(sink));
+
+        self
+    }
+
+    /// Read the amounts written with the separators of another locale (e.g. `1.234,56`)
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.amount_format = amount_format;
+
+        self
+    }
+
+    /// Count the rows read, and those which could not be read as transactions, in the given
+    /// metrics. The bytes are counted by the reader given to the provider, when it is wrapped
+    /// with [`ReaderMetrics::count_bytes`].
+    pub fn with_metrics(mut self, metrics: ReaderMetrics) -> Self {
+        self.metrics = Some(metrics);
+
+        self
+    }
+
+    /// Send the rows which could not be read as transactions to a new stream, which ends once
+    /// every holder of these options is gone
+    fn subscribe_to_row_errors(&mut self) -> BoxStream<'static, RowError> {
+        let (sender, rx) = flume::unbounded();
+
+        self.row_errors = Some(sender);
+
+        rx.into_stream().boxed()
+    }
+}
+
+impl<R> CSVTransactionProvider<R> {
+    pub fn new(file: R) -> Self {
+        Self {
+            file,
+            options: CsvReadOptions::default(),
+        }
+    }
+
+    /// Read the input with the given options, replacing every one chosen so far. The row
+    /// errors have to be subscribed to afterwards.
+    pub fn with_options(mut self, options: CsvReadOptions) -> Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Read the input with the given layout, instead of the one of the specification
+    pub fn with_schema(mut self, schema: CsvSchema) -> Self {
+        self.options = self.options.with_schema(schema);
+
+        self
+    }
+
+    /// Choose whether to carry on reading the input after finding an invalid row
+    pub fn with_error_mode(mut self, error_mode: CsvErrorMode) -> Self {
+        self.options = self.options.with_error_mode(error_mode);
+
+        self
+    }
+
+    /// Choose what is done with the rows of unknown transaction types
+    pub fn with_unknown_types(mut self, unknown_types: UnknownTypes) -> Self {
+        self.options = self.options.with_unknown_types(unknown_types);
+
+        self
+    }
+
+    /// Receive the rows which could not be read as transactions, instead of having them
+    /// printed to the standard error.
+    ///
+    /// The stream ends once the whole input has been read.
+    pub fn subscribe_to_row_errors(&mut self) -> BoxStream<'static, RowError> {
+        self.options.subscribe_to_row_errors()
+    }
+
+    /// The amount of decimal places of the amounts, as [`CsvReadOptions::with_precision`]
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.options = self.options.with_precision(precision);
+
+        self
+    }
+
+    /// Timestamp the transactions which don't have a `timestamp` column with the time they
+    /// were read at
+    pub fn with_ingestion_timestamps(mut self, ingestion_timestamps: bool) -> Self {
+        self.options = self.options.with_ingestion_timestamps(ingestion_timestamps);
+
+        self
+    }
+
+    /// Trust the input with administrative transactions (e.g. unfreezing accounts), which are
+    /// rejected otherwise
+    pub fn with_admin_source(mut self, admin_source: bool) -> Self {
+        self.options = self.options.with_admin_source(admin_source);
+
+        self
+    }
+
+    /// Choose what is done with the amounts which have more decimal places than the
+    /// precision, as [`CsvReadOptions::with_precision_policy`]
+    pub fn with_precision_policy(mut self, precision_policy: PrecisionPolicy) -> Self {
+        self.options = self.options.with_precision_policy(precision_policy);
+
+        self
+    }
+
+    /// Report the amounts which had to be truncated or rounded to the supported precision
+    pub fn with_warning_sink(mut self, sink: impl TWarningSink + 'static) -> Self {
+        self.options = self.options.with_warning_sink(sink);
+
+        self
+    }
+
+    /// Read the amounts written with the separators of another locale (e.g. `1.234,56`)
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.options = self.options.with_amount_format(amount_format);
+
+        self
+    }
+
+    /// Count the rows read, and those which could not be read as transactions, in the given
+    /// metrics, as [`CsvReadOptions::with_metrics`]
+    pub fn with_metrics(mut self, metrics: ReaderMetrics) -> Self {
+        self.options = self.options.with_metrics(metrics);
+
+        self
+    }
+}
+
+impl CsvReadOptions {
+    /// Turn the fields of a row into a transaction, reporting the warnings it raised
+    fn parse_row(
+        &self,
+        row: u64,
+        fields: &[&str],
+        columns: &CsvColumns,
+    ) -> Result<Transaction, RowError> {
+        let (mut tx, warning) = parse_csv_record(
+            fields,
+            columns,
+            self.precision,
+            &self.amount_format,
+            self.precision_policy,
+            self.admin_source,
+        )
+        .map_err(|err| RowError::new(row, fields.join(","), err))?;
+
+        if self.ingestion_timestamps && tx.timestamp().is_none() {
+            tx.assign_timestamp(current_timestamp());
+        }
+
+        if let (Some(warnings), Some(warning)) = (&self.warnings, warning) {
+            warnings.warn(&warning);
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_row();
+        }
+
+        Ok(tx)
+    }
+
+    /// Report a row which is not a valid transaction, returning whether to carry on reading
+    fn report(&self, row_error: RowError) -> bool {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_row();
+            metrics.record_failure();
+        }
+
+        let unknown_type = matches!(row_error.error, RecordParseError::UnknownTransactionType(_));
+
+        if unknown_type && self.unknown_types == UnknownTypes::Skip {
+            tracing::warn!(
+                row = row_error.row,
+                code = row_error.error.rejection_code().code(),
+                error = %row_error.error,
+                "Skipping the row of an unknown type"
+            );
+
+            return true;
+        }
+
+        match &self.row_errors {
+            Some(row_errors) => {
+                let _ = row_errors.send(row_error);
+            }
+            None => tracing::warn!(
+                row = row_error.row,
+                record = %row_error.record,
+                code = row_error.error.rejection_code().code(),
+                error = %row_error.error,
+                "Invalid row"
+            ),
+        }
+
+        if unknown_type && self.unknown_types == UnknownTypes::Abort {
+            return false;
+        }
+
+        self.error_mode == CsvErrorMode::Lenient
+    }
 }
 
 impl<R> TTransactionStreamProvider for CSVTransactionProvider<R>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let schema = &self.options.schema;
+
+        let mut csv_reader = csv_async::AsyncReaderBuilder::new()
+            .delimiter(schema.delimiter)
+            .has_headers(schema.has_headers)
+            .flexible(true)
+            .trim(csv_async::Trim::All)
+            .create_reader(self.file);
+
+        let headers = if schema.has_headers {
+            match csv_reader.headers().await {
+                Ok(headers) => headers.clone(),
+                Err(err) => {
+                    // The columns can't be told apart without the headers
+                    self.options
+                        .report(RowError::new(1, String::new(), err.into()));
+
+                    return stream::empty().boxed();
+                }
+            }
+        } else {
+            csv_async::StringRecord::new()
+        };
+
+        let columns = match schema.columns(&headers) {
+            Ok(columns) => columns,
+            Err(err) => {
+                // None of the rows can be read without their columns, whatever the error mode
+                self.options.report(RowError::new(
+                    1,
+                    headers.iter().collect::<Vec<_>>().join(","),
+                    err,
+                ));
+
+                return stream::empty().boxed();
+            }
+        };
+
+        let records = csv_reader.into_records();
+
+        // Every row is only read once the previous transaction has been consumed
+        stream::unfold(
+            (records, self.options),
+            move |(mut records, options)| async move {
+                while let Some(record) = records.next().await {
+                    let parsed = record
+                        .map_err(|err| {
+                            let row = err.position().map_or(0, |position| position.line());
+
+                            RowError::new(row, String::new(), RecordParseError::from(err))
+                        })
+                        .and_then(|record| {
+                            let row = record.position().map_or(0, |position| position.line());
+
+                            options.parse_row(row, &record.iter().collect::<Vec<_>>(), &columns)
+                        });
+
+                    match parsed {
+                        Ok(tx) => return Some((tx, (records, options))),
+                        Err(row_error) => {
+                            if !options.report(row_error) {
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                None
+            },
+        )
+        .boxed()
+    }
+}
+
+/// A synchronous reader of CSV input, for the sources which can't be read asynchronously
+#[cfg(feature = "blocking-csv")]
+pub struct BlockingReader<R> {
+    reader: R,
+    capacity: usize,
+}
+
+/// The amount of transactions read ahead of the consumer by the blocking CSV reader, which
+/// bounds the memory used when the transactions are processed slower than they are read
+#[cfg(feature = "blocking-csv")]
+pub const DEFAULT_CSV_CHANNEL_CAPACITY: usize = 1024;
+
+#[cfg(feature = "blocking-csv")]
+impl<R> CSVTransactionProvider<BlockingReader<R>> {
+    /// Read the transactions from a synchronous reader, on a thread of its own, so this
+    /// doesn't depend on tokio
+    pub fn blocking(reader: R) -> Self {
+        Self::new(BlockingReader {
+            reader,
+            capacity: DEFAULT_CSV_CHANNEL_CAPACITY,
+        })
+    }
+
+    /// The amount of transactions which can be read ahead of the consumer, which defaults to
+    /// [`DEFAULT_CSV_CHANNEL_CAPACITY`]. Once they are, the reading waits for the consumer to
+    /// catch up.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.file.capacity = capacity;
+
+        self
+    }
+}
+
+#[cfg(feature = "blocking-csv")]
+impl<R> TTransactionStreamProvider for CSVTransactionProvider<BlockingReader<R>>
 where
     R: Read + Send + 'static,
 {
     async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
-        let (tx_sender, rx) = flume::unbounded();
+        let (BlockingReader { reader, capacity }, options) = (self.file, self.options);
+
+        let (tx_sender, rx) = flume::bounded(capacity);
+
+        // The reader blocks, so it is read on its own thread, which sends the transactions
+        // through a flume channel, which will be used to create a stream. Sending blocks while
+        // the channel is full, so the reading never gets too far ahead of the consumer.
+        std::thread::spawn(move || {
+            let schema = &options.schema;
 
-        // Launch a blocking task responsible for reading the CSV file.
-        // This will read from the file and send the transactions through a flume
-        // Channel, which will be used to create a stream.
-        tokio::task::spawn_blocking(move || {
-            // Construct the csv reader from the file reader
             let mut csv_reader = csv::ReaderBuilder::new()
-                .has_headers(true)
+                .delimiter(schema.delimiter)
+                .has_headers(schema.has_headers)
+                .flexible(true)
                 .trim(csv::Trim::All)
-                .from_reader(self.file);
-
-            for record in csv_reader.records() {
-                let csv_record = record.unwrap();
+                .from_reader(reader);
 
-                let type_str = csv_record.get(0).unwrap();
+            let headers = if schema.has_headers {
+                match csv_reader.headers() {
+                    Ok(headers) => headers.clone(),
+                    Err(err) => {
+                        // The columns can't be told apart without the headers
+                        options.report(RowError::new(1, String::new(), err.into()));
 
-                let client_id: ClientID = csv_record.get(1).unwrap().parse().unwrap();
+                        return;
+                    }
+                }
+            } else {
+                csv::StringRecord::new()
+            };
 
-                let tx_id: TransactionID = csv_record.get(2).unwrap().parse().unwrap();
+            let columns = match schema.columns(&headers) {
+                Ok(columns) => columns,
+                Err(err) => {
+                    // None of the rows can be read without their columns, whatever the error
+                    // mode
+                    options.report(RowError::new(
+                        1,
+                        headers.iter().collect::<Vec<_>>().join(","),
+                        err,
+                    ));
 
-                let amount_float: f64 = csv_record.get(3).unwrap().parse().unwrap();
+                    return;
+                }
+            };
 
-                // Get the 4 decimal digit precision in a single integer, so we
-                // Get no funny business with the floating point arithmetic.
-                let amount = (amount_float * (10.0f64.powi(FLOATING_POINT_ACC))) as MoneyType;
+            for record in csv_reader.records() {
+                let parsed = record
+                    .map_err(|err| {
+                        let row = err.position().map_or(0, |position| position.line());
 
-                let tx_type = match type_str {
-                    "deposit" => TransactionType::Deposit {
-                        amount,
-                        dispute: None,
-                    },
-                    "withdrawal" => TransactionType::Withdrawal {
-                        amount,
-                        dispute: None,
-                    },
-                    "dispute" => TransactionType::Dispute,
-                    "resolve" => TransactionType::Resolve,
-                    "chargeback" => TransactionType::Chargeback,
-                    _ => unreachable!("Transaction type is not valid"),
-                };
+                        RowError::new(row, String::new(), RecordParseError::from(err))
+                    })
+                    .and_then(|record| {
+                        let row = record.position().map_or(0, |position| position.line());
 
-                let tx = Transaction::builder()
-                    .with_client_id(client_id)
-                    .with_tx_id(tx_id)
-                    .with_tx_type(tx_type)
-                    .build();
+                        options.parse_row(row, &record.iter().collect::<Vec<_>>(), &columns)
+                    });
 
-                tx_sender.send(tx).unwrap()
+                match parsed {
+                    Ok(tx) => {
+                        if tx_sender.send(tx).is_err() {
+                            // No one is listening for the transactions anymore
+                            break;
+                        }
+                    }
+                    Err(row_error) => {
+                        if !options.report(row_error) {
+                            break;
+                        }
+                    }
+                }
             }
         });
 
@@ -90,47 +655,1142 @@ where
     }
 }
 
-impl From<PathBuf> for CSVTransactionProvider<File> {
-    fn from(file: PathBuf) -> Self {
-        CSVTransactionProvider {
-            file: File::open(file).unwrap(),
+/// Parse a single CSV record into a transaction, along with the warning about its amount having
+/// been truncated or rounded, if it was
+fn parse_csv_record(
+    csv_record: &[&str],
+    columns: &CsvColumns,
+    precision: u32,
+    amount_format: &AmountFormat,
+    precision_policy: PrecisionPolicy,
+    admin_source: bool,
+) -> Result<(Transaction, Option<Warning>), RecordParseError> {
+    let value = |field: CsvField| {
+        columns
+            .get(field)
+            .and_then(|column| csv_record.get(column).copied())
+    };
+
+    // The optional fields are left empty when they don't apply
+    let optional = |field: CsvField| value(field).filter(|value| !value.is_empty());
+
+    let field = |field: CsvField| {
+        value(field).ok_or_else(|| {
+            RecordParseError::MalformedRecord(format!("missing the {} column", field.name()))
+        })
+    };
+
+    let type_str = field(CsvField::Type)?;
+
+    let client_str = field(CsvField::Client)?;
+
+    let client_id: ClientID = client_str.parse().map_err(|_| {
+        RecordParseError::MalformedRecord(format!("invalid client {:?}", client_str))
+    })?;
+
+    let tx_str = field(CsvField::Tx)?;
+
+    let tx_id: TransactionID = tx_str.parse().map_err(|_| {
+        RecordParseError::MalformedRecord(format!("invalid transaction {:?}", tx_str))
+    })?;
+
+    let mut warning = None;
+
+    // Disputes and settlements don't carry an amount
+    let amount = match optional(CsvField::Amount) {
+        Some(amount_str) => {
+            let (amount, rounded) =
+                amount_format.parse_with_policy(amount_str, precision, precision_policy)?;
+
+            if rounded {
+                warning = Some(Warning::AmountRounded {
+                    transaction: tx_id,
+                    given: amount_str.to_string(),
+                    truncated: precision_policy == PrecisionPolicy::Truncate,
+                });
+            }
+
+            Some(amount)
+        }
+        None => None,
+    };
+
+    // Only transfers have the client receiving the funds
+    let to_client: Option<ClientID> = optional(CsvField::To)
+        .map(|to_client| {
+            to_client.parse().map_err(|_| {
+                RecordParseError::MalformedRecord(format!("invalid destination {:?}", to_client))
+            })
+        })
+        .transpose()?;
+
+    let timestamp: Option<Timestamp> = optional(CsvField::Timestamp)
+        .map(|timestamp| {
+            timestamp.parse().map_err(|_| {
+                RecordParseError::MalformedRecord(format!("invalid timestamp {:?}", timestamp))
+            })
+        })
+        .transpose()?;
+
+    let mut tx = Transaction::builder()
+        .with_client_id(client_id)
+        .with_tx_id(tx_id)
+        .with_tx_type(parse_tx_type(type_str, amount, to_client, admin_source)?)
+        .build();
+
+    if let Some(timestamp) = timestamp {
+        tx.assign_timestamp(timestamp);
+    }
+
+    Ok((tx, warning))
+}
+
+/// The current time, for the transactions timestamped when they are received
+pub(crate) fn current_timestamp() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+/// Map the type of a transaction record (as given in the input formats) into the
+/// corresponding transaction type.
+///
+/// Fails if the type is not known, if it requires an amount (or, for transfers, a
+/// destination client) which is not present, if that amount is negative or zero, or if it is
+/// an administrative transaction and the source isn't an admin source.
+pub(crate) fn parse_tx_type(
+    type_str: &str,
+    amount: Option<MoneyType>,
+    to_client: Option<ClientID>,
+    admin_source: bool,
+) -> Result<TransactionType, RecordParseError> {
+    tx_type_of(type_str, amount, to_client, admin_source, true)
+}
+
+/// Same as [`parse_tx_type`] for the transactions of the write ahead log, which were already
+/// accepted, so neither their amounts nor the administrative transactions are checked.
+///
+/// Logs written before the amounts were checked can have negative or zero ones, which must
+/// still be replayed as they were processed.
+pub(crate) fn parse_logged_tx_type(
+    type_str: &str,
+    amount: Option<MoneyType>,
+    to_client: Option<ClientID>,
+) -> Result<TransactionType, RecordParseError> {
+    tx_type_of(type_str, amount, to_client, true, false)
+}
+
+fn tx_type_of(
+    type_str: &str,
+    amount: Option<MoneyType>,
+    to_client: Option<ClientID>,
+    admin_source: bool,
+    check_amount: bool,
+) -> Result<TransactionType, RecordParseError> {
+    // Only the transactions which move funds are checked, the others ignore the amount
+    let amount = || match amount {
+        None => Err(RecordParseError::MissingAmount),
+        Some(amount) if check_amount && amount.is_negative() => {
+            Err(RecordParseError::NegativeAmount(amount))
+        }
+        Some(MoneyType::ZERO) if check_amount => Err(RecordParseError::ZeroAmount),
+        Some(amount) => Ok(amount),
+    };
+
+    let tx_type = match type_str {
+        "deposit" => TransactionType::Deposit {
+            amount: amount()?,
+            dispute: DisputeState::NotDisputed,
+        },
+        "withdrawal" => TransactionType::Withdrawal {
+            amount: amount()?,
+            dispute: DisputeState::NotDisputed,
+        },
+        "dispute" => TransactionType::Dispute,
+        "resolve" => TransactionType::Resolve,
+        "chargeback" => TransactionType::Chargeback,
+        "representment" => TransactionType::Representment,
+        "authorize" => TransactionType::Authorize {
+            amount: amount()?,
+            state: AuthorizationState::Pending,
+        },
+        "capture" => TransactionType::Capture,
+        "transfer" => TransactionType::Transfer {
+            to_client: to_client.ok_or(RecordParseError::MissingDestination)?,
+            amount: amount()?,
+        },
+        "unfreeze" => TransactionType::Unfreeze,
+        "close" => TransactionType::Close,
+        _ => {
+            return Err(RecordParseError::UnknownTransactionType(
+                type_str.to_string(),
+            ))
+        }
+    };
+
+    if tx_type.is_administrative() && !admin_source {
+        return Err(RecordParseError::AdministrativeTransaction(
+            type_str.to_string(),
+        ));
+    }
+
+    Ok(tx_type)
+}
+
+/// The reasons an input record does not describe a valid transaction
+#[derive(Error, Debug)]
+pub enum RecordParseError {
+    #[error("The record is malformed: {0}")]
+    MalformedRecord(String),
+    #[error("Unknown transaction type {0:?}")]
+    UnknownTransactionType(String),
+    #[error("The transaction type requires an amount")]
+    MissingAmount,
+    #[error("Transfers require the client receiving the funds")]
+    MissingDestination,
+    #[error("The amount {0:?} is negative")]
+    NegativeAmount(MoneyType),
+    #[error("The amount is zero")]
+    ZeroAmount,
+    #[error("{0:?} transactions are only accepted from admin sources")]
+    AdministrativeTransaction(String),
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(#[from] AmountParseError),
+}
+
+impl From<csv_async::Error> for RecordParseError {
+    fn from(err: csv_async::Error) -> Self {
+        RecordParseError::MalformedRecord(err.to_string())
+    }
+}
+
+#[cfg(feature = "blocking-csv")]
+impl From<csv::Error> for RecordParseError {
+    fn from(err: csv::Error) -> Self {
+        RecordParseError::MalformedRecord(err.to_string())
+    }
+}
+
+/// What the CSV provider does when it finds a row which is not a valid transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvErrorMode {
+    /// Report the row and stop reading the input
+    #[default]
+    Strict,
+    /// Report the row and carry on with the next one
+    Lenient,
+}
+
+/// What the CSV provider does with the rows whose `type` is not a known transaction type, such
+/// as the ones added by a newer version of the producer of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownTypes {
+    /// Skip the row, only logging it to the standard error instead of reporting it
+    Skip,
+    /// Report the row as any other invalid one, so the [`CsvErrorMode`] decides whether to
+    /// carry on reading
+    #[default]
+    Reject,
+    /// Report the row and stop reading the input, even in the lenient mode
+    Abort,
+}
+
+/// A field of the transactions, as found in a column of the CSV input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CsvField {
+    Type,
+    Client,
+    Tx,
+    Amount,
+    /// The client receiving the funds of a transfer
+    To,
+    Timestamp,
+}
+
+impl CsvField {
+    /// Every field, in the order of the columns of the specification
+    const ALL: [CsvField; 6] = [
+        CsvField::Type,
+        CsvField::Client,
+        CsvField::Tx,
+        CsvField::Amount,
+        CsvField::To,
+        CsvField::Timestamp,
+    ];
+
+    /// Whether every transaction has the field
+    pub fn is_required(&self) -> bool {
+        matches!(self, CsvField::Type | CsvField::Client | CsvField::Tx)
+    }
+
+    /// The header of the field in the specification
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsvField::Type => "type",
+            CsvField::Client => "client",
+            CsvField::Tx => "tx",
+            CsvField::Amount => "amount",
+            CsvField::To => "to",
+            CsvField::Timestamp => "timestamp",
         }
     }
 }
 
+/// Where a field is found in the rows of the CSV input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvColumn {
+    /// The column at the given position, counting from 0
+    Index(usize),
+    /// The column with the given header, regardless of its case
+    Name(String),
+}
+
+/// What is done with the columns of the input which don't hold any field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownColumns {
+    /// Read the input without them
+    #[default]
+    Ignore,
+    /// Refuse to read the input
+    Reject,
+}
+
+/// What is done when the input lacks the column of an optional field (`amount`, `to` or
+/// `timestamp`). Inputs without the column of a required field are never read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingColumns {
+    /// Read the field as empty in every row
+    #[default]
+    LeaveEmpty,
+    /// Refuse to read the input
+    Reject,
+}
+
+/// The layout of the CSV input: how its fields are delimited, whether its first row holds the
+/// headers of the columns, and which columns hold each field of the transactions.
+///
+/// By default the input is comma separated, and every field is found in the column with its
+/// header in the specification (`type`, `client`, `tx`, `amount`, `to` and `timestamp`),
+/// regardless of the order of the columns. Inputs whose headers differ from the specification
+/// (or which have none) can find their fields by position instead. The headers are checked
+/// before reading any row, and an input which lacks the column of a required field (or which
+/// doesn't fit the [`UnknownColumns`] and [`MissingColumns`] of the schema) isn't read, with
+/// its header row reported as invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvSchema {
+    delimiter: u8,
+    has_headers: bool,
+    columns: HashMap<CsvField, CsvColumn>,
+    unknown_columns: UnknownColumns,
+    missing_columns: MissingColumns,
+}
+
+impl Default for CsvSchema {
+    fn default() -> Self {
+        let columns = CsvField::ALL
+            .iter()
+            .map(|field| (*field, CsvColumn::Name(field.name().to_string())))
+            .collect();
+
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            columns,
+            unknown_columns: UnknownColumns::default(),
+            missing_columns: MissingColumns::default(),
+        }
+    }
+}
+
+impl CsvSchema {
+    /// The columns of the specification separated by tabs (TSV)
+    pub fn tsv() -> Self {
+        Self::default().with_delimiter(b'\t')
+    }
+
+    /// The `type`, `client`, `tx`, `amount` and `to` fields in the first columns of the input,
+    /// in that order, whatever their headers, and the `timestamp` in the column with its header
+    pub fn positional() -> Self {
+        CsvField::ALL[..5]
+            .iter()
+            .enumerate()
+            .fold(Self::default(), |schema, (index, field)| {
+                schema.with_column(*field, CsvColumn::Index(index))
+            })
+    }
+
+    /// Separate the columns by the given character (e.g. `b';'`), instead of commas
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+
+        self
+    }
+
+    /// Whether the first row holds the headers of the columns, instead of a transaction
+    pub fn with_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+
+        self
+    }
+
+    /// Read the field from the given column
+    pub fn with_column(mut self, field: CsvField, column: CsvColumn) -> Self {
+        self.columns.insert(field, column);
+
+        self
+    }
+
+    /// Leave the optional field out of every transaction, regardless of the columns
+    pub fn without_column(mut self, field: CsvField) -> Self {
+        self.columns.remove(&field);
+
+        self
+    }
+
+    /// Choose what is done with the columns which don't hold any field
+    pub fn with_unknown_columns(mut self, unknown_columns: UnknownColumns) -> Self {
+        self.unknown_columns = unknown_columns;
+
+        self
+    }
+
+    /// Choose what is done when the input lacks the column of an optional field
+    pub fn with_missing_columns(mut self, missing_columns: MissingColumns) -> Self {
+        self.missing_columns = missing_columns;
+
+        self
+    }
+
+    /// Find the columns of the fields among the headers of the input, failing when the headers
+    /// don't fit the schema
+    fn columns<'a>(
+        &self,
+        headers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<CsvColumns, RecordParseError> {
+        let headers = headers.into_iter().collect::<Vec<_>>();
+
+        let columns = CsvColumns(CsvField::ALL.map(|field| {
+            match self.columns.get(&field)? {
+                CsvColumn::Index(index) => Some(*index),
+                CsvColumn::Name(name) => headers
+                    .iter()
+                    .position(|header| header.eq_ignore_ascii_case(name)),
+            }
+        }));
+
+        let missing = CsvField::ALL.into_iter().find(|field| {
+            self.columns.contains_key(field)
+                && columns.get(*field).is_none()
+                && (field.is_required() || self.missing_columns == MissingColumns::Reject)
+        });
+
+        if let Some(missing) = missing {
+            return Err(RecordParseError::MalformedRecord(format!(
+                "missing the {} column",
+                missing.name()
+            )));
+        }
+
+        if self.unknown_columns == UnknownColumns::Reject {
+            let unknown = (0..headers.len()).find(|column| !columns.0.contains(&Some(*column)));
+
+            if let Some(unknown) = unknown {
+                return Err(RecordParseError::MalformedRecord(format!(
+                    "unknown column {:?}",
+                    headers[unknown]
+                )));
+            }
+        }
+
+        Ok(columns)
+    }
+}
+
+/// The column of each field in the rows of an input, in the order of [`CsvField::ALL`]
+#[derive(Debug, Clone, Copy)]
+struct CsvColumns([Option<usize>; 6]);
+
+impl CsvColumns {
+    /// The columns of an input laid out as the specification, with its timestamps in the given
+    /// column
+    #[cfg(feature = "parquet")]
+    fn in_order(timestamp_column: Option<usize>) -> Self {
+        Self([
+            Some(0),
+            Some(1),
+            Some(2),
+            Some(3),
+            Some(4),
+            timestamp_column,
+        ])
+    }
+
+    fn get(&self, field: CsvField) -> Option<usize> {
+        self.0[field as usize]
+    }
+}
+
+/// A row of the input which could not be read as a transaction
+#[derive(Error, Debug)]
+#[error("Row {row} ({record:?}) is not valid: [{}] {error}", error.rejection_code())]
+pub struct RowError {
+    /// The line of the input the row starts at
+    pub row: u64,
+    /// The fields of the row, as they were read
+    pub record: String,
+    pub error: RecordParseError,
+}
+
+impl RowError {
+    fn new(row: u64, record: String, error: RecordParseError) -> Self {
+        Self { row, record, error }
+    }
+}
+
+impl CSVTransactionProvider<tokio::io::Stdin> {
+    /// Read the transactions from the standard input, so the input can be piped in
+    pub fn stdin() -> Self {
+        Self::new(tokio::io::stdin())
+    }
+}
+
+impl CSVTransactionProvider<tokio::fs::File> {
+    /// Read the transactions from the file at the given path, failing when it can't be opened
+    pub async fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(tokio::fs::File::open(path).await?))
+    }
+}
+
 #[cfg(test)]
 mod reader_test {
-    use std::io::BufReader;
+    use std::sync::Arc;
 
     use futures::StreamExt;
 
-    use crate::models::transactions::TransactionType;
-    use crate::tx_reception::CSVTransactionProvider;
+    use crate::models::money::{AmountFormat, PrecisionPolicy};
+    use crate::models::transactions::{DisputeStage, DisputeState, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::rejections::{RejectionCode, TRejectionReason};
     use crate::tx_reception::TTransactionStreamProvider;
+    use crate::tx_reception::{
+        CSVTransactionProvider, CsvColumn, CsvErrorMode, CsvField, CsvSchema, MissingColumns,
+        RecordParseError, UnknownColumns, UnknownTypes,
+    };
+    use crate::warnings::WarningCounter;
+
+    #[tokio::test]
+    async fn test_csv_reader_missing_file() {
+        assert!(
+            CSVTransactionProvider::open("/nonexistent/transactions.csv")
+                .await
+                .is_err()
+        );
+    }
 
     #[tokio::test]
     async fn test_csv_reader() {
         const CSV_DATA: &str = "type, client, tx, amount\ndeposit, 1, 1, 1.0";
 
-        let csv_provider = CSVTransactionProvider {
-            file: BufReader::new(CSV_DATA.as_bytes()),
-        };
+        let csv_provider = CSVTransactionProvider::new(CSV_DATA.as_bytes());
 
         let mut stream = csv_provider.subscribe_to_tx_stream().await;
 
         let tx = stream.next().await.expect("No transaction found?");
 
-        assert_eq!(tx.client(), 1);
-        assert_eq!(tx.transaction_id(), 1);
+        assert_eq!(tx.client(), ClientID(1));
+        assert_eq!(tx.transaction_id(), TransactionID(1));
 
         match tx.tx_type() {
             TransactionType::Deposit {
                 amount, dispute, ..
             } => {
-                assert!(dispute.is_none());
-                assert_eq!(*amount, 1000);
+                assert_eq!(dispute.stage(), DisputeStage::NotDisputed);
+                assert_eq!(*amount, MoneyType::new(10000));
             }
             _ => panic!("Transaction type is not deposit"),
         }
     }
+
+    #[tokio::test]
+    async fn test_csv_exact_amounts() {
+        const CSV_DATA: &str = "type, client, tx, amount
+deposit, 1, 1, 0.1235
+withdrawal, 1, 2, 92233720368547.75807
+dispute, 1, 1,
+resolve, 1, 1
+deposit, 2, 3, 1.2390001";
+
+        let warnings = Arc::new(WarningCounter::default());
+
+        let csv_provider = CSVTransactionProvider::new(CSV_DATA.as_bytes())
+            .with_precision(5)
+            .with_warning_sink(warnings.clone());
+
+        let transactions = csv_provider
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| tx.tx_type().clone())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            transactions,
+            vec![
+                TransactionType::Deposit {
+                    amount: MoneyType::new(12350),
+                    dispute: DisputeState::NotDisputed
+                },
+                TransactionType::Withdrawal {
+                    amount: MoneyType::MAX,
+                    dispute: DisputeState::NotDisputed
+                },
+                TransactionType::Dispute,
+                TransactionType::Resolve,
+                TransactionType::Deposit {
+                    amount: MoneyType::new(123900),
+                    dispute: DisputeState::NotDisputed
+                },
+            ]
+        );
+
+        // Only the last amount had more decimal places than the precision
+        assert_eq!(warnings.counts().get("amount-rounded"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_csv_unreadable_headers() {
+        let mut csv_provider =
+            CSVTransactionProvider::new(&b"type,client,tx,am\xffount\ndeposit,1,1,1.0"[..]);
+
+        let row_errors = csv_provider.subscribe_to_row_errors();
+
+        assert_eq!(csv_provider.subscribe_to_tx_stream().await.count().await, 0);
+
+        // The error reading the headers is reported, rather than the columns they lack
+        let row_errors = row_errors.collect::<Vec<_>>().await;
+
+        assert_eq!(row_errors.len(), 1);
+        assert_eq!(row_errors[0].row, 1);
+        assert!(matches!(
+            row_errors[0].error,
+            RecordParseError::MalformedRecord(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_csv_row_errors() {
+        const CSV_DATA: &str = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, one, 2, 1.0
+withdrawal, 1, 3, 1e5
+refund, 1, 4, 1.0
+deposit, 1, 5, 2.0";
+
+        let read = |error_mode| async move {
+            let mut csv_provider =
+                CSVTransactionProvider::new(CSV_DATA.as_bytes()).with_error_mode(error_mode);
+
+            let row_errors = csv_provider.subscribe_to_row_errors();
+
+            let transactions = csv_provider
+                .subscribe_to_tx_stream()
+                .await
+                .map(|tx| tx.transaction_id().0)
+                .collect::<Vec<_>>()
+                .await;
+
+            let row_errors = row_errors
+                .map(|row_error| (row_error.row, row_error.error.rejection_code()))
+                .collect::<Vec<_>>()
+                .await;
+
+            (transactions, row_errors)
+        };
+
+        assert_eq!(
+            read(CsvErrorMode::Lenient).await,
+            (
+                vec![1, 5],
+                vec![
+                    (3, RejectionCode::MalformedRecord),
+                    (4, RejectionCode::InvalidAmount),
+                    (5, RejectionCode::UnknownTransactionType),
+                ]
+            )
+        );
+
+        assert_eq!(
+            read(CsvErrorMode::Strict).await,
+            (vec![1], vec![(3, RejectionCode::MalformedRecord)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csv_timestamps() {
+        const CSV_DATA: &str = "type, client, tx, amount, to, timestamp
+deposit, 1, 1, 1.0, , 1700000000
+deposit, 1, 2, 1.0
+transfer, 1, 3, 1.0, 2, 1700000100
+deposit, 1, 4, 1.0, , yesterday";
+
+        let read = |ingestion_timestamps| async move {
+            CSVTransactionProvider::new(CSV_DATA.as_bytes())
+                .with_error_mode(CsvErrorMode::Lenient)
+                .with_ingestion_timestamps(ingestion_timestamps)
+                .subscribe_to_tx_stream()
+                .await
+                .map(|tx| tx.timestamp())
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        assert_eq!(
+            read(false).await,
+            vec![Some(1_700_000_000), None, Some(1_700_000_100)]
+        );
+
+        let ingested = read(true).await;
+
+        assert_eq!(ingested[0], Some(1_700_000_000));
+        assert!(ingested[1].is_some_and(|timestamp| timestamp > 1_700_000_100));
+    }
+
+    #[tokio::test]
+    async fn test_csv_schema() {
+        const TSV_DATA: &str = "Reference\tAccount\tKind\tBranch\tValue
+1\t1\tdeposit\tLisbon\t1.5
+2\t1\twithdrawal\tPorto\t0.5";
+
+        let schema = CsvSchema::tsv()
+            .with_column(CsvField::Tx, CsvColumn::Name("reference".to_string()))
+            .with_column(CsvField::Client, CsvColumn::Name("account".to_string()))
+            .with_column(CsvField::Type, CsvColumn::Name("kind".to_string()))
+            .with_column(CsvField::Amount, CsvColumn::Index(4))
+            .without_column(CsvField::To);
+
+        let transactions = CSVTransactionProvider::new(TSV_DATA.as_bytes())
+            .with_schema(schema)
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| (tx.transaction_id(), tx.tx_type().clone()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            transactions,
+            vec![
+                (
+                    TransactionID(1),
+                    TransactionType::Deposit {
+                        amount: MoneyType::new(15000),
+                        dispute: DisputeState::NotDisputed
+                    }
+                ),
+                (
+                    TransactionID(2),
+                    TransactionType::Withdrawal {
+                        amount: MoneyType::new(5000),
+                        dispute: DisputeState::NotDisputed
+                    }
+                ),
+            ]
+        );
+
+        // Without headers, every row is a transaction
+        const HEADERLESS_DATA: &str = "1;deposit;2;1.0\n2;deposit;2;1.0";
+
+        let schema = CsvSchema::default()
+            .with_delimiter(b';')
+            .with_headers(false)
+            .with_column(CsvField::Tx, CsvColumn::Index(0))
+            .with_column(CsvField::Type, CsvColumn::Index(1))
+            .with_column(CsvField::Client, CsvColumn::Index(2))
+            .with_column(CsvField::Amount, CsvColumn::Index(3));
+
+        let clients = CSVTransactionProvider::new(HEADERLESS_DATA.as_bytes())
+            .with_schema(schema)
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| tx.client())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(clients, vec![ClientID(2), ClientID(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_csv_columns_by_header() {
+        const CSV_DATA: &str = "tx, amount, client, type, branch, timestamp
+1, 1.0, 1, deposit, Lisbon, 1700000000
+2, , 1, dispute, Porto,";
+
+        let read = |schema: CsvSchema| async move {
+            let mut csv_provider = CSVTransactionProvider::new(CSV_DATA.as_bytes())
+                .with_error_mode(CsvErrorMode::Lenient)
+                .with_schema(schema);
+
+            let row_errors = csv_provider.subscribe_to_row_errors();
+
+            let transactions = csv_provider
+                .subscribe_to_tx_stream()
+                .await
+                .map(|tx| (tx.transaction_id().0, tx.timestamp()))
+                .collect::<Vec<_>>()
+                .await;
+
+            let row_errors = row_errors
+                .map(|row_error| (row_error.row, row_error.error.to_string()))
+                .collect::<Vec<_>>()
+                .await;
+
+            (transactions, row_errors)
+        };
+
+        // The extra column is ignored, and the missing `to` column is left empty
+        assert_eq!(
+            read(CsvSchema::default()).await,
+            (vec![(1, Some(1_700_000_000)), (2, None)], vec![])
+        );
+
+        let (transactions, row_errors) =
+            read(CsvSchema::default().with_unknown_columns(UnknownColumns::Reject)).await;
+
+        assert!(transactions.is_empty());
+        assert_eq!(row_errors.len(), 1);
+        assert_eq!(row_errors[0].0, 1);
+        assert!(row_errors[0].1.contains("unknown column \"branch\""));
+
+        let (transactions, row_errors) =
+            read(CsvSchema::default().with_missing_columns(MissingColumns::Reject)).await;
+
+        assert!(transactions.is_empty());
+        assert!(row_errors[0].1.contains("missing the to column"));
+
+        // Unless it is left out of the transactions
+        let (transactions, _) = read(
+            CsvSchema::default()
+                .with_missing_columns(MissingColumns::Reject)
+                .without_column(CsvField::To),
+        )
+        .await;
+
+        assert_eq!(transactions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_csv_localized_amounts() {
+        const CSV_DATA: &str = "type;client;tx;amount
+deposit;1;1;1.234,56
+withdrawal;1;2;(0,5)
+deposit;1;3;1,234.56";
+
+        let mut csv_provider = CSVTransactionProvider::new(CSV_DATA.as_bytes())
+            .with_schema(CsvSchema::default().with_delimiter(b';'))
+            .with_amount_format(AmountFormat::european())
+            .with_error_mode(CsvErrorMode::Lenient);
+
+        let row_errors = csv_provider.subscribe_to_row_errors();
+
+        let amounts = csv_provider
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| tx.amount().ok())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(amounts, vec![Some(MoneyType::new(12_345_600))]);
+
+        // The amount in parentheses is read as negative, and the last one is written with the
+        // separators of another locale
+        let row_errors = row_errors
+            .map(|row_error| (row_error.row, row_error.error.rejection_code()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            row_errors,
+            vec![
+                (3, RejectionCode::NegativeAmount),
+                (4, RejectionCode::InvalidAmount)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csv_unknown_types() {
+        const CSV_DATA: &str = "type,client,tx,amount
+deposit,1,1,1.0
+refund,1,2,1.0
+deposit,1,3,1.0";
+
+        let read = |unknown_types: UnknownTypes, error_mode: CsvErrorMode| async move {
+            let mut csv_provider = CSVTransactionProvider::new(CSV_DATA.as_bytes())
+                .with_unknown_types(unknown_types)
+                .with_error_mode(error_mode);
+
+            let row_errors = csv_provider.subscribe_to_row_errors();
+
+            let transactions = csv_provider
+                .subscribe_to_tx_stream()
+                .await
+                .map(|tx| tx.transaction_id())
+                .collect::<Vec<_>>()
+                .await;
+
+            let row_errors = row_errors
+                .map(|row_error| (row_error.row, row_error.error.rejection_code()))
+                .collect::<Vec<_>>()
+                .await;
+
+            (transactions, row_errors)
+        };
+
+        let unknown_type = vec![(3, RejectionCode::UnknownTransactionType)];
+
+        // Skipped rows are not reported, so they never stop the input
+        assert_eq!(
+            read(UnknownTypes::Skip, CsvErrorMode::Strict).await,
+            (vec![TransactionID(1), TransactionID(3)], vec![])
+        );
+        assert_eq!(
+            read(UnknownTypes::Reject, CsvErrorMode::Lenient).await,
+            (
+                vec![TransactionID(1), TransactionID(3)],
+                unknown_type.clone()
+            )
+        );
+        assert_eq!(
+            read(UnknownTypes::Reject, CsvErrorMode::Strict).await,
+            (vec![TransactionID(1)], unknown_type.clone())
+        );
+        assert_eq!(
+            read(UnknownTypes::Abort, CsvErrorMode::Lenient).await,
+            (vec![TransactionID(1)], unknown_type)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csv_non_positive_amounts() {
+        const CSV_DATA: &str = "type,client,tx,amount
+deposit,1,1,-1.0
+deposit,1,2,0
+deposit,1,3,+1.0
+withdrawal,1,4,-1.0
+withdrawal,1,5,0
+withdrawal,1,6,+1.0
+deposit,1,7,-0.0000
+dispute,1,3,0";
+
+        let mut csv_provider =
+            CSVTransactionProvider::new(CSV_DATA.as_bytes()).with_error_mode(CsvErrorMode::Lenient);
+
+        let row_errors = csv_provider.subscribe_to_row_errors();
+
+        let transactions = csv_provider
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| (tx.transaction_id(), tx.amount().ok()))
+            .collect::<Vec<_>>()
+            .await;
+
+        // Disputes don't move funds, so their amount is not checked
+        assert_eq!(
+            transactions,
+            vec![
+                (TransactionID(3), Some(MoneyType::new(10000))),
+                (TransactionID(6), Some(MoneyType::new(10000))),
+                (TransactionID(3), None),
+            ]
+        );
+
+        let row_errors = row_errors
+            .map(|row_error| (row_error.row, row_error.error.rejection_code()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            row_errors,
+            vec![
+                (2, RejectionCode::NegativeAmount),
+                (3, RejectionCode::ZeroAmount),
+                (5, RejectionCode::NegativeAmount),
+                (6, RejectionCode::ZeroAmount),
+                (8, RejectionCode::ZeroAmount),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csv_precision_policies() {
+        const CSV_DATA: &str = "type,client,tx,amount
+deposit,1,1,1.00005
+deposit,1,2,1.00015
+deposit,1,3,1.0001";
+
+        let read = |policy: PrecisionPolicy| async move {
+            let warnings = Arc::new(WarningCounter::default());
+
+            let mut csv_provider = CSVTransactionProvider::new(CSV_DATA.as_bytes())
+                .with_precision_policy(policy)
+                .with_error_mode(CsvErrorMode::Lenient)
+                .with_warning_sink(warnings.clone());
+
+            let row_errors = csv_provider.subscribe_to_row_errors();
+
+            let amounts = csv_provider
+                .subscribe_to_tx_stream()
+                .await
+                .map(|tx| tx.amount().ok())
+                .collect::<Vec<_>>()
+                .await;
+
+            let row_errors = row_errors
+                .map(|row_error| (row_error.row, row_error.error.rejection_code()))
+                .collect::<Vec<_>>()
+                .await;
+
+            (
+                amounts,
+                row_errors,
+                warnings.counts().get("amount-rounded").copied(),
+            )
+        };
+
+        assert_eq!(
+            read(PrecisionPolicy::Truncate).await,
+            (
+                vec![
+                    Some(MoneyType::new(10000)),
+                    Some(MoneyType::new(10001)),
+                    Some(MoneyType::new(10001))
+                ],
+                vec![],
+                Some(2)
+            )
+        );
+        assert_eq!(
+            read(PrecisionPolicy::RoundHalfEven).await,
+            (
+                vec![
+                    Some(MoneyType::new(10000)),
+                    Some(MoneyType::new(10002)),
+                    Some(MoneyType::new(10001))
+                ],
+                vec![],
+                Some(2)
+            )
+        );
+        assert_eq!(
+            read(PrecisionPolicy::Reject).await,
+            (
+                vec![Some(MoneyType::new(10001))],
+                vec![
+                    (2, RejectionCode::ExcessAmountPrecision),
+                    (3, RejectionCode::ExcessAmountPrecision)
+                ],
+                None
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csv_admin_source() {
+        const CSV_DATA: &str = "type, client, tx, amount
+deposit, 1, 1, 1.0
+unfreeze, 1, 2,";
+
+        let read = |admin_source| async move {
+            let mut csv_provider = CSVTransactionProvider::new(CSV_DATA.as_bytes())
+                .with_error_mode(CsvErrorMode::Lenient)
+                .with_admin_source(admin_source);
+
+            let row_errors = csv_provider.subscribe_to_row_errors();
+
+            let transactions = csv_provider
+                .subscribe_to_tx_stream()
+                .await
+                .map(|tx| tx.tx_type().clone())
+                .collect::<Vec<_>>()
+                .await;
+
+            let row_errors = row_errors
+                .map(|row_error| row_error.error.rejection_code())
+                .collect::<Vec<_>>()
+                .await;
+
+            (transactions.len(), row_errors)
+        };
+
+        assert_eq!(
+            read(false).await,
+            (1, vec![RejectionCode::AdministrativeTransactionNotAllowed])
+        );
+        assert_eq!(read(true).await, (2, vec![]));
+    }
+
+    #[cfg(feature = "blocking-csv")]
+    #[tokio::test]
+    async fn test_blocking_csv_reader() {
+        const CSV_DATA: &str = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, one, 2, 1.0
+withdrawal, 1, 3, 0.5";
+
+        let transactions = CSVTransactionProvider::blocking(std::io::Cursor::new(CSV_DATA))
+            .with_error_mode(CsvErrorMode::Lenient)
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| tx.transaction_id().0)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(transactions, vec![1, 3]);
+    }
+
+    #[cfg(feature = "blocking-csv")]
+    #[tokio::test]
+    async fn test_blocking_csv_reader_backpressure() {
+        use std::io::Read;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        const ROWS: usize = 100;
+
+        /// Input handing out a single line on every read, counting how many were read
+        struct Lines(Arc<AtomicUsize>);
+
+        impl Read for Lines {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let line = match self.0.fetch_add(1, Ordering::SeqCst) {
+                    0 => "type,client,tx,amount\n".to_string(),
+                    row if row <= ROWS => format!("deposit,1,{},1.0\n", row),
+                    _ => return Ok(0),
+                };
+
+                buf[..line.len()].copy_from_slice(line.as_bytes());
+
+                Ok(line.len())
+            }
+        }
+
+        let read = Arc::new(AtomicUsize::new(0));
+
+        let mut transactions = CSVTransactionProvider::blocking(Lines(read.clone()))
+            .with_channel_capacity(2)
+            .subscribe_to_tx_stream()
+            .await;
+
+        assert!(transactions.next().await.is_some());
+
+        // Give the reader plenty of time to get ahead of the consumer
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Only the headers, the row consumed, the two waiting in the channel and the one
+        // waiting to be sent were read
+        assert!(read.load(Ordering::SeqCst) <= 5);
+
+        // The reading carries on as the consumer catches up
+        assert_eq!(transactions.count().await, ROWS - 1);
+    }
 }
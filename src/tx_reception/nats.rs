@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use async_nats::jetstream::consumer::{pull, AckPolicy};
+use async_nats::jetstream::{self, AckKind, Message};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use thiserror::Error;
+
+use crate::models::transactions::Transaction;
+use crate::rejections::{RejectionCode, TRejectionReason};
+use crate::tx_reception::json_lines::JsonTransactionRecord;
+use crate::tx_reception::{
+    AcknowledgeableTransaction, ProcessingOutcome, RecordParseError, TAcknowledgedStreamProvider,
+    TAcknowledger, TTransactionStreamProvider,
+};
+use crate::FLOATING_POINT_ACC;
+
+#[derive(Error, Debug)]
+pub enum NatsError {
+    #[error("Failed to connect to the NATS server {0}")]
+    ConnectFailed(#[from] async_nats::ConnectError),
+    #[error("Failed to consume the stream {0}: {1}")]
+    ConsumerFailed(String, String),
+}
+
+/// Provider consuming transactions from a NATS JetStream stream, through a durable pull
+/// consumer.
+///
+/// Every message is expected to contain a single JSON transaction record, in the same format
+/// used by the JSON Lines provider. The consumer acknowledges every message explicitly, and
+/// the server keeps track of what was acknowledged under the durable name, so a restarted
+/// engine resumes from the first message which was not processed. When used as an
+/// acknowledged stream each message is acknowledged once its transaction was processed, and
+/// the acknowledgement is confirmed by the server before moving on, so it isn't lost.
+///
+/// Malformed messages and transactions rejected by the account rules or by validation
+/// (`E0xxx` to `E2xxx`) are terminated, as processing them again would reject them again. The
+/// ones rejected because of a failure of the storage (`E3xxx`) are negatively acknowledged,
+/// so the server delivers them again after [`RETRY_DELAY`]. A message which is delivered again
+/// after a restart and rejected as a duplicate was already processed, so it is acknowledged
+/// instead.
+pub struct NatsTransactionProvider {
+    messages: pull::Stream,
+}
+
+impl NatsTransactionProvider {
+    /// Consume the given stream of the server at the given address under the given durable
+    /// name, creating the consumer if it doesn't exist yet
+    pub async fn connect(
+        server: &str,
+        stream: &str,
+        durable_name: &str,
+    ) -> Result<Self, NatsError> {
+        let client = async_nats::connect(server).await?;
+
+        Self::new(jetstream::new(client), stream, durable_name).await
+    }
+
+    /// Consume the given stream through an existing JetStream context (e.g. one connected
+    /// with credentials)
+    pub async fn new(
+        context: jetstream::Context,
+        stream: &str,
+        durable_name: &str,
+    ) -> Result<Self, NatsError> {
+        let consumer_failed = |err: String| NatsError::ConsumerFailed(stream.to_string(), err);
+
+        let consumer = context
+            .get_stream(stream)
+            .await
+            .map_err(|err| consumer_failed(err.to_string()))?
+            .get_or_create_consumer(
+                durable_name,
+                pull::Config {
+                    durable_name: Some(durable_name.to_string()),
+                    ack_policy: AckPolicy::Explicit,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|err| consumer_failed(err.to_string()))?;
+
+        let messages = consumer
+            .messages()
+            .await
+            .map_err(|err| consumer_failed(err.to_string()))?;
+
+        Ok(Self { messages })
+    }
+}
+
+/// How long the server waits before delivering again a message whose transaction could not be
+/// processed because of a failure of the storage
+pub const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// What the server is told about a message once its transaction was processed
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    /// Acknowledged, waiting for the server to confirm it
+    Ack,
+    /// Delivered again after the given delay
+    Retry(Duration),
+    /// Never delivered again
+    Term,
+}
+
+impl Reply {
+    fn to(outcome: ProcessingOutcome, redelivered: bool) -> Self {
+        match outcome {
+            ProcessingOutcome::Accepted | ProcessingOutcome::Deferred => Reply::Ack,
+            ProcessingOutcome::Rejected(RejectionCode::DuplicateTransaction) if redelivered => {
+                Reply::Ack
+            }
+            ProcessingOutcome::Rejected(code) if code.is_retryable() => Reply::Retry(RETRY_DELAY),
+            ProcessingOutcome::Rejected(_) => Reply::Term,
+        }
+    }
+}
+
+/// Acknowledges a single message to the server once its transaction was processed
+struct NatsAcknowledger {
+    message: Message,
+}
+
+impl NatsAcknowledger {
+    /// Whether the message was delivered before, such as when the engine stopped before
+    /// acknowledging it
+    fn is_redelivery(&self) -> bool {
+        self.message.info().is_ok_and(|info| info.delivered > 1)
+    }
+}
+
+impl TAcknowledger for NatsAcknowledger {
+    fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            let result = match Reply::to(outcome, self.is_redelivery()) {
+                Reply::Ack => self.message.double_ack().await,
+                Reply::Retry(delay) => self.message.ack_with(AckKind::Nak(Some(delay))).await,
+                Reply::Term => self.message.ack_with(AckKind::Term).await,
+            };
+
+            if let Err(err) = result {
+                tracing::error!(
+                    subject = %self.message.subject,
+                    error = %err,
+                    "Failed to acknowledge a message"
+                );
+            }
+        })
+    }
+}
+
+impl TAcknowledgedStreamProvider for NatsTransactionProvider {
+    async fn subscribe_to_acknowledged_stream(
+        self,
+    ) -> BoxStream<'static, AcknowledgeableTransaction> {
+        // Messages are only pulled as they are handed out, so they don't wait for long enough
+        // to be delivered again
+        let (tx_sender, rx) = flume::bounded(1);
+
+        let mut messages = self.messages;
+
+        tokio::spawn(async move {
+            while let Some(message) = messages.next().await {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(err) => {
+                        tracing::warn!(error = %err, "Failed to receive message from NATS");
+                        continue;
+                    }
+                };
+
+                let transaction = serde_json::from_slice::<JsonTransactionRecord>(&message.payload)
+                    .map_err(|err| RecordParseError::MalformedRecord(err.to_string()))
+                    // Administrative transactions are never taken from the stream
+                    .and_then(|record| record.into_transaction(false, FLOATING_POINT_ACC as u32));
+
+                let acknowledger = Box::new(NatsAcknowledger { message });
+
+                let transaction = match transaction {
+                    Ok(transaction) => transaction,
+                    Err(err) => {
+                        tracing::warn!(
+                            subject = %acknowledger.message.subject,
+                            code = err.rejection_code().code(),
+                            error = %err,
+                            "Skipping the malformed message"
+                        );
+
+                        acknowledger
+                            .acknowledge(ProcessingOutcome::Rejected(err.rejection_code()))
+                            .await;
+                        continue;
+                    }
+                };
+
+                if tx_sender
+                    .send_async(AcknowledgeableTransaction::new(transaction, acknowledger))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+impl TTransactionStreamProvider for NatsTransactionProvider {
+    /// Consume the stream without explicit acknowledgements.
+    ///
+    /// Each message is acknowledged as soon as the transaction is handed out by the stream.
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        self.subscribe_to_acknowledged_stream()
+            .await
+            .then(|acknowledgeable| async move {
+                let (transaction, acknowledger) = acknowledgeable.into_parts();
+
+                acknowledger.acknowledge(ProcessingOutcome::Accepted).await;
+
+                transaction
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod nats_test {
+    use crate::rejections::RejectionCode;
+    use crate::tx_reception::nats::{Reply, RETRY_DELAY};
+    use crate::tx_reception::ProcessingOutcome;
+
+    #[test]
+    fn test_replies() {
+        assert_eq!(Reply::to(ProcessingOutcome::Accepted, false), Reply::Ack);
+
+        // Rejected again if it's processed again
+        for code in [
+            RejectionCode::MalformedRecord,
+            RejectionCode::InsufficientFunds,
+            RejectionCode::ClientBlocked,
+        ] {
+            assert_eq!(
+                Reply::to(ProcessingOutcome::Rejected(code), true),
+                Reply::Term
+            );
+        }
+
+        // The storage might be back by the time it's delivered again
+        assert_eq!(
+            Reply::to(
+                ProcessingOutcome::Rejected(RejectionCode::DatabaseFailure),
+                false
+            ),
+            Reply::Retry(RETRY_DELAY)
+        );
+
+        // Only a redelivered duplicate was already processed
+        let duplicate = ProcessingOutcome::Rejected(RejectionCode::DuplicateTransaction);
+
+        assert_eq!(Reply::to(duplicate, true), Reply::Ack);
+        assert_eq!(Reply::to(duplicate, false), Reply::Term);
+    }
+}
@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use ::parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use ::parquet::errors::ParquetError;
+use ::parquet::file::reader::ChunkReader;
+use arrow::array::{Array, AsArray, StringArray};
+use arrow::compute::cast;
+use arrow::datatypes::DataType;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use thiserror::Error;
+
+use crate::models::transactions::Transaction;
+use crate::tx_reception::{CsvColumns, CsvReadOptions, RowError, TTransactionStreamProvider};
+
+/// The columns read from the input, in the order of the CSV format. Only the first three are
+/// required.
+const COLUMNS: [&str; 6] = ["type", "client", "tx", "amount", "to", "timestamp"];
+
+const REQUIRED_COLUMNS: usize = 3;
+
+/// How many rows are read at once, which is also how many transactions can be read ahead of
+/// the consumer
+const BATCH_SIZE: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum ParquetInputError {
+    #[error("Failed to open the Parquet input {0}")]
+    IoError(#[from] io::Error),
+    #[error("Failed to read the Parquet input {0}")]
+    ParquetError(#[from] ParquetError),
+    #[error("The Parquet input has no {0:?} column")]
+    MissingColumn(&'static str),
+}
+
+/// Provider reading the transactions from a Parquet file, with the columns of the CSV format
+/// (`type`, `client`, `tx`, `amount`, and the optional `to` and `timestamp`).
+///
+/// The columns can be of any type which converts to the one of the CSV field, such as any
+/// integer for the ids or a decimal for the amounts, and the rows are read exactly as their
+/// CSV equivalent would be, with the same options. The file is read one batch of rows at a
+/// time, on a thread of its own, as the transactions are consumed.
+pub struct ParquetTransactionProvider {
+    reader: ParquetRecordBatchReader,
+    options: CsvReadOptions,
+}
+
+impl ParquetTransactionProvider {
+    /// Read the transactions of the given Parquet input, failing if it isn't Parquet or if it
+    /// lacks one of the required columns
+    pub fn try_new(input: impl ChunkReader + 'static) -> Result<Self, ParquetInputError> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(input)?;
+
+        if let Some(missing) = COLUMNS[..REQUIRED_COLUMNS]
+            .iter()
+            .find(|column| builder.schema().column_with_name(column).is_none())
+        {
+            return Err(ParquetInputError::MissingColumn(missing));
+        }
+
+        Ok(Self {
+            reader: builder.with_batch_size(BATCH_SIZE).build()?,
+            options: CsvReadOptions::default(),
+        })
+    }
+
+    /// Read the transactions of the Parquet file at the given path
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ParquetInputError> {
+        Self::try_new(File::open(path)?)
+    }
+
+    /// Read the rows with the given options, replacing every one chosen so far. The columns
+    /// are found by their names, whatever the schema of the options. The row errors have to
+    /// be subscribed to afterwards.
+    pub fn with_options(mut self, options: CsvReadOptions) -> Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Receive the rows which could not be read as transactions, numbered from 1, instead of
+    /// having them printed to the standard error
+    pub fn subscribe_to_row_errors(&mut self) -> BoxStream<'static, RowError> {
+        self.options.subscribe_to_row_errors()
+    }
+}
+
+/// The columns of a batch of rows, converted to strings as if they were read from CSV
+struct BatchColumns {
+    columns: Vec<Option<StringArray>>,
+}
+
+impl BatchColumns {
+    fn new(batch: &RecordBatch) -> Result<Self, ArrowError> {
+        let columns = COLUMNS
+            .iter()
+            .map(|name| {
+                batch
+                    .column_by_name(name)
+                    .map(|column| {
+                        cast(column, &DataType::Utf8)
+                            .map(|column| column.as_string::<i32>().clone())
+                    })
+                    .transpose()
+            })
+            .collect::<Result<_, ArrowError>>()?;
+
+        Ok(Self { columns })
+    }
+
+    /// The fields of the row, in the order of the CSV format, with the missing values empty
+    fn fields(&self, row: usize) -> Vec<&str> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| match column {
+                Some(column) if column.is_valid(row) => {
+                    let field = column.value(row);
+
+                    // Decimals are written with all of the places of their scale, which aren't
+                    // truncated when they are only trailing zeros
+                    if COLUMNS[index] == "amount" && field.contains('.') {
+                        field.trim_end_matches('0').trim_end_matches('.')
+                    } else {
+                        field
+                    }
+                }
+                _ => "",
+            })
+            .collect()
+    }
+
+    /// Where each field is found among the fields of a row
+    fn order(&self) -> CsvColumns {
+        let column = COLUMNS.len() - 1;
+
+        CsvColumns::in_order(self.columns[column].as_ref().map(|_| column))
+    }
+}
+
+impl TTransactionStreamProvider for ParquetTransactionProvider {
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let (reader, options) = (self.reader, self.options);
+
+        let (tx_sender, rx) = flume::bounded(BATCH_SIZE);
+
+        // The file is read synchronously, so it is read on its own thread, which blocks while
+        // the channel is full
+        std::thread::spawn(move || {
+            let mut row = 0;
+
+            for batch in reader {
+                let read =
+                    batch.and_then(|batch| Ok((BatchColumns::new(&batch)?, batch.num_rows())));
+
+                let (columns, rows) = match read {
+                    Ok(read) => read,
+                    Err(err) => {
+                        tracing::error!(error = %err, "Failed to read the Parquet input");
+                        return;
+                    }
+                };
+
+                for index in 0..rows {
+                    row += 1;
+
+                    let parsed = options.parse_row(row, &columns.fields(index), &columns.order());
+
+                    match parsed {
+                        Ok(tx) => {
+                            if tx_sender.send(tx).is_err() {
+                                // No one is listening for the transactions anymore
+                                return;
+                            }
+                        }
+                        Err(row_error) => {
+                            if !options.report(row_error) {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+#[cfg(test)]
+mod parquet_reader_test {
+    use std::sync::Arc;
+
+    use ::parquet::arrow::ArrowWriter;
+    use arrow::array::{Array, ArrayRef, Decimal128Array, StringArray, UInt16Array, UInt64Array};
+    use arrow::record_batch::RecordBatch;
+    use bytes::Bytes;
+    use futures::StreamExt;
+
+    use crate::models::transactions::TransactionType;
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::tx_reception::parquet::{ParquetInputError, ParquetTransactionProvider};
+    use crate::tx_reception::{CsvErrorMode, CsvReadOptions, TTransactionStreamProvider};
+
+    fn column(array: impl Array + 'static) -> ArrayRef {
+        Arc::new(array)
+    }
+
+    fn parquet(columns: Vec<(&str, ArrayRef)>) -> Bytes {
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+
+        let mut file = Vec::new();
+
+        let mut writer = ArrowWriter::try_new(&mut file, batch.schema(), None).unwrap();
+
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        Bytes::from(file)
+    }
+
+    #[tokio::test]
+    async fn test_parquet_reader() {
+        let input = parquet(vec![
+            (
+                "type",
+                column(StringArray::from(vec![
+                    "deposit",
+                    "withdrawal",
+                    "dispute",
+                    "deposit",
+                ])),
+            ),
+            ("client", column(UInt16Array::from(vec![1, 1, 1, 2]))),
+            ("tx", column(UInt64Array::from(vec![1, 2, 1, 3]))),
+            (
+                "amount",
+                column(
+                    Decimal128Array::from(vec![Some(15_000_000), Some(250_000), None, None])
+                        .with_precision_and_scale(20, 6)
+                        .unwrap(),
+                ),
+            ),
+            (
+                "timestamp",
+                column(UInt64Array::from(vec![Some(100), None, Some(300), None])),
+            ),
+        ]);
+
+        let mut provider = ParquetTransactionProvider::try_new(input)
+            .unwrap()
+            .with_options(CsvReadOptions::default().with_error_mode(CsvErrorMode::Lenient));
+
+        let row_errors = provider.subscribe_to_row_errors();
+
+        let txs = provider
+            .subscribe_to_tx_stream()
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(txs.len(), 3);
+
+        assert!(matches!(
+            txs[0].tx_type(),
+            TransactionType::Deposit { amount, .. } if *amount == MoneyType::new(150_000)
+        ));
+        assert_eq!(txs[0].timestamp(), Some(100));
+        assert!(matches!(
+            txs[1].tx_type(),
+            TransactionType::Withdrawal { amount, .. } if *amount == MoneyType::new(2_500)
+        ));
+        assert_eq!(txs[2].transaction_id(), TransactionID(1));
+        assert_eq!(txs[2].client(), ClientID(1));
+
+        // The deposit without an amount
+        let row_errors = row_errors.collect::<Vec<_>>().await;
+
+        assert_eq!(row_errors.len(), 1);
+        assert_eq!(row_errors[0].row, 4);
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let input = parquet(vec![
+            ("type", column(StringArray::from(vec!["deposit"]))),
+            ("client", column(UInt16Array::from(vec![1]))),
+        ]);
+
+        assert!(matches!(
+            ParquetTransactionProvider::try_new(input),
+            Err(ParquetInputError::MissingColumn("tx"))
+        ));
+    }
+}
@@ -0,0 +1,401 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::models::transactions::Transaction;
+use crate::rejections::{RejectionCode, TRejectionReason};
+use crate::tx_reception::json_lines::JsonTransactionRecord;
+use crate::tx_reception::{
+    AcknowledgeableTransaction, ProcessingOutcome, RecordParseError, TAcknowledgedStreamProvider,
+    TAcknowledger, TTransactionStreamProvider,
+};
+use crate::FLOATING_POINT_ACC;
+
+/// A message received from a queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueMessage {
+    pub body: String,
+    /// Identifies this delivery of the message, so it can be deleted
+    pub receipt: String,
+}
+
+/// A message queue (e.g. Amazon SQS) where the received messages are delivered again unless
+/// they are deleted in time
+pub trait TMessageQueue: Send + Sync + 'static {
+    type Error: fmt::Display + Send;
+
+    /// Wait for the next messages, returning none if none arrived for a while
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<QueueMessage>, Self::Error>>;
+
+    /// Remove a received message from the queue, so it isn't delivered again
+    fn delete<'a>(&'a self, message: &'a QueueMessage) -> BoxFuture<'a, Result<(), Self::Error>>;
+
+    /// Add a message which could not be processed to the queue, along with the reason why
+    fn dead_letter<'a>(
+        &'a self,
+        body: &'a str,
+        rejection: RejectionCode,
+    ) -> BoxFuture<'a, Result<(), Self::Error>>;
+}
+
+/// Provider consuming transactions from a message queue.
+///
+/// Every message is expected to contain a single JSON transaction record, in the same format
+/// used by the JSON Lines provider. When used as an acknowledged stream, each message is only
+/// deleted once its transaction has been processed, so the messages of a crashed run are
+/// delivered again. The messages which can never be processed (malformed, or rejected for a
+/// reason which isn't [retryable](RejectionCode::is_retryable)) are moved to the dead-letter
+/// queue, when there is one, and are otherwise left in the queue, to be moved by its own
+/// redrive policy. The ones rejected because the storage failed are always left in the queue,
+/// which delivers them again once they are no longer hidden.
+///
+/// New messages are only received once the previous ones were handed out, so they aren't left
+/// waiting past the time the queue keeps them hidden for.
+pub struct QueueTransactionProvider<Q> {
+    queue: Arc<Q>,
+    dead_letters: Option<Arc<Q>>,
+    retry_delay: Duration,
+}
+
+impl<Q: TMessageQueue> QueueTransactionProvider<Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue: Arc::new(queue),
+            dead_letters: None,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Move the messages which can't be processed to the given queue
+    pub fn with_dead_letter_queue(mut self, dead_letters: Q) -> Self {
+        self.dead_letters = Some(Arc::new(dead_letters));
+
+        self
+    }
+
+    /// How long to wait before polling the queue again after failing to
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+
+        self
+    }
+}
+
+/// Deletes a single message once acknowledged, moving it to the dead-letter queue first if it
+/// was rejected for good
+struct QueueAcknowledger<Q> {
+    queue: Arc<Q>,
+    dead_letters: Option<Arc<Q>>,
+    message: QueueMessage,
+}
+
+impl<Q: TMessageQueue> TAcknowledger for QueueAcknowledger<Q> {
+    fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            if let ProcessingOutcome::Rejected(rejection) = outcome {
+                // Delivered again once hidden for long enough, by when the storage may be back
+                if rejection.is_retryable() {
+                    return;
+                }
+
+                // Kept in the queue, so it is delivered again until its redrive policy moves it
+                let Some(dead_letters) = &self.dead_letters else {
+                    return;
+                };
+
+                if let Err(err) = dead_letters
+                    .dead_letter(&self.message.body, rejection)
+                    .await
+                {
+                    tracing::error!(
+                        error = %err,
+                        "Failed to move a message to the dead-letter queue"
+                    );
+                    return;
+                }
+            }
+
+            if let Err(err) = self.queue.delete(&self.message).await {
+                tracing::error!(error = %err, "Failed to delete a message from the queue");
+            }
+        })
+    }
+}
+
+impl<Q: TMessageQueue> TAcknowledgedStreamProvider for QueueTransactionProvider<Q> {
+    async fn subscribe_to_acknowledged_stream(
+        self,
+    ) -> BoxStream<'static, AcknowledgeableTransaction> {
+        let (tx_sender, rx) = flume::bounded(1);
+
+        let Self {
+            queue,
+            dead_letters,
+            retry_delay,
+        } = self;
+
+        tokio::spawn(async move {
+            while !tx_sender.is_disconnected() {
+                let messages = match queue.receive().await {
+                    Ok(messages) => messages,
+                    Err(err) => {
+                        tracing::warn!(
+                            error = %err,
+                            retry_in = ?retry_delay,
+                            "Failed to receive messages from the queue"
+                        );
+                        tokio::time::sleep(retry_delay).await;
+                        continue;
+                    }
+                };
+
+                for message in messages {
+                    let transaction = serde_json::from_str::<JsonTransactionRecord>(&message.body)
+                        .map_err(|err| RecordParseError::MalformedRecord(err.to_string()))
+                        // Administrative transactions are never taken from the queue
+                        .and_then(|record| {
+                            record.into_transaction(false, FLOATING_POINT_ACC as u32)
+                        });
+
+                    let acknowledger = Box::new(QueueAcknowledger {
+                        queue: queue.clone(),
+                        dead_letters: dead_letters.clone(),
+                        message,
+                    });
+
+                    let transaction = match transaction {
+                        Ok(transaction) => transaction,
+                        Err(err) => {
+                            tracing::warn!(
+                                code = err.rejection_code().code(),
+                                error = %err,
+                                "Skipping the malformed message"
+                            );
+
+                            acknowledger
+                                .acknowledge(ProcessingOutcome::Rejected(err.rejection_code()))
+                                .await;
+                            continue;
+                        }
+                    };
+
+                    if tx_sender
+                        .send_async(AcknowledgeableTransaction::new(transaction, acknowledger))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        });
+
+        rx.into_stream().boxed()
+    }
+}
+
+impl<Q: TMessageQueue> TTransactionStreamProvider for QueueTransactionProvider<Q> {
+    /// Consume the queue without explicit acknowledgements.
+    ///
+    /// Each message is deleted as soon as its transaction is handed out by the stream.
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        self.subscribe_to_acknowledged_stream()
+            .await
+            .then(|acknowledgeable| async move {
+                let (transaction, acknowledger) = acknowledgeable.into_parts();
+
+                acknowledger.acknowledge(ProcessingOutcome::Accepted).await;
+
+                transaction
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod queue_test {
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::future::BoxFuture;
+    use futures::StreamExt;
+
+    use crate::models::TransactionID;
+    use crate::rejections::RejectionCode;
+    use crate::tx_reception::queue::{QueueMessage, QueueTransactionProvider, TMessageQueue};
+    use crate::tx_reception::{ProcessingOutcome, TAcknowledgedStreamProvider};
+
+    #[derive(Clone, Default)]
+    struct MemoryQueue {
+        messages: Arc<Mutex<VecDeque<QueueMessage>>>,
+        deleted: Arc<Mutex<Vec<String>>>,
+        dead_letters: Arc<Mutex<Vec<(String, RejectionCode)>>>,
+    }
+
+    impl MemoryQueue {
+        fn with_messages(bodies: &[&str]) -> Self {
+            let queue = Self::default();
+
+            queue
+                .messages
+                .lock()
+                .unwrap()
+                .extend(bodies.iter().enumerate().map(|(index, body)| QueueMessage {
+                    body: body.to_string(),
+                    receipt: index.to_string(),
+                }));
+
+            queue
+        }
+    }
+
+    impl TMessageQueue for MemoryQueue {
+        type Error = Infallible;
+
+        fn receive(&self) -> BoxFuture<'_, Result<Vec<QueueMessage>, Infallible>> {
+            Box::pin(async move {
+                let messages = self.messages.lock().unwrap().drain(..).collect::<Vec<_>>();
+
+                if messages.is_empty() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+
+                Ok(messages)
+            })
+        }
+
+        fn delete<'a>(
+            &'a self,
+            message: &'a QueueMessage,
+        ) -> BoxFuture<'a, Result<(), Infallible>> {
+            Box::pin(async move {
+                self.deleted.lock().unwrap().push(message.receipt.clone());
+
+                Ok(())
+            })
+        }
+
+        fn dead_letter<'a>(
+            &'a self,
+            body: &'a str,
+            rejection: RejectionCode,
+        ) -> BoxFuture<'a, Result<(), Infallible>> {
+            Box::pin(async move {
+                self.dead_letters
+                    .lock()
+                    .unwrap()
+                    .push((body.to_string(), rejection));
+
+                Ok(())
+            })
+        }
+    }
+
+    const DEPOSIT: &str = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"}"#;
+    const WITHDRAWAL: &str = r#"{"type": "withdrawal", "client": 1, "tx": 2, "amount": "5.0"}"#;
+
+    #[tokio::test]
+    async fn test_messages_deleted_once_processed() {
+        let queue = MemoryQueue::with_messages(&[DEPOSIT, "not a transaction", WITHDRAWAL]);
+        let dead_letters = MemoryQueue::default();
+
+        let mut stream = QueueTransactionProvider::new(queue.clone())
+            .with_dead_letter_queue(dead_letters.clone())
+            .subscribe_to_acknowledged_stream()
+            .await;
+
+        let deposit = stream.next().await.unwrap();
+
+        assert_eq!(deposit.transaction().transaction_id(), TransactionID(1));
+        assert!(!queue.deleted.lock().unwrap().contains(&"0".to_string()));
+
+        deposit.acknowledge(ProcessingOutcome::Accepted).await;
+
+        let withdrawal = stream.next().await.unwrap();
+
+        assert_eq!(withdrawal.transaction().transaction_id(), TransactionID(2));
+
+        withdrawal
+            .acknowledge(ProcessingOutcome::Rejected(
+                RejectionCode::InsufficientFunds,
+            ))
+            .await;
+
+        let mut deleted = queue.deleted.lock().unwrap().clone();
+
+        deleted.sort();
+
+        assert_eq!(deleted, vec!["0", "1", "2"]);
+        assert_eq!(
+            *dead_letters.dead_letters.lock().unwrap(),
+            vec![
+                (
+                    "not a transaction".to_string(),
+                    RejectionCode::MalformedRecord
+                ),
+                (WITHDRAWAL.to_string(), RejectionCode::InsufficientFunds),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_messages_kept_without_dead_letter_queue() {
+        let queue = MemoryQueue::with_messages(&[WITHDRAWAL]);
+
+        let mut stream = QueueTransactionProvider::new(queue.clone())
+            .subscribe_to_acknowledged_stream()
+            .await;
+
+        stream
+            .next()
+            .await
+            .unwrap()
+            .acknowledge(ProcessingOutcome::Rejected(
+                RejectionCode::InsufficientFunds,
+            ))
+            .await;
+
+        // Left for the redrive policy of the queue
+        assert!(queue.deleted.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retryable_rejections_left_in_queue() {
+        let queue = MemoryQueue::with_messages(&[DEPOSIT, WITHDRAWAL]);
+        let dead_letters = MemoryQueue::default();
+
+        let mut stream = QueueTransactionProvider::new(queue.clone())
+            .with_dead_letter_queue(dead_letters.clone())
+            .subscribe_to_acknowledged_stream()
+            .await;
+
+        stream
+            .next()
+            .await
+            .unwrap()
+            .acknowledge(ProcessingOutcome::Rejected(RejectionCode::DatabaseFailure))
+            .await;
+
+        stream
+            .next()
+            .await
+            .unwrap()
+            .acknowledge(ProcessingOutcome::Rejected(
+                RejectionCode::InsufficientFunds,
+            ))
+            .await;
+
+        // The deposit is delivered again, only the withdrawal is rejected for good
+        assert_eq!(*queue.deleted.lock().unwrap(), vec!["1"]);
+        assert_eq!(
+            *dead_letters.dead_letters.lock().unwrap(),
+            vec![(WITHDRAWAL.to_string(), RejectionCode::InsufficientFunds)]
+        );
+    }
+}
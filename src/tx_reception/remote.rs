@@ -0,0 +1,381 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use reqwest::header::{HeaderValue, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Response, StatusCode, Url};
+use thiserror::Error;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio_util::io::StreamReader;
+
+/// How long to wait for the server to be reached, and then for each chunk of the body
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum RemoteInputError {
+    #[error("Failed to download the input {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("The server answered with {0}")]
+    Rejected(StatusCode),
+    #[error("The server stopped sending the input")]
+    TimedOut,
+    #[error("The server can't resume the download where it was interrupted")]
+    NotResumable,
+}
+
+impl RemoteInputError {
+    fn is_temporary(&self) -> bool {
+        match self {
+            RemoteInputError::RequestFailed(_) | RemoteInputError::TimedOut => true,
+            RemoteInputError::Rejected(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            RemoteInputError::NotResumable => false,
+        }
+    }
+}
+
+impl From<RemoteInputError> for io::Error {
+    fn from(err: RemoteInputError) -> Self {
+        io::Error::other(err)
+    }
+}
+
+/// Downloads an HTTP(S) resource, such as a CSV file of transactions, one chunk at a time as
+/// it is read.
+///
+/// When the download fails for a reason which might be temporary (the server could not be
+/// reached, the connection dropped, or the server answered with a server error or
+/// `429 Too Many Requests`), it is resumed where it was interrupted with a `Range` request,
+/// waiting twice as long before each new attempt. Resuming requires the server to support
+/// ranges, and to still have the same resource, as told by its `ETag` or `Last-Modified`.
+pub struct HttpDownload {
+    client: reqwest::Client,
+    url: Url,
+    max_attempts: u32,
+    retry_delay: Duration,
+    response: Option<Response>,
+    /// How many bytes of the body were read so far
+    offset: u64,
+    /// Tells whether the resource changed since the download started
+    validator: Option<HeaderValue>,
+}
+
+impl HttpDownload {
+    pub fn new(url: Url) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("The HTTP client has a valid configuration");
+
+        Self {
+            client,
+            url,
+            max_attempts: 5,
+            retry_delay: Duration::from_millis(500),
+            response: None,
+            offset: 0,
+            validator: None,
+        }
+    }
+
+    /// How many times to try each request, including the first one
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+
+        self
+    }
+
+    /// How long to wait before the first retry
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+
+        self
+    }
+
+    /// Start the download, failing if the resource can't be downloaded at all, and read its
+    /// body as it arrives
+    pub async fn start(mut self) -> Result<RemoteInput, RemoteInputError> {
+        let first = self.next_chunk().await.transpose()?;
+
+        let rest = stream::unfold(self, |mut download| async move {
+            let chunk = download.next_chunk().await?;
+
+            Some((chunk.map_err(io::Error::from), download))
+        });
+
+        Ok(RemoteInput {
+            reader: StreamReader::new(stream::iter(first.map(Ok)).chain(rest).boxed()),
+        })
+    }
+
+    /// The next chunk of the body, retrying (and resuming) the download until it succeeds, it
+    /// fails for good or it runs out of attempts
+    async fn next_chunk(&mut self) -> Option<Result<Bytes, RemoteInputError>> {
+        let mut delay = self.retry_delay;
+
+        for attempt in 1.. {
+            match self.read_chunk().await {
+                Err(err) if attempt < self.max_attempts && err.is_temporary() => {
+                    tracing::warn!(
+                        attempt,
+                        offset = self.offset,
+                        error = %err,
+                        "Failed to download the input, resuming"
+                    );
+
+                    // The body of the failed response is of no use anymore
+                    self.response = None;
+
+                    tokio::time::sleep(delay).await;
+
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result.transpose(),
+            }
+        }
+
+        unreachable!("Every attempt either returns or retries")
+    }
+
+    /// Read the next chunk of the body, requesting the rest of it first if there's no
+    /// response being read
+    async fn read_chunk(&mut self) -> Result<Option<Bytes>, RemoteInputError> {
+        let response = match self.response.take() {
+            Some(response) => response,
+            None => self.request().await?,
+        };
+
+        let response = self.response.insert(response);
+
+        let chunk = tokio::time::timeout(REQUEST_TIMEOUT, response.chunk())
+            .await
+            .map_err(|_| RemoteInputError::TimedOut)??;
+
+        if let Some(chunk) = &chunk {
+            self.offset += chunk.len() as u64;
+        }
+
+        Ok(chunk)
+    }
+
+    /// Request the body, from where it was interrupted if some of it was read already
+    async fn request(&mut self) -> Result<Response, RemoteInputError> {
+        let mut request = self.client.get(self.url.clone());
+
+        if self.offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", self.offset));
+
+            if let Some(validator) = &self.validator {
+                request = request.header(IF_RANGE, validator.clone());
+            }
+        }
+
+        let response = request.send().await?;
+
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT if self.offset > 0 => Ok(response),
+            // The whole resource again, as the server either doesn't support ranges or has a
+            // different version of it
+            status if status.is_success() && self.offset > 0 => Err(RemoteInputError::NotResumable),
+            status if status.is_success() => {
+                self.validator = validator(&response);
+
+                Ok(response)
+            }
+            status => Err(RemoteInputError::Rejected(status)),
+        }
+    }
+}
+
+/// Weak entity tags can't be used to resume a download, so the modification date is used then
+fn validator(response: &Response) -> Option<HeaderValue> {
+    let headers = response.headers();
+
+    headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .cloned()
+}
+
+/// The body of an HTTP(S) resource, read as it is downloaded without ever holding all of it,
+/// so it can be given to the
+/// [`CSVTransactionProvider`](crate::tx_reception::CSVTransactionProvider).
+pub struct RemoteInput {
+    reader: StreamReader<BoxStream<'static, io::Result<Bytes>>, Bytes>,
+}
+
+impl RemoteInput {
+    /// Start downloading the resource at the given URL, with the default retries
+    pub async fn open(url: Url) -> Result<Self, RemoteInputError> {
+        HttpDownload::new(url).start().await
+    }
+}
+
+impl AsyncRead for RemoteInput {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().reader).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod remote_input_test {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use reqwest::{StatusCode, Url};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use crate::models::TransactionID;
+    use crate::tx_reception::remote::{HttpDownload, RemoteInputError};
+    use crate::tx_reception::{CSVTransactionProvider, TTransactionStreamProvider};
+
+    const CSV_DATA: &str = "type, client, tx, amount\n\
+                            deposit, 1, 1, 1.0\n\
+                            deposit, 1, 2, 2.0\n\
+                            deposit, 2, 3, 3.0\n";
+
+    /// How a request is answered
+    enum Answer {
+        /// Drop the connection after this many bytes of the body
+        Interrupted(usize),
+        /// The body from where the `Range` of the request asks for
+        Resumed,
+        Status(u16),
+    }
+
+    /// A server answering each request with the next of the given answers, forwarding the
+    /// `Range` and `If-Range` headers it receives
+    async fn server(answers: Vec<Answer>) -> (Url, flume::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/transactions.csv",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let (headers, received) = flume::unbounded();
+
+        tokio::spawn(async move {
+            for answer in answers {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+
+                let mut ranges = Vec::new();
+                let mut offset = 0;
+
+                loop {
+                    let mut line = String::new();
+
+                    stream.read_line(&mut line).await.unwrap();
+
+                    let line = line.trim().to_ascii_lowercase();
+
+                    if line.is_empty() {
+                        break;
+                    }
+
+                    if let Some(range) = line.strip_prefix("range: bytes=") {
+                        offset = range.trim_end_matches('-').parse().unwrap();
+                    }
+
+                    if line.starts_with("range:") || line.starts_with("if-range:") {
+                        ranges.push(line);
+                    }
+                }
+
+                headers.send(ranges).unwrap();
+
+                let response = match answer {
+                    Answer::Interrupted(length) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"v1\"\r\n\
+                         Connection: close\r\n\r\n{}",
+                        CSV_DATA.len(),
+                        &CSV_DATA[..length]
+                    ),
+                    Answer::Resumed => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\
+                         Content-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n{}",
+                        CSV_DATA.len() - offset,
+                        offset,
+                        CSV_DATA.len() - 1,
+                        CSV_DATA.len(),
+                        &CSV_DATA[offset..]
+                    ),
+                    Answer::Status(status) => format!(
+                        "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        status
+                    ),
+                };
+
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_remote_input_resumes() {
+        let (url, received) = server(vec![
+            Answer::Status(503),
+            Answer::Interrupted(40),
+            Answer::Resumed,
+        ])
+        .await;
+
+        let input = HttpDownload::new(url)
+            .with_retry_delay(Duration::from_millis(1))
+            .start()
+            .await
+            .unwrap();
+
+        let txs = CSVTransactionProvider::new(input)
+            .subscribe_to_tx_stream()
+            .await
+            .map(|tx| tx.transaction_id())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            txs,
+            vec![TransactionID(1), TransactionID(2), TransactionID(3)]
+        );
+
+        let headers = received.drain().collect::<Vec<_>>();
+
+        assert_eq!(headers.len(), 3);
+        assert!(headers[0].is_empty() && headers[1].is_empty());
+        assert!(headers[2].iter().any(|header| header == "if-range: \"v1\""));
+        assert!(headers[2]
+            .iter()
+            .any(|header| header.starts_with("range: bytes=")));
+    }
+
+    #[tokio::test]
+    async fn test_remote_input_rejected() {
+        let (url, received) = server(vec![Answer::Status(404)]).await;
+
+        let result = HttpDownload::new(url)
+            .with_retry_delay(Duration::from_millis(1))
+            .start()
+            .await;
+
+        // Client errors won't go away by retrying
+        assert!(matches!(
+            result,
+            Err(RemoteInputError::Rejected(StatusCode::NOT_FOUND))
+        ));
+        assert_eq!(received.len(), 1);
+    }
+}
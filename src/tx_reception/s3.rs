@@ -0,0 +1,293 @@
+use std::fmt;
+use std::str::FromStr;
+
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::Client;
+use futures::stream::{self, BoxStream};
+use futures::{future, StreamExt};
+use thiserror::Error;
+
+use crate::models::transactions::Transaction;
+#[cfg(feature = "compression")]
+use crate::tx_reception::compression::DecompressingReader;
+#[cfg(feature = "parquet")]
+use crate::tx_reception::parquet::ParquetTransactionProvider;
+use crate::tx_reception::{
+    CSVTransactionProvider, CsvReadOptions, RowError, TTransactionStreamProvider,
+};
+
+const S3_SCHEME: &str = "s3://";
+
+#[derive(Error, Debug)]
+pub enum S3Error {
+    #[error("{0:?} is not an S3 location, such as s3://bucket/prefix")]
+    InvalidLocation(String),
+    #[error("Failed to list the objects of {0}: {1}")]
+    ListFailed(S3Location, String),
+    #[error("Failed to write {0}: {1}")]
+    WriteFailed(S3Location, String),
+    #[error("No object of {0} can be read as transactions")]
+    NoObjects(S3Location),
+}
+
+/// A bucket along with the key of an object, or the prefix of the keys of several, written
+/// as `s3://bucket/key`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Location {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl FromStr for S3Location {
+    type Err = S3Error;
+
+    fn from_str(location: &str) -> Result<Self, Self::Err> {
+        let (bucket, key) = location
+            .strip_prefix(S3_SCHEME)
+            .map(|path| path.split_once('/').unwrap_or((path, "")))
+            .filter(|(bucket, _)| !bucket.is_empty())
+            .ok_or_else(|| S3Error::InvalidLocation(location.to_string()))?;
+
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for S3Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}/{}", S3_SCHEME, self.bucket, self.key)
+    }
+}
+
+/// A client of S3 with the credentials and region of the standard AWS chain (the environment,
+/// the shared configuration files, the instance or task role, etc.)
+pub async fn client_from_env() -> Client {
+    Client::new(&aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await)
+}
+
+/// Provider reading the transactions of the objects of an S3 bucket, either a single object
+/// or every object under a prefix, in the order of their keys.
+///
+/// The objects with the `.csv` extension are read as CSV, streamed as they are read (and
+/// decompressed when compressed, with the `compression` feature, which also reads the `.gz`
+/// and `.zst` objects), and those with the `.parquet` extension are read as Parquet (with the
+/// `parquet` feature), which needs each of them to be downloaded first. Every object is read
+/// as its file would be, with the same options. Reading stops at the first object which
+/// can't be downloaded.
+pub struct S3TransactionProvider {
+    client: Client,
+    bucket: String,
+    keys: Vec<String>,
+    options: CsvReadOptions,
+}
+
+impl S3TransactionProvider {
+    /// Read the object at the given location or, if there is none, the objects under it
+    pub async fn new(client: Client, location: &S3Location) -> Result<Self, S3Error> {
+        let mut pages = client
+            .list_objects_v2()
+            .bucket(&location.bucket)
+            .prefix(&location.key)
+            .into_paginator()
+            .send();
+
+        let mut keys = Vec::new();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|err| {
+                S3Error::ListFailed(location.clone(), DisplayErrorContext(&err).to_string())
+            })?;
+
+            keys.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key())
+                    .filter(|key| is_input(key))
+                    .map(str::to_string),
+            );
+        }
+
+        if keys.contains(&location.key) {
+            keys = vec![location.key.clone()];
+        }
+
+        if keys.is_empty() {
+            return Err(S3Error::NoObjects(location.clone()));
+        }
+
+        keys.sort();
+
+        Ok(Self {
+            client,
+            bucket: location.bucket.clone(),
+            keys,
+            options: CsvReadOptions::default(),
+        })
+    }
+
+    /// Read the objects at the given location with the credentials of the standard AWS chain
+    pub async fn from_env(location: &S3Location) -> Result<Self, S3Error> {
+        Self::new(client_from_env().await, location).await
+    }
+
+    /// The keys of the objects which are read, in order
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Read every object with the given options, replacing every one chosen so far. The row
+    /// errors have to be subscribed to afterwards.
+    pub fn with_options(mut self, options: CsvReadOptions) -> Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Receive the rows of every object which could not be read as transactions, instead of
+    /// having them printed to the standard error
+    pub fn subscribe_to_row_errors(&mut self) -> BoxStream<'static, RowError> {
+        self.options.subscribe_to_row_errors()
+    }
+}
+
+/// The objects which can be read as transactions, by their extension
+fn is_input(key: &str) -> bool {
+    if key.ends_with(".csv") {
+        return true;
+    }
+
+    #[cfg(feature = "compression")]
+    if key.ends_with(".csv.gz") || key.ends_with(".csv.zst") {
+        return true;
+    }
+
+    #[cfg(feature = "parquet")]
+    if key.ends_with(".parquet") {
+        return true;
+    }
+
+    false
+}
+
+/// Download the object, returning the stream of its transactions, or nothing if it can't be
+/// read
+async fn open_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    options: CsvReadOptions,
+) -> Option<BoxStream<'static, Transaction>> {
+    let location = S3Location {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+    };
+
+    let object = match client.get_object().bucket(bucket).key(key).send().await {
+        Ok(object) => object,
+        Err(err) => {
+            tracing::error!(
+                object = %location,
+                error = %DisplayErrorContext(&err),
+                "Failed to read an object"
+            );
+            return None;
+        }
+    };
+
+    #[cfg(feature = "parquet")]
+    if key.ends_with(".parquet") {
+        let provider = match object.body.collect().await {
+            Ok(body) => ParquetTransactionProvider::try_new(body.into_bytes())
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        return match provider {
+            Ok(provider) => Some(
+                provider
+                    .with_options(options)
+                    .subscribe_to_tx_stream()
+                    .await,
+            ),
+            Err(err) => {
+                tracing::error!(object = %location, error = %err, "Failed to read an object");
+                None
+            }
+        };
+    }
+
+    let reader = object.body.into_async_read();
+
+    #[cfg(feature = "compression")]
+    let reader = match DecompressingReader::detect(reader).await {
+        Ok(reader) => reader,
+        Err(err) => {
+            tracing::error!(object = %location, error = %err, "Failed to read an object");
+            return None;
+        }
+    };
+
+    let provider = CSVTransactionProvider::new(reader).with_options(options);
+
+    Some(provider.subscribe_to_tx_stream().await)
+}
+
+impl TTransactionStreamProvider for S3TransactionProvider {
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let Self {
+            client,
+            bucket,
+            keys,
+            options,
+        } = self;
+
+        // Each object is only downloaded once the previous one was read
+        stream::iter(keys)
+            .then(move |key| {
+                let (client, bucket, options) = (client.clone(), bucket.clone(), options.clone());
+
+                async move { open_object(&client, &bucket, &key, options).await }
+            })
+            .take_while(|transactions| future::ready(transactions.is_some()))
+            .filter_map(future::ready)
+            .flatten()
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod s3_test {
+    use crate::tx_reception::s3::{is_input, S3Error, S3Location};
+
+    #[test]
+    fn test_parse_location() {
+        let location: S3Location = "s3://pipelines/transactions/2024-05-01/".parse().unwrap();
+
+        assert_eq!(location.bucket, "pipelines");
+        assert_eq!(location.key, "transactions/2024-05-01/");
+        assert_eq!(
+            location.to_string(),
+            "s3://pipelines/transactions/2024-05-01/"
+        );
+
+        let bucket: S3Location = "s3://pipelines".parse().unwrap();
+
+        assert_eq!(bucket.key, "");
+
+        for invalid in ["pipelines/transactions.csv", "s3:///transactions.csv"] {
+            assert!(matches!(
+                invalid.parse::<S3Location>(),
+                Err(S3Error::InvalidLocation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_input_objects() {
+        assert!(is_input("transactions/2024-05-01.csv"));
+        assert!(!is_input("transactions/_SUCCESS"));
+        assert!(!is_input("transactions/2024-05-01.csv.tmp"));
+    }
+}
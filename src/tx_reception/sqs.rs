@@ -0,0 +1,132 @@
+use aws_sdk_sqs::error::DisplayErrorContext;
+use aws_sdk_sqs::types::MessageAttributeValue;
+use aws_sdk_sqs::Client;
+use futures::future::BoxFuture;
+use thiserror::Error;
+
+use crate::rejections::RejectionCode;
+use crate::tx_reception::queue::{QueueMessage, QueueTransactionProvider, TMessageQueue};
+
+/// The most messages SQS hands out at once
+const MAX_MESSAGES: i32 = 10;
+
+/// How long each poll waits for messages to arrive (long polling), the most SQS allows
+const WAIT_TIME_SECONDS: i32 = 20;
+
+/// The message attribute the reason a message was moved to the dead-letter queue is kept in
+pub const REJECTION_ATTRIBUTE: &str = "rejection";
+
+/// Provider consuming transactions from an SQS queue
+pub type SqsTransactionProvider = QueueTransactionProvider<SqsQueue>;
+
+#[derive(Error, Debug)]
+pub enum SqsError {
+    #[error("Failed to receive messages from {0}: {1}")]
+    ReceiveFailed(String, String),
+    #[error("Failed to delete a message from {0}: {1}")]
+    DeleteFailed(String, String),
+    #[error("Failed to send a message to {0}: {1}")]
+    SendFailed(String, String),
+}
+
+/// An Amazon SQS queue, identified by its URL
+pub struct SqsQueue {
+    client: Client,
+    queue_url: String,
+}
+
+impl SqsQueue {
+    pub fn new(client: Client, queue_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            queue_url: queue_url.into(),
+        }
+    }
+
+    /// Use the queue with the credentials and region of the standard AWS chain
+    pub async fn from_env(queue_url: impl Into<String>) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+
+        Self::new(Client::new(&config), queue_url)
+    }
+}
+
+impl TMessageQueue for SqsQueue {
+    type Error = SqsError;
+
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<QueueMessage>, SqsError>> {
+        Box::pin(async move {
+            let output = self
+                .client
+                .receive_message()
+                .queue_url(&self.queue_url)
+                .max_number_of_messages(MAX_MESSAGES)
+                .wait_time_seconds(WAIT_TIME_SECONDS)
+                .send()
+                .await
+                .map_err(|err| {
+                    SqsError::ReceiveFailed(
+                        self.queue_url.clone(),
+                        DisplayErrorContext(&err).to_string(),
+                    )
+                })?;
+
+            Ok(output
+                .messages()
+                .iter()
+                .filter_map(|message| {
+                    Some(QueueMessage {
+                        body: message.body().unwrap_or_default().to_string(),
+                        receipt: message.receipt_handle()?.to_string(),
+                    })
+                })
+                .collect())
+        })
+    }
+
+    fn delete<'a>(&'a self, message: &'a QueueMessage) -> BoxFuture<'a, Result<(), SqsError>> {
+        Box::pin(async move {
+            self.client
+                .delete_message()
+                .queue_url(&self.queue_url)
+                .receipt_handle(&message.receipt)
+                .send()
+                .await
+                .map_err(|err| {
+                    SqsError::DeleteFailed(
+                        self.queue_url.clone(),
+                        DisplayErrorContext(&err).to_string(),
+                    )
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn dead_letter<'a>(
+        &'a self,
+        body: &'a str,
+        rejection: RejectionCode,
+    ) -> BoxFuture<'a, Result<(), SqsError>> {
+        Box::pin(async move {
+            let send_failed = |err: String| SqsError::SendFailed(self.queue_url.clone(), err);
+
+            let rejection = MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value(rejection.code())
+                .build()
+                .map_err(|err| send_failed(err.to_string()))?;
+
+            self.client
+                .send_message()
+                .queue_url(&self.queue_url)
+                .message_body(body)
+                .message_attributes(REJECTION_ATTRIBUTE, rejection)
+                .send()
+                .await
+                .map_err(|err| send_failed(DisplayErrorContext(&err).to_string()))?;
+
+            Ok(())
+        })
+    }
+}
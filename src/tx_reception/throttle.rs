@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use tokio::time::Instant;
+
+use crate::models::transactions::Transaction;
+use crate::tx_reception::TTransactionStreamProvider;
+
+/// Provider handing out the transactions of another one no faster than a given rate, so a
+/// historical input can be replayed against a live service without overwhelming what is
+/// downstream of it (e.g. the webhooks or the database).
+///
+/// The transactions are spread evenly over each second. The time the consumer spends without
+/// asking for transactions is not made up for afterwards, beyond the burst allowed with
+/// [`Self::with_burst`].
+pub struct ThrottledProvider<P> {
+    provider: P,
+    per_second: u32,
+    burst: u32,
+}
+
+impl<P> ThrottledProvider<P> {
+    /// Hand out at most `per_second` transactions of the provider per second
+    pub fn new(provider: P, per_second: u32) -> Self {
+        Self {
+            provider,
+            per_second: per_second.max(1),
+            burst: 1,
+        }
+    }
+
+    /// The amount of transactions which can be handed out at once, after the consumer didn't
+    /// ask for any for a while, which defaults to one.
+    ///
+    /// The timers only wake up about once every millisecond, so rates above a thousand
+    /// transactions per second need a burst of at least their share of a millisecond to be
+    /// reached.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+
+        self
+    }
+}
+
+/// When each transaction can be handed out, as a virtual clock which moves forward by the
+/// interval between two transactions every time one is
+struct Throttle {
+    interval: Duration,
+    burst: Duration,
+    next: Instant,
+}
+
+impl Throttle {
+    async fn wait(&mut self) {
+        let now = Instant::now();
+
+        // The time left unused only allows for the burst, not for catching up on all of it
+        let slot = self.next.max(now.checked_sub(self.burst).unwrap_or(now));
+
+        if slot > now {
+            tokio::time::sleep_until(slot).await;
+        }
+
+        self.next = slot + self.interval;
+    }
+}
+
+impl<P> TTransactionStreamProvider for ThrottledProvider<P>
+where
+    P: TTransactionStreamProvider,
+{
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        let interval = (Duration::from_secs(1) / self.per_second).max(Duration::from_nanos(1));
+
+        let throttle = Throttle {
+            interval,
+            burst: interval * (self.burst - 1),
+            next: Instant::now(),
+        };
+
+        let transactions = self.provider.subscribe_to_tx_stream().await;
+
+        stream::unfold(
+            (transactions, throttle),
+            |(mut transactions, mut throttle)| async move {
+                throttle.wait().await;
+
+                let tx = transactions.next().await?;
+
+                Some((tx, (transactions, throttle)))
+            },
+        )
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod throttle_test {
+    use std::time::{Duration, Instant};
+
+    use futures::StreamExt;
+
+    use crate::tx_reception::throttle::ThrottledProvider;
+    use crate::tx_reception::{CSVTransactionProvider, TTransactionStreamProvider};
+
+    const CSV_DATA: &str = "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+deposit,1,3,1.0
+deposit,1,4,1.0
+deposit,1,5,1.0
+deposit,1,6,1.0";
+
+    #[tokio::test]
+    async fn test_throttled_provider() {
+        let started = Instant::now();
+
+        let read = ThrottledProvider::new(CSVTransactionProvider::new(CSV_DATA.as_bytes()), 50)
+            .subscribe_to_tx_stream()
+            .await
+            .count()
+            .await;
+
+        assert_eq!(read, 6);
+
+        // The first transaction is handed out right away, and each of the others 20ms after
+        // the one before it
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_throttled_provider_burst() {
+        let mut transactions =
+            ThrottledProvider::new(CSVTransactionProvider::new(CSV_DATA.as_bytes()), 10)
+                .with_burst(3)
+                .subscribe_to_tx_stream()
+                .await;
+
+        assert!(transactions.next().await.is_some());
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let resumed = Instant::now();
+
+        // Having been idle for long enough, the burst is handed out at once
+        for _ in 0..3 {
+            assert!(transactions.next().await.is_some());
+        }
+
+        assert!(resumed.elapsed() < Duration::from_millis(50));
+
+        // But not the rest of the time left unused
+        assert!(transactions.next().await.is_some());
+
+        assert!(resumed.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use thiserror::Error;
+
+use crate::models::transactions::Transaction;
+use crate::tx_reception::{
+    AcknowledgeableTransaction, CSVTransactionProvider, CsvReadOptions, ProcessingOutcome,
+    RowError, TAcknowledgedStreamProvider, TAcknowledger, TTransactionStreamProvider,
+};
+
+/// The folder, inside of the watched directory, the files are moved to once they are read
+pub const DONE_FOLDER: &str = "done";
+
+/// How long the size of a file which was already in the directory has to stay the same for it
+/// to be read, as it might still have been being written
+const SETTLE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Error, Debug)]
+pub enum WatchDirError {
+    #[error("Failed to prepare the watched directory {0}")]
+    IoError(#[from] io::Error),
+    #[error("Failed to watch the directory {0}")]
+    WatchError(#[from] notify::Error),
+}
+
+/// Provider reading the transactions of the CSV files dropped into a directory (a hot folder),
+/// as they appear, until it is dropped.
+///
+/// The files already in the directory are read first, in the order of their names, once their
+/// size stops changing. The new ones follow in the order they are complete: once they are
+/// closed after being written, or once they are renamed (or moved) into the directory. Only
+/// some platforms (e.g. Linux) report the files being closed, so on the others the files
+/// should be written under another name (e.g. `batch.csv.part`) and renamed once complete.
+///
+/// Each file is read as the [`CSVTransactionProvider`] would, with the same [`CsvReadOptions`],
+/// and moved into the [`DONE_FOLDER`] once all of its transactions are read and acknowledged,
+/// so it isn't picked up again. The files with a transaction rejected for a reason which might
+/// be temporary (e.g. the storage failing) are left in the directory instead, to be read again
+/// the next time it is watched.
+pub struct WatchDirProvider {
+    directory: PathBuf,
+    watcher: RecommendedWatcher,
+    arrivals: flume::Receiver<Arrival>,
+    options: CsvReadOptions,
+}
+
+/// A file to read, and whether it is known to be complete
+struct Arrival {
+    path: PathBuf,
+    complete: bool,
+}
+
+impl WatchDirProvider {
+    /// Start watching the given directory, creating its [`DONE_FOLDER`] if needed. The files
+    /// which appear from now on are kept until the transactions are subscribed to.
+    pub fn new(directory: impl AsRef<Path>) -> Result<Self, WatchDirError> {
+        let directory = directory.as_ref().to_path_buf();
+
+        std::fs::create_dir_all(directory.join(DONE_FOLDER))?;
+
+        let (sender, arrivals) = flume::unbounded();
+
+        let existing = sender.clone();
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) if is_complete(&event.kind) => {
+                    for path in event.paths.into_iter().filter(|path| is_csv(path)) {
+                        let _ = sender.send(Arrival {
+                            path,
+                            complete: true,
+                        });
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => tracing::error!(error = %err, "Failed to watch for new files"),
+            })?;
+
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+        // Listed after the watch started, so no file goes unnoticed. Those which are also
+        // reported by the watcher are only read once.
+        let mut files = std::fs::read_dir(&directory)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        files.sort();
+
+        for path in files.into_iter().filter(|path| is_csv(path)) {
+            let _ = existing.send(Arrival {
+                path,
+                complete: false,
+            });
+        }
+
+        Ok(Self {
+            directory,
+            watcher,
+            arrivals,
+            options: CsvReadOptions::default(),
+        })
+    }
+
+    /// Read every file with the given options, replacing every one chosen so far. The row
+    /// errors have to be subscribed to afterwards.
+    pub fn with_options(mut self, options: CsvReadOptions) -> Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Receive the rows of every file which could not be read as transactions, instead of
+    /// having them printed to the standard error
+    pub fn subscribe_to_row_errors(&mut self) -> BoxStream<'static, RowError> {
+        self.options.subscribe_to_row_errors()
+    }
+}
+
+/// Files are complete once they are closed after being written, or once they are renamed (or
+/// moved) into the directory
+fn is_complete(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Access(AccessKind::Close(AccessMode::Write))
+            | EventKind::Modify(ModifyKind::Name(
+                RenameMode::To | RenameMode::Both | RenameMode::Any
+            ))
+    )
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "csv")
+}
+
+/// Wait until the size of the file stops changing
+async fn wait_until_settled(path: &Path) -> io::Result<()> {
+    let mut size = tokio::fs::metadata(path).await?.len();
+
+    loop {
+        tokio::time::sleep(SETTLE_INTERVAL).await;
+
+        let current = tokio::fs::metadata(path).await?.len();
+
+        if current == size {
+            return Ok(());
+        }
+
+        size = current;
+    }
+}
+
+/// A file which is being read, moved into the done folder once it was read to its end and
+/// every one of its transactions was acknowledged, which is when the last reference to it is
+/// dropped
+struct ReadFile {
+    path: PathBuf,
+    done: PathBuf,
+    reading: Arc<Mutex<HashSet<PathBuf>>>,
+    unacknowledged: AtomicUsize,
+    /// Whether a transaction was rejected for a reason which might be temporary, so the file
+    /// has to be read again
+    retry: AtomicBool,
+}
+
+impl Drop for ReadFile {
+    fn drop(&mut self) {
+        // The transactions which were never acknowledged might not have been processed
+        if *self.unacknowledged.get_mut() == 0 && !*self.retry.get_mut() {
+            move_to_done(&self.path, &self.done);
+        }
+
+        self.reading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.path);
+    }
+}
+
+/// Move the file which was read into the done folder
+fn move_to_done(path: &Path, done: &Path) {
+    let Some(name) = path.file_name() else {
+        return;
+    };
+
+    if let Err(err) = std::fs::rename(path, done.join(name)) {
+        tracing::error!(
+            file = ?path,
+            to = ?done,
+            error = %err,
+            "Failed to move a file once read"
+        );
+    }
+}
+
+struct FileAcknowledger(Arc<ReadFile>);
+
+impl TAcknowledger for FileAcknowledger {
+    fn acknowledge(self: Box<Self>, outcome: ProcessingOutcome) -> BoxFuture<'static, ()> {
+        if let ProcessingOutcome::Rejected(rejection) = outcome {
+            if rejection.is_retryable() {
+                self.0.retry.store(true, Ordering::Relaxed);
+            }
+        }
+
+        self.0.unacknowledged.fetch_sub(1, Ordering::Relaxed);
+
+        Box::pin(future::ready(()))
+    }
+}
+
+/// The file being read, along with the stream of its transactions
+struct CurrentFile {
+    file: Arc<ReadFile>,
+    transactions: BoxStream<'static, Transaction>,
+}
+
+struct WatchState {
+    done: PathBuf,
+    // Watches for as long as the stream is alive
+    _watcher: RecommendedWatcher,
+    arrivals: flume::Receiver<Arrival>,
+    options: CsvReadOptions,
+    /// The files read whose transactions are not all acknowledged yet
+    reading: Arc<Mutex<HashSet<PathBuf>>>,
+    current: Option<CurrentFile>,
+}
+
+impl WatchState {
+    async fn next_transaction(&mut self) -> Option<AcknowledgeableTransaction> {
+        loop {
+            if let Some(current) = &mut self.current {
+                if let Some(tx) = current.transactions.next().await {
+                    current.file.unacknowledged.fetch_add(1, Ordering::Relaxed);
+
+                    let acknowledger = Box::new(FileAcknowledger(current.file.clone()));
+
+                    return Some(AcknowledgeableTransaction::new(tx, acknowledger));
+                }
+            }
+
+            // Moved once the transactions still being processed are acknowledged
+            self.current = None;
+
+            let Arrival { path, complete } = self.arrivals.recv_async().await.ok()?;
+
+            // Files reported more than once are either still being read or gone after the
+            // first time
+            let reading = self
+                .reading
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .contains(&path);
+
+            if reading || !path.is_file() {
+                continue;
+            }
+
+            if !complete {
+                if let Err(err) = wait_until_settled(&path).await {
+                    tracing::error!(file = ?path, error = %err, "Failed to read a file");
+                    continue;
+                }
+            }
+
+            let file = match tokio::fs::File::open(&path).await {
+                Ok(file) => file,
+                Err(err) => {
+                    tracing::error!(file = ?path, error = %err, "Failed to open a file");
+                    continue;
+                }
+            };
+
+            self.reading
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(path.clone());
+
+            let transactions = CSVTransactionProvider::new(file)
+                .with_options(self.options.clone())
+                .subscribe_to_tx_stream()
+                .await;
+
+            self.current = Some(CurrentFile {
+                file: Arc::new(ReadFile {
+                    path,
+                    done: self.done.clone(),
+                    reading: self.reading.clone(),
+                    unacknowledged: AtomicUsize::new(0),
+                    retry: AtomicBool::new(false),
+                }),
+                transactions,
+            });
+        }
+    }
+}
+
+impl TAcknowledgedStreamProvider for WatchDirProvider {
+    async fn subscribe_to_acknowledged_stream(
+        self,
+    ) -> BoxStream<'static, AcknowledgeableTransaction> {
+        let state = WatchState {
+            done: self.directory.join(DONE_FOLDER),
+            _watcher: self.watcher,
+            arrivals: self.arrivals,
+            options: self.options,
+            reading: Arc::default(),
+            current: None,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            let tx = state.next_transaction().await?;
+
+            Some((tx, state))
+        })
+        .boxed()
+    }
+}
+
+impl TTransactionStreamProvider for WatchDirProvider {
+    /// Read the files without acknowledging their transactions.
+    ///
+    /// Each file is moved into the done folder as soon as all of its transactions are handed
+    /// out by the stream.
+    async fn subscribe_to_tx_stream(self) -> BoxStream<'static, Transaction> {
+        self.subscribe_to_acknowledged_stream()
+            .await
+            .then(|acknowledgeable| async move {
+                let (transaction, acknowledger) = acknowledgeable.into_parts();
+
+                acknowledger.acknowledge(ProcessingOutcome::Accepted).await;
+
+                transaction
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod watch_test {
+    use std::time::Duration;
+
+    use futures::stream::BoxStream;
+    use futures::StreamExt;
+
+    use crate::models::TransactionID;
+    use crate::rejections::RejectionCode;
+    use crate::tx_reception::watch::{WatchDirProvider, DONE_FOLDER};
+    use crate::tx_reception::{
+        ProcessingOutcome, TAcknowledgedStreamProvider, TTransactionStreamProvider,
+    };
+
+    async fn next<T>(transactions: &mut BoxStream<'static, T>) -> T {
+        tokio::time::timeout(Duration::from_secs(10), transactions.next())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_watch_directory() {
+        let directory = std::env::temp_dir().join(format!("watch-test-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+
+        std::fs::write(
+            directory.join("batch-1.csv"),
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\n",
+        )
+        .unwrap();
+
+        // Only the complete files are picked up
+        std::fs::write(directory.join("batch-2.csv.part"), "").unwrap();
+
+        let mut transactions = WatchDirProvider::new(&directory)
+            .unwrap()
+            .subscribe_to_tx_stream()
+            .await;
+
+        assert_eq!(
+            next(&mut transactions).await.transaction_id(),
+            TransactionID(1)
+        );
+
+        std::fs::write(
+            directory.join("batch-2.csv.part"),
+            "type, client, tx, amount\ndeposit, 1, 2, 1.0\n",
+        )
+        .unwrap();
+
+        std::fs::rename(
+            directory.join("batch-2.csv.part"),
+            directory.join("batch-2.csv"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            next(&mut transactions).await.transaction_id(),
+            TransactionID(2)
+        );
+        assert!(directory.join(DONE_FOLDER).join("batch-1.csv").is_file());
+        assert!(!directory.join("batch-1.csv").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watch_directory_acknowledgements() {
+        let directory = std::env::temp_dir().join(format!("watch-ack-test-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let mut transactions = WatchDirProvider::new(&directory)
+            .unwrap()
+            .subscribe_to_acknowledged_stream()
+            .await;
+
+        // Written in place, so only picked up once it is closed
+        std::fs::write(
+            directory.join("batch-1.csv"),
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\n",
+        )
+        .unwrap();
+
+        let first = next(&mut transactions).await;
+
+        assert_eq!(first.transaction().transaction_id(), TransactionID(1));
+
+        std::fs::write(
+            directory.join("batch-2.csv"),
+            "type, client, tx, amount\ndeposit, 1, 2, 1.0\n",
+        )
+        .unwrap();
+
+        let second = next(&mut transactions).await;
+
+        assert_eq!(second.transaction().transaction_id(), TransactionID(2));
+
+        // The first file was read to its end, but its transaction wasn't processed yet
+        assert!(directory.join("batch-1.csv").is_file());
+
+        first.acknowledge(ProcessingOutcome::Accepted).await;
+
+        assert!(directory.join(DONE_FOLDER).join("batch-1.csv").is_file());
+        assert!(!directory.join("batch-1.csv").exists());
+
+        // Left to be read again, as its transaction may be accepted then
+        second
+            .acknowledge(ProcessingOutcome::Rejected(RejectionCode::StorageFailure))
+            .await;
+
+        drop(transactions);
+
+        assert!(directory.join("batch-2.csv").is_file());
+        assert!(!directory.join(DONE_FOLDER).join("batch-2.csv").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashSet;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::events::{DomainEvent, EventBus, EventKind, EventSubscription};
+use crate::models::money::DisplayAmount;
+use crate::models::{ClientID, TransactionID};
+use crate::tx_reception::http::{Submitter, TransactionSubmission};
+
+/// The kinds of domain events which change the balances of an account
+const BALANCE_EVENTS: [EventKind; 13] = [
+    EventKind::DepositApplied,
+    EventKind::WithdrawalApplied,
+    EventKind::DisputeOpened,
+    EventKind::DisputeResolved,
+    EventKind::ChargebackApplied,
+    EventKind::ChargebackReversed,
+    EventKind::FundsAuthorized,
+    EventKind::AuthorizationCaptured,
+    EventKind::AuthorizationExpired,
+    EventKind::TransferSent,
+    EventKind::TransferReceived,
+    EventKind::FeeCharged,
+    EventKind::FeeCollected,
+];
+
+/// A change to the balances of an account, as pushed to the sockets
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct BalanceChange {
+    event: &'static str,
+    client: ClientID,
+    transaction: TransactionID,
+    amount: DisplayAmount,
+}
+
+impl BalanceChange {
+    /// The change described by the given event, if it is one of the [`BALANCE_EVENTS`], with
+    /// its amount written with the given amount of decimal places
+    fn from_event(event: &DomainEvent, precision: u32) -> Option<Self> {
+        let (event, client, transaction, amount) = match *event {
+            DomainEvent::DepositApplied {
+                client,
+                transaction,
+                amount,
+            } => ("deposit_applied", client, transaction, amount),
+            DomainEvent::WithdrawalApplied {
+                client,
+                transaction,
+                amount,
+            } => ("withdrawal_applied", client, transaction, amount),
+            DomainEvent::DisputeOpened {
+                client,
+                transaction,
+                amount,
+            } => ("dispute_opened", client, transaction, amount),
+            DomainEvent::DisputeResolved {
+                client,
+                transaction,
+                amount,
+            } => ("dispute_resolved", client, transaction, amount),
+            DomainEvent::ChargebackApplied {
+                client,
+                transaction,
+                amount,
+            } => ("chargeback_applied", client, transaction, amount),
+            DomainEvent::ChargebackReversed {
+                client,
+                transaction,
+                amount,
+            } => ("chargeback_reversed", client, transaction, amount),
+            DomainEvent::FundsAuthorized {
+                client,
+                transaction,
+                amount,
+            } => ("funds_authorized", client, transaction, amount),
+            DomainEvent::AuthorizationCaptured {
+                client,
+                transaction,
+                amount,
+            } => ("authorization_captured", client, transaction, amount),
+            DomainEvent::AuthorizationExpired {
+                client,
+                transaction,
+                amount,
+            } => ("authorization_expired", client, transaction, amount),
+            DomainEvent::TransferSent {
+                client,
+                transaction,
+                amount,
+                ..
+            } => ("transfer_sent", client, transaction, amount),
+            DomainEvent::TransferReceived {
+                client,
+                transaction,
+                amount,
+                ..
+            } => ("transfer_received", client, transaction, amount),
+            DomainEvent::FeeCharged {
+                client,
+                transaction,
+                amount,
+                ..
+            } => ("fee_charged", client, transaction, amount),
+            DomainEvent::FeeCollected {
+                client,
+                transaction,
+                amount,
+                ..
+            } => ("fee_collected", client, transaction, amount),
+            DomainEvent::AccountCreated { .. }
+            | DomainEvent::AccountFrozen { .. }
+            | DomainEvent::AccountUnfrozen { .. }
+            | DomainEvent::AccountClosed { .. } => return None,
+        };
+
+        Some(Self {
+            event,
+            client,
+            transaction,
+            amount: amount.display(precision),
+        })
+    }
+}
+
+/// The changes a socket asked to be pushed
+#[derive(Deserialize)]
+struct SocketParams {
+    /// `all`, or the ids of the clients separated by commas
+    events: Option<String>,
+}
+
+/// The clients whose changes are pushed to a socket, `None` for every client
+type ClientFilter = Option<HashSet<ClientID>>;
+
+fn client_filter(events: &str) -> Option<ClientFilter> {
+    if events == "all" {
+        return Some(None);
+    }
+
+    events
+        .split(',')
+        .map(|client| client.trim().parse().ok())
+        .collect::<Option<HashSet<ClientID>>>()
+        .map(Some)
+}
+
+#[derive(Clone)]
+struct SocketState {
+    submitter: Submitter,
+    events: Option<EventBus>,
+    stopped: watch::Receiver<bool>,
+}
+
+/// The route of the sockets, `GET /ws`, which hand the transactions through the given channel
+/// and are closed once `stopped` turns true.
+///
+/// Every text message sent through a socket is a transaction submission, in the same format as
+/// the body of `POST /transactions`, and is answered with the outcome of each of its
+/// transactions once they were processed, as in `{"results": [...]}`. Sockets opened with
+/// `?events=all`, or `?events=1,2` for only some clients, are also pushed every change to the
+/// balances of those accounts published on the event bus, as in
+/// `{"event": "deposit_applied", "client": 1, "transaction": 1, "amount": "1.5000"}`.
+pub(super) fn router(
+    submitter: Submitter,
+    events: Option<EventBus>,
+    stopped: watch::Receiver<bool>,
+) -> Router {
+    Router::new()
+        .route("/ws", get(upgrade))
+        .with_state(SocketState {
+            submitter,
+            events,
+            stopped,
+        })
+}
+
+async fn upgrade(
+    State(state): State<SocketState>,
+    Query(params): Query<SocketParams>,
+    socket: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, &'static str)> {
+    let subscription = match (params.events.as_deref(), &state.events) {
+        (None, _) => None,
+        (Some(events), Some(bus)) => {
+            let clients = client_filter(events).ok_or((
+                StatusCode::BAD_REQUEST,
+                "events must be `all` or client ids separated by commas",
+            ))?;
+
+            Some((bus.subscribe_to(BALANCE_EVENTS), clients))
+        }
+        (Some(_), None) => {
+            return Err((
+                StatusCode::NOT_IMPLEMENTED,
+                "The changes to the balances are not published by this server",
+            ))
+        }
+    };
+
+    Ok(socket.on_upgrade(move |socket| handle_socket(socket, state, subscription)))
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    state: SocketState,
+    subscription: Option<(EventSubscription, ClientFilter)>,
+) {
+    let (mut sink, mut received) = socket.split();
+
+    // Both the answers and the pushed changes are written by a single task
+    let (outgoing, to_send) = flume::unbounded::<Message>();
+
+    let writer = tokio::spawn(async move {
+        while let Ok(message) = to_send.recv_async().await {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+
+        let _ = sink.close().await;
+    });
+
+    let pusher = subscription.map(|(mut events, clients)| {
+        let outgoing = outgoing.clone();
+        let precision = state.submitter.precision;
+
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let Some(change) = BalanceChange::from_event(&event, precision) else {
+                    continue;
+                };
+
+                if clients
+                    .as_ref()
+                    .is_some_and(|clients| !clients.contains(&change.client))
+                {
+                    continue;
+                }
+
+                let Ok(change) = serde_json::to_string(&change) else {
+                    continue;
+                };
+
+                if outgoing.send(Message::Text(change.into())).is_err() {
+                    break;
+                }
+            }
+        })
+    });
+
+    let mut stopped = state.stopped;
+
+    let stopping = async move {
+        // Without a shutdown signal, the sockets are only closed by their clients
+        if stopped.wait_for(|stopped| *stopped).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::pin!(stopping);
+
+    loop {
+        let message = tokio::select! {
+            message = received.next() => message,
+            _ = &mut stopping => break,
+        };
+
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            Some(Ok(_)) => continue,
+        };
+
+        let answer = match serde_json::from_str::<TransactionSubmission>(&text) {
+            Ok(submission) => match state.submitter.submit(submission).await {
+                Some(response) => serde_json::to_string(&response),
+                None => break,
+            },
+            Err(err) => serde_json::to_string(&serde_json::json!({ "error": err.to_string() })),
+        };
+
+        if let Ok(answer) = answer {
+            if outgoing.send(Message::Text(answer.into())).is_err() {
+                break;
+            }
+        }
+    }
+
+    if let Some(pusher) = pusher {
+        pusher.abort();
+    }
+
+    // The writer closes the socket once the answers already given are sent
+    drop(outgoing);
+
+    let _ = writer.await;
+}
+
+#[cfg(test)]
+mod websocket_tests {
+    use std::collections::HashSet;
+
+    use crate::events::DomainEvent;
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::tx_reception::websocket::{client_filter, BalanceChange};
+
+    #[test]
+    fn test_balance_changes() {
+        let change = BalanceChange::from_event(
+            &DomainEvent::TransferReceived {
+                client: ClientID(2),
+                transaction: TransactionID(7),
+                from_client: ClientID(1),
+                amount: MoneyType::new(15000),
+            },
+            4,
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&change).unwrap(),
+            r#"{"event":"transfer_received","client":2,"transaction":7,"amount":"1.5000"}"#
+        );
+
+        assert_eq!(
+            BalanceChange::from_event(
+                &DomainEvent::AccountFrozen {
+                    client: ClientID(2)
+                },
+                4
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_client_filter() {
+        assert_eq!(client_filter("all"), Some(None));
+        assert_eq!(
+            client_filter("1, 2"),
+            Some(Some(HashSet::from([ClientID(1), ClientID(2)])))
+        );
+        assert_eq!(client_filter("1,everyone"), None);
+    }
+}
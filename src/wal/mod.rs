@@ -0,0 +1,388 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use thiserror::Error;
+
+use crate::models::money::{format_amount, parse_amount};
+use crate::models::transactions::{Transaction, TransactionType};
+use crate::models::{ClientID, Timestamp, TransactionID};
+use crate::tx_reception::parse_logged_tx_type;
+use crate::FLOATING_POINT_ACC;
+
+/// The type of the records of the expired authorizations, which is never the type of a transaction
+const EXPIRY_RECORD: &str = "expire";
+
+/// A durable, append only, log of the accepted transactions.
+///
+/// Transactions are appended after being accepted, but before any of their effects are applied,
+/// so replaying the log on startup restores the state which was lost in a crash.
+pub trait TWriteAheadLog: Send + Sync {
+    fn append(&self, transaction: &Transaction) -> io::Result<()>;
+
+    /// The given authorization expired at the given time, releasing the funds it held. Appended
+    /// before the funds are released, as the transactions are.
+    fn append_expiry(&self, authorization: &Transaction, at: Timestamp) -> io::Result<()>;
+}
+
+/// What happened to the accounts, as appended to the write-ahead log
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoggedEntry {
+    /// An accepted transaction
+    Transaction(Transaction),
+    /// The authorization of the client expired at the given time, releasing its funds
+    Expiry {
+        client: ClientID,
+        authorization: TransactionID,
+        at: Timestamp,
+    },
+}
+
+/// Write-ahead log stored in a file, with one CSV record (in the input format, always including
+/// the destination column of transfers, followed by the timestamp) per transaction, and one
+/// `expire` record (with the client, the authorization and the time it expired at) per expiry
+pub struct WriteAheadLog {
+    file: Mutex<File>,
+    sync: bool,
+}
+
+impl WriteAheadLog {
+    /// Open the log at the given path for appending, creating it if it doesn't exist.
+    ///
+    /// A record left partially written by a crash is removed, so the new records don't
+    /// get appended to it.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+
+        truncate_torn_record(&mut file)?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+            sync: true,
+        })
+    }
+
+    /// Choose whether every append waits for the record to reach the disk.
+    ///
+    /// Without it, a crash of the machine (rather than of the process) can lose the last
+    /// appended transactions.
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+
+        self
+    }
+
+    /// Read all of the entries in the log at the given path, in the order they were appended.
+    /// A log which doesn't exist is empty.
+    ///
+    /// A crash can leave the last record partially written, in which case it is dropped,
+    /// as its effects were never applied.
+    pub fn read(path: impl AsRef<Path>) -> Result<Vec<LoggedEntry>, WalError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file);
+
+        let records = csv_reader.records().collect::<Vec<_>>();
+
+        let record_count = records.len();
+
+        let mut entries = Vec::with_capacity(record_count);
+
+        for (index, record) in records.into_iter().enumerate() {
+            match record
+                .map_err(WalError::from)
+                .and_then(|record| decode(&record))
+            {
+                Ok(entry) => entries.push(entry),
+                Err(err) if index + 1 == record_count => {
+                    tracing::warn!(
+                        error = %err,
+                        "Dropping the torn last record of the write-ahead log"
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn write(&self, record: &[u8]) -> io::Result<()> {
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        file.write_all(record)?;
+
+        if self.sync {
+            file.sync_data()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TWriteAheadLog for WriteAheadLog {
+    fn append(&self, transaction: &Transaction) -> io::Result<()> {
+        self.write(&encode(transaction)?)
+    }
+
+    fn append_expiry(&self, authorization: &Transaction, at: Timestamp) -> io::Result<()> {
+        let mut csv_writer = csv::Writer::from_writer(Vec::new());
+
+        csv_writer.write_record([
+            EXPIRY_RECORD,
+            &authorization.client().to_string(),
+            &authorization.transaction_id().to_string(),
+            "",
+            "",
+            &at.to_string(),
+        ])?;
+
+        self.write(&csv_writer.into_inner().map_err(|err| err.into_error())?)
+    }
+}
+
+/// Remove everything after the last complete record of the log
+pub(crate) fn truncate_torn_record(file: &mut File) -> io::Result<()> {
+    const CHUNK_SIZE: u64 = 4096;
+
+    let len = file.metadata()?.len();
+
+    let mut buf = [0u8; CHUNK_SIZE as usize];
+    let mut end = len;
+
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        let chunk = &mut buf[..(end - start) as usize];
+
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+
+        if let Some(newline) = chunk.iter().rposition(|byte| *byte == b'\n') {
+            let complete = start + newline as u64 + 1;
+
+            if complete != len {
+                file.set_len(complete)?;
+            }
+
+            return Ok(());
+        }
+
+        end = start;
+    }
+
+    file.set_len(0)
+}
+
+fn encode(transaction: &Transaction) -> io::Result<Vec<u8>> {
+    let (tx_type, amount) = match transaction.tx_type() {
+        TransactionType::Deposit { amount, .. } => ("deposit", Some(*amount)),
+        TransactionType::Withdrawal { amount, .. } => ("withdrawal", Some(*amount)),
+        TransactionType::Dispute => ("dispute", None),
+        TransactionType::Resolve => ("resolve", None),
+        TransactionType::Chargeback => ("chargeback", None),
+        TransactionType::Representment => ("representment", None),
+        TransactionType::Authorize { amount, .. } => ("authorize", Some(*amount)),
+        TransactionType::Capture => ("capture", None),
+        TransactionType::Transfer { amount, .. } => ("transfer", Some(*amount)),
+        TransactionType::Unfreeze => ("unfreeze", None),
+        TransactionType::Close => ("close", None),
+    };
+
+    let amount = amount
+        .map(|amount| format_amount(amount, FLOATING_POINT_ACC as u32))
+        .unwrap_or_default();
+
+    let to_client = transaction
+        .destination()
+        .map(|to_client| to_client.to_string())
+        .unwrap_or_default();
+
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+
+    let timestamp = transaction
+        .timestamp()
+        .map(|timestamp| timestamp.to_string())
+        .unwrap_or_default();
+
+    csv_writer.write_record([
+        tx_type,
+        &transaction.client().to_string(),
+        &transaction.transaction_id().to_string(),
+        &amount,
+        &to_client,
+        &timestamp,
+    ])?;
+
+    csv_writer.into_inner().map_err(|err| err.into_error())
+}
+
+fn decode(record: &csv::StringRecord) -> Result<LoggedEntry, WalError> {
+    let invalid = || WalError::InvalidRecord(record.iter().collect::<Vec<_>>().join(","));
+
+    let (Some(tx_type), Some(client), Some(tx), Some(amount), Some(to_client)) = (
+        record.get(0),
+        record.get(1),
+        record.get(2),
+        record.get(3),
+        record.get(4),
+    ) else {
+        return Err(invalid());
+    };
+
+    let client: ClientID = client.parse().map_err(|_| invalid())?;
+    let tx: TransactionID = tx.parse().map_err(|_| invalid())?;
+
+    let amount = match amount {
+        "" => None,
+        amount => Some(parse_amount(amount, FLOATING_POINT_ACC as u32).map_err(|_| invalid())?),
+    };
+
+    let to_client = match to_client {
+        "" => None,
+        to_client => Some(to_client.parse().map_err(|_| invalid())?),
+    };
+
+    // Logs written before the transactions had timestamps don't have the column
+    let timestamp = match record.get(5).unwrap_or_default() {
+        "" => None,
+        timestamp => Some(timestamp.parse().map_err(|_| invalid())?),
+    };
+
+    if tx_type == EXPIRY_RECORD {
+        return Ok(LoggedEntry::Expiry {
+            client,
+            authorization: tx,
+            at: timestamp.ok_or_else(invalid)?,
+        });
+    }
+
+    // Only accepted transactions are logged, so they are trusted
+    let tx_type = parse_logged_tx_type(tx_type, amount, to_client).map_err(|_| invalid())?;
+
+    let mut transaction = Transaction::builder()
+        .with_client_id(client)
+        .with_tx_id(tx)
+        .with_tx_type(tx_type)
+        .build();
+
+    if let Some(timestamp) = timestamp {
+        transaction.assign_timestamp(timestamp);
+    }
+
+    Ok(LoggedEntry::Transaction(transaction))
+}
+
+#[derive(Error, Debug)]
+pub enum WalError {
+    #[error("Failed to read the write-ahead log {0:?}")]
+    IoError(#[from] io::Error),
+    #[error("Failed to parse the write-ahead log {0:?}")]
+    CsvError(#[from] csv::Error),
+    #[error("Invalid write-ahead log record {0}")]
+    InvalidRecord(String),
+}
+
+#[cfg(test)]
+mod wal_tests {
+    use std::io::Write;
+
+    use crate::models::transactions::{DisputeState, Transaction, TransactionType};
+    use crate::models::{ClientID, MoneyType, TransactionID};
+    use crate::wal::{LoggedEntry, TWriteAheadLog, WriteAheadLog};
+
+    #[test]
+    fn test_append_and_read() {
+        let path = std::env::temp_dir().join(format!("wal-test-{}.csv", std::process::id()));
+
+        let _ = std::fs::remove_file(&path);
+
+        let transactions = [
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_id(TransactionID(1))
+                .with_tx_type(TransactionType::Deposit {
+                    amount: MoneyType::new(12345),
+                    dispute: DisputeState::NotDisputed,
+                })
+                .with_timestamp(1_700_000_000)
+                .build(),
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_id(TransactionID(1))
+                .with_tx_type(TransactionType::Dispute)
+                .build(),
+            Transaction::builder()
+                .with_client_id(ClientID(1))
+                .with_tx_id(TransactionID(2))
+                .with_tx_type(TransactionType::Transfer {
+                    to_client: ClientID(2),
+                    amount: MoneyType::new(5000),
+                })
+                .build(),
+        ];
+
+        let wal = WriteAheadLog::open(&path).unwrap().with_sync(false);
+
+        for transaction in &transactions {
+            wal.append(transaction).unwrap();
+        }
+
+        wal.append_expiry(&transactions[0], 1_700_086_400).unwrap();
+
+        drop(wal);
+
+        // Simulate a crash in the middle of an append
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"withdrawal,1,")
+            .unwrap();
+
+        let mut logged = transactions
+            .iter()
+            .cloned()
+            .map(LoggedEntry::Transaction)
+            .collect::<Vec<_>>();
+
+        logged.push(LoggedEntry::Expiry {
+            client: ClientID(1),
+            authorization: TransactionID(1),
+            at: 1_700_086_400,
+        });
+
+        assert_eq!(WriteAheadLog::read(&path).unwrap(), logged);
+
+        // Reopening the log drops the torn record, so new records can be appended
+        let wal = WriteAheadLog::open(&path).unwrap().with_sync(false);
+
+        wal.append(&transactions[0]).unwrap();
+
+        drop(wal);
+
+        let recovered = WriteAheadLog::read(&path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recovered.len(), 5);
+        assert_eq!(
+            recovered[4],
+            LoggedEntry::Transaction(transactions[0].clone())
+        );
+    }
+}
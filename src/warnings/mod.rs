@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::models::{ClientID, TransactionID};
+
+/// Something worth reporting which, unlike an error, did not stop a transaction from being
+/// handled, so the more lenient behaviors of the engine remain observable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The amount had more decimal places than supported, so it was truncated, or rounded
+    /// half to even when `truncated` is false
+    AmountRounded {
+        transaction: TransactionID,
+        given: String,
+        truncated: bool,
+    },
+    /// A deposit or withdrawal reusing the id of a stored transaction was skipped
+    DuplicateTransactionIgnored {
+        client: ClientID,
+        transaction: TransactionID,
+    },
+    /// A dispute of a withdrawal was skipped, as the dispute policy ignores them
+    WithdrawalDisputeIgnored {
+        client: ClientID,
+        transaction: TransactionID,
+    },
+    /// A transaction referencing one that has not arrived yet was parked until it arrives
+    TransactionDeferred {
+        client: ClientID,
+        transaction: TransactionID,
+    },
+    /// A parked transaction was dropped to make room for newer ones
+    DeferredTransactionDropped {
+        client: ClientID,
+        transaction: TransactionID,
+    },
+    /// A transaction of a blocked client was set aside, without being applied
+    TransactionQuarantined {
+        client: ClientID,
+        transaction: TransactionID,
+    },
+}
+
+impl Warning {
+    /// A short identifier of the kind of warning, used to group them
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Warning::AmountRounded { .. } => "amount-rounded",
+            Warning::DuplicateTransactionIgnored { .. } => "duplicate-ignored",
+            Warning::WithdrawalDisputeIgnored { .. } => "withdrawal-dispute-ignored",
+            Warning::TransactionDeferred { .. } => "transaction-deferred",
+            Warning::DeferredTransactionDropped { .. } => "deferred-dropped",
+            Warning::TransactionQuarantined { .. } => "transaction-quarantined",
+        }
+    }
+
+    /// The transaction which caused the warning
+    pub fn transaction(&self) -> TransactionID {
+        match self {
+            Warning::AmountRounded { transaction, .. }
+            | Warning::DuplicateTransactionIgnored { transaction, .. }
+            | Warning::WithdrawalDisputeIgnored { transaction, .. }
+            | Warning::TransactionDeferred { transaction, .. }
+            | Warning::DeferredTransactionDropped { transaction, .. }
+            | Warning::TransactionQuarantined { transaction, .. } => *transaction,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::AmountRounded {
+                transaction,
+                given,
+                truncated,
+            } => write!(
+                f,
+                "The amount {} of transaction {} was {} to the supported precision",
+                given,
+                transaction,
+                if *truncated {
+                    "truncated"
+                } else {
+                    "rounded half to even"
+                }
+            ),
+            Warning::DuplicateTransactionIgnored {
+                client,
+                transaction,
+            } => write!(
+                f,
+                "Ignored transaction {} of client {}, as its id is already in use",
+                transaction, client
+            ),
+            Warning::WithdrawalDisputeIgnored {
+                client,
+                transaction,
+            } => write!(
+                f,
+                "Ignored the dispute of withdrawal {} of client {}",
+                transaction, client
+            ),
+            Warning::TransactionDeferred {
+                client,
+                transaction,
+            } => write!(
+                f,
+                "Deferred the transaction of client {} until transaction {} arrives",
+                client, transaction
+            ),
+            Warning::DeferredTransactionDropped {
+                client,
+                transaction,
+            } => write!(
+                f,
+                "Dropped the deferred transaction of client {} referencing transaction {}",
+                client, transaction
+            ),
+            Warning::TransactionQuarantined {
+                client,
+                transaction,
+            } => write!(
+                f,
+                "Quarantined transaction {} of client {}, as the client is blocked",
+                transaction, client
+            ),
+        }
+    }
+}
+
+/// Receives the warnings produced while handling transactions
+pub trait TWarningSink: Send + Sync {
+    fn warn(&self, warning: &Warning);
+}
+
+impl<S: TWarningSink + ?Sized> TWarningSink for Arc<S> {
+    fn warn(&self, warning: &Warning) {
+        (**self).warn(warning)
+    }
+}
+
+/// Counts the warnings of each kind, for the report of a run
+#[derive(Default)]
+pub struct WarningCounter {
+    counts: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl WarningCounter {
+    /// The amount of warnings of each kind received so far
+    pub fn counts(&self) -> BTreeMap<&'static str, u64> {
+        match self.counts.lock() {
+            Ok(counts) => counts.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl TWarningSink for WarningCounter {
+    fn warn(&self, warning: &Warning) {
+        let mut counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        *counts.entry(warning.kind()).or_default() += 1;
+    }
+}
+
+/// Writes every warning as a CSV row, separately from the errors
+pub struct WarningLog<W: Write> {
+    writer: Mutex<csv::Writer<W>>,
+}
+
+impl<W: Write> WarningLog<W> {
+    pub fn new(writer: W) -> Self {
+        let mut writer = csv::Writer::from_writer(writer);
+
+        if let Err(err) = writer.write_record(["kind", "tx", "message"]) {
+            tracing::error!(error = %err, "Failed to write warning log header");
+        }
+
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<W> {
+        self.writer.into_inner().ok()?.into_inner().ok()
+    }
+}
+
+impl<W: Write + Send> TWarningSink for WarningLog<W> {
+    fn warn(&self, warning: &Warning) {
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+
+        let result = writer
+            .write_record([
+                warning.kind(),
+                &warning.transaction().to_string(),
+                &warning.to_string(),
+            ])
+            .and_then(|_| Ok(writer.flush()?));
+
+        if let Err(err) = result {
+            tracing::error!(
+                tx = %warning.transaction(),
+                error = %err,
+                "Failed to write warning log entry"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod warnings_tests {
+    use crate::models::{ClientID, TransactionID};
+    use crate::warnings::{TWarningSink, Warning, WarningCounter, WarningLog};
+
+    #[test]
+    fn test_warning_sinks() {
+        let counter = WarningCounter::default();
+        let log = WarningLog::new(Vec::new());
+
+        let warnings = [
+            Warning::TransactionDeferred {
+                client: ClientID(1),
+                transaction: TransactionID(3),
+            },
+            Warning::DuplicateTransactionIgnored {
+                client: ClientID(1),
+                transaction: TransactionID(2),
+            },
+            Warning::TransactionDeferred {
+                client: ClientID(2),
+                transaction: TransactionID(4),
+            },
+        ];
+
+        for warning in &warnings {
+            counter.warn(warning);
+            log.warn(warning);
+        }
+
+        let counts = counter.counts();
+
+        assert_eq!(counts["transaction-deferred"], 2);
+        assert_eq!(counts["duplicate-ignored"], 1);
+
+        let output = String::from_utf8(log.into_inner().unwrap()).unwrap();
+
+        assert_eq!(output.lines().count(), 4);
+        assert!(output
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("duplicate-ignored,2,"));
+    }
+}